@@ -0,0 +1,239 @@
+//! Throughput of the hot paths: `cargo bench -p dmg-core [-- <filter>]`.
+//!
+//! A small harness in the spirit of Criterion without the dependency. Every
+//! benchmark is calibrated to fill a sample, then timed over several samples
+//! and the median is reported with the fastest and slowest sample around it.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use common::build_rom;
+use dmg_core::bus::HardwareRegister;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::{CPU, CpuContext};
+use dmg_core::headless::Headless;
+use dmg_core::interrupts::{InterruptFlag, InterruptRequest};
+use dmg_core::ppu::PPU;
+
+const SAMPLES: usize = 15;
+const SAMPLE_TIME: Duration = Duration::from_millis(100);
+const DOTS_PER_LINE: usize = 456;
+
+struct Bench {
+    filter: Option<String>,
+}
+
+impl Bench {
+    /// Time `iteration`, which processes `units` of `unit` each time it runs.
+    fn run(&self, name: &str, units: u64, unit: &str, mut iteration: impl FnMut()) {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !name.contains(filter.as_str()))
+        {
+            return;
+        }
+
+        // Double the batch until it takes a noticeable time, doubles as warm up
+        let mut batch = 1u64;
+        let elapsed = loop {
+            let start = Instant::now();
+            for _ in 0..batch {
+                iteration();
+            }
+            let elapsed = start.elapsed();
+
+            if elapsed >= SAMPLE_TIME / 4 {
+                break elapsed;
+            }
+            batch *= 2;
+        };
+        let batch = (batch as f64 * SAMPLE_TIME.as_secs_f64() / elapsed.as_secs_f64()) as u64;
+
+        let mut samples: Vec<f64> = (0..SAMPLES)
+            .map(|_| {
+                let start = Instant::now();
+                for _ in 0..batch {
+                    iteration();
+                }
+                start.elapsed().as_nanos() as f64 / batch as f64
+            })
+            .collect();
+        samples.sort_by(f64::total_cmp);
+
+        let median = samples[SAMPLES / 2];
+        println!(
+            "{name:<24} {median:>12.1} ns/iter [{:.1} .. {:.1}] {:>10.2} M{unit}/s",
+            samples[0],
+            samples[SAMPLES - 1],
+            units as f64 * 1_000.0 / median,
+        );
+    }
+}
+
+/// 64 KiB of memory without any other hardware, so only decoding and
+/// executing instructions is measured.
+struct FlatMemory {
+    memory: Vec<u8>,
+    ticks: u64,
+}
+
+impl CpuContext for FlatMemory {
+    fn tick_cycle(&mut self) {
+        self.ticks += 4;
+    }
+
+    fn read_cycle(&mut self, address: u16) -> u8 {
+        self.tick_cycle();
+        self.memory[address as usize]
+    }
+
+    fn write_cycle(&mut self, address: u16, value: u8) {
+        self.tick_cycle();
+        self.memory[address as usize] = value;
+    }
+
+    fn get_interrupt(&mut self) -> Option<InterruptFlag> {
+        None
+    }
+
+    fn ack_interrupt(&mut self, _f: &InterruptFlag) {}
+
+    fn peek(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn ticks(&self) -> u64 {
+        self.ticks
+    }
+}
+
+struct NoInterrupts;
+
+impl InterruptRequest for NoInterrupts {
+    fn request_interrupt(&mut self, _f: InterruptFlag) {}
+}
+
+/// Loads, ALU operations, a CB-prefixed rotate, memory accesses and a
+/// call, looping forever.
+#[rustfmt::skip]
+const MIXED_LOOP: &[u8] = &[
+    0x21, 0x00, 0xC0,   // loop: LD HL, $C000
+    0x06, 0x10,         // LD B, 16
+    0x7E,               // inner: LD A, (HL)
+    0x80,               // ADD A, B
+    0xCB, 0x07,         // RLC A
+    0xEE, 0x5A,         // XOR $5A
+    0x22,               // LD (HL+), A
+    0x05,               // DEC B
+    0x20, 0xF6,         // JR NZ, inner
+    0xCD, 0x00, 0x02,   // CALL $0200
+    0x18, 0xEB,         // JR loop
+];
+const RET: &[u8] = &[0xC9];
+
+fn cpu_benches(bench: &Bench) {
+    const STEPS: u64 = 1_000;
+
+    let mut memory = vec![0; 0x10000];
+    memory[0x100..0x100 + MIXED_LOOP.len()].copy_from_slice(MIXED_LOOP);
+    memory[0x200] = RET[0];
+    let mut cpu = CPU::new(FlatMemory { memory, ticks: 0 });
+
+    bench.run("cpu/decode_execute", STEPS, "instr", || {
+        for _ in 0..STEPS {
+            black_box(cpu.step());
+        }
+    });
+
+    // The same program with every component ticking along
+    let rom = build_rom(&[(0x150, MIXED_LOOP), (0x200, RET)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("bench.gb", &rom).unwrap());
+
+    bench.run("cpu/system_step", STEPS, "instr", || {
+        for _ in 0..STEPS {
+            black_box(emu.step());
+        }
+    });
+}
+
+fn bus_benches(bench: &Bench) {
+    let rom = build_rom(&[(0x150, &[0x18, 0xFE])]);
+    let mut emu = Headless::new(Cartridge::from_bytes("bench.gb", &rom).unwrap());
+    let emu = emu.emulator_mut();
+
+    // Only implemented registers, the others are logged
+    let registers = [
+        HardwareRegister::P1_JOYP,
+        HardwareRegister::DIV,
+        HardwareRegister::TIMA,
+        HardwareRegister::IF,
+        HardwareRegister::NR52,
+        HardwareRegister::LCDC,
+        HardwareRegister::STAT,
+        HardwareRegister::LY,
+    ];
+    let regions: [(&str, Vec<u16>); 5] = [
+        ("bus/read_rom", (0x0000..=0x3FFF).collect()),
+        ("bus/read_vram", (0x8000..=0x9FFF).collect()),
+        ("bus/read_wram", (0xC000..=0xDFFF).collect()),
+        ("bus/read_io", registers.map(|r| r as u16).to_vec()),
+        ("bus/read_hram", (0xFF80..=0xFFFE).collect()),
+    ];
+
+    for (name, addresses) in regions {
+        bench.run(name, addresses.len() as u64, "reads", || {
+            for &address in &addresses {
+                black_box(emu.peek(black_box(address)));
+            }
+        });
+    }
+}
+
+/// PPU with tile 0 on every map entry and 10 sprites on the top 8 lines.
+fn busy_ppu(fifo_renderer: bool) -> PPU {
+    let mut ppu = PPU::new();
+    ppu.set_fifo_renderer(fifo_renderer);
+
+    for (i, address) in (0x8000..0x8010).enumerate() {
+        ppu.vram_write(address, (i as u8).wrapping_mul(0x35));
+    }
+
+    for sprite in 0..10u16 {
+        let address = 0xFE00 + sprite * 4;
+        ppu.oam_write(address, 16);
+        ppu.oam_write(address + 1, 8 + sprite as u8 * 15);
+    }
+
+    ppu.lcd_write(HardwareRegister::BGP, 0xE4, &mut NoInterrupts);
+    ppu.lcd_write(HardwareRegister::OBP0, 0xE4, &mut NoInterrupts);
+    ppu.lcd_write(HardwareRegister::LCDC, 0x93, &mut NoInterrupts);
+    ppu
+}
+
+fn ppu_benches(bench: &Bench) {
+    for (name, fifo) in [("ppu/scanline_fifo", true), ("ppu/scanline_line", false)] {
+        let mut ppu = busy_ppu(fifo);
+
+        bench.run(name, DOTS_PER_LINE as u64, "dots", || {
+            for _ in 0..DOTS_PER_LINE {
+                ppu.tick(&mut NoInterrupts);
+            }
+            black_box(ppu.get_current_frame());
+        });
+    }
+}
+
+fn main() {
+    // Cargo passes `--bench`, anything else is a name filter
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with("--"));
+    let bench = Bench { filter };
+
+    cpu_benches(&bench);
+    bus_benches(&bench);
+    ppu_benches(&bench);
+}
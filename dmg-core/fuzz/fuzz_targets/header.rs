@@ -0,0 +1,16 @@
+#![no_main]
+
+use dmg_core::cart::{Cartridge, CartridgeHeader};
+use libfuzzer_sys::fuzz_target;
+
+// Malformed headers must be reported, never panic
+fuzz_target!(|data: &[u8]| {
+    if let Ok(header) = CartridgeHeader::load(data) {
+        let _ = header.validate();
+    }
+
+    if let Ok(rom) = Cartridge::from_bytes("fuzz.gb", data) {
+        let _ = rom.to_string();
+        let _ = rom.battery_data();
+    }
+});
@@ -0,0 +1,25 @@
+#![no_main]
+
+use dmg_core::cart::Cartridge;
+use dmg_core::headless::Headless;
+use libfuzzer_sys::fuzz_target;
+
+/// Enough for the first frames, keeps each input fast.
+const MAX_STEPS: u32 = 50_000;
+
+// Arbitrary code and hardware register writes must not panic the core,
+// illegal opcodes stop the CPU with a fault.
+fuzz_target!(|data: &[u8]| {
+    let Ok(rom) = Cartridge::from_bytes("fuzz.gb", data) else {
+        return;
+    };
+
+    let mut emu = Headless::new(rom);
+    emu.cpu_mut().set_report_faults(true);
+
+    for _ in 0..MAX_STEPS {
+        if !matches!(emu.try_step(), Ok(true)) {
+            break;
+        }
+    }
+});
@@ -0,0 +1,1075 @@
+use crate::bus::HardwareRegister;
+use crate::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+/// Bit of the system counter (DIV is its upper byte) whose falling edge
+/// clocks the frame sequencer, bit 4 of DIV gives 512 Hz.
+pub const DIV_APU_BIT: u16 = 1 << 12;
+const WAVE_RAM_START: u16 = 0xFF30;
+
+/// Waveforms of the 4 duties of NR11 and NR21, one bit per step.
+const DUTY_WAVEFORMS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// Run a frequency timer of a channel for `dots`, it is reloaded with
+/// `period` on the dot it runs out. Returns the new timer and how many
+/// times it ran out.
+fn run_timer(timer: u32, period: u32, dots: u64) -> (u32, u64) {
+    let first = timer.max(1) as u64;
+
+    if dots < first {
+        return (timer - dots as u32, 0);
+    }
+
+    let period = period.max(1) as u64;
+    let since = (dots - first) % period;
+    ((period - since) as u32, 1 + (dots - first) / period)
+}
+
+/// Analog output of a channel's DAC for a digital sample, 0 - 15, from 1 to -1.
+fn dac(sample: u8) -> f32 {
+    1.0 - sample as f32 / 7.5
+}
+
+/// Length counter shared by all channels, disables the channel when it expires.
+struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+    max: u16,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        LengthCounter {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    fn load(&mut self, length: u8) {
+        self.counter = self.max - (length as u16);
+    }
+
+    /// Returns true when the counter reached zero on this clock.
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+
+        false
+    }
+}
+
+/// Volume envelope of the square and noise channels, configured by NRx2.
+struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope {
+            register: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    fn period(&self) -> u8 {
+        self.register & 0b111
+    }
+
+    /// The DAC is powered when any of the upper 5 bits of NRx2 is set.
+    fn dac_enabled(&self) -> bool {
+        (self.register & 0xF8) != 0
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.period();
+    }
+
+    fn clock(&mut self) {
+        if self.period() == 0 {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period();
+
+            if (self.register & 0b1000) != 0 && self.volume < 15 {
+                self.volume += 1;
+            } else if (self.register & 0b1000) == 0 && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+/// Frequency sweep unit of channel 1, configured by NR10.
+struct Sweep {
+    register: u8,
+    enabled: bool,
+    timer: u8,
+    shadow_frequency: u16,
+    // Set once a frequency was calculated in negate mode
+    negate_used: bool,
+}
+
+impl Sweep {
+    fn new() -> Self {
+        Sweep {
+            register: 0,
+            enabled: false,
+            timer: 0,
+            shadow_frequency: 0,
+            negate_used: false,
+        }
+    }
+
+    fn period(&self) -> u8 {
+        (self.register >> 4) & 0b111
+    }
+
+    fn negate(&self) -> bool {
+        (self.register & 0b1000) != 0
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0b111
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of 0 is treated as 8
+        self.timer = if self.period() == 0 { 8 } else { self.period() };
+    }
+
+    fn calculate(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.shift();
+
+        if self.negate() {
+            self.negate_used = true;
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+}
+
+/// Channels 1 and 2, channel 1 additionally has a sweep unit.
+struct SquareChannel {
+    enabled: bool,
+    duty: u8,
+    frequency: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+    // Dots until the next step of the duty cycle and the step, 0 - 7
+    timer: u16,
+    duty_step: u8,
+}
+
+impl SquareChannel {
+    fn new(with_sweep: bool) -> Self {
+        SquareChannel {
+            enabled: false,
+            duty: 0,
+            frequency: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep: if with_sweep { Some(Sweep::new()) } else { None },
+            timer: 0,
+            duty_step: 0,
+        }
+    }
+
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
+    fn tick(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer > 1 {
+            self.timer -= 1;
+        } else {
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    fn advance(&mut self, dots: u64) {
+        if !self.enabled {
+            return;
+        }
+
+        let (timer, steps) = run_timer(self.timer as u32, self.period() as u32, dots);
+        self.timer = timer as u16;
+        self.duty_step = ((self.duty_step as u64 + steps) % 8) as u8;
+    }
+
+    fn output(&self) -> u8 {
+        let high = (DUTY_WAVEFORMS[self.duty as usize] >> self.duty_step) & 1;
+        high * self.envelope.volume
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.envelope.trigger();
+        self.timer = self.period();
+
+        let frequency = self.frequency;
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow_frequency = frequency;
+            sweep.negate_used = false;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period() != 0 || sweep.shift() != 0;
+
+            if sweep.shift() != 0 && sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+
+        if sweep.timer > 0 {
+            sweep.timer -= 1;
+        }
+
+        if sweep.timer != 0 {
+            return;
+        }
+
+        sweep.reload_timer();
+
+        if !sweep.enabled || sweep.period() == 0 {
+            return;
+        }
+
+        let frequency = sweep.calculate();
+
+        if frequency > 2047 {
+            self.enabled = false;
+        } else if sweep.shift() != 0 {
+            sweep.shadow_frequency = frequency;
+            self.frequency = frequency;
+
+            // The new frequency is checked for overflow once more
+            if sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+}
+
+/// Channel 3, plays back the 32 4-bit samples stored in wave RAM.
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    output_level: u8,
+    frequency: u16,
+    length: LengthCounter,
+    // Dots until the next sample is fetched and the sample playing, 0 - 31
+    timer: u16,
+    position: u8,
+    // Byte of wave RAM last fetched and the dots since
+    sample_buffer: u8,
+    fetched_ago: u8,
+}
+
+impl WaveChannel {
+    fn new() -> Self {
+        WaveChannel {
+            enabled: false,
+            dac_enabled: false,
+            output_level: 0,
+            frequency: 0,
+            length: LengthCounter::new(256),
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            fetched_ago: u8::MAX,
+        }
+    }
+
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 2
+    }
+
+    fn tick(&mut self, wave_ram: &[u8; 16]) {
+        self.fetched_ago = self.fetched_ago.saturating_add(1);
+
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer > 1 {
+            self.timer -= 1;
+        } else {
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            self.sample_buffer = wave_ram[self.position as usize / 2];
+            self.fetched_ago = 0;
+        }
+    }
+
+    fn advance(&mut self, dots: u64, wave_ram: &[u8; 16]) {
+        let fetched_ago = self.fetched_ago as u64 + dots;
+
+        if self.enabled {
+            let (timer, steps) = run_timer(self.timer as u32, self.period() as u32, dots);
+            self.timer = timer as u16;
+
+            if steps > 0 {
+                self.position = ((self.position as u64 + steps) % 32) as u8;
+                self.sample_buffer = wave_ram[self.position as usize / 2];
+                self.fetched_ago = (self.period() as u32 - timer).min(u8::MAX as u32) as u8;
+                return;
+            }
+        }
+
+        self.fetched_ago = fetched_ago.min(u8::MAX as u64) as u8;
+    }
+
+    fn output(&self) -> u8 {
+        let sample = if self.position.is_multiple_of(2) {
+            self.sample_buffer >> 4
+        } else {
+            self.sample_buffer & 0x0F
+        };
+
+        match self.output_level {
+            0 => 0,
+            level => sample >> (level - 1),
+        }
+    }
+
+    /// Byte of wave RAM the CPU reaches while the channel plays on a DMG,
+    /// only in the 2 dots after the channel fetched it.
+    fn cpu_access(&self) -> Option<usize> {
+        (self.fetched_ago < 2).then_some(self.position as usize / 2)
+    }
+}
+
+/// Channel 4, pseudo-random noise from a linear feedback shift register.
+struct NoiseChannel {
+    enabled: bool,
+    polynomial: u8,
+    length: LengthCounter,
+    envelope: Envelope,
+    // Dots until the next shift and the 15-bit shift register
+    timer: u32,
+    lfsr: u16,
+}
+
+impl NoiseChannel {
+    fn new() -> Self {
+        NoiseChannel {
+            enabled: false,
+            polynomial: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            timer: 0,
+            lfsr: 0x7FFF,
+        }
+    }
+
+    /// Divisor of NR43 shifted by its clock shift, in dots.
+    fn period(&self) -> u32 {
+        let divisor = match self.polynomial & 0b111 {
+            0 => 8,
+            code => code as u32 * 16,
+        };
+        divisor << (self.polynomial >> 4)
+    }
+
+    fn tick(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period();
+        self.shift();
+    }
+
+    fn advance(&mut self, dots: u64) {
+        if !self.enabled {
+            return;
+        }
+
+        let (timer, steps) = run_timer(self.timer, self.period(), dots);
+        self.timer = timer;
+
+        for _ in 0..steps {
+            self.shift();
+        }
+    }
+
+    fn shift(&mut self) {
+        let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (bit << 14);
+
+        // 7-bit mode also feeds bit 6
+        if (self.polynomial & 0b1000) != 0 {
+            self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        (!self.lfsr & 1) as u8 * self.envelope.volume
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.envelope.trigger();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+    }
+}
+
+/// APU (Audio Processing Unit)
+///
+/// The frame sequencer is not driven by its own timer, it is clocked by the
+/// falling edge of DIV bit 4 (DIV-APU) like on hardware. As a result, resetting
+/// DIV while that bit is set clocks the sequencer early.
+///
+/// Frame sequencer steps (512 Hz):
+/// - length counters on steps 0, 2, 4, 6 (256 Hz)
+/// - channel 1 sweep on steps 2 and 6 (128 Hz)
+/// - volume envelopes on step 7 (64 Hz)
+///
+/// The channels count their frequency timers every dot, so where each one
+/// is in its waveform is part of the state. With the wave RAM quirks the
+/// CPU reaches wave RAM while channel 3 plays only as a DMG does: in the
+/// moment the channel fetches a byte and only that byte, and triggering it
+/// just as it fetches corrupts the first bytes of wave RAM.
+pub struct APU {
+    enabled: bool,
+    nr50: u8,
+    nr51: u8,
+    square1: SquareChannel,
+    square2: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+    wave_ram: [u8; 16],
+    // Next frame sequencer step to be executed
+    frame_step: u8,
+    div_apu_bit: bool,
+    wave_ram_quirks: bool,
+}
+
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            enabled: false,
+            nr50: 0,
+            nr51: 0,
+            square1: SquareChannel::new(true),
+            square2: SquareChannel::new(false),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+            wave_ram: [0; 16],
+            frame_step: 0,
+            div_apu_bit: false,
+            wave_ram_quirks: false,
+        }
+    }
+
+    /// Restrict and corrupt wave RAM accesses while channel 3 plays as a
+    /// DMG does, otherwise the CPU reads and writes it freely.
+    pub fn set_wave_ram_quirks(&mut self, enabled: bool) {
+        self.wave_ram_quirks = enabled;
+    }
+
+    /// Advance the channel frequency timers by one dot (T-cycle).
+    pub fn tick(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        self.square1.tick();
+        self.square2.tick();
+        self.wave.tick(&self.wave_ram);
+        self.noise.tick();
+    }
+
+    /// Run `dots` dots at once, as many `tick`s would without the output
+    /// of each.
+    pub fn advance(&mut self, dots: u64) {
+        if !self.enabled {
+            return;
+        }
+
+        self.square1.advance(dots);
+        self.square2.advance(dots);
+        self.wave.advance(dots, &self.wave_ram);
+        self.noise.advance(dots);
+    }
+
+    /// Follow the system counter, must be called every time `DIV_APU_BIT` of
+    /// DIV changes, including when DIV is reset by a write.
+    pub fn update_div(&mut self, div: u16) {
+        let bit = (div & DIV_APU_BIT) != 0;
+
+        if self.div_apu_bit && !bit && self.enabled {
+            self.clock_frame_sequencer();
+        }
+
+        self.div_apu_bit = bit;
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        let step = self.frame_step;
+
+        if step.is_multiple_of(2) {
+            self.clock_length();
+        }
+
+        if step == 2 || step == 6 {
+            self.square1.clock_sweep();
+        }
+
+        if step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+
+        self.frame_step = (step + 1) % 8;
+    }
+
+    fn clock_length(&mut self) {
+        if self.square1.length.clock() {
+            self.square1.enabled = false;
+        }
+
+        if self.square2.length.clock() {
+            self.square2.enabled = false;
+        }
+
+        if self.wave.length.clock() {
+            self.wave.enabled = false;
+        }
+
+        if self.noise.length.clock() {
+            self.noise.enabled = false;
+        }
+    }
+
+    /// Whether the next frame sequencer step does not clock the length counters.
+    fn length_clock_pending(&self) -> bool {
+        self.frame_step % 2 == 1
+    }
+
+    /// Handles the length enable and trigger bits of NRx4.
+    ///
+    /// Enabling the length counter in the first half of a length period
+    /// clocks it once extra, triggering with an expired counter reloads it.
+    fn write_length_control(length: &mut LengthCounter, value: u8, extra_clock: bool) -> bool {
+        let was_enabled = length.enabled;
+        let trigger = (value & 0x80) != 0;
+        let mut expired = false;
+        length.enabled = (value & 0x40) != 0;
+
+        if extra_clock && !was_enabled && length.enabled && length.counter > 0 {
+            length.counter -= 1;
+            expired = length.counter == 0 && !trigger;
+        }
+
+        if trigger && length.counter == 0 {
+            length.counter = length.max;
+
+            if extra_clock && length.enabled {
+                length.counter -= 1;
+            }
+        }
+
+        expired
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        if (WAVE_RAM_START..=0xFF3F).contains(&address) {
+            return match self.wave_ram_index(address) {
+                Some(index) => self.wave_ram[index],
+                None => 0xFF,
+            };
+        }
+
+        // Write-only and unused bits read back as 1
+        match HardwareRegister::from_u16(address) {
+            Some(HardwareRegister::NR10) => {
+                0x80 | self.square1.sweep.as_ref().map_or(0, |s| s.register)
+            }
+            Some(HardwareRegister::NR11) => 0x3F | (self.square1.duty << 6),
+            Some(HardwareRegister::NR12) => self.square1.envelope.register,
+            Some(HardwareRegister::NR14) => 0xBF | ((self.square1.length.enabled as u8) << 6),
+            Some(HardwareRegister::NR21) => 0x3F | (self.square2.duty << 6),
+            Some(HardwareRegister::NR22) => self.square2.envelope.register,
+            Some(HardwareRegister::NR24) => 0xBF | ((self.square2.length.enabled as u8) << 6),
+            Some(HardwareRegister::NR30) => 0x7F | ((self.wave.dac_enabled as u8) << 7),
+            Some(HardwareRegister::NR32) => 0x9F | (self.wave.output_level << 5),
+            Some(HardwareRegister::NR34) => 0xBF | ((self.wave.length.enabled as u8) << 6),
+            Some(HardwareRegister::NR42) => self.noise.envelope.register,
+            Some(HardwareRegister::NR43) => self.noise.polynomial,
+            Some(HardwareRegister::NR44) => 0xBF | ((self.noise.length.enabled as u8) << 6),
+            Some(HardwareRegister::NR50) => self.nr50,
+            Some(HardwareRegister::NR51) => self.nr51,
+            Some(HardwareRegister::NR52) => {
+                0x70 | ((self.enabled as u8) << 7)
+                    | (self.square1.enabled as u8)
+                    | ((self.square2.enabled as u8) << 1)
+                    | ((self.wave.enabled as u8) << 2)
+                    | ((self.noise.enabled as u8) << 3)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        if (WAVE_RAM_START..=0xFF3F).contains(&address) {
+            if let Some(index) = self.wave_ram_index(address) {
+                self.wave_ram[index] = value;
+            }
+            return;
+        }
+
+        let register = HardwareRegister::from_u16(address);
+
+        if register == Some(HardwareRegister::NR52) {
+            self.write_nr52(value);
+            return;
+        }
+
+        if !self.enabled {
+            // While powered off only the DMG length counters can be written
+            match register {
+                Some(HardwareRegister::NR11) => self.square1.length.load(value & 0x3F),
+                Some(HardwareRegister::NR21) => self.square2.length.load(value & 0x3F),
+                Some(HardwareRegister::NR31) => self.wave.length.load(value),
+                Some(HardwareRegister::NR41) => self.noise.length.load(value & 0x3F),
+                _ => (),
+            }
+            return;
+        }
+
+        let extra_clock = self.length_clock_pending();
+
+        match register {
+            Some(HardwareRegister::NR10) => {
+                if let Some(sweep) = &mut self.square1.sweep {
+                    let negate_cleared = (sweep.register & 0b1000) != 0 && (value & 0b1000) == 0;
+                    sweep.register = value & 0x7F;
+
+                    // Leaving negate mode after it was used disables the channel
+                    if negate_cleared && sweep.negate_used {
+                        self.square1.enabled = false;
+                    }
+                }
+            }
+            Some(HardwareRegister::NR11) => {
+                self.square1.duty = value >> 6;
+                self.square1.length.load(value & 0x3F);
+            }
+            Some(HardwareRegister::NR12) => {
+                self.square1.envelope.register = value;
+
+                if !self.square1.envelope.dac_enabled() {
+                    self.square1.enabled = false;
+                }
+            }
+            Some(HardwareRegister::NR13) => {
+                self.square1.frequency = (self.square1.frequency & 0x700) | (value as u16);
+            }
+            Some(HardwareRegister::NR14) => {
+                let channel = &mut self.square1;
+                channel.frequency = (channel.frequency & 0xFF) | (((value & 0b111) as u16) << 8);
+
+                if APU::write_length_control(&mut channel.length, value, extra_clock) {
+                    channel.enabled = false;
+                }
+
+                if (value & 0x80) != 0 {
+                    channel.trigger();
+                }
+            }
+            Some(HardwareRegister::NR21) => {
+                self.square2.duty = value >> 6;
+                self.square2.length.load(value & 0x3F);
+            }
+            Some(HardwareRegister::NR22) => {
+                self.square2.envelope.register = value;
+
+                if !self.square2.envelope.dac_enabled() {
+                    self.square2.enabled = false;
+                }
+            }
+            Some(HardwareRegister::NR23) => {
+                self.square2.frequency = (self.square2.frequency & 0x700) | (value as u16);
+            }
+            Some(HardwareRegister::NR24) => {
+                let channel = &mut self.square2;
+                channel.frequency = (channel.frequency & 0xFF) | (((value & 0b111) as u16) << 8);
+
+                if APU::write_length_control(&mut channel.length, value, extra_clock) {
+                    channel.enabled = false;
+                }
+
+                if (value & 0x80) != 0 {
+                    channel.trigger();
+                }
+            }
+            Some(HardwareRegister::NR30) => {
+                self.wave.dac_enabled = (value & 0x80) != 0;
+
+                if !self.wave.dac_enabled {
+                    self.wave.enabled = false;
+                }
+            }
+            Some(HardwareRegister::NR31) => self.wave.length.load(value),
+            Some(HardwareRegister::NR32) => self.wave.output_level = (value >> 5) & 0b11,
+            Some(HardwareRegister::NR33) => {
+                self.wave.frequency = (self.wave.frequency & 0x700) | (value as u16);
+            }
+            Some(HardwareRegister::NR34) => {
+                let channel = &mut self.wave;
+                channel.frequency = (channel.frequency & 0xFF) | (((value & 0b111) as u16) << 8);
+
+                if APU::write_length_control(&mut channel.length, value, extra_clock) {
+                    channel.enabled = false;
+                }
+
+                if (value & 0x80) != 0 {
+                    if self.wave_ram_quirks && channel.enabled && channel.timer <= 2 {
+                        self.corrupt_wave_ram();
+                    }
+
+                    // The sample buffer isn't refilled, the first sample
+                    // comes 3 APU cycles late
+                    let channel = &mut self.wave;
+                    channel.enabled = channel.dac_enabled;
+                    channel.position = 0;
+                    channel.timer = channel.period() + 6;
+                }
+            }
+            Some(HardwareRegister::NR41) => self.noise.length.load(value & 0x3F),
+            Some(HardwareRegister::NR42) => {
+                self.noise.envelope.register = value;
+
+                if !self.noise.envelope.dac_enabled() {
+                    self.noise.enabled = false;
+                }
+            }
+            Some(HardwareRegister::NR43) => self.noise.polynomial = value,
+            Some(HardwareRegister::NR44) => {
+                let channel = &mut self.noise;
+
+                if APU::write_length_control(&mut channel.length, value, extra_clock) {
+                    channel.enabled = false;
+                }
+
+                if (value & 0x80) != 0 {
+                    channel.trigger();
+                }
+            }
+            Some(HardwareRegister::NR50) => self.nr50 = value,
+            Some(HardwareRegister::NR51) => self.nr51 = value,
+            _ => (),
+        }
+    }
+
+    /// Left and right output, -1.0 to 1.0, after the DACs, the NR51 panning
+    /// and the NR50 master volume.
+    ///
+    /// A DAC that is on outputs its level even while its channel is stopped,
+    /// so the output has a DC offset for the host to filter out.
+    pub fn output(&self) -> (f32, f32) {
+        if !self.enabled {
+            return (0.0, 0.0);
+        }
+
+        let channel = |dac_enabled: bool, enabled: bool, sample: u8| match (dac_enabled, enabled) {
+            (false, _) => 0.0,
+            (true, false) => dac(0),
+            (true, true) => dac(sample),
+        };
+        let square1 = &self.square1;
+        let square2 = &self.square2;
+        let noise = &self.noise;
+        let channels = [
+            channel(
+                square1.envelope.dac_enabled(),
+                square1.enabled,
+                square1.output(),
+            ),
+            channel(
+                square2.envelope.dac_enabled(),
+                square2.enabled,
+                square2.output(),
+            ),
+            channel(self.wave.dac_enabled, self.wave.enabled, self.wave.output()),
+            channel(noise.envelope.dac_enabled(), noise.enabled, noise.output()),
+        ];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        for (i, sample) in channels.iter().enumerate() {
+            if (self.nr51 & (0x10 << i)) != 0 {
+                left += sample;
+            }
+            if (self.nr51 & (0x01 << i)) != 0 {
+                right += sample;
+            }
+        }
+
+        let left_volume = (((self.nr50 >> 4) & 0b111) + 1) as f32 / 8.0;
+        let right_volume = ((self.nr50 & 0b111) + 1) as f32 / 8.0;
+        (left / 4.0 * left_volume, right / 4.0 * right_volume)
+    }
+
+    /// Byte of wave RAM an access to `address` reaches, None when the
+    /// access is lost.
+    fn wave_ram_index(&self, address: u16) -> Option<usize> {
+        if self.wave_ram_quirks && self.wave.enabled {
+            self.wave.cpu_access()
+        } else {
+            Some((address - WAVE_RAM_START) as usize)
+        }
+    }
+
+    /// Triggering channel 3 on a DMG just as it fetches a byte overwrites
+    /// the first bytes of wave RAM with the ones being fetched: the byte
+    /// alone within the first 4, else its aligned group of 4.
+    fn corrupt_wave_ram(&mut self) {
+        let index = ((self.wave.position as usize + 1) % 32) / 2;
+
+        if index < 4 {
+            self.wave_ram[0] = self.wave_ram[index];
+        } else {
+            let start = index & !0b11;
+            self.wave_ram.copy_within(start..start + 4, 0);
+        }
+    }
+
+    fn write_nr52(&mut self, value: u8) {
+        let enable = (value & 0x80) != 0;
+
+        if self.enabled && !enable {
+            // Powering off clears every register, on DMG length counters survive
+            let lengths = [
+                self.square1.length.counter,
+                self.square2.length.counter,
+                self.wave.length.counter,
+                self.noise.length.counter,
+            ];
+            let wave_ram = self.wave_ram;
+
+            *self = APU {
+                enabled: false,
+                nr50: 0,
+                nr51: 0,
+                div_apu_bit: self.div_apu_bit,
+                wave_ram_quirks: self.wave_ram_quirks,
+                ..APU::new()
+            };
+
+            self.square1.length.counter = lengths[0];
+            self.square2.length.counter = lengths[1];
+            self.wave.length.counter = lengths[2];
+            self.noise.length.counter = lengths[3];
+            self.wave_ram = wave_ram;
+        } else if !self.enabled && enable {
+            self.enabled = true;
+            self.frame_step = 0;
+        }
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        APU::new()
+    }
+}
+
+impl Saveable for LengthCounter {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_u16(self.counter);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.counter = state.read_u16()?;
+        Ok(())
+    }
+}
+
+impl Saveable for Envelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.register);
+        state.write_u8(self.volume);
+        state.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.register = state.read_u8()?;
+        self.volume = state.read_u8()?;
+        self.timer = state.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Saveable for Sweep {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.register);
+        state.write_bool(self.enabled);
+        state.write_u8(self.timer);
+        state.write_u16(self.shadow_frequency);
+        state.write_bool(self.negate_used);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.register = state.read_u8()?;
+        self.enabled = state.read_bool()?;
+        self.timer = state.read_u8()?;
+        self.shadow_frequency = state.read_u16()?;
+        self.negate_used = state.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Saveable for SquareChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_u8(self.duty);
+        state.write_u16(self.frequency);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(state);
+        }
+
+        state.write_u16(self.timer);
+        state.write_u8(self.duty_step);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.duty = state.read_u8()?;
+        self.frequency = state.read_u16()?;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.load_state(state)?;
+        }
+
+        self.timer = state.read_u16()?;
+        self.duty_step = state.read_u8()? % 8;
+        Ok(())
+    }
+}
+
+impl Saveable for WaveChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_bool(self.dac_enabled);
+        state.write_u8(self.output_level);
+        state.write_u16(self.frequency);
+        self.length.save_state(state);
+        state.write_u16(self.timer);
+        state.write_u8(self.position);
+        state.write_u8(self.sample_buffer);
+        state.write_u8(self.fetched_ago);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.dac_enabled = state.read_bool()?;
+        self.output_level = state.read_u8()?;
+        self.frequency = state.read_u16()?;
+        self.length.load_state(state)?;
+        self.timer = state.read_u16()?;
+        self.position = state.read_u8()? % 32;
+        self.sample_buffer = state.read_u8()?;
+        self.fetched_ago = state.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Saveable for NoiseChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_u8(self.polynomial);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.write_u32(self.timer);
+        state.write_u16(self.lfsr);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.polynomial = state.read_u8()?;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.timer = state.read_u32()?;
+        self.lfsr = state.read_u16()? & 0x7FFF;
+        Ok(())
+    }
+}
+
+impl Resettable for APU {
+    fn reset(&mut self) {
+        *self = APU {
+            wave_ram_quirks: self.wave_ram_quirks,
+            ..APU::new()
+        };
+    }
+}
+
+impl Saveable for APU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_u8(self.nr50);
+        state.write_u8(self.nr51);
+        self.square1.save_state(state);
+        self.square2.save_state(state);
+        self.wave.save_state(state);
+        self.noise.save_state(state);
+        state.write_bytes(&self.wave_ram);
+        state.write_u8(self.frame_step);
+        state.write_bool(self.div_apu_bit);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.nr50 = state.read_u8()?;
+        self.nr51 = state.read_u8()?;
+        self.square1.load_state(state)?;
+        self.square2.load_state(state)?;
+        self.wave.load_state(state)?;
+        self.noise.load_state(state)?;
+        state.read_into(&mut self.wave_ram)?;
+        self.frame_step = state.read_u8()? % 8;
+        self.div_apu_bit = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use crate::cpu::Register;
+use crate::headless::Headless;
+use crate::watch::Watch;
+
+/// Something about the machine a test expects.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Check {
+    /// A watch expression has a value, like `[C000]` or `HL`
+    Equals(Watch, u16),
+    /// The serial output contains a text
+    Serial(String),
+}
+
+impl Check {
+    /// The byte at `address` is `value`.
+    pub fn memory(address: u16, value: u8) -> Self {
+        let watch = Watch::parse(&format!("[${address:04X}]")).expect("valid address");
+        Check::Equals(watch, value as u16)
+    }
+
+    pub fn register(register: Register, value: u16) -> Self {
+        let watch = Watch::parse(&format!("{register:?}")).expect("valid register");
+        Check::Equals(watch, value)
+    }
+
+    /// A watch expression, see `Watch`, has `value`.
+    pub fn equals(expression: &str, value: u16) -> Result<Self, String> {
+        Ok(Check::Equals(Watch::parse(expression)?, value))
+    }
+
+    pub fn serial(text: &str) -> Self {
+        Check::Serial(text.to_string())
+    }
+
+    fn holds(&self, emu: &Headless) -> bool {
+        match self {
+            Check::Equals(watch, value) => watch.evaluate(emu.cpu()) == *value,
+            Check::Serial(text) => emu.serial_output().contains(text.as_str()),
+        }
+    }
+
+    /// What was found instead, for the failure message.
+    fn actual(&self, emu: &Headless) -> String {
+        match self {
+            Check::Equals(watch, _) => format!("was {}", watch.show(emu.cpu())),
+            Check::Serial(_) => format!("output was {:?}", emu.serial_output()),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Check::Equals(watch, value) => write!(f, "{} == {value:02X}", watch.text()),
+            Check::Serial(text) => write!(f, "serial output contains {text:?}"),
+        }
+    }
+}
+
+/// Point in emulated time, frames as counted by `Emulator::get_current_frame`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Deadline {
+    Frame(u32),
+    /// T-cycles since power on
+    Cycle(u64),
+}
+
+impl Deadline {
+    fn is_reached(self, emu: &Headless) -> bool {
+        match self {
+            Deadline::Frame(frame) => emu.emulator().get_current_frame() >= frame,
+            Deadline::Cycle(ticks) => emu.ticks() >= ticks,
+        }
+    }
+}
+
+impl fmt::Display for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Deadline::Frame(frame) => write!(f, "frame {frame}"),
+            Deadline::Cycle(ticks) => write!(f, "cycle {ticks}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Assertion {
+    deadline: Deadline,
+    check: Check,
+    /// Checked once at the deadline, otherwise it may hold any time before
+    exact: bool,
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let when = if self.exact { "at" } else { "by" };
+        write!(f, "{when} {}: {}", self.deadline, self.check)
+    }
+}
+
+/// An assertion that didn't hold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssertionFailure {
+    /// The assertion, like `at frame 300: [$C000] == 05`
+    pub assertion: String,
+    /// What was found instead, or why the run ended
+    pub actual: String,
+    /// T-cycle the failure was noticed at
+    pub ticks: u64,
+}
+
+impl fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} failed at cycle {}: {}",
+            self.assertion, self.ticks, self.actual
+        )
+    }
+}
+
+impl Error for AssertionFailure {}
+
+/// Expectations about a ROM run, for regression tests driven by ROMs:
+///
+/// ```ignore
+/// TestScript::new()
+///     .at_frame(300, Check::memory(0xC000, 5))
+///     .by_cycle(1_000_000, Check::serial("Passed"))
+///     .run(&mut emu)?;
+/// ```
+///
+/// The run ends at the first assertion that fails or once every one held.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TestScript {
+    assertions: Vec<Assertion>,
+}
+
+impl TestScript {
+    pub fn new() -> Self {
+        TestScript::default()
+    }
+
+    fn with(mut self, deadline: Deadline, check: Check, exact: bool) -> Self {
+        self.assertions.push(Assertion {
+            deadline,
+            check,
+            exact,
+        });
+        self
+    }
+
+    /// `check` holds when frame `frame` starts.
+    pub fn at_frame(self, frame: u32, check: Check) -> Self {
+        self.with(Deadline::Frame(frame), check, true)
+    }
+
+    /// `check` holds once `ticks` T-cycles have run.
+    pub fn at_cycle(self, ticks: u64, check: Check) -> Self {
+        self.with(Deadline::Cycle(ticks), check, true)
+    }
+
+    /// `check` holds at some point before frame `frame` starts.
+    pub fn by_frame(self, frame: u32, check: Check) -> Self {
+        self.with(Deadline::Frame(frame), check, false)
+    }
+
+    /// `check` holds at some point within the first `ticks` T-cycles.
+    pub fn by_cycle(self, ticks: u64, check: Check) -> Self {
+        self.with(Deadline::Cycle(ticks), check, false)
+    }
+
+    /// Run `emu` until every assertion held or one failed. Checks are made
+    /// after every instruction, a CPU that stops fails what is still open.
+    pub fn run(&self, emu: &mut Headless) -> Result<(), AssertionFailure> {
+        let mut pending: Vec<&Assertion> = self.assertions.iter().collect();
+
+        while !pending.is_empty() {
+            let running = emu.step();
+            let mut failure = None;
+
+            pending.retain(|assertion| {
+                let reached = assertion.deadline.is_reached(emu);
+
+                if !assertion.exact && assertion.check.holds(emu) {
+                    return false;
+                }
+                if !reached {
+                    return true;
+                }
+
+                if (!assertion.exact || !assertion.check.holds(emu)) && failure.is_none() {
+                    failure = Some(AssertionFailure {
+                        assertion: assertion.to_string(),
+                        actual: assertion.check.actual(emu),
+                        ticks: emu.ticks(),
+                    });
+                }
+                false
+            });
+
+            if let Some(failure) = failure {
+                return Err(failure);
+            }
+
+            if !running && let Some(assertion) = pending.first() {
+                return Err(AssertionFailure {
+                    assertion: assertion.to_string(),
+                    actual: "the CPU stopped".to_string(),
+                    ticks: emu.ticks(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
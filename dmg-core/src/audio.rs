@@ -0,0 +1,193 @@
+//! Audio on its way from the APU to the host.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::emu::CLOCK_HZ;
+
+/// Gain ramp for the output when the emulator pauses and resumes.
+///
+/// Cutting the output off in the middle of a wave pops, so pausing brings
+/// the gain down to zero over a few milliseconds instead. Once silent the
+/// output can stop pulling samples and drop what it has buffered, and on
+/// resume it fills its buffer again before the gain comes back up.
+#[derive(Clone, Debug)]
+pub struct Fader {
+    gain: f32,
+    target: f32,
+    // Gain change per stereo frame
+    step: f32,
+}
+
+impl Fader {
+    /// Fade over `frames` stereo frames, e.g. 240 for 5 ms at 48 kHz.
+    pub fn new(frames: u32) -> Self {
+        Fader {
+            gain: 1.0,
+            target: 1.0,
+            step: 1.0 / frames.max(1) as f32,
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.target = 0.0;
+    }
+
+    pub fn resume(&mut self) {
+        self.target = 1.0;
+    }
+
+    /// Faded out completely, the output may stop and flush its buffer.
+    pub fn is_silent(&self) -> bool {
+        self.gain == 0.0 && self.target == 0.0
+    }
+
+    /// Scale interleaved stereo `samples`, moving the gain towards the target.
+    pub fn apply(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_mut(2) {
+            if self.gain < self.target {
+                self.gain = (self.gain + self.step).min(self.target);
+            } else if self.gain > self.target {
+                self.gain = (self.gain - self.step).max(self.target);
+            }
+
+            for sample in frame {
+                *sample *= self.gain;
+            }
+        }
+    }
+}
+
+impl Default for Fader {
+    /// 5 ms at 48 kHz.
+    fn default() -> Self {
+        Fader::new(240)
+    }
+}
+
+/// Receives the audio output in chunks of interleaved stereo samples, left
+/// first, see `AudioOutput`. Called on the emulation thread, so sinks should
+/// queue the samples rather than wait for the host to play them.
+pub trait AudioSink: Send {
+    fn on_samples(&mut self, samples: &[f32]);
+}
+
+impl<F: FnMut(&[f32]) + Send> AudioSink for F {
+    fn on_samples(&mut self, samples: &[f32]) {
+        self(samples)
+    }
+}
+
+/// Charge kept per dot by the high-pass filter of the DMG output.
+const CHARGE_PER_DOT: f32 = 0.999958;
+
+/// Resamples the output of the APU to the host rate and hands it to an
+/// `AudioSink` in chunks of a fixed number of stereo frames.
+///
+/// Every dot's output is averaged into the frame it falls in, then passed
+/// through the high-pass filter that removes the DC offset of the DACs, so
+/// every backend gets the same stream of f32 samples. `pause` fades the
+/// stream out through a `Fader` before the frontend stops the emulation.
+pub struct AudioOutput {
+    sink: Box<dyn AudioSink>,
+    sample_rate: u32,
+    chunk_frames: usize,
+    // Interleaved samples of the chunk being filled
+    chunk: Vec<f32>,
+    // Host rate times the dots since the last frame, a frame is due at CLOCK_HZ
+    phase: u64,
+    dots: u32,
+    sum: (f32, f32),
+    capacitor: (f32, f32),
+    // Charge kept per frame
+    charge: f32,
+    fader: Fader,
+}
+
+impl AudioOutput {
+    /// Output at `sample_rate` Hz in chunks of `chunk_frames` stereo frames,
+    /// e.g. 48000 and 512.
+    pub fn new(sink: Box<dyn AudioSink>, sample_rate: u32, chunk_frames: usize) -> Self {
+        let sample_rate = sample_rate.max(1);
+        let chunk_frames = chunk_frames.max(1);
+        let charge =
+            (0..CLOCK_HZ / sample_rate as u64).fold(1.0, |charge, _| charge * CHARGE_PER_DOT);
+
+        AudioOutput {
+            sink,
+            sample_rate,
+            chunk_frames,
+            chunk: Vec::with_capacity(chunk_frames * 2),
+            phase: 0,
+            dots: 0,
+            sum: (0.0, 0.0),
+            capacitor: (0.0, 0.0),
+            charge,
+            // 5 ms
+            fader: Fader::new(sample_rate / 200),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn chunk_frames(&self) -> usize {
+        self.chunk_frames
+    }
+
+    /// Fade the output out over the next 5 ms, see `is_silent`.
+    pub fn pause(&mut self) {
+        self.fader.pause();
+    }
+
+    /// Fade the output back in over 5 ms.
+    pub fn resume(&mut self) {
+        self.fader.resume();
+    }
+
+    /// Faded out completely, the emulation can stop without a pop.
+    pub fn is_silent(&self) -> bool {
+        self.fader.is_silent()
+    }
+
+    /// Add the output of one dot, see `APU::output`.
+    pub fn push(&mut self, (left, right): (f32, f32)) {
+        self.sum.0 += left;
+        self.sum.1 += right;
+        self.dots += 1;
+        self.phase += self.sample_rate as u64;
+
+        if self.phase < CLOCK_HZ {
+            return;
+        }
+
+        self.phase -= CLOCK_HZ;
+        let left = self.sum.0 / self.dots as f32;
+        let right = self.sum.1 / self.dots as f32;
+        self.sum = (0.0, 0.0);
+        self.dots = 0;
+
+        let left_out = left - self.capacitor.0;
+        let right_out = right - self.capacitor.1;
+        self.capacitor.0 = left - left_out * self.charge;
+        self.capacitor.1 = right - right_out * self.charge;
+
+        let mut frame = [left_out, right_out];
+        self.fader.apply(&mut frame);
+        self.chunk.extend_from_slice(&frame);
+
+        if self.chunk.len() == self.chunk_frames * 2 {
+            self.flush();
+        }
+    }
+
+    /// Hand the frames of the unfinished chunk to the sink, e.g. before
+    /// pausing.
+    pub fn flush(&mut self) {
+        if !self.chunk.is_empty() {
+            self.sink.on_samples(&self.chunk);
+            self.chunk.clear();
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::cpu::{CPU, Flags, RegisterFile};
+use crate::emu::Emulator;
+use crate::power::Model;
+use crate::snapshot::MachineSnapshot;
+use crate::state::{self, StateError};
+
+// BESS (Best Effort Save State) as specified by SameBoy: blocks with a four
+// character name and a 32-bit length, little endian, after the data of the
+// emulator and a footer with the offset of the first block and "BESS"
+const FOOTER_MAGIC: &[u8; 4] = b"BESS";
+const VERSION_MAJOR: u16 = 1;
+const VERSION_MINOR: u16 = 1;
+const CORE_LEN: usize = 0xD0;
+// Offsets into the CORE block
+const CORE_REGISTERS: usize = 0x08;
+const CORE_IO: usize = 0x18;
+const CORE_BUFFERS: usize = 0x98;
+/// Contents of the RTC block, the same as the RTC of a battery save.
+const RTC_LEN: usize = 0x30;
+
+/// Memory stored in buffers referenced by the CORE block, in its order.
+/// Color palettes don't exist on the DMG and stay empty.
+const BUFFERS: [Buffer; 5] = [
+    Buffer::Memory(0xC000, 0x2000),
+    Buffer::Memory(0x8000, 0x2000),
+    Buffer::CartridgeRam,
+    Buffer::Memory(0xFE00, 0xA0),
+    Buffer::Memory(0xFF80, 0x7F),
+];
+
+#[derive(Copy, Clone)]
+enum Buffer {
+    Memory(u16, usize),
+    CartridgeRam,
+}
+
+/// Append the BESS section of a savestate to `out`, which holds the state as
+/// this emulator saves it. Other BESS aware emulators like SameBoy load the
+/// registers, memory and mapper from it.
+pub(crate) fn append(cpu: &CPU<Emulator>, out: &mut Vec<u8>) {
+    let emu = cpu.context();
+
+    // The buffers have to be in the file uncompressed
+    let mut buffers = Vec::with_capacity(BUFFERS.len());
+    for buffer in BUFFERS {
+        let offset = out.len() as u32;
+        match buffer {
+            Buffer::Memory(start, len) => {
+                out.extend((start..start + len as u16).map(|address| inspect(emu, address)))
+            }
+            Buffer::CartridgeRam => {
+                out.extend_from_slice(emu.cartridge().map_or(&[], |rom| rom.ram()))
+            }
+        }
+        buffers.push((out.len() as u32 - offset, offset));
+    }
+
+    let first_block = out.len() as u32;
+    write_block(
+        out,
+        b"NAME",
+        concat!("dmg-emulator ", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+
+    if let Some(rom) = emu.cartridge() {
+        // Title and global checksum from the header
+        let mut info = vec![0; 0x12];
+        info[..0x10].copy_from_slice(&rom.data[0x134..0x144]);
+        info[0x10..].copy_from_slice(&rom.data[0x14E..0x150]);
+        write_block(out, b"INFO", &info);
+    }
+
+    let mut core = Vec::with_capacity(CORE_LEN);
+    core.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+    core.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+    core.extend_from_slice(model_id(emu.model()));
+
+    let r = cpu.registers();
+    let pair = |high: u8, low: u8| u16::from_be_bytes([high, low]).to_le_bytes();
+    core.extend_from_slice(&r.pc.to_le_bytes());
+    core.extend_from_slice(&pair(r.a, r.f.bits()));
+    core.extend_from_slice(&pair(r.b, r.c));
+    core.extend_from_slice(&pair(r.d, r.e));
+    core.extend_from_slice(&pair(r.h, r.l));
+    core.extend_from_slice(&r.sp.to_le_bytes());
+    core.push(cpu.ime() as u8);
+    core.push(inspect(emu, 0xFFFF));
+    core.push(cpu.execution_state());
+    core.push(0);
+    core.extend((0xFF00..0xFF80).map(|address| inspect(emu, address)));
+
+    for (len, offset) in buffers {
+        core.extend_from_slice(&len.to_le_bytes());
+        core.extend_from_slice(&offset.to_le_bytes());
+    }
+    // No color palettes
+    core.extend_from_slice(&[0; 16]);
+    write_block(out, b"CORE", &core);
+
+    if let Some(rom) = emu.cartridge() {
+        let writes = rom.mapper_writes();
+
+        if !writes.is_empty() {
+            let mut mbc = Vec::with_capacity(writes.len() * 3);
+            for (address, value) in writes {
+                mbc.extend_from_slice(&address.to_le_bytes());
+                mbc.push(value);
+            }
+            write_block(out, b"MBC ", &mbc);
+        }
+
+        let battery = rom.battery_data();
+        if battery.len() == rom.ram().len() + RTC_LEN {
+            write_block(out, b"RTC ", &battery[rom.ram().len()..]);
+        }
+    }
+
+    write_block(out, b"END ", &[]);
+    out.extend_from_slice(&first_block.to_le_bytes());
+    out.extend_from_slice(FOOTER_MAGIC);
+}
+
+/// Whether `data` ends with a BESS footer.
+pub fn has_footer(data: &[u8]) -> bool {
+    data.ends_with(FOOTER_MAGIC) && data.len() >= 8
+}
+
+/// The blocks of a BESS section with the CORE block and the buffers it
+/// points to checked.
+struct Section<'a> {
+    blocks: Vec<Block<'a>>,
+    core: &'a [u8],
+    buffers: Vec<&'a [u8]>,
+}
+
+impl<'a> Section<'a> {
+    fn read(data: &'a [u8]) -> Result<Self, StateError> {
+        let blocks = read_blocks(data)?;
+
+        let core = blocks
+            .iter()
+            .find(|(id, _)| id == b"CORE")
+            .map(|(_, body)| *body)
+            .ok_or(StateError::InvalidValue("BESS core block"))?;
+        if core.len() < CORE_LEN {
+            return Err(StateError::UnexpectedEnd);
+        }
+        if read_u16(core, 0) != VERSION_MAJOR {
+            return Err(StateError::InvalidValue("BESS version"));
+        }
+        // Game Boy Color states need hardware this emulator doesn't have
+        if !matches!(core[4], b'G' | b'S') {
+            return Err(StateError::InvalidValue("BESS model"));
+        }
+
+        let mut buffers = Vec::with_capacity(BUFFERS.len());
+        for index in 0..BUFFERS.len() {
+            let len = read_u32(core, CORE_BUFFERS + index * 8) as usize;
+            let offset = read_u32(core, CORE_BUFFERS + index * 8 + 4) as usize;
+            let buffer = data
+                .get(offset..offset + len)
+                .ok_or(StateError::InvalidValue("BESS buffer"))?;
+            buffers.push(buffer);
+        }
+
+        Ok(Section {
+            blocks,
+            core,
+            buffers,
+        })
+    }
+
+    fn block(&self, name: &[u8; 4]) -> Option<&'a [u8]> {
+        self.blocks
+            .iter()
+            .find(|(id, _)| id == name)
+            .map(|(_, body)| *body)
+    }
+
+    fn registers(&self) -> RegisterFile {
+        let pair = |offset: usize| read_u16(self.core, CORE_REGISTERS + offset).to_be_bytes();
+        let ([a, f], [b, c], [d, e], [h, l]) = (pair(2), pair(4), pair(6), pair(8));
+
+        RegisterFile {
+            a,
+            f: Flags::from_bits_truncate(f),
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            pc: read_u16(self.core, CORE_REGISTERS),
+            sp: read_u16(self.core, CORE_REGISTERS + 10),
+        }
+    }
+
+    fn io(&self) -> &'a [u8] {
+        &self.core[CORE_IO..CORE_IO + 0x80]
+    }
+
+    fn snapshot(&self) -> MachineSnapshot {
+        // Other emulators may keep more memory, like the banks of the GBC
+        let [wram, vram, cartridge_ram, oam, hram] = [0, 1, 2, 3, 4].map(|i| match BUFFERS[i] {
+            Buffer::Memory(_, len) => self.buffers[i][..len.min(self.buffers[i].len())].to_vec(),
+            Buffer::CartridgeRam => self.buffers[i].to_vec(),
+        });
+
+        MachineSnapshot {
+            registers: self.registers(),
+            ime: self.core[0x14] != 0,
+            ie: self.core[0x15],
+            execution_state: self.core[0x16],
+            io: self.io().to_vec(),
+            wram,
+            vram,
+            cartridge_ram,
+            oam,
+            hram,
+        }
+    }
+}
+
+/// Load a state another emulator saved with a BESS section, with the same
+/// cartridge inserted. The machine is power cycled first, so what BESS
+/// doesn't cover, like the position of the PPU in the frame, starts over.
+pub(crate) fn load(cpu: &mut CPU<Emulator>, data: &[u8]) -> Result<(), StateError> {
+    let section = Section::read(data)?;
+    let snapshot = section.snapshot();
+
+    state::hard_reset(cpu);
+    let emu = cpu.context_mut();
+
+    if let Some(mbc) = section.block(b"MBC ") {
+        for write in mbc.chunks_exact(3) {
+            emu.poke(read_u16(write, 0), write[2]);
+        }
+    }
+
+    snapshot.restore(cpu);
+
+    if let Some(rtc) = section.block(b"RTC ")
+        && let Some(rom) = cpu.context_mut().cartridge_mut()
+    {
+        let mut battery = rom.ram().to_vec();
+        battery.extend_from_slice(rtc);
+        rom.load_battery_data(&battery);
+    }
+    Ok(())
+}
+
+/// Global checksum of the ROM from the INFO block, None without one.
+pub(crate) fn global_checksum(data: &[u8]) -> Option<u16> {
+    let info = Section::read(data).ok()?.block(b"INFO")?;
+    Some(u16::from_be_bytes(info.get(0x10..0x12)?.try_into().ok()?))
+}
+
+/// Registers and memory of a state from its BESS section, without loading it.
+pub(crate) fn snapshot(data: &[u8]) -> Result<MachineSnapshot, StateError> {
+    Ok(Section::read(data)?.snapshot())
+}
+
+/// Name and contents of a block.
+type Block<'a> = ([u8; 4], &'a [u8]);
+
+/// Every block up to END.
+fn read_blocks(data: &[u8]) -> Result<Vec<Block<'_>>, StateError> {
+    if !has_footer(data) {
+        return Err(StateError::InvalidHeader);
+    }
+
+    let footer = data.len() - 8;
+    let mut position = read_u32(data, footer) as usize;
+    let mut blocks = Vec::new();
+
+    loop {
+        let header = data
+            .get(position..position + 8)
+            .filter(|_| position + 8 <= footer)
+            .ok_or(StateError::UnexpectedEnd)?;
+        let id: [u8; 4] = header[..4].try_into().unwrap();
+        let len = read_u32(header, 4) as usize;
+        let body = data
+            .get(position + 8..position + 8 + len)
+            .ok_or(StateError::UnexpectedEnd)?;
+        position += 8 + len;
+
+        if &id == b"END " {
+            return Ok(blocks);
+        }
+
+        blocks.push((id, body));
+    }
+}
+
+fn write_block(out: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+}
+
+/// Family, model, revision and a space.
+fn model_id(model: Model) -> &'static [u8; 4] {
+    match model {
+        Model::Dmg0 => b"GD0 ",
+        Model::Dmg => b"GDB ",
+        Model::Mgb => b"GM  ",
+        Model::Sgb => b"SN  ",
+        Model::Sgb2 => b"S2  ",
+    }
+}
+
+/// Memory as the CPU reads it, registers that aren't emulated read 0xFF.
+fn inspect(emu: &Emulator, address: u16) -> u8 {
+    emu.inspect(address).unwrap_or(0xFF)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,334 @@
+use super::cart::Cartridge;
+use super::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+// 0x0000 - 0x3FFF : ROM Bank 0
+// 0x4000 - 0x7FFF : ROM Bank 1 - Switchable
+// 0x8000 - 0x97FF : CHR RAM
+// 0x9800 - 0x9BFF : BG Map 1
+// 0x9C00 - 0x9FFF : BG Map 2
+// 0xA000 - 0xBFFF : Cartridge RAM
+// 0xC000 - 0xCFFF : RAM Bank 0
+// 0xD000 - 0xDFFF : RAM Bank 1-7 - switchable - Color only
+// 0xE000 - 0xFDFF : Reserved - Echo RAM
+// 0xFE00 - 0xFE9F : Object Attribute Memory
+// 0xFEA0 - 0xFEFF : Reserved - Unusable
+// 0xFF00 - 0xFF7F : I/O Registers
+// 0xFF80 - 0xFFFE : Zero Page or High RAM
+// 0xFFFF: Interrupt Enabled Register
+/// What a 256 byte page of the address space is mapped to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Page {
+    /// ROM banks and cartridge RAM, through the MBC
+    Cartridge,
+    Vram,
+    Wram,
+    /// Echo RAM, reads as 0
+    Echo,
+    /// OAM followed by the unusable area from 0xFEA0
+    Oam,
+    /// I/O registers, high RAM and IE
+    Io,
+}
+
+impl Page {
+    /// Page of `address`, a table lookup instead of comparing with every region.
+    pub fn of(address: u16) -> Page {
+        PAGES[(address >> 8) as usize]
+    }
+}
+
+const PAGES: [Page; 256] = {
+    let mut pages = [Page::Cartridge; 256];
+    let mut page = 0;
+
+    while page < pages.len() {
+        pages[page] = match page {
+            0x80..=0x9F => Page::Vram,
+            0xC0..=0xDF => Page::Wram,
+            0xE0..=0xFD => Page::Echo,
+            0xFE => Page::Oam,
+            0xFF => Page::Io,
+            _ => Page::Cartridge,
+        };
+        page += 1;
+    }
+
+    pages
+};
+
+#[derive(Debug)]
+pub struct MemoryBus {
+    bytes: [u8; 0xFFFF + 1],
+    rom: Option<Cartridge>,
+}
+
+/// P1/JOYP Joypad
+/// SB Serial transfer data
+/// SC Serial transfer control
+/// DIV Divider register
+/// TIMA Timer counter
+/// TMA Timer modulo
+/// TAC Timer control
+/// IF Interrupt flag
+/// LCDC LCD control
+/// STAT LCD status
+/// SCY Background viewport Y position
+/// SCX Background viewport X position
+/// LY LCD Y coordinate
+/// LCY LY Compare
+/// DMA OAM DMA source address & start
+/// BGP Background palette data
+/// OBP0 (Non-CGB Mode only) OBJ palette 0 data
+/// OBP1 (Non-CGB Mode only) OBJ palette 1 data
+/// WY Window Y position
+/// WX Window X position plus 7
+/// NR10-NR52 Audio channel, volume and panning registers
+/// IE Interrupt enable
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HardwareRegister {
+    P1_JOYP = 0xFF00,
+    SB = 0xFF01,
+    SC = 0xFF02,
+    DIV = 0xFF04,
+    TIMA = 0xFF05,
+    TMA = 0xFF06,
+    TAC = 0xFF07,
+    IF = 0xFF0F,
+    NR10 = 0xFF10,
+    NR11 = 0xFF11,
+    NR12 = 0xFF12,
+    NR13 = 0xFF13,
+    NR14 = 0xFF14,
+    NR21 = 0xFF16,
+    NR22 = 0xFF17,
+    NR23 = 0xFF18,
+    NR24 = 0xFF19,
+    NR30 = 0xFF1A,
+    NR31 = 0xFF1B,
+    NR32 = 0xFF1C,
+    NR33 = 0xFF1D,
+    NR34 = 0xFF1E,
+    NR41 = 0xFF20,
+    NR42 = 0xFF21,
+    NR43 = 0xFF22,
+    NR44 = 0xFF23,
+    NR50 = 0xFF24,
+    NR51 = 0xFF25,
+    NR52 = 0xFF26,
+    LCDC = 0xFF40,
+    STAT = 0xFF41,
+    SCY = 0xFF42,
+    SCX = 0xFF43,
+    LY = 0xFF44,
+    LYC = 0xFF45,
+    DMA = 0xFF46,
+    BGP = 0xFF47,
+    OBP0 = 0xFF48,
+    OBP1 = 0xFF49,
+    WY = 0xFF4A,
+    WX = 0xFF4B,
+    /// Unmaps the boot ROM when written
+    BOOT = 0xFF50,
+    /// Infrared port of the Game Boy Color
+    RP = 0xFF56,
+    IE = 0xFFFF,
+}
+
+impl HardwareRegister {
+    pub fn from_u16(address: u16) -> Option<HardwareRegister> {
+        match address {
+            x if x == HardwareRegister::P1_JOYP as u16 => Some(HardwareRegister::P1_JOYP),
+            x if x == HardwareRegister::SB as u16 => Some(HardwareRegister::SB),
+            x if x == HardwareRegister::SC as u16 => Some(HardwareRegister::SC),
+            x if x == HardwareRegister::DIV as u16 => Some(HardwareRegister::DIV),
+            x if x == HardwareRegister::TIMA as u16 => Some(HardwareRegister::TIMA),
+            x if x == HardwareRegister::TMA as u16 => Some(HardwareRegister::TMA),
+            x if x == HardwareRegister::TAC as u16 => Some(HardwareRegister::TAC),
+            x if x == HardwareRegister::IF as u16 => Some(HardwareRegister::IF),
+            x if x == HardwareRegister::NR10 as u16 => Some(HardwareRegister::NR10),
+            x if x == HardwareRegister::NR11 as u16 => Some(HardwareRegister::NR11),
+            x if x == HardwareRegister::NR12 as u16 => Some(HardwareRegister::NR12),
+            x if x == HardwareRegister::NR13 as u16 => Some(HardwareRegister::NR13),
+            x if x == HardwareRegister::NR14 as u16 => Some(HardwareRegister::NR14),
+            x if x == HardwareRegister::NR21 as u16 => Some(HardwareRegister::NR21),
+            x if x == HardwareRegister::NR22 as u16 => Some(HardwareRegister::NR22),
+            x if x == HardwareRegister::NR23 as u16 => Some(HardwareRegister::NR23),
+            x if x == HardwareRegister::NR24 as u16 => Some(HardwareRegister::NR24),
+            x if x == HardwareRegister::NR30 as u16 => Some(HardwareRegister::NR30),
+            x if x == HardwareRegister::NR31 as u16 => Some(HardwareRegister::NR31),
+            x if x == HardwareRegister::NR32 as u16 => Some(HardwareRegister::NR32),
+            x if x == HardwareRegister::NR33 as u16 => Some(HardwareRegister::NR33),
+            x if x == HardwareRegister::NR34 as u16 => Some(HardwareRegister::NR34),
+            x if x == HardwareRegister::NR41 as u16 => Some(HardwareRegister::NR41),
+            x if x == HardwareRegister::NR42 as u16 => Some(HardwareRegister::NR42),
+            x if x == HardwareRegister::NR43 as u16 => Some(HardwareRegister::NR43),
+            x if x == HardwareRegister::NR44 as u16 => Some(HardwareRegister::NR44),
+            x if x == HardwareRegister::NR50 as u16 => Some(HardwareRegister::NR50),
+            x if x == HardwareRegister::NR51 as u16 => Some(HardwareRegister::NR51),
+            x if x == HardwareRegister::NR52 as u16 => Some(HardwareRegister::NR52),
+            x if x == HardwareRegister::LCDC as u16 => Some(HardwareRegister::LCDC),
+            x if x == HardwareRegister::STAT as u16 => Some(HardwareRegister::STAT),
+            x if x == HardwareRegister::SCY as u16 => Some(HardwareRegister::SCY),
+            x if x == HardwareRegister::SCX as u16 => Some(HardwareRegister::SCX),
+            x if x == HardwareRegister::LY as u16 => Some(HardwareRegister::LY),
+            x if x == HardwareRegister::LYC as u16 => Some(HardwareRegister::LYC),
+            x if x == HardwareRegister::DMA as u16 => Some(HardwareRegister::DMA),
+            x if x == HardwareRegister::BGP as u16 => Some(HardwareRegister::BGP),
+            x if x == HardwareRegister::OBP0 as u16 => Some(HardwareRegister::OBP0),
+            x if x == HardwareRegister::OBP1 as u16 => Some(HardwareRegister::OBP1),
+            x if x == HardwareRegister::WY as u16 => Some(HardwareRegister::WY),
+            x if x == HardwareRegister::WX as u16 => Some(HardwareRegister::WX),
+            x if x == HardwareRegister::BOOT as u16 => Some(HardwareRegister::BOOT),
+            x if x == HardwareRegister::RP as u16 => Some(HardwareRegister::RP),
+            x if x == HardwareRegister::IE as u16 => Some(HardwareRegister::IE),
+            _ => None,
+        }
+    }
+}
+
+impl Default for MemoryBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryBus {
+    pub fn new() -> Self {
+        MemoryBus {
+            bytes: [0; 0xFFFF + 1],
+            rom: None,
+        }
+    }
+
+    pub fn from_rom(rom: Option<Cartridge>) -> Self {
+        MemoryBus {
+            bytes: [0; 0xFFFF + 1],
+            rom,
+        }
+    }
+
+    pub fn set_rom(&mut self, rom: Option<Cartridge>) {
+        self.rom = rom;
+    }
+
+    /// Put `rom` in and return the cartridge it replaced.
+    pub fn replace_rom(&mut self, rom: Option<Cartridge>) -> Option<Cartridge> {
+        core::mem::replace(&mut self.rom, rom)
+    }
+
+    pub fn rom(&self) -> Option<&Cartridge> {
+        self.rom.as_ref()
+    }
+
+    pub fn rom_mut(&mut self) -> Option<&mut Cartridge> {
+        self.rom.as_mut()
+    }
+
+    /// Advance the cartridge hardware by `dots` T-cycles.
+    pub fn tick(&mut self, dots: u64) {
+        if let Some(rom) = &mut self.rom {
+            rom.tick(dots);
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match Page::of(address) {
+            // An empty slot reads as open bus
+            Page::Cartridge => self.rom.as_ref().map_or(0xFF, |rom| rom.read(address)),
+            // In DMG mode, 0xD000 - 0xDFFF mirrors 0xC000 - 0xCFFF (RAM Bank 0).
+            // Diabled mirroring for now
+            // TODO: Should we enable it?
+            Page::Vram | Page::Wram | Page::Io => self.bytes[address as usize],
+            // Reserved, echo RAM
+            Page::Echo => 0,
+            // Reserved, unusable
+            Page::Oam if address >= 0xFEA0 => 0,
+            Page::Oam => self.bytes[address as usize],
+        }
+    }
+
+    /// WRAM and HRAM, memory no component but the bus has to see.
+    #[inline]
+    pub fn is_ram(address: u16) -> bool {
+        matches!(address, 0xC000..=0xDFFF | 0xFF80..=0xFFFE)
+    }
+
+    /// Read `address` straight from memory, only valid for `is_ram` addresses.
+    #[inline]
+    pub fn read_ram(&self, address: u16) -> u8 {
+        self.bytes[address as usize]
+    }
+
+    /// Write `address` straight to memory, only valid for `is_ram` addresses.
+    #[inline]
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        self.bytes[address as usize] = value;
+    }
+
+    pub fn read16(&self, address: u16) -> u16 {
+        let lo = self.read(address) as u16;
+        let hi = self.read(address + 1) as u16;
+        lo | (hi << 8)
+    }
+
+    pub fn read_register(&self, register: HardwareRegister) -> u8 {
+        let address = register as u16;
+        self.read(address)
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match Page::of(address) {
+            Page::Cartridge => {
+                if let Some(rom) = &mut self.rom {
+                    rom.write(address, value);
+                }
+            }
+            // TODO: Should we enable mirroring?
+            _ => self.bytes[address as usize] = value,
+        }
+    }
+
+    pub fn write16(&mut self, address: u16, value: u16) {
+        let lo = (value & 0x00FF) as u8;
+        let hi = ((value >> 8) & 0x00FF) as u8;
+        self.bytes[address as usize] = lo;
+        self.bytes[(address + 1) as usize] = hi;
+    }
+
+    pub fn write_register(&mut self, register: HardwareRegister, value: u8) {
+        let address = register as u16;
+        self.write(address, value);
+    }
+}
+
+impl Resettable for MemoryBus {
+    fn reset(&mut self) {
+        // The cartridge stays inserted
+        self.bytes = [0; 0xFFFF + 1];
+
+        if let Some(rom) = &mut self.rom {
+            rom.reset();
+        }
+    }
+}
+
+impl Saveable for MemoryBus {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.bytes);
+
+        if let Some(rom) = &self.rom {
+            rom.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.bytes)?;
+
+        if let Some(rom) = &mut self.rom {
+            rom.load_state(state)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+/// Size of the picture the sensor delivers.
+pub const SENSOR_WIDTH: usize = 128;
+pub const SENSOR_HEIGHT: usize = 112;
+
+// Registers in RAM bank 0x10, mirrored every 0x80 bytes
+const REGISTER_COUNT: usize = 0x36;
+const CAPTURE: usize = 0x00;
+const EDGE: usize = 0x01;
+const EXPOSURE_HIGH: usize = 0x02;
+const EXPOSURE_LOW: usize = 0x03;
+// 4x4 cells of three thresholds from dark to light
+const DITHER_MATRIX: usize = 0x06;
+
+/// Where a capture is stored in the first RAM bank, as 16x14 tiles.
+const IMAGE_OFFSET: usize = 0x100;
+/// Exposure that leaves the brightness of the image as it is.
+const UNIT_EXPOSURE: u32 = 0x1000;
+
+/// Image sensor of the Pocket Camera.
+///
+/// Instead of a camera the sensor sees an image set with `set_image`. A capture
+/// applies the exposure time and the dithering matrix the software programmed,
+/// the analog gain and edge enhancement are not emulated.
+#[derive(Debug)]
+pub struct Camera {
+    registers: [u8; REGISTER_COUNT],
+    // Brightness of every pixel, rows top to bottom
+    image: Vec<u8>,
+    // T-cycles until the running capture is done
+    capture_cycles: u32,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Camera {
+            registers: [0; REGISTER_COUNT],
+            image: vec![0x80; SENSOR_WIDTH * SENSOR_HEIGHT],
+            capture_cycles: 0,
+        }
+    }
+
+    /// Set what the sensor sees: SENSOR_WIDTH x SENSOR_HEIGHT brightness
+    /// values from black (0) to white (255).
+    pub fn set_image(&mut self, image: &[u8]) {
+        assert_eq!(image.len(), SENSOR_WIDTH * SENSOR_HEIGHT);
+        self.image.copy_from_slice(image);
+    }
+
+    /// Whether a capture is running, external RAM reads 0 meanwhile.
+    pub fn capturing(&self) -> bool {
+        self.capture_cycles > 0
+    }
+
+    /// Only the capture register can be read back.
+    pub fn read(&self, address: u16) -> u8 {
+        match (address & 0x7F) as usize {
+            CAPTURE => self.registers[CAPTURE],
+            _ => 0x00,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        let register = (address & 0x7F) as usize;
+
+        match register {
+            CAPTURE => {
+                self.registers[CAPTURE] = value & 0x07;
+
+                if value & 0x01 == 0 {
+                    self.capture_cycles = 0;
+                } else if !self.capturing() {
+                    self.capture_cycles = self.capture_time();
+                }
+            }
+            _ if register < REGISTER_COUNT => self.registers[register] = value,
+            _ => (),
+        }
+    }
+
+    /// Advance by `dots` T-cycles, a finished capture is written to `ram`.
+    pub fn tick(&mut self, ram: &mut [u8], dots: u64) {
+        if !self.capturing() {
+            return;
+        }
+
+        self.capture_cycles = self
+            .capture_cycles
+            .saturating_sub(dots.min(u32::MAX as u64) as u32);
+
+        if self.capture_cycles == 0 {
+            self.registers[CAPTURE] &= !0x01;
+            self.store_capture(ram);
+        }
+    }
+
+    fn exposure(&self) -> u32 {
+        u16::from_be_bytes([self.registers[EXPOSURE_HIGH], self.registers[EXPOSURE_LOW]]) as u32
+    }
+
+    /// Length of a capture from Pan Docs, 32446 M-cycles plus 16 per exposure
+    /// step and 512 more without the N flag.
+    fn capture_time(&self) -> u32 {
+        let n = self.registers[EDGE] & 0x80 != 0;
+        (32446 + if n { 0 } else { 512 } + 16 * self.exposure()) * 4
+    }
+
+    /// Dither the image with the programmed matrix into 2bpp tiles.
+    fn store_capture(&self, ram: &mut [u8]) {
+        let Some(tiles) =
+            ram.get_mut(IMAGE_OFFSET..IMAGE_OFFSET + SENSOR_WIDTH * SENSOR_HEIGHT / 4)
+        else {
+            return;
+        };
+        tiles.fill(0);
+
+        for y in 0..SENSOR_HEIGHT {
+            for x in 0..SENSOR_WIDTH {
+                let brightness = (self.image[y * SENSOR_WIDTH + x] as u32 * self.exposure()
+                    / UNIT_EXPOSURE)
+                    .min(0xFF) as u8;
+                let cell = DITHER_MATRIX + ((x & 3) + (y & 3) * 4) * 3;
+                let thresholds = &self.registers[cell..cell + 3];
+                // Below a threshold is darker, 3 is black
+                let color = 3 - thresholds.iter().take_while(|t| brightness >= **t).count() as u8;
+
+                let tile = (y / 8) * (SENSOR_WIDTH / 8) + x / 8;
+                let offset = tile * 16 + (y % 8) * 2;
+                let bit = 7 - (x % 8);
+                tiles[offset] |= (color & 1) << bit;
+                tiles[offset + 1] |= (color >> 1) << bit;
+            }
+        }
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resettable for Camera {
+    fn reset(&mut self) {
+        // The image is input like the buttons and stays
+        self.registers = [0; REGISTER_COUNT];
+        self.capture_cycles = 0;
+    }
+}
+
+impl Saveable for Camera {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.registers);
+        state.write_u32(self.capture_cycles);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.registers)?;
+        self.capture_cycles = state.read_u32()?;
+        Ok(())
+    }
+}
+
+/// Scale an RGBA image to the sensor size and convert it to brightness,
+/// for `Camera::set_image`. The image is stretched to fit.
+pub fn sensor_image(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), (width * height * 4) as usize);
+    let mut image = Vec::with_capacity(SENSOR_WIDTH * SENSOR_HEIGHT);
+
+    if width == 0 || height == 0 {
+        return vec![0xFF; SENSOR_WIDTH * SENSOR_HEIGHT];
+    }
+
+    for y in 0..SENSOR_HEIGHT {
+        for x in 0..SENSOR_WIDTH {
+            let source_x = x * width as usize / SENSOR_WIDTH;
+            let source_y = y * height as usize / SENSOR_HEIGHT;
+            let pixel = &rgba[(source_y * width as usize + source_x) * 4..][..4];
+            // Rec. 601 luma, transparent pixels are white like the paper behind them
+            let luma =
+                (pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000;
+            let alpha = pixel[3] as u32;
+            image.push(((luma * alpha + 255 * (255 - alpha)) / 255) as u8);
+        }
+    }
+
+    image
+}
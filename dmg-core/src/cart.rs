@@ -0,0 +1,927 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use crate::mbc::{Mapper, MapperRegister, MapperReport, RtcClock};
+use crate::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+/// Logo the boot ROM compares against 0x104 - 0x133 before starting a game.
+pub(crate) const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Problem found in a cartridge header by `CartridgeHeader::validate`.
+#[derive(Debug, PartialEq)]
+pub enum ValidationIssue {
+    /// The boot ROM would lock up on this logo
+    LogoMismatch,
+    /// The boot ROM would lock up on this checksum
+    HeaderChecksum {
+        stored: u8,
+        computed: u8,
+    },
+    /// Not checked by the hardware, but a sign of a corrupt or patched ROM
+    GlobalChecksum {
+        stored: u16,
+        computed: u16,
+    },
+    UnknownCartridgeType(u8),
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    RomSizeMismatch {
+        header: u32,
+        file: usize,
+    },
+    /// The cartridge type and the RAM size byte disagree
+    RamSizeMismatch {
+        cartridge_type: u8,
+        ram_size: u32,
+    },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::LogoMismatch => write!(f, "Nintendo logo does not match"),
+            ValidationIssue::HeaderChecksum { stored, computed } => write!(
+                f,
+                "header checksum is 0x{stored:02X}, computed 0x{computed:02X}"
+            ),
+            ValidationIssue::GlobalChecksum { stored, computed } => write!(
+                f,
+                "global checksum is 0x{stored:04X}, computed 0x{computed:04X}"
+            ),
+            ValidationIssue::UnknownCartridgeType(t) => {
+                write!(f, "unknown cartridge type 0x{t:02X}")
+            }
+            ValidationIssue::UnknownRomSize(code) => write!(f, "unknown ROM size 0x{code:02X}"),
+            ValidationIssue::UnknownRamSize(code) => write!(f, "unknown RAM size 0x{code:02X}"),
+            ValidationIssue::RomSizeMismatch { header, file } => {
+                write!(f, "header declares {header} bytes of ROM, file has {file}")
+            }
+            ValidationIssue::RamSizeMismatch {
+                cartridge_type,
+                ram_size,
+            } => write!(
+                f,
+                "cartridge type 0x{cartridge_type:02X} does not match {ram_size} bytes of RAM"
+            ),
+        }
+    }
+}
+
+/// Change made by `CartridgeHeader::fix`.
+#[derive(Debug, PartialEq)]
+pub enum HeaderFix {
+    /// Padded with 0xFF to the next valid ROM size
+    Padded {
+        from: usize,
+        to: usize,
+    },
+    RomSize {
+        from: u8,
+        to: u8,
+    },
+    HeaderChecksum {
+        from: u8,
+        to: u8,
+    },
+    GlobalChecksum {
+        from: u16,
+        to: u16,
+    },
+}
+
+impl fmt::Display for HeaderFix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderFix::Padded { from, to } => write!(f, "padded from {from} to {to} bytes"),
+            HeaderFix::RomSize { from, to } => {
+                write!(f, "ROM size 0x{from:02X} -> 0x{to:02X}")
+            }
+            HeaderFix::HeaderChecksum { from, to } => {
+                write!(f, "header checksum 0x{from:02X} -> 0x{to:02X}")
+            }
+            HeaderFix::GlobalChecksum { from, to } => {
+                write!(f, "global checksum 0x{from:04X} -> 0x{to:04X}")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct CartridgeHeader {
+    destination: String,
+    nintendo_logo: [u8; 48],
+    cgb_flag: bool,
+    sgb_flag: bool,
+    licensee: String,
+    title: String,
+    rom_size: u32,
+    rom_type: u8,
+    rom_type_name: String,
+    rom_version: u8,
+    ram_size: u32,
+    header_checksum: u8,
+    global_checksum: u16,
+    // Raw size bytes and what the header checksums should be, for `validate`
+    rom_size_code: u8,
+    ram_size_code: u8,
+    file_size: usize,
+    computed_header_checksum: u8,
+    computed_global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    /// Parse the header, which only fails if the ROM is too small to have one.
+    ///
+    /// Invalid values are reported by `validate` rather than rejected here.
+    pub fn load(rom_contents: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if rom_contents.len() < 0x150 {
+            return Err("ROM is too small to contain a header".into());
+        }
+
+        let nintendo_logo;
+
+        if let Ok(logo) = rom_contents[0x104..=0x133].try_into() {
+            nintendo_logo = logo;
+        } else {
+            nintendo_logo = [0_u8; 48];
+        }
+
+        Ok(CartridgeHeader {
+            destination: String::from(CartridgeHeader::get_destination(rom_contents)),
+            nintendo_logo,
+            cgb_flag: rom_contents[0x0143] == 0x80 || rom_contents[0x0143] == 0xC0,
+            sgb_flag: rom_contents[0x146] == 0x03,
+            licensee: String::from(CartridgeHeader::get_licensee(rom_contents)),
+            title: CartridgeHeader::get_game_title(rom_contents),
+            rom_size: CartridgeHeader::get_rom_size(rom_contents).unwrap_or(0),
+            rom_type: rom_contents[0x147],
+            rom_type_name: String::from(CartridgeHeader::get_rom_type(rom_contents)),
+            rom_version: rom_contents[0x14C],
+            ram_size: CartridgeHeader::get_ram_size(rom_contents).unwrap_or(0),
+            header_checksum: rom_contents[0x14D],
+            global_checksum: CartridgeHeader::get_global_checksum(rom_contents),
+            rom_size_code: rom_contents[0x148],
+            ram_size_code: rom_contents[0x149],
+            file_size: rom_contents.len(),
+            computed_header_checksum: CartridgeHeader::checksum(rom_contents),
+            computed_global_checksum: CartridgeHeader::compute_global_checksum(rom_contents),
+        })
+    }
+
+    /// Check the header the way the boot ROM does and for consistency
+    /// with the file, an empty list means no problems were found.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        if self.nintendo_logo != NINTENDO_LOGO {
+            issues.push(ValidationIssue::LogoMismatch);
+        }
+
+        if self.header_checksum != self.computed_header_checksum {
+            issues.push(ValidationIssue::HeaderChecksum {
+                stored: self.header_checksum,
+                computed: self.computed_header_checksum,
+            });
+        }
+
+        if self.global_checksum != self.computed_global_checksum {
+            issues.push(ValidationIssue::GlobalChecksum {
+                stored: self.global_checksum,
+                computed: self.computed_global_checksum,
+            });
+        }
+
+        if self.rom_type_name.is_empty() {
+            issues.push(ValidationIssue::UnknownCartridgeType(self.rom_type));
+        }
+
+        if self.rom_size == 0 {
+            issues.push(ValidationIssue::UnknownRomSize(self.rom_size_code));
+        } else if self.rom_size as usize != self.file_size {
+            issues.push(ValidationIssue::RomSizeMismatch {
+                header: self.rom_size,
+                file: self.file_size,
+            });
+        }
+
+        if self.ram_size_code as usize >= 6 {
+            issues.push(ValidationIssue::UnknownRamSize(self.ram_size_code));
+        } else if let Some(has_ram) = Self::type_has_ram(self.rom_type)
+            && has_ram != (self.ram_size > 0)
+        {
+            issues.push(ValidationIssue::RamSizeMismatch {
+                cartridge_type: self.rom_type,
+                ram_size: self.ram_size,
+            });
+        }
+
+        issues
+    }
+
+    /// Whether a cartridge type has external RAM, None for unknown types.
+    ///
+    /// MBC2 RAM is part of the controller and not declared in the header.
+    fn type_has_ram(cartridge_type: u8) -> Option<bool> {
+        match cartridge_type {
+            0x02 | 0x03 | 0x08 | 0x09 | 0x0C | 0x0D | 0x10 | 0x12 | 0x13 | 0x1A | 0x1B | 0x1D
+            | 0x1E | 0x22 | 0xFC | 0xFF => Some(true),
+            0x00 | 0x01 | 0x05 | 0x06 | 0x0B | 0x0F | 0x11 | 0x19 | 0x1C | 0x20 => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn checksum(rom_contents: &[u8]) -> u8 {
+        let mut sum: u8 = 0;
+        for byte in &rom_contents[0x0134..=0x014C] {
+            // Subtraction with overflow is not associative
+            sum = sum.wrapping_sub(*byte).wrapping_sub(1);
+        }
+
+        sum
+    }
+
+    /// Rewrite the checksums of a ROM image, with `pad` first pad it to the
+    /// next valid size, at least 32 KiB, and declare that size. Returns what
+    /// changed, nothing for a valid header. The image must reach past the
+    /// header, 0x150 bytes.
+    pub fn fix(rom_contents: &mut Vec<u8>, pad: bool) -> Vec<HeaderFix> {
+        let mut fixes = Vec::new();
+
+        if pad {
+            let size = rom_contents.len().next_power_of_two().max(0x8000);
+
+            if size != rom_contents.len() {
+                fixes.push(HeaderFix::Padded {
+                    from: rom_contents.len(),
+                    to: size,
+                });
+                rom_contents.resize(size, 0xFF);
+            }
+
+            let code = (size / 0x8000).trailing_zeros() as u8;
+
+            if rom_contents[0x148] != code {
+                fixes.push(HeaderFix::RomSize {
+                    from: rom_contents[0x148],
+                    to: code,
+                });
+                rom_contents[0x148] = code;
+            }
+        }
+
+        let header_checksum = CartridgeHeader::checksum(rom_contents);
+
+        if rom_contents[0x14D] != header_checksum {
+            fixes.push(HeaderFix::HeaderChecksum {
+                from: rom_contents[0x14D],
+                to: header_checksum,
+            });
+            rom_contents[0x14D] = header_checksum;
+        }
+
+        let global_checksum = CartridgeHeader::compute_global_checksum(rom_contents);
+        let stored = CartridgeHeader::get_global_checksum(rom_contents);
+
+        if stored != global_checksum {
+            fixes.push(HeaderFix::GlobalChecksum {
+                from: stored,
+                to: global_checksum,
+            });
+            rom_contents[0x14E..0x150].copy_from_slice(&global_checksum.to_be_bytes());
+        }
+
+        fixes
+    }
+
+    /// ROM size declared by the header in bytes, 0 if the size code is unknown.
+    pub fn rom_size(&self) -> u32 {
+        self.rom_size
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Cartridge type byte (0x147) and its name.
+    pub fn rom_type(&self) -> (u8, &str) {
+        (self.rom_type, &self.rom_type_name)
+    }
+
+    /// External RAM size declared by the header in bytes.
+    pub fn ram_size(&self) -> u32 {
+        self.ram_size
+    }
+
+    pub fn licensee(&self) -> &str {
+        &self.licensee
+    }
+
+    pub fn rom_version(&self) -> u8 {
+        self.rom_version
+    }
+
+    /// Checksum of the whole ROM (0x14E - 0x14F), identifies a game for per-game settings.
+    pub fn global_checksum(&self) -> u16 {
+        self.global_checksum
+    }
+
+    fn get_global_checksum(rom_contents: &[u8]) -> u16 {
+        ((rom_contents[0x14E] as u16) << 8) | (rom_contents[0x14F] as u16)
+    }
+
+    /// Sum of every ROM byte except the global checksum itself.
+    pub(crate) fn compute_global_checksum(rom_contents: &[u8]) -> u16 {
+        rom_contents
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 0x14E && *i != 0x14F)
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16))
+    }
+
+    fn get_game_title(rom_contents: &[u8]) -> String {
+        let bytes = &rom_contents[0x134..=0x143];
+        let mut title = String::new();
+
+        for (i, b) in bytes.iter().enumerate() {
+            if i > 15 {
+                break;
+            }
+
+            match char::from_u32(*b as u32) {
+                Some(ch) => {
+                    if ch != '\0' {
+                        title.push(ch);
+                    } else {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        title
+    }
+
+    fn get_destination(rom_contents: &[u8]) -> &'static str {
+        if rom_contents[0x014A] == 0 {
+            "Japan"
+        } else {
+            "Overseas"
+        }
+    }
+
+    fn get_rom_size(rom_contents: &[u8]) -> Option<u32> {
+        let known_sizes: BTreeMap<u8, u32> = BTreeMap::from([
+            (0x00, 32 * 1024),           // 32 KiB, 2 banks (no banking)
+            (0x01, 64 * 1024),           // 64 KiB, 4 banks
+            (0x02, 128 * 1024),          // 128 KiB, 8 banks
+            (0x03, 256 * 1024),          // 256 KiB, 16 banks
+            (0x04, 512 * 1024),          // 512 KiB, 32 banks
+            (0x05, 1024 * 1024),         // 1 MiB, 64 banks
+            (0x06, 2 * 1024 * 1024),     // 2 MiB, 128 banks
+            (0x07, 4 * 1024 * 1024),     // 4 MiB, 256 banks
+            (0x08, 8 * 1024 * 1024),     // 8 MiB, 512 banks
+            (0x52, 1_048_576 + 131_072), // 1.1 MiB, 72 banks
+            (0x53, 1_048_576 + 262_144), // 1.2 MiB, 80 banks
+            (0x54, 1_048_576 + 524_288), // 1.5 MiB, 96 banks
+        ]);
+
+        known_sizes.get(&rom_contents[0x148]).copied()
+    }
+
+    fn get_ram_size(rom_contents: &[u8]) -> Option<u32> {
+        let known_sizes: [u32; 6] = [
+            0,
+            0,
+            8 * 1024,   /*KiB, 1 bank*/
+            32 * 1024,  /* 4 banks of 8 KiB each */
+            128 * 1024, /* 16 banks of 8 KiB each */
+            64 * 1024,  /* 8 banks of 8 KiB each */
+        ];
+
+        known_sizes.get(rom_contents[0x149] as usize).copied()
+    }
+
+    fn get_rom_type(rom_contents: &[u8]) -> &'static str {
+        let cartridge_types: BTreeMap<u8, &'static str> = BTreeMap::from([
+            (0x00, "ROM ONLY"),
+            (0x01, "MBC1"),
+            (0x02, "MBC1+RAM"),
+            (0x03, "MBC1+RAM+BATTERY"),
+            (0x05, "MBC2"),
+            (0x06, "MBC2+BATTERY"),
+            (0x08, "ROM+RAM"),
+            (0x09, "ROM+RAM+BATTERY"),
+            (0x0B, "MMM01"),
+            (0x0C, "MMM01+RAM"),
+            (0x0D, "MMM01+RAM+BATTERY"),
+            (0x0F, "MBC3+TIMER+BATTERY"),
+            (0x10, "MBC3+TIMER+RAM+BATTERY"),
+            (0x11, "MBC3"),
+            (0x12, "MBC3+RAM"),
+            (0x13, "MBC3+RAM+BATTERY"),
+            (0x19, "MBC5"),
+            (0x1A, "MBC5+RAM"),
+            (0x1B, "MBC5+RAM+BATTERY"),
+            (0x1C, "MBC5+RUMBLE"),
+            (0x1D, "MBC5+RUMBLE+RAM"),
+            (0x1E, "MBC5+RUMBLE+RAM+BATTERY"),
+            (0x20, "MBC6"),
+            (0x22, "MBC7+SENSOR+RUMBLE+RAM+BATTERY"),
+            (0xFC, "POCKET CAMERA"),
+            (0xFD, "BANDAI TAMA5"),
+            (0xFE, "HuC3"),
+            (0xFF, "HuC1+RAM+BATTERY"),
+        ]);
+
+        cartridge_types
+            .get(&rom_contents[0x147])
+            .copied()
+            .unwrap_or("")
+    }
+
+    fn get_licensee(rom_contents: &[u8]) -> &'static str {
+        let new_licensee_map: BTreeMap<&'static str, &'static str> = BTreeMap::from([
+            ("00", "None"),
+            ("01", "Nintendo Research & Development 1"),
+            ("08", "Capcom"),
+            ("13", "EA (Electronic Arts)"),
+            ("18", "Hudson Soft"),
+            ("19", "B-AI"),
+            ("20", "KSS"),
+            ("22", "Planning Office WADA"),
+            ("24", "PCM Complete"),
+            ("25", "San-X"),
+            ("28", "Kemco"),
+            ("29", "SETA Corporation"),
+            ("30", "Viacom"),
+            ("31", "Nintendo"),
+            ("32", "Bandai"),
+            ("33", "Ocean Software/Acclaim Entertainment"),
+            ("34", "Konami"),
+            ("35", "HectorSoft"),
+            ("37", "Taito"),
+            ("38", "Hudson Soft"),
+            ("39", "Banpresto"),
+            ("41", "Ubi Soft1"),
+            ("42", "Atlus"),
+            ("44", "Malibu Interactive"),
+            ("46", "Angel"),
+            ("47", "Bullet-Proof Software2"),
+            ("49", "Irem"),
+            ("50", "Absolute"),
+            ("51", "Acclaim Entertainment"),
+            ("52", "Activision"),
+            ("53", "Sammy USA Corporation"),
+            ("54", "Konami"),
+            ("55", "Hi Tech Expressions"),
+            ("56", "LJN"),
+            ("57", "Matchbox"),
+            ("58", "Mattel"),
+            ("59", "Milton Bradley Company"),
+            ("60", "Titus Interactive"),
+            ("61", "Virgin Games Ltd.3"),
+            ("64", "Lucasfilm Games4"),
+            ("67", "Ocean Software"),
+            ("69", "EA (Electronic Arts)"),
+            ("70", "Infogrames5"),
+            ("71", "Interplay Entertainment"),
+            ("72", "Broderbund"),
+            ("73", "Sculptured Software6"),
+            ("75", "The Sales Curve Limited7"),
+            ("78", "THQ"),
+            ("79", "Accolade"),
+            ("80", "Misawa Entertainment"),
+            ("83", "lozc"),
+            ("86", "Tokuma Shoten"),
+            ("87", "Tsukuda Original"),
+            ("91", "Chunsoft Co.8"),
+            ("92", "Video System"),
+            ("93", "Ocean Software/Acclaim Entertainment"),
+            ("95", "Varie"),
+            ("96", "Yonezawa/s’pal"),
+            ("97", "Kaneko"),
+            ("99", "Pack-In-Video"),
+            ("9H", "Bottom Up"),
+            ("A4", "Konami (Yu-Gi-Oh!)"),
+            ("BL", "MTO"),
+            ("DK", "Kodansha"),
+        ]);
+
+        let old_licensee_map: BTreeMap<u8, &'static str> = BTreeMap::from([
+            (0x00, "None"),
+            (0x01, "Nintendo"),
+            (0x08, "Capcom"),
+            (0x09, "HOT-B"),
+            (0x0A, "Jaleco"),
+            (0x0B, "Coconuts Japan"),
+            (0x0C, "Elite Systems"),
+            (0x13, "EA (Electronic Arts)"),
+            (0x18, "Hudson Soft"),
+            (0x19, "ITC Entertainment"),
+            (0x1A, "Yanoman"),
+            (0x1D, "Japan Clary"),
+            (0x1F, "Virgin Games Ltd.3"),
+            (0x24, "PCM Complete"),
+            (0x25, "San-X"),
+            (0x28, "Kemco"),
+            (0x29, "SETA Corporation"),
+            (0x30, "Infogrames5"),
+            (0x31, "Nintendo"),
+            (0x32, "Bandai"),
+            (
+                0x33,
+                "Indicates that the New licensee code should be used instead.",
+            ),
+            (0x34, "Konami"),
+            (0x35, "HectorSoft"),
+            (0x38, "Capcom"),
+            (0x39, "Banpresto"),
+            (0x3C, "Entertainment Interactive (stub)"),
+            (0x3E, "Gremlin"),
+            (0x41, "Ubi Soft1"),
+            (0x42, "Atlus"),
+            (0x44, "Malibu Interactive"),
+            (0x46, "Angel"),
+            (0x47, "Spectrum HoloByte"),
+            (0x49, "Irem"),
+            (0x4A, "Virgin Games Ltd.3"),
+            (0x4D, "Malibu Interactive"),
+            (0x4F, "U.S. Gold"),
+            (0x50, "Absolute"),
+            (0x51, "Acclaim Entertainment"),
+            (0x52, "Activision"),
+            (0x53, "Sammy USA Corporation"),
+            (0x54, "GameTek"),
+            (0x55, "Park Place13"),
+            (0x56, "LJN"),
+            (0x57, "Matchbox"),
+            (0x59, "Milton Bradley Company"),
+            (0x5A, "Mindscape"),
+            (0x5B, "Romstar"),
+            (0x5C, "Naxat Soft14"),
+            (0x5D, "Tradewest"),
+            (0x60, "Titus Interactive"),
+            (0x61, "Virgin Games Ltd.3"),
+            (0x67, "Ocean Software"),
+            (0x69, "EA (Electronic Arts)"),
+            (0x6E, "Elite Systems"),
+            (0x6F, "Electro Brain"),
+            (0x70, "Infogrames5"),
+            (0x71, "Interplay Entertainment"),
+            (0x72, "Broderbund"),
+            (0x73, "Sculptured Software6"),
+            (0x75, "The Sales Curve Limited7"),
+            (0x78, "THQ"),
+            (0x79, "Accolade15"),
+            (0x7A, "Triffix Entertainment"),
+            (0x7C, "MicroProse"),
+            (0x7F, "Kemco"),
+            (0x80, "Misawa Entertainment"),
+            (0x83, "LOZC G."),
+            (0x86, "Tokuma Shoten"),
+            (0x8B, "Bullet-Proof Software2"),
+            (0x8C, "Vic Tokai Corp.16"),
+            (0x8E, "Ape Inc.17"),
+            (0x8F, "I’Max18"),
+            (0x91, "Chunsoft Co.8"),
+            (0x92, "Video System"),
+            (0x93, "Tsubaraya Productions"),
+            (0x95, "Varie"),
+            (0x96, "Yonezawa19/S’Pal"),
+            (0x97, "Kemco"),
+            (0x99, "Arc"),
+            (0x9A, "Nihon Bussan"),
+            (0x9B, "Tecmo"),
+            (0x9C, "Imagineer"),
+            (0x9D, "Banpresto"),
+            (0x9F, "Nova"),
+            (0xA1, "Hori Electric"),
+            (0xA2, "Bandai"),
+            (0xA4, "Konami"),
+            (0xA6, "Kawada"),
+            (0xA7, "Takara"),
+            (0xA9, "Technos Japan"),
+            (0xAA, "Broderbund"),
+            (0xAC, "Toei Animation"),
+            (0xAD, "Toho"),
+            (0xAF, "Namco"),
+            (0xB0, "Acclaim Entertainment"),
+            (0xB1, "ASCII Corporation or Nexsoft"),
+            (0xB2, "Bandai"),
+            (0xB4, "Square Enix"),
+            (0xB6, "HAL Laboratory"),
+            (0xB7, "SNK"),
+            (0xB9, "Pony Canyon"),
+            (0xBA, "Culture Brain"),
+            (0xBB, "Sunsoft"),
+            (0xBD, "Sony Imagesoft"),
+            (0xBF, "Sammy Corporation"),
+            (0xC0, "Taito"),
+            (0xC2, "Kemco"),
+            (0xC3, "Square"),
+            (0xC4, "Tokuma Shoten"),
+            (0xC5, "Data East"),
+            (0xC6, "Tonkin House"),
+            (0xC8, "Koei"),
+            (0xC9, "UFL"),
+            (0xCA, "Ultra Games"),
+            (0xCB, "VAP, Inc."),
+            (0xCC, "Use Corporation"),
+            (0xCD, "Meldac"),
+            (0xCE, "Pony Canyon"),
+            (0xCF, "Angel"),
+            (0xD0, "Taito"),
+            (0xD1, "SOFEL (Software Engineering Lab)"),
+            (0xD2, "Quest"),
+            (0xD3, "Sigma Enterprises"),
+            (0xD4, "ASK Kodansha Co."),
+            (0xD6, "Naxat Soft14"),
+            (0xD7, "Copya System"),
+            (0xD9, "Banpresto"),
+            (0xDA, "Tomy"),
+            (0xDB, "LJN"),
+            (0xDD, "Nippon Computer Systems"),
+            (0xDE, "Human Ent."),
+            (0xDF, "Altron"),
+            (0xE0, "Jaleco"),
+            (0xE1, "Towa Chiki"),
+            (0xE2, "Yutaka # Needs more info"),
+            (0xE3, "Varie"),
+            (0xE5, "Epoch"),
+            (0xE7, "Athena"),
+            (0xE8, "Asmik Ace Entertainment"),
+            (0xE9, "Natsume"),
+            (0xEA, "King Records"),
+            (0xEB, "Atlus"),
+            (0xEC, "Epic/Sony Records"),
+            (0xEE, "IGS"),
+            (0xF0, "A Wave"),
+            (0xF3, "Extreme Entertainment"),
+            (0xFF, "LJN"),
+        ]);
+
+        if rom_contents[0x014B] != 0x33 {
+            if let Some(name) = old_licensee_map.get(&rom_contents[0x014B]) {
+                return name;
+            } else {
+                log!(
+                    "Invalid old licensee hex code 0x{:X}.",
+                    rom_contents[0x014B]
+                );
+            }
+        } else if let Ok(code) = String::from_utf8(rom_contents[0x144..=0x145].to_vec()) {
+            if let Some(name) = new_licensee_map.get(code.as_str()) {
+                return name;
+            } else {
+                log!(
+                    "Invalid new licensee ASCII code [0x{}, 0x{}]",
+                    rom_contents[0x144],
+                    rom_contents[0x145]
+                );
+            }
+        } else {
+            log!(
+                "Invalid new licensee ASCII code [0x{}, 0x{}]",
+                rom_contents[0x144],
+                rom_contents[0x145]
+            );
+        }
+
+        ""
+    }
+}
+
+#[derive(Debug)]
+pub struct Cartridge {
+    pub file: String,
+    pub size: u32,
+    pub data: Vec<u8>,
+    pub header: CartridgeHeader,
+    ram: Vec<u8>,
+    mapper: Mapper,
+    // A ROM only cartridge selected a ROM bank, see `detect_undeclared_banking`
+    undeclared_banking: bool,
+    // The mapper was switched to MBC1 for it
+    inferred_mapper: bool,
+}
+
+impl Cartridge {
+    /// Create a cartridge from ROM contents, `file` is only used for reporting.
+    ///
+    /// Header problems don't prevent loading, see `CartridgeHeader::validate`.
+    pub fn from_bytes(file: &str, rom_contents: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let rom_header = CartridgeHeader::load(rom_contents)?;
+
+        Ok(Cartridge {
+            file: file.to_string(),
+            size: rom_contents.len() as u32,
+            data: rom_contents.to_vec(),
+            ram: vec![0; rom_header.ram_size as usize],
+            mapper: Mapper::from_cartridge_type(rom_header.rom_type),
+            header: rom_header,
+            undeclared_banking: false,
+            inferred_mapper: false,
+        })
+    }
+
+    /// Read from the ROM (0x0000 - 0x7FFF) or external RAM (0xA000 - 0xBFFF) area.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.mapper.read_rom(&self.data, address),
+            _ => self.mapper.read_ram(&self.ram, address),
+        }
+    }
+
+    /// Write to the ROM area (mapper registers) or external RAM.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => self.mapper.write_rom(address, value),
+            _ => self.mapper.write_ram(&mut self.ram, address, value),
+        }
+    }
+
+    /// Advance by `dots` T-cycles.
+    pub fn tick(&mut self, dots: u64) {
+        self.mapper.tick(&mut self.ram, dots);
+    }
+
+    /// Whether a write of `value` to 0x2000 - 0x3FFF is a ROM only
+    /// cartridge larger than 32 KiB selecting a bank past 1, true only the
+    /// first time. With `infer` the cartridge becomes MBC1 before the write
+    /// goes through, the header is left as it is.
+    pub fn detect_undeclared_banking(&mut self, value: u8, infer: bool) -> bool {
+        if self.undeclared_banking
+            || !matches!(self.mapper, Mapper::RomOnly)
+            || self.data.len() <= 0x8000
+            || value & 0x1F <= 1
+        {
+            return false;
+        }
+
+        self.undeclared_banking = true;
+        if infer {
+            self.mapper = Mapper::mbc1();
+            self.inferred_mapper = true;
+        }
+        true
+    }
+
+    /// Whether the cartridge switched banks though its header says ROM only.
+    pub fn undeclared_banking(&self) -> bool {
+        self.undeclared_banking
+    }
+
+    /// Whether the mapper was switched to MBC1 for that.
+    pub fn inferred_mapper(&self) -> bool {
+        self.inferred_mapper
+    }
+
+    /// Why code at `address` can't be executed from the cartridge: a ROM bank
+    /// past the end of the ROM or external RAM that is disabled or missing.
+    pub fn unmapped_execution(&self, address: u16) -> Option<String> {
+        match address {
+            0x4000..=0x7FFF if self.mapper.rom_offset(address) >= self.data.len() => Some(format!(
+                "executing ${address:04X} in ROM bank {}, the ROM has {} banks",
+                self.mapper.rom_offset(address) / 0x4000,
+                self.data.len().div_ceil(0x4000)
+            )),
+            0xA000..=0xBFFF if !self.mapper.ram_mapped(self.ram.len(), address) => Some(format!(
+                "executing ${address:04X} with external RAM disabled or missing"
+            )),
+            _ => None,
+        }
+    }
+
+    /// ROM bank mapped at `address`, 0 outside ROM like in `.sym` files.
+    pub fn bank_of(&self, address: u16) -> u16 {
+        match address {
+            0x0000..=0x7FFF => (self.mapper.rom_offset(address) / 0x4000) as u16,
+            _ => 0,
+        }
+    }
+
+    /// Banks, RAM enable and RTC of the mapper, see `MapperReport`.
+    pub fn mapper_report(&self) -> MapperReport {
+        self.mapper.report()
+    }
+
+    /// Mapper register a write to `address` in the ROM area goes to.
+    pub fn mapper_register(&self, address: u16) -> MapperRegister {
+        self.mapper.register_at(address)
+    }
+
+    /// Select the time source of the MBC3 RTC, ignored by cartridges without one.
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        self.mapper.set_rtc_clock(clock);
+    }
+
+    /// Set what the Pocket Camera sees, see `Camera::set_image`. Ignored by
+    /// other cartridges.
+    pub fn set_camera_image(&mut self, image: &[u8]) {
+        if let Some(camera) = self.mapper.camera_mut() {
+            camera.set_image(image);
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.header.rom_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFC | 0xFF
+        )
+    }
+
+    /// External RAM, all banks.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// RAM enable, ROM bank and RAM bank registers, None without a mapper.
+    pub fn mapper_registers(&self) -> Option<[u8; 3]> {
+        self.mapper.registers()
+    }
+
+    /// Register writes that put the mapper back into its current state, for
+    /// the MBC block of BESS states.
+    pub fn mapper_writes(&self) -> Vec<(u16, u8)> {
+        self.mapper.register_writes()
+    }
+
+    /// Contents of a battery save file: external RAM followed by the RTC, if any.
+    pub fn battery_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        self.mapper.save_battery(&mut data);
+        data
+    }
+
+    pub fn load_battery_data(&mut self, data: &[u8]) {
+        let ram_len = self.ram.len().min(data.len());
+        self.ram[..ram_len].copy_from_slice(&data[..ram_len]);
+        self.mapper.load_battery(&data[ram_len..]);
+    }
+}
+
+impl fmt::Display for Cartridge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rom_header = &self.header;
+
+        writeln!(f, "Cartridge Loaded:")?;
+        writeln!(f, "\t Title    : {}", rom_header.title)?;
+        writeln!(
+            f,
+            "\t Type     : {} ({})",
+            rom_header.rom_type, rom_header.rom_type_name
+        )?;
+        writeln!(f, "\t ROM Size : {} KB", rom_header.rom_size / 1024)?;
+        writeln!(f, "\t RAM Size : {} KB", rom_header.ram_size / 1024)?;
+        writeln!(
+            f,
+            "\t LIC Code : {} ({})",
+            self.data[0x014B], rom_header.licensee
+        )?;
+        write!(f, "\t ROM Vers : {}", rom_header.rom_version)
+    }
+}
+
+impl Resettable for Cartridge {
+    fn reset(&mut self) {
+        // External RAM is battery backed or at least not cleared by a reset
+        self.mapper.reset();
+    }
+}
+
+impl Saveable for Cartridge {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.undeclared_banking);
+        state.write_bool(self.inferred_mapper);
+        self.mapper.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.ram)?;
+        self.undeclared_banking = state.read_bool()?;
+        let inferred_mapper = state.read_bool()?;
+
+        // The layout of the mapper state depends on which one it is
+        if inferred_mapper != self.inferred_mapper {
+            self.mapper = match inferred_mapper {
+                true => Mapper::mbc1(),
+                false => Mapper::from_cartridge_type(self.header.rom_type),
+            };
+            self.inferred_mapper = inferred_mapper;
+        }
+        self.mapper.load_state(state)
+    }
+}
@@ -0,0 +1,203 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+/// Decoded cheat code.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CheatCode {
+    /// Replaces a ROM byte as it is read, optionally only when the original
+    /// byte matches `compare` (ABC-DEF or ABC-DEF-GHI).
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Writes a byte to memory once per frame (TTVVAAAA, address little-endian).
+    GameShark { address: u16, value: u8 },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CheatError {
+    InvalidCode(String),
+    InvalidLine(usize),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheatError::InvalidCode(code) => write!(f, "invalid cheat code {code}"),
+            CheatError::InvalidLine(line) => write!(f, "invalid cheat on line {line}"),
+        }
+    }
+}
+
+impl Error for CheatError {}
+
+impl CheatCode {
+    pub fn parse(code: &str) -> Result<Self, CheatError> {
+        let invalid = || CheatError::InvalidCode(code.to_string());
+        let digits: Vec<u8> = code
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+
+        match digits.len() {
+            6 | 9 => {
+                // ABC-DEF-GHI: AB new value, FCDE address with F inverted,
+                // GI compare value rotated and scrambled, H unused
+                let d = &digits;
+                let value = (d[0] << 4) | d[1];
+                let address = (((d[5] ^ 0xF) as u16) << 12)
+                    | ((d[2] as u16) << 8)
+                    | ((d[3] as u16) << 4)
+                    | (d[4] as u16);
+                let compare = if d.len() == 9 {
+                    Some(((d[6] << 4) | d[8]).rotate_right(2) ^ 0xBA)
+                } else {
+                    None
+                };
+
+                Ok(CheatCode::GameGenie {
+                    address,
+                    value,
+                    compare,
+                })
+            }
+            8 => {
+                let byte = |i: usize| (digits[i] << 4) | digits[i + 1];
+
+                Ok(CheatCode::GameShark {
+                    address: u16::from_le_bytes([byte(4), byte(6)]),
+                    value: byte(2),
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+pub struct Cheat {
+    /// Code as entered by the user
+    pub text: String,
+    pub code: CheatCode,
+    pub description: String,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    pub fn new(text: &str, description: &str) -> Result<Self, CheatError> {
+        Ok(Cheat {
+            text: text.to_string(),
+            code: CheatCode::parse(text)?,
+            description: description.to_string(),
+            enabled: true,
+        })
+    }
+}
+
+/// Cheats of a single game.
+///
+/// Stored as text, one cheat per line: `on` or `off`, the code and
+/// the rest of the line as its description. Lines starting with `#` are comments.
+///
+/// ```text
+/// on 01FF19C6 Infinite health
+/// off 00A-17B-C49 Start with 9 lives
+/// ```
+#[derive(Default)]
+pub struct CheatList {
+    cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    pub fn new() -> Self {
+        CheatList { cheats: Vec::new() }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, CheatError> {
+        let mut cheats = Vec::new();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid_line = || CheatError::InvalidLine(line_index + 1);
+            let (state, rest) = line.split_once(' ').ok_or_else(invalid_line)?;
+            let (code, description) = rest.trim_start().split_once(' ').unwrap_or((rest, ""));
+
+            let enabled = match state {
+                "on" => true,
+                "off" => false,
+                _ => return Err(invalid_line()),
+            };
+
+            let mut cheat = Cheat::new(code, description.trim())?;
+            cheat.enabled = enabled;
+            cheats.push(cheat);
+        }
+
+        Ok(CheatList { cheats })
+    }
+
+    pub fn push(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Cheat> {
+        self.cheats.iter_mut()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    /// Value a Game Genie code substitutes for the ROM byte `original` at `address`.
+    pub(crate) fn patch_rom(&self, address: u16, original: u8) -> u8 {
+        for cheat in self.cheats.iter().filter(|c| c.enabled) {
+            if let CheatCode::GameGenie {
+                address: cheat_address,
+                value,
+                compare,
+            } = cheat.code
+                && cheat_address == address
+                && compare.is_none_or(|compare| compare == original)
+            {
+                return value;
+            }
+        }
+
+        original
+    }
+
+    /// Memory writes the GameShark codes perform every frame.
+    pub(crate) fn frame_writes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.cheats
+            .iter()
+            .filter(|c| c.enabled)
+            .filter_map(|cheat| match cheat.code {
+                CheatCode::GameShark { address, value } => Some((address, value)),
+                CheatCode::GameGenie { .. } => None,
+            })
+    }
+}
+
+impl fmt::Display for CheatList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for cheat in &self.cheats {
+            let state = if cheat.enabled { "on" } else { "off" };
+            writeln!(f, "{state} {} {}", cheat.text, cheat.description)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use core::fmt;
+
+use crate::emu::DOTS_PER_FRAME;
+
+/// Dots per scanline.
+const DOTS_PER_LINE: u64 = 456;
+
+/// When a debug event happened, the same for trace lines, interrupts,
+/// serial exchanges, warnings and mapper writes so their logs line up.
+///
+/// `ticks` alone orders events. `frame` is counted from it like
+/// `Emulator::frame_count`, `ly` and `dot` are where the PPU was in its
+/// frame, which drifts from `ticks` once the game turns the LCD off.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EmuClock {
+    /// T-cycles since power on
+    pub ticks: u64,
+    pub frame: u64,
+    /// Scanline, 144 - 153 are VBlank
+    pub ly: u8,
+    /// Dot within the scanline, 0 - 455
+    pub dot: u16,
+}
+
+impl EmuClock {
+    pub fn new(ticks: u64, ly: u8, dot: u16) -> Self {
+        EmuClock {
+            ticks,
+            frame: ticks / DOTS_PER_FRAME,
+            ly,
+            dot,
+        }
+    }
+
+    /// Clock of a PPU that never stopped, for contexts without one.
+    pub fn from_ticks(ticks: u64) -> Self {
+        let in_frame = ticks % DOTS_PER_FRAME;
+        EmuClock::new(
+            ticks,
+            (in_frame / DOTS_PER_LINE) as u8,
+            (in_frame % DOTS_PER_LINE) as u16,
+        )
+    }
+}
+
+/// `ticks f<frame> <ly>:<dot>`, like `1234567 f17 044:120`.
+impl fmt::Display for EmuClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} f{} {:03}:{:03}",
+            self.ticks, self.frame, self.ly, self.dot
+        )
+    }
+}
@@ -0,0 +1,158 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+// LZ4 block format: a token with the literal and match lengths, the literals,
+// a 16-bit little endian offset back into the output and the match length
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = 0xFFFF;
+// The last match has to start 12 bytes and end 5 bytes before the end
+const MATCH_LIMIT: usize = 12;
+const LAST_LITERALS: usize = 5;
+const HASH_BITS: u32 = 12;
+// Each byte of a length adds at most 255 to a match
+const MAX_RATIO: usize = 255;
+
+/// Compress `data` as an LZ4 block.
+///
+/// Savestates are mostly empty RAM and repeated tile data, a greedy match
+/// finder with a small hash table shrinks them several times over while
+/// staying fast enough to run every frame.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 4 + 16);
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut pos = 0;
+
+    while pos + MATCH_LIMIT < data.len() {
+        let sequence = read_u32(data, pos);
+        let slot = hash(sequence);
+        let candidate = table[slot];
+        table[slot] = pos;
+
+        if candidate >= pos || pos - candidate > MAX_OFFSET || read_u32(data, candidate) != sequence
+        {
+            pos += 1;
+            continue;
+        }
+
+        let end = data.len() - LAST_LITERALS;
+        let mut len = MIN_MATCH;
+        while pos + len < end && data[candidate + len] == data[pos + len] {
+            len += 1;
+        }
+
+        write_sequence(
+            &mut out,
+            &data[literal_start..pos],
+            Some((pos - candidate, len)),
+        );
+        pos += len;
+        literal_start = pos;
+    }
+
+    write_sequence(&mut out, &data[literal_start..], None);
+    out
+}
+
+/// Decompress an LZ4 block that expands to exactly `len` bytes, None when it
+/// is malformed.
+pub fn decompress(data: &[u8], len: usize) -> Option<Vec<u8>> {
+    // Checked before allocating, the length comes with the data
+    if len > data.len().saturating_mul(MAX_RATIO) {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(len);
+    let mut pos = 0;
+
+    loop {
+        let token = *data.get(pos)?;
+        pos += 1;
+
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals += read_length(data, &mut pos)?;
+        }
+
+        let end = pos.checked_add(literals)?;
+        if out.len() + literals > len {
+            return None;
+        }
+        out.extend_from_slice(data.get(pos..end)?);
+        pos = end;
+
+        // The last sequence has no match
+        if pos == data.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+        pos += 2;
+
+        let mut match_len = (token & 0x0F) as usize;
+        if match_len == 15 {
+            match_len += read_length(data, &mut pos)?;
+        }
+        match_len += MIN_MATCH;
+
+        if offset == 0 || offset > out.len() || out.len() + match_len > len {
+            return None;
+        }
+
+        // Copied a byte at a time, the match may overlap what it produces
+        let start = out.len() - offset;
+        for i in start..start + match_len {
+            out.push(out[i]);
+        }
+    }
+
+    (out.len() == len).then_some(out)
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], found: Option<(usize, usize)>) {
+    let match_len = found.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8);
+
+    if literals.len() >= 15 {
+        write_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+
+    if let Some((offset, _)) = found {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+
+        if match_len >= 15 {
+            write_length(out, match_len - 15);
+        }
+    }
+}
+
+fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+fn read_length(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut len = 0usize;
+
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        len = len.checked_add(byte as usize)?;
+
+        if byte != 255 {
+            return Some(len);
+        }
+    }
+}
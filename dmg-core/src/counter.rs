@@ -0,0 +1,74 @@
+use super::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+/// The 16-bit system counter, counting every dot since power on.
+///
+/// DIV is its upper byte, TIMA counts falling edges of the bit TAC selects
+/// and the APU frame sequencer those of `DIV_APU_BIT`. All of them read this
+/// one counter, so they stay in phase and a reset through DIV reaches every
+/// one of them: bits that drop to 0 count as falling edges.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SystemCounter {
+    value: u16,
+}
+
+impl SystemCounter {
+    pub fn new() -> Self {
+        SystemCounter::default()
+    }
+
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    /// The DIV register.
+    pub fn div(&self) -> u8 {
+        (self.value >> 8) as u8
+    }
+
+    /// Advance by one dot (T-cycle).
+    pub fn tick(&mut self) {
+        self.value = self.value.wrapping_add(1);
+    }
+
+    /// Advance by `dots` dots at once.
+    pub fn advance(&mut self, dots: u64) {
+        // Only the low 16 bits of the count matter to a 16-bit counter
+        self.value = self.value.wrapping_add(dots as u16);
+    }
+
+    /// Load a value without it counting as a reset, like the boot ROM
+    /// leaving the counter somewhere or a restored snapshot.
+    pub fn set(&mut self, value: u16) {
+        self.value = value;
+    }
+
+    /// Clear the counter as a DIV write or STOP does, returns the value it
+    /// had so the edges the reset made can be counted.
+    pub fn clear(&mut self) -> u16 {
+        core::mem::take(&mut self.value)
+    }
+
+    /// Dots until the bits of the counter in `mask` next change, `mask` is a
+    /// single bit.
+    pub fn next_toggle(&self, mask: u16) -> u64 {
+        let half = mask as u64;
+        half - self.value as u64 % half
+    }
+}
+
+impl Resettable for SystemCounter {
+    fn reset(&mut self) {
+        *self = SystemCounter::new();
+    }
+}
+
+impl Saveable for SystemCounter {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.value);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.value = state.read_u16()?;
+        Ok(())
+    }
+}
@@ -1,44 +1,89 @@
+mod coverage;
+mod disasm;
 mod instructions;
 mod register_file;
+mod trace;
 
-use std::fmt;
-use std::sync::{Arc, Mutex};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use core::error::Error;
+use core::fmt;
 
+use super::clock::EmuClock;
 use super::interrupts::{InterruptFlag, get_hadler_address};
+use super::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+pub use coverage::OpcodeCoverage;
+pub use disasm::{Disassembly, disassemble, disassemble_around};
 use instructions::*;
-use register_file::{Register, RegisterFile};
+pub use instructions::{AddressMode, Condition, Instruction, InstructionIter, InstructionType};
+pub use register_file::{Flags, Register, RegisterFile};
+pub use trace::{
+    MemoryAccess, TRACE_MAGIC, TextTrace, TraceConfig, TraceRecord, TraceSink, interrupt_line,
+};
+
+/// True for the opcodes that lock up the CPU.
+pub fn is_illegal_opcode(opcode: u8) -> bool {
+    Instruction::is_illegal(opcode)
+}
+
+/// Error returned by `CPU::try_step` once the CPU has stopped on a fault.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EmulatorError {
+    /// The instruction at `pc` can't be executed, e.g. an illegal opcode.
+    Fault { pc: u16, message: String },
+}
 
-use std::sync::OnceLock;
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::Fault { pc, message } => write!(f, "fault at ${pc:04X}: {message}"),
+        }
+    }
+}
 
-pub static CPU_DEBUG_LOG: OnceLock<bool> = OnceLock::new();
+impl Error for EmulatorError {}
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(u8)]
 enum CpuMode {
     Running,
     Halted,
+    // Stopped for good, on a fault
     Stopped,
+    // STOP executed, the clock is off until a joypad line falls
+    Standby,
 }
 
 // #[derive(Debug)]
 #[allow(dead_code)]
-pub struct CPU {
+pub struct CPU<C: CpuContext> {
     registers: RegisterFile,
     // Current fetch
+    instruction_pc: u16,
     fetched_data: u16,
     mem_dest: u16,
     dest_is_mem: bool,
     cur_opcode: u8,
+    // Index into `DECODED` and `EXECUTE`, 0x100 and up for CB-prefixed opcodes
+    opcode_index: usize,
     instruction: Instruction,
 
     mode: CpuMode,
     ime: bool,
     ime_scheduled: bool,
 
-    ctx: Arc<Mutex<dyn CpuContext>>,
+    // Stop with a fault instead of panicking
+    report_faults: bool,
+    fault: Option<EmulatorError>,
+    coverage: Option<OpcodeCoverage>,
+    // Receives a line per executed instruction
+    trace: Option<Box<dyn TraceSink>>,
+    trace_config: TraceConfig,
+    ctx: C,
 }
 
-pub trait CpuContext: Send + Sync {
+pub trait CpuContext {
     fn tick_cycle(&mut self);
     fn read_cycle(&mut self, address: u16) -> u8;
     fn write_cycle(&mut self, address: u16, value: u8);
@@ -46,53 +91,266 @@ pub trait CpuContext: Send + Sync {
     fn ack_interrupt(&mut self, f: &InterruptFlag);
     fn peek(&mut self, address: u16) -> u8;
     fn ticks(&self) -> u64;
+    /// `ticks` with the position of the PPU, for the trace.
+    fn clock(&self) -> EmuClock {
+        EmuClock::from_ticks(self.ticks())
+    }
+    /// Called before the opcode of the instruction at `pc` is fetched.
+    fn begin_instruction(&mut self, _pc: u16) {}
+    /// Collect the memory accesses of every instruction for the trace.
+    fn set_access_trace(&mut self, _enabled: bool) {}
+    /// Hand the accesses collected since `begin_instruction` to `f`.
+    fn drain_accesses(&mut self, _f: &mut dyn FnMut(&MemoryAccess)) {}
+    /// Called before every instruction, may run the machine ahead over a loop
+    /// that would leave `registers` unchanged. True when it did, the
+    /// instruction at `registers.pc` is then run by the next step.
+    fn skip_idle(&mut self, _registers: &RegisterFile) -> bool {
+        false
+    }
+    /// ROM bank mapped at `address`, for the trace.
+    fn bank_of(&self, _address: u16) -> u16 {
+        0
+    }
+    /// RAM enable, ROM bank and RAM bank registers of a banked cartridge,
+    /// for the trace.
+    fn mapper_registers(&self) -> Option<[u8; 3]> {
+        None
+    }
+    /// Problem found by `begin_instruction`, stops the CPU with a fault.
+    fn take_fault(&mut self) -> Option<String> {
+        None
+    }
+    /// Called after a push moved the stack pointer down to `sp`.
+    fn stack_push(&mut self, _sp: u16) {}
+    /// Called after a pop moved the stack pointer up to `sp`.
+    fn stack_pop(&mut self, _sp: u16) {}
+    /// Let M-cycles pass while the CPU is halted with no interrupt pending,
+    /// up to `ticks`. A context knowing when the next interrupt can be
+    /// requested may let all cycles before it pass at once, by default one
+    /// cycle is ticked.
+    fn tick_halted(&mut self, _ticks: u64) {
+        self.tick_cycle();
+    }
+    /// Called when STOP is executed, the system clock stops.
+    fn enter_stop(&mut self) {}
+    /// Let one M-cycle pass in STOP mode, returns true when a joypad line
+    /// fell and the CPU wakes up.
+    fn tick_stopped(&mut self) -> bool {
+        true
+    }
+    /// Registers as the boot ROM leaves them, used on power on and reset.
+    fn power_on_registers(&self) -> RegisterFile {
+        RegisterFile::new()
+    }
 }
 
-impl CPU {
-    pub fn new(ctx: Arc<Mutex<dyn CpuContext>>) -> Self {
+impl<C: CpuContext> CPU<C> {
+    pub fn new(ctx: C) -> Self {
         CPU {
-            registers: RegisterFile::new(),
+            registers: ctx.power_on_registers(),
+            instruction_pc: 0,
             fetched_data: 0,
             mem_dest: 0,
             dest_is_mem: false,
             cur_opcode: 0,
+            opcode_index: 0,
             instruction: Instruction::default(),
             mode: CpuMode::Running,
             ime: false,
             ime_scheduled: false,
+            report_faults: false,
+            fault: None,
+            coverage: None,
+            trace: None,
+            trace_config: TraceConfig::default(),
             ctx,
         }
     }
 
+    pub fn registers(&self) -> &RegisterFile {
+        &self.registers
+    }
+
+    /// In HALT or STOP, waiting for an interrupt or a button.
+    pub fn is_halted(&self) -> bool {
+        matches!(self.mode, CpuMode::Halted | CpuMode::Standby)
+    }
+
+    pub(crate) fn ime(&self) -> bool {
+        self.ime
+    }
+
+    /// 0 when running, 1 when halted and 2 in STOP, as BESS stores it.
+    pub(crate) fn execution_state(&self) -> u8 {
+        match self.mode {
+            CpuMode::Halted => 1,
+            CpuMode::Standby => 2,
+            CpuMode::Running | CpuMode::Stopped => 0,
+        }
+    }
+
+    /// Take over the registers, IME and execution state of a BESS state.
+    pub(crate) fn restore_core(&mut self, registers: RegisterFile, ime: bool, execution_state: u8) {
+        self.registers = registers;
+        self.ime = ime;
+        self.ime_scheduled = false;
+        self.mode = match execution_state {
+            1 => CpuMode::Halted,
+            2 => CpuMode::Standby,
+            _ => CpuMode::Running,
+        };
+    }
+
+    /// The system the CPU is attached to.
+    pub fn context(&self) -> &C {
+        &self.ctx
+    }
+
+    pub fn context_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+
+    /// Stop with an `EmulatorError::Fault` instead of panicking on illegal
+    /// opcodes, so embedders that must keep running can recover with a reset.
+    pub fn set_report_faults(&mut self, enabled: bool) {
+        self.report_faults = enabled;
+    }
+
+    /// Fault the CPU stopped on, cleared by reset.
+    pub fn fault(&self) -> Option<&EmulatorError> {
+        self.fault.as_ref()
+    }
+
+    /// Like `step`, but a fault is returned as an error instead of stopping silently.
+    pub fn try_step(&mut self) -> Result<bool, EmulatorError> {
+        let running = self.step();
+
+        match &self.fault {
+            Some(fault) => Err(fault.clone()),
+            None => Ok(running),
+        }
+    }
+
+    /// Count executed opcodes into `coverage`, None stops counting.
+    pub fn set_coverage(&mut self, coverage: Option<OpcodeCoverage>) {
+        self.coverage = coverage;
+    }
+
+    pub fn coverage(&self) -> Option<&OpcodeCoverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Hand a `TraceRecord` of every executed instruction to `trace`: the
+    /// `EmuClock`, bank and address, the instruction bytes, the registers
+    /// and the mapper registers, and every interrupt dispatch. None stops
+    /// tracing.
+    pub fn set_trace(&mut self, trace: Option<Box<dyn TraceSink>>) {
+        self.trace = trace;
+        self.update_access_trace();
+    }
+
+    pub fn take_trace(&mut self) -> Option<Box<dyn TraceSink>> {
+        let trace = self.trace.take();
+        self.update_access_trace();
+        trace
+    }
+
+    /// Limit the trace to some instructions, kept until changed.
+    pub fn set_trace_config(&mut self, config: TraceConfig) {
+        self.trace_config = config;
+        self.update_access_trace();
+    }
+
+    pub fn trace_config(&self) -> &TraceConfig {
+        &self.trace_config
+    }
+
+    /// Pause or resume the trace while running.
+    pub fn set_tracing(&mut self, enabled: bool) {
+        self.trace_config.enabled = enabled;
+    }
+
+    /// Execute instructions until the context reached `ticks`, returns false
+    /// once the CPU has stopped.
+    ///
+    /// The instruction crossing `ticks` is finished, so the context can end up
+    /// to one instruction past it. Callers that check something after every
+    /// instruction have to `step` instead.
+    ///
+    /// While halted the context runs up to the next scheduled event in one
+    /// batch, see `CpuContext::tick_halted`. Running instructions still tick
+    /// every dot, their memory accesses have to see the machine at their
+    /// own M-cycle.
+    pub fn run_until(&mut self, ticks: u64) -> bool {
+        while self.ctx.ticks() < ticks {
+            if self.mode == CpuMode::Halted
+                && !self.ime_scheduled
+                && self.ctx.get_interrupt().is_none()
+            {
+                self.ctx.tick_halted(ticks);
+
+                // An interrupt requested in the last cycle is taken right
+                // away, as `step` does
+                if self.ime {
+                    self.handle_interrupts();
+                }
+                continue;
+            }
+
+            if !self.step() {
+                return false;
+            }
+        }
+
+        true
+    }
+
     pub fn step(&mut self) -> bool {
         match self.mode {
+            CpuMode::Running if self.ctx.skip_idle(&self.registers) => {}
             CpuMode::Running => {
                 let pc = self.registers.pc;
+                self.instruction_pc = pc;
+                self.ctx.begin_instruction(pc);
+
+                if let Some(message) = self.ctx.take_fault() {
+                    self.raise_fault(message);
+                    return false;
+                }
+
                 self.fetch_instruction();
+
+                if self.fault.is_some() {
+                    return false;
+                }
+
                 self.fetch_data();
-                if *CPU_DEBUG_LOG.get_or_init(|| false) {
-                    let mut ctx = self.ctx.lock().unwrap();
-                    println!(
-                        "{:08X} - {:04X}: {:-12} ({:02X} {:02X} {:02X}) {}",
-                        ctx.ticks(),
-                        pc,
-                        self.instruction.fmt_with_data(self.fetched_data),
-                        self.cur_opcode,
-                        ctx.peek(pc + 1),
-                        ctx.peek(pc + 2),
-                        self.registers
-                    );
+                let traced = self.trace.is_some() && self.trace_config.accepts(pc);
+                if traced {
+                    self.trace_instruction(pc);
                 }
                 self.execute();
+                if traced {
+                    self.trace_accesses();
+                }
+
+                if self.fault.is_some() {
+                    return false;
+                }
             }
             CpuMode::Halted => {
-                let mut ctx = self.ctx.lock().unwrap();
+                let ctx = &mut self.ctx;
                 if ctx.get_interrupt().is_some() {
                     // Resume if an interrupt is requested
                     self.mode = CpuMode::Running;
                 }
                 ctx.tick_cycle();
             }
+            CpuMode::Standby => {
+                if self.ctx.tick_stopped() {
+                    self.mode = CpuMode::Running;
+                }
+            }
             CpuMode::Stopped => {
                 return false;
             }
@@ -110,19 +368,69 @@ impl CPU {
         true
     }
 
+    fn trace_instruction(&mut self, pc: u16) {
+        let ctx = &mut self.ctx;
+        let record = TraceRecord {
+            clock: ctx.clock(),
+            bank: ctx.bank_of(pc),
+            pc,
+            bytes: [
+                self.cur_opcode,
+                ctx.peek(pc.wrapping_add(1)),
+                ctx.peek(pc.wrapping_add(2)),
+            ],
+            registers: self.registers,
+            mapper: ctx.mapper_registers(),
+        };
+
+        if let Some(trace) = &mut self.trace {
+            trace.record(&record);
+        }
+    }
+
+    /// Accesses are only collected while they are traced, the context
+    /// doesn't keep them otherwise.
+    fn update_access_trace(&mut self) {
+        let enabled = self.trace.is_some() && self.trace_config.memory;
+        self.ctx.set_access_trace(enabled);
+    }
+
+    fn trace_accesses(&mut self) {
+        if let Some(trace) = &mut self.trace
+            && self.trace_config.memory
+        {
+            self.ctx.drain_accesses(&mut |access| trace.access(access));
+        }
+    }
+
     fn fetch_instruction(&mut self) {
-        let mut ctx = self.ctx.lock().unwrap();
+        let ctx = &mut self.ctx;
         self.cur_opcode = ctx.read_cycle(self.registers.pc);
         self.registers.pc = self.registers.pc.wrapping_add(1);
 
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(self.cur_opcode as u16);
+        }
+
+        if Instruction::is_illegal(self.cur_opcode) {
+            self.raise_fault(format!("Illegal opcode 0x{:02X}", self.cur_opcode));
+            return;
+        }
+
         if self.cur_opcode != 0xCB {
-            self.instruction = Instruction::from_opcode(self.cur_opcode);
+            self.opcode_index = self.cur_opcode as usize;
+            self.instruction = DECODED[self.opcode_index];
             return;
         }
 
         self.cur_opcode = ctx.read_cycle(self.registers.pc);
         self.registers.pc = self.registers.pc.wrapping_add(1);
-        self.instruction = Instruction::from_opcode_prefixed(self.cur_opcode);
+        self.opcode_index = 0x100 + self.cur_opcode as usize;
+        self.instruction = DECODED[self.opcode_index];
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(0x100 + self.cur_opcode as u16);
+        }
     }
 
     fn fetch_data(&mut self) {
@@ -154,11 +462,11 @@ impl CPU {
                 }
             }
             AddressMode::R_D8 => {
-                self.fetched_data = self.ctx.lock().unwrap().read_cycle(self.registers.pc) as u16;
+                self.fetched_data = self.ctx.read_cycle(self.registers.pc) as u16;
                 self.registers.pc = self.registers.pc.wrapping_add(1);
             }
             AddressMode::R_D16 | AddressMode::D16 => {
-                let mut ctx = self.ctx.lock().unwrap();
+                let ctx = &mut self.ctx;
                 let lo = ctx.read_cycle(self.registers.pc) as u16;
                 let hi = ctx.read_cycle(self.registers.pc.wrapping_add(1)) as u16;
                 self.fetched_data = lo | (hi << 8);
@@ -168,7 +476,7 @@ impl CPU {
                 let reg2 = self.instruction.reg2.unwrap();
                 assert!(reg2 == Register::HL);
                 let address = self.registers.read16(reg2);
-                self.fetched_data = self.ctx.lock().unwrap().read_cycle(address) as u16;
+                self.fetched_data = self.ctx.read_cycle(address) as u16;
                 self.registers
                     .write16(Register::HL, address.wrapping_add(1));
             }
@@ -176,7 +484,7 @@ impl CPU {
                 let reg2 = self.instruction.reg2.unwrap();
                 assert!(reg2 == Register::HL);
                 let address = self.registers.read16(reg2);
-                self.fetched_data = self.ctx.lock().unwrap().read_cycle(address) as u16;
+                self.fetched_data = self.ctx.read_cycle(address) as u16;
                 self.registers
                     .write16(Register::HL, address.wrapping_sub(1));
             }
@@ -201,7 +509,7 @@ impl CPU {
                     .write16(Register::HL, address.wrapping_sub(1));
             }
             AddressMode::HL_SPR => {
-                self.fetched_data = self.ctx.lock().unwrap().read_cycle(self.registers.pc) as u16;
+                self.fetched_data = self.ctx.read_cycle(self.registers.pc) as u16;
                 self.registers.pc = self.registers.pc.wrapping_add(1);
             }
             AddressMode::MR_R => {
@@ -222,41 +530,40 @@ impl CPU {
                 } else {
                     self.registers.read16(reg2)
                 };
-                self.fetched_data = self.ctx.lock().unwrap().read_cycle(address) as u16;
+                self.fetched_data = self.ctx.read_cycle(address) as u16;
             }
             AddressMode::R_A8 => {
-                let mut ctx = self.ctx.lock().unwrap();
+                let ctx = &mut self.ctx;
                 let a8 = ctx.read_cycle(self.registers.pc) as u16;
                 self.registers.pc = self.registers.pc.wrapping_add(1);
                 let address = a8 | 0xFF00;
                 self.fetched_data = ctx.read_cycle(address) as u16;
             }
             AddressMode::D8 => {
-                self.fetched_data = self.ctx.lock().unwrap().read_cycle(self.registers.pc) as u16;
+                self.fetched_data = self.ctx.read_cycle(self.registers.pc) as u16;
                 self.registers.pc = self.registers.pc.wrapping_add(1);
             }
             AddressMode::A8_R => {
                 self.dest_is_mem = true;
                 // Only used by LDH, hardcoded its data
                 self.fetched_data = self.registers.a as u16;
-                self.mem_dest =
-                    (self.ctx.lock().unwrap().read_cycle(self.registers.pc) as u16) | 0xFF00;
+                self.mem_dest = (self.ctx.read_cycle(self.registers.pc) as u16) | 0xFF00;
                 self.registers.pc = self.registers.pc.wrapping_add(1); // Should probably be wrapping add everywhere
             }
             AddressMode::MR => {
                 let reg1 = self.registers.read16(self.instruction.reg1.unwrap());
                 self.mem_dest = reg1;
                 self.dest_is_mem = true;
-                self.fetched_data = self.ctx.lock().unwrap().read_cycle(reg1) as u16;
+                self.fetched_data = self.ctx.read_cycle(reg1) as u16;
             }
             AddressMode::MR_D8 => {
-                self.fetched_data = self.ctx.lock().unwrap().read_cycle(self.registers.pc) as u16;
+                self.fetched_data = self.ctx.read_cycle(self.registers.pc) as u16;
                 self.registers.pc = self.registers.pc.wrapping_add(1);
                 self.mem_dest = self.registers.read16(self.instruction.reg1.unwrap());
                 self.dest_is_mem = true;
             }
             AddressMode::A16_R | AddressMode::D16_R => {
-                let mut ctx = self.ctx.lock().unwrap();
+                let ctx = &mut self.ctx;
                 let lo = ctx.read_cycle(self.registers.pc) as u16;
                 let hi = ctx.read_cycle(self.registers.pc.wrapping_add(1)) as u16;
                 self.mem_dest = lo | (hi << 8);
@@ -272,7 +579,7 @@ impl CPU {
                 }
             }
             AddressMode::R_A16 => {
-                let mut ctx = self.ctx.lock().unwrap();
+                let ctx = &mut self.ctx;
                 let lo = ctx.read_cycle(self.registers.pc) as u16;
                 let hi = ctx.read_cycle(self.registers.pc.wrapping_add(1)) as u16;
 
@@ -291,129 +598,116 @@ impl CPU {
                     0xEF => 0x28,
                     0xF7 => 0x30,
                     0xFF => 0x38,
-                    _ => panic!("Invalid opcode for RST {}", self.cur_opcode),
+                    _ => {
+                        self.raise_fault(format!("Invalid opcode for RST {}", self.cur_opcode));
+                        0
+                    }
                 };
             }
         }
     }
 
+    // Handler of every entry of `DECODED`, looked up by opcode instead of
+    // matching on the instruction type
+    const EXECUTE: [fn(&mut Self); 512] = {
+        let mut table = [Self::undefined as fn(&mut Self); 512];
+        let mut index = 0;
+
+        while index < table.len() {
+            table[index] = Self::handler(DECODED[index].itype);
+            index += 1;
+        }
+
+        table
+    };
+
+    const fn handler(itype: InstructionType) -> fn(&mut Self) {
+        match itype {
+            InstructionType::NONE => Self::undefined,
+            // Nothing to do
+            InstructionType::NOP => |_| {},
+            InstructionType::HALT => |cpu| cpu.mode = CpuMode::Halted,
+            InstructionType::STOP => Self::stop,
+            InstructionType::DI => Self::disable_interrupts,
+            InstructionType::EI => Self::enable_interrupts,
+            InstructionType::DEC => Self::decrement,
+            InstructionType::INC => Self::increment,
+            InstructionType::JP => Self::jump,
+            InstructionType::JR => Self::jump_rel,
+            InstructionType::LD => Self::load,
+            InstructionType::LDH => Self::load_high,
+            InstructionType::CALL => Self::call,
+            InstructionType::RST => Self::rst,
+            InstructionType::RET => Self::ret,
+            InstructionType::RETI => |cpu| {
+                cpu.enable_interrupts();
+                cpu.ret();
+            },
+            InstructionType::POP => Self::pop,
+            InstructionType::PUSH => Self::push,
+            InstructionType::CCF => Self::ccf,
+            InstructionType::SCF => Self::scf,
+            InstructionType::CPL => Self::cpl,
+            InstructionType::DAA => Self::daa,
+            InstructionType::ADC => Self::adc,
+            InstructionType::ADD => Self::add,
+            InstructionType::CP => Self::cp,
+            InstructionType::SBC => Self::sbc,
+            InstructionType::SUB => Self::sub,
+            InstructionType::AND => Self::and,
+            InstructionType::OR => Self::or,
+            InstructionType::XOR => Self::xor,
+            InstructionType::RLA => Self::rla,
+            InstructionType::RLCA => Self::rlca,
+            InstructionType::RRA => Self::rra,
+            InstructionType::RRCA => Self::rrca,
+            InstructionType::RLC | InstructionType::RL => Self::rlc_rl,
+            InstructionType::RRC | InstructionType::RR => Self::rrc_rc,
+            InstructionType::SLA => Self::sla,
+            InstructionType::SRA => Self::sra,
+            InstructionType::SWAP => Self::swap,
+            InstructionType::SRL => Self::srl,
+            InstructionType::BIT => Self::bit,
+            InstructionType::RES => Self::res,
+            InstructionType::SET => Self::set,
+            _ => Self::unimplemented,
+        }
+    }
+
     fn execute(&mut self) {
-        match self.instruction.itype {
-            InstructionType::NONE => {
-                // TODO: Should we remove it?
-                panic!("Invalid instruction NONE");
-            }
-            InstructionType::NOP => {
-                // Nothing to do
-            }
-            InstructionType::HALT => {
-                self.mode = CpuMode::Halted;
-            }
-            InstructionType::STOP => {
-                self.mode = CpuMode::Stopped;
-            }
-            InstructionType::DI => {
-                self.disable_interrupts();
-            }
-            InstructionType::EI => {
-                self.enable_interrupts();
-            }
-            InstructionType::DEC => {
-                self.decrement();
-            }
-            InstructionType::INC => {
-                self.increment();
-            }
-            InstructionType::JP => {
-                self.jump();
-            }
-            InstructionType::JR => {
-                self.jump_rel();
-            }
-            InstructionType::LD => {
-                self.load();
-            }
-            InstructionType::LDH => {
-                self.load_high();
-            }
-            InstructionType::CALL => {
-                self.call();
-            }
-            InstructionType::RST => {
-                self.rst();
-            }
-            InstructionType::RET => {
-                self.ret();
-            }
-            InstructionType::RETI => {
-                self.enable_interrupts();
-                self.ret();
-            }
-            InstructionType::POP => {
-                self.pop();
-            }
-            InstructionType::PUSH => {
-                self.push();
-            }
-            InstructionType::CCF => {
-                self.ccf();
-            }
-            InstructionType::SCF => {
-                self.scf();
-            }
-            InstructionType::CPL => {
-                self.cpl();
-            }
-            InstructionType::DAA => {
-                self.daa();
-            }
-            InstructionType::ADC => {
-                self.adc();
-            }
-            InstructionType::ADD => {
-                self.add();
-            }
-            InstructionType::CP => {
-                self.cp();
-            }
-            InstructionType::SBC => {
-                self.sbc();
-            }
-            InstructionType::SUB => {
-                self.sub();
-            }
-            InstructionType::AND => {
-                self.and();
-            }
-            InstructionType::OR => {
-                self.or();
-            }
-            InstructionType::XOR => {
-                self.xor();
-            }
-            InstructionType::RLA => {
-                self.rla();
-            }
-            InstructionType::RLCA => {
-                self.rlca();
-            }
-            InstructionType::RRA => {
-                self.rra();
-            }
-            InstructionType::RRCA => {
-                self.rrca();
-            }
-            InstructionType::RLC | InstructionType::RL => self.rlc_rl(),
-            InstructionType::RRC | InstructionType::RR => self.rrc_rc(),
-            InstructionType::SLA => self.sla(),
-            InstructionType::SRA => self.sra(),
-            InstructionType::SWAP => self.swap(),
-            InstructionType::SRL => self.srl(),
-            InstructionType::BIT => self.bit(),
-            InstructionType::RES => self.res(),
-            InstructionType::SET => self.set(),
-            _ => panic!("Instruction {:?} not implemented.", self.instruction.itype),
+        Self::EXECUTE[self.opcode_index](self);
+    }
+
+    fn undefined(&mut self) {
+        // TODO: Should we remove it?
+        self.raise_fault("Invalid instruction NONE".into());
+    }
+
+    fn unimplemented(&mut self) {
+        self.raise_fault(format!(
+            "Instruction {:?} not implemented.",
+            self.instruction.itype
+        ));
+    }
+
+    /// Panic, or stop and keep the fault for `try_step` when reporting faults.
+    fn raise_fault(&mut self, message: String) {
+        if !self.report_faults {
+            panic!("{message}");
         }
+
+        self.fault = Some(EmulatorError::Fault {
+            pc: self.instruction_pc,
+            message,
+        });
+        self.mode = CpuMode::Stopped;
+    }
+
+    fn stop(&mut self) {
+        // STOP is followed by a byte that is skipped
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        self.ctx.enter_stop();
+        self.mode = CpuMode::Standby;
     }
 
     fn check_flags(&self) -> bool {
@@ -438,7 +732,7 @@ impl CPU {
     }
 
     fn handle_interrupts(&mut self) {
-        let interrupt = match self.ctx.lock().unwrap().get_interrupt() {
+        let interrupt = match self.ctx.get_interrupt() {
             Some(i) => i,
             None => InterruptFlag::empty(),
         };
@@ -451,11 +745,24 @@ impl CPU {
 
         self.ime = false;
         self.mode = CpuMode::Running;
-        self.ctx.lock().unwrap().ack_interrupt(&interrupt);
+        let ctx = &mut self.ctx;
+        // Interrupt dispatch is traced as its own instruction
+        ctx.begin_instruction(self.registers.pc);
+        ctx.ack_interrupt(&interrupt);
+
+        if let Some(trace) = &mut self.trace
+            && self.trace_config.enabled
+        {
+            trace.interrupt(interrupt, ctx.clock());
+        }
 
         self.push_value(self.registers.pc);
         self.registers.pc = get_hadler_address(interrupt);
-        self.ctx.lock().unwrap().tick_cycle();
+        self.ctx.tick_cycle();
+
+        if self.trace_config.enabled {
+            self.trace_accesses();
+        }
     }
 
     /// DEC s
@@ -479,7 +786,7 @@ impl CPU {
         self.registers.set_hf((value & 0x0F) == 0x00);
 
         if self.dest_is_mem {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
@@ -493,7 +800,7 @@ impl CPU {
         let reg1 = self.instruction.reg1.unwrap();
 
         if reg1.is_16bit() {
-            self.ctx.lock().unwrap().tick_cycle();
+            self.ctx.tick_cycle();
         }
 
         if reg1.is_16bit() && !self.dest_is_mem {
@@ -510,7 +817,7 @@ impl CPU {
         self.registers.set_hf((value & 0x0F) + 1 > 0x0F);
 
         if self.dest_is_mem {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
@@ -519,7 +826,7 @@ impl CPU {
     fn jump(&mut self) {
         if self.check_flags() {
             self.registers.pc = self.fetched_data;
-            self.ctx.lock().unwrap().tick_cycle();
+            self.ctx.tick_cycle();
         }
     }
 
@@ -529,7 +836,7 @@ impl CPU {
             let e8 = self.fetched_data as i8;
             // wrapping_add handles signed addition
             self.registers.pc = self.registers.pc.wrapping_add(e8 as u16);
-            self.ctx.lock().unwrap().tick_cycle();
+            self.ctx.tick_cycle();
         }
     }
 
@@ -537,27 +844,21 @@ impl CPU {
         if self.dest_is_mem {
             if self.instruction.reg2.is_none() {
                 // 0x36 LD [HL], n8
-                self.ctx
-                    .lock()
-                    .unwrap()
-                    .write_cycle(self.mem_dest, self.fetched_data as u8);
+                self.ctx.write_cycle(self.mem_dest, self.fetched_data as u8);
                 return;
             }
 
             let reg2 = self.instruction.reg2.unwrap();
             if reg2.is_16bit() {
                 // 0x08 LD [a16], SP
-                let mut ctx = self.ctx.lock().unwrap();
+                let ctx = &mut self.ctx;
                 ctx.write_cycle(self.mem_dest, self.fetched_data as u8); // lo
                 ctx.write_cycle(
                     self.mem_dest.wrapping_add(1),
                     (self.fetched_data >> 8) as u8,
                 ); // hi
             } else {
-                self.ctx
-                    .lock()
-                    .unwrap()
-                    .write_cycle(self.mem_dest, self.fetched_data as u8);
+                self.ctx.write_cycle(self.mem_dest, self.fetched_data as u8);
             }
             return;
         }
@@ -589,14 +890,11 @@ impl CPU {
 
     fn load_high(&mut self) {
         if self.dest_is_mem {
-            self.ctx
-                .lock()
-                .unwrap()
-                .write_cycle(self.mem_dest, self.fetched_data as u8);
+            self.ctx.write_cycle(self.mem_dest, self.fetched_data as u8);
         } else {
             assert!(self.instruction.reg1.unwrap() == Register::A);
             self.registers.write8(Register::A, self.fetched_data as u8);
-            self.ctx.lock().unwrap().tick_cycle();
+            self.ctx.tick_cycle();
         }
     }
 
@@ -615,7 +913,7 @@ impl CPU {
     fn ret(&mut self) {
         if self.check_flags() {
             self.registers.pc = self.pop_value();
-            self.ctx.lock().unwrap().tick_cycle();
+            self.ctx.tick_cycle();
         }
     }
 
@@ -631,10 +929,11 @@ impl CPU {
     }
 
     fn pop_value(&mut self) -> u16 {
-        let lo = self.ctx.lock().unwrap().read_cycle(self.registers.sp);
+        let lo = self.ctx.read_cycle(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
-        let hi = self.ctx.lock().unwrap().read_cycle(self.registers.sp);
+        let hi = self.ctx.read_cycle(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
+        self.ctx.stack_pop(self.registers.sp);
         ((hi as u16) << 8) | (lo as u16)
     }
 
@@ -650,12 +949,13 @@ impl CPU {
     fn push_value(&mut self, value: u16) {
         let msb = (value >> 8) as u8;
         let lsb = (value & 0xFF) as u8;
-        let mut ctx = self.ctx.lock().unwrap();
+        let ctx = &mut self.ctx;
         ctx.tick_cycle();
         self.registers.sp = self.registers.sp.wrapping_sub(1);
         ctx.write_cycle(self.registers.sp, msb);
         self.registers.sp = self.registers.sp.wrapping_sub(1);
         ctx.write_cycle(self.registers.sp, lsb);
+        ctx.stack_push(self.registers.sp);
     }
 
     /// CCF
@@ -959,7 +1259,7 @@ impl CPU {
         self.registers.set_cf(carry != 0);
 
         if reg1 == Register::HL {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
@@ -985,7 +1285,7 @@ impl CPU {
         self.registers.set_cf(carry != 0);
 
         if reg1 == Register::HL {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
@@ -1007,7 +1307,7 @@ impl CPU {
         self.registers.set_cf(carry != 0);
 
         if reg1 == Register::HL {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
@@ -1030,7 +1330,7 @@ impl CPU {
         self.registers.set_cf(carry != 0);
 
         if reg1 == Register::HL {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
@@ -1053,7 +1353,7 @@ impl CPU {
         self.registers.set_cf(false);
 
         if reg1 == Register::HL {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
@@ -1075,7 +1375,7 @@ impl CPU {
         self.registers.set_cf(carry != 0);
 
         if reg1 == Register::HL {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
@@ -1107,7 +1407,7 @@ impl CPU {
         let reg1 = self.instruction.reg1.unwrap();
 
         if reg1 == Register::HL {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
@@ -1125,14 +1425,61 @@ impl CPU {
         let reg1 = self.instruction.reg1.unwrap();
 
         if reg1 == Register::HL {
-            self.ctx.lock().unwrap().write_cycle(self.mem_dest, result);
+            self.ctx.write_cycle(self.mem_dest, result);
         } else {
             self.registers.write8(reg1, result);
         }
     }
 }
 
-impl fmt::Display for CPU {
+impl<C: CpuContext> Resettable for CPU<C> {
+    fn reset(&mut self) {
+        self.registers = self.ctx.power_on_registers();
+        self.mode = CpuMode::Running;
+        self.ime = false;
+        self.ime_scheduled = false;
+        self.fault = None;
+    }
+}
+
+// Only state that outlives a single instruction is saved,
+// savestates are taken between `step` calls.
+impl<C: CpuContext> Saveable for CPU<C> {
+    fn save_state(&self, state: &mut StateWriter) {
+        let r = &self.registers;
+        state.write_bytes(&[r.a, r.f.bits(), r.b, r.c, r.d, r.e, r.h, r.l]);
+        state.write_u16(r.pc);
+        state.write_u16(r.sp);
+        state.write_u8(self.mode as u8);
+        state.write_bool(self.ime);
+        state.write_bool(self.ime_scheduled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut bytes = [0; 8];
+        state.read_into(&mut bytes)?;
+        let [a, f, b, c, d, e, h, l] = bytes;
+
+        let r = &mut self.registers;
+        (r.a, r.b, r.c, r.d, r.e, r.h, r.l) = (a, b, c, d, e, h, l);
+        r.f = Flags::from_bits_truncate(f);
+        r.pc = state.read_u16()?;
+        r.sp = state.read_u16()?;
+
+        self.mode = match state.read_u8()? {
+            0 => CpuMode::Running,
+            1 => CpuMode::Halted,
+            2 => CpuMode::Stopped,
+            3 => CpuMode::Standby,
+            _ => return Err(StateError::InvalidValue("CPU mode")),
+        };
+        self.ime = state.read_bool()?;
+        self.ime_scheduled = state.read_bool()?;
+        Ok(())
+    }
+}
+
+impl<C: CpuContext> fmt::Display for CPU<C> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "CPU register file:\n{}", self.registers)
     }
@@ -0,0 +1,128 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use super::instructions::{ILLEGAL_OPCODES, Instruction};
+
+/// Number of times each of the 512 opcodes was executed.
+///
+/// Opcodes are indexed `0x000..=0x0FF` without and `0x100..=0x1FF` with the
+/// CB prefix, the prefix itself counts as `0x0CB`. Stored as text, one
+/// `opcode count` line per executed opcode with the opcode in hex
+/// (`7C 120`, `CB7C 3`), so reports of several runs can be merged.
+#[derive(Clone)]
+pub struct OpcodeCoverage {
+    counts: Box<[u64; 512]>,
+}
+
+impl OpcodeCoverage {
+    pub fn new() -> Self {
+        OpcodeCoverage {
+            counts: Box::new([0; 512]),
+        }
+    }
+
+    pub(super) fn record(&mut self, index: u16) {
+        self.counts[index as usize] += 1;
+    }
+
+    pub fn count(&self, index: u16) -> u64 {
+        self.counts[index as usize]
+    }
+
+    /// Add the counts of another run.
+    pub fn merge(&mut self, other: &OpcodeCoverage) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut coverage = OpcodeCoverage::new();
+
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let (opcode, count) = line.trim().split_once(' ')?;
+            let index = match (opcode.len(), opcode.strip_prefix("CB")) {
+                (2, _) => u16::from_str_radix(opcode, 16).ok()?,
+                (4, Some(opcode)) => 0x100 + u16::from_str_radix(opcode, 16).ok()?,
+                _ => return None,
+            };
+
+            coverage.counts[index as usize] += count.trim().parse::<u64>().ok()?;
+        }
+
+        Some(coverage)
+    }
+
+    /// Legal opcodes that were never executed.
+    pub fn unexercised(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..512u16).filter(|&index| self.count(index) == 0 && !is_illegal(index))
+    }
+
+    /// Number of legal opcodes, the most `executed` can reach.
+    pub fn legal_count() -> usize {
+        512 - ILLEGAL_OPCODES.len()
+    }
+
+    /// Number of distinct opcodes executed.
+    pub fn executed(&self) -> usize {
+        self.counts.iter().filter(|&&count| count > 0).count()
+    }
+
+    /// Opcode in the form used by the text format, e.g. `7C` or `CB7C`.
+    pub fn opcode_name(index: u16) -> String {
+        if index >= 0x100 {
+            alloc::format!("CB{:02X}", index & 0xFF)
+        } else {
+            alloc::format!("{index:02X}")
+        }
+    }
+
+    /// Disassembly of an opcode with its operands as `n8`/`n16`.
+    pub fn mnemonic(index: u16) -> String {
+        if is_illegal(index) {
+            return "ILLEGAL".to_string();
+        }
+
+        if index == 0xCB {
+            return "PREFIX CB".to_string();
+        }
+
+        let opcode = (index & 0xFF) as u8;
+        let instruction = if index >= 0x100 {
+            Instruction::from_opcode_prefixed(opcode)
+        } else {
+            Instruction::from_opcode(opcode)
+        };
+
+        instruction
+            .fmt_with_data(0)
+            .replace("$0000", "n16")
+            .replace("$00", "n8")
+    }
+}
+
+fn is_illegal(index: u16) -> bool {
+    index < 0x100 && Instruction::is_illegal(index as u8)
+}
+
+impl Default for OpcodeCoverage {
+    fn default() -> Self {
+        OpcodeCoverage::new()
+    }
+}
+
+impl fmt::Display for OpcodeCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for index in (0..512u16).filter(|&index| self.count(index) > 0) {
+            writeln!(
+                f,
+                "{} {}",
+                OpcodeCoverage::opcode_name(index),
+                self.count(index)
+            )?;
+        }
+
+        Ok(())
+    }
+}
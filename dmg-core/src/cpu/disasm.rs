@@ -0,0 +1,121 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::instructions::{Instruction, InstructionIter, InstructionType};
+
+/// An instruction decoded from memory for display.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Disassembly {
+    pub address: u16,
+    /// Opcode and operands, 1 to 3 bytes
+    pub bytes: Vec<u8>,
+    /// Mnemonic with the condition and operands, e.g. `JR NZ, $0150`
+    pub text: String,
+}
+
+impl Disassembly {
+    /// Address of the following instruction.
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+}
+
+/// Decode the instruction at `address`, `read` returns the byte at an address.
+pub fn disassemble(read: impl Fn(u16) -> u8, address: u16) -> Disassembly {
+    let (instruction, operands) = Instruction::decode(&read, address);
+    describe(read(address), address, instruction, operands)
+}
+
+/// The text of an instruction `InstructionIter` decoded.
+fn describe(opcode: u8, address: u16, instruction: Instruction, operands: Vec<u8>) -> Disassembly {
+    let mut bytes = [opcode].to_vec();
+    bytes.extend_from_slice(&operands);
+
+    if Instruction::is_illegal(opcode) {
+        return Disassembly {
+            address,
+            bytes,
+            text: "ILLEGAL".to_string(),
+        };
+    }
+
+    if opcode == 0xCB {
+        return Disassembly {
+            address,
+            bytes,
+            text: instruction.fmt_with_data(0),
+        };
+    }
+
+    let data = match (instruction.itype, &operands[..]) {
+        // Relative jumps show where they go
+        (InstructionType::JR, &[offset]) => {
+            address.wrapping_add(2).wrapping_add(offset as i8 as u16)
+        }
+        (InstructionType::RST, _) => (opcode & 0x38) as u16,
+        (_, &[value]) => value as u16,
+        (_, &[low, high]) => u16::from_le_bytes([low, high]),
+        _ => 0,
+    };
+    let text = match instruction.itype {
+        InstructionType::JR => format!("JR ${data:04X}"),
+        _ => instruction.fmt_with_data(data),
+    };
+    let text = match (instruction.cond, text.split_once(' ')) {
+        (Some(cond), Some((mnemonic, operands))) => format!("{mnemonic} {cond:?}, {operands}"),
+        (Some(cond), None) => format!("{text} {cond:?}"),
+        (None, _) => text,
+    };
+
+    Disassembly {
+        address,
+        bytes,
+        text,
+    }
+}
+
+/// Up to `before` instructions leading to `pc`, the one at `pc` and `after`
+/// more.
+///
+/// Code can't be decoded backwards reliably, instructions are 1 to 3 bytes.
+/// The earliest start that decodes into an instruction boundary at `pc` is
+/// taken, which is usually right once a few instructions are in between.
+pub fn disassemble_around(
+    read: impl Fn(u16) -> u8,
+    pc: u16,
+    before: usize,
+    after: usize,
+) -> Vec<Disassembly> {
+    let mut lines = (1..=before * 3)
+        .rev()
+        .find_map(|distance| decode_until(&read, pc.wrapping_sub(distance as u16), pc))
+        .unwrap_or_default();
+
+    if lines.len() > before {
+        lines.drain(..lines.len() - before);
+    }
+
+    let mut address = pc;
+    for _ in 0..=after {
+        let line = disassemble(&read, address);
+        address = line.next_address();
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// The instructions from `start` up to `end`, None when one runs past it.
+fn decode_until(read: impl Fn(u16) -> u8, start: u16, end: u16) -> Option<Vec<Disassembly>> {
+    let mut lines = Vec::new();
+
+    for (address, instruction, operands) in InstructionIter::new(&read, start, end) {
+        if operands.len() as u16 >= end.wrapping_sub(address) {
+            return None;
+        }
+        lines.push(describe(read(address), address, instruction, operands));
+    }
+
+    Some(lines)
+}
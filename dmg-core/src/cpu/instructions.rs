@@ -34,10 +34,14 @@
 /// - `SLA`: Performs an arithmetic left shift on a specific register by 1.
 /// - `SWAP`: Swaps the upper and lower nibbles of a specific register.
 /// - `LDH`: Load a value to or from a specific memory address in the high RAM area (0xFF00-0xFFFF)
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use super::register_file::Register;
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(u8)]
 pub enum Condition {
     NZ,
@@ -142,7 +146,7 @@ pub enum InstructionType {
     SET,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Instruction {
     pub itype: InstructionType,
     pub mode: AddressMode,
@@ -153,18 +157,55 @@ pub struct Instruction {
 
 impl Default for Instruction {
     fn default() -> Self {
-        Instruction {
-            itype: InstructionType::NONE,
-            mode: AddressMode::IMP,
-            reg1: None,
-            reg2: None,
-            cond: None,
-        }
+        Instruction::NONE
     }
 }
 
+/// Every opcode decoded at compile time, the CB-prefixed ones from 0x100 on
+/// as in `OpcodeCoverage`. Illegal opcodes and the prefix itself are `NONE`.
+pub static DECODED: [Instruction; 512] = {
+    let mut table = [Instruction::NONE; 512];
+    let mut opcode = 0;
+
+    while opcode < 0x100 {
+        if opcode != 0xCB && !Instruction::is_illegal(opcode as u8) {
+            table[opcode] = Instruction::from_opcode(opcode as u8);
+        }
+        table[0x100 + opcode] = Instruction::from_opcode_prefixed(opcode as u8);
+        opcode += 1;
+    }
+
+    table
+};
+
+/// Opcodes that lock up the CPU.
+pub const ILLEGAL_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
 impl Instruction {
-    fn get_register_for_prefixed(opcode: u8) -> Register {
+    pub const NONE: Instruction = Instruction {
+        itype: InstructionType::NONE,
+        mode: AddressMode::IMP,
+        reg1: None,
+        reg2: None,
+        cond: None,
+    };
+
+    pub const fn is_illegal(opcode: u8) -> bool {
+        let mut i = 0;
+
+        while i < ILLEGAL_OPCODES.len() {
+            if ILLEGAL_OPCODES[i] == opcode {
+                return true;
+            }
+            i += 1;
+        }
+
+        false
+    }
+
+    const fn get_register_for_prefixed(opcode: u8) -> Register {
         let reg_bits = opcode & 0b111; // equivalent to opcode % 8
         match reg_bits {
             0 => Register::B,
@@ -175,10 +216,45 @@ impl Instruction {
             5 => Register::L,
             6 => Register::HL,
             7 => Register::A,
-            _ => panic!("Invalid register specifier {}", reg_bits),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Bytes of immediate data or address after the opcode.
+    pub const fn operand_count(&self) -> u16 {
+        match self.mode {
+            AddressMode::D8
+            | AddressMode::R_D8
+            | AddressMode::R_A8
+            | AddressMode::A8_R
+            | AddressMode::MR_D8
+            | AddressMode::HL_SPR => 1,
+            AddressMode::D16
+            | AddressMode::R_D16
+            | AddressMode::R_A16
+            | AddressMode::A16_R
+            | AddressMode::D16_R => 2,
+            _ => 0,
         }
     }
 
+    /// The instruction at `address` and the bytes after its opcode, see
+    /// `InstructionIter`.
+    pub fn decode(read: &dyn Fn(u16) -> u8, address: u16) -> (Instruction, Vec<u8>) {
+        let opcode = read(address);
+
+        if opcode == 0xCB {
+            let opcode = read(address.wrapping_add(1));
+            return (DECODED[0x100 + opcode as usize], [opcode].to_vec());
+        }
+
+        let instruction = DECODED[opcode as usize];
+        let operands = (1..=instruction.operand_count())
+            .map(|i| read(address.wrapping_add(i)))
+            .collect();
+        (instruction, operands)
+    }
+
     pub fn fmt_with_data(&self, data: u16) -> String {
         match self.mode {
             AddressMode::IMP => format!("{:?}", self.itype),
@@ -253,9 +329,9 @@ impl Instruction {
         }
     }
 
-    pub fn from_opcode_prefixed(opcode: u8) -> Self {
+    pub const fn from_opcode_prefixed(opcode: u8) -> Self {
         let reg1 = Instruction::get_register_for_prefixed(opcode);
-        let mode = if reg1 == Register::HL {
+        let mode = if matches!(reg1, Register::HL) {
             AddressMode::MR
         } else {
             AddressMode::R
@@ -292,8 +368,7 @@ impl Instruction {
             }
             4..=7 => InstructionType::BIT,
             8..=0xB => InstructionType::RES,
-            0xC..=0xF => InstructionType::SET,
-            _ => panic!("Invalid prefixed intruction code {:01X}", itype_code),
+            _ => InstructionType::SET,
         };
 
         Instruction {
@@ -305,7 +380,8 @@ impl Instruction {
         }
     }
 
-    pub fn from_opcode(opcode: u8) -> Self {
+    /// Panics for the opcodes in `ILLEGAL_OPCODES`.
+    pub const fn from_opcode(opcode: u8) -> Self {
         match opcode {
             0x00 => Instruction {
                 itype: InstructionType::NOP,
@@ -1837,7 +1913,7 @@ impl Instruction {
                 reg2: None,
                 cond: Some(Condition::NC),
             },
-            0xD3 => panic!("Illegal opcode 0x{opcode:X}"),
+            0xD3 => panic!("Illegal opcode 0xD3"),
             0xD4 => Instruction {
                 itype: InstructionType::CALL,
                 mode: AddressMode::D16,
@@ -1887,7 +1963,7 @@ impl Instruction {
                 reg2: None,
                 cond: Some(Condition::C),
             },
-            0xDB => panic!("Illegal opcode 0x{opcode:X}"),
+            0xDB => panic!("Illegal opcode 0xDB"),
             0xDC => Instruction {
                 itype: InstructionType::CALL,
                 mode: AddressMode::D16,
@@ -1895,7 +1971,7 @@ impl Instruction {
                 reg2: None,
                 cond: Some(Condition::C),
             },
-            0xDD => panic!("Illegal opcode 0x{opcode:X}"),
+            0xDD => panic!("Illegal opcode 0xDD"),
             0xDE => Instruction {
                 itype: InstructionType::SBC,
                 mode: AddressMode::R_D8,
@@ -1931,8 +2007,8 @@ impl Instruction {
                 reg2: Some(Register::A),
                 cond: None,
             },
-            0xE3 => panic!("Illegal opcode 0x{opcode:X}"),
-            0xE4 => panic!("Illegal opcode 0x{opcode:X}"),
+            0xE3 => panic!("Illegal opcode 0xE3"),
+            0xE4 => panic!("Illegal opcode 0xE4"),
             0xE5 => Instruction {
                 itype: InstructionType::PUSH,
                 mode: AddressMode::R,
@@ -1975,9 +2051,9 @@ impl Instruction {
                 reg2: Some(Register::A),
                 cond: None,
             },
-            0xEB => panic!("Illegal opcode 0x{opcode:X}"),
-            0xEC => panic!("Illegal opcode 0x{opcode:X}"),
-            0xED => panic!("Illegal opcode 0x{opcode:X}"),
+            0xEB => panic!("Illegal opcode 0xEB"),
+            0xEC => panic!("Illegal opcode 0xEC"),
+            0xED => panic!("Illegal opcode 0xED"),
             0xEE => Instruction {
                 itype: InstructionType::XOR,
                 mode: AddressMode::R_D8,
@@ -2020,7 +2096,7 @@ impl Instruction {
                 reg2: None,
                 cond: None,
             },
-            0xF4 => panic!("Illegal opcode 0x{opcode:X}"),
+            0xF4 => panic!("Illegal opcode 0xF4"),
             0xF5 => Instruction {
                 itype: InstructionType::PUSH,
                 mode: AddressMode::R,
@@ -2070,8 +2146,8 @@ impl Instruction {
                 reg2: None,
                 cond: None,
             },
-            0xFC => panic!("Illegal opcode 0x{opcode:X}"),
-            0xFD => panic!("Illegal opcode 0x{opcode:X}"),
+            0xFC => panic!("Illegal opcode 0xFC"),
+            0xFD => panic!("Illegal opcode 0xFD"),
             0xFE => Instruction {
                 itype: InstructionType::CP,
                 mode: AddressMode::R_D8,
@@ -2089,3 +2165,49 @@ impl Instruction {
         }
     }
 }
+
+/// Decodes the instructions of a memory range one after the other, what
+/// the disassembler, the debugger window and traces read code with. Yields
+/// the address, the instruction and the bytes after its opcode: the
+/// operands, or the second opcode byte of CB-prefixed instructions.
+/// Illegal opcodes decode as `Instruction::NONE` without operands.
+pub struct InstructionIter<'a> {
+    read: &'a dyn Fn(u16) -> u8,
+    address: u16,
+    end: u16,
+}
+
+impl<'a> InstructionIter<'a> {
+    /// Instructions from `start` up to `end`, which isn't included. The last
+    /// one may run past `end`, `start == end` is an empty range.
+    pub fn new(read: &'a dyn Fn(u16) -> u8, start: u16, end: u16) -> Self {
+        InstructionIter {
+            read,
+            address: start,
+            end,
+        }
+    }
+}
+
+impl Iterator for InstructionIter<'_> {
+    type Item = (u16, Instruction, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.address == self.end {
+            return None;
+        }
+
+        let address = self.address;
+        let (instruction, operands) = Instruction::decode(self.read, address);
+        let length = 1 + operands.len() as u16;
+
+        // Stop at the end of the range rather than wrap around past it
+        self.address = if length >= self.end.wrapping_sub(address) {
+            self.end
+        } else {
+            address.wrapping_add(length)
+        };
+
+        Some((address, instruction, operands))
+    }
+}
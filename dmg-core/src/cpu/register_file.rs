@@ -1,5 +1,5 @@
 use bitflags::bitflags;
-use std::fmt;
+use core::fmt;
 
 bitflags!(
     /// The flags register is the lower 8 bits of the `AF` register and
@@ -12,6 +12,7 @@ bitflags!(
     ///   in the result (used for BCD arithmetic).
     /// - **C (Carry flag)**: Set if there was a carry from the most significant
     ///   bit in the result.
+    #[derive(Copy, Clone, PartialEq)]
     pub struct Flags: u8 {
     const ZERO         = 0b_1000_0000;
     const SUBTRACT = 0b_0100_0000;
@@ -40,6 +41,7 @@ pub enum Register {
     PC = 13,
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub struct RegisterFile {
     pub a: u8,
     pub f: Flags,
@@ -74,6 +76,12 @@ impl Register {
     }
 }
 
+impl Default for RegisterFile {
+    fn default() -> Self {
+        RegisterFile::new()
+    }
+}
+
 impl RegisterFile {
     pub fn new() -> RegisterFile {
         RegisterFile {
@@ -90,6 +98,8 @@ impl RegisterFile {
         }
     }
 
+    /// Value of an 8-bit register, the low byte of a register pair. The
+    /// instructions only read pairs with `read16`.
     pub fn read8(&self, reg: Register) -> u8 {
         match reg {
             Register::A => self.a,
@@ -100,10 +110,11 @@ impl RegisterFile {
             Register::E => self.e,
             Register::H => self.h,
             Register::L => self.l,
-            _ => panic!("Invalid register, only u8 supported"),
+            _ => self.read16(reg) as u8,
         }
     }
 
+    /// Value of a register pair, PC or SP, an 8-bit register zero-extended.
     pub fn read16(&self, reg: Register) -> u16 {
         match reg {
             Register::AF => ((self.a as u16) << 8) | (self.f.bits() as u16),
@@ -112,10 +123,11 @@ impl RegisterFile {
             Register::HL => ((self.h as u16) << 8) | (self.l as u16),
             Register::PC => self.pc,
             Register::SP => self.sp,
-            _ => panic!("Invalid register, only u16 supported"),
+            _ => self.read8(reg) as u16,
         }
     }
 
+    /// Set an 8-bit register, a register pair is set to `value` zero-extended.
     pub fn write8(&mut self, reg: Register, value: u8) {
         match reg {
             Register::A => self.a = value,
@@ -126,10 +138,11 @@ impl RegisterFile {
             Register::E => self.e = value,
             Register::H => self.h = value,
             Register::L => self.l = value,
-            _ => panic!("Invalid register, only u8 supported"),
+            _ => self.write16(reg, value as u16),
         }
     }
 
+    /// Set a register pair, PC or SP, an 8-bit register to the low byte.
     pub fn write16(&mut self, reg: Register, value: u16) {
         let lo = (value & 0x00FF) as u8;
         let hi = ((value & 0xFF00) >> 8) as u8;
@@ -153,7 +166,7 @@ impl RegisterFile {
             }
             Register::PC => self.pc = value,
             Register::SP => self.sp = value,
-            _ => panic!("Invalid register, only u16 supported"),
+            _ => self.write8(reg, lo),
         }
     }
 
@@ -0,0 +1,212 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use super::disasm::disassemble;
+use super::register_file::{Flags, RegisterFile};
+use crate::clock::EmuClock;
+use crate::interrupts::InterruptFlag;
+
+/// Start of a binary trace, followed by records of `TraceRecord::SIZE` bytes.
+pub const TRACE_MAGIC: &[u8; 5] = b"DMGT\x02";
+
+/// An executed instruction as the CPU saw it before executing it.
+#[derive(Copy, Clone, PartialEq)]
+pub struct TraceRecord {
+    pub clock: EmuClock,
+    /// ROM bank of `pc`, 0 outside switchable ROM
+    pub bank: u16,
+    pub pc: u16,
+    /// Opcode and the two bytes after it
+    pub bytes: [u8; 3],
+    /// PC already points past the opcode
+    pub registers: RegisterFile,
+    /// RAM enable, ROM bank and RAM bank registers of banked cartridges
+    pub mapper: Option<[u8; 3]>,
+}
+
+impl TraceRecord {
+    /// Bytes of a record in a binary trace.
+    pub const SIZE: usize = 32;
+
+    /// Append the record in the binary layout, numbers little endian. The
+    /// frame of the clock is left out, it follows from the ticks.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let r = &self.registers;
+        out.extend_from_slice(&self.clock.ticks.to_le_bytes());
+        out.extend_from_slice(&self.bank.to_le_bytes());
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.bytes);
+        out.extend_from_slice(&[r.a, r.f.bits(), r.b, r.c, r.d, r.e, r.h, r.l]);
+        out.extend_from_slice(&r.sp.to_le_bytes());
+        out.push(self.mapper.is_some() as u8);
+        out.extend_from_slice(&self.mapper.unwrap_or_default());
+        out.push(self.clock.ly);
+        out.extend_from_slice(&self.clock.dot.to_le_bytes());
+    }
+
+    /// A record of the binary layout, None when `data` is too short.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let data: &[u8; Self::SIZE] = data.get(..Self::SIZE)?.try_into().ok()?;
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let pc = u16_at(10);
+
+        let mut registers = RegisterFile::new();
+        registers.a = data[15];
+        registers.f = Flags::from_bits_truncate(data[16]);
+        registers.b = data[17];
+        registers.c = data[18];
+        registers.d = data[19];
+        registers.e = data[20];
+        registers.h = data[21];
+        registers.l = data[22];
+        registers.sp = u16_at(23);
+        registers.pc = pc.wrapping_add(1);
+
+        Some(TraceRecord {
+            clock: EmuClock::new(
+                u64::from_le_bytes(data[..8].try_into().ok()?),
+                data[29],
+                u16_at(30),
+            ),
+            bank: u16_at(8),
+            pc,
+            bytes: [data[12], data[13], data[14]],
+            registers,
+            mapper: (data[25] != 0).then_some([data[26], data[27], data[28]]),
+        })
+    }
+}
+
+/// The trace line of the text format, like
+/// `107252 f1 062:080 - 05:4A10: LD A, (HL)   (7E 23 FE) A: ... MBC 0000=0A 2000=05 4000=00`.
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pc = self.pc;
+        let instruction = disassemble(
+            |address| self.bytes[address.wrapping_sub(pc) as usize % 3],
+            pc,
+        );
+        let [opcode, first, second] = self.bytes;
+
+        write!(
+            f,
+            "{} - {:02X}:{pc:04X}: {:-12} ({opcode:02X} {first:02X} {second:02X}) {}",
+            self.clock, self.bank, instruction.text, self.registers
+        )?;
+
+        if let Some([ram_enable, rom_bank, ram_bank]) = self.mapper {
+            write!(
+                f,
+                " MBC 0000={ram_enable:02X} 2000={rom_bank:02X} 4000={ram_bank:02X}"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A memory access of a traced instruction, handed to the sink after the
+/// instruction's record.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MemoryAccess {
+    pub ticks: u64,
+    /// PC of the instruction making the access
+    pub pc: u16,
+    /// M-cycle of the access within the instruction, from 0
+    pub m_cycle: u8,
+    pub write: bool,
+    pub address: u16,
+    pub value: u8,
+}
+
+/// The trace line of a memory access, like `0001A2F4 - 4A10: M1 R $C0A0 $3C`.
+impl fmt::Display for MemoryAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:08X} - {:04X}: M{} {} ${:04X} ${:02X}",
+            self.ticks,
+            self.pc,
+            self.m_cycle,
+            if self.write { "W" } else { "R" },
+            self.address,
+            self.value
+        )
+    }
+}
+
+/// Which executed instructions reach the trace, so long sessions only
+/// trace the part that matters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceConfig {
+    /// Off pauses the trace, the sink stays attached
+    pub enabled: bool,
+    /// Only instructions with their PC in the range
+    pub range: Option<RangeInclusive<u16>>,
+    /// Turn the trace on the first time this address executes
+    pub start_at: Option<u16>,
+    /// Also trace the memory accesses of the traced instructions
+    pub memory: bool,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        TraceConfig {
+            enabled: true,
+            range: None,
+            start_at: None,
+            memory: false,
+        }
+    }
+}
+
+impl TraceConfig {
+    /// Whether the instruction at `pc` is traced.
+    pub(super) fn accepts(&mut self, pc: u16) -> bool {
+        if self.start_at == Some(pc) {
+            self.enabled = true;
+            self.start_at = None;
+        }
+
+        self.enabled && self.range.as_ref().is_none_or(|range| range.contains(&pc))
+    }
+}
+
+/// Receives a record for every instruction the CPU executes, see
+/// `CPU::set_trace`. Called on the emulation thread, so sinks should hand
+/// the records off rather than format or write them there.
+pub trait TraceSink: Send {
+    fn record(&mut self, record: &TraceRecord);
+
+    /// Called when the CPU dispatches the interrupt of `source`, before the
+    /// first instruction of its handler.
+    fn interrupt(&mut self, _source: InterruptFlag, _clock: EmuClock) {}
+
+    /// Called for every memory access of a traced instruction when
+    /// `TraceConfig::memory` is on.
+    fn access(&mut self, _access: &MemoryAccess) {}
+}
+
+/// Writes the text line of every record to `W` as it comes.
+pub struct TextTrace<W>(pub W);
+
+impl<W: fmt::Write + Send> TraceSink for TextTrace<W> {
+    fn record(&mut self, record: &TraceRecord) {
+        let _ = writeln!(self.0, "{record}");
+    }
+
+    fn interrupt(&mut self, source: InterruptFlag, clock: EmuClock) {
+        let _ = writeln!(self.0, "{}", interrupt_line(source, clock));
+    }
+
+    fn access(&mut self, access: &MemoryAccess) {
+        let _ = writeln!(self.0, "{access}");
+    }
+}
+
+/// The trace line of an interrupt dispatch, like `107264 f1 062:092 - interrupt lcd`.
+pub fn interrupt_line(source: InterruptFlag, clock: EmuClock) -> String {
+    format!("{clock} - interrupt {}", source.source_name())
+}
@@ -0,0 +1,119 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+
+/// Frames between checksums, about a second.
+pub const CHECKSUM_INTERVAL: u32 = 60;
+
+/// The two machines disagreed on the state at the end of `frame`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Desync {
+    pub frame: u32,
+    pub local: u64,
+    pub remote: u64,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "desync at frame {}: {:016x} != {:016x}",
+            self.frame, self.local, self.remote
+        )
+    }
+}
+
+/// Matches the checksums of two machines that should run in lockstep, e.g.
+/// netplay peers or a run and its replay.
+///
+/// Checksums from `state::checksum` are pushed for every `interval`th frame
+/// as they become known on either side, in any order between the sides.
+/// A checksum only one side has for a frame is skipped.
+pub struct ChecksumStream {
+    interval: u32,
+    // Checksums not matched yet, oldest first
+    local: VecDeque<(u32, u64)>,
+    remote: VecDeque<(u32, u64)>,
+    matched: u64,
+}
+
+impl ChecksumStream {
+    pub fn new(interval: u32) -> Self {
+        ChecksumStream {
+            interval,
+            local: VecDeque::new(),
+            remote: VecDeque::new(),
+            matched: 0,
+        }
+    }
+
+    /// Whether the checksum of `frame` is taken.
+    pub fn is_due(&self, frame: u32) -> bool {
+        frame.is_multiple_of(self.interval)
+    }
+
+    pub fn push_local(&mut self, frame: u32, checksum: u64) -> Result<(), Desync> {
+        self.local.push_back((frame, checksum));
+        self.match_pending()
+    }
+
+    pub fn push_remote(&mut self, frame: u32, checksum: u64) -> Result<(), Desync> {
+        self.remote.push_back((frame, checksum));
+        self.match_pending()
+    }
+
+    /// Checksums both sides agreed on.
+    pub fn matched(&self) -> u64 {
+        self.matched
+    }
+
+    fn match_pending(&mut self) -> Result<(), Desync> {
+        while let (Some(&(local_frame, local)), Some(&(remote_frame, remote))) =
+            (self.local.front(), self.remote.front())
+        {
+            if local_frame < remote_frame {
+                self.local.pop_front();
+                continue;
+            }
+
+            if remote_frame < local_frame {
+                self.remote.pop_front();
+                continue;
+            }
+
+            self.local.pop_front();
+            self.remote.pop_front();
+
+            if local != remote {
+                return Err(Desync {
+                    frame: local_frame,
+                    local,
+                    remote,
+                });
+            }
+
+            self.matched += 1;
+        }
+
+        Ok(())
+    }
+
+    /// A checksum as a line of a checksum log, `frame 60 0123456789abcdef`.
+    pub fn format_line(frame: u32, checksum: u64) -> String {
+        format!("frame {frame} {checksum:016x}")
+    }
+
+    /// Parse a line written by `format_line`.
+    pub fn parse_line(line: &str) -> Option<(u32, u64)> {
+        let mut words = line.split_whitespace();
+
+        if words.next()? != "frame" {
+            return None;
+        }
+
+        let frame = words.next()?.parse().ok()?;
+        let checksum = u64::from_str_radix(words.next()?, 16).ok()?;
+        words.next().is_none().then_some((frame, checksum))
+    }
+}
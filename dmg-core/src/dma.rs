@@ -0,0 +1,154 @@
+use super::bus::MemoryBus;
+use super::ppu::PPU;
+use super::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+/// M-cycles from the write to DMA until the first byte is copied.
+const START_DELAY: u8 = 2;
+
+/// OAM DMA, copies 160 bytes from `register` * 0x100 to OAM, one per M-cycle.
+///
+/// Writing DMA while a transfer runs restarts it: the running transfer goes
+/// on copying during the start delay and the new one takes over from the
+/// first byte. A fresh transfer leaves OAM to the CPU during its delay, a
+/// restart doesn't.
+pub struct DMA {
+    // Copying bytes, OAM is busy
+    copying: bool,
+    byte: u8,
+    // Page being copied
+    source: u8,
+    // M-cycles until the last written transfer starts, 0 when none is pending
+    start_delay: u8,
+    // Last value written, what reads of DMA return
+    register: u8,
+}
+
+impl DMA {
+    pub fn new() -> Self {
+        DMA {
+            copying: false,
+            byte: 0,
+            source: 0,
+            start_delay: 0,
+            register: 0,
+        }
+    }
+
+    /// Read of the DMA register, the last value written.
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    /// Write to the DMA register, starts or restarts a transfer from `value` * 0x100.
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+        self.start_delay = START_DELAY;
+    }
+
+    /// CPU read of OAM, 0xFF while a transfer copies.
+    pub fn oam_read(&self, ppu: &PPU, address: u16) -> u8 {
+        if self.copying {
+            return 0xFF;
+        }
+        ppu.oam_read(address)
+    }
+
+    /// CPU write to OAM, lost while a transfer copies.
+    pub fn oam_write(&self, ppu: &mut PPU, address: u16, value: u8) {
+        if !self.copying {
+            ppu.oam_write(address, value);
+        }
+    }
+
+    /// Byte a CPU read of `address` sees instead of memory when the transfer
+    /// copies from the same bus, the external bus or VRAM.
+    pub fn bus_conflict(&self, bus: &MemoryBus, address: u16) -> Option<u8> {
+        let source = self.source_address()?;
+        let vram = |address| matches!(address, 0x8000..=0x9FFF);
+        let external = |address| matches!(address, 0x0000..=0x7FFF | 0xA000..=0xFDFF);
+
+        if (vram(address) && vram(source)) || (external(address) && external(source)) {
+            Some(bus.read(source))
+        } else {
+            None
+        }
+    }
+
+    pub fn tick_cycle(&mut self, bus: &MemoryBus, ppu: &mut PPU) {
+        if self.start_delay > 0 {
+            self.start_delay -= 1;
+
+            // The transfer being restarted gets to copy during the delay
+            if self.copying {
+                self.copy_byte(bus, ppu);
+            }
+
+            if self.start_delay == 0 {
+                self.copying = true;
+                self.source = self.register;
+                self.byte = 0;
+            }
+            return;
+        }
+
+        if self.copying {
+            self.copy_byte(bus, ppu);
+        }
+    }
+
+    fn copy_byte(&mut self, bus: &MemoryBus, ppu: &mut PPU) {
+        let address = (self.source as u16) * 0x100 + self.byte as u16;
+        ppu.oam_write(self.byte as u16, bus.read(address));
+
+        self.byte += 1;
+        self.copying = self.byte < 0xA0; // Up to 160 bytes
+    }
+
+    /// Whether a transfer is copying or about to start, it needs its next
+    /// M-cycle.
+    pub fn is_busy(&self) -> bool {
+        self.copying || self.start_delay > 0
+    }
+
+    /// Whether a transfer is copying, only HRAM is left to the CPU.
+    pub fn is_active(&self) -> bool {
+        self.copying
+    }
+
+    /// Address the next byte is copied from, None when no transfer is copying.
+    fn source_address(&self) -> Option<u16> {
+        self.copying
+            .then(|| (self.source as u16) * 0x100 + self.byte as u16)
+    }
+}
+
+impl Default for DMA {
+    fn default() -> Self {
+        DMA::new()
+    }
+}
+
+impl Resettable for DMA {
+    fn reset(&mut self) {
+        *self = DMA::new();
+    }
+}
+
+impl Saveable for DMA {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.copying);
+        state.write_u8(self.byte);
+        state.write_u8(self.start_delay);
+        state.write_u8(self.register);
+        state.write_u8(self.source);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.copying = state.read_bool()?;
+        self.byte = state.read_u8()?;
+        self.start_delay = state.read_u8()?;
+        self.register = state.read_u8()?;
+        self.source = state.read_u8()?;
+        Ok(())
+    }
+}
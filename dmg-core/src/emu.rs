@@ -0,0 +1,1717 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::interrupts::InterruptFlag;
+
+use super::apu::{APU, DIV_APU_BIT};
+use super::audio::AudioOutput;
+use super::bus::{HardwareRegister, MemoryBus, Page};
+use super::cart::Cartridge;
+use super::cheats::CheatList;
+use super::clock::EmuClock;
+use super::counter::SystemCounter;
+use super::cpu::*;
+use super::dma::DMA;
+use super::frame::Palette;
+use super::hash;
+use super::idle::IdleDetector;
+use super::infrared::{Infrared, InfraredDevice};
+use super::interrupts::{InterruptLine, InterruptStats};
+use super::joypad::{Buttons, Joypad};
+use super::lcd::{LcdControl, LcdMode, PaletteRegister};
+use super::mapper_log::{MapperLog, MapperRegister, MapperState, MapperWrite};
+use super::polling::PollCounter;
+use super::power::{BOOT_ROM_SIZE, Model, PowerOnState, Quirks, RamInit};
+use super::ppu::{Layers, PPU, PpuEvent};
+use super::profiler::Profiler;
+use super::raster::{LcdRegisters, ScanlineHook};
+use super::scheduler::{Event, Scheduler};
+use super::serial::{Serial, SerialDevice};
+use super::state::{
+    APU_CHUNK, BOOT_CHUNK, BUS_CHUNK, COUNTER_CHUNK, DMA_CHUNK, INFRARED_CHUNK, INTERRUPTS_CHUNK,
+    JOYPAD_CHUNK, PPU_CHUNK, Resettable, SERIAL_CHUNK, Saveable, StateError, StateReader,
+    StateWriter, TICKS_CHUNK, TIMER_CHUNK, load_chunks,
+};
+use super::stats::Stats;
+use super::timer::{TacRegister, Timer};
+use super::vram::TileSet;
+use super::warnings::{WarningKind, WarningLog};
+use super::watchdog::HangWatchdog;
+
+/// Dots (T-cycles) per second.
+pub const CLOCK_HZ: u64 = 4_194_304;
+
+/// Dots (T-cycles) per CPU memory cycle (M-cycle).
+pub const DOTS_PER_M_CYCLE: u64 = 4;
+
+/// Dots (T-cycles) per frame, 154 lines of 456 dots.
+pub const DOTS_PER_FRAME: u64 = 154 * 456;
+
+/// Accuracy features that cost speed, see `AccuracyLevel` for presets.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AccuracyConfig {
+    /// Draw with the pixel FIFO so writes during mode 3 show up mid-line,
+    /// otherwise each line is drawn at once when mode 3 starts.
+    pub fifo_renderer: bool,
+    /// CPU reads from the bus OAM DMA copies from, the external bus or VRAM,
+    /// return the byte being transferred.
+    pub dma_bus_conflicts: bool,
+    /// Run ahead over loops that only wait for LY, STAT or IF to change, in
+    /// whole iterations, so a loop can see the change up to one iteration late.
+    pub idle_skip: bool,
+    /// Wave RAM is only reachable while channel 3 plays in the moment it
+    /// fetches a byte, and retriggering it then corrupts wave RAM, as on DMG.
+    pub wave_ram_quirks: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AccuracyLevel {
+    Fast,
+    Balanced,
+    Accurate,
+}
+
+impl AccuracyConfig {
+    pub fn preset(level: AccuracyLevel) -> Self {
+        match level {
+            AccuracyLevel::Fast => AccuracyConfig {
+                fifo_renderer: false,
+                dma_bus_conflicts: false,
+                idle_skip: true,
+                wave_ram_quirks: false,
+            },
+            AccuracyLevel::Balanced => AccuracyConfig {
+                fifo_renderer: true,
+                dma_bus_conflicts: false,
+                idle_skip: false,
+                wave_ram_quirks: false,
+            },
+            AccuracyLevel::Accurate => AccuracyConfig {
+                fifo_renderer: true,
+                dma_bus_conflicts: true,
+                idle_skip: false,
+                wave_ram_quirks: true,
+            },
+        }
+    }
+}
+
+impl Default for AccuracyConfig {
+    fn default() -> Self {
+        AccuracyConfig::preset(AccuracyLevel::Balanced)
+    }
+}
+
+/// What to do when the CPU writes VRAM during mode 3 or OAM during modes 2
+/// and 3, writes the real PPU ignores. A common bug in homebrew that works
+/// in emulators letting them through, like this one does.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum RestrictedWrites {
+    #[default]
+    Allow,
+    /// Print every such write
+    Log,
+    /// Report the first one with `Emulator::take_restricted_write`
+    Break,
+}
+
+/// The main emulator state.
+///
+/// The emulator is composed of the following components:
+/// - Cartridge
+/// - CPU
+/// - Address bus
+/// - PPU (Pixel Processing Unit)
+/// - APU (Audio Processing Unit)
+/// - Timer
+/// - Serial port
+/// - Infrared port, with a device attached
+/// - Joypad
+///
+// #[derive(Debug)]
+pub struct Emulator {
+    ticks: u64,
+    bus: MemoryBus,
+    interrupts: InterruptLine,
+    dma: DMA,
+    ppu: PPU,
+    apu: APU,
+    audio: Option<AudioOutput>,
+    // DIV, the clock of TIMA and of the APU frame sequencer
+    counter: SystemCounter,
+    timer: Timer,
+    serial: Serial,
+    infrared: Infrared,
+    joypad: Joypad,
+    cheats: CheatList,
+    // Frame the GameShark codes were last applied to and the watchdog checked
+    cheat_frame: u32,
+    // Address to value, rewritten after every CPU write
+    frozen: BTreeMap<u16, u8>,
+    // Start of the current instruction, used by the memory access trace
+    instruction_pc: u16,
+    instruction_ticks: u64,
+    // Memory accesses of the current instruction, None when not traced
+    accesses: Option<Vec<MemoryAccess>>,
+    // Fault on execution from unmapped cartridge memory
+    bank_guard: bool,
+    // Fault on execution outside HRAM during OAM DMA
+    dma_guard: bool,
+    // Fault on pushes and pops outside WRAM and HRAM
+    stack_guard: bool,
+    // Warn on writes to the vectors and header that no mapper takes
+    header_guard: bool,
+    // Turn ROM only cartridges that switch banks into MBC1
+    mapper_inference: bool,
+    // Run on power on and reset instead of starting from the post-boot state
+    boot_rom: Option<[u8; BOOT_ROM_SIZE]>,
+    // Until the boot ROM writes 0xFF50
+    boot_mapped: bool,
+    restricted_writes: RestrictedWrites,
+    // The write that broke with `RestrictedWrites::Break`
+    restricted_write: Option<String>,
+    accuracy: AccuracyConfig,
+    fault: Option<String>,
+    stats: Stats,
+    model: Model,
+    ram_init: RamInit,
+    // Reads of LY and STAT per instruction, when counting them
+    polls: Option<PollCounter>,
+    // Set with `AccuracyConfig::idle_skip`
+    idle: Option<IdleDetector>,
+    watchdog: Option<HangWatchdog>,
+    profiler: Option<Profiler>,
+    warnings: Option<WarningLog>,
+    mapper_log: Option<MapperLog>,
+    // PPU transition to stop at and whether it happened
+    ppu_break: Option<PpuEvent>,
+    ppu_break_hit: bool,
+    scanline_hook: Option<Box<dyn ScanlineHook>>,
+    // Derived from the state of the components, not saved
+    scheduler: Scheduler,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CpuContext for Emulator {
+    fn tick_cycle(&mut self) {
+        // 1 Memory cycle is 4 CPU cycle
+        for _ in 0..DOTS_PER_M_CYCLE {
+            self.tick_dot();
+        }
+    }
+
+    fn enter_stop(&mut self) {
+        // DIV is reset and stays at 0, the LCD goes blank
+        self.clear_counter();
+        self.ppu.blank_frame();
+        self.joypad.take_line_fall();
+    }
+
+    fn tick_halted(&mut self, ticks: u64) {
+        // Whole M-cycles before the one something happens in, up to the
+        // cycle that reaches `ticks` as stepping would
+        let cycles = (self.quiet_dots() / DOTS_PER_M_CYCLE)
+            .min(ticks.saturating_sub(self.ticks).div_ceil(DOTS_PER_M_CYCLE));
+
+        if cycles == 0 {
+            self.tick_cycle();
+        } else {
+            self.skip_dots(cycles * DOTS_PER_M_CYCLE);
+        }
+    }
+
+    fn tick_stopped(&mut self) -> bool {
+        // Frames still count at the usual rate so frontends keep presenting
+        self.ticks += DOTS_PER_M_CYCLE;
+        if self.ticks % DOTS_PER_FRAME < DOTS_PER_M_CYCLE {
+            self.ppu.blank_frame();
+        }
+
+        if !self.joypad.take_line_fall() {
+            return false;
+        }
+
+        // Events were due while the clock stood still
+        self.schedule_events();
+        true
+    }
+
+    fn read_cycle(&mut self, address: u16) -> u8 {
+        let value = match self.dma_conflict(address) {
+            Some(value) => value,
+            None => self.peek(address),
+        };
+
+        if let Some(polls) = &mut self.polls
+            && (address == HardwareRegister::LY as u16 || address == HardwareRegister::STAT as u16)
+        {
+            polls.record(self.instruction_pc, address);
+        }
+        if let Some(idle) = &mut self.idle {
+            idle.record_read(address);
+        }
+        #[cfg(feature = "ram-tracking")]
+        if self
+            .warnings
+            .as_ref()
+            .is_some_and(|warnings| warnings.is_uninitialized(address))
+        {
+            self.warn(WarningKind::UninitializedRead, address);
+        }
+
+        self.trace_access(false, address, value);
+        self.tick_cycle();
+        value
+    }
+
+    fn write_cycle(&mut self, address: u16, value: u8) {
+        if let Some(idle) = &mut self.idle {
+            idle.record_write();
+        }
+
+        if (self.restricted_writes != RestrictedWrites::Allow || self.warnings.is_some())
+            && let Some(mode) = self.restricted_mode(address)
+        {
+            if self.warnings.is_some() {
+                self.warn(WarningKind::RestrictedWrite, address);
+            }
+
+            let message = format!(
+                "${:04X} wrote ${address:04X} during mode {}",
+                self.instruction_pc, mode as u8
+            );
+
+            match self.restricted_writes {
+                RestrictedWrites::Allow => {}
+                RestrictedWrites::Log => log!("{message}"),
+                RestrictedWrites::Break => {
+                    self.restricted_write.get_or_insert(message);
+                }
+            }
+        }
+        if self.header_guard && address <= 0x014F && self.warnings.is_some() {
+            self.check_header_write(address, value);
+        }
+        #[cfg(feature = "ram-tracking")]
+        if let Some(warnings) = &mut self.warnings {
+            warnings.record_write(address);
+        }
+
+        self.trace_access(true, address, value);
+        if (0x2000..=0x3FFF).contains(&address) {
+            self.check_undeclared_banking(value);
+        }
+
+        // Frozen addresses keep their value whatever the program writes. The
+        // write goes through once, IO registers don't see a second one.
+        let value = self.frozen.get(&address).copied().unwrap_or(value);
+        if address <= 0x7FFF && self.mapper_log.is_some() {
+            self.log_mapper_write(address, value);
+        } else {
+            self.write(address, value);
+        }
+
+        self.tick_cycle();
+    }
+
+    fn get_interrupt(&mut self) -> Option<InterruptFlag> {
+        // TODO: How the bus should update these values?
+        let ier = self.interrupts.interrupt_enable.bits();
+        let ifr = self.interrupts.interrupt_flag.bits();
+
+        let bus_ier = self.bus.read_register(HardwareRegister::IE);
+        let bus_ifr = self.bus.read_register(HardwareRegister::IF);
+
+        if bus_ier != ier || bus_ifr != ifr {
+            //panic!("Interrupt registers are not synchronized.");
+        }
+
+        if (ier & ifr) != 0 {
+            return Some(InterruptFlag::from_bits_truncate(ier & ifr));
+        }
+
+        None
+    }
+
+    /// Clear the interrupt flag
+    fn ack_interrupt(&mut self, f: &InterruptFlag) {
+        let ifr = self.interrupts.interrupt_flag.bits();
+        let new_ifr = ifr & !(f.highest_priority().bits());
+        self.interrupts.interrupt_flag = InterruptFlag::from_bits_truncate(new_ifr);
+        self.interrupts.stats.record_serviced(*f, self.ticks);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.interrupt();
+        }
+        // TODO: How the bus should update these values?
+        self.bus.write_register(HardwareRegister::IF, new_ifr);
+    }
+
+    fn peek(&mut self, address: u16) -> u8 {
+        self.inspect(address).unwrap_or_else(|| {
+            log!("Unimplemented hardware register read ${:02X}.", address);
+            self.bus.read(address)
+        })
+    }
+
+    fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    fn clock(&self) -> EmuClock {
+        Emulator::clock(self)
+    }
+
+    fn stack_push(&mut self, sp: u16) {
+        self.check_stack(WarningKind::StackOverflow, sp);
+    }
+
+    fn stack_pop(&mut self, sp: u16) {
+        self.check_stack(WarningKind::StackUnderflow, sp.wrapping_sub(2));
+    }
+
+    fn begin_instruction(&mut self, pc: u16) {
+        self.instruction_pc = pc;
+        self.instruction_ticks = self.ticks;
+        if let Some(accesses) = &mut self.accesses {
+            accesses.clear();
+        }
+
+        // A fault of the stack guard from the last instruction goes first
+        if self.bank_guard && self.fault.is_none() {
+            self.fault = self.cartridge().and_then(|rom| rom.unmapped_execution(pc));
+        }
+
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.record(pc);
+        }
+
+        if self.profiler.is_some() {
+            let bank = self.cartridge().map_or(0, |rom| rom.bank_of(pc));
+            let opcode = self.inspect(pc).unwrap_or(0xFF);
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record(bank, pc, opcode, self.ticks);
+            }
+        }
+
+        if self.warnings.is_some() && is_illegal_opcode(self.inspect(pc).unwrap_or(0)) {
+            self.warn(WarningKind::IllegalOpcode, pc);
+        }
+
+        if self.dma_guard
+            && self.fault.is_none()
+            && self.dma.is_active()
+            && !matches!(pc, 0xFF80..=0xFFFE)
+        {
+            self.fault = Some(format!(
+                "executing ${pc:04X} during OAM DMA, only HRAM is accessible"
+            ));
+        }
+    }
+
+    fn set_access_trace(&mut self, enabled: bool) {
+        if enabled != self.accesses.is_some() {
+            self.accesses = enabled.then(Vec::new);
+        }
+    }
+
+    fn drain_accesses(&mut self, f: &mut dyn FnMut(&MemoryAccess)) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.drain(..).for_each(|access| f(&access));
+        }
+    }
+
+    fn bank_of(&self, address: u16) -> u16 {
+        self.cartridge().map_or(0, |rom| rom.bank_of(address))
+    }
+
+    fn mapper_registers(&self) -> Option<[u8; 3]> {
+        self.cartridge()?.mapper_registers()
+    }
+
+    fn take_fault(&mut self) -> Option<String> {
+        self.fault.take()
+    }
+
+    fn skip_idle(&mut self, registers: &RegisterFile) -> bool {
+        let Some(idle) = &mut self.idle else {
+            return false;
+        };
+        let Some(iteration) = idle.check(registers, self.ticks) else {
+            return false;
+        };
+
+        // At most LY, STAT and IF, compared after every iteration
+        let mut polled = [None; 3];
+        for (slot, address) in polled.iter_mut().zip(idle.polled()) {
+            *slot = Some(*address);
+        }
+        let state = |emu: &mut Emulator| -> ([u8; 3], u8) {
+            let values = polled.map(|address| address.map_or(0, |address| emu.peek(address)));
+            let pending = emu.interrupts.interrupt_enable & emu.interrupts.interrupt_flag;
+            (values, pending.bits())
+        };
+
+        // Whole iterations until the loop would see something else, at most a frame
+        let before = state(self);
+        let limit = self.ticks + DOTS_PER_FRAME;
+
+        while self.ticks < limit {
+            for _ in 0..iteration {
+                self.tick_dot();
+            }
+
+            if state(self) != before {
+                break;
+            }
+        }
+
+        true
+    }
+
+    fn power_on_registers(&self) -> RegisterFile {
+        if self.boot_mapped {
+            return PowerOnState::boot_registers();
+        }
+        PowerOnState::for_model(self.model).registers
+    }
+}
+
+impl Emulator {
+    /// Value of `address` as the CPU would read it, without side effects.
+    /// None for IO registers that aren't emulated.
+    pub(crate) fn inspect(&self, address: u16) -> Option<u8> {
+        // Most accesses are to RAM, which needs no decoding
+        if MemoryBus::is_ram(address) {
+            return Some(self.bus.read_ram(address));
+        }
+
+        let value = match Page::of(address) {
+            Page::Vram => self.ppu.vram_read(address),
+            Page::Oam if address <= 0xFE9F => self.dma.oam_read(&self.ppu, address),
+            Page::Io if matches!(address, 0xFF10..=0xFF3F) => self.apu.read(address),
+            Page::Io if !matches!(address, 0xFF80..=0xFFFE) => {
+                let register = HardwareRegister::from_u16(address);
+                match register {
+                    Some(HardwareRegister::P1_JOYP) => self.joypad.read(),
+                    Some(HardwareRegister::SB) | Some(HardwareRegister::SC) => {
+                        self.serial.read(address)
+                    }
+                    Some(HardwareRegister::DIV) => self.counter.div(),
+                    Some(HardwareRegister::TIMA)
+                    | Some(HardwareRegister::TMA)
+                    | Some(HardwareRegister::TAC) => self.timer.read(address),
+                    // Unused bits read as 1
+                    Some(HardwareRegister::IF) => self.interrupts.interrupt_flag.bits() | 0xE0,
+
+                    Some(HardwareRegister::LCDC)
+                    | Some(HardwareRegister::STAT)
+                    | Some(HardwareRegister::SCY)
+                    | Some(HardwareRegister::SCX)
+                    | Some(HardwareRegister::LY)
+                    | Some(HardwareRegister::LYC)
+                    | Some(HardwareRegister::BGP)
+                    | Some(HardwareRegister::OBP0)
+                    | Some(HardwareRegister::OBP1)
+                    | Some(HardwareRegister::WY)
+                    | Some(HardwareRegister::WX) => self.ppu.lcd_read(register.unwrap()),
+                    Some(HardwareRegister::IE) => self.interrupts.interrupt_enable.bits(),
+                    Some(HardwareRegister::DMA) => self.dma.read(),
+                    Some(HardwareRegister::BOOT) => 0xFF,
+                    Some(HardwareRegister::RP) if self.infrared.is_attached() => {
+                        self.infrared.read()
+                    }
+                    _ => return None,
+                }
+            }
+            Page::Cartridge if self.boot_mapped && (address as usize) < BOOT_ROM_SIZE => {
+                self.boot_rom.map_or(0xFF, |rom| rom[address as usize])
+            }
+            Page::Cartridge if address <= 0x7FFF => {
+                self.cheats.patch_rom(address, self.bus.read(address))
+            }
+            _ => self.bus.read(address),
+        };
+
+        Some(value)
+    }
+
+    /// Take over the IO registers (0xFF00 - 0xFF7F) and IE of a state from
+    /// another emulator. Writes that would start something are left out: no
+    /// sound channel is triggered, no OAM DMA or serial transfer starts and
+    /// LY stays where the PPU is.
+    pub(crate) fn restore_io(&mut self, io: &[u8], ie: u8) {
+        let register = |address: u16| io[(address - 0xFF00) as usize];
+
+        // The APU ignores writes while powered off
+        self.write(0xFF26, register(0xFF26));
+        for address in (0xFF10..=0xFF3F).filter(|address| *address != 0xFF26) {
+            let value = match address {
+                0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => register(address) & 0x7F,
+                _ => register(address),
+            };
+            self.write(address, value);
+        }
+
+        self.write(0xFF00, register(0xFF00));
+        self.write(0xFF01, register(0xFF01));
+        self.write(0xFF02, register(0xFF02) & 0x7F);
+
+        // Only the upper byte of the system counter is stored
+        self.counter.set((register(0xFF04) as u16) << 8);
+        self.timer.tima = register(0xFF05);
+        self.timer.tma = register(0xFF06);
+        self.timer.tac = TacRegister::from_bits_truncate(register(0xFF07));
+        self.apu.update_div(self.counter.value());
+        self.schedule_timer();
+
+        for address in (0xFF40..=0xFF4B).filter(|address| !matches!(address, 0xFF44 | 0xFF46)) {
+            self.write(address, register(address));
+        }
+
+        // Last, writing STAT can request an interrupt
+        self.write(0xFF0F, register(0xFF0F));
+        self.write(0xFFFF, ie);
+    }
+
+    fn trace_access(&mut self, write: bool, address: u16, value: u8) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(MemoryAccess {
+                ticks: self.ticks,
+                pc: self.instruction_pc,
+                m_cycle: ((self.ticks - self.instruction_ticks) / 4) as u8,
+                write,
+                address,
+                value,
+            });
+        }
+    }
+
+    /// Byte a CPU read of `address` sees instead of memory while OAM DMA uses the same bus.
+    fn dma_conflict(&self, address: u16) -> Option<u8> {
+        if !self.accuracy.dma_bus_conflicts {
+            return None;
+        }
+        self.dma.bus_conflict(&self.bus, address)
+    }
+
+    /// Write without taking a memory cycle.
+    fn write(&mut self, address: u16, value: u8) {
+        if MemoryBus::is_ram(address) {
+            self.bus.write_ram(address, value);
+            return;
+        }
+
+        // Write everything to bus just in case
+        self.bus.write(address, value);
+
+        match Page::of(address) {
+            Page::Vram => self.ppu.vram_write(address, value),
+            Page::Oam if address <= 0xFE9F => self.dma.oam_write(&mut self.ppu, address, value),
+            Page::Io if matches!(address, 0xFF10..=0xFF3F) => self.apu.write(address, value),
+            Page::Io if !matches!(address, 0xFF80..=0xFFFE) => {
+                let register = HardwareRegister::from_u16(address);
+                match register {
+                    Some(HardwareRegister::P1_JOYP) => self.joypad.write(value),
+                    Some(HardwareRegister::SB) | Some(HardwareRegister::SC) => {
+                        let clock = self.clock();
+                        self.serial.write(address, value, clock)
+                    }
+                    Some(HardwareRegister::DIV) => self.clear_counter(),
+                    Some(HardwareRegister::TIMA)
+                    | Some(HardwareRegister::TMA)
+                    | Some(HardwareRegister::TAC) => {
+                        let counter = self.counter.value();
+                        self.timer
+                            .write(address, value, counter, &mut self.interrupts);
+                        self.schedule_timer();
+                    }
+                    Some(HardwareRegister::IF) => {
+                        self.interrupts.interrupt_flag = InterruptFlag::from_bits_truncate(value);
+                        self.interrupts
+                            .stats
+                            .retain_pending(self.interrupts.interrupt_flag);
+                    }
+                    Some(HardwareRegister::LCDC)
+                    | Some(HardwareRegister::STAT)
+                    | Some(HardwareRegister::SCY)
+                    | Some(HardwareRegister::SCX)
+                    | Some(HardwareRegister::LY)
+                    | Some(HardwareRegister::LYC)
+                    | Some(HardwareRegister::BGP)
+                    | Some(HardwareRegister::OBP0)
+                    | Some(HardwareRegister::OBP1)
+                    | Some(HardwareRegister::WY)
+                    | Some(HardwareRegister::WX) => {
+                        self.ppu
+                            .lcd_write(register.unwrap(), value, &mut self.interrupts);
+                    }
+                    Some(HardwareRegister::DMA) => {
+                        self.dma.write(value);
+                        self.schedule_dma();
+                    }
+                    Some(HardwareRegister::IE) => {
+                        self.interrupts.interrupt_enable = InterruptFlag::from_bits_truncate(value);
+                    }
+                    // Only the boot ROM's own write counts, it can't be mapped back
+                    Some(HardwareRegister::BOOT) if self.boot_mapped && value != 0 => {
+                        self.boot_mapped = false;
+                        log!("Boot ROM unmapped at frame {}", self.frame_count());
+                    }
+                    Some(HardwareRegister::BOOT) => (),
+                    Some(HardwareRegister::RP) if self.infrared.is_attached() => {
+                        let clock = self.clock();
+                        self.infrared.write(value, clock);
+                    }
+                    _ => log!("Unimplemented hardware register write ${:04X}.", address),
+                };
+            }
+            _ => (),
+        }
+    }
+
+    pub fn new() -> Self {
+        let mut emulator = Emulator {
+            ticks: 0,
+            bus: MemoryBus::new(),
+            interrupts: InterruptLine::new(),
+            dma: DMA::new(),
+            ppu: PPU::new(),
+            apu: APU::new(),
+            audio: None,
+            counter: SystemCounter::new(),
+            timer: Timer::new(),
+            serial: Serial::new(),
+            infrared: Infrared::new(),
+            joypad: Joypad::new(),
+            cheats: CheatList::new(),
+            cheat_frame: 0,
+            frozen: BTreeMap::new(),
+            instruction_pc: 0,
+            instruction_ticks: 0,
+            accesses: None,
+            bank_guard: false,
+            stack_guard: false,
+            header_guard: false,
+            mapper_inference: false,
+            boot_rom: None,
+            boot_mapped: false,
+            dma_guard: false,
+            restricted_writes: RestrictedWrites::Allow,
+            restricted_write: None,
+            accuracy: AccuracyConfig::default(),
+            fault: None,
+            stats: Stats::new(),
+            model: Model::default(),
+            ram_init: RamInit::default(),
+            polls: None,
+            idle: None,
+            watchdog: None,
+            profiler: None,
+            warnings: None,
+            mapper_log: None,
+            ppu_break: None,
+            ppu_break_hit: false,
+            scanline_hook: None,
+            scheduler: Scheduler::new(),
+        };
+
+        emulator.set_quirks(Quirks::for_model(emulator.model));
+        emulator.apply_power_on();
+        emulator
+    }
+
+    /// Set the IO registers and DIV to what the boot ROM of the model leaves
+    /// behind, or leave them cleared for the boot ROM to set up.
+    fn apply_power_on(&mut self) {
+        self.fill_ram();
+
+        if !self.boot_mapped {
+            let power_on = PowerOnState::for_model(self.model);
+
+            for (register, value) in power_on.io {
+                self.write(*register as u16, *value);
+            }
+            if self.quirks().contains(Quirks::DIV_PHASE) {
+                self.counter.set(power_on.div);
+            }
+        }
+
+        self.apu.update_div(self.counter.value());
+        self.schedule_events();
+    }
+
+    /// Clear the system counter through DIV or STOP. Bits that were set
+    /// fall, which can count TIMA and clock the APU frame sequencer early.
+    fn clear_counter(&mut self) {
+        let before = self.counter.clear();
+        self.timer.counter_cleared(before, &mut self.interrupts);
+        self.apu.update_div(self.counter.value());
+        self.schedule_timer();
+    }
+
+    /// Schedule the next TIMA increment and frame sequencer clock from DIV
+    /// and TAC, after either changed other than by counting.
+    fn schedule_timer(&mut self) {
+        match self.timer.next_increment(self.counter.value()) {
+            Some(dots) => self
+                .scheduler
+                .schedule(self.ticks + dots, Event::TimaIncrement),
+            None => self.scheduler.cancel(Event::TimaIncrement),
+        }
+
+        let dots = self.counter.next_toggle(DIV_APU_BIT);
+        self.scheduler.schedule(self.ticks + dots, Event::DivApuBit);
+    }
+
+    fn run_event(&mut self, event: Event) {
+        match event {
+            Event::TimaIncrement => {
+                self.timer.increment_tima(&mut self.interrupts);
+
+                if let Some(dots) = self.timer.next_increment(self.counter.value()) {
+                    self.scheduler
+                        .schedule(self.ticks + dots, Event::TimaIncrement);
+                }
+            }
+            Event::DivApuBit => {
+                self.apu.update_div(self.counter.value());
+                let dots = self.counter.next_toggle(DIV_APU_BIT);
+                self.scheduler.schedule(self.ticks + dots, Event::DivApuBit);
+            }
+            Event::PpuUpdate => {
+                self.update_ppu();
+                self.schedule_ppu();
+            }
+            Event::DmaCycle => {
+                self.dma.tick_cycle(&self.bus, &mut self.ppu);
+                self.schedule_dma();
+            }
+        }
+    }
+
+    fn schedule_ppu(&mut self) {
+        let dots = self.ppu.dots_until_update();
+        self.scheduler.schedule(self.ticks + dots, Event::PpuUpdate);
+    }
+
+    /// Schedule the next M-cycle of OAM DMA while a transfer has some left,
+    /// one byte is copied per M-cycle.
+    fn schedule_dma(&mut self) {
+        if self.dma.is_busy() {
+            let next = (self.ticks / DOTS_PER_M_CYCLE + 1) * DOTS_PER_M_CYCLE;
+            self.scheduler.schedule(next, Event::DmaCycle);
+        } else {
+            self.scheduler.cancel(Event::DmaCycle);
+        }
+    }
+
+    /// Schedule every event again, after the components were reset, loaded
+    /// or stood still.
+    fn schedule_events(&mut self) {
+        self.schedule_timer();
+        self.schedule_ppu();
+        self.schedule_dma();
+    }
+
+    /// Switch to another hardware model, the machine is power cycled with
+    /// its post-boot state. The CPU registers are taken on its next reset.
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.set_quirks(Quirks::for_model(model));
+        self.reset();
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Run `rom`, a DMG boot ROM, on power on and reset instead of starting
+    /// from the post-boot state of the model. The machine is power cycled,
+    /// the CPU takes the registers on its next reset.
+    pub fn set_boot_rom(&mut self, rom: Option<[u8; BOOT_ROM_SIZE]>) {
+        self.boot_rom = rom;
+        self.reset();
+    }
+
+    /// Whether the boot ROM is still mapped over 0x0000 - 0x00FF.
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_mapped
+    }
+
+    /// Hardware bugs to emulate, set to those of the model by `set_model`.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.ppu.set_quirks(quirks);
+        self.timer.set_quirks(quirks);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.timer.quirks()
+    }
+
+    /// Reset every component to its post-boot state but keep the contents of
+    /// VRAM, WRAM, OAM and HRAM, like a game restarting itself.
+    /// The CPU has to be reset as well, see `state::soft_reset`.
+    pub fn soft_reset(&mut self) {
+        let ranges = [
+            (0x8000u16, 0x9FFFu16),
+            (0xC000, 0xDFFF),
+            (0xFE00, 0xFE9F),
+            (0xFF80, 0xFFFE),
+        ];
+        let ram: Vec<Vec<u8>> = ranges
+            .iter()
+            .map(|&(start, end)| {
+                (start..=end)
+                    .map(|address| self.read_memory(address))
+                    .collect()
+            })
+            .collect();
+
+        // The game restarts, the boot ROM doesn't run again
+        let boot_rom = self.boot_rom.take();
+        self.reset();
+        self.boot_rom = boot_rom;
+
+        for ((start, end), bytes) in ranges.into_iter().zip(ram) {
+            for (address, value) in (start..=end).zip(bytes) {
+                self.write_memory(address, value);
+            }
+        }
+    }
+
+    /// Byte of VRAM, OAM, WRAM or HRAM as stored, whatever the PPU or DMA
+    /// would let the CPU see.
+    fn read_memory(&self, address: u16) -> u8 {
+        match Page::of(address) {
+            Page::Vram => self.ppu.vram_read(address),
+            Page::Oam => self.ppu.oam_read(address),
+            _ => self.bus.read_ram(address),
+        }
+    }
+
+    /// Store a byte read by `read_memory`.
+    fn write_memory(&mut self, address: u16, value: u8) {
+        match Page::of(address) {
+            Page::Vram => self.ppu.vram_write(address, value),
+            Page::Oam => self.ppu.oam_write(address, value),
+            _ => self.bus.write_ram(address, value),
+        }
+    }
+
+    /// Fill WRAM, HRAM and VRAM now and on every reset.
+    pub fn set_ram_init(&mut self, ram_init: RamInit) {
+        self.ram_init = ram_init;
+        self.fill_ram();
+    }
+
+    fn fill_ram(&mut self) {
+        for (start, end) in [(0x8000u16, 0x9FFFu16), (0xC000, 0xDFFF), (0xFF80, 0xFFFE)] {
+            let mut ram = alloc::vec![0; (end - start) as usize + 1];
+            self.ram_init.fill(&mut ram, start as u64);
+
+            for (address, value) in (start..=end).zip(ram) {
+                self.write(address, value);
+            }
+        }
+    }
+
+    /// Advance every component except the CPU by one dot (T-cycle).
+    ///
+    /// The CPU consumes dots in groups of 4 through `tick_cycle`, components
+    /// that need sub M-cycle accuracy should do their work here.
+    pub fn tick_dot(&mut self) {
+        self.ticks += 1;
+        self.counter.tick();
+        self.ppu.count_dots(1);
+
+        while let Some(event) = self.scheduler.pop_due(self.ticks) {
+            self.run_event(event);
+        }
+
+        self.apu.tick();
+
+        if let Some(audio) = &mut self.audio {
+            audio.push(self.apu.output());
+        }
+
+        let (ticks, ppu) = (self.ticks, &self.ppu);
+        self.serial.tick(&mut self.interrupts, || {
+            let (ly, dot) = ppu.position();
+            EmuClock::new(ticks, ly, dot)
+        });
+        self.bus.tick(1);
+        self.interrupts.stats.stamp(self.ticks);
+    }
+
+    /// Dots from now on that only count, before the next scheduled event or
+    /// serial bit. None while audio is collected, every dot is a sample.
+    fn quiet_dots(&self) -> u64 {
+        if self.audio.is_some() {
+            return 0;
+        }
+
+        let event = self.scheduler.next_due().saturating_sub(self.ticks + 1);
+        event.min(self.serial.dots_until_bit() - 1)
+    }
+
+    /// Let `dots` dots pass at once, at most `quiet_dots`, the same as as
+    /// many `tick_dot`s.
+    fn skip_dots(&mut self, dots: u64) {
+        self.interrupts.stats.stamp(self.ticks + 1);
+        self.ticks += dots;
+        self.counter.advance(dots);
+        self.ppu.count_dots(dots);
+        self.apu.advance(dots);
+        self.serial.count_dots(dots);
+        self.bus.tick(dots);
+    }
+
+    /// Run the PPU on a dot it has something to do on, with the hooks
+    /// watching its lines and modes.
+    fn update_ppu(&mut self) {
+        let mode = self.ppu.mode();
+        let line = self
+            .scanline_hook
+            .is_some()
+            .then(|| self.ppu.lcd_read(HardwareRegister::LY));
+        self.ppu.update(&mut self.interrupts);
+
+        if let Some(hook) = &mut self.scanline_hook
+            && let Some(line) = line
+        {
+            let ly = self.ppu.lcd_read(HardwareRegister::LY);
+
+            if ly != line {
+                hook.on_scanline(
+                    ly,
+                    &mut LcdRegisters::new(&mut self.ppu, &mut self.interrupts),
+                );
+            }
+        }
+
+        if self.ppu_break.is_some() && !self.ppu_break_hit && self.ppu.mode() != mode {
+            let ly = self.ppu.lcd_read(HardwareRegister::LY);
+            self.ppu_break_hit = PpuEvent::of_transition(self.ppu.mode(), ly) == self.ppu_break;
+        }
+
+        if self.cheat_frame != self.ppu.get_current_frame() {
+            self.cheat_frame = self.ppu.get_current_frame();
+            self.apply_frame_cheats();
+
+            if let Some(watchdog) = &mut self.watchdog {
+                watchdog.end_frame(self.ppu.frame().hash());
+            }
+        }
+    }
+
+    fn apply_frame_cheats(&mut self) {
+        for (address, value) in self.cheats.frame_writes() {
+            // Only RAM, a write to ROM would select a bank instead
+            if matches!(address, 0xA000..=0xDFFF | 0xFF80..=0xFFFE) {
+                self.bus.write(address, value);
+            }
+        }
+    }
+
+    pub fn cheats(&self) -> &CheatList {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut CheatList {
+        &mut self.cheats
+    }
+
+    /// Hold `buttons` until the next call, e.g. between frames of a scripted run.
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.joypad.set_pressed(buttons, &mut self.interrupts);
+    }
+
+    /// Buttons currently held.
+    pub fn buttons(&self) -> Buttons {
+        self.joypad.pressed()
+    }
+
+    /// P1 as the CPU reads it now, with the groups it selected.
+    pub fn joypad_register(&self) -> u8 {
+        self.joypad.read()
+    }
+
+    /// Write a value to any address as the CPU would, without taking a cycle.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.write(address, value);
+    }
+
+    /// Hold an address at a value, CPU writes to it write the value instead.
+    pub fn freeze(&mut self, address: u16, value: u8) {
+        self.frozen.insert(address, value);
+        self.write(address, value);
+    }
+
+    pub fn unfreeze(&mut self, address: u16) {
+        self.frozen.remove(&address);
+    }
+
+    /// Frozen addresses and their values, in address order.
+    pub fn frozen(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.frozen
+            .iter()
+            .map(|(address, value)| (*address, *value))
+    }
+
+    /// Replace the active cheats, Game Genie codes take effect on the next
+    /// ROM read and GameShark codes once per frame.
+    pub fn set_cheats(&mut self, cheats: CheatList) {
+        self.cheats = cheats;
+    }
+
+    pub fn load_cartridge(&mut self, rom: Cartridge) {
+        self.bus.set_rom(Some(rom));
+    }
+
+    /// Take the cartridge out and power on with `rom`, for switching games
+    /// without a new emulator. Settings and diagnostics stay, the cheats and
+    /// frozen addresses of the old game go. Returns the old cartridge for
+    /// its battery RAM and RTC to be saved. The CPU has to be reset as well,
+    /// see `state::swap_cartridge`.
+    pub fn swap_cartridge(&mut self, rom: Cartridge) -> Option<Cartridge> {
+        let old = self.bus.replace_rom(Some(rom));
+
+        self.cheats = CheatList::new();
+        self.frozen.clear();
+        self.fault = None;
+        self.restricted_write = None;
+        self.reset();
+        old
+    }
+
+    /// Stop the CPU with a fault when it executes a ROM bank past the end of
+    /// the ROM or disabled external RAM, instead of running whatever is read
+    /// there. See `CPU::set_report_faults`, the CPU panics otherwise.
+    pub fn set_bank_guard(&mut self, enabled: bool) {
+        self.bank_guard = enabled;
+    }
+
+    /// Stop the CPU with a fault when it executes outside HRAM while OAM DMA
+    /// runs, code there reads whatever DMA puts on the bus. Faults are
+    /// reported like with `set_bank_guard`.
+    pub fn set_dma_guard(&mut self, enabled: bool) {
+        self.dma_guard = enabled;
+    }
+
+    /// Stop the CPU with a fault when a push or pop reaches outside WRAM and
+    /// HRAM, into OAM, the IO registers or around the end of memory, which
+    /// usually means the game crashed. Reported at the start of the next
+    /// instruction, like `set_bank_guard` faults.
+    pub fn set_stack_guard(&mut self, enabled: bool) {
+        self.stack_guard = enabled;
+    }
+
+    /// Warn about writes to the vectors and header at $0000 - $014F that
+    /// aren't mapper operations, a pointer into ROM or code written for
+    /// another mapper. Reported as `WarningKind::HeaderWrite` to the log of
+    /// `set_warnings`, the write goes on as on hardware.
+    pub fn set_header_guard(&mut self, enabled: bool) {
+        self.header_guard = enabled;
+    }
+
+    /// Switch a cartridge whose header says ROM only to MBC1 when it selects
+    /// a ROM bank, for bad dumps and homebrew with the wrong type byte. The
+    /// bank switch is logged either way, off by default.
+    pub fn set_mapper_inference(&mut self, enabled: bool) {
+        self.mapper_inference = enabled;
+    }
+
+    /// Log or break on writes to VRAM and OAM the PPU would block. Allowed
+    /// without a word by default.
+    pub fn set_restricted_writes(&mut self, restricted_writes: RestrictedWrites) {
+        self.restricted_writes = restricted_writes;
+        self.restricted_write = None;
+    }
+
+    pub fn restricted_writes(&self) -> RestrictedWrites {
+        self.restricted_writes
+    }
+
+    /// The first write `RestrictedWrites::Break` caught since the last call.
+    pub fn take_restricted_write(&mut self) -> Option<String> {
+        self.restricted_write.take()
+    }
+
+    /// Collect diagnostics for ROM developers into `warnings`, None stops.
+    /// Each kind is printed the first time an instruction runs into it. Off
+    /// by default.
+    pub fn set_warnings(&mut self, warnings: Option<WarningLog>) {
+        self.warnings = warnings;
+    }
+
+    pub fn warnings(&self) -> Option<&WarningLog> {
+        self.warnings.as_ref()
+    }
+
+    /// Log the writes of the CPU to the ROM area into `log`, None stops.
+    /// Off by default.
+    pub fn set_mapper_log(&mut self, log: Option<MapperLog>) {
+        self.mapper_log = log;
+    }
+
+    pub fn mapper_log(&self) -> Option<&MapperLog> {
+        self.mapper_log.as_ref()
+    }
+
+    fn mapper_state(&self) -> MapperState {
+        let registers = self.mapper_registers().unwrap_or_default();
+        MapperState {
+            ram_enabled: registers[0] & 0x0F == 0x0A,
+            rom_bank: self.bank_of(0x4000),
+            ram_bank: registers[2],
+        }
+    }
+
+    fn log_mapper_write(&mut self, address: u16, value: u8) {
+        let before = self.mapper_state();
+        self.write(address, value);
+
+        let register = self
+            .cartridge()
+            .map_or(MapperRegister::None, |rom| rom.mapper_register(address));
+        let write = MapperWrite {
+            clock: self.clock(),
+            bank: self.bank_of(self.instruction_pc),
+            pc: self.instruction_pc,
+            address,
+            value,
+            register,
+            before,
+            after: self.mapper_state(),
+        };
+
+        if let Some(log) = &mut self.mapper_log {
+            log.record(write);
+        }
+    }
+
+    /// Warn and fault on a push or pop of the two bytes at `bottom` outside
+    /// WRAM and HRAM.
+    fn check_stack(&mut self, kind: WarningKind, bottom: u16) {
+        let in_ram = |address: u16| matches!(address, 0xC000..=0xDFFF | 0xFF80..=0xFFFE);
+        if in_ram(bottom) && in_ram(bottom.wrapping_add(1)) {
+            return;
+        }
+
+        if self.warnings.is_some() {
+            self.warn(kind, bottom);
+        }
+
+        if self.stack_guard && self.fault.is_none() {
+            let access = match kind {
+                WarningKind::StackUnderflow => "popped from",
+                _ => "pushed to",
+            };
+            self.fault = Some(format!(
+                "${:04X} {access} ${bottom:04X}, outside WRAM and HRAM",
+                self.instruction_pc
+            ));
+        }
+    }
+
+    /// Warn on a write to the vectors or header no mapper register makes
+    /// sense of. RAM enable writes of 0x00 or with 0xA in the low nibble
+    /// are what games write on purpose.
+    fn check_header_write(&mut self, address: u16, value: u8) {
+        let register = self
+            .cartridge()
+            .map_or(MapperRegister::None, |rom| rom.mapper_register(address));
+        let mapped = match register {
+            MapperRegister::RamEnable => value == 0x00 || value & 0x0F == 0x0A,
+            MapperRegister::None => false,
+            _ => true,
+        };
+
+        if !mapped {
+            self.warn(WarningKind::HeaderWrite, address);
+        }
+    }
+
+    fn check_undeclared_banking(&mut self, value: u8) {
+        let infer = self.mapper_inference;
+        let Some(rom) = self.cartridge_mut() else {
+            return;
+        };
+
+        if rom.detect_undeclared_banking(value, infer) {
+            let bank = value & 0x1F;
+            if infer {
+                log!("ROM only cartridge selected ROM bank {bank:02X}, switching to MBC1");
+            } else {
+                log!(
+                    "Warning: ROM only cartridge selected ROM bank {bank:02X}, the header type is \
+                     likely wrong, mapper inference would switch to MBC1"
+                );
+            }
+        }
+    }
+
+    fn warn(&mut self, kind: WarningKind, address: u16) {
+        let bank = self
+            .cartridge()
+            .map_or(0, |rom| rom.bank_of(self.instruction_pc));
+        let (pc, clock) = (self.instruction_pc, self.clock());
+
+        if let Some(warnings) = &mut self.warnings
+            && warnings.record(kind, bank, pc, address, clock)
+        {
+            log!("Warning: {kind} at {bank:02X}:{pc:04X}, ${address:04X} ({clock})");
+        }
+    }
+
+    /// Mode of the PPU if it blocks the CPU from `address` now.
+    fn restricted_mode(&self, address: u16) -> Option<LcdMode> {
+        let lcdc = LcdControl::from_bits_truncate(self.ppu.lcd_read(HardwareRegister::LCDC));
+        if !lcdc.contains(LcdControl::LCD_PPU_ENABLE) {
+            return None;
+        }
+
+        let mode = self.ppu.mode();
+        match (Page::of(address), mode) {
+            (Page::Vram, LcdMode::XFER) => Some(mode),
+            (Page::Oam, LcdMode::OAM | LcdMode::XFER) if address <= 0xFE9F => Some(mode),
+            _ => None,
+        }
+    }
+
+    pub fn set_accuracy(&mut self, accuracy: AccuracyConfig) {
+        self.accuracy = accuracy;
+        self.ppu.set_fifo_renderer(accuracy.fifo_renderer);
+        self.schedule_ppu();
+        self.apu.set_wave_ram_quirks(accuracy.wave_ram_quirks);
+        self.idle = accuracy.idle_skip.then(IdleDetector::new);
+    }
+
+    pub fn accuracy(&self) -> AccuracyConfig {
+        self.accuracy
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.bus.rom()
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.bus.rom_mut()
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    /// Colors used to present frames, see `Frame`.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.ppu.set_palette(palette);
+    }
+
+    pub fn palette(&self) -> Palette {
+        *self.ppu.frame().palette()
+    }
+
+    /// Value of BGP, OBP0 or OBP1.
+    pub fn palette_register(&self, register: PaletteRegister) -> u8 {
+        self.ppu.lcd_read(register.hardware_register())
+    }
+
+    /// Write BGP, OBP0 or OBP1 as the game would, pixels drawn from now on
+    /// use it until the game writes the register again.
+    pub fn set_palette_register(&mut self, register: PaletteRegister, value: u8) {
+        self.ppu
+            .lcd_write(register.hardware_register(), value, &mut self.interrupts);
+    }
+
+    /// Layers drawn into frames, see `PPU::set_visible_layers`.
+    pub fn set_visible_layers(&mut self, layers: Layers) {
+        self.ppu.set_visible_layers(layers);
+    }
+
+    /// Sprites drawn per line, see `PPU::set_sprite_limit`. Not accurate
+    /// above `SPRITES_PER_LINE`.
+    pub fn set_sprite_limit(&mut self, limit: usize) {
+        self.ppu.set_sprite_limit(limit);
+    }
+
+    /// Frame rate and speed on the host.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// For frontends to report audio figures, see `Stats::set_audio_drift`.
+    pub fn stats_mut(&mut self) -> &mut Stats {
+        &mut self.stats
+    }
+
+    /// Report the host time taken by the last frame, see `Stats::record_frame`.
+    pub fn record_frame_time(&mut self, frame_time: Duration) {
+        self.stats.record_frame(frame_time);
+    }
+
+    /// Tiles written since the last call, see `PPU::take_dirty_tiles`.
+    pub fn take_dirty_tiles(&mut self) -> TileSet {
+        self.ppu.take_dirty_tiles()
+    }
+
+    pub fn get_current_frame(&self) -> u32 {
+        self.ppu.get_current_frame()
+    }
+
+    /// Hash of WRAM, the IO registers and HRAM, see `state::checksum`.
+    pub fn memory_checksum(&self) -> u64 {
+        let memory: Vec<u8> = (0xC000..=0xDFFF)
+            .chain(0xFF00..=0xFFFF)
+            .map(|address| self.bus.read(address))
+            .collect();
+
+        hash::fnv1a64(&memory)
+    }
+
+    /// Tick at which the next frame is complete, a bound for `CPU::run_until`.
+    pub fn next_frame_tick(&self) -> u64 {
+        self.ticks + self.ppu.dots_until_frame()
+    }
+
+    /// The current T-cycle with the frame and the position of the PPU, what
+    /// debug events are stamped with.
+    pub fn clock(&self) -> EmuClock {
+        let (ly, dot) = self.ppu.position();
+        EmuClock::new(self.ticks, ly, dot)
+    }
+
+    /// Frames of emulated time since power on.
+    ///
+    /// Counted from ticks alone, without looking at the PPU, so actions
+    /// scheduled by it happen at the same point of every run.
+    pub fn frame_count(&self) -> u64 {
+        self.ticks / DOTS_PER_FRAME
+    }
+
+    /// The system counter DIV, TIMA and the APU frame sequencer run on.
+    pub fn system_counter(&self) -> SystemCounter {
+        self.counter
+    }
+
+    /// Emulated time since power on, from ticks alone.
+    pub fn emulated_duration(&self) -> Duration {
+        let nanos = (self.ticks % CLOCK_HZ) * 1_000_000_000 / CLOCK_HZ;
+        Duration::new(self.ticks / CLOCK_HZ, nanos as u32)
+    }
+
+    /// Count reads of LY and STAT per instruction into `polls`, None stops
+    /// counting. Off by default.
+    pub fn set_poll_counter(&mut self, polls: Option<PollCounter>) {
+        self.polls = polls;
+    }
+
+    pub fn poll_counter(&self) -> Option<&PollCounter> {
+        self.polls.as_ref()
+    }
+
+    /// Watch for frames that stop changing, None turns it off. Off by default.
+    pub fn set_watchdog(&mut self, watchdog: Option<HangWatchdog>) {
+        self.watchdog = watchdog;
+    }
+
+    /// Time the executed code per instruction into `profiler`, None stops
+    /// profiling. Off by default.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Watch for a transition of the PPU, `take_ppu_break` tells when it
+    /// happened. None stops watching.
+    pub fn set_ppu_break(&mut self, event: Option<PpuEvent>) {
+        self.ppu_break = event;
+        self.ppu_break_hit = false;
+    }
+
+    pub fn ppu_break(&self) -> Option<PpuEvent> {
+        self.ppu_break
+    }
+
+    /// The event set with `set_ppu_break` once it happened, which stops
+    /// watching for it.
+    pub fn take_ppu_break(&mut self) -> Option<PpuEvent> {
+        if !self.ppu_break_hit {
+            return None;
+        }
+
+        self.ppu_break_hit = false;
+        self.ppu_break.take()
+    }
+
+    /// Instructions around `pc` as they are in memory now, see
+    /// `cpu::disassemble_around`. IO registers that aren't emulated read 0xFF.
+    pub fn disassemble_around(&self, pc: u16, before: usize, after: usize) -> Vec<Disassembly> {
+        disassemble_around(
+            |address| self.inspect(address).unwrap_or(0xFF),
+            pc,
+            before,
+            after,
+        )
+    }
+
+    /// True once the watchdog saw the game lock up, once per lock-up.
+    pub fn take_hang(&mut self) -> bool {
+        self.watchdog.as_mut().is_some_and(HangWatchdog::take_hang)
+    }
+
+    /// Interrupts requested and dispatched since power on, per source.
+    pub fn interrupt_stats(&self) -> &InterruptStats {
+        &self.interrupts.stats
+    }
+
+    /// Send the audio to a host backend, None stops the output.
+    pub fn set_audio_output(&mut self, audio: Option<AudioOutput>) {
+        self.audio = audio;
+    }
+
+    pub fn audio_output_mut(&mut self) -> Option<&mut AudioOutput> {
+        self.audio.as_mut()
+    }
+
+    /// Call `hook` at the start of every scanline, None removes it. Not
+    /// part of save states.
+    pub fn set_scanline_hook(&mut self, hook: Option<Box<dyn ScanlineHook>>) {
+        self.scanline_hook = hook;
+    }
+
+    /// Attach a link partner to the serial port, None disconnects it.
+    pub fn set_serial_device(&mut self, device: Option<Box<dyn SerialDevice>>) {
+        self.serial.set_device(device);
+    }
+
+    /// Attach what faces the infrared port of a Game Boy Color, mapping RP
+    /// ($FF56). None takes it away, as on the monochrome models.
+    pub fn set_infrared_device(&mut self, device: Option<Box<dyn InfraredDevice>>) {
+        self.infrared.set_device(device);
+        let clock = self.clock();
+        self.infrared.update_device(clock);
+    }
+
+    /// Bytes sent over the serial port so far, test ROMs report results this way.
+    pub fn serial_output(&self) -> &str {
+        self.serial.output()
+    }
+
+    /// See `Serial::truncate_output`, the output is not part of save states.
+    pub fn truncate_serial_output(&mut self, len: usize) {
+        self.serial.truncate_output(len);
+    }
+}
+
+impl Resettable for Emulator {
+    fn reset(&mut self) {
+        // Destructured so that a new component can't be left out
+        let Emulator {
+            ticks,
+            bus,
+            interrupts,
+            dma,
+            ppu,
+            apu,
+            audio: _,
+            counter,
+            timer,
+            serial,
+            infrared,
+            joypad,
+            cheats: _,
+            cheat_frame: _,
+            frozen: _,
+            instruction_pc: _,
+            instruction_ticks: _,
+            accesses: _,
+            bank_guard: _,
+            stack_guard: _,
+            header_guard: _,
+            mapper_inference: _,
+            boot_rom,
+            boot_mapped,
+            dma_guard: _,
+            restricted_writes: _,
+            restricted_write: _,
+            accuracy: _,
+            fault: _,
+            stats: _,
+            model: _,
+            ram_init: _,
+            polls: _,
+            idle: _,
+            watchdog: _,
+            profiler: _,
+            warnings: _,
+            mapper_log: _,
+            ppu_break: _,
+            ppu_break_hit: _,
+            scanline_hook: _,
+            scheduler,
+        } = self;
+
+        *ticks = 0;
+        scheduler.clear();
+        bus.reset();
+        interrupts.reset();
+        dma.reset();
+        ppu.reset();
+        apu.reset();
+        counter.reset();
+        timer.reset();
+        serial.reset();
+        infrared.reset();
+        joypad.reset();
+        *boot_mapped = boot_rom.is_some();
+        self.apply_power_on();
+    }
+}
+
+impl Saveable for Emulator {
+    fn save_state(&self, state: &mut StateWriter) {
+        // Destructured so that a new component can't be left out
+        let Emulator {
+            ticks,
+            bus,
+            interrupts,
+            dma,
+            ppu,
+            apu,
+            audio: _,
+            counter,
+            timer,
+            serial,
+            infrared,
+            joypad,
+            cheats: _,
+            cheat_frame: _,
+            frozen: _,
+            instruction_pc: _,
+            instruction_ticks: _,
+            accesses: _,
+            bank_guard: _,
+            stack_guard: _,
+            header_guard: _,
+            mapper_inference: _,
+            boot_rom: _,
+            boot_mapped,
+            dma_guard: _,
+            restricted_writes: _,
+            restricted_write: _,
+            accuracy: _,
+            fault: _,
+            stats: _,
+            model: _,
+            ram_init: _,
+            polls: _,
+            idle: _,
+            watchdog: _,
+            profiler: _,
+            warnings: _,
+            mapper_log: _,
+            ppu_break: _,
+            ppu_break_hit: _,
+            scanline_hook: _,
+            scheduler: _,
+        } = self;
+
+        state.write_chunk(TICKS_CHUNK, |state| state.write_u64(*ticks));
+        state.write_chunk(BUS_CHUNK, |state| bus.save_state(state));
+        state.write_chunk(INTERRUPTS_CHUNK, |state| interrupts.save_state(state));
+        state.write_chunk(DMA_CHUNK, |state| dma.save_state(state));
+        state.write_chunk(PPU_CHUNK, |state| ppu.save_state(state));
+        state.write_chunk(APU_CHUNK, |state| apu.save_state(state));
+        state.write_chunk(COUNTER_CHUNK, |state| counter.save_state(state));
+        state.write_chunk(TIMER_CHUNK, |state| timer.save_state(state));
+        state.write_chunk(SERIAL_CHUNK, |state| serial.save_state(state));
+        state.write_chunk(INFRARED_CHUNK, |state| infrared.save_state(state));
+        state.write_chunk(JOYPAD_CHUNK, |state| joypad.save_state(state));
+        state.write_chunk(BOOT_CHUNK, |state| state.write_bool(*boot_mapped));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let Emulator {
+            ticks,
+            bus,
+            interrupts,
+            dma,
+            ppu,
+            apu,
+            audio: _,
+            counter,
+            timer,
+            serial,
+            infrared,
+            joypad,
+            cheats: _,
+            cheat_frame: _,
+            frozen: _,
+            instruction_pc: _,
+            instruction_ticks: _,
+            accesses: _,
+            bank_guard: _,
+            stack_guard: _,
+            header_guard: _,
+            mapper_inference: _,
+            boot_rom,
+            boot_mapped,
+            dma_guard: _,
+            restricted_writes: _,
+            restricted_write: _,
+            accuracy: _,
+            fault: _,
+            stats: _,
+            model: _,
+            ram_init: _,
+            polls: _,
+            idle: _,
+            watchdog: _,
+            profiler: _,
+            warnings: _,
+            mapper_log: _,
+            ppu_break: _,
+            ppu_break_hit: _,
+            scanline_hook: _,
+            scheduler: _,
+        } = self;
+
+        load_chunks(state, |tag, chunk| {
+            match tag {
+                TICKS_CHUNK => *ticks = chunk.read_u64()?,
+                BUS_CHUNK => bus.load_state(chunk)?,
+                INTERRUPTS_CHUNK => interrupts.load_state(chunk)?,
+                DMA_CHUNK => dma.load_state(chunk)?,
+                PPU_CHUNK => ppu.load_state(chunk)?,
+                APU_CHUNK => apu.load_state(chunk)?,
+                COUNTER_CHUNK => counter.load_state(chunk)?,
+                TIMER_CHUNK => timer.load_state(chunk)?,
+                SERIAL_CHUNK => serial.load_state(chunk)?,
+                INFRARED_CHUNK => infrared.load_state(chunk)?,
+                JOYPAD_CHUNK => joypad.load_state(chunk)?,
+                // A state from the boot can only go on with the same boot ROM
+                BOOT_CHUNK => *boot_mapped = chunk.read_bool()? && boot_rom.is_some(),
+                _ => return Ok(false),
+            }
+            Ok(true)
+        })?;
+
+        self.schedule_events();
+        let clock = self.clock();
+        self.infrared.update_device(clock);
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+use alloc::vec::Vec;
+
+use super::hash;
+use super::lcd::DEFAULT_COLORS;
+use super::png;
+use super::ppu::{XRES, YRES};
+
+const WINDOW_BIT: u8 = 0b01_0000;
+const OPAQUE_BIT: u8 = 0b10_0000;
+
+/// Source of a pixel, selects the palette it is presented with.
+///
+/// The window is drawn with the background palette and reported as background.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Layer {
+    Background,
+    Object0,
+    Object1,
+}
+
+/// What won priority for a pixel, the background split by whether sprites
+/// behind it still show through. For finding priority bugs, see
+/// `Frame::priority_pixel`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PixelSource {
+    /// Background color 0, or the background turned off
+    BackgroundColor0,
+    /// Background colors 1 to 3
+    Background,
+    Window,
+    Object0,
+    Object1,
+}
+
+impl PixelSource {
+    pub const ALL: [PixelSource; 5] = [
+        PixelSource::BackgroundColor0,
+        PixelSource::Background,
+        PixelSource::Window,
+        PixelSource::Object0,
+        PixelSource::Object1,
+    ];
+
+    /// ARGB8888 color priority views tint pixels of the source with.
+    pub fn tint(self) -> u32 {
+        match self {
+            PixelSource::BackgroundColor0 => 0xFF808080,
+            PixelSource::Background => 0xFF2060FF,
+            PixelSource::Window => 0xFF20C040,
+            PixelSource::Object0 => 0xFFFF3030,
+            PixelSource::Object1 => 0xFFFFA000,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PixelSource::BackgroundColor0 => "BG0",
+            PixelSource::Background => "BG",
+            PixelSource::Window => "WIN",
+            PixelSource::Object0 => "OBP0",
+            PixelSource::Object1 => "OBP1",
+        }
+    }
+}
+
+/// Colors used to present each layer, indexed by the shade (0 is the lightest)
+/// that the DMG palette registers produced for a pixel.
+///
+/// All layers use the same grey ramp on a DMG, the CGB colorizes DMG games by
+/// giving each of them its own set of colors.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Palette {
+    /// ARGB8888 colors of the background and window
+    pub background: [u32; 4],
+    /// ARGB8888 colors of objects using OBP0
+    pub object0: [u32; 4],
+    /// ARGB8888 colors of objects using OBP1
+    pub object1: [u32; 4],
+}
+
+impl Palette {
+    pub const DMG: Palette = Palette::uniform(DEFAULT_COLORS);
+
+    /// Palettes that can be picked by name, all going from light to dark so
+    /// that shades stay apart by brightness alone. `viridis` and `cividis`
+    /// follow the colormaps of the same name, which stay distinct with any
+    /// kind of color blindness.
+    pub const PRESETS: [(&'static str, Palette); 6] = [
+        ("grey", Palette::DMG),
+        (
+            "green",
+            Palette::uniform([0xFF9BBC0F, 0xFF8BAC0F, 0xFF306230, 0xFF0F380F]),
+        ),
+        (
+            "pocket",
+            Palette::uniform([0xFFC4CFA1, 0xFF8B956D, 0xFF4D533C, 0xFF1F1F1F]),
+        ),
+        (
+            "high_contrast",
+            Palette::uniform([0xFFFFFFFF, 0xFFFFD000, 0xFF0070E0, 0xFF000000]),
+        ),
+        (
+            "viridis",
+            Palette::uniform([0xFFFDE725, 0xFF35B779, 0xFF31688E, 0xFF440154]),
+        ),
+        (
+            "cividis",
+            Palette::uniform([0xFFFFEA46, 0xFFA69D75, 0xFF575C6D, 0xFF00224E]),
+        ),
+    ];
+
+    /// The same colors for every layer.
+    pub const fn uniform(colors: [u32; 4]) -> Self {
+        Palette {
+            background: colors,
+            object0: colors,
+            object1: colors,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Palette> {
+        Self::PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, palette)| *palette)
+    }
+
+    /// Parse a `.pal` file, four `RRGGBB` hex colors (`#` is optional) from
+    /// the lightest to the darkest, separated by spaces or new lines.
+    /// Lines starting with `;` are comments.
+    pub fn parse(text: &str) -> Option<Palette> {
+        let mut colors = [0; 4];
+        let mut count = 0;
+
+        for line in text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with(';'))
+        {
+            for word in line.split_whitespace() {
+                let hex = word.strip_prefix('#').unwrap_or(word);
+
+                if hex.len() != 6 || count == colors.len() {
+                    return None;
+                }
+
+                colors[count] = 0xFF000000 | u32::from_str_radix(hex, 16).ok()?;
+                count += 1;
+            }
+        }
+
+        (count == colors.len()).then(|| Palette::uniform(colors))
+    }
+
+    fn color(&self, layer: Layer, shade: u8) -> u32 {
+        let colors = match layer {
+            Layer::Background => &self.background,
+            Layer::Object0 => &self.object0,
+            Layer::Object1 => &self.object1,
+        };
+
+        colors[(shade & 0b11) as usize]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::DMG
+    }
+}
+
+/// A complete picture produced by the PPU.
+///
+/// Pixels are stored row by row, `XRES` pixels per line and `YRES` lines.
+/// Each pixel keeps the shade selected by BGP/OBP0/OBP1 when it was drawn and
+/// the layer it came from, colors are only looked up when the frame is read.
+/// Mid-frame palette register writes are therefore preserved, while the
+/// presentation palette can be switched at any time.
+#[derive(Clone)]
+pub struct Frame {
+    // Shade in bits 0-1, layer in bits 2-3, window in bit 4 and a background
+    // color other than 0 in bit 5
+    pixels: [u8; XRES * YRES],
+    palette: Palette,
+}
+
+/// Frame as 2-bit shades and their layers, colors are given by `palette`.
+pub struct IndexedFrame {
+    pub indices: Vec<u8>,
+    pub layers: Vec<Layer>,
+    pub palette: Palette,
+}
+
+impl Frame {
+    pub const WIDTH: usize = XRES;
+    pub const HEIGHT: usize = YRES;
+
+    pub fn new() -> Self {
+        Frame {
+            pixels: [0; XRES * YRES],
+            palette: Palette::DMG,
+        }
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Change the colors the frame is presented with, applies to pixels already drawn.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Fill the frame with the lightest shade, as the LCD shows while off.
+    pub fn clear(&mut self) {
+        self.pixels.fill(0);
+    }
+
+    /// Pixels as `0xAARRGGBB` values.
+    pub fn as_argb8888(&self) -> Vec<u32> {
+        (0..self.pixels.len()).map(|i| self.color(i)).collect()
+    }
+
+    /// Pixels as bytes in R, G, B, A order, the layout most image encoders expect.
+    pub fn as_rgba8888(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+
+        for i in 0..self.pixels.len() {
+            let [a, r, g, b] = self.color(i).to_be_bytes();
+            bytes.extend_from_slice(&[r, g, b, a]);
+        }
+
+        bytes
+    }
+
+    /// The frame encoded as a PNG file.
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode_rgba(XRES as u32, YRES as u32, &self.as_rgba8888())
+    }
+
+    /// Pixels as indices into the DMG shades, lightest first, and the layer
+    /// each one came from.
+    pub fn as_indexed(&self) -> IndexedFrame {
+        IndexedFrame {
+            indices: self.pixels.iter().map(|pixel| pixel & 0b11).collect(),
+            layers: self
+                .pixels
+                .iter()
+                .map(|pixel| Self::layer(*pixel))
+                .collect(),
+            palette: self.palette,
+        }
+    }
+
+    /// Hash of the shades and layers, independent of the presentation
+    /// palette, to tell frames apart without storing them.
+    pub fn hash(&self) -> u64 {
+        let visible: Vec<u8> = self.pixels.iter().map(|pixel| pixel & 0b1111).collect();
+        hash::fnv1a64(&visible)
+    }
+
+    /// ARGB8888 color of the pixel at `x`, `y`.
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.color(x + y * XRES)
+    }
+
+    /// What won priority for the pixel at `x`, `y`.
+    pub fn source(&self, x: usize, y: usize) -> PixelSource {
+        let pixel = self.pixels[x + y * XRES];
+
+        match Self::layer(pixel) {
+            Layer::Object0 => PixelSource::Object0,
+            Layer::Object1 => PixelSource::Object1,
+            Layer::Background if pixel & WINDOW_BIT != 0 => PixelSource::Window,
+            Layer::Background if pixel & OPAQUE_BIT != 0 => PixelSource::Background,
+            Layer::Background => PixelSource::BackgroundColor0,
+        }
+    }
+
+    /// ARGB8888 color of the pixel at `x`, `y` mixed half and half with the
+    /// tint of its source, the picture stays recognizable under the tint.
+    pub fn priority_pixel(&self, x: usize, y: usize) -> u32 {
+        let (color, tint) = (self.pixel(x, y), self.source(x, y).tint());
+        0xFF000000 | (((color & 0xFEFEFE) >> 1) + ((tint & 0xFEFEFE) >> 1))
+    }
+
+    /// Pack a shade and its layer into the internal pixel format.
+    pub(crate) fn pack(shade: u8, layer: Layer) -> u8 {
+        (shade & 0b11) | ((layer as u8) << 2)
+    }
+
+    /// Pack a background or window pixel of color `color_index`.
+    pub(crate) fn pack_background(shade: u8, color_index: u8, window: bool) -> u8 {
+        let mut pixel = Self::pack(shade, Layer::Background);
+        if window {
+            pixel |= WINDOW_BIT;
+        }
+        if color_index != 0 {
+            pixel |= OPAQUE_BIT;
+        }
+        pixel
+    }
+
+    pub(crate) fn set_pixel(&mut self, pixel_index: usize, pixel: u8) {
+        self.pixels[pixel_index] = pixel;
+    }
+
+    fn layer(pixel: u8) -> Layer {
+        match (pixel >> 2) & 0b11 {
+            1 => Layer::Object0,
+            2 => Layer::Object1,
+            _ => Layer::Background,
+        }
+    }
+
+    fn color(&self, pixel_index: usize) -> u32 {
+        let pixel = self.pixels[pixel_index];
+        self.palette.color(Self::layer(pixel), pixel)
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame::new()
+    }
+}
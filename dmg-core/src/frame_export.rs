@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::frame::Frame;
+
+/// Buffers shared by the writer and the readers. One holds the latest
+/// frame, the writer needs a free one besides and readers slow to let go
+/// of an older frame can hold the others.
+const SLOTS: usize = 4;
+
+struct Slot {
+    // Counted from 1 by `publish`, 0 for nothing yet
+    number: u64,
+    frame: Frame,
+}
+
+struct Shared {
+    slots: [RwLock<Slot>; SLOTS],
+    latest: AtomicUsize,
+    // Number of the frame in `latest`
+    number: AtomicU64,
+}
+
+/// The latest completed frame, for capture software like a GIF recorder
+/// or a remote viewer reading at its own rate on other threads.
+///
+/// Publishing never waits: the writer fills a slot no reader holds and
+/// makes it the latest, or drops the frame when readers hold every other
+/// slot. Readers only lock the latest slot, for reading, so any number of
+/// them read at once; one that loses the race with the writer waits for the
+/// copy of a frame at most. Clones share the same frames.
+#[derive(Clone)]
+pub struct FrameExport {
+    shared: Arc<Shared>,
+}
+
+impl FrameExport {
+    pub fn new() -> Self {
+        let slot = || {
+            RwLock::new(Slot {
+                number: 0,
+                frame: Frame::new(),
+            })
+        };
+
+        FrameExport {
+            shared: Arc::new(Shared {
+                slots: [slot(), slot(), slot(), slot()],
+                latest: AtomicUsize::new(0),
+                number: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Make a copy of `frame` the latest, false when it was dropped because
+    /// readers held every slot. Meant for a single writer, the emulation thread.
+    pub fn publish(&self, frame: &Frame) -> bool {
+        let shared = &*self.shared;
+        let latest = shared.latest.load(Ordering::SeqCst);
+
+        // A reader that got hold of a slot after it stopped being the latest
+        // sees so and lets go without reading
+        let free = (0..SLOTS)
+            .filter(|index| *index != latest)
+            .find_map(|index| Some((index, shared.slots[index].try_write().ok()?)));
+
+        let Some((index, mut slot)) = free else {
+            return false;
+        };
+
+        let number = shared.number.load(Ordering::SeqCst) + 1;
+        slot.number = number;
+        slot.frame.clone_from(frame);
+        drop(slot);
+
+        shared.latest.store(index, Ordering::SeqCst);
+        shared.number.store(number, Ordering::SeqCst);
+        true
+    }
+
+    /// Number of the latest frame, counted from 1, 0 before the first.
+    /// Cheap to poll for a new frame.
+    pub fn frame_number(&self) -> u64 {
+        self.shared.number.load(Ordering::SeqCst)
+    }
+
+    /// Call `read` with the latest frame and its number, None before the first.
+    pub fn read<R>(&self, read: impl FnOnce(u64, &Frame) -> R) -> Option<R> {
+        let shared = &*self.shared;
+
+        loop {
+            let latest = shared.latest.load(Ordering::SeqCst);
+            let slot = shared.slots[latest].read().unwrap();
+
+            // The writer moved on and may be filling this slot again
+            if shared.latest.load(Ordering::SeqCst) != latest {
+                continue;
+            }
+
+            return (slot.number > 0).then(|| read(slot.number, &slot.frame));
+        }
+    }
+
+    /// Copy of the latest frame and its number, None before the first.
+    pub fn latest(&self) -> Option<(u64, Frame)> {
+        self.read(|number, frame| (number, frame.clone()))
+    }
+}
+
+impl Default for FrameExport {
+    fn default() -> Self {
+        FrameExport::new()
+    }
+}
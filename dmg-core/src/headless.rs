@@ -0,0 +1,366 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::cart::Cartridge;
+use super::cpu::{CPU, CpuContext, EmulatorError};
+use super::emu::Emulator;
+use super::frame::Frame;
+use super::state::{self, StateError};
+
+/// What `run_until` runs the machine until.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// This many more frames were rendered
+    FrameCount(u32),
+    /// The serial output contains the text
+    SerialMatch(String),
+    /// The CPU is about to execute the address
+    PcEquals(u16),
+    /// The byte at the address, as the CPU would read it, has the value
+    MemoryEquals(u16, u8),
+}
+
+/// How a `run_until` ended.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RunOutcome {
+    Met,
+    /// `max_ticks` went by first
+    TimedOut,
+    CpuStopped,
+}
+
+/// Run `cpu` as fast as the host allows until `condition` holds or the
+/// machine reaches `max_ticks`, the building block of test runners, scripts
+/// and the fast boot.
+///
+/// Frame counts are run in batches up to the end of each frame, the other
+/// conditions are checked after every instruction.
+pub fn run_until(cpu: &mut CPU<Emulator>, condition: &Condition, max_ticks: u64) -> RunOutcome {
+    run_watched(cpu, condition, max_ticks, |_| ())
+}
+
+/// `run_until` with `after` called after every instruction or batch.
+fn run_watched(
+    cpu: &mut CPU<Emulator>,
+    condition: &Condition,
+    max_ticks: u64,
+    mut after: impl FnMut(&CPU<Emulator>),
+) -> RunOutcome {
+    let target_frame = match condition {
+        Condition::FrameCount(frames) => cpu.context().get_current_frame() + frames,
+        _ => 0,
+    };
+    // Only search the serial output again when it grew
+    let mut serial_checked = None;
+
+    loop {
+        let emu = cpu.context();
+        let met = match condition {
+            Condition::FrameCount(_) => emu.get_current_frame() >= target_frame,
+            Condition::SerialMatch(pattern) => {
+                let output = emu.serial_output();
+                let grew = serial_checked != Some(output.len());
+                serial_checked = Some(output.len());
+                grew && output.contains(pattern.as_str())
+            }
+            Condition::PcEquals(address) => cpu.registers().pc == *address,
+            Condition::MemoryEquals(address, value) => emu.inspect(*address) == Some(*value),
+        };
+
+        if met {
+            return RunOutcome::Met;
+        }
+        if emu.ticks() >= max_ticks {
+            return RunOutcome::TimedOut;
+        }
+
+        let running = match condition {
+            Condition::FrameCount(_) => {
+                let batch_end = emu.next_frame_tick().min(max_ticks);
+                cpu.run_until(batch_end)
+            }
+            _ => cpu.step(),
+        };
+        after(cpu);
+
+        if !running {
+            return RunOutcome::CpuStopped;
+        }
+    }
+}
+
+/// Runs the emulator without a window.
+///
+/// The CPU is stepped on the calling thread and nothing is throttled,
+/// so runs are as fast as the host allows. Used by the integration tests
+/// to drive test ROMs that report their results over the serial port or in
+/// cartridge RAM.
+pub struct Headless {
+    cpu: CPU<Emulator>,
+    // Copy of the last completed frame, the PPU draws over its own
+    last_frame: Frame,
+    frame_number: u32,
+}
+
+impl Headless {
+    pub fn new(rom: Cartridge) -> Self {
+        let mut emu = Emulator::new();
+        emu.load_cartridge(rom);
+
+        Headless {
+            cpu: CPU::new(emu),
+            last_frame: Frame::new(),
+            frame_number: 0,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_file(rom_file: &str) -> Result<Self, alloc::boxed::Box<dyn core::error::Error>> {
+        let rom = Cartridge::from_bytes(rom_file, &std::fs::read(rom_file)?)?;
+        Ok(Headless::new(rom))
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        self.cpu.context()
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Emulator {
+        self.cpu.context_mut()
+    }
+
+    pub fn cpu(&self) -> &CPU<Emulator> {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU<Emulator> {
+        &mut self.cpu
+    }
+
+    /// The most recent frame the PPU completed.
+    pub fn last_frame(&self) -> &Frame {
+        &self.last_frame
+    }
+
+    /// Save the last completed frame as `<name>.png`, a state as `<name>.state`
+    /// and the registers, fault and serial output as `<name>.txt` to `dir`,
+    /// so failing runs can be looked at later.
+    #[cfg(feature = "std")]
+    pub fn save_diagnostics(&self, dir: &std::path::Path, name: &str) -> std::io::Result<()> {
+        use alloc::format;
+
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join(format!("{name}.png")), self.last_frame.to_png())?;
+        std::fs::write(dir.join(format!("{name}.state")), self.save_state())?;
+
+        let fault = match self.cpu.fault() {
+            Some(fault) => format!("{fault}"),
+            None => "none".into(),
+        };
+        let report = format!(
+            "{}\nPC: {:04X}\nframe: {}\nfault: {fault}\nserial output:\n{}\n",
+            self.cpu,
+            self.cpu.registers().pc,
+            self.frame_number,
+            self.serial_output()
+        );
+        std::fs::write(dir.join(format!("{name}.txt")), report)
+    }
+
+    /// Number of T-cycles (4.194304 MHz) emulated so far.
+    pub fn ticks(&self) -> u64 {
+        self.emulator().ticks()
+    }
+
+    pub fn serial_output(&self) -> &str {
+        self.emulator().serial_output()
+    }
+
+    /// Snapshot of the whole machine, see `state::save_machine`.
+    pub fn save_state(&self) -> Vec<u8> {
+        state::save_machine(&self.cpu)
+    }
+
+    /// Restore a snapshot taken by `save_state` with the same cartridge inserted.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        state::load_machine(&mut self.cpu, data)
+    }
+
+    /// Power on with another cartridge, returns the one taken out so its
+    /// battery RAM can be saved. See `Emulator::swap_cartridge`.
+    pub fn swap_cartridge(&mut self, rom: Cartridge) -> Option<Cartridge> {
+        let old = state::swap_cartridge(&mut self.cpu, rom);
+        self.last_frame = Frame::new();
+        self.frame_number = 0;
+        old
+    }
+
+    /// Power cycle the machine, the cartridge stays inserted.
+    pub fn reset(&mut self) {
+        state::hard_reset(&mut self.cpu);
+        self.last_frame = Frame::new();
+        self.frame_number = 0;
+    }
+
+    /// Restart at 0x0100 keeping RAM, see `state::soft_reset`.
+    pub fn soft_reset(&mut self) {
+        state::soft_reset(&mut self.cpu);
+        self.frame_number = 0;
+    }
+
+    fn track_frame(&mut self) {
+        track_frame(&self.cpu, &mut self.last_frame, &mut self.frame_number);
+    }
+
+    /// Execute a single instruction, returns false once the CPU has stopped.
+    pub fn step(&mut self) -> bool {
+        let running = self.cpu.step();
+        self.track_frame();
+        running
+    }
+
+    /// Run until `frames` more frames have been rendered, returns false if the CPU stopped.
+    pub fn run_frames(&mut self, frames: u32) -> bool {
+        let target_frame = self.emulator().get_current_frame() + frames;
+
+        while self.emulator().get_current_frame() < target_frame {
+            let frame_end = self.emulator().next_frame_tick();
+            let running = self.cpu.run_until(frame_end);
+            self.track_frame();
+
+            if !running {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Like `step`, but a fault is returned as an error, see `CPU::set_report_faults`.
+    pub fn try_step(&mut self) -> Result<bool, EmulatorError> {
+        let result = self.cpu.try_step();
+        self.track_frame();
+        result
+    }
+
+    /// Like `run_frames`, but a fault is returned as an error.
+    pub fn try_run_frames(&mut self, frames: u32) -> Result<bool, EmulatorError> {
+        let target_frame = self.emulator().get_current_frame() + frames;
+
+        while self.emulator().get_current_frame() < target_frame {
+            if !self.try_step()? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Run until `condition` holds or `max_ticks` have elapsed, see `run_until`.
+    pub fn run_until(&mut self, condition: &Condition, max_ticks: u64) -> RunOutcome {
+        let (last_frame, frame_number) = (&mut self.last_frame, &mut self.frame_number);
+        run_watched(&mut self.cpu, condition, max_ticks, |cpu| {
+            track_frame(cpu, last_frame, frame_number)
+        })
+    }
+
+    /// Run until the CPU executes `LD B, B` or `max_ticks` have elapsed.
+    ///
+    /// `LD B, B` is a no-op that test ROMs (e.g. Mooneye) use as a software
+    /// breakpoint to signal that their results are ready in the registers.
+    pub fn run_until_breakpoint(&mut self, max_ticks: u64) -> bool {
+        const LD_B_B: u8 = 0x40;
+
+        while self.ticks() < max_ticks {
+            let pc = self.cpu.registers().pc;
+            let opcode = self.emulator_mut().peek(pc);
+
+            if !self.step() {
+                return false;
+            }
+
+            if opcode == LD_B_B {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Run until a test ROM reports its result code in cartridge RAM or
+    /// `max_ticks` have elapsed, checked once a frame.
+    ///
+    /// Blargg's newer ROMs, like dmg_sound, write 0xDE 0xB0 0x61 to 0xA001
+    /// and keep 0x80 in 0xA000 while running, then the result code there,
+    /// 0 on a pass. Returns None when the budget ran out or the CPU stopped.
+    pub fn run_until_ram_result(&mut self, max_ticks: u64) -> Option<u8> {
+        const RUNNING: u8 = 0x80;
+
+        while self.ticks() < max_ticks {
+            if !self.run_frames(1) {
+                return None;
+            }
+
+            let emu = self.emulator_mut();
+            let signature = [emu.peek(0xA001), emu.peek(0xA002), emu.peek(0xA003)];
+            let code = emu.peek(0xA000);
+
+            if signature == [0xDE, 0xB0, 0x61] && code != RUNNING {
+                return Some(code);
+            }
+        }
+
+        None
+    }
+
+    /// Text a test ROM wrote to cartridge RAM next to its result code, up to
+    /// the terminating zero, see `run_until_ram_result`.
+    pub fn ram_output(&mut self) -> String {
+        let emu = self.emulator_mut();
+        (0xA004..0xC000)
+            .map(|address| emu.peek(address))
+            .take_while(|byte| *byte != 0)
+            .map(char::from)
+            .collect()
+    }
+
+    /// Run until the serial output contains one of `patterns` or `max_ticks` have elapsed.
+    ///
+    /// Returns the first matching pattern, or None when the cycle budget ran out
+    /// or the CPU stopped.
+    pub fn run_until_serial<'a>(
+        &mut self,
+        patterns: &[&'a str],
+        max_ticks: u64,
+    ) -> Option<&'a str> {
+        let mut checked_len = 0;
+
+        while self.ticks() < max_ticks {
+            if !self.step() {
+                return None;
+            }
+
+            let emu = self.emulator();
+            let output = emu.serial_output();
+
+            if output.len() != checked_len {
+                checked_len = output.len();
+
+                if let Some(pattern) = patterns.iter().find(|p| output.contains(**p)) {
+                    return Some(pattern);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Copy the frame the PPU completed since `frame_number`, if it did.
+fn track_frame(cpu: &CPU<Emulator>, last_frame: &mut Frame, frame_number: &mut u32) {
+    let current = cpu.context().get_current_frame();
+
+    if current != *frame_number {
+        *frame_number = current;
+        last_frame.clone_from(cpu.context().ppu().frame());
+    }
+}
@@ -0,0 +1,91 @@
+use alloc::vec::Vec;
+
+use crate::bus::HardwareRegister;
+use crate::cpu::RegisterFile;
+
+// Longest loop body looked at, in instructions
+const MAX_LOOP_INSTRUCTIONS: u32 = 16;
+
+/// Finds loops that only wait for LY, STAT or IF to change.
+///
+/// An iteration that writes nothing, reads no other I/O register and comes
+/// back to its first instruction with the same registers repeats exactly
+/// until a register it polls changes or an interrupt is dispatched, so the
+/// machine can be run ahead without executing it.
+pub(crate) struct IdleDetector {
+    // Registers and ticks at the first instruction of the watched iteration
+    start: Option<(RegisterFile, u64)>,
+    instructions: u32,
+    wrote: bool,
+    other_io: bool,
+    polled: Vec<u16>,
+}
+
+impl IdleDetector {
+    pub fn new() -> Self {
+        IdleDetector {
+            start: None,
+            instructions: 0,
+            wrote: false,
+            other_io: false,
+            polled: Vec::new(),
+        }
+    }
+
+    pub fn record_read(&mut self, address: u16) {
+        if !matches!(address, 0xFF00..=0xFF7F) {
+            return;
+        }
+
+        let volatile = [
+            HardwareRegister::LY,
+            HardwareRegister::STAT,
+            HardwareRegister::IF,
+        ];
+
+        if !volatile.iter().any(|register| *register as u16 == address) {
+            self.other_io = true;
+        } else if !self.polled.contains(&address) {
+            self.polled.push(address);
+        }
+    }
+
+    pub fn record_write(&mut self) {
+        self.wrote = true;
+    }
+
+    /// Called before every instruction, the length of an iteration in dots
+    /// when the one ending here was idle.
+    pub fn check(&mut self, registers: &RegisterFile, ticks: u64) -> Option<u64> {
+        let iteration = match &self.start {
+            Some((start, start_ticks)) if start.pc == registers.pc => {
+                let idle =
+                    start == registers && ticks > *start_ticks && !self.wrote && !self.other_io;
+                idle.then(|| ticks - start_ticks)
+            }
+            Some(_) if self.instructions < MAX_LOOP_INSTRUCTIONS => {
+                self.instructions += 1;
+                return None;
+            }
+            // Too long for an idle loop, watch from here instead
+            _ => None,
+        };
+
+        // After a skip the next iteration is timed from where it ends
+        self.start = iteration.is_none().then_some((*registers, ticks));
+        self.instructions = 0;
+        self.wrote = false;
+        self.other_io = false;
+
+        if iteration.is_none() {
+            self.polled.clear();
+        }
+
+        iteration
+    }
+
+    /// Registers the idle iteration read, valid after `check` found one.
+    pub fn polled(&self) -> &[u16] {
+        &self.polled
+    }
+}
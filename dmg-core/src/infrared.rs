@@ -0,0 +1,152 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::clock::EmuClock;
+use crate::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+// RP bits
+const LED: u8 = 0x01;
+/// Reads 0 while light reaches the sensor
+const NO_LIGHT: u8 = 0x02;
+/// Both bits set to read the sensor
+const READ_ENABLE: u8 = 0xC0;
+
+/// What faces the infrared port: it sees the LED of the Game Boy and may
+/// shine light back at its sensor.
+pub trait InfraredDevice: Send {
+    /// Called when the Game Boy turns its LED on or off at `clock`.
+    fn set_led(&mut self, on: bool, clock: EmuClock);
+
+    /// Whether light reaches the sensor of the Game Boy.
+    fn light(&self) -> bool;
+}
+
+/// The port facing a mirror, the sensor sees the Game Boy's own LED.
+#[derive(Default)]
+pub struct InfraredLoopback {
+    led: bool,
+}
+
+impl InfraredDevice for InfraredLoopback {
+    fn set_led(&mut self, on: bool, _clock: EmuClock) {
+        self.led = on;
+    }
+
+    fn light(&self) -> bool {
+        self.led
+    }
+}
+
+/// One of two ports facing each other, for emulators in the same process.
+///
+/// Each end sees the LED of the other one as soon as it changes. Games time
+/// the pulses of their protocols, so the two emulators have to run in step,
+/// e.g. an instruction or a few at a time on one thread.
+pub struct InfraredLink {
+    leds: Arc<[AtomicBool; 2]>,
+    side: usize,
+}
+
+impl InfraredLink {
+    pub fn pair() -> (InfraredLink, InfraredLink) {
+        let leds = Arc::new([AtomicBool::new(false), AtomicBool::new(false)]);
+
+        (
+            InfraredLink {
+                leds: leds.clone(),
+                side: 0,
+            },
+            InfraredLink { leds, side: 1 },
+        )
+    }
+}
+
+impl InfraredDevice for InfraredLink {
+    fn set_led(&mut self, on: bool, _clock: EmuClock) {
+        self.leds[self.side].store(on, Ordering::Relaxed);
+    }
+
+    fn light(&self) -> bool {
+        self.leds[1 - self.side].load(Ordering::Relaxed)
+    }
+}
+
+/// Infrared port of the Game Boy Color (RP).
+///
+/// Only mapped while an `InfraredDevice` is attached, the monochrome models
+/// have no port. Bit 0 turns the LED on, bit 1 reads 0 while light reaches
+/// the sensor and bits 6 and 7 both have to be set for it to be read.
+pub struct Infrared {
+    rp: u8,
+    device: Option<Box<dyn InfraredDevice>>,
+}
+
+impl Infrared {
+    pub fn new() -> Self {
+        Infrared {
+            rp: 0,
+            device: None,
+        }
+    }
+
+    /// Attach what faces the port, None takes it away.
+    pub fn set_device(&mut self, device: Option<Box<dyn InfraredDevice>>) {
+        self.device = device;
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.device.is_some()
+    }
+
+    pub fn read(&self) -> u8 {
+        let light = self.rp & READ_ENABLE == READ_ENABLE
+            && self.device.as_ref().is_some_and(|device| device.light());
+
+        // Unused bits read as 1
+        self.rp | 0x3C | if light { 0 } else { NO_LIGHT }
+    }
+
+    /// Write RP, `clock` is the time for the attached device.
+    pub fn write(&mut self, value: u8, clock: EmuClock) {
+        let led = self.rp & LED;
+        self.rp = value & (LED | READ_ENABLE);
+
+        if self.rp & LED != led {
+            self.update_device(clock);
+        }
+    }
+
+    /// Tell the device whether the LED is on, after the register was
+    /// changed without it.
+    pub fn update_device(&mut self, clock: EmuClock) {
+        if let Some(device) = &mut self.device {
+            device.set_led(self.rp & LED != 0, clock);
+        }
+    }
+}
+
+impl Default for Infrared {
+    fn default() -> Self {
+        Infrared::new()
+    }
+}
+
+impl Resettable for Infrared {
+    fn reset(&mut self) {
+        // The device stays in front of the port, with the LED off
+        self.rp = 0;
+        self.update_device(EmuClock::from_ticks(0));
+    }
+}
+
+impl Saveable for Infrared {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rp);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rp = state.read_u8()? & (LED | READ_ENABLE);
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use bitflags::bitflags;
+
+use crate::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+bitflags!(
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct InterruptFlag: u8 {
+        const VBLANK = 0b1;
+        const LCD = 0b10;
+        const TIMER = 0b100;
+        const SERIAL = 0b1000;
+        const JOYPAD = 0b1_0000;
+    }
+);
+
+// Names of the sources in priority order, as used by debugger commands
+const SOURCE_NAMES: [&str; 5] = ["vblank", "stat", "timer", "serial", "joypad"];
+
+impl InterruptFlag {
+    pub fn highest_priority(&self) -> InterruptFlag {
+        InterruptFlag::from_bits_truncate(isolate_rightmost_one(self.bits()))
+    }
+
+    /// Source by name: `vblank`, `stat` (or `lcd`), `timer`, `serial` or `joypad`.
+    pub fn from_source_name(name: &str) -> Option<InterruptFlag> {
+        let name = name.to_ascii_lowercase();
+        let index = match name.as_str() {
+            "lcd" => 1,
+            name => SOURCE_NAMES.iter().position(|source| *source == name)?,
+        };
+        Some(InterruptFlag::from_bits_truncate(1 << index))
+    }
+
+    /// Name of the highest priority source.
+    pub fn source_name(&self) -> &'static str {
+        SOURCE_NAMES
+            .get(source_index(*self))
+            .copied()
+            .unwrap_or("none")
+    }
+}
+
+/// Address of a handler named like `vblank-handler` or `timer-handler`, so
+/// breakpoints can be set without looking up the vectors.
+pub fn handler_address(name: &str) -> Option<u16> {
+    let source = InterruptFlag::from_source_name(name.strip_suffix("-handler")?)?;
+    Some(get_hadler_address(source))
+}
+
+/// Counters of one interrupt source.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct SourceStats {
+    /// Requests, including those made while one was already pending
+    pub requested: u64,
+    /// Dispatches to the handler
+    pub serviced: u64,
+    /// Longest time in T-cycles from a request to its dispatch
+    pub max_latency: u64,
+}
+
+/// Interrupt requests and dispatches since power on, per source.
+///
+/// More requests than dispatches mean some were merged while pending or never
+/// enabled, e.g. a game that misses VBlanks. Not part of save states, like the
+/// frame statistics.
+#[derive(Clone, Default)]
+pub struct InterruptStats {
+    sources: [SourceStats; 5],
+    // Tick the pending request of each source was made at
+    pending_since: [Option<u64>; 5],
+    // Sources requested since the last `stamp`
+    unstamped: u8,
+}
+
+impl InterruptStats {
+    pub fn new() -> Self {
+        InterruptStats::default()
+    }
+
+    fn record_requested(&mut self, f: InterruptFlag, pending: InterruptFlag) {
+        for source in f.iter() {
+            self.sources[source_index(source)].requested += 1;
+        }
+
+        // Latency is counted from the first of merged requests
+        self.unstamped |= (f - pending).bits();
+    }
+
+    /// Give the requests made since the last call the current time.
+    pub(crate) fn stamp(&mut self, now: u64) {
+        if self.unstamped == 0 {
+            return;
+        }
+
+        for source in InterruptFlag::from_bits_truncate(self.unstamped).iter() {
+            self.pending_since[source_index(source)] = Some(now);
+        }
+        self.unstamped = 0;
+    }
+
+    pub(crate) fn record_serviced(&mut self, f: InterruptFlag, now: u64) {
+        let index = source_index(f);
+        let source = &mut self.sources[index];
+        source.serviced += 1;
+
+        if let Some(since) = self.pending_since[index].take() {
+            source.max_latency = source.max_latency.max(now.saturating_sub(since));
+        }
+    }
+
+    /// Forget when the pending requests were made, after the clock was moved
+    /// by loading a state.
+    pub(crate) fn forget_pending(&mut self) {
+        self.pending_since = [None; 5];
+        self.unstamped = 0;
+    }
+
+    /// Forget requests the game cleared by writing IF without servicing them.
+    pub(crate) fn retain_pending(&mut self, pending: InterruptFlag) {
+        for source in InterruptFlag::all().difference(pending).iter() {
+            self.pending_since[source_index(source)] = None;
+        }
+        self.unstamped &= pending.bits();
+    }
+
+    pub fn source(&self, f: InterruptFlag) -> SourceStats {
+        self.sources[source_index(f)]
+    }
+
+    /// Every source with its counters, in priority order.
+    pub fn iter(&self) -> impl Iterator<Item = (InterruptFlag, SourceStats)> + '_ {
+        InterruptFlag::all().iter().map(|f| (f, self.source(f)))
+    }
+}
+
+fn source_index(f: InterruptFlag) -> usize {
+    f.highest_priority().bits().trailing_zeros() as usize
+}
+
+pub trait InterruptRequest {
+    fn request_interrupt(&mut self, f: InterruptFlag);
+}
+
+pub struct InterruptLine {
+    // Equivalent to hardware registers IE, IF
+    pub interrupt_enable: InterruptFlag,
+    pub interrupt_flag: InterruptFlag,
+    pub stats: InterruptStats,
+}
+
+impl InterruptLine {
+    pub fn new() -> Self {
+        InterruptLine {
+            interrupt_enable: InterruptFlag::empty(),
+            interrupt_flag: InterruptFlag::empty(),
+            stats: InterruptStats::new(),
+        }
+    }
+}
+
+impl Default for InterruptLine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InterruptRequest for InterruptLine {
+    fn request_interrupt(&mut self, f: InterruptFlag) {
+        self.stats.record_requested(f, self.interrupt_flag);
+        self.interrupt_flag |= f;
+    }
+}
+
+impl Resettable for InterruptLine {
+    fn reset(&mut self) {
+        *self = InterruptLine::new();
+    }
+}
+
+impl Saveable for InterruptLine {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.interrupt_enable.bits());
+        state.write_u8(self.interrupt_flag.bits());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.interrupt_enable = InterruptFlag::from_bits_truncate(state.read_u8()?);
+        self.interrupt_flag = InterruptFlag::from_bits_truncate(state.read_u8()?);
+        self.stats.forget_pending();
+        Ok(())
+    }
+}
+
+/// Vector of the highest priority interrupt in `f`. With none, like when
+/// the push of the dispatch overwrote IE, the CPU jumps to $0000.
+pub fn get_hadler_address(f: InterruptFlag) -> u16 {
+    let high_f = f.highest_priority();
+
+    if high_f.contains(InterruptFlag::VBLANK) {
+        return 0x40;
+    } else if high_f.contains(InterruptFlag::LCD) {
+        return 0x48;
+    } else if high_f.contains(InterruptFlag::TIMER) {
+        return 0x50;
+    } else if high_f.contains(InterruptFlag::SERIAL) {
+        return 0x58;
+    } else if high_f.contains(InterruptFlag::JOYPAD) {
+        return 0x60;
+    }
+
+    0x0000
+}
+
+fn isolate_rightmost_one(f: u8) -> u8 {
+    // Unsigned negation, -f
+    let neg_f = (!f).wrapping_add(1);
+    // The bitwise AND operation isolates the rightmost 1 bit in x.
+    // The two's complement negation (-x) flips all bits after the rightmost 1 bit in x and leaves the rest unchanged.
+    f & neg_f
+}
@@ -0,0 +1,139 @@
+use crate::interrupts::{InterruptFlag, InterruptRequest};
+use crate::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+use bitflags::bitflags;
+
+bitflags!(
+    /// Buttons held on the joypad, the d-pad in the low nibble and the
+    /// others in the high one as the two P1 groups report them.
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct Buttons : u8 {
+        const RIGHT = 0b0000_0001;
+        const LEFT = 0b0000_0010;
+        const UP = 0b0000_0100;
+        const DOWN = 0b0000_1000;
+        const A = 0b0001_0000;
+        const B = 0b0010_0000;
+        const SELECT = 0b0100_0000;
+        const START = 0b1000_0000;
+    }
+);
+
+impl Buttons {
+    pub const DPAD: Buttons = Buttons::RIGHT
+        .union(Buttons::LEFT)
+        .union(Buttons::UP)
+        .union(Buttons::DOWN);
+
+    /// Parse a button name, e.g. `A`, `start` or `Up`.
+    pub fn from_button_name(name: &str) -> Option<Buttons> {
+        let button = match name.to_ascii_lowercase().as_str() {
+            "right" => Buttons::RIGHT,
+            "left" => Buttons::LEFT,
+            "up" => Buttons::UP,
+            "down" => Buttons::DOWN,
+            "a" => Buttons::A,
+            "b" => Buttons::B,
+            "select" => Buttons::SELECT,
+            "start" => Buttons::START,
+            _ => return None,
+        };
+
+        Some(button)
+    }
+}
+
+/// Joypad (P1/JOYP)
+///
+/// Bits 4 and 5 select the d-pad and the button group (active low),
+/// bits 0-3 report the selected keys, 0 means pressed.
+///
+/// Pressed keys are kept as `Buttons`, whose nibbles match the two groups.
+pub struct Joypad {
+    select: u8,
+    pressed: Buttons,
+    // A selected line went low since the last `take_line_fall`
+    line_fell: bool,
+}
+
+impl Joypad {
+    const SELECT_DPAD: u8 = 0b0001_0000;
+    const SELECT_BUTTONS: u8 = 0b0010_0000;
+
+    pub fn new() -> Self {
+        Joypad {
+            select: Self::SELECT_DPAD | Self::SELECT_BUTTONS,
+            pressed: Buttons::empty(),
+            line_fell: false,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        // Unused bits read as 1
+        0xC0 | self.select | (!self.selected_lines() & 0x0F)
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.select = value & (Self::SELECT_DPAD | Self::SELECT_BUTTONS);
+    }
+
+    /// Update the pressed keys, a selected line going low requests the joypad interrupt.
+    pub fn set_pressed<I: InterruptRequest>(&mut self, pressed: Buttons, ctx: &mut I) {
+        let prev_lines = self.selected_lines();
+        self.pressed = pressed;
+
+        if (self.selected_lines() & !prev_lines) != 0 {
+            self.line_fell = true;
+            ctx.request_interrupt(InterruptFlag::JOYPAD);
+        }
+    }
+
+    /// Whether a selected line went low since the last call, what ends STOP.
+    pub fn take_line_fall(&mut self) -> bool {
+        core::mem::take(&mut self.line_fell)
+    }
+
+    pub fn pressed(&self) -> Buttons {
+        self.pressed
+    }
+
+    fn selected_lines(&self) -> u8 {
+        let mut lines = 0;
+
+        if (self.select & Self::SELECT_DPAD) == 0 {
+            lines |= self.pressed.bits() & 0x0F;
+        }
+
+        if (self.select & Self::SELECT_BUTTONS) == 0 {
+            lines |= self.pressed.bits() >> 4;
+        }
+
+        lines
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad::new()
+    }
+}
+
+impl Resettable for Joypad {
+    fn reset(&mut self) {
+        // Held keys belong to the frontend and survive a reset
+        self.select = Self::SELECT_DPAD | Self::SELECT_BUTTONS;
+        self.line_fell = false;
+    }
+}
+
+impl Saveable for Joypad {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.select);
+        state.write_bool(self.line_fell);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.select = state.read_u8()? & (Self::SELECT_DPAD | Self::SELECT_BUTTONS);
+        self.line_fell = state.read_bool()?;
+        Ok(())
+    }
+}
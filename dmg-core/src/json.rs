@@ -0,0 +1,228 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// A parsed JSON value. Numbers are kept as written, the files read here
+/// store their numbers as hex strings.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order of the file
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(crate) fn parse(text: &str) -> Option<Json> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        (parser.position == text.len()).then_some(value)
+    }
+
+    /// Member of an object.
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn members(&self) -> &[(String, Json)] {
+        match self {
+            Json::Object(members) => members,
+            _ => &[],
+        }
+    }
+}
+
+/// `text` as a quoted JSON string.
+pub(crate) fn string(text: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+
+        match *self.text.get(self.position)? {
+            b'{' => self.object(),
+            b'[' => self.array(),
+            b'"' => self.string().map(Json::String),
+            b't' => self.keyword("true", Json::Bool(true)),
+            b'f' => self.keyword("false", Json::Bool(false)),
+            b'n' => self.keyword("null", Json::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.position += 1;
+        let mut members = Vec::new();
+
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Some(Json::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(b':') {
+                return None;
+            }
+            members.push((key, self.value()?));
+
+            self.skip_whitespace();
+            if self.eat(b'}') {
+                return Some(Json::Object(members));
+            }
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.position += 1;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.eat(b']') {
+            return Some(Json::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+
+            self.skip_whitespace();
+            if self.eat(b']') {
+                return Some(Json::Array(values));
+            }
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if !self.eat(b'"') {
+            return None;
+        }
+
+        let mut bytes = Vec::new();
+        loop {
+            let byte = *self.text.get(self.position)?;
+            self.position += 1;
+
+            match byte {
+                b'"' => return String::from_utf8(bytes).ok(),
+                b'\\' => {
+                    let escaped = *self.text.get(self.position)?;
+                    self.position += 1;
+                    let c = match escaped {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => {
+                            let digits = self.text.get(self.position..self.position + 4)?;
+                            self.position += 4;
+                            let code = u32::from_str_radix(core::str::from_utf8(digits).ok()?, 16);
+                            char::from_u32(code.ok()?)?
+                        }
+                        c => c as char,
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.position;
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, b'-' | b'+' | b'.' | b'e' | b'E'))
+        {
+            self.position += 1;
+        }
+
+        let number = core::str::from_utf8(&self.text[start..self.position]).ok()?;
+        (!number.is_empty()).then(|| Json::Number(String::from(number)))
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Option<Json> {
+        if !self.text[self.position..].starts_with(word.as_bytes()) {
+            return None;
+        }
+        self.position += word.len();
+        Some(value)
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.text.get(self.position) == Some(&byte);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+    }
+}
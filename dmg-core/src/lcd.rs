@@ -0,0 +1,352 @@
+use crate::ppu::{LINES_PER_FRAME, YRES};
+
+use super::bus::HardwareRegister;
+use super::interrupts::{InterruptFlag, InterruptRequest};
+use super::power::Quirks;
+use super::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+use bitflags::bitflags;
+
+pub static DEFAULT_COLORS: [u32; 4] = [0xFFFFFFFF, 0xFFAAAAAA, 0xFF555555, 0xFF000000];
+
+bitflags!(
+    #[derive(Debug)]
+    pub struct LcdControl : u8 {
+        const LCD_PPU_ENABLE = 0b1000_0000;
+        const WINDOW_TILE_MAP_AREA = 0b0100_0000;
+        const WINDOW_ENABLE = 0b0010_0000;
+        const BG_WINDOW_TILE_DATA_AREA = 0b0001_0000;
+        const BG_TILE_MAP_AREA = 0b0000_1000;
+        const OBJ_SIZE = 0b0000_0100;
+        const OBJ_ENABLE = 0b0000_0010;
+        const BG_WINDOW_ENABLE = 0b0000_0001;
+    }
+);
+
+bitflags!(
+/// LYC int select (Read/Write): If set, selects the LYC == LY condition for the STAT interrupt.
+///
+/// Mode (0=HBLANK, 1=VBLANK, 2=OAM) int select (Read/Write):
+/// If set, selects the Mode 2 (1 or 0) condition for the STAT interrupt.
+///
+/// LYC == LY (Read-only): Set when LY contains the same value as LYC; it is constantly updated.
+///
+/// PPU mode (Read-only): Indicates the PPU’s current status (0=HBLANK, 1=VBLANK, 2=OAM, 3=XFER).
+/// Reports 0 instead when the PPU is disabled.
+    pub struct LcdStatus: u8 {
+        const LYC_INT_SELECT = 0b0100_0000;
+        const OAM_INT_SELECT = 0b0010_0000;
+        const VBLANK_INT_SELECT = 0b0001_0000;
+        const HBLANK_INT_SELECT = 0b0000_1000;
+        const LYC_EQUAL_LY = 0b0000_0100;
+        const PPU_MODE = 0b0000_0011;
+
+        const STAT_WRITABLE = Self::LYC_INT_SELECT.bits()
+            | Self::OAM_INT_SELECT.bits()
+            | Self::VBLANK_INT_SELECT.bits()
+            | Self::HBLANK_INT_SELECT.bits();
+    }
+);
+
+pub struct LCD {
+    pub lcdc: LcdControl,
+    pub lcds: LcdStatus,
+    pub scroll_x: u8,
+    pub scroll_y: u8,
+    pub ly: u8,
+    pub lyc: u8,
+    bg_palette: u8,
+    obj_palette: [u8; 2],
+    pub win_x: u8,
+    pub win_y: u8,
+    // Set by the emulated model, kept on reset
+    quirks: Quirks,
+
+    // Shade of each color index, decoded from the palette registers
+    pub bg_shades: [u8; 4],
+    pub sp0_shades: [u8; 4],
+    pub sp1_shades: [u8; 4],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum LcdMode {
+    HBLANK,
+    VBLANK,
+    OAM,
+    XFER,
+}
+
+impl From<u8> for LcdMode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => LcdMode::HBLANK,
+            1 => LcdMode::VBLANK,
+            2 => LcdMode::OAM,
+            3 => LcdMode::XFER,
+            _ => panic!("Invalid LcdMode value"),
+        }
+    }
+}
+
+/// DMG palette register, maps the color indices of a layer to one of the
+/// four shades.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum PaletteRegister {
+    /// BGP, the background and window
+    Background,
+    /// OBP0
+    Object0,
+    /// OBP1
+    Object1,
+}
+
+impl PaletteRegister {
+    pub const ALL: [PaletteRegister; 3] = [
+        PaletteRegister::Background,
+        PaletteRegister::Object0,
+        PaletteRegister::Object1,
+    ];
+
+    pub fn hardware_register(self) -> HardwareRegister {
+        match self {
+            PaletteRegister::Background => HardwareRegister::BGP,
+            PaletteRegister::Object0 => HardwareRegister::OBP0,
+            PaletteRegister::Object1 => HardwareRegister::OBP1,
+        }
+    }
+}
+
+impl Default for LCD {
+    fn default() -> Self {
+        LCD::new()
+    }
+}
+
+impl LCD {
+    pub fn new() -> Self {
+        LCD {
+            lcdc: LcdControl::empty(),
+            lcds: LcdStatus::from_bits_truncate(0),
+            scroll_x: 0,
+            scroll_y: 0,
+            ly: 0,
+            lyc: 0,
+            bg_palette: 0,
+            obj_palette: [0xFF, 0xFF],
+            win_x: 0,
+            win_y: 0,
+            quirks: Quirks::empty(),
+            bg_shades: [0; 4],
+            sp0_shades: [0, 1, 2, 3],
+            sp1_shades: [0, 1, 2, 3],
+        }
+    }
+
+    pub fn get_mode(&self) -> LcdMode {
+        let mode = self.lcds.bits() & LcdStatus::PPU_MODE.bits();
+        LcdMode::from(mode)
+    }
+
+    pub fn set_mode(&mut self, mode: LcdMode) {
+        // reset PPU_MODE and set its new value
+        self.lcds.remove(LcdStatus::PPU_MODE);
+        self.lcds = LcdStatus::from_bits_truncate(self.lcds.bits() | mode as u8);
+        // TODO: Remove this check later
+        assert!(self.get_mode() == mode);
+    }
+
+    pub fn get_bg_map_area(&self) -> u16 {
+        if self.lcdc.contains(LcdControl::BG_TILE_MAP_AREA) {
+            0x9C00
+        } else {
+            0x9800
+        }
+    }
+
+    pub fn get_win_map_area(&self) -> u16 {
+        if self.lcdc.contains(LcdControl::WINDOW_TILE_MAP_AREA) {
+            0x9C00
+        } else {
+            0x9800
+        }
+    }
+
+    pub fn get_bgw_data_area(&self) -> u16 {
+        if self.lcdc.contains(LcdControl::BG_WINDOW_TILE_DATA_AREA) {
+            0x8000
+        } else {
+            0x8800
+        }
+    }
+
+    pub fn get_sprite_height(&self) -> u8 {
+        if self.lcdc.contains(LcdControl::OBJ_SIZE) {
+            16
+        } else {
+            8
+        }
+    }
+
+    pub fn read(&self, address: HardwareRegister) -> u8 {
+        match address {
+            HardwareRegister::LCDC => self.lcdc.bits(),
+            HardwareRegister::STAT => self.read_stat(),
+            HardwareRegister::SCY => self.scroll_y,
+            HardwareRegister::SCX => self.scroll_x,
+            HardwareRegister::LY => self.ly,
+            HardwareRegister::LYC => self.lyc,
+            HardwareRegister::BGP => self.bg_palette,
+            HardwareRegister::OBP0 => self.obj_palette[0],
+            HardwareRegister::OBP1 => self.obj_palette[1],
+            HardwareRegister::WY => self.win_y,
+            HardwareRegister::WX => self.win_x,
+            _ => panic!("Invalid LCD register 0x{:04X}.", address as u8),
+        }
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn write<I: InterruptRequest>(
+        &mut self,
+        address: HardwareRegister,
+        value: u8,
+        ctx: &mut I,
+    ) {
+        match address {
+            HardwareRegister::LCDC => self.lcdc = LcdControl::from_bits_truncate(value),
+            HardwareRegister::STAT => {
+                // Mode and LYC == LY bits are read-only
+                let writable = LcdStatus::STAT_WRITABLE.bits();
+                self.lcds = LcdStatus::from_bits_truncate(
+                    (self.lcds.bits() & !writable) | (value & writable),
+                );
+
+                if self.quirks.contains(Quirks::STAT_WRITE_INTERRUPT) && self.stat_write_bug() {
+                    ctx.request_interrupt(InterruptFlag::LCD);
+                }
+            }
+            HardwareRegister::SCY => self.scroll_y = value,
+            HardwareRegister::SCX => self.scroll_x = value,
+            HardwareRegister::LY => {
+                // LY is read-only, writes are ignored
+            }
+            HardwareRegister::LYC => self.lyc = value,
+            HardwareRegister::BGP => {
+                self.bg_palette = value;
+                self.update_palette(PaletteRegister::Background, value);
+            }
+            HardwareRegister::OBP0 => {
+                self.obj_palette[0] = value;
+                self.update_palette(PaletteRegister::Object0, value & 0b11111100);
+            }
+            HardwareRegister::OBP1 => {
+                self.obj_palette[1] = value;
+                self.update_palette(PaletteRegister::Object1, value & 0b11111100);
+            }
+            HardwareRegister::WY => self.win_y = value,
+            HardwareRegister::WX => self.win_x = value,
+            _ => panic!("Invalid LCD register 0x{:04X}.", address as u8),
+        }
+    }
+
+    /// On a DMG STAT reads as 0xFF for a cycle while written, the HBlank,
+    /// VBlank and LYC == LY sources fire if their condition holds.
+    fn stat_write_bug(&self) -> bool {
+        if !self.lcdc.contains(LcdControl::LCD_PPU_ENABLE) {
+            return false;
+        }
+
+        matches!(self.get_mode(), LcdMode::HBLANK | LcdMode::VBLANK) || self.ly == self.lyc
+    }
+
+    /// STAT as seen by the CPU, the LYC == LY and mode bits are computed at read time.
+    fn read_stat(&self) -> u8 {
+        // Bit 7 is unused and always reads as 1
+        let mut stat = 0x80 | (self.lcds.bits() & LcdStatus::STAT_WRITABLE.bits());
+
+        if self.ly == self.lyc {
+            stat |= LcdStatus::LYC_EQUAL_LY.bits();
+        }
+
+        if self.lcdc.contains(LcdControl::LCD_PPU_ENABLE) {
+            stat |= self.get_mode() as u8;
+        }
+
+        stat
+    }
+
+    pub fn is_window_visible(&self) -> bool {
+        self.lcdc.contains(LcdControl::WINDOW_ENABLE)
+            && self.win_x <= 166
+            && self.win_y < (YRES as u8)
+    }
+
+    fn update_palette(&mut self, palette: PaletteRegister, color_indices: u8) {
+        let shades = match palette {
+            PaletteRegister::Background => &mut self.bg_shades,
+            PaletteRegister::Object0 => &mut self.sp0_shades,
+            PaletteRegister::Object1 => &mut self.sp1_shades,
+        };
+
+        shades[0] = color_indices & 0b11;
+        shades[1] = (color_indices >> 2) & 0b11;
+        shades[2] = (color_indices >> 4) & 0b11;
+        shades[3] = (color_indices >> 6) & 0b11;
+    }
+}
+
+impl Resettable for LCD {
+    fn reset(&mut self) {
+        let quirks = self.quirks;
+        *self = LCD::new();
+        self.quirks = quirks;
+    }
+}
+
+impl Saveable for LCD {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.lcdc.bits());
+        state.write_u8(self.lcds.bits());
+        state.write_u8(self.scroll_x);
+        state.write_u8(self.scroll_y);
+        state.write_u8(self.ly);
+        state.write_u8(self.lyc);
+        // Where the DMA register was before it moved to `DMA`, kept so that
+        // the fields after it stay where older builds read them
+        state.write_u8(0);
+        state.write_u8(self.bg_palette);
+        state.write_bytes(&self.obj_palette);
+        state.write_u8(self.win_x);
+        state.write_u8(self.win_y);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.lcdc = LcdControl::from_bits_truncate(state.read_u8()?);
+        self.lcds = LcdStatus::from_bits_truncate(state.read_u8()?);
+        self.scroll_x = state.read_u8()?;
+        self.scroll_y = state.read_u8()?;
+        self.ly = state.read_u8()?;
+        if self.ly as u32 >= LINES_PER_FRAME {
+            return Err(StateError::InvalidValue("LY"));
+        }
+        self.lyc = state.read_u8()?;
+        state.read_u8()?;
+        self.bg_palette = state.read_u8()?;
+        state.read_into(&mut self.obj_palette)?;
+        self.win_x = state.read_u8()?;
+        self.win_y = state.read_u8()?;
+
+        // Shades are derived from the palette registers
+        self.update_palette(PaletteRegister::Background, self.bg_palette);
+        self.update_palette(PaletteRegister::Object0, self.obj_palette[0] & 0b11111100);
+        self.update_palette(PaletteRegister::Object1, self.obj_palette[1] & 0b11111100);
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+/// Print a diagnostic line with the `std` feature, compiled out otherwise.
+macro_rules! log {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "std")]
+        std::println!($($arg)*);
+        #[cfg(not(feature = "std"))]
+        let _ = core::format_args!($($arg)*);
+    }};
+}
+
+pub mod apu;
+pub mod assertions;
+pub mod audio;
+pub mod bess;
+pub mod bus;
+pub mod camera;
+pub mod cart;
+pub mod cheats;
+pub mod clock;
+pub mod compress;
+pub mod counter;
+pub mod cpu;
+pub mod desync;
+pub mod dma;
+pub mod emu;
+pub mod frame;
+#[cfg(feature = "std")]
+pub mod frame_export;
+pub mod hash;
+pub mod headless;
+mod idle;
+pub mod infrared;
+pub mod interrupts;
+pub mod joypad;
+mod json;
+pub mod lcd;
+pub mod lockstep;
+pub mod mapper_log;
+pub mod mbc;
+pub mod monkey;
+pub mod peripherals;
+pub mod placeholder;
+pub mod png;
+pub mod polling;
+pub mod power;
+pub mod ppu;
+pub mod profiler;
+pub mod raster;
+pub mod rewind;
+pub mod romdb;
+pub mod scheduler;
+pub mod selftest;
+pub mod serial;
+pub mod snapshot;
+pub mod state;
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod storage;
+pub mod symbols;
+pub mod timer;
+pub mod vram;
+pub mod warnings;
+pub mod watch;
+pub mod watchdog;
+
+pub use emu::*;
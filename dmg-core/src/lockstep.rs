@@ -0,0 +1,265 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::cpu::{CPU, CpuContext, Register};
+use crate::emu::Emulator;
+use crate::watch;
+
+/// Trace lines as Gameboy Doctor reads them and SameBoy and other emulators
+/// can write them, the state before each instruction.
+pub const DOCTOR_FORMAT: &str =
+    "A:{a} F:{f} B:{b} C:{c} D:{d} E:{e} H:{h} L:{l} SP:{sp} PC:{pc} PCMEM:{_}";
+
+/// Value a trace column holds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Field {
+    Register(Register),
+    /// T-cycles in decimal, only the time since the first line is compared
+    Cycles,
+}
+
+impl Field {
+    fn value(self, cpu: &CPU<Emulator>) -> u64 {
+        match self {
+            Field::Register(register) if register.is_16bit() => {
+                cpu.registers().read16(register) as u64
+            }
+            Field::Register(register) => cpu.registers().read8(register) as u64,
+            Field::Cycles => cpu.context().ticks(),
+        }
+    }
+
+    fn show(self, value: u64) -> String {
+        match self {
+            Field::Register(register) if register.is_16bit() => format!("{value:04X}"),
+            Field::Register(_) => format!("{value:02X}"),
+            Field::Cycles => value.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+    /// A column that isn't compared, up to the next space
+    Skip,
+}
+
+/// Layout of the lines of a reference trace, text with placeholders for
+/// the columns: `{a}` to `{l}`, `{af}`, `{bc}`, `{de}`, `{hl}`, `{sp}` and
+/// `{pc}` in hex, `{cycles}` in decimal and `{_}` for a column to ignore.
+/// Spaces match any run of spaces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceFormat {
+    parts: Vec<Part>,
+}
+
+impl TraceFormat {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+
+        while !rest.is_empty() {
+            if let Some(placeholder) = rest.strip_prefix('{') {
+                let end = placeholder
+                    .find('}')
+                    .ok_or_else(|| format!("Missing }} in trace format {template}"))?;
+                let name = &placeholder[..end];
+                parts.push(match name {
+                    "_" => Part::Skip,
+                    "cycles" => Part::Field(Field::Cycles),
+                    _ => Part::Field(Field::Register(
+                        watch::register(&name.to_ascii_uppercase())
+                            .ok_or_else(|| format!("Unknown trace column {{{name}}}"))?,
+                    )),
+                });
+                rest = &placeholder[end + 1..];
+            } else {
+                let end = rest.find('{').unwrap_or(rest.len());
+                parts.push(Part::Text(rest[..end].to_string()));
+                rest = &rest[end..];
+            }
+        }
+
+        Ok(TraceFormat { parts })
+    }
+
+    /// The columns of `line`, None when it doesn't have the layout.
+    fn read(&self, line: &str) -> Option<Vec<(Field, u64)>> {
+        let mut values = Vec::new();
+        let mut rest = line.trim_end();
+
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => {
+                    for word in text.split(' ') {
+                        if word.is_empty() {
+                            rest = rest.trim_start();
+                        } else {
+                            rest = rest.strip_prefix(word)?;
+                        }
+                    }
+                }
+                Part::Field(field) => {
+                    let radix = if *field == Field::Cycles { 10 } else { 16 };
+                    let end = rest
+                        .find(|c: char| !c.is_digit(radix))
+                        .unwrap_or(rest.len());
+                    values.push((*field, u64::from_str_radix(&rest[..end], radix).ok()?));
+                    rest = &rest[end..];
+                }
+                Part::Skip => {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    rest = &rest[end..];
+                }
+            }
+        }
+
+        rest.is_empty().then_some(values)
+    }
+
+    /// The state of `cpu` as a line of the trace, ignored columns as `..`.
+    fn show(&self, cpu: &CPU<Emulator>, cycles_base: u64) -> String {
+        let mut line = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => line.push_str(text),
+                Part::Field(Field::Cycles) => {
+                    line.push_str(&Field::Cycles.show(cpu.context().ticks() - cycles_base))
+                }
+                Part::Field(field) => line.push_str(&field.show(field.value(cpu))),
+                Part::Skip => line.push_str(".."),
+            }
+        }
+
+        line
+    }
+}
+
+/// Where the emulator stopped following the reference trace.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    /// A line without the layout of the trace format
+    Unreadable { line: usize, text: String },
+    /// The registers or cycle count differ from the reference
+    Diverged {
+        line: usize,
+        /// Names of the differing columns
+        columns: Vec<String>,
+        /// Line before, the instruction that went wrong
+        previous: Option<String>,
+        reference: String,
+        emulator: String,
+    },
+    /// The CPU stopped on a fault or illegal opcode before the trace ended
+    Stopped { line: usize },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Unreadable { line, text } => {
+                write!(f, "line {line} doesn't have the trace format: {text}")
+            }
+            Mismatch::Diverged {
+                line,
+                columns,
+                previous,
+                reference,
+                emulator,
+            } => {
+                writeln!(f, "Diverged at line {line} in {}", columns.join(", "))?;
+                if let Some(previous) = previous {
+                    writeln!(f, "after:     {previous}")?;
+                }
+                writeln!(f, "reference: {reference}")?;
+                write!(f, "emulator:  {emulator}")
+            }
+            Mismatch::Stopped { line } => write!(f, "the CPU stopped before line {line}"),
+        }
+    }
+}
+
+/// Run `cpu` one instruction per line of a reference trace and stop at the
+/// first line whose columns don't match, returns the number of lines that
+/// matched. Lines hold the state before their instruction and empty ones
+/// are skipped. Instructions are counted the way traces count them, the
+/// time spent in HALT belongs to the HALT.
+pub fn compare<I, S>(
+    cpu: &mut CPU<Emulator>,
+    format: &TraceFormat,
+    lines: I,
+) -> Result<usize, Mismatch>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut matched = 0;
+    let mut previous: Option<String> = None;
+    // Cycles of the first line and ticks of the emulator then
+    let mut base: Option<(u64, u64)> = None;
+
+    for (index, text) in lines.into_iter().enumerate() {
+        let text = text.as_ref();
+        let line = index + 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let values = format.read(text).ok_or_else(|| Mismatch::Unreadable {
+            line,
+            text: text.to_string(),
+        })?;
+
+        if previous.is_some() {
+            if !cpu.step() {
+                return Err(Mismatch::Stopped { line });
+            }
+            while cpu.is_halted() {
+                if !cpu.step() {
+                    return Err(Mismatch::Stopped { line });
+                }
+            }
+        }
+
+        let ticks = cpu.context().ticks();
+        let mut columns = Vec::new();
+
+        for (field, expected) in values {
+            let differs = match field {
+                Field::Cycles => {
+                    let (cycles, start) = *base.get_or_insert((expected, ticks));
+                    expected.wrapping_sub(cycles) != ticks - start
+                }
+                field => field.value(cpu) != expected,
+            };
+
+            if differs {
+                columns.push(match field {
+                    Field::Register(register) => format!("{register:?}"),
+                    Field::Cycles => "cycles".to_string(),
+                });
+            }
+        }
+
+        if !columns.is_empty() {
+            let start = base.map_or(0, |(_, start)| start);
+            return Err(Mismatch::Diverged {
+                line,
+                columns,
+                previous,
+                reference: text.trim_end().to_string(),
+                emulator: format.show(cpu, start),
+            });
+        }
+
+        matched += 1;
+        previous = Some(text.trim_end().to_string());
+    }
+
+    Ok(matched)
+}
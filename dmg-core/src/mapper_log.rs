@@ -0,0 +1,127 @@
+use alloc::collections::VecDeque;
+use core::fmt;
+
+use crate::clock::EmuClock;
+
+pub use crate::mbc::MapperRegister;
+
+/// Banks a mapper has selected.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MapperState {
+    pub ram_enabled: bool,
+    /// ROM bank at 0x4000 - 0x7FFF
+    pub rom_bank: u16,
+    /// RAM bank register, MBC3 RTC registers are 0x08 - 0x0C
+    pub ram_bank: u8,
+}
+
+impl fmt::Display for MapperState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ram = if self.ram_enabled { "on" } else { "off" };
+        write!(
+            f,
+            "ROM {:02X} RAM {:02X} {ram}",
+            self.rom_bank, self.ram_bank
+        )
+    }
+}
+
+/// A write of the CPU to the ROM area.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapperWrite {
+    /// When the write happened
+    pub clock: EmuClock,
+    /// ROM bank of the instruction, 0 outside switchable ROM
+    pub bank: u16,
+    pub pc: u16,
+    pub address: u16,
+    pub value: u8,
+    pub register: MapperRegister,
+    pub before: MapperState,
+    pub after: MapperState,
+}
+
+/// Writes to 0x0000 - 0x7FFF decoded as mapper register operations, for
+/// mapper development and finding how a ROM switches banks. Games write
+/// bank registers all the time, only the latest `capacity` writes are kept.
+#[derive(Clone)]
+pub struct MapperLog {
+    writes: VecDeque<MapperWrite>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl Default for MapperLog {
+    fn default() -> Self {
+        MapperLog::new(MapperLog::DEFAULT_CAPACITY)
+    }
+}
+
+impl MapperLog {
+    pub const DEFAULT_CAPACITY: usize = 100_000;
+
+    pub fn new(capacity: usize) -> Self {
+        MapperLog {
+            writes: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, write: MapperWrite) {
+        if self.writes.len() == self.capacity {
+            self.writes.pop_front();
+            self.dropped += 1;
+        }
+        self.writes.push_back(write);
+    }
+
+    /// Kept writes, oldest first.
+    pub fn writes(&self) -> impl Iterator<Item = &MapperWrite> {
+        self.writes.iter()
+    }
+
+    /// Writes that didn't fit and were dropped, the earliest ones.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+}
+
+/// One tab separated line per write under a header, like `WarningLog`.
+impl fmt::Display for MapperLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.dropped > 0 {
+            writeln!(f, "# {} earlier writes dropped", self.dropped)?;
+        }
+        writeln!(
+            f,
+            "cycle\tframe\tline:dot\tlocation\twrite\tregister\tbefore\tafter"
+        )?;
+        for write in &self.writes {
+            writeln!(
+                f,
+                "{}\t{}\t{:03}:{:03}\t{:02X}:{:04X}\t${:04X}={:02X}\t{}\t{}\t{}",
+                write.clock.ticks,
+                write.clock.frame,
+                write.clock.ly,
+                write.clock.dot,
+                write.bank,
+                write.pc,
+                write.address,
+                write.value,
+                write.register.name(),
+                write.before,
+                write.after
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,880 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::camera::Camera;
+use crate::emu::CLOCK_HZ;
+use crate::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+/// T-cycles per RTC second when the RTC follows emulated time.
+const RTC_CYCLES_PER_SECOND: u32 = CLOCK_HZ as u32;
+
+/// Time source of the MBC3 real time clock.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RtcClock {
+    /// Follow the host clock, so the counter keeps running while the emulator
+    /// is closed. Only available with the `std` feature, without it the clock
+    /// does not advance.
+    WallClock,
+    /// Advance one second every 4194304 emulated T-cycles. Runs are
+    /// reproducible, which TAS playback and netplay rely on.
+    Emulated,
+}
+
+/// MBC3 real time clock.
+///
+/// Registers: seconds, minutes, hours, day counter low byte and
+/// day high (bit 0 day counter bit 8, bit 6 halt, bit 7 day counter carry).
+#[derive(Debug)]
+pub struct Rtc {
+    clock: RtcClock,
+    registers: [u8; 5],
+    latched: [u8; 5],
+    latch_prev: u8,
+    cycles: u32,
+    // Host time of the last update in seconds since the Unix epoch
+    last_sync: u64,
+}
+
+impl Rtc {
+    const SECONDS: usize = 0;
+    const MINUTES: usize = 1;
+    const HOURS: usize = 2;
+    const DAY_LOW: usize = 3;
+    const DAY_HIGH: usize = 4;
+
+    const HALT: u8 = 0b0100_0000;
+    const DAY_CARRY: u8 = 0b1000_0000;
+    const MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+
+    fn new() -> Self {
+        Rtc {
+            clock: RtcClock::WallClock,
+            registers: [0; 5],
+            latched: [0; 5],
+            latch_prev: 0xFF,
+            cycles: 0,
+            last_sync: Self::now(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn now() -> u64 {
+        0
+    }
+
+    fn halted(&self) -> bool {
+        (self.registers[Self::DAY_HIGH] & Self::HALT) != 0
+    }
+
+    /// Advance by `dots` T-cycles, only has an effect with the emulated clock.
+    fn tick(&mut self, dots: u64) {
+        if self.clock != RtcClock::Emulated || self.halted() {
+            return;
+        }
+
+        let cycles = self.cycles as u64 + dots;
+        let per_second = RTC_CYCLES_PER_SECOND as u64;
+
+        if cycles < per_second {
+            self.cycles = cycles as u32;
+            return;
+        }
+
+        self.cycles = (cycles % per_second) as u32;
+        self.advance(cycles / per_second);
+    }
+
+    /// Catch up with the host clock.
+    fn sync(&mut self) {
+        if self.clock != RtcClock::WallClock {
+            return;
+        }
+
+        let now = Self::now();
+
+        if !self.halted() && now > self.last_sync {
+            self.advance(now - self.last_sync);
+        }
+
+        self.last_sync = now;
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        let r = &mut self.registers;
+        let day = (((r[Self::DAY_HIGH] & 1) as u64) << 8) | (r[Self::DAY_LOW] as u64);
+        let total = seconds
+            + r[Self::SECONDS] as u64
+            + (r[Self::MINUTES] as u64) * 60
+            + (r[Self::HOURS] as u64) * 3600
+            + day * 86400;
+
+        let day = total / 86400;
+        r[Self::SECONDS] = (total % 60) as u8;
+        r[Self::MINUTES] = ((total / 60) % 60) as u8;
+        r[Self::HOURS] = ((total / 3600) % 24) as u8;
+        r[Self::DAY_LOW] = day as u8;
+        r[Self::DAY_HIGH] = (r[Self::DAY_HIGH] & !1) | (((day >> 8) & 1) as u8);
+
+        // The day counter is 9 bits wide, the carry stays set until cleared
+        if day > 0x1FF {
+            r[Self::DAY_HIGH] |= Self::DAY_CARRY;
+        }
+    }
+
+    fn latch(&mut self, value: u8) {
+        if self.latch_prev == 0x00 && value == 0x01 {
+            self.sync();
+            self.latched = self.registers;
+        }
+
+        self.latch_prev = value;
+    }
+
+    fn read(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        self.sync();
+        let index = (register - 0x08) as usize;
+        self.registers[index] = value & Self::MASKS[index];
+
+        if index == Self::SECONDS {
+            self.cycles = 0;
+        }
+    }
+
+    /// Battery file layout used by most emulators: live and latched registers
+    /// as 32-bit values followed by the 64-bit Unix timestamp of the save.
+    fn save_battery(&self, data: &mut Vec<u8>) {
+        for value in self.registers.iter().chain(self.latched.iter()) {
+            data.extend_from_slice(&(*value as u32).to_le_bytes());
+        }
+
+        data.extend_from_slice(&self.last_sync.to_le_bytes());
+    }
+
+    fn load_battery(&mut self, data: &[u8]) {
+        if data.len() < 48 {
+            return;
+        }
+
+        for i in 0..5 {
+            self.registers[i] = data[i * 4] & Self::MASKS[i];
+            self.latched[i] = data[20 + i * 4] & Self::MASKS[i];
+        }
+
+        self.last_sync = u64::from_le_bytes(data[40..48].try_into().unwrap());
+        // Time spent while the emulator was closed
+        self.sync();
+    }
+}
+
+impl fmt::Display for Rtc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let time = |r: &[u8; 5]| {
+            let day = (((r[Self::DAY_HIGH] & 1) as u16) << 8) | r[Self::DAY_LOW] as u16;
+            format!(
+                "day {day} {:02}:{:02}:{:02}",
+                r[Self::HOURS],
+                r[Self::MINUTES],
+                r[Self::SECONDS]
+            )
+        };
+
+        write!(f, "{}", time(&self.registers))?;
+        if self.halted() {
+            write!(f, ", halted")?;
+        }
+        if self.registers[Self::DAY_HIGH] & Self::DAY_CARRY != 0 {
+            write!(f, ", day carry")?;
+        }
+        write!(f, ", latched {}", time(&self.latched))?;
+        // Latching takes a 0 and then a 1
+        if self.latch_prev == 0x00 {
+            write!(f, ", 0 written to latch")?;
+        }
+        Ok(())
+    }
+}
+
+impl Saveable for Rtc {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.registers);
+        state.write_bytes(&self.latched);
+        state.write_u8(self.latch_prev);
+        state.write_u32(self.cycles);
+        state.write_u64(self.last_sync);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.registers)?;
+        state.read_into(&mut self.latched)?;
+        self.latch_prev = state.read_u8()?;
+        self.cycles = state.read_u32()?;
+        self.last_sync = state.read_u64()?;
+        Ok(())
+    }
+}
+
+/// What a write to the ROM area does on a mapper.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MapperRegister {
+    RamEnable,
+    RomBank,
+    /// Also selects the RTC registers of MBC3 and the camera registers
+    RamBank,
+    /// MBC3 RTC latch
+    Latch,
+    /// MBC1 ROM or RAM banking of the upper bank bits
+    BankingMode,
+    /// Nothing there, ROM only cartridges ignore every write
+    None,
+}
+
+impl MapperRegister {
+    pub fn name(self) -> &'static str {
+        match self {
+            MapperRegister::RamEnable => "ram-enable",
+            MapperRegister::RomBank => "rom-bank",
+            MapperRegister::RamBank => "ram-bank",
+            MapperRegister::Latch => "rtc-latch",
+            MapperRegister::BankingMode => "banking-mode",
+            MapperRegister::None => "none",
+        }
+    }
+}
+
+/// The registers of a mapper decoded, what `dmgemu`'s `mapper` command
+/// prints. Mappers leave out what they don't have.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapperReport {
+    pub name: &'static str,
+    /// ROM bank at 0x4000 - 0x7FFF
+    pub rom_bank: u16,
+    pub ram_bank: Option<u8>,
+    pub ram_enabled: Option<bool>,
+    /// Banking mode of mappers with a mode register, like MBC1
+    pub banking_mode: Option<u8>,
+    /// Live and latched time of the RTC
+    pub rtc: Option<String>,
+}
+
+impl fmt::Display for MapperReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Mapper: {}", self.name)?;
+        writeln!(f, "ROM bank: {:02X}", self.rom_bank)?;
+        if let Some(bank) = self.ram_bank {
+            match bank {
+                0x08..=0x0C if self.rtc.is_some() => {
+                    writeln!(f, "RAM bank: {bank:02X} (RTC register)")?
+                }
+                _ => writeln!(f, "RAM bank: {bank:02X}")?,
+            }
+        }
+        if let Some(enabled) = self.ram_enabled {
+            writeln!(f, "RAM: {}", if enabled { "enabled" } else { "disabled" })?;
+        }
+        if let Some(mode) = self.banking_mode {
+            writeln!(f, "Banking mode: {mode}")?;
+        }
+        if let Some(rtc) = &self.rtc {
+            writeln!(f, "RTC: {rtc}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Memory bank controller of a cartridge.
+#[derive(Debug)]
+pub enum Mapper {
+    /// 32 KiB of ROM, optionally a single RAM bank
+    RomOnly,
+    /// Up to 2 MiB of ROM and 32 KiB of RAM
+    Mbc1 {
+        ram_enabled: bool,
+        // Low 5 bits of the ROM bank, 0 maps bank 1
+        rom_bank: u8,
+        // Bits 5 and 6 of the ROM bank, or the RAM bank in mode 1
+        upper_bank: u8,
+        // Mode 1 also applies `upper_bank` to 0x0000 - 0x3FFF and RAM
+        mode: bool,
+    },
+    Mbc3 {
+        ram_enabled: bool,
+        rom_bank: u8,
+        // 0x00 - 0x03 selects a RAM bank, 0x08 - 0x0C an RTC register
+        ram_bank: u8,
+        rtc: Option<Rtc>,
+    },
+    /// Pocket Camera, up to 1 MiB of ROM and 128 KiB of RAM
+    Camera {
+        ram_enabled: bool,
+        rom_bank: u8,
+        // 0x00 - 0x0F selects a RAM bank, bit 4 the camera registers
+        ram_bank: u8,
+        camera: Camera,
+    },
+}
+
+impl Mapper {
+    /// Mapper for the cartridge type byte of the header (0x147).
+    pub fn from_cartridge_type(cartridge_type: u8) -> Self {
+        match cartridge_type {
+            0x0F..=0x13 => Mapper::Mbc3 {
+                ram_enabled: false,
+                rom_bank: 1,
+                ram_bank: 0,
+                rtc: if cartridge_type <= 0x10 {
+                    Some(Rtc::new())
+                } else {
+                    None
+                },
+            },
+            0x00 | 0x08 | 0x09 => Mapper::RomOnly,
+            0x01..=0x03 => Mapper::mbc1(),
+            0xFC => Mapper::Camera {
+                ram_enabled: false,
+                rom_bank: 1,
+                ram_bank: 0,
+                camera: Camera::new(),
+            },
+            _ => {
+                log!("Unsupported cartridge type 0x{cartridge_type:02X}, using ROM only");
+                Mapper::RomOnly
+            }
+        }
+    }
+
+    pub fn mbc1() -> Self {
+        Mapper::Mbc1 {
+            ram_enabled: false,
+            rom_bank: 1,
+            upper_bank: 0,
+            mode: false,
+        }
+    }
+
+    pub fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        // Bank numbers past the end of the ROM wrap around
+        rom[self.rom_offset(address) % rom.len()]
+    }
+
+    /// Offset into the ROM of `address` with the selected bank, may be past the end.
+    pub fn rom_offset(&self, address: u16) -> usize {
+        match self {
+            Mapper::Mbc1 {
+                rom_bank,
+                upper_bank,
+                mode,
+                ..
+            } => {
+                let bank = match address {
+                    0x4000.. => *upper_bank << 5 | *rom_bank,
+                    _ if *mode => *upper_bank << 5,
+                    _ => 0,
+                };
+                (bank as usize) * ROM_BANK_SIZE + (address as usize % ROM_BANK_SIZE)
+            }
+            Mapper::Mbc3 { rom_bank, .. } | Mapper::Camera { rom_bank, .. }
+                if address >= 0x4000 =>
+            {
+                (*rom_bank as usize) * ROM_BANK_SIZE + (address as usize - ROM_BANK_SIZE)
+            }
+            _ => address as usize,
+        }
+    }
+
+    /// Whether `address` in 0xA000 - 0xBFFF reaches external RAM of `ram_len`
+    /// bytes or the RTC, rather than reading 0xFF.
+    pub fn ram_mapped(&self, ram_len: usize, address: u16) -> bool {
+        match self {
+            Mapper::RomOnly => (address as usize - 0xA000) < ram_len,
+            Mapper::Mbc1 { ram_enabled, .. } => {
+                *ram_enabled && self.mbc1_ram_offset(ram_len, address) < ram_len
+            }
+            Mapper::Mbc3 {
+                ram_enabled,
+                ram_bank,
+                rtc,
+                ..
+            } => {
+                *ram_enabled
+                    && match (ram_bank, rtc) {
+                        (0x08..=0x0C, Some(_)) => true,
+                        (0x00..=0x03, _) => Self::ram_offset(*ram_bank, address) < ram_len,
+                        _ => false,
+                    }
+            }
+            Mapper::Camera { ram_bank, .. } => {
+                *ram_bank & 0x10 != 0 || Self::ram_offset(*ram_bank, address) < ram_len
+            }
+        }
+    }
+
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        match self {
+            Mapper::RomOnly => (),
+            Mapper::Mbc1 {
+                ram_enabled,
+                rom_bank,
+                upper_bank,
+                mode,
+            } => match address {
+                0x0000..=0x1FFF => *ram_enabled = (value & 0x0F) == 0x0A,
+                0x2000..=0x3FFF => *rom_bank = (value & 0x1F).max(1),
+                0x4000..=0x5FFF => *upper_bank = value & 0x03,
+                _ => *mode = value & 0x01 != 0,
+            },
+            Mapper::Mbc3 {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                rtc,
+            } => match address {
+                0x0000..=0x1FFF => *ram_enabled = (value & 0x0F) == 0x0A,
+                0x2000..=0x3FFF => *rom_bank = (value & 0x7F).max(1),
+                0x4000..=0x5FFF => *ram_bank = value,
+                _ => {
+                    if let Some(rtc) = rtc {
+                        rtc.latch(value);
+                    }
+                }
+            },
+            Mapper::Camera {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                ..
+            } => match address {
+                0x0000..=0x1FFF => *ram_enabled = (value & 0x0F) == 0x0A,
+                // Bank 0 can be mapped twice
+                0x2000..=0x3FFF => *rom_bank = value & 0x3F,
+                0x4000..=0x5FFF => *ram_bank = value & 0x1F,
+                _ => (),
+            },
+        }
+    }
+
+    pub fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        match self {
+            Mapper::RomOnly => ram.get(address as usize - 0xA000).copied().unwrap_or(0xFF),
+            Mapper::Mbc1 { ram_enabled, .. } => match ram_enabled {
+                true => ram
+                    .get(self.mbc1_ram_offset(ram.len(), address))
+                    .copied()
+                    .unwrap_or(0xFF),
+                false => 0xFF,
+            },
+            Mapper::Mbc3 {
+                ram_enabled,
+                ram_bank,
+                rtc,
+                ..
+            } => {
+                if !ram_enabled {
+                    return 0xFF;
+                }
+
+                match (ram_bank, rtc) {
+                    (0x08..=0x0C, Some(rtc)) => rtc.read(*ram_bank),
+                    (0x00..=0x03, _) => ram
+                        .get(Self::ram_offset(*ram_bank, address))
+                        .copied()
+                        .unwrap_or(0xFF),
+                    _ => 0xFF,
+                }
+            }
+            // Readable without enabling, but not while the sensor writes to it
+            Mapper::Camera {
+                ram_bank, camera, ..
+            } => match ram_bank {
+                0x10.. => camera.read(address),
+                _ if camera.capturing() => 0x00,
+                _ => ram
+                    .get(Self::ram_offset(*ram_bank, address))
+                    .copied()
+                    .unwrap_or(0xFF),
+            },
+        }
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) {
+        let offset = match self {
+            Mapper::RomOnly => address as usize - 0xA000,
+            Mapper::Mbc1 { ram_enabled, .. } => {
+                if !*ram_enabled {
+                    return;
+                }
+                self.mbc1_ram_offset(ram.len(), address)
+            }
+            Mapper::Mbc3 {
+                ram_enabled,
+                ram_bank,
+                rtc,
+                ..
+            } => {
+                if !*ram_enabled {
+                    return;
+                }
+
+                match (*ram_bank, rtc) {
+                    (0x08..=0x0C, Some(rtc)) => {
+                        rtc.write(*ram_bank, value);
+                        return;
+                    }
+                    (0x00..=0x03, _) => Self::ram_offset(*ram_bank, address),
+                    _ => return,
+                }
+            }
+            Mapper::Camera {
+                ram_enabled,
+                ram_bank,
+                camera,
+                ..
+            } => {
+                if !*ram_enabled {
+                    return;
+                }
+
+                match *ram_bank {
+                    0x10.. => {
+                        camera.write(address, value);
+                        return;
+                    }
+                    _ if camera.capturing() => return,
+                    _ => Self::ram_offset(*ram_bank, address),
+                }
+            }
+        };
+
+        if let Some(byte) = ram.get_mut(offset) {
+            *byte = value;
+        }
+    }
+
+    /// Advance by `dots` T-cycles.
+    pub fn tick(&mut self, ram: &mut [u8], dots: u64) {
+        match self {
+            Mapper::Mbc3 { rtc: Some(rtc), .. } => rtc.tick(dots),
+            Mapper::Camera { camera, .. } => camera.tick(ram, dots),
+            _ => (),
+        }
+    }
+
+    pub fn rtc(&self) -> Option<&Rtc> {
+        match self {
+            Mapper::Mbc3 { rtc, .. } => rtc.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn camera_mut(&mut self) -> Option<&mut Camera> {
+        match self {
+            Mapper::Camera { camera, .. } => Some(camera),
+            _ => None,
+        }
+    }
+
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        if let Mapper::Mbc3 { rtc: Some(rtc), .. } = self {
+            rtc.clock = clock;
+            rtc.last_sync = Rtc::now();
+        }
+    }
+
+    pub fn save_battery(&self, data: &mut Vec<u8>) {
+        if let Some(rtc) = self.rtc() {
+            rtc.save_battery(data);
+        }
+    }
+
+    pub fn load_battery(&mut self, data: &[u8]) {
+        if let Mapper::Mbc3 { rtc: Some(rtc), .. } = self {
+            rtc.load_battery(data);
+        }
+    }
+
+    /// Writes to the mapper registers that select the current banks.
+    pub fn register_writes(&self) -> Vec<(u16, u8)> {
+        match self {
+            Mapper::RomOnly => Vec::new(),
+            Mapper::Mbc1 {
+                ram_enabled,
+                rom_bank,
+                upper_bank,
+                mode,
+            } => vec![
+                (0x0000, if *ram_enabled { 0x0A } else { 0x00 }),
+                (0x2000, *rom_bank),
+                (0x4000, *upper_bank),
+                (0x6000, *mode as u8),
+            ],
+            Mapper::Mbc3 {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                ..
+            }
+            | Mapper::Camera {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                ..
+            } => vec![
+                (0x0000, if *ram_enabled { 0x0A } else { 0x00 }),
+                (0x2000, *rom_bank),
+                (0x4000, *ram_bank),
+            ],
+        }
+    }
+
+    /// RAM enable (0x0A when enabled), ROM bank and RAM bank of the
+    /// mapper, the same three for every mapper that has them. MBC1 gives
+    /// the banks its two bank registers and mode select together.
+    pub fn registers(&self) -> Option<[u8; 3]> {
+        let ram_enable = |enabled: bool| if enabled { 0x0A } else { 0x00 };
+
+        match self {
+            Mapper::RomOnly => None,
+            Mapper::Mbc1 {
+                ram_enabled,
+                rom_bank,
+                upper_bank,
+                mode,
+            } => Some([
+                ram_enable(*ram_enabled),
+                *upper_bank << 5 | *rom_bank,
+                if *mode { *upper_bank } else { 0 },
+            ]),
+            Mapper::Mbc3 {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                ..
+            }
+            | Mapper::Camera {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                ..
+            } => Some([ram_enable(*ram_enabled), *rom_bank, *ram_bank]),
+        }
+    }
+
+    /// Current state of the registers, the same fields for every mapper.
+    pub fn report(&self) -> MapperReport {
+        let rom_bank = (self.rom_offset(0x4000) / ROM_BANK_SIZE) as u16;
+
+        match self {
+            Mapper::RomOnly => MapperReport {
+                name: "ROM only",
+                rom_bank,
+                ram_bank: None,
+                ram_enabled: None,
+                banking_mode: None,
+                rtc: None,
+            },
+            Mapper::Mbc1 {
+                ram_enabled,
+                upper_bank,
+                mode,
+                ..
+            } => MapperReport {
+                name: "MBC1",
+                rom_bank,
+                ram_bank: Some(if *mode { *upper_bank } else { 0 }),
+                ram_enabled: Some(*ram_enabled),
+                banking_mode: Some(*mode as u8),
+                rtc: None,
+            },
+            Mapper::Mbc3 {
+                ram_enabled,
+                ram_bank,
+                rtc,
+                ..
+            } => MapperReport {
+                name: if rtc.is_some() { "MBC3+RTC" } else { "MBC3" },
+                rom_bank,
+                ram_bank: Some(*ram_bank),
+                ram_enabled: Some(*ram_enabled),
+                banking_mode: None,
+                rtc: rtc.as_ref().map(|rtc| rtc.to_string()),
+            },
+            Mapper::Camera {
+                ram_enabled,
+                ram_bank,
+                ..
+            } => MapperReport {
+                name: "Pocket Camera",
+                rom_bank,
+                ram_bank: Some(*ram_bank),
+                ram_enabled: Some(*ram_enabled),
+                banking_mode: None,
+                rtc: None,
+            },
+        }
+    }
+
+    /// Register a write to `address` in 0x0000 - 0x7FFF goes to.
+    pub fn register_at(&self, address: u16) -> MapperRegister {
+        match (self, address) {
+            (Mapper::RomOnly, _) => MapperRegister::None,
+            (Mapper::Mbc1 { .. }, 0x6000..=0x7FFF) => MapperRegister::BankingMode,
+            (_, 0x0000..=0x1FFF) => MapperRegister::RamEnable,
+            (_, 0x2000..=0x3FFF) => MapperRegister::RomBank,
+            (_, 0x4000..=0x5FFF) => MapperRegister::RamBank,
+            (Mapper::Mbc3 { rtc: Some(_), .. }, _) => MapperRegister::Latch,
+            _ => MapperRegister::None,
+        }
+    }
+
+    /// Offset into external RAM on MBC1, carts with 8 KiB or less of RAM
+    /// ignore the bank.
+    fn mbc1_ram_offset(&self, ram_len: usize, address: u16) -> usize {
+        match self {
+            Mapper::Mbc1 {
+                upper_bank,
+                mode: true,
+                ..
+            } if ram_len > RAM_BANK_SIZE => Self::ram_offset(*upper_bank, address),
+            _ => Self::ram_offset(0, address),
+        }
+    }
+
+    fn ram_offset(bank: u8, address: u16) -> usize {
+        (bank as usize) * RAM_BANK_SIZE + (address as usize - 0xA000)
+    }
+}
+
+impl Resettable for Mapper {
+    fn reset(&mut self) {
+        // The RTC is battery backed and keeps running
+        match self {
+            Mapper::RomOnly => (),
+            Mapper::Mbc1 { .. } => *self = Mapper::mbc1(),
+            Mapper::Mbc3 {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                ..
+            } => {
+                *ram_enabled = false;
+                *rom_bank = 1;
+                *ram_bank = 0;
+            }
+            Mapper::Camera {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                camera,
+            } => {
+                *ram_enabled = false;
+                *rom_bank = 1;
+                *ram_bank = 0;
+                camera.reset();
+            }
+        }
+    }
+}
+
+impl Saveable for Mapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        match self {
+            Mapper::RomOnly => (),
+            Mapper::Mbc1 {
+                ram_enabled,
+                rom_bank,
+                upper_bank,
+                mode,
+            } => {
+                state.write_bool(*ram_enabled);
+                state.write_u8(*rom_bank);
+                state.write_u8(*upper_bank);
+                state.write_bool(*mode);
+            }
+            Mapper::Mbc3 {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                rtc,
+            } => {
+                state.write_bool(*ram_enabled);
+                state.write_u8(*rom_bank);
+                state.write_u8(*ram_bank);
+
+                if let Some(rtc) = rtc {
+                    rtc.save_state(state);
+                }
+            }
+            Mapper::Camera {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                camera,
+            } => {
+                state.write_bool(*ram_enabled);
+                state.write_u8(*rom_bank);
+                state.write_u8(*ram_bank);
+                camera.save_state(state);
+            }
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        match self {
+            Mapper::RomOnly => (),
+            Mapper::Mbc1 {
+                ram_enabled,
+                rom_bank,
+                upper_bank,
+                mode,
+            } => {
+                *ram_enabled = state.read_bool()?;
+                *rom_bank = state.read_u8()?;
+                *upper_bank = state.read_u8()?;
+                *mode = state.read_bool()?;
+            }
+            Mapper::Mbc3 {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                rtc,
+            } => {
+                *ram_enabled = state.read_bool()?;
+                *rom_bank = state.read_u8()?;
+                *ram_bank = state.read_u8()?;
+
+                if let Some(rtc) = rtc {
+                    rtc.load_state(state)?;
+                }
+            }
+            Mapper::Camera {
+                ram_enabled,
+                rom_bank,
+                ram_bank,
+                camera,
+            } => {
+                *ram_enabled = state.read_bool()?;
+                *rom_bank = state.read_u8()?;
+                *ram_bank = state.read_u8()?;
+                camera.load_state(state)?;
+            }
+        }
+
+        Ok(())
+    }
+}
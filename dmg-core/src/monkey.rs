@@ -0,0 +1,64 @@
+//! Random joypad input for monkey testing.
+
+use crate::joypad::Buttons;
+
+/// Longest a set of buttons is held, in frames.
+const MAX_HOLD_FRAMES: u32 = 16;
+
+/// Seeded pseudo-random buttons, a new set every few frames.
+///
+/// Run headless over a ROM library it mashes through menus and into the
+/// game logic, where crashes of the emulator tend to hide. Opposite
+/// directions are never held together, a D-pad can't do that, and neither
+/// is the A+B+Start+Select reset most games check for.
+#[derive(Clone, Debug)]
+pub struct MonkeyInput {
+    // xorshift64*, never zero
+    state: u64,
+    held: Buttons,
+    // Frames until the next set
+    hold: u32,
+}
+
+impl MonkeyInput {
+    /// The same seed gives the same sequence of buttons.
+    pub fn new(seed: u64) -> Self {
+        MonkeyInput {
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+            held: Buttons::empty(),
+            hold: 0,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Buttons to hold for the next frame.
+    pub fn next_frame(&mut self) -> Buttons {
+        if self.hold > 0 {
+            self.hold -= 1;
+            return self.held;
+        }
+
+        let random = self.next_u64();
+        let mut buttons = Buttons::from_bits_truncate((random >> 56) as u8);
+
+        if buttons.contains(Buttons::LEFT | Buttons::RIGHT) {
+            buttons.remove(Buttons::LEFT);
+        }
+        if buttons.contains(Buttons::UP | Buttons::DOWN) {
+            buttons.remove(Buttons::UP);
+        }
+        if buttons.contains(Buttons::A | Buttons::B | Buttons::START | Buttons::SELECT) {
+            buttons.remove(Buttons::SELECT);
+        }
+
+        self.held = buttons;
+        self.hold = (random as u32) % MAX_HOLD_FRAMES;
+        buttons
+    }
+}
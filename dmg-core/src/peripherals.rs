@@ -0,0 +1,142 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use crate::serial::{Loopback, SerialDevice};
+
+/// Creates a device from the argument of `name:argument`, empty without one.
+pub type PeripheralFactory = fn(&str) -> Result<Box<dyn SerialDevice>, String>;
+
+/// Device of a `PeripheralRegistry`.
+pub struct PeripheralEntry {
+    pub name: &'static str,
+    /// How to select it, e.g. `log:<file>`
+    pub usage: &'static str,
+    pub description: &'static str,
+    create: PeripheralFactory,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PeripheralError {
+    Unknown(String),
+    /// The device didn't accept its argument
+    Invalid {
+        name: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for PeripheralError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeripheralError::Unknown(name) => write!(f, "unknown serial device {name}"),
+            PeripheralError::Invalid { name, message } => {
+                write!(f, "serial device {name}: {message}")
+            }
+        }
+    }
+}
+
+impl Error for PeripheralError {}
+
+/// Serial devices selectable by name, the frontend's `--serial=` picks from
+/// `PeripheralRegistry::builtin`.
+///
+/// A new peripheral implements `Peripheral` (or `SerialDevice` when it needs
+/// the time of transfers) and gets an entry in `builtin`, the serial port
+/// doesn't have to know about it.
+pub struct PeripheralRegistry {
+    entries: Vec<PeripheralEntry>,
+}
+
+impl PeripheralRegistry {
+    pub fn new() -> Self {
+        PeripheralRegistry {
+            entries: Vec::new(),
+        }
+    }
+
+    /// The devices that come with the emulator, file and console backed ones
+    /// need the `std` feature.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register("loopback", "loopback", "sends every byte back", |_| {
+            Ok(Box::new(Loopback))
+        });
+
+        #[cfg(feature = "std")]
+        {
+            use crate::serial::{SerialExchange, SerialLog, SerialReplay};
+
+            registry.register("stdout", "stdout", "prints the sent bytes", |_| {
+                Ok(Box::new(SerialLog::new(std::io::stdout())))
+            });
+            registry.register(
+                "log",
+                "log:<file>",
+                "writes the sent bytes to a file",
+                |path| {
+                    let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+                    Ok(Box::new(SerialLog::new(file)))
+                },
+            );
+            registry.register(
+                "replay",
+                "replay:<file>",
+                "answers with the bytes of a serial capture",
+                |path| {
+                    let capture = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+                    let exchanges =
+                        SerialExchange::parse_capture(&capture).ok_or("invalid serial capture")?;
+                    Ok(Box::new(SerialReplay::new(exchanges)))
+                },
+            );
+        }
+
+        registry
+    }
+
+    /// Add a device, replacing one with the same name.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        description: &'static str,
+        create: PeripheralFactory,
+    ) {
+        self.entries.retain(|entry| entry.name != name);
+        self.entries.push(PeripheralEntry {
+            name,
+            usage,
+            description,
+            create,
+        });
+    }
+
+    pub fn entries(&self) -> &[PeripheralEntry] {
+        &self.entries
+    }
+
+    /// Create the device selected by `name` or `name:argument`.
+    pub fn create(&self, spec: &str) -> Result<Box<dyn SerialDevice>, PeripheralError> {
+        let (name, argument) = spec.split_once(':').unwrap_or((spec, ""));
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| PeripheralError::Unknown(name.to_string()))?;
+
+        (entry.create)(argument).map_err(|message| PeripheralError::Invalid {
+            name: name.to_string(),
+            message,
+        })
+    }
+}
+
+impl Default for PeripheralRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
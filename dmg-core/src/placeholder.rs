@@ -0,0 +1,158 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::cart::{CartridgeHeader, NINTENDO_LOGO};
+use crate::ppu::{XRES, YRES};
+
+/// Characters the placeholder can show, a tile each in this order. The
+/// others show as spaces.
+const GLYPHS: &str = " ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.+-:/";
+
+/// 5x7 pixel glyphs of `GLYPHS`, a row per byte with the leftmost pixel in
+/// bit 4.
+#[rustfmt::skip]
+const FONT: [[u8; 7]; 42] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+    [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+];
+
+const FONT_ADDRESS: u16 = 0x0200;
+const MAP_ADDRESS: u16 = 0x0600;
+const COPY_ADDRESS: u16 = 0x01C0;
+/// Tiles of the background map the screen shows, 32 per row.
+const MAP_SIZE: u16 = 32 * (YRES / 8) as u16;
+
+/// A 32 KiB ROM that shows `lines` centered on the screen and waits, what
+/// the frontend runs when there is no game to run. Lines are cut to the 20
+/// tiles of a screen row and characters outside `GLYPHS` show as spaces,
+/// lower case as upper case.
+pub fn placeholder_rom(lines: &[&str]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+
+    // VBlank handler, the main loop halts until the next frame
+    rom[0x40] = 0xD9; // RETI
+    // NOP; JP $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    rom[0x134..0x13A].copy_from_slice(b"NO ROM");
+
+    let [font_low, font_high] = FONT_ADDRESS.to_le_bytes();
+    let [map_low, map_high] = MAP_ADDRESS.to_le_bytes();
+    let [copy_low, copy_high] = COPY_ADDRESS.to_le_bytes();
+    let [font_len_low, font_len_high] = (FONT.len() as u16 * 16).to_le_bytes();
+    let [map_len_low, map_len_high] = MAP_SIZE.to_le_bytes();
+
+    #[rustfmt::skip]
+    let main = [
+        0xF3,                                   // DI
+        0x31, 0xFE, 0xFF,                       // LD SP, $FFFE
+        0xF0, 0x44,                             // LDH A, (LY)
+        0xFE, 0x90,                             // CP 144
+        0x38, 0xFA,                             // JR C, -6, until VBlank
+        0xAF,                                   // XOR A
+        0xE0, 0x40,                             // LDH (LCDC), A, LCD off
+        0x21, 0x00, 0x80,                       // LD HL, $8000
+        0x11, font_low, font_high,              // LD DE, font
+        0x01, font_len_low, font_len_high,      // LD BC, font size
+        0xCD, copy_low, copy_high,              // CALL copy
+        0x21, 0x00, 0x98,                       // LD HL, $9800
+        0x11, map_low, map_high,                // LD DE, map
+        0x01, map_len_low, map_len_high,        // LD BC, map size
+        0xCD, copy_low, copy_high,              // CALL copy
+        0x3E, 0xE4,                             // LD A, $E4
+        0xE0, 0x47,                             // LDH (BGP), A
+        0x3E, 0x91,                             // LD A, $91
+        0xE0, 0x40,                             // LDH (LCDC), A, LCD and background on
+        0x3E, 0x01,                             // LD A, VBlank
+        0xE0, 0xFF,                             // LDH (IE), A
+        0xAF,                                   // XOR A
+        0xE0, 0x0F,                             // LDH (IF), A
+        0xFB,                                   // EI
+        0x76,                                   // HALT
+        0x18, 0xFD,                             // JR -3
+    ];
+    #[rustfmt::skip]
+    let copy = [
+        0x1A,                                   // LD A, (DE)
+        0x22,                                   // LD (HL+), A
+        0x13,                                   // INC DE
+        0x0B,                                   // DEC BC
+        0x78,                                   // LD A, B
+        0xB1,                                   // OR C
+        0x20, 0xF8,                             // JR NZ, -8
+        0xC9,                                   // RET
+    ];
+    rom[0x150..0x150 + main.len()].copy_from_slice(&main);
+    let copy_address = COPY_ADDRESS as usize;
+    rom[copy_address..copy_address + copy.len()].copy_from_slice(&copy);
+
+    // Both bit planes set, the glyphs are drawn in color 3
+    let font = &mut rom[FONT_ADDRESS as usize..];
+    for (glyph, tile) in FONT.iter().zip(font.chunks_mut(16)) {
+        for (row, bits) in glyph.iter().enumerate() {
+            tile[row * 2] = bits << 2;
+            tile[row * 2 + 1] = bits << 2;
+        }
+    }
+
+    let columns = XRES / 8;
+    let rows = YRES / 8;
+    let lines = &lines[..lines.len().min(rows)];
+    let map = &mut rom[MAP_ADDRESS as usize..(MAP_ADDRESS + MAP_SIZE) as usize];
+    let top = (rows - lines.len()) / 2;
+
+    for (row, line) in lines.iter().enumerate() {
+        let tiles: Vec<u8> = line
+            .chars()
+            .take(columns)
+            .map(|c| GLYPHS.find(c.to_ascii_uppercase()).unwrap_or(0) as u8)
+            .collect();
+        let start = (top + row) * 32 + (columns - tiles.len()) / 2;
+        map[start..start + tiles.len()].copy_from_slice(&tiles);
+    }
+
+    rom[0x14D] = CartridgeHeader::checksum(&rom);
+    let global_checksum = CartridgeHeader::compute_global_checksum(&rom);
+    rom[0x14E..0x150].copy_from_slice(&global_checksum.to_be_bytes());
+    rom
+}
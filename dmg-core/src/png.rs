@@ -0,0 +1,497 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use crate::hash::crc32;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// Largest stored deflate block
+const MAX_BLOCK: usize = 0xFFFF;
+// Widest and tallest image decoded, well past photos taken for the camera
+const MAX_SIZE: u32 = 8192;
+
+/// Encode `rgba` (R, G, B, A bytes per pixel, rows top to bottom) as a PNG file.
+///
+/// Image data is stored without compression, Game Boy sized images stay small
+/// and this avoids pulling a deflate compressor into the core.
+pub fn encode_rgba(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), (width * height * 4) as usize);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Every row starts with its filter type, 0 is none
+    let mut raw = Vec::with_capacity(rgba.len() + height as usize);
+    for row in rgba.chunks((width * 4) as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut png = Vec::new();
+    png.extend_from_slice(&SIGNATURE);
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// zlib stream made of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK * 5 + 11);
+    // Deflate with a 32 KiB window, no preset dictionary
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PngError {
+    NotPng,
+    Corrupt,
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PngError::NotPng => write!(f, "not a PNG file"),
+            PngError::Corrupt => write!(f, "PNG file is corrupt"),
+            PngError::Unsupported(what) => write!(f, "unsupported PNG: {what}"),
+        }
+    }
+}
+
+impl Error for PngError {}
+
+/// Decode a PNG file to its width, height and RGBA pixels with 8 bits per channel.
+///
+/// Every color type and bit depth is read, interlaced images aren't.
+pub fn decode_rgba(png: &[u8]) -> Result<(u32, u32, Vec<u8>), PngError> {
+    if !png.starts_with(&SIGNATURE) {
+        return Err(PngError::NotPng);
+    }
+
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = Vec::new();
+    let mut pos = SIGNATURE.len();
+
+    while pos + 12 <= png.len() {
+        let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &png[pos + 4..pos + 8];
+        let data = png.get(pos + 8..pos + 8 + len).ok_or(PngError::Corrupt)?;
+        pos += len + 12;
+
+        match kind {
+            b"IHDR" if data.len() == 13 => header = Some(data),
+            b"PLTE" => palette = data,
+            b"tRNS" => transparency = data,
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => (),
+        }
+    }
+
+    let header = header.ok_or(PngError::Corrupt)?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    let (depth, color_type) = (header[8], header[9]);
+
+    if width == 0 || height == 0 {
+        return Err(PngError::Corrupt);
+    }
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(PngError::Unsupported("image size"));
+    }
+
+    if header[12] != 0 {
+        return Err(PngError::Unsupported("interlacing"));
+    }
+
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(PngError::Corrupt),
+    };
+
+    if !matches!(depth, 1 | 2 | 4 | 8 | 16) || (channels > 1 && depth < 8) {
+        return Err(PngError::Corrupt);
+    }
+
+    // zlib header, the method has to be deflate
+    if compressed.len() < 2 || compressed[0] & 0x0F != 8 {
+        return Err(PngError::Corrupt);
+    }
+
+    let bits_per_pixel = depth as usize * channels;
+    let stride = (width as usize)
+        .checked_mul(bits_per_pixel)
+        .ok_or(PngError::Unsupported("image size"))?
+        .div_ceil(8);
+    let raw_len = (stride + 1)
+        .checked_mul(height as usize)
+        .ok_or(PngError::Unsupported("image size"))?;
+    let rgba_len = (width as usize * 4)
+        .checked_mul(height as usize)
+        .ok_or(PngError::Unsupported("image size"))?;
+    let raw = inflate(&compressed[2..], raw_len)?;
+
+    if raw.len() < raw_len {
+        return Err(PngError::Corrupt);
+    }
+
+    let pixels = unfilter(&raw, stride, height as usize, bits_per_pixel.div_ceil(8))?;
+    let max = (1u32 << depth.min(8)) - 1;
+    let mut rgba = Vec::with_capacity(rgba_len);
+
+    for row in pixels.chunks(stride) {
+        for x in 0..width as usize {
+            // Samples scaled to 8 bits, 16 bit samples keep their high byte
+            let sample = |channel: usize| -> u32 {
+                let bit = (x * channels + channel) * depth as usize;
+
+                match depth {
+                    8 | 16 => row[bit / 8] as u32,
+                    _ => (row[bit / 8] as u32 >> (8 - depth as usize - bit % 8)) & max,
+                }
+            };
+            let grey = |value: u32| (value * 255 / max) as u8;
+
+            match color_type {
+                0 => {
+                    let value = grey(sample(0));
+                    rgba.extend_from_slice(&[value, value, value, 0xFF]);
+                }
+                2 => rgba.extend_from_slice(&[
+                    sample(0) as u8,
+                    sample(1) as u8,
+                    sample(2) as u8,
+                    0xFF,
+                ]),
+                3 => {
+                    let index = sample(0) as usize;
+                    let color = palette
+                        .get(index * 3..index * 3 + 3)
+                        .ok_or(PngError::Corrupt)?;
+                    let alpha = transparency.get(index).copied().unwrap_or(0xFF);
+                    rgba.extend_from_slice(&[color[0], color[1], color[2], alpha]);
+                }
+                4 => {
+                    let value = sample(0) as u8;
+                    rgba.extend_from_slice(&[value, value, value, sample(1) as u8]);
+                }
+                _ => rgba.extend_from_slice(&[
+                    sample(0) as u8,
+                    sample(1) as u8,
+                    sample(2) as u8,
+                    sample(3) as u8,
+                ]),
+            }
+        }
+    }
+
+    Ok((width, height, rgba))
+}
+
+/// Undo the filter each row starts with, `bpp` is the number of bytes per
+/// pixel rounded up that filters look back by.
+fn unfilter(raw: &[u8], stride: usize, height: usize, bpp: usize) -> Result<Vec<u8>, PngError> {
+    let mut pixels = vec![0u8; stride * height];
+
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+
+        for x in 0..stride {
+            let left = if x >= bpp {
+                pixels[y * stride + x - bpp]
+            } else {
+                0
+            };
+            let up = if y > 0 {
+                pixels[(y - 1) * stride + x]
+            } else {
+                0
+            };
+            let up_left = if x >= bpp && y > 0 {
+                pixels[(y - 1) * stride + x - bpp]
+            } else {
+                0
+            };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(PngError::Corrupt),
+            };
+            pixels[y * stride + x] = line[x].wrapping_add(predicted);
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Base values and extra bits of the deflate length and distance codes
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order the code lengths of the code length alphabet are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    // Position in bits
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> Result<u32, PngError> {
+        let mut value = 0;
+
+        for i in 0..count {
+            let byte = self.data.get(self.pos / 8).ok_or(PngError::Corrupt)?;
+            value |= ((*byte as u32 >> (self.pos % 8)) & 1) << i;
+            self.pos += 1;
+        }
+
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.pos = self.pos.next_multiple_of(8);
+    }
+}
+
+/// Canonical Huffman code as the number of codes per length and the symbols
+/// ordered by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for len in 1..16 {
+            for (symbol, _) in lengths.iter().enumerate().filter(|(_, l)| **l == len) {
+                symbols.push(symbol as u16);
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> Result<u16, PngError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+
+            if code - first < count {
+                return self
+                    .symbols
+                    .get((index + code - first) as usize)
+                    .copied()
+                    .ok_or(PngError::Corrupt);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(PngError::Corrupt)
+    }
+}
+
+/// Decompress a raw deflate stream, `expected` is only a capacity hint.
+fn inflate(data: &[u8], expected: usize) -> Result<Vec<u8>, PngError> {
+    let mut out = Vec::with_capacity(expected);
+    let mut bits = BitReader { data, pos: 0 };
+
+    loop {
+        let last = bits.bits(1)? == 1;
+
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let start = bits.pos / 8;
+                let header = data.get(start..start + 4).ok_or(PngError::Corrupt)?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let block = data
+                    .get(start + 4..start + 4 + len)
+                    .ok_or(PngError::Corrupt)?;
+                out.extend_from_slice(block);
+                bits.pos = (start + 4 + len) * 8;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err(PngError::Corrupt),
+        }
+
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn read_dynamic_codes(bits: &mut BitReader) -> Result<(Huffman, Huffman), PngError> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_length_count = bits.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = bits.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(bits)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 => (*lengths.last().ok_or(PngError::Corrupt)?, 3 + bits.bits(2)?),
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend((0..repeat).map(|_| value));
+    }
+
+    if lengths.len() > literal_count + distance_count {
+        return Err(PngError::Corrupt);
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), PngError> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let code = symbol - 257;
+                let len = *LENGTH_BASE.get(code).ok_or(PngError::Corrupt)? as usize
+                    + bits.bits(LENGTH_EXTRA[code] as u32)? as usize;
+                let code = distances.decode(bits)? as usize;
+                let distance = *DISTANCE_BASE.get(code).ok_or(PngError::Corrupt)? as usize
+                    + bits.bits(DISTANCE_EXTRA[code] as u32)? as usize;
+
+                if distance > out.len() {
+                    return Err(PngError::Corrupt);
+                }
+
+                // Copies may overlap what they produce
+                for _ in 0..len {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+        }
+    }
+}
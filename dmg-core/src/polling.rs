@@ -0,0 +1,84 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::bus::HardwareRegister;
+
+/// Reads of LY and STAT by one instruction.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct PollCount {
+    pub ly: u64,
+    pub stat: u64,
+}
+
+impl PollCount {
+    pub fn total(&self) -> u64 {
+        self.ly + self.stat
+    }
+}
+
+/// Reads of LY and STAT per address of the reading instruction.
+///
+/// Loops waiting for a line or a PPU mode read these registers over and over,
+/// the addresses reading them most are where a game busy-waits and where
+/// skipping idle time would pay off. Addresses in the switchable ROM bank are
+/// not told apart by bank.
+#[derive(Clone, Default)]
+pub struct PollCounter {
+    reads: BTreeMap<u16, PollCount>,
+}
+
+impl PollCounter {
+    /// Lines in the `Display` report.
+    pub const REPORT_LINES: usize = 20;
+
+    pub fn new() -> Self {
+        PollCounter::default()
+    }
+
+    pub(crate) fn record(&mut self, pc: u16, address: u16) {
+        let count = self.reads.entry(pc).or_default();
+
+        if address == HardwareRegister::LY as u16 {
+            count.ly += 1;
+        } else {
+            count.stat += 1;
+        }
+    }
+
+    pub fn get(&self, pc: u16) -> PollCount {
+        self.reads.get(&pc).copied().unwrap_or_default()
+    }
+
+    /// Reads of both registers from all addresses.
+    pub fn total(&self) -> u64 {
+        self.reads.values().map(PollCount::total).sum()
+    }
+
+    /// Addresses by their number of reads, most first.
+    pub fn hottest(&self) -> Vec<(u16, PollCount)> {
+        let mut reads: Vec<_> = self.reads.iter().map(|(pc, count)| (*pc, *count)).collect();
+        reads.sort_by_key(|(pc, count)| (core::cmp::Reverse(count.total()), *pc));
+        reads
+    }
+}
+
+/// The addresses reading most, one per line with their share of all reads.
+impl fmt::Display for PollCounter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().max(1);
+        writeln!(f, "PC       LY reads  STAT reads  share")?;
+
+        for (pc, count) in self.hottest().into_iter().take(Self::REPORT_LINES) {
+            writeln!(
+                f,
+                "{pc:04X} {:>12} {:>11} {:>5.1}%",
+                count.ly,
+                count.stat,
+                count.total() as f64 * 100.0 / total as f64
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use bitflags::bitflags;
+
+use crate::bus::HardwareRegister;
+use crate::cpu::{Flags, RegisterFile};
+
+/// Game Boy hardware revision, selects the state the boot ROM leaves behind.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Model {
+    /// Early DMG boot ROM, only in the first Japanese units
+    Dmg0,
+    /// DMG-A/B/C
+    #[default]
+    Dmg,
+    /// Game Boy Pocket
+    Mgb,
+    /// Super Game Boy
+    Sgb,
+    Sgb2,
+}
+
+bitflags!(
+    /// Hardware bugs games rely on, emulated when set.
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct Quirks: u8 {
+        /// Writing STAT in HBlank, VBlank or while LY == LYC requests an LCD
+        /// interrupt whatever sources are selected, Road Rash and Zerd no
+        /// Densetsu depend on it
+        const STAT_WRITE_INTERRUPT = 0b01;
+        /// Writing DIV or TAC so that the DIV bit selected for TIMA falls
+        /// counts TIMA once
+        const TIMER_WRITE_EDGE = 0b10;
+        /// The system counter starts in the phase the boot ROM of the model
+        /// hands over in, see `PowerOnState::div`, otherwise at 0
+        const DIV_PHASE = 0b100;
+    }
+);
+
+impl Quirks {
+    /// Quirks of a model. The STAT write bug was only fixed in the Game Boy
+    /// Color, the SGB boot ROM hands over whenever the SNES lets it, in no
+    /// particular DIV phase.
+    pub fn for_model(model: Model) -> Self {
+        match model {
+            Model::Dmg0 | Model::Dmg | Model::Mgb => Quirks::all(),
+            Model::Sgb | Model::Sgb2 => Quirks::all().difference(Quirks::DIV_PHASE),
+        }
+    }
+}
+
+/// Contents of WRAM, HRAM and VRAM at power on.
+///
+/// Real RAM powers on with semi-random contents, the boot ROM only clears VRAM.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum RamInit {
+    #[default]
+    Zero,
+    /// Pseudo-random bytes, the same seed gives the same contents
+    Random(u64),
+    Pattern(u8),
+}
+
+impl RamInit {
+    /// Fill `ram` starting at `offset` bytes into the sequence, so regions
+    /// filled separately don't repeat each other.
+    pub fn fill(&self, ram: &mut [u8], offset: u64) {
+        match *self {
+            RamInit::Zero => ram.fill(0),
+            RamInit::Pattern(value) => ram.fill(value),
+            RamInit::Random(seed) => {
+                // xorshift64*, the state must not be zero
+                let mut state = (seed ^ offset.wrapping_mul(0x9E37_79B9_7F4A_7C15)) | 1;
+
+                for byte in ram {
+                    state ^= state >> 12;
+                    state ^= state << 25;
+                    state ^= state >> 27;
+                    *byte = (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// State of the machine when the boot ROM hands over to the cartridge at 0x0100.
+///
+/// IO registers are set by replaying the last value the boot ROM wrote to
+/// each of them, so registers with unreadable bits read back as documented in
+/// the Pan Docs. LY and STAT follow from where the PPU starts, line 0 in mode 2.
+pub struct PowerOnState {
+    pub registers: RegisterFile,
+    /// Internal counter, DIV is its upper byte
+    pub div: u16,
+    /// Register writes in the order they are applied, NR52 first since the
+    /// other sound registers ignore writes while the APU is off
+    pub io: &'static [(HardwareRegister, u8)],
+}
+
+/// The DMG boot ROM plays its sound on channel 1, which is still on at 0x0100.
+const DMG_IO: &[(HardwareRegister, u8)] = &[
+    (HardwareRegister::NR52, 0x80),
+    (HardwareRegister::NR11, 0x80),
+    (HardwareRegister::NR12, 0xF3),
+    (HardwareRegister::NR51, 0xF3),
+    (HardwareRegister::NR50, 0x77),
+    (HardwareRegister::NR13, 0xC1),
+    (HardwareRegister::NR14, 0x87),
+    (HardwareRegister::P1_JOYP, 0x00),
+    (HardwareRegister::IF, 0x01),
+    (HardwareRegister::BGP, 0xFC),
+    (HardwareRegister::LCDC, 0x91),
+];
+
+/// The SGB boot ROM is silent, the sound registers are set up but no channel is on.
+const SGB_IO: &[(HardwareRegister, u8)] = &[
+    (HardwareRegister::NR52, 0x80),
+    (HardwareRegister::NR11, 0x80),
+    (HardwareRegister::NR12, 0xF3),
+    (HardwareRegister::NR51, 0xF3),
+    (HardwareRegister::NR50, 0x77),
+    (HardwareRegister::P1_JOYP, 0x00),
+    (HardwareRegister::IF, 0x01),
+    (HardwareRegister::BGP, 0xFC),
+    (HardwareRegister::LCDC, 0x91),
+];
+
+/// Size of the DMG boot ROM, mapped over 0x0000 - 0x00FF until it writes 0xFF50.
+pub const BOOT_ROM_SIZE: usize = 0x100;
+
+impl PowerOnState {
+    /// Registers when a boot ROM starts. Real hardware powers on with random
+    /// values, the boot ROM sets every one it uses.
+    pub fn boot_registers() -> RegisterFile {
+        RegisterFile {
+            a: 0,
+            f: Flags::empty(),
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            pc: 0,
+            sp: 0,
+        }
+    }
+
+    pub fn for_model(model: Model) -> Self {
+        let dmg = RegisterFile::new();
+        let sgb = RegisterFile {
+            a: 0x01,
+            f: Flags::empty(),
+            b: 0x00,
+            c: 0x14,
+            d: 0x00,
+            e: 0x00,
+            h: 0xC0,
+            l: 0x60,
+            ..RegisterFile::new()
+        };
+
+        match model {
+            Model::Dmg0 => PowerOnState {
+                registers: RegisterFile {
+                    a: 0x01,
+                    f: Flags::empty(),
+                    b: 0xFF,
+                    c: 0x13,
+                    d: 0x00,
+                    e: 0xC1,
+                    h: 0x84,
+                    l: 0x03,
+                    ..RegisterFile::new()
+                },
+                div: 0x1830,
+                io: DMG_IO,
+            },
+            // 0xABCC in the docs, the boot ROM timing here lands a few dots later
+            Model::Dmg => PowerOnState {
+                registers: dmg,
+                div: 0xAC00,
+                io: DMG_IO,
+            },
+            Model::Mgb => PowerOnState {
+                registers: RegisterFile { a: 0xFF, ..dmg },
+                div: 0xAC00,
+                io: DMG_IO,
+            },
+            // The SGB boot ROM waits for the SNES, DIV isn't fixed at hand over
+            Model::Sgb => PowerOnState {
+                registers: sgb,
+                div: 0x0000,
+                io: SGB_IO,
+            },
+            Model::Sgb2 => PowerOnState {
+                registers: RegisterFile { a: 0xFF, ..sgb },
+                div: 0x0000,
+                io: SGB_IO,
+            },
+        }
+    }
+}
@@ -0,0 +1,1170 @@
+use bitflags::bitflags;
+use core::fmt;
+use core::mem;
+
+use crate::bus::HardwareRegister;
+use crate::interrupts::InterruptFlag;
+use crate::lcd::{LcdControl, LcdStatus};
+use crate::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+use crate::vram::{TILE_COUNT, TileSet};
+
+use super::frame::{Frame, Layer, Palette};
+use super::interrupts::InterruptRequest;
+use super::lcd::{LCD, LcdMode};
+use super::power::Quirks;
+
+bitflags!(
+/// Priority: 0 = No, 1 = BG and Window color indices 1–3 are drawn over this OBJ
+/// Y flip: 0 = Normal, 1 = Entire OBJ is vertically mirrored
+/// X flip: 0 = Normal, 1 = Entire OBJ is horizontally mirrored
+/// DMG palette [Non CGB Mode only]: 0 = OBP0, 1 = OBP1
+/// Bank [CGB Mode Only]: 0 = Fetch tile from VRAM bank 0, 1 = Fetch tile from VRAM bank 1
+/// CGB palette [CGB Mode Only]: Which of OBP0–7 to use
+    #[derive(Clone, Copy, Debug)]
+    pub struct SpriteFlags: u8 {
+        const PRIORITY = 0b1000_0000;
+        const Y_FLIP = 0b0100_0000;
+        const X_FLIP = 0b0010_0000;
+        const DMG_PALETTE = 0b0001_0000;
+        const BANK = 0b0000_1000;
+        const CGB_PALETTE2 = 0b0000_0100;
+        const CGB_PALETTE1 = 0b0000_0010;
+        const CGB_PALETTE0 = 0b0000_0001;
+    }
+);
+
+bitflags!(
+    /// Layers of the picture, hiding one only changes what is drawn, not LCDC.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Layers: u8 {
+        const BACKGROUND = 0b001;
+        const WINDOW = 0b010;
+        const SPRITES = 0b100;
+    }
+);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum FetchState {
+    Tile,
+    DataLow,
+    DataHigh,
+    Idle,
+    Push,
+}
+
+/// Ring buffer of pixels in the `Frame` format, the fetcher only adds 8 more
+/// while 8 or fewer are waiting so 16 always fit.
+#[derive(Default)]
+struct PixelQueue {
+    pixels: [u8; 16],
+    head: usize,
+    len: usize,
+}
+
+impl PixelQueue {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push_back(&mut self, pixel: u8) {
+        debug_assert!(self.len < self.pixels.len());
+        self.pixels[(self.head + self.len) % self.pixels.len()] = pixel;
+        self.len += 1;
+    }
+
+    fn pop_front(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let pixel = self.pixels[self.head];
+        self.head = (self.head + 1) % self.pixels.len();
+        self.len -= 1;
+        Some(pixel)
+    }
+
+    fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len).map(|i| self.pixels[(self.head + i) % self.pixels.len()])
+    }
+}
+
+/// OAM indices of sprites, the hardware never looks at more than `N` at once.
+#[derive(Copy, Clone)]
+struct SpriteIndices<const N: usize> {
+    indices: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> SpriteIndices<N> {
+    fn new() -> Self {
+        SpriteIndices {
+            indices: [0; N],
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.indices[..self.len]
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Add `index` at the end, ignored when the list is full.
+    fn push(&mut self, index: u8) {
+        if !self.is_full() {
+            self.indices[self.len] = index;
+            self.len += 1;
+        }
+    }
+}
+
+struct PixelFifo {
+    fetch_state: FetchState,
+    fifo: PixelQueue,
+    line_x: u8,
+    pushed_x: u8,
+    fetch_x: u8,
+    bgw_fetch_data: [u8; 3],
+    fetch_entry_data: [u8; 6], // OAM data
+    map_y: u8,
+    map_x: u8,
+    tile_y: u8,
+    fifo_x: u8,
+    // The fetched tile is from the window rather than the background
+    window_tile: bool,
+}
+
+impl PixelFifo {
+    pub fn new() -> Self {
+        PixelFifo {
+            fetch_state: FetchState::Tile,
+            fifo: PixelQueue::default(),
+            line_x: 0,
+            pushed_x: 0,
+            fetch_x: 0,
+            bgw_fetch_data: [0; 3],
+            fetch_entry_data: [0; 6],
+            map_y: 0,
+            map_x: 0,
+            tile_y: 0,
+            fifo_x: 0,
+            window_tile: false,
+        }
+    }
+}
+
+/// PPU (Pixel Processing Unit)
+///
+/// OAM (Object Attribute Memory) RAM stores sprite information.
+/// It holds 40 sprites in total, 4 bytes each.
+///
+/// DMG has 8KB (0x2000) of VRAM (Video RAM) located at 0x8000–0x9FFF.
+///
+/// Breakdown of VRAM Usage:
+/// 1. Tile Data (0x8000–0x97FF):
+///     * Stores graphical data for tiles used in backgrounds and sprites.
+///     * Each tile is 8x8 pixels, with 2 bits per pixel for color, 16 bytes in total.
+/// 2. Tile Maps (0x9800–0x9BFF and 0x9C00–0x9FFF):
+///     * Stores the arrangement of tiles for the background.
+///     * Two separate tile maps are available, allowing for different layouts.
+const OAM_SIZE: usize = 0xA0;
+const VRAM_SIZE: usize = 0x2000;
+pub(crate) const LINES_PER_FRAME: u32 = 154;
+const TICKS_PER_LINE: u32 = 456;
+// Mode 3 length without sprites or a scrolled background, used by the line renderer
+const XFER_TICKS: u32 = 172;
+pub const YRES: usize = 144;
+pub const XRES: usize = 160;
+/// Sprites the hardware selects per line, see `PPU::set_sprite_limit`.
+pub const SPRITES_PER_LINE: usize = 10;
+
+/// Transitions of the PPU a debugger can run to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PpuEvent {
+    /// Mode 1 begins and VBlank is requested
+    VBlank,
+    /// Mode 2 begins on a visible line, LY is the line
+    LineStart(u8),
+}
+
+impl PpuEvent {
+    /// The event the PPU entering `mode` on line `ly` is, if any.
+    pub fn of_transition(mode: LcdMode, ly: u8) -> Option<Self> {
+        match mode {
+            LcdMode::VBLANK => Some(PpuEvent::VBlank),
+            LcdMode::OAM => Some(PpuEvent::LineStart(ly)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PpuEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PpuEvent::VBlank => write!(f, "VBlank"),
+            PpuEvent::LineStart(ly) => write!(f, "line {ly}"),
+        }
+    }
+}
+
+// window_line window line to draw
+pub struct PPU {
+    oam_ram: [Sprite; OAM_SIZE / 4],
+    vram: [u8; VRAM_SIZE], // 8KB
+    lcd: LCD,
+    current_frame: u32,
+    line_ticks: u32,
+    frame: Frame,
+    pixel_fifo: PixelFifo,
+    // Sorted by X, the first of equal ones earlier in OAM
+    line_sprites: SpriteIndices<40>,
+    // Sprites selected per line, `SPRITES_PER_LINE` unless raised
+    sprite_limit: usize,
+    fetched_entries: SpriteIndices<3>,
+    window_line: u8,
+    // WY matched LY at the start of a line this frame, the window can only
+    // be drawn from then on
+    wy_triggered: bool,
+    // Draw with the pixel FIFO instead of a whole line at once
+    fifo_renderer: bool,
+    visible_layers: Layers,
+    // Tiles written since the last `take_dirty_tiles`
+    dirty_tiles: TileSet,
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        let mut lcd = LCD::new();
+        lcd.set_mode(LcdMode::OAM);
+
+        PPU {
+            oam_ram: core::array::from_fn(|_| Sprite::new()),
+            vram: [0; VRAM_SIZE],
+            lcd,
+            current_frame: 0,
+            line_ticks: 0,
+            frame: Frame::new(),
+            pixel_fifo: PixelFifo::new(),
+            line_sprites: SpriteIndices::new(),
+            sprite_limit: SPRITES_PER_LINE,
+            fetched_entries: SpriteIndices::new(),
+            window_line: 0,
+            wy_triggered: false,
+            fifo_renderer: true,
+            visible_layers: Layers::all(),
+            dirty_tiles: TileSet::all(),
+        }
+    }
+
+    pub fn get_current_frame(&self) -> u32 {
+        self.current_frame
+    }
+
+    /// Scanline and dot within it being drawn, for `EmuClock`.
+    pub fn position(&self) -> (u8, u16) {
+        (self.lcd.ly, self.line_ticks as u16)
+    }
+
+    /// Dots until `get_current_frame` changes, at the start of VBlank.
+    pub fn dots_until_frame(&self) -> u64 {
+        let ly = self.lcd.ly as u32;
+        let lines = if ly < YRES as u32 {
+            YRES as u32 - ly
+        } else {
+            LINES_PER_FRAME - ly + YRES as u32
+        };
+
+        (lines * TICKS_PER_LINE)
+            .saturating_sub(self.line_ticks)
+            .max(1) as u64
+    }
+
+    pub fn oam_read(&self, address: u16) -> u8 {
+        // Both ranges are valid, one is for DMA
+        let oam_address = if address >= 0xFE00 {
+            (address - 0xFE00) as usize
+        } else {
+            address as usize
+        };
+
+        let sprite_index = oam_address / 4;
+        let sprite_field = oam_address % 4;
+        let sprite = &self.oam_ram[sprite_index];
+
+        match sprite_field {
+            0 => sprite.y,
+            1 => sprite.x,
+            2 => sprite.tile_index,
+            3 => sprite.flags.bits(),
+            _ => unreachable!("sprite_field is oam_address % 4"),
+        }
+    }
+
+    pub fn oam_write(&mut self, address: u16, value: u8) {
+        let oam_address = if address >= 0xFE00 {
+            (address - 0xFE00) as usize
+        } else {
+            address as usize
+        };
+
+        let sprite_index = oam_address / 4;
+        let sprite_field = oam_address % 4;
+        let sprite = &mut self.oam_ram[sprite_index];
+
+        match sprite_field {
+            0 => sprite.y = value,
+            1 => sprite.x = value,
+            2 => sprite.tile_index = value,
+            3 => sprite.flags = SpriteFlags::from_bits_truncate(value),
+            _ => unreachable!("sprite_field is oam_address % 4"),
+        };
+    }
+
+    pub fn vram_read(&self, address: u16) -> u8 {
+        let vram_address = (address - 0x8000) as usize;
+        self.vram[vram_address]
+    }
+
+    pub fn vram_write(&mut self, address: u16, value: u8) {
+        let vram_address = (address - 0x8000) as usize;
+        self.vram[vram_address] = value;
+
+        if vram_address < TILE_COUNT * 16 {
+            self.dirty_tiles.insert(vram_address / 16);
+        }
+    }
+
+    /// Tiles written since the last call, e.g. to highlight graphics a game
+    /// streams in. All tiles count as written after a reset or loading a state.
+    pub fn take_dirty_tiles(&mut self) -> TileSet {
+        mem::take(&mut self.dirty_tiles)
+    }
+
+    pub fn lcd_read(&self, register: HardwareRegister) -> u8 {
+        self.lcd.read(register)
+    }
+
+    pub fn lcd_write<I: InterruptRequest>(
+        &mut self,
+        register: HardwareRegister,
+        value: u8,
+        ctx: &mut I,
+    ) {
+        let lyc_matched = self.lcd.ly == self.lcd.lyc;
+        self.lcd.write(register, value, ctx);
+
+        // A new LYC is compared right away, the interrupt fires when it
+        // makes the condition true
+        if register == HardwareRegister::LYC && !lyc_matched {
+            self.compare_ly(ctx);
+        }
+    }
+
+    /// Count a blank frame while the CPU is in STOP mode, the PPU itself
+    /// doesn't run so LY and STAT stay as they were.
+    pub fn blank_frame(&mut self) {
+        self.frame.clear();
+        self.current_frame += 1;
+    }
+
+    /// The picture being drawn, complete once `get_current_frame` changes.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// Colors the frame is presented with, takes effect immediately.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.frame.set_palette(palette);
+    }
+
+    /// Hardware bugs of the LCD registers to emulate.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.lcd.set_quirks(quirks);
+    }
+
+    /// Draw with the pixel FIFO so writes during mode 3 show up mid-line,
+    /// otherwise each line is drawn at once when mode 3 starts, which is faster.
+    pub fn set_fifo_renderer(&mut self, enabled: bool) {
+        self.fifo_renderer = enabled;
+    }
+
+    /// Select up to `limit` sprites per line instead of the 10 of the
+    /// hardware, 40 or more selects every sprite on the line. Not accurate:
+    /// it takes away the flicker of games that cycle their sprites, but
+    /// games also hide sprites on purpose with the limit.
+    pub fn set_sprite_limit(&mut self, limit: usize) {
+        self.sprite_limit = limit.clamp(1, OAM_SIZE / 4);
+    }
+
+    pub fn sprite_limit(&self) -> usize {
+        self.sprite_limit
+    }
+
+    /// Hide layers from the picture, e.g. to see what the background draws
+    /// under the sprites. Hidden background and window pixels use color 0.
+    pub fn set_visible_layers(&mut self, layers: Layers) {
+        self.visible_layers = layers;
+    }
+
+    pub fn visible_layers(&self) -> Layers {
+        self.visible_layers
+    }
+
+    pub fn mode(&self) -> LcdMode {
+        self.lcd.get_mode()
+    }
+
+    /// Advance the PPU by one dot (T-cycle).
+    pub fn tick<I: InterruptRequest>(&mut self, ctx: &mut I) {
+        self.count_dots(1);
+        self.update(ctx);
+    }
+
+    /// Count `dots` dots, `update` has to follow on the dot
+    /// `dots_until_update` points at and can't be skipped over.
+    pub fn count_dots(&mut self, dots: u64) {
+        self.line_ticks += dots as u32;
+    }
+
+    /// Dots until `update` has more to do than wait, at least 1. Every dot
+    /// of mode 3 with the pixel FIFO, otherwise the sprite search at the
+    /// start of a line and the ends of the modes.
+    pub fn dots_until_update(&self) -> u64 {
+        let at = match self.lcd.get_mode() {
+            LcdMode::OAM if self.line_ticks < 1 => 1,
+            LcdMode::OAM => 80,
+            LcdMode::XFER if self.fifo_renderer => return 1,
+            LcdMode::XFER => 80 + XFER_TICKS,
+            LcdMode::VBLANK | LcdMode::HBLANK => TICKS_PER_LINE,
+        };
+
+        at.saturating_sub(self.line_ticks).max(1) as u64
+    }
+
+    /// Switch modes and lines, or draw a dot with the pixel FIFO, after
+    /// `count_dots`.
+    pub fn update<I: InterruptRequest>(&mut self, ctx: &mut I) {
+        let lcd_mode = self.lcd.get_mode();
+
+        match lcd_mode {
+            LcdMode::OAM => self.tick_oam(),
+            LcdMode::XFER => self.tick_xfer(ctx),
+            LcdMode::VBLANK => self.tick_vblank(ctx),
+            LcdMode::HBLANK => self.tick_hblank(ctx),
+        }
+    }
+
+    fn load_line_sprites(&mut self) {
+        let ly = self.lcd.ly;
+        let sprite_height = self.lcd.get_sprite_height();
+
+        for (index, sprite) in self.oam_ram.iter().enumerate() {
+            if sprite.x == 0 {
+                // Not visible
+                continue;
+            }
+
+            if self.line_sprites.len() >= self.sprite_limit {
+                // Max 10 sprites per line, more only with the inaccurate
+                // raised limit
+                break;
+            }
+
+            if sprite.y <= (ly + 16) && (sprite.y + sprite_height) > (ly + 16) {
+                // This sprite is on the current line
+                self.line_sprites.push(index as u8);
+            }
+        }
+
+        // A lower X wins, between equal ones the first in OAM
+        let oam = &self.oam_ram;
+        let len = self.line_sprites.len;
+        self.line_sprites.indices[..len].sort_unstable_by_key(|&i| (oam[i as usize].x, i));
+    }
+
+    fn tick_oam(&mut self) {
+        if self.line_ticks >= 80 {
+            self.lcd.set_mode(LcdMode::XFER);
+
+            if !self.fifo_renderer {
+                self.render_line();
+            }
+
+            self.pixel_fifo.fetch_state = FetchState::Tile;
+            self.pixel_fifo.line_x = 0;
+            self.pixel_fifo.fetch_x = 0;
+            self.pixel_fifo.pushed_x = 0;
+            self.pixel_fifo.fifo_x = 0;
+        }
+
+        if self.line_ticks == 1 {
+            // Read all sprites on the first tick, not as in hardware
+            self.line_sprites.clear();
+            self.load_line_sprites();
+
+            // Only compared at the start of the line, a WY written later
+            // in the line has to wait for the next one
+            if self.lcd.ly == self.lcd.win_y {
+                self.wy_triggered = true;
+            }
+        }
+    }
+
+    fn tick_xfer<I: InterruptRequest>(&mut self, ctx: &mut I) {
+        let line_done = if self.fifo_renderer {
+            self.pipeline_process();
+            (self.pixel_fifo.pushed_x as usize) >= XRES
+        } else {
+            self.line_ticks >= 80 + XFER_TICKS
+        };
+
+        if line_done {
+            self.pixel_fifo.fifo.clear(); // Reset pixel FIFO
+
+            self.lcd.set_mode(LcdMode::HBLANK);
+
+            if self.lcd.lcds.contains(LcdStatus::HBLANK_INT_SELECT) {
+                ctx.request_interrupt(InterruptFlag::LCD);
+            }
+        }
+    }
+
+    fn tick_vblank<I: InterruptRequest>(&mut self, ctx: &mut I) {
+        if self.line_ticks >= TICKS_PER_LINE {
+            self.increment_ly(ctx);
+
+            if self.lcd.ly == 0 {
+                self.lcd.set_mode(LcdMode::OAM);
+                self.window_line = 0;
+                self.wy_triggered = false;
+            }
+
+            self.line_ticks = 0;
+        }
+    }
+
+    fn tick_hblank<I: InterruptRequest>(&mut self, ctx: &mut I) {
+        if self.line_ticks >= TICKS_PER_LINE {
+            self.increment_ly(ctx);
+
+            if (self.lcd.ly as usize) >= YRES {
+                self.lcd.set_mode(LcdMode::VBLANK);
+
+                ctx.request_interrupt(InterruptFlag::VBLANK);
+
+                if self.lcd.lcds.contains(LcdStatus::VBLANK_INT_SELECT) {
+                    ctx.request_interrupt(InterruptFlag::LCD);
+                }
+
+                self.current_frame += 1;
+            } else {
+                self.lcd.set_mode(LcdMode::OAM);
+            }
+
+            self.line_ticks = 0;
+        }
+    }
+
+    /// Draw the whole current line from the registers as they are now.
+    fn render_line(&mut self) {
+        let ly = self.lcd.ly;
+        let bg_enabled = self.lcd.lcdc.contains(LcdControl::BG_WINDOW_ENABLE);
+        let window = self.window_active() && self.visible_layers.contains(Layers::WINDOW);
+        let background = self.visible_layers.contains(Layers::BACKGROUND);
+        let mut bg_indices = [0u8; XRES];
+
+        for (x, bg_index) in bg_indices.iter_mut().enumerate() {
+            let in_window = window && (x + 7) >= self.lcd.win_x as usize;
+            let (map_area, map_x, map_y) = if in_window {
+                let map_x = (x + 7 - self.lcd.win_x as usize) as u8;
+                (self.lcd.get_win_map_area(), map_x, self.window_line)
+            } else {
+                let map_x = (x as u8).wrapping_add(self.lcd.scroll_x);
+                (
+                    self.lcd.get_bg_map_area(),
+                    map_x,
+                    ly.wrapping_add(self.lcd.scroll_y),
+                )
+            };
+
+            if bg_enabled {
+                let mut tile_index =
+                    self.vram_read(map_area + (map_x as u16) / 8 + ((map_y as u16) / 8) * 32);
+
+                if self.lcd.get_bgw_data_area() == 0x8800 {
+                    // Signed tile indices, -128 is the first tile
+                    tile_index = tile_index.wrapping_add(128);
+                }
+
+                let address = self.lcd.get_bgw_data_area()
+                    + (tile_index as u16) * 16
+                    + ((map_y % 8) as u16) * 2;
+                *bg_index = Self::color_index(
+                    self.vram_read(address),
+                    self.vram_read(address + 1),
+                    7 - map_x % 8,
+                );
+            }
+
+            let shown_index = if in_window || background {
+                *bg_index
+            } else {
+                0
+            };
+            let pixel = Frame::pack_background(
+                self.lcd.bg_shades[shown_index as usize],
+                shown_index,
+                in_window && bg_enabled,
+            );
+            self.frame.set_pixel(x + (ly as usize) * XRES, pixel);
+        }
+
+        if !self.lcd.lcdc.contains(LcdControl::OBJ_ENABLE)
+            || !self.visible_layers.contains(Layers::SPRITES)
+        {
+            return;
+        }
+
+        let sprite_height = self.lcd.get_sprite_height();
+        let mut drawn = [false; XRES];
+
+        // Sprites with a lower X, then earlier in OAM, are drawn on top
+        let line_sprites = self.line_sprites;
+
+        for &index in line_sprites.as_slice() {
+            let sprite = self.oam_ram[index as usize];
+            let mut row = ly.wrapping_add(16).wrapping_sub(sprite.y);
+
+            if sprite.flags.contains(SpriteFlags::Y_FLIP) {
+                row = sprite_height - 1 - row;
+            }
+
+            let mut tile_index = sprite.tile_index as u16;
+
+            if sprite_height == 16 {
+                tile_index &= !1;
+            }
+
+            let address = 0x8000 + tile_index * 16 + (row as u16) * 2;
+            let (lo, hi) = (self.vram_read(address), self.vram_read(address + 1));
+
+            for offset in 0..8u8 {
+                let x = sprite.x as usize + offset as usize;
+
+                if !(8..XRES + 8).contains(&x) || drawn[x - 8] {
+                    continue;
+                }
+
+                let bit = if sprite.flags.contains(SpriteFlags::X_FLIP) {
+                    offset
+                } else {
+                    7 - offset
+                };
+                let color_index = Self::color_index(lo, hi, bit);
+
+                if color_index == 0 {
+                    // Transparent
+                    continue;
+                }
+
+                drawn[x - 8] = true;
+
+                if sprite.flags.contains(SpriteFlags::PRIORITY) && bg_indices[x - 8] != 0 {
+                    continue;
+                }
+
+                let pixel = if sprite.flags.contains(SpriteFlags::DMG_PALETTE) {
+                    Frame::pack(self.lcd.sp1_shades[color_index as usize], Layer::Object1)
+                } else {
+                    Frame::pack(self.lcd.sp0_shades[color_index as usize], Layer::Object0)
+                };
+                self.frame.set_pixel(x - 8 + (ly as usize) * XRES, pixel);
+            }
+        }
+    }
+
+    /// 2-bit color of pixel `bit` of a tile row stored as its low and high bytes.
+    fn color_index(lo: u8, hi: u8, bit: u8) -> u8 {
+        (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1)
+    }
+
+    fn pipeline_process(&mut self) {
+        // The background map wraps around at 256 pixels
+        self.pixel_fifo.map_y = self.lcd.ly.wrapping_add(self.lcd.scroll_y);
+        self.pixel_fifo.map_x = self.pixel_fifo.fetch_x.wrapping_add(self.lcd.scroll_x);
+        self.pixel_fifo.tile_y = (self.pixel_fifo.map_y % 8) * 2;
+
+        if (self.line_ticks & 1) == 0 {
+            // Even line
+            self.pipeline_fetch();
+        }
+
+        self.pipeline_push_pixel();
+    }
+
+    fn pipeline_load_sprite_tile(&mut self) {
+        for &index in self.line_sprites.as_slice() {
+            let entry = &self.oam_ram[index as usize];
+            // Sprites with X < 8 are partly off the left edge
+            let sp_x = entry.x.wrapping_sub(8).wrapping_add(self.lcd.scroll_x % 8);
+
+            if (sp_x >= self.pixel_fifo.fetch_x && sp_x < (self.pixel_fifo.fetch_x + 8))
+                || (sp_x.wrapping_add(8) >= self.pixel_fifo.fetch_x
+                    && sp_x.wrapping_add(8) < (self.pixel_fifo.fetch_x + 8))
+            {
+                self.fetched_entries.push(index);
+            }
+
+            if self.fetched_entries.is_full() {
+                // Max checking 3 sprites per pixel
+                break;
+            }
+        }
+    }
+
+    fn pipeline_load_sprite_data(&mut self, offset: usize) {
+        let ly = self.lcd.ly;
+        let sprite_height = self.lcd.get_sprite_height();
+
+        for i in 0..self.fetched_entries.len() {
+            let entry = &self.oam_ram[self.fetched_entries.as_slice()[i] as usize];
+            let mut ty = ((ly + 16) - entry.y) * 2;
+
+            if entry.flags.contains(SpriteFlags::Y_FLIP) {
+                ty = (2 * sprite_height - 2) - ty;
+            }
+
+            let mut tile_index = entry.tile_index as u16;
+
+            if sprite_height == 16 {
+                tile_index &= !1; // Remove last bit
+            }
+
+            let address = 0x8000 + (tile_index * 16) + (ty as u16) + (offset as u16);
+
+            self.pixel_fifo.fetch_entry_data[(i * 2) + offset] = self.vram_read(address);
+        }
+    }
+
+    fn pipeline_load_window_tile(&mut self) {
+        if !self.window_active() || !self.visible_layers.contains(Layers::WINDOW) {
+            return;
+        }
+
+        if (self.pixel_fifo.fetch_x + 7) >= self.lcd.win_x
+            && (self.pixel_fifo.fetch_x + 7) < self.lcd.win_x.wrapping_add(YRES as u8 + 14)
+        {
+            self.pixel_fifo.window_tile = true;
+            let window_tile_y = (self.window_line as u16) / 8;
+            let address = self.lcd.get_win_map_area()
+                + (((self.pixel_fifo.fetch_x + 7 - self.lcd.win_x) / 8) as u16)
+                + (window_tile_y * 32);
+            self.pixel_fifo.bgw_fetch_data[0] = self.vram_read(address);
+
+            if self.lcd.get_bgw_data_area() == 0x8800 {
+                // Load from the second tile set data
+                // Here we convert from negative to positive indices, -128 is 0
+                self.pixel_fifo.bgw_fetch_data[0] =
+                    self.pixel_fifo.bgw_fetch_data[0].wrapping_add(128);
+            }
+        }
+    }
+
+    fn pipeline_fetch(&mut self) {
+        match self.pixel_fifo.fetch_state {
+            FetchState::Tile => {
+                self.fetched_entries.clear();
+                self.pixel_fifo.window_tile = false;
+
+                if self.lcd.lcdc.contains(LcdControl::BG_WINDOW_ENABLE) {
+                    let address = self.lcd.get_bg_map_area()
+                        + ((self.pixel_fifo.map_x as u16) / 8)
+                        + (((self.pixel_fifo.map_y as u16) / 8) * 32);
+                    self.pixel_fifo.bgw_fetch_data[0] = self.vram_read(address);
+
+                    if self.lcd.get_bgw_data_area() == 0x8800 {
+                        // Load from the second tile set data
+                        // Here we convert from negative to positive indices, -128 is 0
+                        self.pixel_fifo.bgw_fetch_data[0] =
+                            self.pixel_fifo.bgw_fetch_data[0].wrapping_add(128);
+                    }
+
+                    self.pipeline_load_window_tile();
+                }
+
+                if self.lcd.lcdc.contains(LcdControl::OBJ_ENABLE) && !self.line_sprites.is_empty() {
+                    self.pipeline_load_sprite_tile();
+                }
+
+                self.pixel_fifo.fetch_state = FetchState::DataLow;
+                self.pixel_fifo.fetch_x += 8;
+            }
+            FetchState::DataLow => {
+                let address = self.lcd.get_bgw_data_area()
+                    + ((self.pixel_fifo.bgw_fetch_data[0] as u16) * 16)
+                    + (self.pixel_fifo.tile_y as u16);
+                self.pixel_fifo.bgw_fetch_data[1] = self.vram_read(address);
+
+                self.pipeline_load_sprite_data(0);
+
+                self.pixel_fifo.fetch_state = FetchState::DataHigh;
+            }
+            FetchState::DataHigh => {
+                let address = self.lcd.get_bgw_data_area()
+                    + ((self.pixel_fifo.bgw_fetch_data[0] as u16) * 16)
+                    + (self.pixel_fifo.tile_y as u16)
+                    + 1;
+                self.pixel_fifo.bgw_fetch_data[2] = self.vram_read(address);
+
+                self.pipeline_load_sprite_data(1);
+
+                self.pixel_fifo.fetch_state = FetchState::Idle;
+            }
+            FetchState::Idle => {
+                self.pixel_fifo.fetch_state = FetchState::Push;
+            }
+            FetchState::Push => {
+                if self.pipeline_fifo_add() {
+                    self.pixel_fifo.fetch_state = FetchState::Tile;
+                }
+            }
+        }
+    }
+
+    fn pipeline_push_pixel(&mut self) {
+        if self.pixel_fifo.fifo.len() > 8 {
+            // 8 pixels are required for the Pixel Rendering operation to take place
+            let pixel_data = self.pixel_fifo.fifo.pop_front().unwrap();
+
+            if self.pixel_fifo.line_x >= (self.lcd.scroll_x % 8) {
+                let pixel_index =
+                    (self.pixel_fifo.pushed_x as usize) + ((self.lcd.ly as usize) * XRES);
+                self.frame.set_pixel(pixel_index, pixel_data);
+                self.pixel_fifo.pushed_x += 1;
+            }
+
+            self.pixel_fifo.line_x += 1;
+        }
+    }
+
+    fn pipeline_fifo_add(&mut self) -> bool {
+        if self.pixel_fifo.fifo.len() > 8 {
+            // Pixel FIFO is full
+            return false;
+        }
+
+        let x = (self.pixel_fifo.fetch_x as i32) - (8 - ((self.lcd.scroll_x as i32) % 8));
+
+        for i in 0..8 {
+            let bit = 7 - i;
+            let lo = ((self.pixel_fifo.bgw_fetch_data[1] & (1 << bit)) != 0) as u8;
+            let hi = ((self.pixel_fifo.bgw_fetch_data[2] & (1 << bit)) != 0) as u8;
+            let color_index = ((hi << 1) | lo) as usize;
+            let mut color = Frame::pack_background(
+                self.lcd.bg_shades[color_index],
+                color_index as u8,
+                self.pixel_fifo.window_tile,
+            );
+
+            let hidden =
+                !self.pixel_fifo.window_tile && !self.visible_layers.contains(Layers::BACKGROUND);
+
+            if !self.lcd.lcdc.contains(LcdControl::BG_WINDOW_ENABLE) || hidden {
+                color = Frame::pack_background(self.lcd.bg_shades[0], 0, false);
+            }
+
+            if self.lcd.lcdc.contains(LcdControl::OBJ_ENABLE)
+                && self.visible_layers.contains(Layers::SPRITES)
+            {
+                color = self.fetch_sprite_pixels(color_index, color);
+            }
+
+            if x >= 0 {
+                self.pixel_fifo.fifo.push_back(color);
+                self.pixel_fifo.fifo_x += 1;
+            }
+        }
+
+        true
+    }
+
+    fn fetch_sprite_pixels(&self, bg_color_index: usize, default_color: u8) -> u8 {
+        let mut color = default_color;
+        for (i, &index) in self.fetched_entries.as_slice().iter().enumerate() {
+            let entry = &self.oam_ram[index as usize];
+            let sp_x = entry.x.wrapping_sub(8).wrapping_add(self.lcd.scroll_x % 8);
+
+            if sp_x.wrapping_add(8) < self.pixel_fifo.fifo_x {
+                // Passed pixel point already
+                continue;
+            }
+            // TODO: Is wrapping_sub correct?
+            let offset = self.pixel_fifo.fifo_x.wrapping_sub(sp_x);
+
+            if offset > 7 {
+                // Out of bounds
+                continue;
+            }
+
+            let mut bit = 7 - offset;
+
+            if entry.flags.contains(SpriteFlags::X_FLIP) {
+                bit = offset;
+            }
+
+            let lo = ((self.pixel_fifo.fetch_entry_data[i * 2] & (1 << bit)) != 0) as u8;
+            let hi = ((self.pixel_fifo.fetch_entry_data[i * 2 + 1] & (1 << bit)) != 0) as u8;
+            let color_index = ((hi << 1) | lo) as usize;
+            let bg_priority = entry.flags.contains(SpriteFlags::PRIORITY);
+
+            if color_index == 0 {
+                // Transparent
+                continue;
+            }
+
+            if !bg_priority || bg_color_index == 0 {
+                color = if entry.flags.contains(SpriteFlags::DMG_PALETTE) {
+                    Frame::pack(self.lcd.sp1_shades[color_index], Layer::Object1)
+                } else {
+                    Frame::pack(self.lcd.sp0_shades[color_index], Layer::Object0)
+                };
+
+                break;
+            }
+        }
+
+        color
+    }
+
+    /// Whether the window is drawn on the current line: enabled, WX on
+    /// screen and WY matched LY on this or an earlier line of the frame.
+    fn window_active(&self) -> bool {
+        self.wy_triggered && self.lcd.is_window_visible()
+    }
+
+    pub fn increment_ly<I: InterruptRequest>(&mut self, ctx: &mut I) {
+        // The window keeps its own line counter, lines it wasn't drawn on
+        // don't count
+        if self.window_active() && (self.lcd.ly as usize) < YRES {
+            self.window_line += 1;
+        }
+
+        self.lcd.ly += 1;
+        if (self.lcd.ly as u32) >= LINES_PER_FRAME {
+            self.lcd.ly = 0;
+        }
+
+        self.compare_ly(ctx);
+    }
+
+    /// Update the LYC == LY flag and request the STAT interrupt when it's
+    /// selected and LY matches.
+    fn compare_ly<I: InterruptRequest>(&mut self, ctx: &mut I) {
+        if self.lcd.ly == self.lcd.lyc {
+            self.lcd.lcds.insert(LcdStatus::LYC_EQUAL_LY);
+
+            if self.lcd.lcds.contains(LcdStatus::LYC_INT_SELECT) {
+                ctx.request_interrupt(InterruptFlag::LCD);
+            }
+        } else {
+            self.lcd.lcds.remove(LcdStatus::LYC_EQUAL_LY);
+        }
+    }
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        PPU::new()
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Sprite {
+    y: u8,
+    x: u8,
+    tile_index: u8,
+    flags: SpriteFlags,
+}
+
+impl Sprite {
+    pub fn new() -> Self {
+        Sprite {
+            y: 0,
+            x: 0,
+            tile_index: 0,
+            flags: SpriteFlags::empty(),
+        }
+    }
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Sprite::new()
+    }
+}
+
+impl Sprite {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.y);
+        state.write_u8(self.x);
+        state.write_u8(self.tile_index);
+        state.write_u8(self.flags.bits());
+    }
+
+    fn load_state(state: &mut StateReader) -> Result<Self, StateError> {
+        Ok(Sprite {
+            y: state.read_u8()?,
+            x: state.read_u8()?,
+            tile_index: state.read_u8()?,
+            flags: SpriteFlags::from_bits_truncate(state.read_u8()?),
+        })
+    }
+}
+
+fn load_indices<const N: usize>(
+    state: &mut StateReader,
+    indices: &mut SpriteIndices<N>,
+    field: &'static str,
+) -> Result<(), StateError> {
+    let len = state.read_u8()? as usize;
+    if len > N {
+        return Err(StateError::InvalidValue(field));
+    }
+
+    indices.clear();
+    for _ in 0..len {
+        let index = state.read_u8()?;
+        if index as usize >= OAM_SIZE / 4 {
+            return Err(StateError::InvalidValue(field));
+        }
+        indices.push(index);
+    }
+
+    Ok(())
+}
+
+impl Saveable for PixelFifo {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.fetch_state as u8);
+        state.write_u8(self.fifo.len() as u8);
+        for pixel in self.fifo.iter() {
+            state.write_u8(pixel);
+        }
+        state.write_u8(self.line_x);
+        state.write_u8(self.pushed_x);
+        state.write_u8(self.fetch_x);
+        state.write_bytes(&self.bgw_fetch_data);
+        state.write_bytes(&self.fetch_entry_data);
+        state.write_u8(self.map_y);
+        state.write_u8(self.map_x);
+        state.write_u8(self.tile_y);
+        state.write_u8(self.fifo_x);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.fetch_state = match state.read_u8()? {
+            0 => FetchState::Tile,
+            1 => FetchState::DataLow,
+            2 => FetchState::DataHigh,
+            3 => FetchState::Idle,
+            4 => FetchState::Push,
+            _ => return Err(StateError::InvalidValue("pixel fetcher state")),
+        };
+
+        let fifo_len = state.read_u8()?;
+        if fifo_len as usize > self.fifo.pixels.len() {
+            return Err(StateError::InvalidValue("pixel FIFO length"));
+        }
+
+        self.fifo.clear();
+        for _ in 0..fifo_len {
+            self.fifo.push_back(state.read_u8()?);
+        }
+
+        self.line_x = state.read_u8()?;
+        self.pushed_x = state.read_u8()?;
+        self.fetch_x = state.read_u8()?;
+        state.read_into(&mut self.bgw_fetch_data)?;
+        state.read_into(&mut self.fetch_entry_data)?;
+        self.map_y = state.read_u8()?;
+        self.map_x = state.read_u8()?;
+        self.tile_y = state.read_u8()?;
+        self.fifo_x = state.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Resettable for PPU {
+    fn reset(&mut self) {
+        // The presentation palette is a frontend setting
+        let palette = *self.frame.palette();
+        let (fifo_renderer, visible_layers, sprite_limit) =
+            (self.fifo_renderer, self.visible_layers, self.sprite_limit);
+        let quirks = self.lcd.quirks();
+        *self = PPU::new();
+        self.frame.set_palette(palette);
+        self.lcd.set_quirks(quirks);
+        self.fifo_renderer = fifo_renderer;
+        self.visible_layers = visible_layers;
+        self.sprite_limit = sprite_limit;
+    }
+}
+
+impl Saveable for PPU {
+    fn save_state(&self, state: &mut StateWriter) {
+        for sprite in &self.oam_ram {
+            sprite.save_state(state);
+        }
+        state.write_bytes(&self.vram);
+        self.lcd.save_state(state);
+        state.write_u32(self.current_frame);
+        state.write_u32(self.line_ticks);
+        self.pixel_fifo.save_state(state);
+
+        state.write_u8(self.line_sprites.len() as u8);
+        state.write_bytes(self.line_sprites.as_slice());
+        state.write_u8(self.fetched_entries.len() as u8);
+        state.write_bytes(self.fetched_entries.as_slice());
+
+        state.write_u8(self.window_line);
+        state.write_bool(self.wy_triggered);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        for sprite in self.oam_ram.iter_mut() {
+            *sprite = Sprite::load_state(state)?;
+        }
+        state.read_into(&mut self.vram)?;
+        self.lcd.load_state(state)?;
+        self.current_frame = state.read_u32()?;
+        self.line_ticks = state.read_u32()?;
+        self.pixel_fifo.load_state(state)?;
+
+        load_indices(state, &mut self.line_sprites, "sprites on the line")?;
+        load_indices(state, &mut self.fetched_entries, "fetched sprites")?;
+
+        self.window_line = state.read_u8()?;
+        self.wy_triggered = state.read_bool()?;
+        self.dirty_tiles = TileSet::all();
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::json;
+use crate::symbols::SymbolTable;
+
+/// Time spent in one instruction or routine.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct ProfileCount {
+    pub executions: u64,
+    /// T-cycles from the start of the instruction to the start of the next
+    pub cycles: u64,
+    /// Times it was entered by CALL, RST or an interrupt
+    pub calls: u64,
+}
+
+impl ProfileCount {
+    fn add(&mut self, other: &ProfileCount) {
+        self.executions += other.executions;
+        self.cycles += other.cycles;
+        self.calls += other.calls;
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Instruction {
+    bank: u16,
+    pc: u16,
+    opcode: u8,
+    ticks: u64,
+    // An interrupt was dispatched instead of running it
+    interrupted: bool,
+}
+
+/// Executions, cycles and calls per instruction, by bank and address.
+///
+/// The cycles of an instruction are only known once the next one begins, so
+/// the last instruction of a run isn't timed. Waiting in HALT counts to the
+/// HALT, the dispatch of an interrupt to the instruction it came before, as if
+/// it were a CALL there. The instruction before the clock moved back, by a
+/// reset or a loaded state, isn't timed either.
+#[derive(Clone, Default)]
+pub struct Profiler {
+    counts: BTreeMap<(u16, u16), ProfileCount>,
+    current: Option<Instruction>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// An instruction in ROM `bank` (0 outside switchable ROM) begins at
+    /// T-cycle `ticks`.
+    pub(crate) fn record(&mut self, bank: u16, pc: u16, opcode: u8, ticks: u64) {
+        let mut called = false;
+
+        if let Some(last) = self.current.take()
+            && ticks >= last.ticks
+        {
+            self.counts.entry((last.bank, last.pc)).or_default().cycles += ticks - last.ticks;
+            called = last.interrupted
+                || match last.opcode {
+                    // Not taken when it went on to the next instruction
+                    0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC => pc != last.pc.wrapping_add(3),
+                    // RST
+                    opcode => opcode & 0xC7 == 0xC7,
+                };
+        }
+
+        let count = self.counts.entry((bank, pc)).or_default();
+        count.executions += 1;
+        count.calls += called as u64;
+
+        self.current = Some(Instruction {
+            bank,
+            pc,
+            opcode,
+            ticks,
+            interrupted: false,
+        });
+    }
+
+    /// The instruction just recorded didn't run, an interrupt was dispatched.
+    pub(crate) fn interrupt(&mut self) {
+        if let Some(current) = &mut self.current {
+            current.interrupted = true;
+            self.counts
+                .entry((current.bank, current.pc))
+                .or_default()
+                .executions -= 1;
+        }
+    }
+
+    pub fn get(&self, bank: u16, address: u16) -> ProfileCount {
+        self.counts
+            .get(&(bank, address))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// T-cycles of every timed instruction.
+    pub fn total_cycles(&self) -> u64 {
+        self.counts.values().map(|count| count.cycles).sum()
+    }
+
+    /// Counts summed per routine of `symbols`, most cycles first. Instructions
+    /// before the first symbol of their bank, or all without symbols, are
+    /// named by their own location as `BB:AAAA`.
+    pub fn by_symbol(
+        &self,
+        symbols: Option<&SymbolTable>,
+    ) -> Vec<(String, u16, u16, ProfileCount)> {
+        let mut routines: BTreeMap<(u16, u16), (String, ProfileCount)> = BTreeMap::new();
+
+        for (&(bank, address), count) in &self.counts {
+            let (name, start) = match symbols.and_then(|s| s.containing(bank, address)) {
+                Some((name, offset)) => (String::from(name), address - offset),
+                None => (format!("{bank:02X}:{address:04X}"), address),
+            };
+            routines
+                .entry((bank, start))
+                .or_insert_with(|| (name, ProfileCount::default()))
+                .1
+                .add(count);
+        }
+
+        let mut routines: Vec<_> = routines
+            .into_iter()
+            .map(|((bank, address), (name, count))| (name, bank, address, count))
+            .collect();
+        routines.sort_by_key(|(_, bank, address, count)| {
+            (core::cmp::Reverse(count.cycles), *bank, *address)
+        });
+        routines
+    }
+
+    /// The counts of `by_symbol` as JSON for external viewers:
+    /// `{"cycles": total, "symbols": {"Name": {"bank", "address",
+    /// "executions", "cycles", "calls"}}}`, numbers in decimal.
+    pub fn to_json(&self, symbols: Option<&SymbolTable>) -> String {
+        let mut json = format!(
+            "{{\n  \"cycles\": {},\n  \"symbols\": {{",
+            self.total_cycles()
+        );
+
+        for (i, (name, bank, address, count)) in self.by_symbol(symbols).iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}\n    {}: {{\"bank\": {bank}, \"address\": {address}, \"executions\": {}, \
+                 \"cycles\": {}, \"calls\": {}}}",
+                if i == 0 { "" } else { "," },
+                json::string(name),
+                count.executions,
+                count.cycles,
+                count.calls
+            );
+        }
+
+        json.push_str("\n  }\n}\n");
+        json
+    }
+}
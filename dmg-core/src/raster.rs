@@ -0,0 +1,68 @@
+//! Hooks into the PPU as it draws, for embedders.
+
+use crate::bus::HardwareRegister;
+use crate::interrupts::InterruptLine;
+use crate::ppu::PPU;
+
+/// Called at the start of every scanline, see `Emulator::set_scanline_hook`.
+///
+/// Runs on the emulation thread between two dots, so it sees and changes
+/// the LCD registers exactly where a game's LY or STAT interrupt handler
+/// would at the earliest, e.g. for raster effects or to test a game against
+/// a register that changes mid-frame.
+pub trait ScanlineHook: Send {
+    /// LY just became `ly`, 0 - 153, lines from 144 are VBlank.
+    fn on_scanline(&mut self, ly: u8, lcd: &mut LcdRegisters);
+}
+
+impl<F: FnMut(u8, &mut LcdRegisters) + Send> ScanlineHook for F {
+    fn on_scanline(&mut self, ly: u8, lcd: &mut LcdRegisters) {
+        self(ly, lcd)
+    }
+}
+
+/// The LCD registers, LCDC to WX without DMA, as the CPU reads and writes
+/// them. Other registers read as 0xFF and ignore writes.
+pub struct LcdRegisters<'a> {
+    ppu: &'a mut PPU,
+    interrupts: &'a mut InterruptLine,
+}
+
+impl<'a> LcdRegisters<'a> {
+    pub(crate) fn new(ppu: &'a mut PPU, interrupts: &'a mut InterruptLine) -> Self {
+        LcdRegisters { ppu, interrupts }
+    }
+
+    fn is_lcd(register: HardwareRegister) -> bool {
+        matches!(
+            register,
+            HardwareRegister::LCDC
+                | HardwareRegister::STAT
+                | HardwareRegister::SCY
+                | HardwareRegister::SCX
+                | HardwareRegister::LY
+                | HardwareRegister::LYC
+                | HardwareRegister::BGP
+                | HardwareRegister::OBP0
+                | HardwareRegister::OBP1
+                | HardwareRegister::WY
+                | HardwareRegister::WX
+        )
+    }
+
+    pub fn read(&self, register: HardwareRegister) -> u8 {
+        if Self::is_lcd(register) {
+            self.ppu.lcd_read(register)
+        } else {
+            0xFF
+        }
+    }
+
+    /// Write like the CPU does, a STAT or LYC write can request the STAT
+    /// interrupt.
+    pub fn write(&mut self, register: HardwareRegister, value: u8) {
+        if Self::is_lcd(register) {
+            self.ppu.lcd_write(register, value, self.interrupts);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::compress::{compress, decompress};
+
+/// Machine states of the last moments of play, taken back newest first.
+///
+/// Only the newest state is kept whole, every older one is stored as the
+/// compressed XOR of it and the state after it. States a frame apart differ
+/// in few bytes, so a delta takes a fraction of a compressed state and the
+/// same memory holds a much longer history.
+pub struct RewindBuffer {
+    newest: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+    /// Bytes held by `deltas`
+    delta_bytes: usize,
+    budget: usize,
+}
+
+impl RewindBuffer {
+    /// Buffer dropping its oldest states once they take more than `budget` bytes.
+    pub fn new(budget: usize) -> Self {
+        RewindBuffer {
+            newest: Vec::new(),
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            budget,
+        }
+    }
+
+    /// Add a state from `state::capture_machine`.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if !self.newest.is_empty() && self.newest.len() == state.len() {
+            let delta = compress(&xor(&self.newest, &state));
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        } else {
+            // Another cartridge, older states can't be rebuilt from this one
+            self.deltas.clear();
+            self.delta_bytes = 0;
+        }
+
+        self.newest = state;
+
+        while self.memory_used() > self.budget
+            && let Some(oldest) = self.deltas.pop_front()
+        {
+            self.delta_bytes -= oldest.len();
+        }
+    }
+
+    /// Take out the newest state.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        if self.newest.is_empty() {
+            return None;
+        }
+
+        let previous = match self.deltas.pop_back() {
+            Some(delta) => {
+                self.delta_bytes -= delta.len();
+                let delta = decompress(&delta, self.newest.len()).expect("rewind delta is valid");
+                xor(&self.newest, &delta)
+            }
+            None => Vec::new(),
+        };
+
+        Some(mem::replace(&mut self.newest, previous))
+    }
+
+    pub fn len(&self) -> usize {
+        if self.newest.is_empty() {
+            0
+        } else {
+            self.deltas.len() + 1
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest.clear();
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+
+    /// Bytes taken by the stored states.
+    pub fn memory_used(&self) -> usize {
+        self.newest.len() + self.delta_bytes
+    }
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
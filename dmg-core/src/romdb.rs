@@ -0,0 +1,163 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::hash::{crc32, sha1};
+
+/// Hashes identifying a ROM dump.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RomHashes {
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+}
+
+impl RomHashes {
+    pub fn new(rom: &[u8]) -> Self {
+        RomHashes {
+            size: rom.len(),
+            crc32: crc32(rom),
+            sha1: sha1(rom),
+        }
+    }
+}
+
+/// A dump listed in a ROM database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomEntry {
+    /// Verified game name, e.g. `Tetris (World) (Rev 1)`
+    pub name: String,
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: Option<[u8; 20]>,
+    /// Listed as a known bad dump
+    pub bad_dump: bool,
+}
+
+/// Result of looking a ROM up in a database.
+#[derive(Debug, PartialEq)]
+pub enum RomIdentity<'a> {
+    Verified(&'a RomEntry),
+    /// Matches a known dump that is listed as bad
+    BadDump(&'a RomEntry),
+    /// Matches a known dump once the data past the header ROM size is cut off
+    Overdump(&'a RomEntry),
+    Unknown,
+}
+
+/// ROM database read from a No-Intro style (Logiqx XML) DAT file.
+#[derive(Default)]
+pub struct RomDatabase {
+    entries: Vec<RomEntry>,
+}
+
+impl RomDatabase {
+    /// Parse the `<game name="..."><rom size=".." crc=".." sha1=".."/></game>`
+    /// entries of a DAT file, everything else is ignored.
+    pub fn parse_dat(text: &str) -> Self {
+        let mut entries = Vec::new();
+
+        for game in text.split("<game ").skip(1) {
+            let Some(name) = attribute(game, "name") else {
+                continue;
+            };
+
+            for rom in game.split("<rom ").skip(1) {
+                let rom = &rom[..rom.find('>').unwrap_or(rom.len())];
+                let size = attribute(rom, "size").and_then(|s| s.parse().ok());
+                let crc32 = attribute(rom, "crc").and_then(|c| u32::from_str_radix(&c, 16).ok());
+
+                let (Some(size), Some(crc32)) = (size, crc32) else {
+                    continue;
+                };
+
+                entries.push(RomEntry {
+                    name: name.clone(),
+                    size,
+                    crc32,
+                    sha1: attribute(rom, "sha1").and_then(|s| parse_hex(&s)),
+                    bad_dump: attribute(rom, "status").is_some_and(|s| s == "baddump"),
+                });
+            }
+        }
+
+        RomDatabase { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn find(&self, hashes: &RomHashes) -> Option<&RomEntry> {
+        self.entries.iter().find(|entry| {
+            entry.size == hashes.size
+                && entry.crc32 == hashes.crc32
+                && entry.sha1.is_none_or(|sha1| sha1 == hashes.sha1)
+        })
+    }
+
+    /// Identify `rom`, `header_size` is the ROM size declared in its header.
+    pub fn identify(&self, rom: &[u8], header_size: usize) -> RomIdentity<'_> {
+        if let Some(entry) = self.find(&RomHashes::new(rom)) {
+            return if entry.bad_dump {
+                RomIdentity::BadDump(entry)
+            } else {
+                RomIdentity::Verified(entry)
+            };
+        }
+
+        if header_size > 0
+            && rom.len() > header_size
+            && let Some(entry) = self.find(&RomHashes::new(&rom[..header_size]))
+        {
+            return RomIdentity::Overdump(entry);
+        }
+
+        RomIdentity::Unknown
+    }
+}
+
+/// Value of `name="value"` in an XML tag, with the basic entities decoded.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+
+    loop {
+        let start = rest.find(name)?;
+        let before = rest[..start].chars().last();
+        rest = &rest[start + name.len()..];
+
+        // Skip matches inside longer names, e.g. `name` in `filename`
+        if before.is_some_and(|c| !c.is_whitespace()) {
+            continue;
+        }
+
+        if let Some(value) = rest.strip_prefix("=\"") {
+            let value = &value[..value.find('"')?];
+            return Some(
+                value
+                    .replace("&amp;", "&")
+                    .replace("&apos;", "'")
+                    .replace("&quot;", "\"")
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">"),
+            );
+        }
+    }
+}
+
+fn parse_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != N * 2 {
+        return None;
+    }
+
+    let mut bytes = [0; N];
+
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(bytes)
+}
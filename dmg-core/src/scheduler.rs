@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+
+/// Something a component does at a time known in advance.
+///
+/// Events due at the same tick run in the order of the variants, the PPU
+/// before OAM DMA as when every component was ticked each dot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+    /// TIMA counts on a falling edge of the DIV bit selected by TAC
+    TimaIncrement,
+    /// The DIV bit clocking the APU frame sequencer changes
+    DivApuBit,
+    /// The PPU switches modes or lines, or draws a dot with the pixel FIFO
+    PpuUpdate,
+    /// OAM DMA runs an M-cycle, the last one of a transfer isn't followed
+    /// by another
+    DmaCycle,
+}
+
+/// Pending events ordered by the tick they are due at.
+///
+/// Components whose next change can be computed schedule it here instead of
+/// checking for it every dot, so a dot without a due event only compares the
+/// current tick with the earliest one.
+#[derive(Default)]
+pub struct Scheduler {
+    // Latest first, the next due event is at the end
+    events: Vec<(u64, Event)>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    /// Run `event` at tick `at`, replacing an earlier schedule of it.
+    pub fn schedule(&mut self, at: u64, event: Event) {
+        self.cancel(event);
+        let index = self
+            .events
+            .partition_point(|&(due, scheduled)| (due, scheduled) > (at, event));
+        self.events.insert(index, (at, event));
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        self.events.retain(|(_, scheduled)| *scheduled != event);
+    }
+
+    /// Tick the next event is due at, `u64::MAX` when nothing is scheduled.
+    pub fn next_due(&self) -> u64 {
+        self.events.last().map_or(u64::MAX, |(due, _)| *due)
+    }
+
+    /// Take the next event if it is due at `now` or earlier.
+    pub fn pop_due(&mut self, now: u64) -> Option<Event> {
+        if self.next_due() > now {
+            return None;
+        }
+
+        self.events.pop().map(|(_, event)| event)
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
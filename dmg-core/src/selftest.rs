@@ -0,0 +1,282 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::cart::{Cartridge, CartridgeHeader, NINTENDO_LOGO};
+use crate::cpu::CpuContext;
+use crate::headless::Headless;
+
+/// Where the ROM keeps the code copied to HRAM and the data it copies.
+const COPY_ADDRESS: u16 = 0x0200;
+const DMA_ROUTINE_ADDRESS: u16 = 0x0300;
+const OAM_ADDRESS: u16 = 0x0400;
+const TILES_ADDRESS: u16 = 0x0500;
+const MAP_ADDRESS: u16 = 0x0600;
+
+/// Results of the CPU operations, stored from $C000.
+const CPU_RESULTS: [u8; 4] = [
+    0xB0, // Flags of $3C + $C4: Z, H and C
+    0x90, // H of $8FFF + 1
+    0xF0, // SWAP $0F
+    0x83, // DAA after $45 + $38
+];
+const CPU_RESULTS_ADDRESS: u16 = 0xC000;
+/// Set to 1 by the timer interrupt handler.
+const TIMER_FLAG_ADDRESS: u16 = 0xC010;
+/// Set to $AA once the picture is set up.
+const DONE_ADDRESS: u16 = 0xC0FF;
+const DONE: u8 = 0xAA;
+
+/// Frames run, the LCD is turned back on during the first one.
+const FRAMES: u32 = 5;
+
+/// `Frame::hash` of the picture with the DMG palette: a checkerboard of
+/// background tiles with four sprites using both palettes, X flip and
+/// background priority.
+const FRAME_HASH: u64 = 0xAEF8970295FC56A1;
+
+/// A part of the self-test and how it went.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfTestCheck {
+    pub name: &'static str,
+    pub passed: bool,
+    /// What was found, with what was expected when it failed
+    pub detail: String,
+}
+
+/// A 32 KiB ROM checking the basics of the emulator: a few CPU operations,
+/// a timer interrupt, OAM DMA and drawing a frame with the background and
+/// sprites. `run_selftest` runs it and checks what it left behind.
+pub fn selftest_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+
+    #[rustfmt::skip]
+    let timer_handler = [
+        0x3E, 0x01,                             // LD A, $01
+        0xEA, 0x10, 0xC0,                       // LD ($C010), A
+        0xD9,                                   // RETI
+    ];
+    rom[0x50..0x50 + timer_handler.len()].copy_from_slice(&timer_handler);
+    // NOP; JP $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    rom[0x134..0x13C].copy_from_slice(b"SELFTEST");
+
+    let [copy_low, copy_high] = COPY_ADDRESS.to_le_bytes();
+    let [dma_low, dma_high] = DMA_ROUTINE_ADDRESS.to_le_bytes();
+    let [tiles_low, tiles_high] = TILES_ADDRESS.to_le_bytes();
+    let [map_low, map_high] = MAP_ADDRESS.to_le_bytes();
+    let [_, oam_page] = OAM_ADDRESS.to_le_bytes();
+
+    #[rustfmt::skip]
+    let main = [
+        0xF3,                                   // DI
+        0x31, 0xFE, 0xFF,                       // LD SP, $FFFE
+        0xF0, 0x44,                             // LDH A, (LY)
+        0xFE, 0x90,                             // CP 144
+        0x38, 0xFA,                             // JR C, -6, until VBlank
+        0xAF,                                   // XOR A
+        0xE0, 0x40,                             // LDH (LCDC), A, LCD off
+        // CPU
+        0x3E, 0x3C,                             // LD A, $3C
+        0xC6, 0xC4,                             // ADD A, $C4
+        0xF5,                                   // PUSH AF
+        0xC1,                                   // POP BC
+        0x79,                                   // LD A, C
+        0xEA, 0x00, 0xC0,                       // LD ($C000), A
+        0x21, 0xFF, 0x8F,                       // LD HL, $8FFF
+        0x01, 0x01, 0x00,                       // LD BC, $0001
+        0x09,                                   // ADD HL, BC
+        0x7C,                                   // LD A, H
+        0xEA, 0x01, 0xC0,                       // LD ($C001), A
+        0x3E, 0x0F,                             // LD A, $0F
+        0xCB, 0x37,                             // SWAP A
+        0xEA, 0x02, 0xC0,                       // LD ($C002), A
+        0x3E, 0x45,                             // LD A, $45
+        0xC6, 0x38,                             // ADD A, $38
+        0x27,                                   // DAA
+        0xEA, 0x03, 0xC0,                       // LD ($C003), A
+        // Timer interrupt, TIMA overflows after 16 increments
+        0xAF,                                   // XOR A
+        0xEA, 0x10, 0xC0,                       // LD ($C010), A
+        0xE0, 0x06,                             // LDH (TMA), A
+        0x3E, 0xF0,                             // LD A, $F0
+        0xE0, 0x05,                             // LDH (TIMA), A
+        0x3E, 0x04,                             // LD A, Timer
+        0xE0, 0xFF,                             // LDH (IE), A
+        0xAF,                                   // XOR A
+        0xE0, 0x0F,                             // LDH (IF), A
+        0x3E, 0x05,                             // LD A, $05
+        0xE0, 0x07,                             // LDH (TAC), A, 262144 Hz
+        0xFB,                                   // EI
+        0x21, 0x10, 0xC0,                       // LD HL, $C010
+        0x06, 0x00,                             // LD B, 0
+        0x7E,                                   // LD A, (HL)
+        0xB7,                                   // OR A
+        0x20, 0x03,                             // JR NZ, +3, interrupted
+        0x05,                                   // DEC B
+        0x20, 0xF9,                             // JR NZ, -7, up to 256 times
+        0xF3,                                   // DI
+        0xAF,                                   // XOR A
+        0xE0, 0x07,                             // LDH (TAC), A
+        // OAM DMA from HRAM
+        0x21, 0x80, 0xFF,                       // LD HL, $FF80
+        0x11, dma_low, dma_high,                // LD DE, DMA routine
+        0x01, 0x08, 0x00,                       // LD BC, 8
+        0xCD, copy_low, copy_high,              // CALL copy
+        0x3E, oam_page,                         // LD A, OAM data page
+        0xCD, 0x80, 0xFF,                       // CALL $FF80
+        // Frame
+        0x21, 0x00, 0x80,                       // LD HL, $8000
+        0x11, tiles_low, tiles_high,            // LD DE, tiles
+        0x01, 0x20, 0x00,                       // LD BC, 2 tiles
+        0xCD, copy_low, copy_high,              // CALL copy
+        0x21, 0x00, 0x98,                       // LD HL, $9800
+        0x11, map_low, map_high,                // LD DE, map
+        0x01, 0x00, 0x04,                       // LD BC, $400
+        0xCD, copy_low, copy_high,              // CALL copy
+        0x3E, 0xE4,                             // LD A, $E4
+        0xE0, 0x47,                             // LDH (BGP), A
+        0xE0, 0x48,                             // LDH (OBP0), A
+        0x3E, 0x1B,                             // LD A, $1B
+        0xE0, 0x49,                             // LDH (OBP1), A
+        0xAF,                                   // XOR A
+        0xE0, 0x42,                             // LDH (SCY), A
+        0xE0, 0x43,                             // LDH (SCX), A
+        0x3E, 0x93,                             // LD A, $93
+        0xE0, 0x40,                             // LDH (LCDC), A, LCD, background and sprites on
+        0x3E, DONE,                             // LD A, DONE
+        0xEA, 0xFF, 0xC0,                       // LD ($C0FF), A
+        0x18, 0xFE,                             // JR @
+    ];
+    #[rustfmt::skip]
+    let copy = [
+        0x1A,                                   // LD A, (DE)
+        0x22,                                   // LD (HL+), A
+        0x13,                                   // INC DE
+        0x0B,                                   // DEC BC
+        0x78,                                   // LD A, B
+        0xB1,                                   // OR C
+        0x20, 0xF8,                             // JR NZ, -8
+        0xC9,                                   // RET
+    ];
+    #[rustfmt::skip]
+    let dma_routine = [
+        0xE0, 0x46,                             // LDH (DMA), A
+        0x3E, 0x28,                             // LD A, 40
+        0x3D,                                   // DEC A
+        0x20, 0xFD,                             // JR NZ, -3, until the copy is done
+        0xC9,                                   // RET
+    ];
+    rom[0x150..0x150 + main.len()].copy_from_slice(&main);
+    let copy_address = COPY_ADDRESS as usize;
+    rom[copy_address..copy_address + copy.len()].copy_from_slice(&copy);
+    let dma_address = DMA_ROUTINE_ADDRESS as usize;
+    rom[dma_address..dma_address + dma_routine.len()].copy_from_slice(&dma_routine);
+
+    let oam = OAM_ADDRESS as usize;
+    rom[oam..oam + 160].copy_from_slice(&oam_data());
+
+    // Tile 0 blank, tile 1 with all four colors
+    #[rustfmt::skip]
+    let tile = [
+        0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xF0, 0x0F,
+        0x0F, 0xF0, 0xAA, 0x55, 0x55, 0xAA, 0x81, 0x7E,
+    ];
+    let tiles = TILES_ADDRESS as usize + 16;
+    rom[tiles..tiles + 16].copy_from_slice(&tile);
+
+    // A checkerboard of both tiles
+    let map = &mut rom[MAP_ADDRESS as usize..MAP_ADDRESS as usize + 0x400];
+    for (i, entry) in map.iter_mut().enumerate() {
+        *entry = ((i / 32 + i % 32) % 2) as u8;
+    }
+
+    rom[0x14D] = CartridgeHeader::checksum(&rom);
+    let global_checksum = CartridgeHeader::compute_global_checksum(&rom);
+    rom[0x14E..0x150].copy_from_slice(&global_checksum.to_be_bytes());
+    rom
+}
+
+/// What DMA copies to OAM: four sprites of tile 1, the second flipped, the
+/// third with OBP1 and the fourth behind the background. The others are
+/// off the screen.
+fn oam_data() -> [u8; 160] {
+    let mut oam = [0; 160];
+    let flags = [0x00, 0x20, 0x10, 0x80];
+
+    for (i, (sprite, flags)) in oam.chunks_mut(4).zip(flags).enumerate() {
+        let i = i as u8;
+        sprite.copy_from_slice(&[56 + i * 16, 48 + i * 24, 1, flags]);
+    }
+
+    oam
+}
+
+/// Run the self-test ROM and check what it left behind, a check per part.
+pub fn run_selftest() -> Vec<SelfTestCheck> {
+    let rom = Cartridge::from_bytes("selftest.gb", &selftest_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+    let ran = emu.run_frames(FRAMES);
+    let emulator = emu.emulator_mut();
+
+    let cpu: Vec<u8> = (0..CPU_RESULTS.len() as u16)
+        .map(|i| emulator.peek(CPU_RESULTS_ADDRESS + i))
+        .collect();
+    let timer = emulator.peek(TIMER_FLAG_ADDRESS);
+    let oam: Vec<u8> = (0..160).map(|i| emulator.peek(0xFE00 + i)).collect();
+    let oam_differences = oam
+        .iter()
+        .zip(oam_data())
+        .filter(|(a, b)| **a != *b)
+        .count();
+    let done = emulator.peek(DONE_ADDRESS) == DONE;
+    let hash = emu.last_frame().hash();
+
+    vec![
+        check(
+            "CPU operations",
+            cpu == CPU_RESULTS,
+            hex(&cpu),
+            hex(&CPU_RESULTS),
+        ),
+        check(
+            "timer interrupt",
+            timer == 1,
+            format!("handler flag {timer}"),
+            String::from("1"),
+        ),
+        check(
+            "OAM DMA",
+            oam_differences == 0,
+            format!("{oam_differences} of 160 bytes differ"),
+            String::from("none"),
+        ),
+        check(
+            "rendered frame",
+            ran && done && hash == FRAME_HASH,
+            format!("hash {hash:016x}"),
+            format!("{FRAME_HASH:016x}"),
+        ),
+    ]
+}
+
+fn check(name: &'static str, passed: bool, found: String, expected: String) -> SelfTestCheck {
+    let detail = if passed {
+        found
+    } else {
+        format!("{found}, expected {expected}")
+    };
+
+    SelfTestCheck {
+        name,
+        passed,
+        detail,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+    bytes.join(" ")
+}
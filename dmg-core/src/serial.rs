@@ -0,0 +1,416 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::bus::HardwareRegister;
+use crate::clock::EmuClock;
+use crate::interrupts::{InterruptFlag, InterruptRequest};
+use crate::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+/// Dots per transferred bit with the internal 8192 Hz clock.
+const DOTS_PER_BIT: u16 = 512;
+
+/// Link partner at the other end of the serial cable.
+pub trait SerialDevice: Send {
+    /// Called when a transfer starts at `clock` with the byte the Game Boy
+    /// sends, returns the byte shifted in from the partner.
+    fn exchange(&mut self, sent: u8, clock: EmuClock) -> u8;
+
+    /// Polled while a transfer with the external clock waits for the partner
+    /// to clock it, with the byte the Game Boy would send. Returns the byte
+    /// shifted in once the partner ran the transfer, None until then. A
+    /// partner that never drives the clock leaves the transfer waiting.
+    fn external_exchange(&mut self, _sent: u8, _clock: EmuClock) -> Option<u8> {
+        None
+    }
+}
+
+/// Peripheral speaking a byte protocol over the link cable, like the Barcode
+/// Boy or the Workboy. Simpler to write than a `SerialDevice`, which every
+/// `Peripheral` is. See `PeripheralRegistry` to make one selectable by name.
+pub trait Peripheral: Send {
+    /// Answer to a byte the Game Boy sent with its own clock.
+    fn on_byte(&mut self, sent: u8) -> u8;
+
+    /// Called once per bit time while the Game Boy waits for the peripheral
+    /// to clock a transfer, with the byte it would send. Returns the byte the
+    /// peripheral sends when it runs the transfer, None keeps the Game Boy waiting.
+    fn clock(&mut self, _sent: u8) -> Option<u8> {
+        None
+    }
+}
+
+impl<P: Peripheral> SerialDevice for P {
+    fn exchange(&mut self, sent: u8, _clock: EmuClock) -> u8 {
+        self.on_byte(sent)
+    }
+
+    fn external_exchange(&mut self, sent: u8, _clock: EmuClock) -> Option<u8> {
+        self.clock(sent)
+    }
+}
+
+/// Sends every byte straight back.
+pub struct Loopback;
+
+impl Peripheral for Loopback {
+    fn on_byte(&mut self, sent: u8) -> u8 {
+        sent
+    }
+}
+
+/// One byte exchange of a serial capture.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SerialExchange {
+    pub ticks: u64,
+    pub sent: u8,
+    pub received: u8,
+}
+
+impl SerialExchange {
+    /// Parse a capture, one `ticks sent received` line per exchange with the
+    /// bytes in hex, as written by `SerialCapture`. Anything after a `#` is
+    /// a comment.
+    pub fn parse_capture(text: &str) -> Option<Vec<SerialExchange>> {
+        text.lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut fields = line.split_whitespace();
+                let exchange = SerialExchange {
+                    ticks: fields.next()?.parse().ok()?,
+                    sent: u8::from_str_radix(fields.next()?, 16).ok()?,
+                    received: u8::from_str_radix(fields.next()?, 16).ok()?,
+                };
+
+                fields.next().is_none().then_some(exchange)
+            })
+            .collect()
+    }
+}
+
+/// Plays back the partner side of a capture.
+///
+/// Exchanges are answered in order with the captured bytes. Transfers with
+/// the external clock are clocked at their captured time. The Game Boy
+/// clocks those with the internal clock itself, so `drift` reports how far
+/// the replayed run is from the captured timing, which is also printed once
+/// the capture runs out.
+pub struct SerialReplay {
+    exchanges: Vec<SerialExchange>,
+    next: usize,
+    drift: i64,
+}
+
+impl SerialReplay {
+    pub fn new(exchanges: Vec<SerialExchange>) -> Self {
+        SerialReplay {
+            exchanges,
+            next: 0,
+            drift: 0,
+        }
+    }
+
+    /// T-cycles between the last exchange and its captured time, negative when early.
+    pub fn drift(&self) -> i64 {
+        self.drift
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.exchanges.len()
+    }
+}
+
+impl SerialDevice for SerialReplay {
+    fn exchange(&mut self, sent: u8, clock: EmuClock) -> u8 {
+        let Some(exchange) = self.exchanges.get(self.next) else {
+            // Cable pulled once the capture runs out
+            return 0xFF;
+        };
+
+        if exchange.sent != sent {
+            log!(
+                "Serial replay #{}: sent ${:02X}, captured ${:02X}",
+                self.next,
+                sent,
+                exchange.sent
+            );
+        }
+
+        self.drift = clock.ticks as i64 - exchange.ticks as i64;
+        self.next += 1;
+
+        if self.is_finished() {
+            log!(
+                "Serial replay finished, the last exchange {} T-cycles off the capture",
+                self.drift
+            );
+        }
+        exchange.received
+    }
+
+    fn external_exchange(&mut self, sent: u8, clock: EmuClock) -> Option<u8> {
+        // The partner clocks the next captured exchange at its captured time
+        let exchange = self.exchanges.get(self.next)?;
+        (clock.ticks >= exchange.ticks).then(|| self.exchange(sent, clock))
+    }
+}
+
+/// Writes sent bytes to `W`, e.g. stdout or a log file, and receives 0xFF
+/// like an unconnected port.
+#[cfg(feature = "std")]
+pub struct SerialLog<W: std::io::Write + Send> {
+    writer: W,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write + Send> SerialLog<W> {
+    pub fn new(writer: W) -> Self {
+        SerialLog { writer }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write + Send> SerialDevice for SerialLog<W> {
+    fn exchange(&mut self, sent: u8, _clock: EmuClock) -> u8 {
+        // A failing log must not stop the game
+        let _ = self
+            .writer
+            .write_all(&[sent])
+            .and_then(|_| self.writer.flush());
+        0xFF
+    }
+}
+
+/// Records every exchange with another device, or an unconnected port,
+/// to `W` in the format read by `SerialExchange::parse_capture`, with the
+/// frame and position of the PPU in a comment.
+#[cfg(feature = "std")]
+pub struct SerialCapture<W: std::io::Write + Send> {
+    writer: W,
+    device: Option<Box<dyn SerialDevice>>,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write + Send> SerialCapture<W> {
+    pub fn new(writer: W, device: Option<Box<dyn SerialDevice>>) -> Self {
+        SerialCapture { writer, device }
+    }
+}
+
+#[cfg(feature = "std")]
+fn write_exchange<W: std::io::Write>(writer: &mut W, clock: EmuClock, sent: u8, received: u8) {
+    let _ = writeln!(
+        writer,
+        "{} {sent:02X} {received:02X} # f{} {:03}:{:03}",
+        clock.ticks, clock.frame, clock.ly, clock.dot
+    );
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write + Send> SerialDevice for SerialCapture<W> {
+    fn exchange(&mut self, sent: u8, clock: EmuClock) -> u8 {
+        let received = match &mut self.device {
+            Some(device) => device.exchange(sent, clock),
+            None => 0xFF,
+        };
+
+        write_exchange(&mut self.writer, clock, sent, received);
+        received
+    }
+
+    fn external_exchange(&mut self, sent: u8, clock: EmuClock) -> Option<u8> {
+        let received = self.device.as_mut()?.external_exchange(sent, clock)?;
+
+        write_exchange(&mut self.writer, clock, sent, received);
+        Some(received)
+    }
+}
+
+/// Serial port (SB, SC).
+///
+/// A transfer with the internal clock exchanges a byte with the attached
+/// `SerialDevice` and shifts it in over 8 bits. Without a device 1s are
+/// shifted in. With the external clock the transfer waits until the device
+/// clocks it, the whole byte is exchanged at once. Sent bytes are captured
+/// since test ROMs report their results over the serial port.
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    bit_ticks: u16,
+    bits_left: u8,
+    // Byte being shifted in, most significant bit first
+    incoming: u8,
+    output: String,
+    device: Option<Box<dyn SerialDevice>>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Serial {
+            sb: 0,
+            sc: 0,
+            bit_ticks: 0,
+            bits_left: 0,
+            incoming: 0xFF,
+            output: String::new(),
+            device: None,
+        }
+    }
+
+    /// Attach a link partner, None disconnects the cable.
+    pub fn set_device(&mut self, device: Option<Box<dyn SerialDevice>>) {
+        self.device = device;
+    }
+
+    /// Bytes sent so far.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Drop output past `len` bytes, e.g. bytes sent in a frame that was rolled back.
+    pub fn truncate_output(&mut self, len: usize) {
+        self.output.truncate(len);
+    }
+
+    /// SB or SC, other addresses read as an open bus.
+    pub fn read(&self, address: u16) -> u8 {
+        match HardwareRegister::from_u16(address) {
+            Some(HardwareRegister::SB) => self.sb,
+            // Unused bits read as 1
+            Some(HardwareRegister::SC) => self.sc | 0x7E,
+            _ => 0xFF,
+        }
+    }
+
+    /// Write SB or SC, `clock` is the time for the attached device. Writes
+    /// to other addresses are ignored.
+    pub fn write(&mut self, address: u16, value: u8, clock: EmuClock) {
+        match HardwareRegister::from_u16(address) {
+            Some(HardwareRegister::SB) => self.sb = value,
+            Some(HardwareRegister::SC) => {
+                self.sc = value;
+
+                // Transfer start with the internal clock
+                if (value & 0x81) == 0x81 {
+                    self.output.push(self.sb as char);
+                    self.incoming = match &mut self.device {
+                        Some(device) => device.exchange(self.sb, clock),
+                        None => 0xFF,
+                    };
+                    self.bits_left = 8;
+                    self.bit_ticks = 0;
+                } else if (value & 0x81) == 0x80 {
+                    // The partner is asked for clocks from the next bit time on
+                    self.bit_ticks = 0;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Dots until `tick` shifts a bit or asks the device for one, u64::MAX
+    /// while no transfer runs or waits for a clock.
+    pub fn dots_until_bit(&self) -> u64 {
+        if self.bits_left == 0 && (self.sc & 0x81) != 0x80 {
+            return u64::MAX;
+        }
+
+        DOTS_PER_BIT.saturating_sub(self.bit_ticks).max(1) as u64
+    }
+
+    /// Count `dots` dots before the one `dots_until_bit` points at.
+    pub fn count_dots(&mut self, dots: u64) {
+        if self.dots_until_bit() != u64::MAX {
+            self.bit_ticks += dots as u16;
+        }
+    }
+
+    /// Advance the serial port by one dot (T-cycle), `clock` gives the time
+    /// since power on for the attached device.
+    pub fn tick<I: InterruptRequest>(&mut self, ctx: &mut I, clock: impl FnOnce() -> EmuClock) {
+        if self.bits_left == 0 {
+            if (self.sc & 0x81) == 0x80 {
+                self.tick_external(ctx, clock);
+            }
+            return;
+        }
+
+        self.bit_ticks += 1;
+
+        if self.bit_ticks < DOTS_PER_BIT {
+            return;
+        }
+
+        self.bit_ticks = 0;
+        self.sb = (self.sb << 1) | (self.incoming >> 7);
+        self.incoming <<= 1;
+        self.bits_left -= 1;
+
+        if self.bits_left == 0 {
+            self.sc &= 0x7F;
+            ctx.request_interrupt(InterruptFlag::SERIAL);
+        }
+    }
+
+    /// Ask the device whether it clocked the waiting transfer, once per bit
+    /// time instead of every dot.
+    fn tick_external<I: InterruptRequest>(
+        &mut self,
+        ctx: &mut I,
+        clock: impl FnOnce() -> EmuClock,
+    ) {
+        self.bit_ticks += 1;
+
+        if self.bit_ticks < DOTS_PER_BIT {
+            return;
+        }
+
+        self.bit_ticks = 0;
+
+        let Some(device) = &mut self.device else {
+            return;
+        };
+
+        if let Some(received) = device.external_exchange(self.sb, clock()) {
+            self.output.push(self.sb as char);
+            self.sb = received;
+            self.sc &= 0x7F;
+            ctx.request_interrupt(InterruptFlag::SERIAL);
+        }
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Serial::new()
+    }
+}
+
+impl Resettable for Serial {
+    fn reset(&mut self) {
+        // The cable stays plugged in
+        let device = self.device.take();
+        *self = Serial::new();
+        self.device = device;
+    }
+}
+
+impl Saveable for Serial {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.sb);
+        state.write_u8(self.sc);
+        state.write_u16(self.bit_ticks);
+        state.write_u8(self.bits_left);
+        state.write_u8(self.incoming);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.sb = state.read_u8()?;
+        self.sc = state.read_u8()?;
+        self.bit_ticks = state.read_u16()?;
+        self.bits_left = state.read_u8()?;
+        self.incoming = state.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use crate::bess;
+use crate::bus::HardwareRegister;
+use crate::cpu::{CPU, Flags, RegisterFile};
+use crate::emu::Emulator;
+use crate::json::{self, Json};
+use crate::state::StateError;
+
+/// Regions in the `memory` object of the JSON form, in address order.
+const JSON_REGIONS: [Region; 5] = [
+    Region::Vram,
+    Region::Wram,
+    Region::Oam,
+    Region::Io,
+    Region::Hram,
+];
+/// Bytes per line of memory in the JSON form.
+const JSON_ROW: usize = 16;
+/// Most cartridge RAM a mapper has, 16 banks of 8 KiB.
+const MAX_CARTRIDGE_RAM: usize = 128 * 1024;
+
+/// Part of memory a snapshot holds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Region {
+    Wram,
+    Vram,
+    /// All banks one after the other
+    CartridgeRam,
+    Oam,
+    /// $FF00 to $FF7F
+    Io,
+    Hram,
+}
+
+impl Region {
+    /// Address of the first byte, the first byte of bank 0 for cartridge RAM.
+    pub fn start(self) -> u16 {
+        match self {
+            Region::Wram => 0xC000,
+            Region::Vram => 0x8000,
+            Region::CartridgeRam => 0xA000,
+            Region::Oam => 0xFE00,
+            Region::Io => 0xFF00,
+            Region::Hram => 0xFF80,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Wram => "WRAM",
+            Region::Vram => "VRAM",
+            Region::CartridgeRam => "SRAM",
+            Region::Oam => "OAM",
+            Region::Io => "IO",
+            Region::Hram => "HRAM",
+        }
+    }
+}
+
+/// A value two snapshots disagree on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Difference {
+    /// CPU register, IME or IE
+    Register {
+        name: &'static str,
+        before: u16,
+        after: u16,
+    },
+    Memory {
+        region: Region,
+        offset: usize,
+        before: u8,
+        after: u8,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Difference::Register {
+                name: name @ ("PC" | "SP"),
+                before,
+                after,
+            } => write!(f, "{name:<12} {before:04X} -> {after:04X}"),
+            Difference::Register {
+                name,
+                before,
+                after,
+            } => write!(f, "{name:<12} {before:02X} -> {after:02X}"),
+            Difference::Memory {
+                region: Region::CartridgeRam,
+                offset,
+                before,
+                after,
+            } => write!(
+                f,
+                "SRAM {:02X}:{:04X} {before:02X} -> {after:02X}",
+                offset / 0x2000,
+                0xA000 + offset % 0x2000
+            ),
+            Difference::Memory {
+                region,
+                offset,
+                before,
+                after,
+            } => {
+                let address = region.start() + offset as u16;
+                let name = match HardwareRegister::from_u16(address) {
+                    Some(register) if region == Region::Io => format!("{register:?}"),
+                    _ => region.name().into(),
+                };
+                write!(f, "${address:04X} {name:<6} {before:02X} -> {after:02X}")
+            }
+        }
+    }
+}
+
+/// Registers and memory of the machine in a form that doesn't depend on how
+/// this emulator stores its state, to compare states or look into them.
+#[derive(Clone, PartialEq)]
+pub struct MachineSnapshot {
+    pub registers: RegisterFile,
+    pub ime: bool,
+    pub ie: u8,
+    /// 0 when running, 1 when halted and 2 in STOP, as BESS stores it
+    pub execution_state: u8,
+    /// $FF00 to $FF7F as the CPU reads them
+    pub io: Vec<u8>,
+    pub wram: Vec<u8>,
+    pub vram: Vec<u8>,
+    pub cartridge_ram: Vec<u8>,
+    pub oam: Vec<u8>,
+    pub hram: Vec<u8>,
+}
+
+impl MachineSnapshot {
+    pub fn capture(cpu: &CPU<Emulator>) -> Self {
+        let emu = cpu.context();
+        let read = |start: u16, len: u16| -> Vec<u8> {
+            (start..start + len)
+                .map(|address| emu.inspect(address).unwrap_or(0xFF))
+                .collect()
+        };
+
+        MachineSnapshot {
+            registers: *cpu.registers(),
+            ime: cpu.ime(),
+            execution_state: cpu.execution_state(),
+            ie: emu.inspect(0xFFFF).unwrap_or(0xFF),
+            io: read(0xFF00, 0x80),
+            wram: read(0xC000, 0x2000),
+            vram: read(0x8000, 0x2000),
+            cartridge_ram: emu
+                .cartridge()
+                .map_or_else(Vec::new, |rom| rom.ram().to_vec()),
+            oam: read(0xFE00, 0xA0),
+            hram: read(0xFF80, 0x7F),
+        }
+    }
+
+    /// Read a savestate file of `state::save_machine`, or of another
+    /// emulator, from its BESS section. No cartridge is needed.
+    pub fn from_state(data: &[u8]) -> Result<Self, StateError> {
+        if !bess::has_footer(data) {
+            return Err(StateError::InvalidValue("BESS section"));
+        }
+
+        bess::snapshot(data)
+    }
+
+    /// Put the registers and memory into the machine, the cartridge keeps its
+    /// mapper state and clock. IO registers are written like
+    /// `Emulator::restore_io` does, without starting anything.
+    pub fn restore(&self, cpu: &mut CPU<Emulator>) {
+        let emu = cpu.context_mut();
+
+        for region in [Region::Vram, Region::Wram, Region::Oam, Region::Hram] {
+            for (offset, value) in self.memory(region).iter().enumerate() {
+                emu.poke(region.start() + offset as u16, *value);
+            }
+        }
+
+        if let Some(rom) = emu.cartridge_mut()
+            && !self.cartridge_ram.is_empty()
+        {
+            let mut battery = rom.battery_data();
+            let len = rom.ram().len().min(self.cartridge_ram.len());
+            battery[..len].copy_from_slice(&self.cartridge_ram[..len]);
+            rom.load_battery_data(&battery);
+        }
+
+        emu.restore_io(&self.io, self.ie);
+        cpu.restore_core(self.registers, self.ime, self.execution_state);
+    }
+
+    /// The snapshot as JSON for bug reports and test fixtures: the registers
+    /// as hex strings and memory in lines of 16 bytes keyed by address, like
+    /// `"C000": "00 01 ..."`. Cartridge RAM is keyed by its offset.
+    pub fn to_json(&self) -> String {
+        let r = &self.registers;
+        let state = match self.execution_state {
+            1 => "halted",
+            2 => "stopped",
+            _ => "running",
+        };
+        let mut json = format!(
+            "{{\n  \"registers\": {{\"pc\": \"{:04X}\", \"sp\": \"{:04X}\", \"a\": \"{:02X}\", \
+             \"f\": \"{:02X}\", \"b\": \"{:02X}\", \"c\": \"{:02X}\", \"d\": \"{:02X}\", \
+             \"e\": \"{:02X}\", \"h\": \"{:02X}\", \"l\": \"{:02X}\"}},\n  \"ime\": {},\n  \
+             \"ie\": \"{:02X}\",\n  \"state\": {},\n  \"memory\": {{",
+            r.pc,
+            r.sp,
+            r.a,
+            r.f.bits(),
+            r.b,
+            r.c,
+            r.d,
+            r.e,
+            r.h,
+            r.l,
+            self.ime,
+            self.ie,
+            json::string(state)
+        );
+
+        let rows = JSON_REGIONS.iter().flat_map(|&region| {
+            self.memory(region)
+                .chunks(JSON_ROW)
+                .enumerate()
+                .map(move |(row, bytes)| (region.start() as usize + row * JSON_ROW, bytes))
+        });
+        write_rows(&mut json, rows, 4);
+        json.push_str("\n  },\n  \"cartridge_ram\": {");
+        write_rows(
+            &mut json,
+            self.cartridge_ram
+                .chunks(JSON_ROW)
+                .enumerate()
+                .map(|(row, bytes)| (row * JSON_ROW, bytes)),
+            4,
+        );
+        json.push_str("\n  }\n}\n");
+        json
+    }
+
+    /// Take over what `text`, in the form of `to_json`, has. Anything left
+    /// out keeps its value, so fixtures may list only what they need.
+    pub fn merge_json(&mut self, text: &str) -> Result<(), StateError> {
+        let json = Json::parse(text).ok_or(StateError::InvalidValue("JSON"))?;
+        let hex = |value: &Json, field: &'static str| -> Result<u16, StateError> {
+            value
+                .as_str()
+                .and_then(|text| u16::from_str_radix(text, 16).ok())
+                .ok_or(StateError::InvalidValue(field))
+        };
+
+        if let Some(registers) = json.get("registers") {
+            let r = &mut self.registers;
+            for (name, value) in registers.members() {
+                let value = hex(value, "register")?;
+                match name.as_str() {
+                    "pc" => r.pc = value,
+                    "sp" => r.sp = value,
+                    "a" => r.a = value as u8,
+                    "f" => r.f = Flags::from_bits_truncate(value as u8),
+                    "b" => r.b = value as u8,
+                    "c" => r.c = value as u8,
+                    "d" => r.d = value as u8,
+                    "e" => r.e = value as u8,
+                    "h" => r.h = value as u8,
+                    "l" => r.l = value as u8,
+                    _ => return Err(StateError::InvalidValue("register name")),
+                }
+            }
+        }
+
+        if let Some(ime) = json.get("ime") {
+            self.ime = ime.as_bool().ok_or(StateError::InvalidValue("ime"))?;
+        }
+        if let Some(ie) = json.get("ie") {
+            self.ie = hex(ie, "ie")? as u8;
+        }
+        if let Some(state) = json.get("state") {
+            self.execution_state = match state.as_str() {
+                Some("running") => 0,
+                Some("halted") => 1,
+                Some("stopped") => 2,
+                _ => return Err(StateError::InvalidValue("state")),
+            };
+        }
+
+        for (address, bytes) in rows(json.get("memory"))? {
+            for (i, value) in bytes.into_iter().enumerate() {
+                let address = address
+                    .checked_add(i)
+                    .ok_or(StateError::InvalidValue("memory address"))?;
+                let region = JSON_REGIONS
+                    .into_iter()
+                    .find(|region| {
+                        let start = region.start() as usize;
+                        (start..start + self.memory(*region).len()).contains(&address)
+                    })
+                    .ok_or(StateError::InvalidValue("memory address"))?;
+                self.memory_mut(region)[address - region.start() as usize] = value;
+            }
+        }
+
+        for (offset, bytes) in rows(json.get("cartridge_ram"))? {
+            let end = offset
+                .checked_add(bytes.len())
+                .filter(|&end| end <= MAX_CARTRIDGE_RAM)
+                .ok_or(StateError::InvalidValue("cartridge RAM offset"))?;
+            if end > self.cartridge_ram.len() {
+                self.cartridge_ram.resize(end, 0);
+            }
+            self.cartridge_ram[offset..end].copy_from_slice(&bytes);
+        }
+
+        Ok(())
+    }
+
+    pub fn memory(&self, region: Region) -> &[u8] {
+        match region {
+            Region::Wram => &self.wram,
+            Region::Vram => &self.vram,
+            Region::CartridgeRam => &self.cartridge_ram,
+            Region::Oam => &self.oam,
+            Region::Io => &self.io,
+            Region::Hram => &self.hram,
+        }
+    }
+
+    fn memory_mut(&mut self, region: Region) -> &mut Vec<u8> {
+        match region {
+            Region::Wram => &mut self.wram,
+            Region::Vram => &mut self.vram,
+            Region::CartridgeRam => &mut self.cartridge_ram,
+            Region::Oam => &mut self.oam,
+            Region::Io => &mut self.io,
+            Region::Hram => &mut self.hram,
+        }
+    }
+
+    /// Registers as `(name, value)`, IME and IE included.
+    fn register_values(&self) -> [(&'static str, u16); 12] {
+        let r = &self.registers;
+        [
+            ("PC", r.pc),
+            ("SP", r.sp),
+            ("A", r.a as u16),
+            ("F", r.f.bits() as u16),
+            ("B", r.b as u16),
+            ("C", r.c as u16),
+            ("D", r.d as u16),
+            ("E", r.e as u16),
+            ("H", r.h as u16),
+            ("L", r.l as u16),
+            ("IME", self.ime as u16),
+            ("IE", self.ie as u16),
+        ]
+    }
+
+    /// Everything that changed from `self` to `other`, registers first and
+    /// then memory by region. Bytes past the end of the shorter cartridge RAM
+    /// aren't compared.
+    pub fn diff(&self, other: &MachineSnapshot) -> Vec<Difference> {
+        let mut differences: Vec<Difference> = self
+            .register_values()
+            .into_iter()
+            .zip(other.register_values())
+            .filter(|((_, before), (_, after))| before != after)
+            .map(|((name, before), (_, after))| Difference::Register {
+                name,
+                before,
+                after,
+            })
+            .collect();
+
+        for region in [
+            Region::Io,
+            Region::Hram,
+            Region::Wram,
+            Region::CartridgeRam,
+            Region::Vram,
+            Region::Oam,
+        ] {
+            let before = self.memory(region).iter();
+            let after = other.memory(region).iter();
+
+            for (offset, (&before, &after)) in before.zip(after).enumerate() {
+                if before != after {
+                    differences.push(Difference::Memory {
+                        region,
+                        offset,
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+
+        differences
+    }
+}
+
+/// Lines of `"AAAA": "00 01 ..."` members, keys as four hex digits.
+fn write_rows<'a>(json: &mut String, rows: impl Iterator<Item = (usize, &'a [u8])>, indent: usize) {
+    for (i, (address, bytes)) in rows.enumerate() {
+        let _ = write!(
+            json,
+            "{}\n{:indent$}\"{address:04X}\": \"",
+            if i == 0 { "" } else { "," },
+            ""
+        );
+        for (j, byte) in bytes.iter().enumerate() {
+            let _ = write!(json, "{}{byte:02X}", if j == 0 { "" } else { " " });
+        }
+        json.push('"');
+    }
+}
+
+/// The members of `write_rows` as addresses and bytes.
+fn rows(object: Option<&Json>) -> Result<Vec<(usize, Vec<u8>)>, StateError> {
+    let members = object.map_or(&[][..], Json::members);
+
+    members
+        .iter()
+        .map(|(address, bytes)| {
+            let address = usize::from_str_radix(address, 16)
+                .map_err(|_| StateError::InvalidValue("memory address"))?;
+            let bytes = bytes
+                .as_str()
+                .ok_or(StateError::InvalidValue("memory bytes"))?
+                .split_whitespace()
+                .map(|byte| u8::from_str_radix(byte, 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| StateError::InvalidValue("memory bytes"))?;
+            Ok((address, bytes))
+        })
+        .collect()
+}
@@ -0,0 +1,382 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use crate::bess;
+use crate::cart::Cartridge;
+use crate::compress::{compress, decompress};
+use crate::cpu::CPU;
+use crate::emu::Emulator;
+use crate::hash;
+use crate::snapshot::MachineSnapshot;
+
+const STATE_MAGIC: &[u8; 4] = b"DMGS";
+// Version 1 stored the machine uncompressed, 2 as an LZ4 block, 3 adds the
+// length of the block, followed by a BESS section, and 4 splits the machine
+// into chunks. Later versions have to keep the header of version 4 so that
+// older builds can still read the chunks they know.
+const STATE_VERSION: u8 = 4;
+
+/// Four character code naming a chunk of a savestate.
+///
+/// The machine is a list of chunks, each the tag, the length of its data as
+/// a little endian u32 and the data. A loader skips chunks it doesn't know
+/// and data after what it reads from a known chunk, so a build can load the
+/// states of a newer one as far as it understands them. A chunk missing from
+/// an older state leaves its component as it was.
+pub type ChunkTag = [u8; 4];
+
+/// `CPU::save_state`, the registers and execution mode.
+pub const CPU_CHUNK: ChunkTag = *b"CPU ";
+/// `Emulator::save_state`, itself made of the chunks below.
+pub const EMULATOR_CHUNK: ChunkTag = *b"EMU ";
+/// T-cycles since power on as a u64.
+pub const TICKS_CHUNK: ChunkTag = *b"TICK";
+/// RAM, IO bytes and the cartridge: mapper, cartridge RAM and clock.
+pub const BUS_CHUNK: ChunkTag = *b"BUS ";
+/// IE and IF.
+pub const INTERRUPTS_CHUNK: ChunkTag = *b"INT ";
+/// OAM DMA: the DMA register, the byte being copied and the start delay.
+pub const DMA_CHUNK: ChunkTag = *b"DMA ";
+/// VRAM, OAM, the LCD registers and where the PPU is in its frame.
+pub const PPU_CHUNK: ChunkTag = *b"PPU ";
+/// The sound registers, the four channels, wave RAM and the frame sequencer.
+pub const APU_CHUNK: ChunkTag = *b"APU ";
+/// The system counter, DIV is its upper byte.
+pub const COUNTER_CHUNK: ChunkTag = *b"DIV ";
+/// TIMA, TMA and TAC.
+pub const TIMER_CHUNK: ChunkTag = *b"TIMR";
+/// SB, SC and the transfer being shifted.
+pub const SERIAL_CHUNK: ChunkTag = *b"SER ";
+/// The RP register of the infrared port.
+pub const INFRARED_CHUNK: ChunkTag = *b"IR  ";
+/// The button groups P1 selects and whether a line fell for the interrupt.
+pub const JOYPAD_CHUNK: ChunkTag = *b"JOYP";
+/// Whether the boot ROM is still mapped.
+pub const BOOT_CHUNK: ChunkTag = *b"BOOT";
+
+/// Component whose state can be written to and restored from a savestate.
+///
+/// Fields are written in a fixed order without names, `load_state` must read
+/// them back in the same order as `save_state` wrote them. New fields go at
+/// the end, where older builds stop reading.
+pub trait Saveable {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+/// Component that can be put back into its power-on state.
+pub trait Resettable {
+    fn reset(&mut self);
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    UnexpectedEnd,
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::UnexpectedEnd => write!(f, "savestate is truncated"),
+            StateError::InvalidHeader => write!(f, "not a savestate"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported savestate version {v}"),
+            StateError::InvalidValue(field) => write!(f, "invalid value for {field}"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// Power cycle the machine, RAM is filled as set by `Emulator::set_ram_init`.
+pub fn hard_reset(cpu: &mut CPU<Emulator>) {
+    cpu.context_mut().reset();
+    cpu.reset();
+}
+
+/// Power on with another cartridge, returns the one taken out, see
+/// `Emulator::swap_cartridge`.
+pub fn swap_cartridge(cpu: &mut CPU<Emulator>, rom: Cartridge) -> Option<Cartridge> {
+    let old = cpu.context_mut().swap_cartridge(rom);
+    cpu.reset();
+    old
+}
+
+/// Restart the game at 0x0100 with the post-boot registers, keeping RAM,
+/// see `Emulator::soft_reset`.
+pub fn soft_reset(cpu: &mut CPU<Emulator>) {
+    cpu.context_mut().soft_reset();
+    cpu.reset();
+}
+
+/// Snapshot of the whole machine, the cartridge ROM itself is not included.
+///
+/// The state starts with `DMGS`, the version as a byte, the length of the
+/// machine chunks and the length of their LZ4 block as u32s. The machine
+/// is compressed, raw it takes around 74 KiB. A BESS section follows with
+/// the memory uncompressed, so emulators like SameBoy can load the state
+/// as well.
+pub fn save_machine(cpu: &CPU<Emulator>) -> Vec<u8> {
+    let machine = capture_machine(cpu);
+    let compressed = compress(&machine);
+
+    let mut state = StateWriter::new();
+    state.write_bytes(STATE_MAGIC);
+    state.write_u8(STATE_VERSION);
+    state.write_u32(machine.len() as u32);
+    state.write_u32(compressed.len() as u32);
+    state.write_bytes(&compressed);
+
+    let mut data = state.into_bytes();
+    bess::append(cpu, &mut data);
+    data
+}
+
+/// Restore a snapshot taken by `save_machine` with the same cartridge inserted.
+///
+/// States of other emulators are loaded from their BESS section, see `bess::load`.
+pub fn load_machine(cpu: &mut CPU<Emulator>, data: &[u8]) -> Result<(), StateError> {
+    let mut state = StateReader::new(data);
+
+    if !data.starts_with(STATE_MAGIC) && bess::has_footer(data) {
+        return bess::load(cpu, data);
+    }
+
+    if state.read_bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+        return Err(StateError::InvalidHeader);
+    }
+
+    let machine = match state.read_u8()? {
+        // Only the BESS section of a state without chunks is still readable
+        3 if bess::has_footer(data) => return bess::load(cpu, data),
+        version if version < STATE_VERSION => {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        _ => {
+            let len = state.read_u32()? as usize;
+            let compressed_len = state.read_u32()? as usize;
+            decompress(state.read_bytes(compressed_len)?, len)
+                .ok_or(StateError::InvalidValue("compressed data"))?
+        }
+    };
+
+    restore_machine(cpu, &machine)
+}
+
+/// Uncompressed snapshot without a header, for states kept in memory like
+/// the rewind buffer and run ahead.
+pub fn capture_machine(cpu: &CPU<Emulator>) -> Vec<u8> {
+    let mut state = StateWriter::new();
+    state.write_chunk(CPU_CHUNK, |state| cpu.save_state(state));
+    state.write_chunk(EMULATOR_CHUNK, |state| cpu.context().save_state(state));
+    state.into_bytes()
+}
+
+/// Restore a snapshot taken by `capture_machine` with the same cartridge inserted.
+pub fn restore_machine(cpu: &mut CPU<Emulator>, data: &[u8]) -> Result<(), StateError> {
+    load_chunks(&mut StateReader::new(data), |tag, chunk| {
+        match tag {
+            CPU_CHUNK => cpu.load_state(chunk)?,
+            EMULATOR_CHUNK => cpu.context_mut().load_state(chunk)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })
+}
+
+/// Read chunks until the end of `state` and hand each to `load`, which
+/// returns false for tags it doesn't know. Those are skipped, they come
+/// from a newer build.
+pub fn load_chunks(
+    state: &mut StateReader,
+    mut load: impl FnMut(ChunkTag, &mut StateReader) -> Result<bool, StateError>,
+) -> Result<(), StateError> {
+    while !state.is_empty() {
+        let (tag, mut chunk) = state.read_chunk()?;
+
+        if !load(tag, &mut chunk)? {
+            log!(
+                "Skipped savestate chunk {} of a newer version",
+                String::from_utf8_lossy(&tag)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Global checksum of the ROM a state of `save_machine` or another BESS
+/// aware emulator was saved with, None when the state doesn't tell.
+pub fn rom_checksum(data: &[u8]) -> Option<u16> {
+    bess::global_checksum(data)
+}
+
+/// Registers and memory as JSON, see `MachineSnapshot::to_json`. Readable in
+/// bug reports and test fixtures, unlike `save_machine`, but without the
+/// inner state of the PPU, APU and mapper.
+pub fn save_json(cpu: &CPU<Emulator>) -> String {
+    MachineSnapshot::capture(cpu).to_json()
+}
+
+/// Load JSON of `save_json` with the same cartridge inserted. The machine is
+/// power cycled first and what the JSON leaves out keeps its power-on value.
+/// The machine stays as it was when the JSON is invalid.
+pub fn load_json(cpu: &mut CPU<Emulator>, text: &str) -> Result<(), StateError> {
+    let backup = capture_machine(cpu);
+    hard_reset(cpu);
+
+    let mut snapshot = MachineSnapshot::capture(cpu);
+    if let Err(e) = snapshot.merge_json(text) {
+        restore_machine(cpu, &backup)?;
+        return Err(e);
+    }
+
+    snapshot.restore(cpu);
+    Ok(())
+}
+
+/// Checksum of WRAM, HRAM, the IO registers and the CPU registers. Two
+/// machines running the same game with the same input agree on it frame
+/// for frame, a difference means they went apart.
+pub fn checksum(cpu: &CPU<Emulator>) -> u64 {
+    let r = cpu.registers();
+    let [pc_high, pc_low] = r.pc.to_be_bytes();
+    let [sp_high, sp_low] = r.sp.to_be_bytes();
+    let registers = [
+        r.a,
+        r.f.bits(),
+        r.b,
+        r.c,
+        r.d,
+        r.e,
+        r.h,
+        r.l,
+        pc_high,
+        pc_low,
+        sp_high,
+        sp_low,
+    ];
+
+    cpu.context().memory_checksum() ^ hash::fnv1a64(&registers).rotate_left(32)
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Write what `save` writes as a chunk named `tag`, see `ChunkTag`.
+    pub fn write_chunk(&mut self, tag: ChunkTag, save: impl FnOnce(&mut StateWriter)) {
+        self.data.extend_from_slice(&tag);
+        let start = self.data.len();
+        self.write_u32(0);
+
+        save(self);
+
+        let len = (self.data.len() - start - 4) as u32;
+        self.data[start..start + 4].copy_from_slice(&len.to_le_bytes());
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, position: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let end = self.position + len;
+
+        if end > self.data.len() {
+            return Err(StateError::UnexpectedEnd);
+        }
+
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Everything not read yet.
+    pub fn read_rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.position.min(self.data.len())..];
+        self.position = self.data.len();
+        rest
+    }
+
+    pub fn read_into(&mut self, buffer: &mut [u8]) -> Result<(), StateError> {
+        buffer.copy_from_slice(self.read_bytes(buffer.len())?);
+        Ok(())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Tag of the next chunk and a reader of its data alone.
+    pub fn read_chunk(&mut self) -> Result<(ChunkTag, StateReader<'a>), StateError> {
+        let tag = self.read_bytes(4)?.try_into().unwrap();
+        let len = self.read_u32()? as usize;
+        Ok((tag, StateReader::new(self.read_bytes(len)?)))
+    }
+}
@@ -0,0 +1,292 @@
+use core::fmt;
+use core::time::Duration;
+
+use crate::emu::{CLOCK_HZ, DOTS_PER_FRAME};
+
+/// Host time per frame the frontend aims for, 60 Hz.
+pub const TARGET_FRAME_TIME: Duration = Duration::from_millis(16);
+
+/// Emulated time of one frame, `DOTS_PER_FRAME` at `CLOCK_HZ`.
+pub const FRAME_DURATION: Duration =
+    Duration::from_nanos(DOTS_PER_FRAME * 1_000_000_000 / CLOCK_HZ);
+
+/// Frames kept for the averages and the frametime graph.
+pub const STATS_HISTORY: usize = 120;
+
+/// Largest change to the emulation speed `AvSync` makes, 0.5 %.
+pub const MAX_SPEED_ADJUST: f32 = 0.005;
+
+/// Drift in seconds at which `AvSync` makes the largest change.
+const FULL_ADJUST_DRIFT: f32 = 0.05;
+
+/// How the frame limiter handles a host display that doesn't refresh at the
+/// 59.73 Hz of the Game Boy LCD.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PacingMode {
+    /// Frames take as long as on hardware, the display shows one twice or
+    /// skips one now and then
+    Exact,
+    /// A frame per refresh of the display, the game runs that much faster
+    /// or slower
+    Refresh,
+}
+
+/// How the frame limiter waits out the rest of a frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SleepStrategy {
+    /// Sleep all of it, the cheapest, but the OS may wake the thread a
+    /// millisecond or more late
+    #[default]
+    Sleep,
+    /// Sleep until shortly before the frame is due and spin the rest,
+    /// steady frame times for a busy core
+    Hybrid,
+    /// Wait until the window shows the frame at the next refresh of the
+    /// display, a frame per refresh like `PacingMode::Refresh`
+    Vsync,
+}
+
+/// Frame pacing for a display refreshing at `display_rate` Hz.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pacing {
+    pub mode: PacingMode,
+    pub display_rate: f32,
+}
+
+impl Pacing {
+    /// Host time per emulated frame.
+    pub fn frame_time(&self) -> Duration {
+        match self.mode {
+            PacingMode::Exact => FRAME_DURATION,
+            PacingMode::Refresh => Duration::from_secs_f32(1.0 / self.display_rate),
+        }
+    }
+
+    /// Emulation speed in percent the pacing aims for, 100 is hardware speed.
+    pub fn speed_percent(&self) -> f32 {
+        FRAME_DURATION.as_secs_f32() / self.frame_time().as_secs_f32() * 100.0
+    }
+
+    /// Refreshes per second showing a frame again, negative for frames per
+    /// second never shown. Zero when synced to the display.
+    pub fn repeated_frames(&self) -> f32 {
+        self.display_rate - 1.0 / self.frame_time().as_secs_f32()
+    }
+}
+
+impl fmt::Display for Pacing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            PacingMode::Refresh => write!(
+                f,
+                "synced to the {:.0} Hz display at {:.2}% speed",
+                self.display_rate,
+                self.speed_percent()
+            ),
+            PacingMode::Exact => match self.repeated_frames() {
+                0.0 => write!(f, "exact speed"),
+                repeated if repeated > 0.0 => write!(
+                    f,
+                    "exact speed, the {:.0} Hz display repeats a frame every {:.1} s",
+                    self.display_rate,
+                    1.0 / repeated
+                ),
+                repeated => write!(
+                    f,
+                    "exact speed, the {:.0} Hz display skips a frame every {:.1} s",
+                    self.display_rate,
+                    -1.0 / repeated
+                ),
+            },
+        }
+    }
+}
+
+/// How fast a run without frame limiting went, the multiple of real time is
+/// the one number to compare builds and hosts by.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SpeedReport {
+    pub frames: u32,
+    /// Host time the frames took
+    pub elapsed: Duration,
+    /// Time the frames take on hardware
+    pub emulated: Duration,
+}
+
+impl SpeedReport {
+    /// Frames per second of host time, 0 for a run too short to time.
+    pub fn fps(&self) -> f64 {
+        self.per_second(self.frames as f64)
+    }
+
+    /// How many times faster than the hardware the run went, 0 for a run too
+    /// short to time.
+    pub fn multiple(&self) -> f64 {
+        self.per_second(self.emulated.as_secs_f64())
+    }
+
+    fn per_second(&self, amount: f64) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            0.0 => 0.0,
+            elapsed => amount / elapsed,
+        }
+    }
+}
+
+impl fmt::Display for SpeedReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} frames in {:.3} s, {:.0} fps, {:.1}x real time",
+            self.frames,
+            self.elapsed.as_secs_f64(),
+            self.fps(),
+            self.multiple()
+        )
+    }
+}
+
+/// Performance of the emulator on the host.
+///
+/// The core has no clock of its own, the frontend reports the host time
+/// between completed frames with `record_frame`, including time spent waiting
+/// for the frame limiter.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    // Ring buffer of the last frame times, `next` is the oldest once full
+    frame_times: [Duration; STATS_HISTORY],
+    next: usize,
+    len: usize,
+    audio_buffer_fill: Option<f32>,
+    audio_drift: Option<f32>,
+    pacing: Option<Pacing>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            frame_times: [Duration::ZERO; STATS_HISTORY],
+            next: 0,
+            len: 0,
+            audio_buffer_fill: None,
+            audio_drift: None,
+            pacing: None,
+        }
+    }
+
+    pub fn record_frame(&mut self, frame_time: Duration) {
+        self.frame_times[self.next] = frame_time;
+        self.next = (self.next + 1) % STATS_HISTORY;
+        self.len = (self.len + 1).min(STATS_HISTORY);
+    }
+
+    /// Recent frame times, oldest first.
+    pub fn frame_times(&self) -> impl Iterator<Item = Duration> + '_ {
+        let start = (self.next + STATS_HISTORY - self.len) % STATS_HISTORY;
+        (0..self.len).map(move |i| self.frame_times[(start + i) % STATS_HISTORY])
+    }
+
+    /// Average of the recent frame times, zero before the first frame.
+    pub fn average_frame_time(&self) -> Duration {
+        if self.len == 0 {
+            return Duration::ZERO;
+        }
+
+        self.frame_times().sum::<Duration>() / self.len as u32
+    }
+
+    /// Frames per second of host time.
+    pub fn fps(&self) -> f32 {
+        match self.average_frame_time().as_secs_f32() {
+            0.0 => 0.0,
+            frame_time => 1.0 / frame_time,
+        }
+    }
+
+    /// Emulated time per host time in percent, 100 is real hardware speed.
+    pub fn speed_percent(&self) -> f32 {
+        match self.average_frame_time().as_secs_f32() {
+            0.0 => 0.0,
+            frame_time => FRAME_DURATION.as_secs_f32() / frame_time * 100.0,
+        }
+    }
+
+    /// Fill level of the audio output buffer from 0 to 1, None without audio output.
+    pub fn audio_buffer_fill(&self) -> Option<f32> {
+        self.audio_buffer_fill
+    }
+
+    pub fn set_audio_buffer_fill(&mut self, fill: Option<f32>) {
+        self.audio_buffer_fill = fill;
+    }
+
+    /// Seconds the video is ahead of the played audio, see `AvSync::drift`.
+    /// None without audio output.
+    pub fn audio_drift(&self) -> Option<f32> {
+        self.audio_drift
+    }
+
+    pub fn set_audio_drift(&mut self, drift: Option<f32>) {
+        self.audio_drift = drift;
+    }
+
+    /// How the frontend paces the frames, None without a frame limiter.
+    pub fn pacing(&self) -> Option<Pacing> {
+        self.pacing
+    }
+
+    pub fn set_pacing(&mut self, pacing: Option<Pacing>) {
+        self.pacing = pacing;
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats::new()
+    }
+}
+
+/// Keeps the emulated video in step with the audio the host has played.
+///
+/// The audio device consumes samples on its own clock, which drifts from the
+/// host timer that paces the frames. The frontend reports both, and the
+/// emulation speed is nudged by up to `MAX_SPEED_ADJUST` against the drift,
+/// too little to be heard as a change of pitch.
+#[derive(Clone, Debug)]
+pub struct AvSync {
+    sample_rate: u32,
+    frames: u64,
+    samples: u64,
+}
+
+impl AvSync {
+    pub fn new(sample_rate: u32) -> Self {
+        AvSync {
+            sample_rate,
+            frames: 0,
+            samples: 0,
+        }
+    }
+
+    pub fn record_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Samples per channel the audio device has played since the last call.
+    pub fn record_samples(&mut self, samples: u64) {
+        self.samples += samples;
+    }
+
+    /// Seconds of emulated video ahead of the played audio, negative when behind.
+    pub fn drift(&self) -> f32 {
+        let video = self.frames as f64 * FRAME_DURATION.as_secs_f64();
+        let audio = self.samples as f64 / self.sample_rate as f64;
+
+        (video - audio) as f32
+    }
+
+    /// Factor for the emulation speed, below 1 while the video is ahead.
+    pub fn speed_factor(&self) -> f32 {
+        1.0 - (self.drift() / FULL_ADJUST_DRIFT).clamp(-1.0, 1.0) * MAX_SPEED_ADJUST
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Where battery saves and savestates are kept, so that a network share
+/// or a sync service can take the place of the files next to the ROM.
+///
+/// Keys are the paths the files have with `FileStorage`, like
+/// `games/tetris.sav`; other backends can use them as names. Calls come
+/// from both the GUI and the emulation thread.
+pub trait StorageBackend: Send + Sync {
+    /// What was stored under `key`, None when nothing was.
+    fn load(&self, key: &Path) -> io::Result<Option<Vec<u8>>>;
+
+    /// Store `data` under `key`, replacing what was there.
+    fn store(&self, key: &Path, data: &[u8]) -> io::Result<()>;
+
+    /// Forget what was stored under `key`, if anything was.
+    fn remove(&self, key: &Path) -> io::Result<()>;
+
+    /// Where `key` is stored, for messages.
+    fn location(&self, key: &Path) -> String {
+        key.display().to_string()
+    }
+}
+
+/// Files at the paths of their keys, the default, or under one directory
+/// like a synced folder.
+#[derive(Clone, Debug, Default)]
+pub struct FileStorage {
+    dir: Option<PathBuf>,
+}
+
+impl FileStorage {
+    pub fn new() -> Self {
+        FileStorage::default()
+    }
+
+    /// Keep the files in `dir` at the paths of their keys, e.g.
+    /// `dir/games/tetris.sav`, created on the first store.
+    pub fn in_dir(dir: impl Into<PathBuf>) -> Self {
+        FileStorage {
+            dir: Some(dir.into()),
+        }
+    }
+
+    /// Where the file of `key` is.
+    pub fn path(&self, key: &Path) -> PathBuf {
+        match &self.dir {
+            // Roots and `..` are left out so that every key stays in `dir`
+            Some(dir) => key
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .fold(dir.clone(), |path, component| path.join(component)),
+            None => key.to_path_buf(),
+        }
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn load(&self, key: &Path) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn store(&self, key: &Path, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key);
+        if self.dir.is_some()
+            && let Some(parent) = path.parent()
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, data)
+    }
+
+    fn remove(&self, key: &Path) -> io::Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn location(&self, key: &Path) -> String {
+        self.path(key).display().to_string()
+    }
+}
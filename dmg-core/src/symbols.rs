@@ -0,0 +1,68 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+
+/// Labels of a ROM from a `.sym` file as RGBDS and other assemblers write it,
+/// one `BB:AAAA Name` per line with the bank and address in hex.
+///
+/// Local labels (`Name.local`) are kept but belong to the label before them
+/// when looking up the routine an address is in.
+#[derive(Clone, Default, Debug)]
+pub struct SymbolTable {
+    symbols: BTreeMap<(u16, u16), String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    /// Parse a `.sym` file, None when a line isn't a symbol or a `;` comment.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut table = SymbolTable::new();
+
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (location, name) = line.split_once(char::is_whitespace)?;
+            let (bank, address) = location.split_once(':')?;
+            table.insert(
+                u16::from_str_radix(bank, 16).ok()?,
+                u16::from_str_radix(address, 16).ok()?,
+                name.trim(),
+            );
+        }
+
+        Some(table)
+    }
+
+    /// Add a symbol, replacing one at the same location.
+    pub fn insert(&mut self, bank: u16, address: u16, name: &str) {
+        self.symbols.insert((bank, address), name.to_string());
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// The symbol at exactly this location.
+    pub fn get(&self, bank: u16, address: u16) -> Option<&str> {
+        self.symbols.get(&(bank, address)).map(String::as_str)
+    }
+
+    /// The closest label that isn't local at or before the location in the
+    /// same bank, with the distance to it.
+    pub fn containing(&self, bank: u16, address: u16) -> Option<(&str, u16)> {
+        self.symbols
+            .range((bank, 0)..=(bank, address))
+            .rev()
+            .find(|(_, name)| !name.contains('.'))
+            .map(|((_, start), name)| (name.as_str(), address - start))
+    }
+}
@@ -0,0 +1,166 @@
+use bitflags::bitflags;
+
+use crate::{bus::HardwareRegister, interrupts::InterruptFlag};
+
+use super::interrupts::InterruptRequest;
+use super::power::Quirks;
+use super::state::{Resettable, Saveable, StateError, StateReader, StateWriter};
+
+bitflags!(
+    pub struct TacRegister: u8 {
+        const ENABLE = 0b100;
+        const CLOCK1 = 0b010;
+        const CLOCK0 = 0b001;
+    }
+);
+
+/// TIMA, TMA and TAC. The dots they count come from the `SystemCounter`,
+/// methods that depend on its phase take its value.
+pub struct Timer {
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: TacRegister,
+    // Set by the emulated model, kept on reset
+    quirks: Quirks,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer {
+            tima: 0,
+            tma: 0,
+            tac: TacRegister::from_bits_truncate(0),
+            quirks: Quirks::empty(),
+        }
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// TIMA, TMA or TAC, other addresses read as an open bus.
+    pub fn read(&self, address: u16) -> u8 {
+        match HardwareRegister::from_u16(address) {
+            Some(HardwareRegister::TIMA) => self.tima,
+            Some(HardwareRegister::TMA) => self.tma,
+            // Unused bits read as 1
+            Some(HardwareRegister::TAC) => self.tac.bits() | 0xF8,
+            _ => 0xFF,
+        }
+    }
+
+    /// Write TIMA, TMA or TAC with the system counter at `counter`, writes
+    /// to other addresses are ignored. DIV writes clear the counter, see
+    /// `counter_cleared`.
+    pub fn write<I: InterruptRequest>(
+        &mut self,
+        address: u16,
+        value: u8,
+        counter: u16,
+        ctx: &mut I,
+    ) {
+        let input = self.input(counter);
+
+        match HardwareRegister::from_u16(address) {
+            Some(HardwareRegister::TIMA) => self.tima = value,
+            Some(HardwareRegister::TMA) => self.tma = value,
+            Some(HardwareRegister::TAC) => self.tac = TacRegister::from_bits_truncate(value),
+            _ => return,
+        }
+
+        self.count_write_edge(input, self.input(counter), ctx);
+    }
+
+    /// The system counter was cleared from `before`, through DIV or by
+    /// STOP. A selected bit that was set falls.
+    pub fn counter_cleared<I: InterruptRequest>(&mut self, before: u16, ctx: &mut I) {
+        self.count_write_edge(self.input(before), self.input(0), ctx);
+    }
+
+    /// TIMA counts falling edges of its input, not only those from counting.
+    fn count_write_edge<I: InterruptRequest>(&mut self, before: bool, after: bool, ctx: &mut I) {
+        if self.quirks.contains(Quirks::TIMER_WRITE_EDGE) && before && !after {
+            self.increment_tima(ctx);
+        }
+    }
+
+    /// The DIV register acts as the source clock,
+    /// specific bits of DIV are used to trigger TIMA updates:
+    ///     DIV[9] for 4096 Hz.
+    ///     DIV[3] for 262144 Hz.
+    ///     DIV[5] for 65536 Hz.
+    ///     DIV[7] for 16384 Hz.
+    fn selected_bit(&self) -> u32 {
+        match self.tac.bits() & 0b11 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        }
+    }
+
+    /// The selected bit of the system counter gated by the enable bit.
+    fn input(&self, counter: u16) -> bool {
+        self.tac.contains(TacRegister::ENABLE) && counter & (1 << self.selected_bit()) != 0
+    }
+
+    /// Dots from the system counter at `counter` until the next TIMA
+    /// increment, None while the timer is stopped. TIMA is counted by
+    /// `increment_tima` at that time.
+    pub fn next_increment(&self, counter: u16) -> Option<u64> {
+        if !self.tac.contains(TacRegister::ENABLE) {
+            return None;
+        }
+
+        let bit = self.selected_bit();
+
+        // The bit falls when the bits up to it wrap around
+        let period = 1u64 << (bit + 1);
+        Some(period - counter as u64 % period)
+    }
+
+    /// Count a falling edge of the selected DIV bit.
+    pub fn increment_tima<I: InterruptRequest>(&mut self, ctx: &mut I) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+
+        // Counting past $FF reloads TMA
+        if overflow {
+            self.tima = self.tma;
+            ctx.request_interrupt(InterruptFlag::TIMER);
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resettable for Timer {
+    fn reset(&mut self) {
+        let quirks = self.quirks;
+        *self = Timer::new();
+        self.quirks = quirks;
+    }
+}
+
+impl Saveable for Timer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.tima);
+        state.write_u8(self.tma);
+        state.write_u8(self.tac.bits());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.tima = state.read_u8()?;
+        self.tma = state.read_u8()?;
+        self.tac = TacRegister::from_bits_truncate(state.read_u8()?);
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+use alloc::vec::Vec;
+
+use crate::bus::HardwareRegister;
+use crate::lcd::LcdControl;
+use crate::png;
+use crate::ppu::{PPU, SpriteFlags};
+
+/// Tiles per row of the tile sheet.
+const SHEET_COLUMNS: usize = 16;
+/// Tiles in VRAM, 0x8000 - 0x97FF.
+pub const TILE_COUNT: usize = 384;
+
+/// Set of tile numbers, 0 is the tile at 0x8000 and 383 the one at 0x97F0.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TileSet {
+    bits: [u64; TILE_COUNT / 64],
+}
+
+impl TileSet {
+    pub fn new() -> Self {
+        TileSet::default()
+    }
+
+    pub fn all() -> Self {
+        TileSet {
+            bits: [u64::MAX; TILE_COUNT / 64],
+        }
+    }
+
+    pub fn insert(&mut self, tile: usize) {
+        self.bits[tile / 64] |= 1 << (tile % 64);
+    }
+
+    pub fn contains(&self, tile: usize) -> bool {
+        self.bits[tile / 64] & (1 << (tile % 64)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|bits| *bits == 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|bits| bits.count_ones() as usize)
+            .sum()
+    }
+
+    /// Add the tiles of `other`.
+    pub fn extend(&mut self, other: &TileSet) {
+        for (bits, other) in self.bits.iter_mut().zip(other.bits) {
+            *bits |= other;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..TILE_COUNT).filter(|tile| self.contains(*tile))
+    }
+}
+
+/// VRAM contents decoded to an image.
+pub struct VramImage {
+    pub width: usize,
+    pub height: usize,
+    /// Pixels as `0xAARRGGBB` values, row by row
+    pub pixels: Vec<u32>,
+}
+
+impl VramImage {
+    fn new(width: usize, height: usize) -> Self {
+        VramImage {
+            width,
+            height,
+            pixels: alloc::vec![0; width * height],
+        }
+    }
+
+    /// Pixels as bytes in R, G, B, A order, for textures and image libraries.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.pixels.len() * 4);
+
+        for pixel in &self.pixels {
+            let [a, r, g, b] = pixel.to_be_bytes();
+            rgba.extend_from_slice(&[r, g, b, a]);
+        }
+
+        rgba
+    }
+
+    /// The image encoded as a PNG file.
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode_rgba(self.width as u32, self.height as u32, &self.to_rgba())
+    }
+
+    /// Draw the tile starting at `address` with its top left corner at `x`, `y`.
+    fn draw_tile(&mut self, ppu: &PPU, address: u16, x: usize, y: usize, colors: &[u32; 4]) {
+        let data: [u8; 16] = core::array::from_fn(|i| ppu.vram_read(address + i as u16));
+
+        for (row, indices) in decode_tile(&data).iter().enumerate() {
+            let offset = (y + row) * self.width + x;
+            for (pixel, index) in self.pixels[offset..offset + 8].iter_mut().zip(indices) {
+                *pixel = colors[*index as usize];
+            }
+        }
+    }
+}
+
+/// Color indices 0 to 3 of the 8x8 pixels of a tile, row by row, from its
+/// 16 bytes of tile data: two bit planes per row, low plane first.
+pub fn decode_tile(data: &[u8; 16]) -> [[u8; 8]; 8] {
+    core::array::from_fn(|row| {
+        let (lo, hi) = (data[row * 2], data[row * 2 + 1]);
+        core::array::from_fn(|bit| (((hi >> (7 - bit)) & 1) << 1) | ((lo >> (7 - bit)) & 1))
+    })
+}
+
+/// Colors of the four shades of a palette register like BGP or OBP0, from
+/// the presentation colors of `base`.
+fn register_colors(register: u8, base: &[u32; 4]) -> [u32; 4] {
+    core::array::from_fn(|index| base[((register >> (index * 2)) & 0b11) as usize])
+}
+
+/// One of the two 32x32 tile maps.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileMapArea {
+    /// 0x9800 - 0x9BFF
+    Low,
+    /// 0x9C00 - 0x9FFF
+    High,
+}
+
+impl TileMapArea {
+    pub fn address(self) -> u16 {
+        match self {
+            TileMapArea::Low => 0x9800,
+            TileMapArea::High => 0x9C00,
+        }
+    }
+
+    fn select(high: bool) -> Self {
+        if high {
+            TileMapArea::High
+        } else {
+            TileMapArea::Low
+        }
+    }
+}
+
+/// How the background and window find the tile of a tile number.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileDataArea {
+    /// Numbers 0 to 255 from 0x8000, like sprites
+    Unsigned,
+    /// Numbers -128 to 127 from 0x9000
+    Signed,
+}
+
+impl TileDataArea {
+    /// Address of the tile data of tile `number`.
+    pub fn tile_address(self, number: u8) -> u16 {
+        match self {
+            TileDataArea::Unsigned => 0x8000 + number as u16 * 16,
+            TileDataArea::Signed => 0x9000u16.wrapping_add_signed(number as i8 as i16 * 16),
+        }
+    }
+}
+
+/// An entry of OAM.
+#[derive(Copy, Clone, Debug)]
+pub struct Sprite {
+    /// OAM index, 0 to 39
+    pub index: u8,
+    /// Position as stored, the screen position plus 16
+    pub y: u8,
+    /// Position as stored, the screen position plus 8
+    pub x: u8,
+    pub tile: u8,
+    pub flags: SpriteFlags,
+}
+
+impl Sprite {
+    /// Top left corner in screen pixels, negative or past the screen when
+    /// the sprite is partly or wholly hidden.
+    pub fn screen_position(&self) -> (i32, i32) {
+        (self.x as i32 - 8, self.y as i32 - 16)
+    }
+}
+
+/// All 384 tiles, 16 per row, in tile data order.
+///
+/// Tiles are shown with their raw color indices since the same tile can be
+/// used with any of the palettes.
+pub fn tile_sheet(ppu: &PPU) -> VramImage {
+    let colors = ppu.frame().palette().background;
+    let mut image = VramImage::new(SHEET_COLUMNS * 8, TILE_COUNT / SHEET_COLUMNS * 8);
+
+    for tile in 0..TILE_COUNT {
+        let (x, y) = ((tile % SHEET_COLUMNS) * 8, (tile / SHEET_COLUMNS) * 8);
+        image.draw_tile(ppu, 0x8000 + tile as u16 * 16, x, y, &colors);
+    }
+
+    image
+}
+
+/// One tile, 0 to 383 like `TileSet`, in `colors` by color index. `None`
+/// past the last tile.
+pub fn tile(ppu: &PPU, tile: usize, colors: &[u32; 4]) -> Option<VramImage> {
+    if tile >= TILE_COUNT {
+        return None;
+    }
+
+    let mut image = VramImage::new(8, 8);
+    image.draw_tile(ppu, 0x8000 + tile as u16 * 16, 0, 0, colors);
+    Some(image)
+}
+
+/// A whole 256x256 tile map with the tiles of `data` in the colors of BGP,
+/// whatever LCDC selects.
+pub fn tile_map(ppu: &PPU, map: TileMapArea, data: TileDataArea) -> VramImage {
+    let bgp = ppu.lcd_read(HardwareRegister::BGP);
+    let colors = register_colors(bgp, &ppu.frame().palette().background);
+    let mut image = VramImage::new(256, 256);
+
+    for tile in 0..32 * 32u16 {
+        let number = ppu.vram_read(map.address() + tile);
+        let (x, y) = ((tile % 32) as usize * 8, (tile / 32) as usize * 8);
+        image.draw_tile(ppu, data.tile_address(number), x, y, &colors);
+    }
+
+    image
+}
+
+/// Tile data area LCDC selects for the background and the window.
+fn lcdc_tile_data(lcdc: LcdControl) -> TileDataArea {
+    if lcdc.contains(LcdControl::BG_WINDOW_TILE_DATA_AREA) {
+        TileDataArea::Unsigned
+    } else {
+        TileDataArea::Signed
+    }
+}
+
+/// The whole 256x256 background map selected by LCDC, with the tile data
+/// area from LCDC and the colors of BGP.
+pub fn background_map(ppu: &PPU) -> VramImage {
+    let lcdc = LcdControl::from_bits_truncate(ppu.lcd_read(HardwareRegister::LCDC));
+    let map = TileMapArea::select(lcdc.contains(LcdControl::BG_TILE_MAP_AREA));
+    tile_map(ppu, map, lcdc_tile_data(lcdc))
+}
+
+/// The tile map LCDC selects for the window, like `background_map`. Only
+/// the top left corner of it is ever shown.
+pub fn window_map(ppu: &PPU) -> VramImage {
+    let lcdc = LcdControl::from_bits_truncate(ppu.lcd_read(HardwareRegister::LCDC));
+    let map = TileMapArea::select(lcdc.contains(LcdControl::WINDOW_TILE_MAP_AREA));
+    tile_map(ppu, map, lcdc_tile_data(lcdc))
+}
+
+/// The 40 entries of OAM.
+pub fn sprites(ppu: &PPU) -> Vec<Sprite> {
+    (0..40u8)
+        .map(|index| {
+            let address = 0xFE00 + index as u16 * 4;
+            Sprite {
+                index,
+                y: ppu.oam_read(address),
+                x: ppu.oam_read(address + 1),
+                tile: ppu.oam_read(address + 2),
+                flags: SpriteFlags::from_bits_truncate(ppu.oam_read(address + 3)),
+            }
+        })
+        .collect()
+}
+
+/// Height of sprites in pixels, 8 or 16 as LCDC selects.
+pub fn sprite_height(ppu: &PPU) -> usize {
+    let lcdc = LcdControl::from_bits_truncate(ppu.lcd_read(HardwareRegister::LCDC));
+    if lcdc.contains(LcdControl::OBJ_SIZE) {
+        16
+    } else {
+        8
+    }
+}
+
+/// A sprite as it's drawn: flipped, in the colors of its OBP register and
+/// with color 0 transparent. 8x16 sprites ignore bit 0 of the tile number.
+pub fn sprite(ppu: &PPU, sprite: &Sprite) -> VramImage {
+    let height = sprite_height(ppu);
+    let mut image = VramImage::new(8, height);
+    draw_sprite(ppu, &mut image, sprite, 0, 0);
+    image
+}
+
+/// All 40 sprites in OAM order, 8 per row, for a sprite viewer.
+pub fn oam_sheet(ppu: &PPU) -> VramImage {
+    let height = sprite_height(ppu);
+    let mut image = VramImage::new(8 * 8, 5 * height);
+
+    for sprite in sprites(ppu) {
+        let index = sprite.index as usize;
+        draw_sprite(
+            ppu,
+            &mut image,
+            &sprite,
+            (index % 8) * 8,
+            (index / 8) * height,
+        );
+    }
+
+    image
+}
+
+fn draw_sprite(ppu: &PPU, image: &mut VramImage, sprite: &Sprite, x: usize, y: usize) {
+    let height = sprite_height(ppu);
+    let palette = ppu.frame().palette();
+    let (obp, base) = if sprite.flags.contains(SpriteFlags::DMG_PALETTE) {
+        (ppu.lcd_read(HardwareRegister::OBP1), &palette.object1)
+    } else {
+        (ppu.lcd_read(HardwareRegister::OBP0), &palette.object0)
+    };
+    let mut colors = register_colors(obp, base);
+    colors[0] = 0;
+
+    let first = if height == 16 {
+        sprite.tile & 0xFE
+    } else {
+        sprite.tile
+    };
+
+    for half in 0..height / 8 {
+        let address = 0x8000 + (first as u16 + half as u16) * 16;
+        let data: [u8; 16] = core::array::from_fn(|i| ppu.vram_read(address + i as u16));
+
+        for (row, indices) in decode_tile(&data).iter().enumerate() {
+            let mut row = half * 8 + row;
+            if sprite.flags.contains(SpriteFlags::Y_FLIP) {
+                row = height - 1 - row;
+            }
+
+            for (column, index) in indices.iter().enumerate() {
+                let column = if sprite.flags.contains(SpriteFlags::X_FLIP) {
+                    7 - column
+                } else {
+                    column
+                };
+                image.pixels[(y + row) * image.width + x + column] = colors[*index as usize];
+            }
+        }
+    }
+}
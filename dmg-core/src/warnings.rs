@@ -0,0 +1,219 @@
+use alloc::collections::BTreeMap;
+#[cfg(feature = "ram-tracking")]
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::clock::EmuClock;
+
+/// Code that runs on hardware by luck or not as intended, things a ROM
+/// developer wants to hear about.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WarningKind {
+    /// VRAM written during mode 3 or OAM during modes 2 and 3, the PPU
+    /// drops the write
+    RestrictedWrite,
+    /// WRAM or HRAM read before anything was written there, random on power
+    /// on. Only with the `ram-tracking` feature
+    UninitializedRead,
+    /// One of the 11 opcodes that lock up the CPU
+    IllegalOpcode,
+    /// A push outside WRAM and HRAM, like a stack at the top of HRAM growing
+    /// into the IO registers or one wrapping around from $0000
+    StackOverflow,
+    /// A pop outside WRAM and HRAM, more returns than calls
+    StackUnderflow,
+    /// A write to the restart and interrupt vectors or the header,
+    /// $0000 - $014F, that isn't a mapper operation: a ROM only cartridge
+    /// ignores it, a mapper takes it as a RAM disable. Only with the
+    /// header guard, see `Emulator::set_header_guard`
+    HeaderWrite,
+}
+
+impl WarningKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningKind::RestrictedWrite => "restricted-write",
+            WarningKind::UninitializedRead => "uninitialized-read",
+            WarningKind::IllegalOpcode => "illegal-opcode",
+            WarningKind::StackOverflow => "stack-overflow",
+            WarningKind::StackUnderflow => "stack-underflow",
+            WarningKind::HeaderWrite => "header-write",
+        }
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Every time one instruction ran into the same kind of problem.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    /// ROM bank of the instruction, 0 outside switchable ROM
+    pub bank: u16,
+    pub pc: u16,
+    /// Address accessed the first time, `pc` for illegal opcodes
+    pub address: u16,
+    pub count: u64,
+    /// When it happened the first time
+    pub clock: EmuClock,
+}
+
+/// One bit per byte of WRAM and HRAM, set once the CPU wrote it.
+#[cfg(feature = "ram-tracking")]
+#[derive(Clone)]
+struct RamShadow {
+    written: Vec<u64>,
+}
+
+#[cfg(feature = "ram-tracking")]
+impl Default for RamShadow {
+    fn default() -> Self {
+        RamShadow {
+            written: vec![0; (Self::WRAM_SIZE + Self::HRAM_SIZE) / 64],
+        }
+    }
+}
+
+#[cfg(feature = "ram-tracking")]
+impl RamShadow {
+    const WRAM_SIZE: usize = 0x2000;
+    // HRAM rounded up to whole words
+    const HRAM_SIZE: usize = 0x80;
+
+    // Echo RAM is the same memory as WRAM
+    fn index(address: u16) -> Option<usize> {
+        match address {
+            0xC000..=0xFDFF => Some((address - 0xC000) as usize % Self::WRAM_SIZE),
+            0xFF80..=0xFFFE => Some(Self::WRAM_SIZE + (address - 0xFF80) as usize),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, address: u16) {
+        if let Some(index) = Self::index(address) {
+            self.written[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    fn is_written(&self, address: u16) -> Option<bool> {
+        Self::index(address).map(|index| self.written[index / 64] & (1 << (index % 64)) != 0)
+    }
+}
+
+/// Diagnostics for homebrew, one entry per kind and instruction. With the
+/// `ram-tracking` feature it also knows which bytes of WRAM and HRAM were
+/// written since it was set, to tell reads of uninitialized memory. That
+/// costs a lookup on every access, so it's left out of normal builds.
+#[derive(Clone, Default)]
+pub struct WarningLog {
+    warnings: BTreeMap<(WarningKind, u16, u16), Warning>,
+    #[cfg(feature = "ram-tracking")]
+    written: RamShadow,
+}
+
+impl WarningLog {
+    pub fn new() -> Self {
+        WarningLog::default()
+    }
+
+    /// Count a warning, true the first time for this kind at `bank:pc`.
+    pub(crate) fn record(
+        &mut self,
+        kind: WarningKind,
+        bank: u16,
+        pc: u16,
+        address: u16,
+        clock: EmuClock,
+    ) -> bool {
+        let mut first = false;
+        self.warnings
+            .entry((kind, bank, pc))
+            .or_insert_with(|| {
+                first = true;
+                Warning {
+                    kind,
+                    bank,
+                    pc,
+                    address,
+                    count: 0,
+                    clock,
+                }
+            })
+            .count += 1;
+        first
+    }
+
+    #[cfg(feature = "ram-tracking")]
+    pub(crate) fn record_write(&mut self, address: u16) {
+        self.written.set(address);
+    }
+
+    /// True when `address` is WRAM or HRAM that wasn't written yet.
+    #[cfg(feature = "ram-tracking")]
+    pub(crate) fn is_uninitialized(&self, address: u16) -> bool {
+        self.written.is_written(address) == Some(false)
+    }
+
+    /// Whether the CPU wrote `address` since the log was set, None outside
+    /// WRAM and HRAM.
+    #[cfg(feature = "ram-tracking")]
+    pub fn is_written(&self, address: u16) -> Option<bool> {
+        self.written.is_written(address)
+    }
+
+    /// Warnings in the order they first happened.
+    pub fn warnings(&self) -> Vec<&Warning> {
+        let mut warnings: Vec<&Warning> = self.warnings.values().collect();
+        warnings.sort_by_key(|warning| (warning.clock, warning.kind));
+        warnings
+    }
+
+    pub fn count(&self, kind: WarningKind) -> u64 {
+        self.warnings
+            .values()
+            .filter(|warning| warning.kind == kind)
+            .map(|warning| warning.count)
+            .sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.warnings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+/// One tab separated line per warning under a header, for reading and
+/// for tools alike.
+impl fmt::Display for WarningLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "kind\tlocation\taddress\tcount\tfirst cycle\tframe\tline:dot"
+        )?;
+        for warning in self.warnings() {
+            let clock = warning.clock;
+            writeln!(
+                f,
+                "{}\t{:02X}:{:04X}\t${:04X}\t{}\t{}\t{}\t{:03}:{:03}",
+                warning.kind,
+                warning.bank,
+                warning.pc,
+                warning.address,
+                warning.count,
+                clock.ticks,
+                clock.frame,
+                clock.ly,
+                clock.dot
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use crate::cpu::{CPU, Register};
+use crate::emu::Emulator;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(u16),
+    Register(Register),
+    /// ROM bank mapped at $4000
+    Bank,
+    /// Byte at an address
+    Memory(Box<Expr>),
+    Sum(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Value and whether it's a byte.
+    fn evaluate(&self, cpu: &CPU<Emulator>) -> (u16, bool) {
+        match self {
+            Expr::Number(value) => (*value, false),
+            Expr::Register(register) if register.is_16bit() => {
+                (cpu.registers().read16(*register), false)
+            }
+            Expr::Register(register) => (cpu.registers().read8(*register) as u16, true),
+            Expr::Bank => {
+                let bank = cpu
+                    .context()
+                    .cartridge()
+                    .map_or(0, |rom| rom.bank_of(0x4000));
+                (bank, bank <= 0xFF)
+            }
+            Expr::Memory(address) => {
+                let address = address.evaluate(cpu).0;
+                (cpu.context().inspect(address).unwrap_or(0xFF) as u16, true)
+            }
+            Expr::Sum(left, right) => {
+                let (left, left_byte) = left.evaluate(cpu);
+                let (right, right_byte) = right.evaluate(cpu);
+                (left.wrapping_add(right), left_byte && right_byte)
+            }
+        }
+    }
+}
+
+/// A value shown while the game runs, for ROM hacking and debugging.
+///
+/// Expressions are registers (`A`, `HL`, `SP`...), `bank` for the ROM bank at
+/// $4000, hex numbers with an optional `$`, bytes of memory in brackets and
+/// sums of those: `[C0A5]`, `[HL+1]`, `[$FF00+C]`. Names of registers win
+/// over hex numbers, `$C` is the number.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watch {
+    text: String,
+    expr: Expr,
+}
+
+impl Watch {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let source: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut parser = Parser {
+            source: &source,
+            position: 0,
+        };
+
+        let expr = parser.sum()?;
+        if parser.position != source.len() {
+            return Err(format!(
+                "Unexpected '{}' in watch {text}",
+                &source[parser.position..]
+            ));
+        }
+
+        Ok(Watch {
+            text: text.trim().to_string(),
+            expr,
+        })
+    }
+
+    /// The expression as it was typed.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn evaluate(&self, cpu: &CPU<Emulator>) -> u16 {
+        self.expr.evaluate(cpu).0
+    }
+
+    /// The value in hex, two digits for bytes and four for words.
+    pub fn show(&self, cpu: &CPU<Emulator>) -> String {
+        match self.expr.evaluate(cpu) {
+            (value, true) => format!("{value:02X}"),
+            (value, false) => format!("{value:04X}"),
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.atom()?;
+
+        while self.eat('+') {
+            expr = Expr::Sum(Box::new(expr), Box::new(self.atom()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        if self.eat('[') {
+            let address = self.sum()?;
+            if !self.eat(']') {
+                return Err("Missing ] in watch".to_string());
+            }
+            return Ok(Expr::Memory(Box::new(address)));
+        }
+
+        let hex = self.eat('$');
+        let rest = &self.source[self.position..];
+        let word = &rest[..rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len())];
+        if word.is_empty() {
+            return Err(format!("Expected a value in watch at '{rest}'"));
+        }
+        self.position += word.len();
+
+        let name = word.to_ascii_uppercase();
+        if !hex {
+            if name == "BANK" {
+                return Ok(Expr::Bank);
+            }
+            if let Some(register) = register(&name) {
+                return Ok(Expr::Register(register));
+            }
+        }
+
+        let digits = name.strip_prefix("0X").unwrap_or(&name);
+        u16::from_str_radix(digits, 16)
+            .map(Expr::Number)
+            .map_err(|_| format!("Unknown value {word} in watch"))
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.source[self.position..].starts_with(c);
+        if found {
+            self.position += c.len_utf8();
+        }
+        found
+    }
+}
+
+/// Register of an upper case name like `A` or `HL`.
+pub(crate) fn register(name: &str) -> Option<Register> {
+    Some(match name {
+        "A" => Register::A,
+        "F" => Register::F,
+        "B" => Register::B,
+        "C" => Register::C,
+        "D" => Register::D,
+        "E" => Register::E,
+        "H" => Register::H,
+        "L" => Register::L,
+        "AF" => Register::AF,
+        "BC" => Register::BC,
+        "DE" => Register::DE,
+        "HL" => Register::HL,
+        "SP" => Register::SP,
+        "PC" => Register::PC,
+        _ => return None,
+    })
+}
@@ -0,0 +1,66 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::hash;
+
+/// Flags a likely lock-up: the picture and the addresses the CPU executed
+/// stayed the same for a number of frames in a row.
+///
+/// A game waiting for input on a still screen looks just the same, the
+/// threshold should be several seconds to keep those apart.
+pub struct HangWatchdog {
+    frames: u32,
+    // Bit per address executed during the current frame
+    executed: Vec<u8>,
+    // Hashes of the last frame and its executed addresses
+    last: Option<(u64, u64)>,
+    unchanged: u32,
+    reported: bool,
+}
+
+impl HangWatchdog {
+    /// Watchdog flagging `frames` frames without change.
+    pub fn new(frames: u32) -> Self {
+        HangWatchdog {
+            frames,
+            executed: vec![0; 0x10000 / 8],
+            last: None,
+            unchanged: 0,
+            reported: false,
+        }
+    }
+
+    pub(crate) fn record(&mut self, pc: u16) {
+        self.executed[pc as usize / 8] |= 1 << (pc % 8);
+    }
+
+    /// Compare the frame that just completed with the one before.
+    pub(crate) fn end_frame(&mut self, frame_hash: u64) {
+        let hashes = (frame_hash, hash::fnv1a64(&self.executed));
+        self.executed.fill(0);
+
+        if self.last == Some(hashes) {
+            self.unchanged = self.unchanged.saturating_add(1);
+        } else {
+            self.last = Some(hashes);
+            self.unchanged = 0;
+            self.reported = false;
+        }
+    }
+
+    /// Frames in a row that looked like the one before.
+    pub fn unchanged_frames(&self) -> u32 {
+        self.unchanged
+    }
+
+    /// True once per lock-up, when the threshold is reached. Another is only
+    /// reported after something changed.
+    pub fn take_hang(&mut self) -> bool {
+        if self.reported || self.unchanged < self.frames {
+            return false;
+        }
+
+        self.reported = true;
+        true
+    }
+}
@@ -0,0 +1,116 @@
+use dmg_core::apu::APU;
+use dmg_core::state::{Saveable, StateReader, StateWriter};
+
+const WAVE_RAM: u16 = 0xFF30;
+
+/// Powered on APU with wave RAM holding 0x00, 0x11 .. 0xFF and channel 3
+/// triggered with a period of 32 dots, so the first fetch is 38 dots away.
+fn playing_wave(quirks: bool) -> APU {
+    let mut apu = APU::new();
+    apu.set_wave_ram_quirks(quirks);
+    apu.write(0xFF26, 0x80);
+
+    for i in 0..16 {
+        apu.write(WAVE_RAM + i, i as u8 * 0x11);
+    }
+
+    apu.write(0xFF1A, 0x80);
+    apu.write(0xFF1D, 0xF0);
+    apu.write(0xFF1E, 0x87);
+    apu
+}
+
+fn tick(apu: &mut APU, dots: u32) {
+    for _ in 0..dots {
+        apu.tick();
+    }
+}
+
+fn save(apu: &APU) -> Vec<u8> {
+    let mut state = StateWriter::new();
+    apu.save_state(&mut state);
+    state.into_bytes()
+}
+
+#[test]
+fn wave_ram_reads_the_fetched_byte_while_playing() {
+    let mut apu = playing_wave(true);
+    assert_eq!(apu.read(WAVE_RAM), 0xFF);
+
+    // The first fetch is the second sample, in byte 0
+    tick(&mut apu, 38);
+    assert_eq!(apu.read(0xFF3F), 0x00);
+    tick(&mut apu, 1);
+    assert_eq!(apu.read(0xFF3F), 0x00);
+    tick(&mut apu, 1);
+    assert_eq!(apu.read(WAVE_RAM), 0xFF);
+
+    // Samples 2 and 3 are in byte 1
+    tick(&mut apu, 30);
+    assert_eq!(apu.read(WAVE_RAM), 0x11);
+
+    let mut apu = playing_wave(false);
+    tick(&mut apu, 40);
+    assert_eq!(apu.read(0xFF3F), 0xFF);
+    assert_eq!(apu.read(0xFF31), 0x11);
+}
+
+#[test]
+fn wave_ram_writes_only_reach_the_fetched_byte_while_playing() {
+    let mut apu = playing_wave(true);
+    apu.write(0xFF35, 0xAB);
+
+    tick(&mut apu, 70);
+    apu.write(0xFF35, 0xCD);
+
+    apu.set_wave_ram_quirks(false);
+    assert_eq!(apu.read(0xFF31), 0xCD);
+    assert_eq!(apu.read(0xFF35), 0x55);
+}
+
+#[test]
+fn retriggering_wave_on_a_fetch_corrupts_wave_ram() {
+    // Sample 9 plays and the fetch of sample 10, in byte 5, is 1 dot away
+    let mut apu = playing_wave(true);
+    tick(&mut apu, 38 + 32 * 8 + 31);
+    apu.write(0xFF1E, 0x87);
+
+    apu.set_wave_ram_quirks(false);
+    let bytes: Vec<u8> = (0..8).map(|i| apu.read(WAVE_RAM + i)).collect();
+    assert_eq!(bytes, [0x44, 0x55, 0x66, 0x77, 0x44, 0x55, 0x66, 0x77]);
+
+    // Within the first 4 bytes only byte 0 changes
+    let mut apu = playing_wave(true);
+    tick(&mut apu, 38 + 31);
+    apu.write(0xFF1E, 0x87);
+
+    apu.set_wave_ram_quirks(false);
+    assert_eq!(apu.read(WAVE_RAM), 0x11);
+    assert_eq!(apu.read(0xFF31), 0x11);
+
+    let mut apu = playing_wave(false);
+    tick(&mut apu, 38 + 31);
+    apu.write(0xFF1E, 0x87);
+    assert_eq!(apu.read(WAVE_RAM), 0x00);
+}
+
+#[test]
+fn channel_phase_survives_a_savestate() {
+    let mut apu = playing_wave(true);
+    apu.write(0xFF12, 0xF0);
+    apu.write(0xFF14, 0x80);
+    apu.write(0xFF21, 0xF0);
+    apu.write(0xFF22, 0x21);
+    apu.write(0xFF23, 0x80);
+    tick(&mut apu, 1234);
+
+    let mut restored = APU::new();
+    restored
+        .load_state(&mut StateReader::new(&save(&apu)))
+        .unwrap();
+    assert_eq!(save(&restored), save(&apu));
+
+    tick(&mut apu, 777);
+    tick(&mut restored, 777);
+    assert_eq!(save(&restored), save(&apu));
+}
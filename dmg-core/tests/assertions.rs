@@ -0,0 +1,65 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::assertions::{Check, TestScript};
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::Register;
+use dmg_core::headless::Headless;
+
+/// Store 5 at $C000 and send "OK" over the serial port.
+fn emulator() -> Headless {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x05,       // $0150 LD A, 5
+        0xEA, 0x00, 0xC0, // $0152 LD ($C000), A
+        0x3E, 0x4F,       // $0155 LD A, 'O'
+        0xCD, 0x70, 0x01, // $0157 CALL send
+        0x3E, 0x4B,       // $015A LD A, 'K'
+        0xCD, 0x70, 0x01, // $015C CALL send
+        0x18, 0xFE,       // $015F JR -2
+    ];
+    #[rustfmt::skip]
+    let send: &[u8] = &[
+        0xE0, 0x01,       // $0170 send: LDH (SB), A
+        0x3E, 0x81,       // LD A, $81
+        0xE0, 0x02,       // LDH (SC), A
+        0xF0, 0x02,       // wait: LDH A, (SC)
+        0xCB, 0x7F,       // BIT 7, A
+        0x20, 0xFA,       // JR NZ, wait
+        0xC9,             // RET
+    ];
+    let rom = build_rom(&[(0x150, main), (0x170, send)]);
+    Headless::new(Cartridge::from_bytes("assert.gb", &rom).unwrap())
+}
+
+#[test]
+fn assertions_pass_when_the_rom_does_its_job() {
+    TestScript::new()
+        .at_frame(3, Check::memory(0xC000, 5))
+        .at_frame(3, Check::register(Register::PC, 0x15F))
+        .by_cycle(1_000_000, Check::serial("OK"))
+        .by_frame(2, Check::equals("[C000] + 1", 6).unwrap())
+        .run(&mut emulator())
+        .unwrap();
+}
+
+#[test]
+fn failed_assertions_say_what_was_found() {
+    let failure = TestScript::new()
+        .at_frame(2, Check::memory(0xC000, 6))
+        .run(&mut emulator())
+        .unwrap_err();
+    assert_eq!(failure.assertion, "at frame 2: [$C000] == 06");
+    assert_eq!(failure.actual, "was 05");
+
+    let failure = TestScript::new()
+        .by_cycle(10_000, Check::serial("Passed"))
+        .run(&mut emulator())
+        .unwrap_err();
+    assert!(failure.ticks >= 10_000);
+    assert_eq!(
+        failure.assertion,
+        "by cycle 10000: serial output contains \"Passed\""
+    );
+    assert_eq!(failure.actual, "output was \"OK\"");
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{CLOCK_HZ, build_rom};
+use dmg_core::audio::{AudioOutput, Fader};
+use dmg_core::cart::Cartridge;
+use dmg_core::headless::Headless;
+
+/// Output collecting the chunks it is handed.
+fn collecting_output(
+    sample_rate: u32,
+    chunk_frames: usize,
+) -> (AudioOutput, Arc<Mutex<Vec<Vec<f32>>>>) {
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+    let sink = chunks.clone();
+    let output = AudioOutput::new(
+        Box::new(move |samples: &[f32]| sink.lock().unwrap().push(samples.to_vec())),
+        sample_rate,
+        chunk_frames,
+    );
+    (output, chunks)
+}
+
+#[test]
+fn pause_fades_out_and_resume_fades_in() {
+    let mut fader = Fader::new(4);
+    let mut samples = [1.0; 8];
+    fader.apply(&mut samples);
+    assert_eq!(samples, [1.0; 8]);
+
+    fader.pause();
+    let mut samples = [1.0; 12];
+    fader.apply(&mut samples);
+    assert_eq!(
+        samples,
+        [
+            0.75, 0.75, 0.5, 0.5, 0.25, 0.25, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0
+        ]
+    );
+    assert!(fader.is_silent());
+
+    fader.resume();
+    assert!(!fader.is_silent());
+    let mut samples = [1.0; 4];
+    fader.apply(&mut samples);
+    assert_eq!(samples, [0.25, 0.25, 0.5, 0.5]);
+}
+
+#[test]
+fn output_comes_in_chunks_of_interleaved_stereo() {
+    let (mut output, chunks) = collecting_output(48000, 100);
+
+    for _ in 0..CLOCK_HZ {
+        output.push((0.5, -0.5));
+    }
+
+    let chunks = chunks.lock().unwrap();
+    assert_eq!(chunks.len(), 480);
+    assert!(chunks.iter().all(|chunk| chunk.len() == 200));
+
+    // The high-pass filter lets the step through, then takes the offset away
+    let first = &chunks[0];
+    assert_eq!(&first[..2], [0.5, -0.5]);
+    let last = chunks.last().unwrap();
+    assert!(last[198] < 0.5 && last[198] > 0.0);
+    assert!(last[199] > -0.5 && last[199] < 0.0);
+}
+
+#[test]
+fn flush_hands_over_a_partial_chunk() {
+    let (mut output, chunks) = collecting_output(48000, 512);
+
+    // Dots of 48 frames, 1 ms
+    for _ in 0..4195 {
+        output.push((0.0, 0.0));
+    }
+    assert!(chunks.lock().unwrap().is_empty());
+
+    output.flush();
+    assert_eq!(chunks.lock().unwrap()[0].len(), 96);
+}
+
+#[test]
+fn paused_output_fades_to_silence() {
+    let (mut output, chunks) = collecting_output(48000, 480);
+
+    output.pause();
+    // 10 ms of a DC offset, twice the fade
+    for _ in 0..41943 {
+        output.push((0.5, 0.5));
+    }
+    output.flush();
+    assert!(output.is_silent());
+
+    let chunks = chunks.lock().unwrap();
+    let samples = &chunks[0];
+    assert!(samples[0] > 0.0 && samples[0] < 0.5);
+    assert!(samples[..470].iter().all(|&sample| sample > 0.0));
+    assert!(samples[480..].iter().all(|&sample| sample == 0.0));
+
+    drop(chunks);
+    output.resume();
+    assert!(!output.is_silent());
+}
+
+#[test]
+fn emulator_feeds_its_audio_output() {
+    // JR -2
+    let rom = build_rom(&[(0x150, &[0x18, 0xFE])]);
+    let mut emu = Headless::new(Cartridge::from_bytes("loop.gb", &rom).unwrap());
+    let (output, chunks) = collecting_output(44100, 441);
+    emu.emulator_mut().set_audio_output(Some(output));
+
+    assert!(emu.run_frames(60));
+
+    // 60 frames are a little over a second
+    let chunks = chunks.lock().unwrap();
+    assert_eq!(chunks.len(), 100);
+    assert!(chunks.iter().all(|chunk| chunk.len() == 882));
+}
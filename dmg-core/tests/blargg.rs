@@ -0,0 +1,95 @@
+mod common;
+
+use common::{CLOCK_HZ, rom_path, save_diagnostics};
+use dmg_core::emu::{AccuracyConfig, AccuracyLevel};
+use dmg_core::headless::Headless;
+
+/// Run one of Blargg's test ROMs until it reports a result over the serial port.
+fn run_blargg(rom: &str, seconds: u64) {
+    let Some(path) = rom_path(rom) else {
+        return;
+    };
+
+    let mut emu = Headless::from_file(path.to_str().unwrap()).unwrap();
+    let result = emu.run_until_serial(&["Passed", "Failed"], seconds * CLOCK_HZ);
+
+    if result != Some("Passed") {
+        save_diagnostics(&emu, rom);
+    }
+
+    assert_eq!(
+        result,
+        Some("Passed"),
+        "{rom} did not pass, serial output:\n{}",
+        emu.serial_output()
+    );
+}
+
+/// Run one of Blargg's sound test ROMs at the accurate preset until it
+/// reports a result in cartridge RAM.
+fn run_blargg_sound(rom: &str, seconds: u64) {
+    let Some(path) = rom_path(rom) else {
+        return;
+    };
+
+    let mut emu = Headless::from_file(path.to_str().unwrap()).unwrap();
+    let accuracy = AccuracyConfig::preset(AccuracyLevel::Accurate);
+    emu.emulator_mut().set_accuracy(accuracy);
+    let result = emu.run_until_ram_result(seconds * CLOCK_HZ);
+
+    if result != Some(0) {
+        save_diagnostics(&emu, rom);
+    }
+
+    assert_eq!(
+        result,
+        Some(0),
+        "{rom} did not pass, output:\n{}",
+        emu.ram_output()
+    );
+}
+
+macro_rules! blargg_tests {
+    ($runner:ident { $($name:ident: $rom:expr, $seconds:expr;)* }) => {
+        $(
+            #[test]
+            fn $name() {
+                $runner($rom, $seconds);
+            }
+        )*
+    };
+}
+
+blargg_tests!(run_blargg {
+    cpu_instrs_01_special: "cpu_instrs/individual/01-special.gb", 10;
+    cpu_instrs_02_interrupts: "cpu_instrs/individual/02-interrupts.gb", 10;
+    cpu_instrs_03_op_sp_hl: "cpu_instrs/individual/03-op sp,hl.gb", 10;
+    cpu_instrs_04_op_r_imm: "cpu_instrs/individual/04-op r,imm.gb", 10;
+    cpu_instrs_05_op_rp: "cpu_instrs/individual/05-op rp.gb", 10;
+    cpu_instrs_06_ld_r_r: "cpu_instrs/individual/06-ld r,r.gb", 10;
+    cpu_instrs_07_jr_jp_call_ret_rst: "cpu_instrs/individual/07-jr,jp,call,ret,rst.gb", 10;
+    cpu_instrs_08_misc_instrs: "cpu_instrs/individual/08-misc instrs.gb", 10;
+    cpu_instrs_09_op_r_r: "cpu_instrs/individual/09-op r,r.gb", 20;
+    cpu_instrs_10_bit_ops: "cpu_instrs/individual/10-bit ops.gb", 20;
+    cpu_instrs_11_op_a_hl: "cpu_instrs/individual/11-op a,(hl).gb", 20;
+    instr_timing: "instr_timing/instr_timing.gb", 10;
+    mem_timing_01_read_timing: "mem_timing/individual/01-read_timing.gb", 10;
+    mem_timing_02_write_timing: "mem_timing/individual/02-write_timing.gb", 10;
+    mem_timing_03_modify_timing: "mem_timing/individual/03-modify_timing.gb", 10;
+});
+
+// cgb_sound needs a CGB, only the DMG suite runs
+blargg_tests!(run_blargg_sound {
+    dmg_sound_01_registers: "dmg_sound/rom_singles/01-registers.gb", 10;
+    dmg_sound_02_len_ctr: "dmg_sound/rom_singles/02-len ctr.gb", 10;
+    dmg_sound_03_trigger: "dmg_sound/rom_singles/03-trigger.gb", 10;
+    dmg_sound_04_sweep: "dmg_sound/rom_singles/04-sweep.gb", 10;
+    dmg_sound_05_sweep_details: "dmg_sound/rom_singles/05-sweep details.gb", 10;
+    dmg_sound_06_overflow_on_trigger: "dmg_sound/rom_singles/06-overflow on trigger.gb", 10;
+    dmg_sound_07_len_sweep_period_sync: "dmg_sound/rom_singles/07-len sweep period sync.gb", 10;
+    dmg_sound_08_len_ctr_during_power: "dmg_sound/rom_singles/08-len ctr during power.gb", 10;
+    dmg_sound_09_wave_read_while_on: "dmg_sound/rom_singles/09-wave read while on.gb", 10;
+    dmg_sound_10_wave_trigger_while_on: "dmg_sound/rom_singles/10-wave trigger while on.gb", 10;
+    dmg_sound_11_regs_after_power: "dmg_sound/rom_singles/11-regs after power.gb", 10;
+    dmg_sound_12_wave_write_while_on: "dmg_sound/rom_singles/12-wave write while on.gb", 10;
+});
@@ -0,0 +1,137 @@
+mod common;
+
+use common::{CLOCK_HZ, build_rom};
+use dmg_core::camera::{SENSOR_HEIGHT, SENSOR_WIDTH};
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::CpuContext;
+use dmg_core::headless::Headless;
+use dmg_core::png;
+
+/// POCKET CAMERA program that sets a neutral exposure and an even dithering
+/// matrix, takes a picture and maps the first RAM bank back in.
+fn build_test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x0A,             // LD A, $0A
+        0xEA, 0x00, 0x00,       // LD ($0000), A    ; enable RAM
+        0x3E, 0x10,             // LD A, $10
+        0xEA, 0x00, 0x40,       // LD ($4000), A    ; select the camera registers
+        0xEA, 0x02, 0xA0,       // LD ($A002), A
+        0xAF,                   // XOR A
+        0xEA, 0x03, 0xA0,       // LD ($A003), A    ; exposure $1000
+        0x21, 0x06, 0xA0,       // LD HL, $A006
+        0x06, 0x10,             // LD B, 16
+        0x3E, 0x40,             // matrix: LD A, $40
+        0x22,                   // LD (HL+), A
+        0x3E, 0x80,             // LD A, $80
+        0x22,                   // LD (HL+), A
+        0x3E, 0xC0,             // LD A, $C0
+        0x22,                   // LD (HL+), A
+        0x05,                   // DEC B
+        0x20, 0xF4,             // JR NZ, matrix
+        0x3E, 0x01,             // LD A, $01
+        0xEA, 0x00, 0xA0,       // LD ($A000), A    ; capture
+        0xFA, 0x00, 0xA0,       // wait: LD A, ($A000)
+        0xE6, 0x01,             // AND $01
+        0x20, 0xF9,             // JR NZ, wait
+        0xAF,                   // XOR A
+        0xEA, 0x00, 0x40,       // LD ($4000), A    ; RAM bank 0
+        0x18, 0xFE,             // JR -2
+    ];
+
+    build_rom(&[(0x147, &[0xFC, 0x00, 0x04]), (0x150, main)])
+}
+
+#[test]
+fn capture_dithers_the_image_into_tiles() {
+    let mut rom = Cartridge::from_bytes("camera.gb", &build_test_rom()).unwrap();
+    // White left half, grey and black columns on the right
+    let image: Vec<u8> = (0..SENSOR_WIDTH * SENSOR_HEIGHT)
+        .map(|i| match i % SENSOR_WIDTH {
+            0..64 => 0xFF,
+            64..72 => 0x90,
+            _ => 0x00,
+        })
+        .collect();
+    rom.set_camera_image(&image);
+    let mut emu = Headless::new(rom);
+
+    while emu.ticks() < CLOCK_HZ / 4 {
+        assert!(emu.step());
+    }
+
+    let emulator = emu.emulator_mut();
+    // Every tile row is a low and a high bit plane, the picture starts at $A100
+    let mut tile_row = |tile: u16| {
+        [
+            emulator.peek(0xA100 + tile * 16),
+            emulator.peek(0xA101 + tile * 16),
+        ]
+    };
+    assert_eq!(tile_row(0), [0x00, 0x00]);
+    assert_eq!(tile_row(8), [0xFF, 0x00]);
+    assert_eq!(tile_row(15), [0xFF, 0xFF]);
+}
+
+#[test]
+fn decodes_compressed_filtered_png() {
+    // 8x8 RGB image compressed by zlib with dynamic Huffman codes, the rows
+    // use the filters none, sub, up, average and Paeth in turn
+    #[rustfmt::skip]
+    let file: &[u8] = &[
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D,
+    0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x08,
+    0x08, 0x02, 0x00, 0x00, 0x00, 0x4B, 0x6D, 0x29, 0xDC, 0x00, 0x00, 0x00,
+    0x69, 0x49, 0x44, 0x41, 0x54, 0x78, 0xDA, 0x75, 0x8E, 0xB1, 0x0E, 0xC2,
+    0x30, 0x0C, 0x44, 0x5F, 0x12, 0xA7, 0x9C, 0x58, 0xDA, 0x81, 0xA1, 0x2C,
+    0xA8, 0x12, 0xCB, 0x8D, 0x1D, 0xFB, 0xFF, 0x5F, 0x56, 0x07, 0x16, 0xA4,
+    0x0A, 0xEB, 0x0D, 0x96, 0xEF, 0x7C, 0x36, 0x80, 0x60, 0x81, 0x15, 0x36,
+    0x30, 0xEC, 0x70, 0x40, 0xC9, 0xB1, 0x28, 0x57, 0xEA, 0xF0, 0x2B, 0xE5,
+    0x6C, 0x1A, 0x0A, 0xD4, 0xD1, 0x84, 0x6E, 0x2D, 0x53, 0x22, 0x6A, 0x44,
+    0xFB, 0xF0, 0xAD, 0x3E, 0x18, 0x1B, 0x19, 0x48, 0x85, 0x06, 0xF1, 0x83,
+    0x91, 0xFB, 0xE2, 0xFB, 0xEA, 0x79, 0xF3, 0xC3, 0x7E, 0xEE, 0x7E, 0x1D,
+    0x7E, 0x97, 0x7C, 0x42, 0x4C, 0x57, 0xFE, 0x1E, 0x3F, 0x01, 0xF6, 0x15,
+    0x05, 0xAB, 0x4E, 0x40, 0x96, 0x72, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45,
+    0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+    ];
+
+    let (width, height, rgba) = png::decode_rgba(file).unwrap();
+    assert_eq!((width, height), (8, 8));
+
+    for (i, pixel) in rgba.chunks(4).enumerate() {
+        let (x, y) = (i as u8 % 8, i as u8 / 8);
+        assert_eq!(pixel, [x * 8, y * 8, x * y, 0xFF], "pixel {x}, {y}");
+    }
+}
+
+#[test]
+fn decodes_what_the_encoder_writes() {
+    let rgba: Vec<u8> = (0..=255).cycle().take(20 * 10 * 4).collect();
+    let file = png::encode_rgba(20, 10, &rgba);
+
+    assert_eq!(png::decode_rgba(&file), Ok((20, 10, rgba)));
+    assert_eq!(png::decode_rgba(b"GIF89a"), Err(png::PngError::NotPng));
+}
+
+#[test]
+fn rejects_empty_and_huge_images() {
+    let file = png::encode_rgba(2, 2, &[0; 16]);
+    // Width and height follow the signature and the IHDR length and type
+    let with_size = |width: u32, height: u32| {
+        let mut file = file.clone();
+        file[16..20].copy_from_slice(&width.to_be_bytes());
+        file[20..24].copy_from_slice(&height.to_be_bytes());
+        png::decode_rgba(&file)
+    };
+
+    assert_eq!(with_size(0, 2), Err(png::PngError::Corrupt));
+    assert_eq!(with_size(2, 0), Err(png::PngError::Corrupt));
+    assert_eq!(
+        with_size(u32::MAX, 2),
+        Err(png::PngError::Unsupported("image size"))
+    );
+    assert_eq!(
+        with_size(0x4000_0000, 0x4000_0000),
+        Err(png::PngError::Unsupported("image size"))
+    );
+}
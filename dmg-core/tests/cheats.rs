@@ -0,0 +1,99 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::cheats::{CheatCode, CheatList};
+use dmg_core::cpu::CpuContext;
+use dmg_core::headless::Headless;
+
+fn idle_rom() -> Cartridge {
+    // JR -2
+    let rom = build_rom(&[(0x150, &[0x18, 0xFE])]);
+    Cartridge::from_bytes("cheats.gb", &rom).unwrap()
+}
+
+#[test]
+fn codes_decode() {
+    assert_eq!(
+        CheatCode::parse("421-51F").unwrap(),
+        CheatCode::GameGenie {
+            address: 0x0151,
+            value: 0x42,
+            compare: None
+        }
+    );
+    assert_eq!(
+        CheatCode::parse("015500C0").unwrap(),
+        CheatCode::GameShark {
+            address: 0xC000,
+            value: 0x55
+        }
+    );
+    assert!(CheatCode::parse("12345").is_err());
+}
+
+#[test]
+fn cheat_file_round_trip() {
+    let text = "# comment\non 015500C0 Infinite lives\noff 421-51F\n";
+    let cheats = CheatList::parse(text).unwrap();
+    let enabled: Vec<_> = cheats.iter().map(|c| c.enabled).collect();
+
+    assert_eq!(enabled, [true, false]);
+    assert_eq!(cheats.iter().next().unwrap().description, "Infinite lives");
+    assert_eq!(
+        CheatList::parse(&cheats.to_string()).unwrap().to_string(),
+        cheats.to_string()
+    );
+    assert!(CheatList::parse("maybe 015500C0").is_err());
+}
+
+#[test]
+fn cheats_patch_rom_and_ram() {
+    let mut emu = Headless::new(idle_rom());
+    emu.emulator_mut()
+        .set_cheats(CheatList::parse("on 421-51F\non 015500C0").unwrap());
+
+    assert_eq!(emu.emulator_mut().peek(0x0151), 0x42);
+
+    emu.run_frames(2);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x55);
+}
+
+#[test]
+fn frozen_address_survives_writes() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0x00, 0xC0,   // LD HL, $C000
+        0x34,               // loop: INC (HL)
+        0x18, 0xFD,         // JR loop
+    ];
+    let rom = Cartridge::from_bytes("freeze.gb", &build_rom(&[(0x150, main)])).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.emulator_mut().freeze(0xC000, 7);
+
+    emu.run_frames(1);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 7);
+
+    emu.emulator_mut().unfreeze(0xC000);
+    emu.run_frames(1);
+    assert_ne!(emu.emulator_mut().peek(0xC000), 7);
+}
+
+#[test]
+fn frozen_register_is_written_once() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x77,         // LD A, $77
+        0xE0, 0x24,         // LDH ($24), A     ; NR50
+        0xAF,               // XOR A
+        0xE0, 0x26,         // LDH ($26), A     ; NR52, APU off
+        0x18, 0xFE,         // JR @
+    ];
+    let rom = Cartridge::from_bytes("freeze.gb", &build_rom(&[(0x150, main)])).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.emulator_mut().freeze(0xFF26, 0x80);
+
+    // The APU never powers off, so it doesn't clear NR50
+    emu.run_frames(1);
+    assert_eq!(emu.emulator_mut().peek(0xFF24), 0x77);
+}
@@ -0,0 +1,84 @@
+// Not every test binary uses every helper
+#![allow(dead_code)]
+
+use std::env;
+use std::path::PathBuf;
+
+use dmg_core::headless::Headless;
+use dmg_core::interrupts::{InterruptFlag, InterruptRequest};
+
+/// T-cycles per emulated second.
+pub const CLOCK_HZ: u64 = 4_194_304;
+
+/// Locate a test ROM relative to the test ROM directory.
+///
+/// The directory is taken from `DMG_TEST_ROMS` and defaults to `tests/roms`.
+/// Returns None when the ROM is missing so that suites which were not
+/// downloaded are skipped instead of failing.
+pub fn rom_path(relative: &str) -> Option<PathBuf> {
+    let root = match env::var("DMG_TEST_ROMS") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms"),
+    };
+    let path = root.join(relative);
+
+    if path.exists() {
+        Some(path)
+    } else {
+        eprintln!("Skipping, test ROM {} not found", path.display());
+        None
+    }
+}
+
+/// Save the last frame, a state and the registers of a failed run.
+///
+/// Files go to the directory set in `DMG_TEST_DIAGNOSTICS`, by default
+/// `diagnostics` in Cargo's temporary directory for integration tests.
+pub fn save_diagnostics(emu: &Headless, name: &str) {
+    let dir = match env::var("DMG_TEST_DIAGNOSTICS") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("diagnostics"),
+    };
+    // File names can't contain the directories of the ROM path
+    let name = name.replace(['/', '\\'], "_");
+
+    match emu.save_diagnostics(&dir, &name) {
+        Ok(()) => eprintln!("Saved diagnostics for {name} to {}", dir.display()),
+        Err(e) => eprintln!("Failed to save diagnostics for {name}: {e}"),
+    }
+}
+
+/// Build a 32 KiB ROM ONLY cartridge image with a valid header.
+///
+/// Each section is copied to its address, the entry point at 0x100 jumps to 0x150
+/// so that the program itself should be placed there.
+pub fn build_rom(sections: &[(u16, &[u8])]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // NOP; JP $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x134..0x13C].copy_from_slice(b"TESTROM\0");
+
+    for (address, bytes) in sections {
+        let start = *address as usize;
+        rom[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    let mut checksum: u8 = 0;
+    for byte in &rom[0x134..=0x14C] {
+        checksum = checksum.wrapping_sub(*byte).wrapping_sub(1);
+    }
+    rom[0x14D] = checksum;
+
+    rom
+}
+
+/// Collects the interrupts a component requests, to step it without an
+/// emulator around it.
+#[derive(Default)]
+pub struct Requests(pub Vec<InterruptFlag>);
+
+impl InterruptRequest for Requests {
+    fn request_interrupt(&mut self, flag: InterruptFlag) {
+        self.0.push(flag);
+    }
+}
@@ -0,0 +1,34 @@
+use dmg_core::compress::{compress, decompress};
+
+#[test]
+fn compressed_data_round_trips() {
+    let mut noise = Vec::new();
+    let mut seed = 0x1234_5678u32;
+    for _ in 0..5000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        noise.push((seed >> 16) as u8);
+    }
+
+    let mut mixed = vec![0; 40_000];
+    mixed.extend_from_slice(&noise);
+    mixed.extend(b"tile".iter().cycle().take(3000));
+
+    for data in [&[][..], b"short", &noise, &mixed] {
+        let packed = compress(data);
+        assert_eq!(decompress(&packed, data.len()).as_deref(), Some(data));
+    }
+
+    assert!(compress(&mixed).len() < mixed.len() / 4);
+}
+
+#[test]
+fn malformed_data_is_rejected() {
+    let packed = compress(&[7; 1000]);
+
+    assert_eq!(decompress(&packed, 999), None);
+    assert_eq!(decompress(&packed[..packed.len() - 1], 1000), None);
+    // Match reaching back before the start of the output
+    assert_eq!(decompress(&[0x10, 0xAA, 0x02, 0x00], 5), None);
+    // Length more than the block could ever expand to
+    assert_eq!(decompress(&packed, usize::MAX), None);
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::OpcodeCoverage;
+use dmg_core::headless::Headless;
+
+#[test]
+fn counts_executed_opcodes() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x01,         // LD A, $01
+        0xCB, 0x37,         // SWAP A
+        0x18, 0xFE,         // JR -2
+    ];
+
+    let rom = Cartridge::from_bytes("coverage.gb", &build_rom(&[(0x150, main)])).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.cpu_mut().set_coverage(Some(OpcodeCoverage::new()));
+    emu.run_frames(1);
+
+    let coverage = emu.cpu().coverage().unwrap();
+    assert_eq!(coverage.count(0x3E), 1);
+    assert_eq!(coverage.count(0xCB), 1);
+    assert_eq!(coverage.count(0x137), 1);
+    assert!(coverage.count(0x18) > 1);
+    assert!(
+        coverage
+            .unexercised()
+            .all(|index| ![0x3E, 0x137].contains(&index))
+    );
+    // Illegal opcodes are never reported
+    assert!(coverage.unexercised().all(|index| index != 0xD3));
+
+    let mut merged = OpcodeCoverage::parse(&coverage.to_string()).unwrap();
+    merged.merge(coverage);
+    assert_eq!(merged.count(0x137), 2);
+    assert_eq!(merged.executed(), coverage.executed());
+    assert_eq!(OpcodeCoverage::mnemonic(0x3E), "LD A, n8");
+}
@@ -0,0 +1,443 @@
+mod common;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use common::build_rom;
+use dmg_core::bus::HardwareRegister;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::{TextTrace, TraceConfig, TraceRecord, TraceSink};
+use dmg_core::emu::RestrictedWrites;
+use dmg_core::headless::Headless;
+use dmg_core::lcd::LcdMode;
+use dmg_core::mapper_log::{MapperLog, MapperRegister, MapperWrite};
+use dmg_core::ppu::PpuEvent;
+use dmg_core::warnings::{WarningKind, WarningLog};
+use dmg_core::watch::Watch;
+
+fn run_to(emu: &mut Headless, event: PpuEvent) {
+    emu.emulator_mut().set_ppu_break(Some(event));
+
+    for _ in 0..100_000 {
+        assert!(emu.step());
+
+        if let Some(reached) = emu.emulator_mut().take_ppu_break() {
+            assert_eq!(reached, event);
+            return;
+        }
+    }
+
+    panic!("{event} not reached");
+}
+
+#[test]
+fn runs_to_ppu_transitions() {
+    let rom = build_rom(&[(0x150, &[0x18, 0xFE])]); // JR -2
+    let mut emu = Headless::new(Cartridge::from_bytes("ppu.gb", &rom).unwrap());
+    assert!(emu.run_frames(1));
+
+    run_to(&mut emu, PpuEvent::LineStart(100));
+    let ppu = emu.emulator().ppu();
+    assert_eq!(ppu.lcd_read(HardwareRegister::LY), 100);
+    assert_eq!(ppu.mode(), LcdMode::OAM);
+
+    let frame = ppu.get_current_frame();
+    run_to(&mut emu, PpuEvent::VBlank);
+    let ppu = emu.emulator().ppu();
+    assert_eq!(ppu.lcd_read(HardwareRegister::LY), 144);
+    assert_eq!(ppu.get_current_frame(), frame + 1);
+
+    // Only once
+    assert_eq!(emu.emulator().ppu_break(), None);
+    emu.run_frames(1);
+    assert_eq!(emu.emulator_mut().take_ppu_break(), None);
+}
+
+#[test]
+fn writes_the_ppu_blocks_are_caught() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x01,       // loop: LD A, $01
+        0xEA, 0x00, 0x80, // LD ($8000), A
+        0xEA, 0x00, 0xFE, // LD ($FE00), A
+        0x18, 0xF6,       // JR loop
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("vram.gb", &rom).unwrap());
+    emu.emulator_mut()
+        .set_restricted_writes(RestrictedWrites::Break);
+    assert!(emu.run_frames(1));
+
+    let message = emu.emulator_mut().take_restricted_write().unwrap();
+    assert!(
+        message == "$0152 wrote $8000 during mode 3"
+            || message.starts_with("$0155 wrote $FE00 during mode"),
+        "{message}"
+    );
+
+    // Nothing is blocked with the LCD off
+    #[rustfmt::skip]
+    let lcd_off: &[u8] = &[
+        0xAF,             // XOR A
+        0xE0, 0x40,       // LDH (LCDC), A
+        0xEA, 0x00, 0x80, // loop: LD ($8000), A
+        0x18, 0xFB,       // JR loop
+    ];
+    let rom = build_rom(&[(0x150, lcd_off)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("lcd_off.gb", &rom).unwrap());
+    emu.emulator_mut()
+        .set_restricted_writes(RestrictedWrites::Break);
+    emu.run_frames(1);
+    assert_eq!(emu.emulator_mut().take_restricted_write(), None);
+}
+
+#[test]
+fn homebrew_mistakes_are_warned_about() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x31, 0x81, 0xFF, // $0150 LD SP, $FF81
+        0xC5,             // $0153 PUSH BC
+        0x31, 0xFE, 0xFF, // $0154 LD SP, $FFFE
+        0x06, 0x00,       // $0157 LD B, 0
+        0xEA, 0x00, 0x80, // $0159 loop: LD ($8000), A
+        0x05,             // $015C DEC B
+        0x20, 0xFA,       // $015D JR NZ, loop
+        0xD3,             // $015F illegal
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("homebrew.gb", &rom).unwrap());
+    emu.emulator_mut().set_warnings(Some(WarningLog::new()));
+    emu.cpu_mut().set_report_faults(true);
+    assert!(emu.try_run_frames(1).is_err());
+
+    let warnings = emu.emulator().warnings().unwrap();
+    let found: Vec<(WarningKind, u16, u16)> = warnings
+        .warnings()
+        .iter()
+        .map(|warning| (warning.kind, warning.pc, warning.address))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (WarningKind::StackOverflow, 0x153, 0xFF7F),
+            (WarningKind::RestrictedWrite, 0x159, 0x8000),
+            (WarningKind::IllegalOpcode, 0x15F, 0x15F),
+        ]
+    );
+    assert!(warnings.count(WarningKind::RestrictedWrite) > 1);
+    assert!(
+        warnings
+            .to_string()
+            .contains("stack-overflow\t00:0153\t$FF7F\t1\t")
+    );
+
+    let first = warnings.warnings()[0].clock;
+    assert_eq!(first.frame, 0);
+    assert!(first.ticks > 0 && first.dot < 456);
+}
+
+#[test]
+#[cfg(feature = "ram-tracking")]
+fn reads_of_unwritten_ram_are_warned_about() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0xFA, 0x00, 0xC0, // $0150 LD A, ($C000)
+        0xEA, 0x01, 0xC0, // $0153 LD ($C001), A
+        0xFA, 0x01, 0xE0, // $0156 LD A, ($E001), echo of $C001
+        0xF0, 0x90,       // $0159 LDH A, ($FF90)
+        0x18, 0xFE,       // $015B JR -2
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("uninit.gb", &rom).unwrap());
+    emu.emulator_mut().set_warnings(Some(WarningLog::new()));
+    emu.run_frames(1);
+
+    let warnings = emu.emulator().warnings().unwrap();
+    let found: Vec<(u16, u16)> = warnings
+        .warnings()
+        .iter()
+        .map(|warning| (warning.pc, warning.address))
+        .collect();
+    assert_eq!(found, [(0x150, 0xC000), (0x159, 0xFF90)]);
+    assert_eq!(warnings.is_written(0xC001), Some(true));
+    assert_eq!(warnings.is_written(0x8000), None);
+}
+
+#[test]
+fn watches_show_registers_and_memory() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0xA4, 0xC0, // LD HL, $C0A4
+        0x3E, 0x3F,       // LD A, $3F
+        0xEA, 0xA5, 0xC0, // LD ($C0A5), A
+        0x18, 0xFE,       // JR -2
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("watch.gb", &rom).unwrap());
+    emu.run_frames(1);
+
+    let show = |text: &str| Watch::parse(text).unwrap().show(emu.cpu());
+    assert_eq!(show("[C0A5]"), "3F");
+    assert_eq!(show("[hl + 1]"), "3F");
+    assert_eq!(show("HL"), "C0A4");
+    assert_eq!(show("a"), "3F");
+    assert_eq!(show("$C + 1"), "000D");
+    assert_eq!(show("bank"), "01");
+
+    assert!(Watch::parse("[C0A5").is_err());
+    assert!(Watch::parse("HL+").is_err());
+    assert!(Watch::parse("XYZ").is_err());
+}
+
+/// Trace lines kept where the test can read them.
+#[derive(Clone, Default)]
+struct SharedTrace(Arc<Mutex<String>>);
+
+impl fmt::Write for SharedTrace {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.lock().unwrap().push_str(s);
+        Ok(())
+    }
+}
+
+/// Records kept as they are.
+#[derive(Clone, Default)]
+struct SharedRecords(Arc<Mutex<Vec<TraceRecord>>>);
+
+impl TraceSink for SharedRecords {
+    fn record(&mut self, record: &TraceRecord) {
+        self.0.lock().unwrap().push(*record);
+    }
+}
+
+/// Switch to ROM bank 1 on an MBC3 cartridge and loop there.
+fn banked_emulator() -> Headless {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x01,       // LD A, 1
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0xC3, 0x00, 0x40, // JP $4000
+    ];
+    let banked: &[u8] = &[0x00, 0x18, 0xFE]; // NOP, JR -2
+    // MBC3
+    let rom = build_rom(&[(0x147, &[0x11]), (0x150, main), (0x4000, banked)]);
+    Headless::new(Cartridge::from_bytes("trace.gb", &rom).unwrap())
+}
+
+#[test]
+fn traces_show_the_bank_and_mapper_registers() {
+    let mut emu = banked_emulator();
+    let trace = SharedTrace::default();
+    emu.cpu_mut()
+        .set_trace(Some(Box::new(TextTrace(trace.clone()))));
+
+    for _ in 0..6 {
+        emu.step();
+    }
+    assert!(emu.cpu_mut().take_trace().is_some());
+    emu.step();
+
+    let text = trace.0.lock().unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert!(lines[2].contains(" - 00:0150: LD A,"), "{}", lines[2]);
+    assert!(lines[2].ends_with(" MBC 0000=00 2000=01 4000=00"));
+    assert!(lines[5].contains(" - 01:4000: NOP"), "{}", lines[5]);
+    assert!(lines[5].ends_with(" MBC 0000=00 2000=01 4000=00"));
+}
+
+#[test]
+fn binary_trace_records_read_back() {
+    let mut emu = banked_emulator();
+    let records = SharedRecords::default();
+    emu.cpu_mut().set_trace(Some(Box::new(records.clone())));
+    for _ in 0..6 {
+        emu.step();
+    }
+
+    let records = records.0.lock().unwrap();
+    let mut data = Vec::new();
+    for record in records.iter() {
+        record.encode(&mut data);
+    }
+    assert_eq!(data.len(), 6 * TraceRecord::SIZE);
+
+    for (record, chunk) in records.iter().zip(data.chunks(TraceRecord::SIZE)) {
+        let decoded = TraceRecord::decode(chunk).unwrap();
+        assert_eq!(decoded.to_string(), record.to_string());
+        assert_eq!(decoded.clock, record.clock);
+        assert_eq!(decoded.mapper, Some([0x00, 0x01, 0x00]));
+    }
+    assert!(TraceRecord::decode(&data[..TraceRecord::SIZE - 1]).is_none());
+}
+
+#[test]
+fn traces_show_interrupt_dispatches_with_the_ppu_position() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0xAF,             // XOR A
+        0xE0, 0x0F,       // LDH ($0F), A    ; drop the boot ROM's requests
+        0x3C,             // INC A
+        0xE0, 0xFF,       // LDH ($FF), A    ; VBlank only
+        0xFB,             // EI
+        0x76,             // loop: HALT
+        0x18, 0xFD,       // JR loop
+    ];
+    let rom = build_rom(&[(0x40, &[0xD9]), (0x150, main)]); // RETI
+    let mut emu = Headless::new(Cartridge::from_bytes("vblank.gb", &rom).unwrap());
+    let trace = SharedTrace::default();
+    emu.cpu_mut()
+        .set_trace(Some(Box::new(TextTrace(trace.clone()))));
+    emu.run_frames(2);
+
+    let text = trace.0.lock().unwrap();
+    let line = text
+        .lines()
+        .find(|line| line.ends_with(" - interrupt vblank"))
+        .unwrap();
+    assert!(line.contains(" 144:"), "{line}");
+
+    let handler = text.lines().find(|line| line.contains(":0040: RETI"));
+    assert!(handler.unwrap().contains(" 144:"));
+}
+
+#[test]
+fn traces_are_limited_to_a_range_and_start_at_an_address() {
+    let traced = |config: TraceConfig, steps: usize| -> Vec<u16> {
+        let mut emu = banked_emulator();
+        let records = SharedRecords::default();
+        emu.cpu_mut().set_trace(Some(Box::new(records.clone())));
+        emu.cpu_mut().set_trace_config(config);
+        for _ in 0..steps {
+            emu.step();
+        }
+        records.0.lock().unwrap().iter().map(|r| r.pc).collect()
+    };
+
+    let in_bank = TraceConfig {
+        range: Some(0x4000..=0x7FFF),
+        ..TraceConfig::default()
+    };
+    assert_eq!(traced(in_bank, 8), [0x4000, 0x4001, 0x4001]);
+
+    let from_jump = TraceConfig {
+        enabled: false,
+        start_at: Some(0x155),
+        ..TraceConfig::default()
+    };
+    assert_eq!(traced(from_jump, 7), [0x155, 0x4000, 0x4001]);
+
+    let off = TraceConfig {
+        enabled: false,
+        ..TraceConfig::default()
+    };
+    assert!(traced(off, 6).is_empty());
+}
+
+#[test]
+fn traces_follow_instructions_with_their_memory_accesses() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        0x36, 0x42,       // LD (HL), $42
+        0x18, 0xFE,       // JR -2
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("access.gb", &rom).unwrap());
+    let trace = SharedTrace::default();
+    emu.cpu_mut()
+        .set_trace(Some(Box::new(TextTrace(trace.clone()))));
+    emu.cpu_mut().set_trace_config(TraceConfig {
+        range: Some(0x153..=0x153),
+        memory: true,
+        ..TraceConfig::default()
+    });
+    for _ in 0..5 {
+        emu.step();
+    }
+
+    let text = trace.0.lock().unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 4, "{text}");
+    assert!(lines[0].contains(":0153: "), "{text}");
+    assert!(lines[1].ends_with(" - 0153: M0 R $0153 $36"), "{text}");
+    assert!(lines[2].ends_with(" - 0153: M1 R $0154 $42"), "{text}");
+    assert!(lines[3].ends_with(" - 0153: M2 W $C000 $42"), "{text}");
+}
+
+#[test]
+fn rom_writes_are_logged_as_mapper_operations() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x0A,       // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A
+        0x3E, 0x02,       // LD A, 2
+        0xEA, 0x00, 0x20, // LD ($2000), A
+        0x3E, 0x08,       // LD A, 8
+        0xEA, 0x00, 0x40, // LD ($4000), A
+        0x18, 0xFE,       // JR -2
+    ];
+    // MBC3 with RTC
+    let rom = build_rom(&[(0x147, &[0x10]), (0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("mapper.gb", &rom).unwrap());
+    emu.emulator_mut()
+        .set_mapper_log(Some(MapperLog::default()));
+
+    for _ in 0..10 {
+        emu.step();
+    }
+
+    let log = emu.emulator().mapper_log().unwrap();
+    let writes: Vec<&MapperWrite> = log.writes().collect();
+    assert_eq!(writes.len(), 3);
+    assert_eq!(writes[0].register, MapperRegister::RamEnable);
+    assert_eq!((writes[0].pc, writes[0].address), (0x152, 0x0000));
+    assert!(!writes[0].before.ram_enabled && writes[0].after.ram_enabled);
+    assert_eq!(writes[1].register, MapperRegister::RomBank);
+    assert_eq!(
+        (writes[1].before.rom_bank, writes[1].after.rom_bank),
+        (1, 2)
+    );
+    assert_eq!(writes[2].register, MapperRegister::RamBank);
+    assert_eq!(writes[2].after.ram_bank, 8);
+
+    let text = log.to_string();
+    let line = text.lines().nth(2).unwrap();
+    assert!(
+        line.ends_with("\t00:0157\t$2000=02\trom-bank\tROM 01 RAM 00 on\tROM 02 RAM 00 on"),
+        "{line}"
+    );
+}
+
+#[test]
+fn mapper_state_is_reported() {
+    let mut emu = banked_emulator();
+    let report = emu.emulator().cartridge().unwrap().mapper_report();
+    assert_eq!(report.name, "MBC3");
+    assert_eq!(report.ram_enabled, Some(false));
+    assert!(report.rtc.is_none());
+
+    emu.emulator_mut().poke(0x0000, 0x0A);
+    emu.emulator_mut().poke(0x2000, 0x05);
+    emu.emulator_mut().poke(0x4000, 0x02);
+    let text = emu
+        .emulator()
+        .cartridge()
+        .unwrap()
+        .mapper_report()
+        .to_string();
+    assert_eq!(
+        text,
+        "Mapper: MBC3\nROM bank: 05\nRAM bank: 02\nRAM: enabled\n"
+    );
+
+    let rom = build_rom(&[(0x147, &[0x10])]);
+    let emu = Headless::new(Cartridge::from_bytes("rtc.gb", &rom).unwrap());
+    let report = emu.emulator().cartridge().unwrap().mapper_report();
+    assert_eq!(report.name, "MBC3+RTC");
+    assert!(
+        report
+            .to_string()
+            .contains("RTC: day 0 00:00:00, latched day 0 00:00:00")
+    );
+}
@@ -0,0 +1,230 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::CpuContext;
+use dmg_core::desync::{ChecksumStream, Desync};
+use dmg_core::headless::Headless;
+use dmg_core::state;
+
+const FRAMES: u32 = 30;
+
+/// Program that keeps the PPU, timer interrupt and DIV busy, so any
+/// dependence on wall-clock time or thread timing shows up in its output.
+fn build_test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x31, 0xFE, 0xFF,       // LD SP, $FFFE
+        0x21, 0x10, 0x80,       // LD HL, $8010
+        0x06, 0x10,             // LD B, 16
+        0xF0, 0x04,             // fill: LDH A, (DIV)
+        0x22,                   // LD (HL+), A
+        0x05,                   // DEC B
+        0x20, 0xFA,             // JR NZ, fill
+        0x3E, 0x05,             // LD A, $05
+        0xE0, 0x07,             // LDH (TAC), A
+        0x3E, 0x04,             // LD A, $04
+        0xE0, 0xFF,             // LDH (IE), A
+        0xFB,                   // EI
+        0x21, 0x00, 0x98,       // main: LD HL, $9800
+        0xF0, 0x04,             // map: LDH A, (DIV)
+        0xE6, 0x01,             // AND $01
+        0x22,                   // LD (HL+), A
+        0x7C,                   // LD A, H
+        0xFE, 0x9C,             // CP $9C
+        0x20, 0xF7,             // JR NZ, map
+        0x18, 0xF2,             // JR main
+    ];
+    #[rustfmt::skip]
+    let timer_handler: &[u8] = &[
+        0xF5,                   // PUSH AF
+        0xFA, 0x01, 0xC0,       // LD A, ($C001)
+        0x3C,                   // INC A
+        0xEA, 0x01, 0xC0,       // LD ($C001), A
+        0xF0, 0x44,             // LDH A, (LY)
+        0xEA, 0x02, 0xC0,       // LD ($C002), A
+        0xF1,                   // POP AF
+        0xD9,                   // RETI
+    ];
+
+    build_rom(&[(0x150, main), (0x50, timer_handler)])
+}
+
+struct Snapshot {
+    ticks: u64,
+    frame: Vec<u32>,
+    memory: Vec<u8>,
+}
+
+fn run_from_reset() -> Snapshot {
+    let rom = Cartridge::from_bytes("determinism.gb", &build_test_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+    assert!(emu.run_frames(FRAMES));
+
+    let state = emu.emulator_mut();
+    let frame = state.ppu().frame().as_argb8888();
+    // VRAM, WRAM and HRAM
+    let memory = (0x8000..=0x9FFF)
+        .chain(0xC000..=0xDFFF)
+        .chain(0xFF80..=0xFFFE)
+        .map(|address| state.peek(address))
+        .collect();
+
+    Snapshot {
+        ticks: state.ticks(),
+        frame,
+        memory,
+    }
+}
+
+#[test]
+fn repeated_runs_are_identical() {
+    let first = run_from_reset();
+    let second = run_from_reset();
+
+    assert_eq!(first.ticks, second.ticks, "tick counts differ");
+    assert!(first.frame == second.frame, "framebuffers differ");
+    assert!(first.memory == second.memory, "memory differs");
+    // Make sure the program actually ran and took timer interrupts
+    assert_ne!(first.memory[0x2000 + 1], 0);
+}
+
+fn new_emulator() -> Headless {
+    let rom = Cartridge::from_bytes("determinism.gb", &build_test_rom()).unwrap();
+    Headless::new(rom)
+}
+
+#[test]
+fn loaded_state_resumes_identically() {
+    let mut emu = new_emulator();
+    assert!(emu.run_frames(FRAMES / 2));
+    let saved = emu.save_state();
+
+    assert!(emu.run_frames(FRAMES / 2));
+    let first = emu.save_state();
+
+    emu.load_state(&saved).unwrap();
+    assert!(emu.run_frames(FRAMES / 2));
+    let second = emu.save_state();
+
+    assert!(first == second, "states differ after resuming");
+}
+
+#[test]
+fn states_are_compressed() {
+    let mut emu = new_emulator();
+    assert!(emu.run_frames(FRAMES));
+
+    // VRAM, WRAM, OAM and HRAM alone take over 16 KiB uncompressed, the
+    // length of the compressed machine follows the magic, version and length
+    let state = emu.save_state();
+    let compressed_len = u32::from_le_bytes(state[9..13].try_into().unwrap());
+    assert!(compressed_len < 16 * 1024);
+}
+
+/// Memory and registers the BESS section of a state covers.
+fn bess_memory(emu: &mut Headless) -> Vec<u8> {
+    (0x8000..=0x9FFF)
+        .chain(0xC000..=0xDFFF)
+        .chain(0xFE00..=0xFE9F)
+        .chain(0xFF80..=0xFFFF)
+        .chain([0xFF04, 0xFF05, 0xFF06, 0xFF07, 0xFF0F, 0xFF40, 0xFF47])
+        .map(|address| emu.emulator_mut().peek(address))
+        .collect()
+}
+
+#[test]
+fn bess_section_restores_registers_and_memory() {
+    let mut emu = new_emulator();
+    assert!(emu.run_frames(FRAMES));
+    let mut saved = emu.save_state();
+    assert!(saved.ends_with(b"BESS"));
+
+    // Without the header only the BESS section is read, as for a state of another emulator
+    saved[..4].copy_from_slice(b"SAME");
+    let mut other = new_emulator();
+    other.load_state(&saved).unwrap();
+
+    assert!(
+        emu.cpu().registers() == other.cpu().registers(),
+        "registers differ"
+    );
+    assert!(
+        bess_memory(&mut emu) == bess_memory(&mut other),
+        "memory differs"
+    );
+    assert!(other.run_frames(FRAMES));
+}
+
+#[test]
+fn reset_matches_power_on() {
+    let power_on = new_emulator().save_state();
+
+    let mut emu = new_emulator();
+    assert!(emu.run_frames(FRAMES / 2));
+    emu.reset();
+
+    assert!(
+        emu.save_state() == power_on,
+        "reset state differs from power-on"
+    );
+}
+
+#[test]
+fn batched_run_matches_stepping() {
+    let mut batched = new_emulator();
+    assert!(batched.run_frames(FRAMES));
+
+    let mut stepped = new_emulator();
+    while stepped.emulator().get_current_frame() < FRAMES {
+        assert!(stepped.step());
+    }
+
+    assert_eq!(batched.ticks(), stepped.ticks());
+    assert!(
+        batched.save_state() == stepped.save_state(),
+        "states differ"
+    );
+    assert_eq!(batched.last_frame().hash(), stepped.last_frame().hash());
+}
+
+#[test]
+fn checksum_stream_reports_the_first_diverging_frame() {
+    let machine = || {
+        let rom = Cartridge::from_bytes("determinism.gb", &build_test_rom()).unwrap();
+        Headless::new(rom)
+    };
+    let (mut local, mut remote) = (machine(), machine());
+    let mut stream = ChecksumStream::new(2);
+
+    let mut desync = None;
+    while desync.is_none() && local.emulator().get_current_frame() < FRAMES {
+        local.run_frames(1);
+        remote.run_frames(1);
+
+        let frame = local.emulator().get_current_frame();
+        if frame == 11 {
+            remote.emulator_mut().poke(0xC010, 0x55);
+        }
+
+        if stream.is_due(frame) {
+            let local_checksum = state::checksum(local.cpu());
+            desync = stream.push_local(frame, local_checksum).err();
+            let remote_checksum = state::checksum(remote.cpu());
+            desync = desync.or(stream.push_remote(frame, remote_checksum).err());
+        }
+    }
+
+    assert!(
+        matches!(desync, Some(Desync { frame: 12, .. })),
+        "{desync:?}"
+    );
+    assert_eq!(stream.matched(), 5);
+
+    let line = ChecksumStream::format_line(60, 0x0123_4567_89AB_CDEF);
+    assert_eq!(line, "frame 60 0123456789abcdef");
+    assert_eq!(
+        ChecksumStream::parse_line(&line),
+        Some((60, 0x0123_4567_89AB_CDEF))
+    );
+}
@@ -0,0 +1,79 @@
+use dmg_core::cpu::{
+    Instruction, InstructionIter, InstructionType, disassemble, disassemble_around,
+};
+
+#[test]
+fn instructions_around_pc_are_decoded() {
+    #[rustfmt::skip]
+    let code: &[u8] = &[
+        0x3E, 0x01,       // $0150 LD A, $01
+        0xCB, 0x37,       // $0152 SWAP A
+        0xCD, 0x00, 0x02, // $0154 CALL $0200
+        0x20, 0xF7,       // $0157 JR NZ, $0150
+        0xFF,             // $0159 RST $38
+        0xD3,             // $015A illegal
+        0xC8,             // $015B RET Z
+    ];
+    let read = |address: u16| {
+        address
+            .checked_sub(0x150)
+            .and_then(|offset| code.get(offset as usize))
+            .copied()
+            .unwrap_or(0x00)
+    };
+
+    let call = disassemble(read, 0x154);
+    assert_eq!(call.bytes, [0xCD, 0x00, 0x02]);
+    assert_eq!(call.next_address(), 0x157);
+
+    let lines = disassemble_around(read, 0x157, 3, 3);
+    let texts: Vec<(u16, &str)> = lines
+        .iter()
+        .map(|line| (line.address, line.text.as_str()))
+        .collect();
+    assert_eq!(
+        texts,
+        [
+            (0x150, "LD A, $01"),
+            (0x152, "SWAP A"),
+            (0x154, "CALL $0200"),
+            (0x157, "JR NZ, $0150"),
+            (0x159, "RST $38"),
+            (0x15A, "ILLEGAL"),
+            (0x15B, "RET Z"),
+        ]
+    );
+
+    // Fewer instructions before when the code starts at PC
+    assert_eq!(disassemble_around(read, 0x150, 2, 0).len(), 3);
+}
+
+#[test]
+fn instruction_iter_walks_a_range() {
+    #[rustfmt::skip]
+    let code: &[u8] = &[
+        0x00,             // $0000 NOP
+        0xCB, 0x37,       // $0001 SWAP A
+        0xD3,             // $0003 illegal
+        0xC3, 0x50, 0x01, // $0004 JP $0150
+    ];
+    let read = |address: u16| code.get(address as usize).copied().unwrap_or(0x00);
+
+    let decoded: Vec<(u16, InstructionType, Vec<u8>)> = InstructionIter::new(&read, 0x0000, 0x0005)
+        .map(|(address, instruction, operands)| (address, instruction.itype, operands))
+        .collect();
+    assert_eq!(
+        decoded,
+        [
+            (0x0000, InstructionType::NOP, vec![]),
+            (0x0001, InstructionType::SWAP, vec![0x37]),
+            (0x0003, InstructionType::NONE, vec![]),
+            // Runs past the end of the range, which stops there
+            (0x0004, InstructionType::JP, vec![0x50, 0x01]),
+        ]
+    );
+
+    let (_, instruction, _) = InstructionIter::new(&read, 0x0003, 0x0004).next().unwrap();
+    assert_eq!(instruction, Instruction::NONE);
+    assert_eq!(InstructionIter::new(&read, 0x0004, 0x0004).count(), 0);
+}
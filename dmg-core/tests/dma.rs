@@ -0,0 +1,78 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::CpuContext;
+use dmg_core::emu::{AccuracyConfig, AccuracyLevel};
+use dmg_core::headless::Headless;
+
+/// Fill $8100 - $819F with $77, copy it to OAM and read $8000 while the
+/// transfer runs, storing the result in $C000.
+fn read_vram_during_dma(level: AccuracyLevel) -> u8 {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0x00, 0x81,   // LD HL, $8100
+        0x3E, 0x77,         // LD A, $77
+        0x06, 0xA0,         // LD B, $A0
+        0x22,               // loop: LD (HL+), A
+        0x05,               // DEC B
+        0x20, 0xFC,         // JR NZ, loop
+        0x3E, 0x81,         // LD A, $81
+        0xE0, 0x46,         // LDH (DMA), A
+        0x00, 0x00,         // NOP, NOP
+        0xFA, 0x00, 0x80,   // LD A, ($8000)
+        0xEA, 0x00, 0xC0,   // LD ($C000), A
+        0x18, 0xFE,         // JR -2
+    ];
+
+    let rom = Cartridge::from_bytes("dma.gb", &build_rom(&[(0x150, main)])).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.emulator_mut()
+        .set_accuracy(AccuracyConfig::preset(level));
+    emu.run_frames(1);
+    emu.emulator_mut().peek(0xC000)
+}
+
+#[test]
+fn reads_on_the_dma_bus_see_the_transferred_byte() {
+    assert_eq!(read_vram_during_dma(AccuracyLevel::Balanced), 0x00);
+    assert_eq!(read_vram_during_dma(AccuracyLevel::Accurate), 0x77);
+}
+
+#[test]
+fn writing_dma_during_a_transfer_restarts_it() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0x00, 0xC1,   // LD HL, $C100
+        0x3E, 0x11,         // LD A, $11
+        0x06, 0xA0,         // LD B, $A0
+        0x22,               // loop: LD (HL+), A
+        0x05,               // DEC B
+        0x20, 0xFC,         // JR NZ, loop
+        0x21, 0x00, 0xC2,   // LD HL, $C200
+        0x3E, 0x22,         // LD A, $22
+        0x06, 0xA0,         // LD B, $A0
+        0x22,               // loop: LD (HL+), A
+        0x05,               // DEC B
+        0x20, 0xFC,         // JR NZ, loop
+        0x3E, 0xC1,         // LD A, $C1
+        0xE0, 0x46,         // LDH (DMA), A
+        0x00, 0x00, 0x00,   // NOP, NOP, NOP
+        0x3E, 0xC2,         // LD A, $C2
+        0xE0, 0x46,         // LDH (DMA), A
+        0xFA, 0x00, 0xFE,   // LD A, ($FE00)    ; still busy
+        0xEA, 0x00, 0xC0,   // LD ($C000), A
+        0xF0, 0x46,         // LDH A, (DMA)
+        0xEA, 0x01, 0xC0,   // LD ($C001), A
+        0x18, 0xFE,         // JR -2
+    ];
+
+    let rom = Cartridge::from_bytes("dma.gb", &build_rom(&[(0x150, main)])).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.run_frames(1);
+
+    let emu = emu.emulator_mut();
+    assert_eq!(emu.peek(0xC000), 0xFF);
+    assert_eq!(emu.peek(0xC001), 0xC2);
+    assert!((0xFE00..=0xFE9F).all(|address| emu.peek(address) == 0x22));
+}
@@ -0,0 +1,197 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::clock::EmuClock;
+use dmg_core::cpu::{EmulatorError, Register, RegisterFile};
+use dmg_core::headless::Headless;
+use dmg_core::interrupts::{InterruptFlag, get_hadler_address};
+use dmg_core::serial::Serial;
+use dmg_core::timer::Timer;
+use dmg_core::warnings::{WarningKind, WarningLog};
+use dmg_core::watchdog::HangWatchdog;
+
+fn illegal_opcode_rom() -> Headless {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x00,               // NOP
+        0xD3,               // illegal
+    ];
+
+    let rom = Cartridge::from_bytes("fault.gb", &build_rom(&[(0x150, main)])).unwrap();
+    Headless::new(rom)
+}
+
+#[test]
+fn illegal_opcode_is_reported_as_fault() {
+    let mut emu = illegal_opcode_rom();
+    emu.cpu_mut().set_report_faults(true);
+
+    let fault = emu.try_run_frames(1).unwrap_err();
+    assert!(matches!(fault, EmulatorError::Fault { pc: 0x151, .. }));
+    assert_eq!(fault.to_string(), "fault at $0151: Illegal opcode 0xD3");
+    // Stays stopped on the fault until reset
+    assert_eq!(emu.try_step(), Err(fault));
+    assert!(!emu.step());
+
+    emu.reset();
+    assert!(emu.cpu().fault().is_none());
+    assert_eq!(emu.try_step(), Ok(true));
+}
+
+#[test]
+#[should_panic(expected = "Illegal opcode 0xD3")]
+fn illegal_opcode_panics_by_default() {
+    illegal_opcode_rom().run_frames(1);
+}
+
+#[test]
+fn components_take_any_address_or_register() {
+    let mut serial = Serial::new();
+    serial.write(0xFF04, 0x12, EmuClock::default());
+    assert_eq!(serial.read(0xFF04), 0xFF);
+    assert_eq!(Timer::new().read(0xFF01), 0xFF);
+
+    // No interrupt left to dispatch
+    assert_eq!(get_hadler_address(InterruptFlag::empty()), 0x0000);
+
+    let mut registers = RegisterFile::new();
+    registers.write16(Register::A, 0x1234);
+    assert_eq!(registers.read16(Register::A), 0x0034);
+    registers.write8(Register::BC, 0x56);
+    assert_eq!(registers.read16(Register::BC), 0x0056);
+    assert_eq!(registers.read8(Register::BC), 0x56);
+}
+
+#[test]
+fn bank_guard_traps_execution_past_the_rom() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x05,         // LD A, 5
+        0xEA, 0x00, 0x20,   // LD ($2000), A ; ROM bank 5 of 2
+        0xC3, 0x00, 0x40,   // JP $4000
+    ];
+
+    let mut rom = build_rom(&[(0x150, main)]);
+    rom[0x147] = 0x11; // MBC3
+    let rom = Cartridge::from_bytes("bank.gb", &rom).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.cpu_mut().set_report_faults(true);
+    emu.emulator_mut().set_bank_guard(true);
+
+    let fault = emu.try_run_frames(1).unwrap_err();
+    assert!(matches!(fault, EmulatorError::Fault { pc: 0x4000, .. }));
+}
+
+#[test]
+fn dma_guard_traps_execution_outside_hram() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0xC0,         // LD A, $C0
+        0xE0, 0x46,         // LDH (DMA), A
+        0x00,               // NOP, fetched before the transfer takes the bus
+        0x00,               // NOP, still in ROM
+    ];
+
+    let rom = Cartridge::from_bytes("dma.gb", &build_rom(&[(0x150, main)])).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.cpu_mut().set_report_faults(true);
+    emu.emulator_mut().set_dma_guard(true);
+
+    let fault = emu.try_run_frames(1).unwrap_err();
+    assert!(matches!(fault, EmulatorError::Fault { pc: 0x155, .. }));
+}
+
+#[test]
+fn stack_guard_traps_pops_past_the_end_of_memory() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x31, 0xFE, 0xFF,   // LD SP, $FFFE
+        0xCD, 0x00, 0x02,   // CALL $0200
+        0xC9,               // RET, with nothing on the stack
+    ];
+    let rom = build_rom(&[(0x150, main), (0x200, &[0xC9])]); // RET
+
+    let rom = Cartridge::from_bytes("stack.gb", &rom).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.cpu_mut().set_report_faults(true);
+    emu.emulator_mut().set_stack_guard(true);
+    emu.emulator_mut().set_warnings(Some(WarningLog::new()));
+
+    let fault = emu.try_run_frames(1).unwrap_err();
+    assert!(matches!(fault, EmulatorError::Fault { .. }));
+    assert!(
+        fault.to_string().contains("$0156 popped from $FFFE"),
+        "{fault}"
+    );
+
+    let warnings = emu.emulator().warnings().unwrap();
+    assert_eq!(warnings.count(WarningKind::StackUnderflow), 1);
+    assert_eq!(warnings.count(WarningKind::StackOverflow), 0);
+}
+
+#[test]
+fn header_guard_warns_about_writes_to_the_vectors() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x12,         // LD A, $12
+        0xEA, 0x40, 0x00,   // LD ($0040), A
+        0xEA, 0x00, 0x20,   // LD ($2000), A, past the header
+        0x18, 0xFE,         // JR -2
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+
+    let run = |guard: bool| {
+        let rom = Cartridge::from_bytes("header.gb", &rom).unwrap();
+        let mut emu = Headless::new(rom);
+        emu.emulator_mut().set_header_guard(guard);
+        emu.emulator_mut().set_warnings(Some(WarningLog::new()));
+        emu.run_frames(1);
+        emu.emulator().warnings().unwrap().clone()
+    };
+
+    let warnings = run(true);
+    assert_eq!(warnings.count(WarningKind::HeaderWrite), 1);
+    assert_eq!(warnings.warnings()[0].address, 0x0040);
+    assert_eq!(run(false).count(WarningKind::HeaderWrite), 0);
+}
+
+fn watched_rom(main: &[u8]) -> Headless {
+    let rom = Cartridge::from_bytes("hang.gb", &build_rom(&[(0x150, main)])).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.emulator_mut().set_watchdog(Some(HangWatchdog::new(30)));
+    emu
+}
+
+#[test]
+fn watchdog_reports_a_lock_up_once() {
+    let mut emu = watched_rom(&[0x18, 0xFE]); // JR -2
+
+    emu.run_frames(20);
+    assert!(!emu.emulator_mut().take_hang());
+    emu.run_frames(20);
+    assert!(emu.emulator_mut().take_hang());
+    emu.run_frames(20);
+    assert!(!emu.emulator_mut().take_hang());
+}
+
+#[test]
+fn watchdog_ignores_a_changing_picture() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0xF0, 0x44,         // loop: LDH A, (LY)
+        0xFE, 0x90,         // CP 144
+        0x20, 0xFA,         // JR NZ, loop
+        0xF0, 0x47,         // LDH A, (BGP)
+        0x2F,               // CPL
+        0xE0, 0x47,         // LDH (BGP), A
+        0xF0, 0x44,         // wait: LDH A, (LY)
+        0xFE, 0x90,         // CP 144
+        0x28, 0xFA,         // JR Z, wait
+        0x18, 0xED,         // JR loop
+    ];
+    let mut emu = watched_rom(main);
+
+    emu.run_frames(60);
+    assert!(!emu.emulator_mut().take_hang());
+}
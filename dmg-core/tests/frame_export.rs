@@ -0,0 +1,52 @@
+use std::thread;
+
+use dmg_core::frame::{Frame, Palette};
+use dmg_core::frame_export::FrameExport;
+
+/// Frame telling its number through the palette, the same in every field.
+fn numbered_frame(number: u32) -> Frame {
+    let mut frame = Frame::new();
+    frame.set_palette(Palette::uniform([number; 4]));
+    frame
+}
+
+#[test]
+fn readers_see_whole_frames_in_order() {
+    let export = FrameExport::new();
+    assert_eq!(export.frame_number(), 0);
+    assert!(export.latest().is_none());
+
+    let readers: Vec<_> = (0..3)
+        .map(|_| {
+            let export = export.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+
+                while last < 2000 {
+                    let Some((number, palette)) =
+                        export.read(|number, frame| (number, *frame.palette()))
+                    else {
+                        continue;
+                    };
+
+                    assert!(number >= last);
+                    assert_eq!(palette, Palette::uniform([number as u32; 4]));
+                    last = number;
+                }
+            })
+        })
+        .collect();
+
+    for number in 1..=2000 {
+        // Dropped when the readers happen to hold every other slot
+        while !export.publish(&numbered_frame(number)) {}
+    }
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    let (number, frame) = export.latest().unwrap();
+    assert_eq!(number, 2000);
+    assert_eq!(frame.palette().background[0], 2000);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::{Cartridge, CartridgeHeader, HeaderFix, ValidationIssue};
+
+const LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+fn set_global_checksum(rom: &mut [u8]) {
+    let sum = rom
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != 0x14E && *i != 0x14F)
+        .fold(0u16, |sum, (_, b)| sum.wrapping_add(*b as u16));
+    rom[0x14E..0x150].copy_from_slice(&sum.to_be_bytes());
+}
+
+#[test]
+fn valid_header_has_no_issues() {
+    let mut rom = build_rom(&[(0x104, &LOGO)]);
+    set_global_checksum(&mut rom);
+    let cart = Cartridge::from_bytes("valid.gb", &rom).unwrap();
+
+    assert_eq!(cart.header.validate(), []);
+}
+
+#[test]
+fn issues_are_reported_instead_of_failing() {
+    let mut rom = build_rom(&[(0x147, &[0x13, 0x00, 0x00])]);
+    // Corrupt the header checksum
+    rom[0x14D] ^= 0xFF;
+    let cart = Cartridge::from_bytes("broken.gb", &rom).unwrap();
+    let issues = cart.header.validate();
+
+    assert!(issues.contains(&ValidationIssue::LogoMismatch));
+    assert!(
+        issues
+            .iter()
+            .any(|i| matches!(i, ValidationIssue::HeaderChecksum { .. }))
+    );
+    assert!(
+        issues
+            .iter()
+            .any(|i| matches!(i, ValidationIssue::GlobalChecksum { .. }))
+    );
+    assert!(issues.contains(&ValidationIssue::RamSizeMismatch {
+        cartridge_type: 0x13,
+        ram_size: 0
+    }));
+    assert!(Cartridge::from_bytes("tiny.gb", &rom[..0x100]).is_err());
+}
+
+#[test]
+fn fix_rewrites_the_checksums_and_pads() {
+    let mut rom = build_rom(&[(0x104, &LOGO)]);
+    rom.truncate(0x5000);
+    rom[0x14D] ^= 0xFF;
+
+    let fixes = CartridgeHeader::fix(&mut rom, true);
+    assert_eq!(
+        fixes,
+        [
+            HeaderFix::Padded {
+                from: 0x5000,
+                to: 0x8000
+            },
+            HeaderFix::HeaderChecksum {
+                from: rom[0x14D] ^ 0xFF,
+                to: rom[0x14D]
+            },
+            HeaderFix::GlobalChecksum {
+                from: 0,
+                to: u16::from_be_bytes([rom[0x14E], rom[0x14F]])
+            },
+        ]
+    );
+    assert_eq!(rom.len(), 0x8000);
+    assert!(rom[0x5000..].iter().all(|byte| *byte == 0xFF));
+
+    let cart = Cartridge::from_bytes("fixed.gb", &rom).unwrap();
+    assert_eq!(cart.header.validate(), []);
+    assert_eq!(CartridgeHeader::fix(&mut rom, true), []);
+
+    // A larger image gets its size declared
+    rom.resize(0x9000, 0);
+    let fixes = CartridgeHeader::fix(&mut rom, true);
+    assert_eq!(fixes[1], HeaderFix::RomSize { from: 0, to: 1 });
+    assert_eq!(rom.len(), 0x10000);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::{CpuContext, OpcodeCoverage};
+use dmg_core::emu::{AccuracyConfig, AccuracyLevel};
+use dmg_core::headless::Headless;
+
+/// Counts frames at 0xC000 by waiting for LY to reach and leave 144.
+fn build_ly_wait_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0xF0, 0x44,         // wait: LDH A, (LY)
+        0xFE, 0x90,         // CP 144
+        0x20, 0xFA,         // JR NZ, wait
+        0x21, 0x00, 0xC0,   // LD HL, $C000
+        0x34,               // INC (HL)
+        0xF0, 0x44,         // leave: LDH A, (LY)
+        0xFE, 0x90,         // CP 144
+        0x28, 0xFA,         // JR Z, leave
+        0x18, 0xEE,         // JR wait
+    ];
+
+    build_rom(&[(0x150, main)])
+}
+
+fn run(idle_skip: bool) -> (u8, u64) {
+    let rom = Cartridge::from_bytes("ly_wait.gb", &build_ly_wait_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+    let accuracy = AccuracyConfig {
+        idle_skip,
+        ..AccuracyConfig::preset(AccuracyLevel::Fast)
+    };
+    emu.emulator_mut().set_accuracy(accuracy);
+    emu.cpu_mut().set_coverage(Some(OpcodeCoverage::new()));
+
+    assert!(emu.run_frames(30));
+
+    let frames = emu.emulator_mut().peek(0xC000);
+    (frames, emu.cpu().coverage().unwrap().count(0xF0))
+}
+
+#[test]
+fn idle_loops_are_skipped_without_changing_the_result() {
+    let (frames, polls) = run(false);
+    let (skipped_frames, skipped_polls) = run(true);
+
+    assert_eq!(skipped_frames, frames);
+    assert!(skipped_polls * 4 < polls, "{skipped_polls} of {polls}");
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::CpuContext;
+use dmg_core::headless::Headless;
+use dmg_core::infrared::{InfraredLink, InfraredLoopback};
+
+fn emulator(name: &str, main: &[u8]) -> Headless {
+    let rom = build_rom(&[(0x150, main)]);
+    Headless::new(Cartridge::from_bytes(name, &rom).unwrap())
+}
+
+#[test]
+fn loopback_sensor_sees_the_led() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0xC1,         // LD A, $C1       ; LED on, reading enabled
+        0xE0, 0x56,         // LDH (RP), A
+        0xF0, 0x56,         // LDH A, (RP)
+        0xEA, 0x00, 0xC0,   // LD ($C000), A
+        0x3E, 0xC0,         // LD A, $C0       ; LED off
+        0xE0, 0x56,         // LDH (RP), A
+        0xF0, 0x56,         // LDH A, (RP)
+        0xEA, 0x01, 0xC0,   // LD ($C001), A
+        0x3E, 0x01,         // LD A, $01       ; LED on, reading disabled
+        0xE0, 0x56,         // LDH (RP), A
+        0xF0, 0x56,         // LDH A, (RP)
+        0xEA, 0x02, 0xC0,   // LD ($C002), A
+        0x18, 0xFE,         // JR -2
+    ];
+    let mut emu = emulator("loopback.gb", main);
+    emu.emulator_mut()
+        .set_infrared_device(Some(Box::new(InfraredLoopback::default())));
+    emu.run_frames(2);
+
+    let emulator = emu.emulator_mut();
+    assert_eq!(emulator.peek(0xC000), 0xFD);
+    assert_eq!(emulator.peek(0xC001), 0xFE);
+    assert_eq!(emulator.peek(0xC002), 0x3F);
+}
+
+#[test]
+fn linked_instances_complete_a_handshake() {
+    // Turns the LED on until the other side answers, then off until it's
+    // dark again
+    #[rustfmt::skip]
+    let sender: &[u8] = &[
+        0x3E, 0xC1,         // LD A, $C1       ; LED on, reading enabled
+        0xE0, 0x56,         // LDH (RP), A
+        0xF0, 0x56,         // wait: LDH A, (RP)
+        0xCB, 0x4F,         // BIT 1, A
+        0x20, 0xFA,         // JR NZ, wait     ; until there is light
+        0x3E, 0xC0,         // LD A, $C0
+        0xE0, 0x56,         // LDH (RP), A
+        0xF0, 0x56,         // dark: LDH A, (RP)
+        0xCB, 0x4F,         // BIT 1, A
+        0x28, 0xFA,         // JR Z, dark      ; until the light is gone
+        0x3E, 0x42,         // LD A, $42
+        0xEA, 0x00, 0xC0,   // LD ($C000), A
+        0x18, 0xFE,         // JR -2
+    ];
+    // Answers with its LED while the sender's is on
+    #[rustfmt::skip]
+    let receiver: &[u8] = &[
+        0x3E, 0xC0,         // LD A, $C0       ; reading enabled
+        0xE0, 0x56,         // LDH (RP), A
+        0xF0, 0x56,         // wait: LDH A, (RP)
+        0xCB, 0x4F,         // BIT 1, A
+        0x20, 0xFA,         // JR NZ, wait
+        0x3E, 0xC1,         // LD A, $C1
+        0xE0, 0x56,         // LDH (RP), A
+        0xF0, 0x56,         // dark: LDH A, (RP)
+        0xCB, 0x4F,         // BIT 1, A
+        0x28, 0xFA,         // JR Z, dark
+        0x3E, 0xC0,         // LD A, $C0
+        0xE0, 0x56,         // LDH (RP), A
+        0x3E, 0x42,         // LD A, $42
+        0xEA, 0x00, 0xC0,   // LD ($C000), A
+        0x18, 0xFE,         // JR -2
+    ];
+
+    // Alone the sender never gets an answer
+    let mut alone = emulator("sender.gb", sender);
+    let (end, _) = InfraredLink::pair();
+    alone
+        .emulator_mut()
+        .set_infrared_device(Some(Box::new(end)));
+    alone.run_frames(2);
+    assert_ne!(alone.emulator_mut().peek(0xC000), 0x42);
+
+    let mut a = emulator("sender.gb", sender);
+    let mut b = emulator("receiver.gb", receiver);
+    let (end_a, end_b) = InfraredLink::pair();
+    a.emulator_mut().set_infrared_device(Some(Box::new(end_a)));
+    b.emulator_mut().set_infrared_device(Some(Box::new(end_b)));
+
+    for _ in 0..10_000 {
+        a.step();
+        b.step();
+    }
+
+    assert_eq!(a.emulator_mut().peek(0xC000), 0x42);
+    assert_eq!(b.emulator_mut().peek(0xC000), 0x42);
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::{Requests, build_rom};
+use dmg_core::bus::HardwareRegister;
+use dmg_core::cart::Cartridge;
+use dmg_core::headless::Headless;
+use dmg_core::interrupts::{InterruptFlag, handler_address};
+use dmg_core::lcd::LCD;
+use dmg_core::power::{Model, Quirks};
+
+#[test]
+fn handlers_resolve_by_name() {
+    assert_eq!(handler_address("vblank-handler"), Some(0x40));
+    assert_eq!(handler_address("stat-handler"), Some(0x48));
+    assert_eq!(handler_address("timer-handler"), Some(0x50));
+    assert_eq!(handler_address("serial-handler"), Some(0x58));
+    assert_eq!(handler_address("joypad-handler"), Some(0x60));
+    assert_eq!(handler_address("vblank"), None);
+    assert_eq!(handler_address("hblank-handler"), None);
+}
+
+#[test]
+fn interrupts_are_counted_per_source() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x01,       // LD A, $01
+        0xE0, 0xFF,       // LDH (IE), A, VBlank only
+        0xFB,             // EI
+        0x18, 0xFE,       // loop: JR loop
+    ];
+    let vblank: &[u8] = &[0xD9]; // RETI
+    let rom = build_rom(&[(0x40, vblank), (0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("vblank.gb", &rom).unwrap());
+
+    assert!(emu.run_frames(10));
+
+    let stats = emu.emulator().interrupt_stats();
+    let vblank = stats.source(InterruptFlag::VBLANK);
+    assert!((9..=11).contains(&vblank.serviced));
+    // The boot ROM leaves a VBlank pending that was never requested here
+    assert!(vblank.serviced - vblank.requested <= 1);
+    // Dispatched once the 3 M-cycle jump completes
+    assert!(vblank.max_latency <= 12, "{vblank:?}");
+    assert_eq!(stats.source(InterruptFlag::TIMER).serviced, 0);
+}
+
+#[test]
+fn restoring_an_earlier_state_forgets_pending_requests() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x01,       // LD A, $01
+        0xE0, 0xFF,       // LDH (IE), A, VBlank only
+        0xAF,             // XOR A
+        0xE0, 0x0F,       // LDH (IF), A
+        0xF0, 0x80,       // wait: LDH A, ($80)
+        0xA7,             // AND A
+        0x28, 0xFB,       // JR Z, wait
+        0xFB,             // EI
+        0x18, 0xFE,       // loop: JR loop
+    ];
+    let vblank: &[u8] = &[0xD9]; // RETI
+    let rom = build_rom(&[(0x40, vblank), (0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("restore.gb", &rom).unwrap());
+
+    for _ in 0..10 {
+        emu.step();
+    }
+    // A VBlank pending in the state, not requested by the PPU
+    emu.emulator_mut().poke(0xFF0F, 0x01);
+    let early = emu.save_state();
+    emu.emulator_mut().poke(0xFF0F, 0x00);
+
+    // A request made later stays pending with interrupts disabled
+    assert!(emu.run_frames(2));
+    emu.load_state(&early).unwrap();
+    emu.emulator_mut().poke(0xFF80, 0x01);
+    assert!(emu.run_frames(1));
+
+    let vblank = emu
+        .emulator()
+        .interrupt_stats()
+        .source(InterruptFlag::VBLANK);
+    assert!(vblank.serviced >= 1);
+    assert!(vblank.max_latency < 70224, "{vblank:?}");
+}
+
+#[test]
+fn stat_write_interrupts_on_dmg() {
+    assert!(Quirks::for_model(Model::Dmg).contains(Quirks::STAT_WRITE_INTERRUPT));
+
+    let stat_write = |quirks: Quirks| {
+        let mut lcd = LCD::new();
+        lcd.set_quirks(quirks);
+        let mut requests = Requests(Vec::new());
+        // Enabled in HBlank with no source selected
+        lcd.write(HardwareRegister::LYC, 0x90, &mut requests);
+        lcd.write(HardwareRegister::LCDC, 0x80, &mut requests);
+        lcd.write(HardwareRegister::STAT, 0x00, &mut requests);
+        requests.0
+    };
+
+    assert_eq!(
+        stat_write(Quirks::STAT_WRITE_INTERRUPT),
+        [InterruptFlag::LCD]
+    );
+    assert_eq!(stat_write(Quirks::empty()), []);
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::CpuContext;
+use dmg_core::headless::Headless;
+use dmg_core::joypad::Buttons;
+use dmg_core::monkey::MonkeyInput;
+
+/// Copies the d-pad lines to 0xC000 and the buttons to 0xC001 in a loop.
+fn build_joypad_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x20,         // loop: LD A, $20
+        0xE0, 0x00,         // LDH (P1), A, select the d-pad
+        0xF0, 0x00,         // LDH A, (P1)
+        0xEA, 0x00, 0xC0,   // LD ($C000), A
+        0x3E, 0x10,         // LD A, $10
+        0xE0, 0x00,         // LDH (P1), A, select the buttons
+        0xF0, 0x00,         // LDH A, (P1)
+        0xEA, 0x01, 0xC0,   // LD ($C001), A
+        0x18, 0xEC,         // JR loop
+    ];
+
+    build_rom(&[(0x150, main)])
+}
+
+#[test]
+fn buttons_set_between_frames_reach_the_game() {
+    let rom = Cartridge::from_bytes("joypad.gb", &build_joypad_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+
+    let buttons = Buttons::LEFT | Buttons::A | Buttons::START;
+    emu.emulator_mut().set_buttons(buttons);
+    emu.run_frames(1);
+
+    assert_eq!(emu.emulator().buttons(), buttons);
+    // Held lines read as 0
+    assert_eq!(emu.emulator_mut().peek(0xC000) & 0x0F, 0b1101);
+    assert_eq!(emu.emulator_mut().peek(0xC001) & 0x0F, 0b0110);
+
+    emu.emulator_mut().set_buttons(Buttons::empty());
+    emu.run_frames(1);
+
+    assert_eq!(emu.emulator_mut().peek(0xC000) & 0x0F, 0x0F);
+    assert_eq!(emu.emulator_mut().peek(0xC001) & 0x0F, 0x0F);
+}
+
+#[test]
+fn stop_waits_for_a_button_with_div_frozen_and_the_lcd_blank() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x10,         // LD A, $10
+        0xE0, 0x00,         // LDH (P1), A, select the buttons
+        0x10, 0x00,         // STOP
+        0x3E, 0x42,         // LD A, $42
+        0xEA, 0x00, 0xC0,   // LD ($C000), A
+        0x18, 0xFE,         // JR -2
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("stop.gb", &rom).unwrap());
+
+    assert!(emu.run_frames(5));
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x00);
+    assert_eq!(emu.emulator_mut().peek(0xFF04), 0x00);
+    assert!(
+        emu.last_frame()
+            .as_indexed()
+            .indices
+            .iter()
+            .all(|&i| i == 0)
+    );
+
+    // The d-pad isn't selected, only a button wakes the CPU
+    emu.emulator_mut().set_buttons(Buttons::UP);
+    emu.run_frames(1);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x00);
+
+    emu.emulator_mut().set_buttons(Buttons::UP | Buttons::A);
+    emu.run_frames(1);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x42);
+}
+
+#[test]
+fn monkey_input_repeats_with_its_seed() {
+    let frames = |seed| {
+        let mut monkey = MonkeyInput::new(seed);
+        (0..600).map(|_| monkey.next_frame()).collect::<Vec<_>>()
+    };
+    let run = frames(7);
+
+    assert_eq!(run, frames(7));
+    assert_ne!(run, frames(8));
+    assert!(run.iter().all(|buttons| {
+        !buttons.contains(Buttons::LEFT | Buttons::RIGHT)
+            && !buttons.contains(Buttons::UP | Buttons::DOWN)
+            && !buttons.contains(Buttons::A | Buttons::B | Buttons::START | Buttons::SELECT)
+    }));
+    // Held for a few frames at a time
+    assert!(run.windows(2).filter(|pair| pair[0] != pair[1]).count() < 300);
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::headless::Headless;
+use dmg_core::lockstep::{self, DOCTOR_FORMAT, Mismatch, TraceFormat};
+
+fn headless() -> Headless {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x12, // LD A, $12
+        0x3C,       // INC A
+        0x47,       // LD B, A
+        0x18, 0xFE, // JR -2
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    Headless::new(Cartridge::from_bytes("lockstep.gb", &rom).unwrap())
+}
+
+const TRACE: &str = "\
+A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01
+A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,50,01,00
+A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150 PCMEM:3E,12,3C,47
+A:12 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0152 PCMEM:3C,47,18,FE
+A:13 F:10 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0153 PCMEM:47,18,FE,00
+A:13 F:10 B:13 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0154 PCMEM:18,FE,00,00
+";
+
+#[test]
+fn matching_traces_run_to_the_end() {
+    let format = TraceFormat::parse(DOCTOR_FORMAT).unwrap();
+    let mut emu = headless();
+    assert_eq!(
+        lockstep::compare(emu.cpu_mut(), &format, TRACE.lines()),
+        Ok(6)
+    );
+
+    // Columns in another layout, cycles from a later start
+    let format = TraceFormat::parse("{pc}  {a} cy={cycles}").unwrap();
+    let trace = "0100 01 cy=1000\n0101 01 cy=1004\n0150 01 cy=1020\n0152 12 cy=1028\n";
+    let mut emu = headless();
+    assert_eq!(
+        lockstep::compare(emu.cpu_mut(), &format, trace.lines()),
+        Ok(4)
+    );
+}
+
+#[test]
+fn the_first_divergence_shows_both_states() {
+    let format = TraceFormat::parse(DOCTOR_FORMAT).unwrap();
+    let trace = TRACE.replace("A:13 F:10 B:00", "A:14 F:10 B:00");
+    let mut emu = headless();
+
+    let Err(Mismatch::Diverged {
+        line,
+        columns,
+        previous,
+        reference,
+        emulator,
+    }) = lockstep::compare(emu.cpu_mut(), &format, trace.lines())
+    else {
+        panic!("no divergence");
+    };
+    assert_eq!(line, 5);
+    assert_eq!(columns, ["A"]);
+    assert!(previous.unwrap().contains("PC:0152"));
+    assert!(reference.starts_with("A:14 F:10"));
+    assert_eq!(
+        emulator,
+        "A:13 F:10 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0153 PCMEM:.."
+    );
+
+    let format = TraceFormat::parse("{pc} {cycles}").unwrap();
+    let mut emu = headless();
+    let mismatch = lockstep::compare(emu.cpu_mut(), &format, "0100 0\n0101 8\n".lines());
+    assert!(matches!(mismatch, Err(Mismatch::Diverged { line: 2, .. })));
+
+    let mut emu = headless();
+    let mismatch = lockstep::compare(emu.cpu_mut(), &format, "0100 0\nPC 0101\n".lines());
+    assert!(matches!(
+        mismatch,
+        Err(Mismatch::Unreadable { line: 2, .. })
+    ));
+    assert!(TraceFormat::parse("{pc} {ix}").is_err());
+}
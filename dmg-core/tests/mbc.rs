@@ -0,0 +1,113 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::CpuContext;
+use dmg_core::headless::Headless;
+
+/// 64 KiB cartridge of `rom_type` that selects ROM bank 2 and copies its
+/// first byte, 0x42, to $C000.
+fn build_test_rom(rom_type: u8) -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x02,             // LD A, $02
+        0xEA, 0x00, 0x20,       // LD ($2000), A    ; select ROM bank 2
+        0xFA, 0x00, 0x40,       // LD A, ($4000)
+        0xEA, 0x00, 0xC0,       // LD ($C000), A
+        0x18, 0xFE,             // JR @
+    ];
+
+    let mut rom = build_rom(&[(0x147, &[rom_type]), (0x150, main)]);
+    rom.resize(0x10000, 0);
+    rom[0x8000] = 0x42;
+    rom
+}
+
+fn run(rom_type: u8, infer: bool) -> Headless {
+    let rom = Cartridge::from_bytes("mbc.gb", &build_test_rom(rom_type)).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.emulator_mut().set_mapper_inference(infer);
+
+    for _ in 0..100 {
+        assert!(emu.step());
+    }
+    emu
+}
+
+#[test]
+fn mbc1_switches_rom_banks() {
+    let mut emu = run(0x01, false);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x42);
+}
+
+#[test]
+fn rom_only_bank_switch_is_detected() {
+    let mut emu = run(0x00, false);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x00);
+
+    let rom = emu.emulator_mut().cartridge().unwrap();
+    assert!(rom.undeclared_banking());
+    assert!(!rom.inferred_mapper());
+}
+
+#[test]
+fn mapper_inference_switches_to_mbc1() {
+    let mut emu = run(0x00, true);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x42);
+    assert!(emu.emulator_mut().cartridge().unwrap().inferred_mapper());
+}
+
+#[test]
+fn mbc1_banks_ram_in_mode_1() {
+    // MBC1+RAM+BATTERY with 32 KiB of RAM
+    let data = build_rom(&[(0x147, &[0x03]), (0x149, &[0x03])]);
+    let mut rom = Cartridge::from_bytes("mbc1.gb", &data).unwrap();
+
+    assert_eq!(rom.read(0xA000), 0xFF);
+    rom.write(0x0000, 0x0A);
+    rom.write(0x6000, 0x01);
+    for bank in 0..4 {
+        rom.write(0x4000, bank);
+        rom.write(0xA000, 0x10 + bank);
+    }
+
+    for bank in 0..4 {
+        rom.write(0x4000, bank);
+        assert_eq!(rom.read(0xA000), 0x10 + bank);
+    }
+    assert_eq!(rom.mapper_registers(), Some([0x0A, 0x61, 0x03]));
+
+    // Mode 0 always maps the first RAM bank
+    rom.write(0x6000, 0x00);
+    assert_eq!(rom.read(0xA000), 0x10);
+    assert_eq!(rom.mapper_report().ram_bank, Some(0));
+}
+
+#[test]
+fn mbc1_maps_upper_bank_at_0000_in_mode_1() {
+    // 1 MiB MBC1 cartridge, every bank starts with its own number
+    let mut data = build_rom(&[(0x147, &[0x01]), (0x148, &[0x05])]);
+    data.resize(0x100000, 0);
+    for bank in 1..64 {
+        data[bank * 0x4000] = bank as u8;
+    }
+    let mut rom = Cartridge::from_bytes("mbc1.gb", &data).unwrap();
+
+    rom.write(0x2000, 0x03);
+    rom.write(0x4000, 0x01);
+    assert_eq!(rom.read(0x4000), 0x23);
+    assert_eq!(rom.read(0x0000), 0x00);
+
+    rom.write(0x6000, 0x01);
+    assert_eq!(rom.read(0x0000), 0x20);
+    assert_eq!(rom.read(0x4000), 0x23);
+}
+
+#[test]
+fn mbc1_registers_reach_the_trace() {
+    let mut emu = run(0x01, false);
+    assert_eq!(
+        emu.emulator_mut().mapper_registers(),
+        Some([0x00, 0x02, 0x00])
+    );
+}
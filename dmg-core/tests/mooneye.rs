@@ -0,0 +1,163 @@
+//! Runs the Mooneye-GB acceptance suite headless and prints a result matrix.
+//!
+//! The suite is looked up in `tests/roms/mooneye/acceptance` (or under
+//! `DMG_TEST_ROMS`). Failures are reported but do not fail `cargo test`,
+//! the matrix is meant to track accuracy progress over time.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use common::{CLOCK_HZ, rom_path, save_diagnostics};
+use dmg_core::headless::Headless;
+
+const TIMEOUT_SECONDS: u64 = 10;
+// Mooneye tests load the Fibonacci numbers into B, C, D, E, H, L on success
+const PASS_FINGERPRINT: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Outcome {
+    Pass,
+    Fail,
+    Timeout,
+    Panic,
+}
+
+#[derive(Default)]
+struct CategoryResult {
+    passed: usize,
+    failed: Vec<(String, Outcome)>,
+}
+
+/// Model suffixes follow the Mooneye naming, e.g. `boot_regs-dmgABC` or `di_timing-GS`,
+/// where G stands for DMG. ROMs without a suffix run on every model.
+fn runs_on_dmg(path: &Path) -> bool {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+
+    match stem.rsplit_once('-') {
+        None => true,
+        Some((_, models)) => {
+            models.contains("dmgABC")
+                || (models.chars().all(|c| c.is_ascii_uppercase()) && models.contains('G'))
+        }
+    }
+}
+
+fn collect_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            collect_roms(&path, roms);
+        } else if path.extension().is_some_and(|ext| ext == "gb") && runs_on_dmg(&path) {
+            roms.push(path);
+        }
+    }
+}
+
+fn run_rom(path: &Path) -> Outcome {
+    let Ok(mut emu) = Headless::from_file(path.to_str().unwrap()) else {
+        return Outcome::Panic;
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        if !emu.run_until_breakpoint(TIMEOUT_SECONDS * CLOCK_HZ) {
+            return Outcome::Timeout;
+        }
+
+        let r = emu.cpu().registers();
+        let fingerprint = [r.b, r.c, r.d, r.e, r.h, r.l];
+
+        if fingerprint == PASS_FINGERPRINT {
+            Outcome::Pass
+        } else {
+            // Failing tests load 0x42 into every register
+            Outcome::Fail
+        }
+    }));
+
+    let outcome = result.unwrap_or(Outcome::Panic);
+
+    if outcome != Outcome::Pass {
+        save_diagnostics(&emu, &path.file_stem().unwrap().to_string_lossy());
+    }
+
+    outcome
+}
+
+fn main() {
+    let Some(suite) = rom_path("mooneye/acceptance") else {
+        return;
+    };
+
+    let mut roms = Vec::new();
+    collect_roms(&suite, &mut roms);
+    roms.sort();
+
+    // Emulator panics are reported in the matrix, keep the output readable
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut results: BTreeMap<String, CategoryResult> = BTreeMap::new();
+
+    for rom in &roms {
+        let category = match rom.parent().unwrap().strip_prefix(&suite) {
+            Ok(dir) if dir.as_os_str().is_empty() => String::from("general"),
+            Ok(dir) => dir.to_string_lossy().to_string(),
+            Err(_) => String::from("general"),
+        };
+        let name = rom.file_stem().unwrap().to_string_lossy().to_string();
+        let outcome = run_rom(rom);
+        let entry = results.entry(category).or_default();
+
+        if outcome == Outcome::Pass {
+            entry.passed += 1;
+        } else {
+            entry.failed.push((name, outcome));
+        }
+    }
+
+    let _ = panic::take_hook();
+
+    println!("\nMooneye acceptance results");
+    println!(
+        "{:<16} {:>6} {:>6} {:>6}",
+        "category", "passed", "failed", "total"
+    );
+
+    let mut total_passed = 0;
+    let mut total = 0;
+
+    for (category, result) in &results {
+        let count = result.passed + result.failed.len();
+        total_passed += result.passed;
+        total += count;
+        println!(
+            "{:<16} {:>6} {:>6} {:>6}",
+            category,
+            result.passed,
+            result.failed.len(),
+            count
+        );
+    }
+
+    println!(
+        "{:<16} {:>6} {:>6} {:>6}",
+        "total",
+        total_passed,
+        total - total_passed,
+        total
+    );
+
+    for (category, result) in &results {
+        for (name, outcome) in &result.failed {
+            println!("  {category}/{name}: {outcome:?}");
+        }
+    }
+}
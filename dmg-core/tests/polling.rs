@@ -0,0 +1,39 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::headless::Headless;
+use dmg_core::polling::PollCounter;
+
+#[test]
+fn busy_wait_loops_lead_the_report() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0xF0, 0x44,       // wait: LDH A, (LY)
+        0xFE, 0x90,       // CP 144
+        0x20, 0xFA,       // JR NZ, wait
+        0xF0, 0x41,       // LDH A, (STAT)
+        0x18, 0xF4,       // JR wait
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("poll.gb", &rom).unwrap());
+    emu.emulator_mut()
+        .set_poll_counter(Some(PollCounter::new()));
+
+    assert!(emu.run_frames(5));
+
+    let polls = emu.emulator().poll_counter().unwrap();
+    let hottest = polls.hottest();
+    assert_eq!(hottest[0].0, 0x150);
+    assert_eq!(hottest[1].0, 0x156);
+    assert!(hottest[0].1.ly > 100 * hottest[1].1.stat);
+    assert_eq!(hottest[1].1.ly, 0);
+    assert!(
+        polls
+            .to_string()
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("0150")
+    );
+}
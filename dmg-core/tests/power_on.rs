@@ -0,0 +1,202 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::CpuContext;
+use dmg_core::headless::Headless;
+use dmg_core::power::{BOOT_ROM_SIZE, Model, Quirks, RamInit};
+
+/// Registers as read at 0x0100 on a DMG, from the Pan Docs.
+const DMG_IO: &[(u16, u8)] = &[
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7E), // SC
+    (0xFF04, 0xAC), // DIV
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF26, 0xF1), // NR52
+    (0xFF40, 0x91), // LCDC
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF45, 0x00), // LYC
+    (0xFF47, 0xFC), // BGP
+    (0xFF4A, 0x00), // WY
+    (0xFF4B, 0x00), // WX
+    (0xFFFF, 0x00), // IE
+];
+
+fn power_on(model: Model) -> Headless {
+    let rom = Cartridge::from_bytes("power.gb", &build_rom(&[])).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.emulator_mut().set_model(model);
+    emu.reset();
+    emu
+}
+
+#[test]
+fn dmg_io_registers_match_boot_rom() {
+    let mut emu = power_on(Model::Dmg);
+
+    for &(address, value) in DMG_IO {
+        assert_eq!(
+            emu.emulator_mut().peek(address),
+            value,
+            "register ${address:04X}"
+        );
+    }
+}
+
+#[test]
+fn models_differ_in_registers_and_sound() {
+    let mut emu = power_on(Model::Sgb);
+    let r = emu.cpu().registers();
+    assert_eq!((r.a, r.c, r.h, r.l), (0x01, 0x14, 0xC0, 0x60));
+    // No boot sound on the SGB
+    assert_eq!(emu.emulator_mut().peek(0xFF26), 0xF0);
+
+    let emu = power_on(Model::Mgb);
+    assert_eq!(emu.cpu().registers().a, 0xFF);
+}
+
+#[test]
+fn div_phase_quirk_selects_the_starting_div() {
+    assert!(!Quirks::for_model(Model::Sgb).contains(Quirks::DIV_PHASE));
+
+    let mut emu = power_on(Model::Dmg0);
+    assert_eq!(emu.emulator_mut().peek(0xFF04), 0x18);
+
+    let quirks = emu.emulator().quirks();
+    emu.emulator_mut()
+        .set_quirks(quirks.difference(Quirks::DIV_PHASE));
+    emu.reset();
+    assert_eq!(emu.emulator_mut().peek(0xFF04), 0x00);
+}
+
+#[test]
+fn ram_init_fills_ram_on_reset() {
+    let mut emu = power_on(Model::Dmg);
+    emu.emulator_mut().set_ram_init(RamInit::Pattern(0x55));
+    emu.reset();
+    assert_eq!(emu.emulator_mut().peek(0xC123), 0x55);
+    assert_eq!(emu.emulator_mut().peek(0xFF90), 0x55);
+    assert_eq!(emu.emulator_mut().peek(0x8000), 0x55);
+
+    emu.emulator_mut().set_ram_init(RamInit::Random(42));
+    let first: Vec<u8> = (0xC000..0xC100)
+        .map(|address| emu.emulator_mut().peek(address))
+        .collect();
+    emu.reset();
+    let second: Vec<u8> = (0xC000..0xC100)
+        .map(|address| emu.emulator_mut().peek(address))
+        .collect();
+
+    assert_eq!(first, second);
+    assert!(first.iter().any(|byte| *byte != first[0]));
+}
+
+#[test]
+fn soft_reset_keeps_ram() {
+    let mut emu = power_on(Model::Dmg);
+    emu.run_frames(1);
+    emu.emulator_mut().poke(0xC000, 0x12);
+    emu.emulator_mut().poke(0xFF40, 0x00);
+    emu.emulator_mut().poke(0xC100, 0x34);
+    // OAM reads as 0xFF while a DMA runs, the reset keeps what is stored
+    emu.emulator_mut().poke(0xFF46, 0xC1);
+    for _ in 0..4 {
+        emu.step();
+    }
+    assert_eq!(emu.emulator_mut().peek(0xFE00), 0xFF);
+
+    emu.soft_reset();
+    assert_eq!(emu.cpu().registers().pc, 0x100);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x12);
+    assert_eq!(emu.emulator_mut().peek(0xFE00), 0x34);
+    assert_eq!(emu.emulator_mut().peek(0xFF40), 0x91);
+
+    emu.reset();
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x00);
+}
+
+#[test]
+fn boot_rom_runs_until_it_unmaps_itself() {
+    // NOPs up to the hand over at 0x00FC, like the real one ends
+    let mut boot_rom = [0x00; BOOT_ROM_SIZE];
+    boot_rom[0xFC..].copy_from_slice(&[
+        0x3E, 0x01, // LD A, $01
+        0xE0, 0x50, // LDH ($50), A
+    ]);
+
+    let mut emu = power_on(Model::Dmg);
+    emu.emulator_mut().set_boot_rom(Some(boot_rom));
+    emu.reset();
+    assert_eq!(emu.cpu().registers().pc, 0x0000);
+    assert_eq!(emu.emulator_mut().peek(0x00FC), 0x3E);
+    assert_eq!(emu.emulator_mut().peek(0xFF40), 0x00);
+
+    for _ in 0..BOOT_ROM_SIZE {
+        if !emu.emulator().boot_rom_mapped() {
+            break;
+        }
+        assert!(emu.step());
+    }
+
+    assert!(!emu.emulator().boot_rom_mapped());
+    assert_eq!(emu.cpu().registers().pc, 0x0100);
+    assert_eq!(emu.emulator_mut().peek(0x00FC), 0x00);
+
+    emu.soft_reset();
+    assert!(!emu.emulator().boot_rom_mapped());
+    emu.reset();
+    assert!(emu.emulator().boot_rom_mapped());
+}
+
+#[test]
+fn swapping_cartridges_starts_the_new_game() {
+    #[rustfmt::skip]
+    let save: &[u8] = &[
+        0x3E, 0x0A,       // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A    ; enable cartridge RAM
+        0x3E, 0x42,       // LD A, $42
+        0xEA, 0x00, 0xA0, // LD ($A000), A
+        0x18, 0xFE,       // JR @
+    ];
+    // MBC1 with battery backed RAM
+    let first = build_rom(&[(0x147, &[0x03]), (0x149, &[0x02]), (0x150, save)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("first.gb", &first).unwrap());
+    emu.run_frames(2);
+    emu.emulator_mut().freeze(0xC000, 0x99);
+
+    let second = build_rom(&[(0x150, &[0x18, 0xFE])]);
+    let old = emu.swap_cartridge(Cartridge::from_bytes("second.gb", &second).unwrap());
+    assert_eq!(old.unwrap().battery_data()[0], 0x42);
+    assert_eq!(emu.cpu().registers().pc, 0x0100);
+    assert_eq!(emu.emulator().get_current_frame(), 0);
+    assert_eq!(emu.emulator().frozen().count(), 0);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x00);
+
+    assert!(emu.run_frames(1));
+    assert_eq!(emu.cpu().registers().pc, 0x0150);
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::Requests;
+use dmg_core::bus::HardwareRegister;
+use dmg_core::interrupts::InterruptFlag;
+use dmg_core::lcd::{LcdMode, LcdStatus};
+use dmg_core::ppu::PPU;
+
+const DOTS_PER_LINE: u32 = 456;
+
+fn run_ppu(ppu: &mut PPU, requests: &mut Requests, dots: u32) {
+    for _ in 0..dots {
+        ppu.tick(requests);
+    }
+}
+
+/// PPU drawing each line at once, so mode 3 has a fixed length.
+fn line_renderer() -> PPU {
+    let mut ppu = PPU::new();
+    ppu.set_fifo_renderer(false);
+    ppu
+}
+
+#[test]
+fn modes_change_on_the_exact_dot() {
+    let mut ppu = line_renderer();
+    let mut requests = Requests::default();
+
+    run_ppu(&mut ppu, &mut requests, 79);
+    assert_eq!(ppu.mode(), LcdMode::OAM);
+    run_ppu(&mut ppu, &mut requests, 1);
+    assert_eq!(ppu.mode(), LcdMode::XFER);
+
+    // 172 dots of mode 3 on a line without sprites
+    run_ppu(&mut ppu, &mut requests, 171);
+    assert_eq!(ppu.mode(), LcdMode::XFER);
+    run_ppu(&mut ppu, &mut requests, 1);
+    assert_eq!(ppu.mode(), LcdMode::HBLANK);
+
+    run_ppu(&mut ppu, &mut requests, DOTS_PER_LINE - 253);
+    assert_eq!(ppu.lcd_read(HardwareRegister::LY), 0);
+    assert_eq!(ppu.mode(), LcdMode::HBLANK);
+    run_ppu(&mut ppu, &mut requests, 1);
+    assert_eq!(ppu.lcd_read(HardwareRegister::LY), 1);
+    assert_eq!(ppu.mode(), LcdMode::OAM);
+    assert!(requests.0.is_empty());
+}
+
+#[test]
+fn vblank_starts_and_ends_on_the_exact_dot() {
+    let mut ppu = line_renderer();
+    let mut requests = Requests::default();
+
+    run_ppu(&mut ppu, &mut requests, 144 * DOTS_PER_LINE - 1);
+    assert_eq!(ppu.lcd_read(HardwareRegister::LY), 143);
+    assert!(requests.0.is_empty());
+
+    run_ppu(&mut ppu, &mut requests, 1);
+    assert_eq!(ppu.lcd_read(HardwareRegister::LY), 144);
+    assert_eq!(ppu.mode(), LcdMode::VBLANK);
+    assert_eq!(requests.0, [InterruptFlag::VBLANK]);
+    assert_eq!(ppu.get_current_frame(), 1);
+
+    run_ppu(&mut ppu, &mut requests, 10 * DOTS_PER_LINE - 1);
+    assert_eq!(ppu.lcd_read(HardwareRegister::LY), 153);
+    run_ppu(&mut ppu, &mut requests, 1);
+    assert_eq!(ppu.lcd_read(HardwareRegister::LY), 0);
+    assert_eq!(ppu.mode(), LcdMode::OAM);
+    assert_eq!(requests.0, [InterruptFlag::VBLANK]);
+}
+
+#[test]
+fn stat_interrupts_follow_the_selected_sources() {
+    let mut ppu = line_renderer();
+    let mut requests = Requests::default();
+    let stat = LcdStatus::HBLANK_INT_SELECT | LcdStatus::LYC_INT_SELECT;
+    ppu.lcd_write(HardwareRegister::STAT, stat.bits(), &mut requests);
+    ppu.lcd_write(HardwareRegister::LYC, 2, &mut requests);
+
+    // Mode 0 of line 0
+    run_ppu(&mut ppu, &mut requests, 251);
+    assert!(requests.0.is_empty());
+    run_ppu(&mut ppu, &mut requests, 1);
+    assert_eq!(requests.0, [InterruptFlag::LCD]);
+
+    // LY reaching LYC at the start of line 2, before its mode 0
+    run_ppu(&mut ppu, &mut requests, 2 * DOTS_PER_LINE - 252);
+    assert_eq!(requests.0, [InterruptFlag::LCD; 3]);
+    let stat = ppu.lcd_read(HardwareRegister::STAT);
+    assert_ne!(stat & LcdStatus::LYC_EQUAL_LY.bits(), 0);
+
+    run_ppu(&mut ppu, &mut requests, DOTS_PER_LINE);
+    let stat = ppu.lcd_read(HardwareRegister::STAT);
+    assert_eq!(stat & LcdStatus::LYC_EQUAL_LY.bits(), 0);
+}
+
+#[test]
+fn lyc_matches_line_0_after_the_wrap() {
+    let mut ppu = line_renderer();
+    let mut requests = Requests::default();
+    ppu.lcd_write(HardwareRegister::LYC, 0, &mut requests);
+    let stat = LcdStatus::LYC_INT_SELECT;
+    ppu.lcd_write(HardwareRegister::STAT, stat.bits(), &mut requests);
+
+    run_ppu(&mut ppu, &mut requests, 154 * DOTS_PER_LINE - 1);
+    assert_eq!(requests.0, [InterruptFlag::VBLANK]);
+    run_ppu(&mut ppu, &mut requests, 1);
+    assert_eq!(ppu.lcd_read(HardwareRegister::LY), 0);
+    assert_eq!(requests.0, [InterruptFlag::VBLANK, InterruptFlag::LCD]);
+    let stat = ppu.lcd_read(HardwareRegister::STAT);
+    assert_ne!(stat & LcdStatus::LYC_EQUAL_LY.bits(), 0);
+}
+
+#[test]
+fn lyc_writes_are_compared_right_away() {
+    let mut ppu = line_renderer();
+    let mut requests = Requests::default();
+    let stat = LcdStatus::LYC_INT_SELECT;
+    ppu.lcd_write(HardwareRegister::LYC, 0x90, &mut requests);
+    ppu.lcd_write(HardwareRegister::STAT, stat.bits(), &mut requests);
+
+    run_ppu(&mut ppu, &mut requests, 5 * DOTS_PER_LINE + 100);
+    assert!(requests.0.is_empty());
+
+    // LY is 5, the line already started
+    ppu.lcd_write(HardwareRegister::LYC, 5, &mut requests);
+    assert_eq!(requests.0, [InterruptFlag::LCD]);
+
+    // Writing the value it already matches isn't a new match
+    ppu.lcd_write(HardwareRegister::LYC, 5, &mut requests);
+    assert_eq!(requests.0, [InterruptFlag::LCD]);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::headless::Headless;
+use dmg_core::profiler::ProfileCount;
+use dmg_core::profiler::Profiler;
+use dmg_core::symbols::SymbolTable;
+
+#[test]
+fn cycles_and_calls_are_summed_per_symbol() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0xCD, 0x00, 0x02, // Main: CALL Sub
+        0xFF,             // RST $38
+        0x18, 0xFA,       // JR Main
+    ];
+    #[rustfmt::skip]
+    let sub: &[u8] = &[
+        0x00,             // Sub: NOP
+        0xC9,             // .end: RET
+    ];
+    let rom = build_rom(&[(0x38, &[0xC9]), (0x150, main), (0x200, sub)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("profile.gb", &rom).unwrap());
+    emu.emulator_mut().set_profiler(Some(Profiler::new()));
+    assert!(emu.run_frames(2));
+
+    let symbols = SymbolTable::parse(
+        "; labels\n00:0038 Rst38\n00:0150 Main\n00:0200 Sub\n00:0201 Sub.end ; local\n",
+    )
+    .unwrap();
+    assert_eq!(symbols.get(0, 0x201), Some("Sub.end"));
+    assert_eq!(symbols.containing(0, 0x201), Some(("Sub", 1)));
+    assert_eq!(symbols.containing(1, 0x4000), None);
+    assert!(SymbolTable::parse("Main 00:0150").is_none());
+
+    let profiler = emu.emulator().profiler().unwrap();
+    let routines = profiler.by_symbol(Some(&symbols));
+    let routine = |name: &str| routines.iter().find(|r| r.0 == name).unwrap().3;
+
+    let (main, sub, rst) = (routine("Main"), routine("Sub"), routine("Rst38"));
+    assert_eq!(main.calls, 0);
+    assert!(sub.calls > 1000);
+    assert_eq!(sub.executions, 2 * sub.calls);
+    assert!(rst.calls.abs_diff(sub.calls) <= 1);
+    // NOP and RET, the last call may not be finished
+    assert!(sub.cycles.abs_diff(20 * sub.calls) <= 20);
+    assert_eq!(profiler.get(0, 0x200).executions, sub.calls);
+
+    let json = profiler.to_json(Some(&symbols));
+    assert!(json.contains(&format!("\"cycles\": {}", profiler.total_cycles())));
+    assert!(json.contains("\"Sub\": {\"bank\": 0, \"address\": 512, "));
+    // Without labels every instruction is its own entry
+    assert!(profiler.to_json(None).contains("\"00:0201\": {"));
+}
+
+#[test]
+fn loading_an_earlier_state_leaves_the_last_instruction_untimed() {
+    let main: &[u8] = &[0x18, 0xFE]; // loop: JR loop
+    let rom = build_rom(&[(0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("profile.gb", &rom).unwrap());
+    emu.emulator_mut().set_profiler(Some(Profiler::new()));
+    let early = emu.save_state();
+
+    assert!(emu.run_frames(1));
+    emu.load_state(&early).unwrap();
+    assert!(emu.run_frames(1));
+
+    let profiler = emu.emulator().profiler().unwrap();
+    let ProfileCount {
+        executions, cycles, ..
+    } = profiler.get(0, 0x150);
+    // 12 T-cycles each, but the one before the load and the last one
+    assert_eq!(cycles, 12 * (executions - 2));
+}
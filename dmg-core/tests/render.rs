@@ -0,0 +1,422 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::build_rom;
+use dmg_core::bus::HardwareRegister;
+use dmg_core::cart::Cartridge;
+use dmg_core::emu::{AccuracyConfig, AccuracyLevel};
+use dmg_core::frame::{Palette, PixelSource};
+use dmg_core::headless::Headless;
+use dmg_core::lcd::PaletteRegister;
+use dmg_core::placeholder::placeholder_rom;
+use dmg_core::ppu::{Layers, SPRITES_PER_LINE};
+use dmg_core::raster::LcdRegisters;
+use dmg_core::vram::{self, TileSet};
+
+/// Background of tile 1 on the first two map rows and the same tile as two
+/// sprites on one line.
+fn build_scene_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0x10, 0x80,   // LD HL, $8010
+        0x3E, 0x3C,         // LD A, $3C
+        0x06, 0x10,         // LD B, 16
+        0x22,               // tile: LD (HL+), A
+        0x05,               // DEC B
+        0x20, 0xFC,         // JR NZ, tile
+        0x21, 0x00, 0x98,   // LD HL, $9800
+        0x3E, 0x01,         // LD A, 1
+        0x06, 0x40,         // LD B, 64
+        0x22,               // map: LD (HL+), A
+        0x05,               // DEC B
+        0x20, 0xFC,         // JR NZ, map
+        0x21, 0x00, 0xFE,   // LD HL, $FE00
+        0x3E, 0x28, 0x22,   // LD A, 40 ; LD (HL+), A (Y)
+        0x3E, 0x1E, 0x22,   // LD A, 30 ; LD (HL+), A (X)
+        0x3E, 0x01, 0x22,   // LD A, 1 ; LD (HL+), A (tile)
+        0xAF, 0x22,         // XOR A ; LD (HL+), A (flags)
+        0x3E, 0x28, 0x22,   // LD A, 40 ; LD (HL+), A (Y)
+        0x3E, 0x3C, 0x22,   // LD A, 60 ; LD (HL+), A (X)
+        0x3E, 0x01, 0x22,   // LD A, 1 ; LD (HL+), A (tile)
+        0xAF, 0x22,         // XOR A ; LD (HL+), A (flags)
+        0x3E, 0xE4,         // LD A, $E4
+        0xE0, 0x47,         // LDH (BGP), A
+        0xE0, 0x48,         // LDH (OBP0), A
+        0x3E, 0x93,         // LD A, $93
+        0xE0, 0x40,         // LDH (LCDC), A, sprites on
+        0x18, 0xFE,         // JR -2
+    ];
+
+    build_rom(&[(0x150, main)])
+}
+
+fn render(level: AccuracyLevel, layers: Layers) -> Vec<u32> {
+    let rom = Cartridge::from_bytes("scene.gb", &build_scene_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.emulator_mut()
+        .set_accuracy(AccuracyConfig::preset(level));
+    emu.emulator_mut().set_visible_layers(layers);
+    emu.run_frames(3);
+    emu.emulator().ppu().frame().as_argb8888()
+}
+
+#[test]
+fn line_renderer_matches_pixel_fifo() {
+    let fifo = render(AccuracyLevel::Balanced, Layers::all());
+    let line = render(AccuracyLevel::Fast, Layers::all());
+
+    assert!(fifo.iter().any(|pixel| *pixel != fifo[0]));
+    assert!(fifo == line);
+}
+
+#[test]
+fn sprites_on_one_line_are_all_drawn() {
+    for level in [AccuracyLevel::Balanced, AccuracyLevel::Fast] {
+        let frame = render(level, Layers::all());
+        // Row 26 is below the background tiles, the tile is opaque from x 2 to 5
+        let pixel = |x: usize| frame[26 * 160 + x];
+
+        assert_ne!(pixel(30 - 8 + 3), pixel(100), "{level:?}");
+        assert_ne!(pixel(60 - 8 + 3), pixel(100), "{level:?}");
+    }
+}
+
+#[test]
+fn hidden_layers_are_not_drawn() {
+    for level in [AccuracyLevel::Balanced, AccuracyLevel::Fast] {
+        let all = render(level, Layers::all());
+        let background = render(level, Layers::BACKGROUND | Layers::WINDOW);
+        let nothing = render(level, Layers::empty());
+
+        assert!(all != background);
+        assert!(background.iter().any(|pixel| *pixel != background[0]));
+        assert!(nothing.iter().all(|pixel| *pixel == nothing[0]));
+    }
+}
+
+#[test]
+fn pixels_tell_which_layer_won_priority() {
+    for level in [AccuracyLevel::Balanced, AccuracyLevel::Fast] {
+        let rom = Cartridge::from_bytes("scene.gb", &build_scene_rom()).unwrap();
+        let mut emu = Headless::new(rom);
+        emu.emulator_mut()
+            .set_accuracy(AccuracyConfig::preset(level));
+        emu.run_frames(3);
+        let frame = emu.emulator().ppu().frame();
+
+        assert_eq!(
+            frame.source(0, 0),
+            PixelSource::BackgroundColor0,
+            "{level:?}"
+        );
+        assert_eq!(frame.source(3, 0), PixelSource::Background, "{level:?}");
+        // The sprite at 22, 24 is transparent on its first two columns
+        assert_eq!(
+            frame.source(22, 24),
+            PixelSource::BackgroundColor0,
+            "{level:?}"
+        );
+        assert_eq!(frame.source(25, 24), PixelSource::Object0, "{level:?}");
+        assert_ne!(frame.priority_pixel(25, 24), frame.pixel(25, 24));
+    }
+}
+
+/// Twelve solid sprites side by side on lines 24 to 31, 12 pixels apart.
+fn build_crowded_line_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0x10, 0x80,   // LD HL, $8010
+        0x3E, 0xFF,         // LD A, $FF
+        0x06, 0x10,         // LD B, 16
+        0x22,               // tile: LD (HL+), A
+        0x05,               // DEC B
+        0x20, 0xFC,         // JR NZ, tile
+        0x21, 0x00, 0xFE,   // LD HL, $FE00
+        0x06, 0x0C,         // LD B, 12
+        0x0E, 0x08,         // LD C, 8
+        0x3E, 0x28,         // sprite: LD A, 40
+        0x22,               // LD (HL+), A (Y)
+        0x79,               // LD A, C
+        0x22,               // LD (HL+), A (X)
+        0xC6, 0x0C,         // ADD A, 12
+        0x4F,               // LD C, A
+        0x3E, 0x01,         // LD A, 1
+        0x22,               // LD (HL+), A (tile)
+        0xAF,               // XOR A
+        0x22,               // LD (HL+), A (flags)
+        0x05,               // DEC B
+        0x20, 0xF0,         // JR NZ, sprite
+        0x3E, 0xE4,         // LD A, $E4
+        0xE0, 0x47,         // LDH (BGP), A
+        0xE0, 0x48,         // LDH (OBP0), A
+        0x3E, 0x93,         // LD A, $93
+        0xE0, 0x40,         // LDH (LCDC), A, sprites on
+        0x18, 0xFE,         // JR -2
+    ];
+
+    build_rom(&[(0x150, main)])
+}
+
+#[test]
+fn sprite_limit_can_be_raised() {
+    for level in [AccuracyLevel::Balanced, AccuracyLevel::Fast] {
+        for (limit, drawn) in [(SPRITES_PER_LINE, 10), (40, 12)] {
+            let rom = Cartridge::from_bytes("crowded.gb", &build_crowded_line_rom()).unwrap();
+            let mut emu = Headless::new(rom);
+            emu.emulator_mut()
+                .set_accuracy(AccuracyConfig::preset(level));
+            emu.emulator_mut().set_sprite_limit(limit);
+            emu.run_frames(3);
+            let frame = emu.emulator().ppu().frame();
+
+            let shown = (0..12)
+                .filter(|i| frame.source(i * 12 + 3, 28) == PixelSource::Object0)
+                .count();
+            assert_eq!(shown, drawn, "{level:?} limit {limit}");
+        }
+    }
+}
+
+/// The window on tile 1 from line `wy`, WY is set on line 20 of every frame
+/// and hidden again at VBlank.
+fn build_window_rom(wy: u8) -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0x10, 0x80,   // LD HL, $8010
+        0x3E, 0xFF,         // LD A, $FF
+        0x06, 0x10,         // LD B, 16
+        0x22,               // tile: LD (HL+), A
+        0x05,               // DEC B
+        0x20, 0xFC,         // JR NZ, tile
+        0x21, 0x00, 0x9C,   // LD HL, $9C00
+        0x01, 0x00, 0x04,   // LD BC, $0400
+        0x3E, 0x01,         // map: LD A, 1
+        0x22,               // LD (HL+), A
+        0x0B,               // DEC BC
+        0x78,               // LD A, B
+        0xB1,               // OR C
+        0x20, 0xF8,         // JR NZ, map
+        0x3E, 0x07,         // LD A, 7
+        0xE0, 0x4B,         // LDH (WX), A
+        0x3E, 0xF1,         // LD A, $F1
+        0xE0, 0x40,         // LDH (LCDC), A, window on from $9C00
+        0x3E, 0xC8,         // frame: LD A, 200
+        0xE0, 0x4A,         // LDH (WY), A
+        0xF0, 0x44,         // line20: LDH A, (LY)
+        0xFE, 0x14,         // CP 20
+        0x20, 0xFA,         // JR NZ, line20
+        0x3E, wy,           // LD A, wy
+        0xE0, 0x4A,         // LDH (WY), A
+        0xF0, 0x44,         // vblank: LDH A, (LY)
+        0xFE, 0x90,         // CP 144
+        0x20, 0xFA,         // JR NZ, vblank
+        0x18, 0xEA,         // JR frame
+    ];
+
+    build_rom(&[(0x150, main)])
+}
+
+#[test]
+fn window_waits_for_wy_to_match_ly() {
+    for level in [AccuracyLevel::Balanced, AccuracyLevel::Fast] {
+        for (wy, first_line) in [(21, Some(21)), (10, None)] {
+            let rom = Cartridge::from_bytes("window.gb", &build_window_rom(wy)).unwrap();
+            let mut emu = Headless::new(rom);
+            emu.emulator_mut()
+                .set_accuracy(AccuracyConfig::preset(level));
+            emu.run_frames(4);
+            let frame = emu.emulator().ppu().frame();
+
+            // WY = 10 is written after line 10, LY never matches it
+            for y in [0, 15, 20, 21, 30, 143] {
+                let expected = match first_line {
+                    Some(first) if y >= first => PixelSource::Window,
+                    _ => PixelSource::BackgroundColor0,
+                };
+                assert_eq!(frame.source(40, y), expected, "{level:?} WY {wy} line {y}");
+            }
+        }
+    }
+}
+
+#[test]
+fn background_map_matches_frame() {
+    let rom = Cartridge::from_bytes("scene.gb", &build_scene_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.run_frames(3);
+
+    let ppu = emu.emulator().ppu();
+    let map = vram::background_map(ppu);
+    let sheet = vram::tile_sheet(ppu);
+    assert_eq!((map.width, map.height), (256, 256));
+    assert_eq!((sheet.width, sheet.height), (128, 192));
+    // Tile 1 is the second tile of the sheet
+    assert_eq!(sheet.pixels[8 + 3], ppu.frame().pixel(3, 0));
+
+    // Map rows above the sprite, no scrolling
+    for y in 0..16 {
+        for x in 0..160 {
+            assert_eq!(map.pixels[y * 256 + x], ppu.frame().pixel(x, y));
+        }
+    }
+}
+
+#[test]
+fn sprites_decode_like_the_frame() {
+    let rom = Cartridge::from_bytes("scene.gb", &build_scene_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.run_frames(3);
+
+    let ppu = emu.emulator().ppu();
+    let sprites = vram::sprites(ppu);
+    assert_eq!(sprites.len(), 40);
+    assert_eq!(sprites[1].screen_position(), (52, 24));
+    assert_eq!(sprites[1].tile, 1);
+
+    // Rows of 0x3C: two pixels of color 0 on each side of color 3
+    let tile = [0x3C; 16];
+    assert_eq!(vram::decode_tile(&tile)[0], [0, 0, 3, 3, 3, 3, 0, 0]);
+
+    let image = vram::sprite(ppu, &sprites[0]);
+    assert_eq!((image.width, image.height), (8, 8));
+    assert_eq!(image.pixels[0], 0, "color 0 is transparent");
+    assert_eq!(image.pixels[3], ppu.frame().pixel(22 + 3, 24));
+
+    let sheet = vram::oam_sheet(ppu);
+    assert_eq!((sheet.width, sheet.height), (64, 40));
+    assert_eq!(sheet.pixels[8 + 3], image.pixels[3]);
+    assert_eq!(sheet.to_rgba().len(), 64 * 40 * 4);
+
+    let window = vram::window_map(ppu);
+    let low = vram::tile_map(ppu, vram::TileMapArea::Low, vram::TileDataArea::Unsigned);
+    // LCDC $93 puts both on 0x9800 with tiles from 0x8000
+    assert_eq!(window.pixels, low.pixels);
+    assert_eq!(
+        vram::tile(ppu, 1, &Palette::default().background)
+            .unwrap()
+            .pixels[..8],
+        low.pixels[..8]
+    );
+    assert!(vram::tile(ppu, vram::TILE_COUNT, &Palette::default().background).is_none());
+    assert!(vram::tile(ppu, 512, &Palette::default().background).is_none());
+}
+
+#[test]
+fn written_tiles_are_dirty() {
+    let rom = Cartridge::from_bytes("scene.gb", &build_scene_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+    // Everything counts as changed at power on
+    assert_eq!(emu.emulator_mut().take_dirty_tiles(), TileSet::all());
+
+    emu.run_frames(1);
+    let dirty = emu.emulator_mut().take_dirty_tiles();
+    assert_eq!(dirty.iter().collect::<Vec<_>>(), [1]);
+
+    emu.run_frames(1);
+    assert!(emu.emulator_mut().take_dirty_tiles().is_empty());
+}
+
+#[test]
+fn palette_files_parse() {
+    let palette = Palette::parse("; Light to dark\n#E0F8D0 88C070\n346856\n081820\n").unwrap();
+
+    assert_eq!(
+        palette.background,
+        [0xFFE0F8D0, 0xFF88C070, 0xFF346856, 0xFF081820]
+    );
+    assert_eq!(palette.object0, palette.background);
+    assert!(Palette::parse("E0F8D0 88C070 346856").is_none());
+    assert!(Palette::parse("E0F8D0 88C070 346856 081820 000000").is_none());
+    assert!(Palette::parse("E0F8D0 88C070 346856 0818GG").is_none());
+
+    // Shades have to stay apart without telling hues apart
+    let luma = |color: u32| {
+        let [_, r, g, b] = color.to_be_bytes();
+        r as u32 * 299 + g as u32 * 587 + b as u32 * 114
+    };
+
+    for (name, palette) in Palette::PRESETS {
+        let colors = palette.background;
+        assert!(
+            colors.windows(2).all(|pair| luma(pair[0]) > luma(pair[1])),
+            "{name} isn't light to dark"
+        );
+    }
+}
+
+#[test]
+fn placeholder_rom_shows_its_text() {
+    let rom = Cartridge::from_bytes("placeholder.gb", &placeholder_rom(&["No ROM"])).unwrap();
+    assert_eq!(rom.header.validate(), []);
+    let mut emu = Headless::new(rom);
+    assert!(emu.run_frames(3));
+
+    // One line centered on the 18 rows of tiles, row 8
+    let frame = emu.emulator().ppu().frame();
+    let paper = frame.pixel(0, 0);
+    let inked_rows: Vec<usize> = (0..144)
+        .filter(|&y| (0..160).any(|x| frame.pixel(x, y) != paper))
+        .collect();
+    assert_eq!(inked_rows, (64..71).collect::<Vec<_>>());
+}
+
+#[test]
+fn scanline_hook_changes_registers_mid_frame() {
+    let rom = Cartridge::from_bytes("scene.gb", &build_scene_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let seen = lines.clone();
+
+    // A white background palette on lines 4 to 11
+    emu.emulator_mut()
+        .set_scanline_hook(Some(Box::new(move |ly: u8, lcd: &mut LcdRegisters| {
+            seen.lock().unwrap().push(ly);
+            match ly {
+                4 => lcd.write(HardwareRegister::BGP, 0x00),
+                12 => lcd.write(HardwareRegister::BGP, 0xE4),
+                _ => (),
+            }
+        })));
+    emu.run_frames(3);
+
+    let lines = lines.lock().unwrap();
+    let frame: Vec<u8> = lines.iter().rev().take(154).rev().copied().collect();
+    assert_eq!(frame.iter().filter(|ly| **ly == 0).count(), 1);
+    assert!(frame.windows(2).all(|pair| pair[1] == (pair[0] + 1) % 154));
+
+    let plain = render(AccuracyLevel::Balanced, Layers::all());
+    let hooked = emu.emulator().ppu().frame().as_argb8888();
+    let row = |pixels: &[u32], y: usize| pixels[y * 160..(y + 1) * 160].to_vec();
+
+    for y in 0..16 {
+        let background = row(&hooked, y)
+            .iter()
+            .all(|pixel| *pixel == hooked[4 * 160]);
+        assert_eq!(row(&hooked, y) == row(&plain, y), !(4..12).contains(&y));
+        assert_eq!(background, (4..12).contains(&y));
+    }
+}
+
+#[test]
+fn palette_registers_can_be_set_from_outside() {
+    let rom = Cartridge::from_bytes("scene.gb", &build_scene_rom()).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.run_frames(3);
+    let emulator = emu.emulator_mut();
+    assert_eq!(emulator.palette_register(PaletteRegister::Background), 0xE4);
+    assert_eq!(emulator.palette_register(PaletteRegister::Object0), 0xE4);
+
+    let green = Palette::preset("green").unwrap();
+    emulator.set_palette(green);
+    assert_eq!(emulator.palette(), green);
+
+    // Every color index to the lightest shade, the scene disappears
+    for register in PaletteRegister::ALL {
+        emulator.set_palette_register(register, 0x00);
+    }
+    emu.run_frames(2);
+
+    let pixels = emu.emulator().ppu().frame().as_argb8888();
+    assert!(pixels.iter().all(|pixel| *pixel == green.background[0]));
+}
@@ -0,0 +1,44 @@
+use dmg_core::rewind::RewindBuffer;
+
+fn state(frame: u8) -> Vec<u8> {
+    let mut state = vec![0; 8192];
+    state[100] = frame;
+    state[4000..4004].copy_from_slice(&(frame as u32 * 3).to_le_bytes());
+    state
+}
+
+#[test]
+fn states_come_back_newest_first() {
+    let mut rewind = RewindBuffer::new(1 << 20);
+
+    for frame in 0..50 {
+        rewind.push(state(frame));
+    }
+
+    assert_eq!(rewind.len(), 50);
+    // Deltas of states this similar are a few bytes each
+    assert!(rewind.memory_used() < 8192 + 50 * 64);
+
+    for frame in (0..50).rev() {
+        assert_eq!(rewind.pop(), Some(state(frame)));
+    }
+    assert_eq!(rewind.pop(), None);
+}
+
+#[test]
+fn oldest_states_are_dropped_over_budget() {
+    let mut rewind = RewindBuffer::new(8192 + 200);
+
+    for frame in 0..100 {
+        rewind.push(state(frame));
+    }
+
+    assert!(rewind.memory_used() <= 8192 + 200);
+    assert!(rewind.len() > 1 && rewind.len() < 100);
+    assert_eq!(rewind.pop(), Some(state(99)));
+    assert_eq!(rewind.pop(), Some(state(98)));
+
+    // A state of another size starts over
+    rewind.push(vec![1; 16]);
+    assert_eq!(rewind.len(), 1);
+}
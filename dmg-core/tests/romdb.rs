@@ -0,0 +1,63 @@
+use dmg_core::hash::{crc32, fnv1a64, sha1};
+use dmg_core::romdb::{RomDatabase, RomHashes, RomIdentity};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[test]
+fn hashes_match_reference_values() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(fnv1a64(b"a"), 0xAF63_DC4C_8601_EC8C);
+    assert_eq!(
+        hex(&sha1(b"abc")),
+        "a9993e364706816aba3e25717850c26c9cd0d89d"
+    );
+    assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    // Padding spills into a second block
+    assert_eq!(
+        hex(&sha1(&[b'a'; 56])),
+        "c2db330f6083854c99d4b5bfb6e8f29f201be699"
+    );
+}
+
+#[test]
+fn dat_lookup_identifies_dumps() {
+    let rom: Vec<u8> = (0..0x8000u32).map(|i| (i * 7) as u8).collect();
+    let hashes = RomHashes::new(&rom);
+    let dat = format!(
+        r#"<?xml version="1.0"?>
+<datafile>
+    <game name="Test &amp; Verify (World)">
+        <description>Test</description>
+        <rom name="test.gb" size="32768" crc="{:08x}" sha1="{}"/>
+    </game>
+    <game name="Broken (USA)">
+        <rom name="broken.gb" size="4" crc="{:08x}" status="baddump"/>
+    </game>
+</datafile>"#,
+        hashes.crc32,
+        hex(&hashes.sha1),
+        crc32(b"oops"),
+    );
+    let database = RomDatabase::parse_dat(&dat);
+    assert_eq!(database.len(), 2);
+
+    let RomIdentity::Verified(entry) = database.identify(&rom, 0x8000) else {
+        panic!("ROM not verified");
+    };
+    assert_eq!(entry.name, "Test & Verify (World)");
+
+    let mut overdump = rom.clone();
+    overdump.extend_from_slice(&[0xFF; 0x8000]);
+    assert!(matches!(
+        database.identify(&overdump, 0x8000),
+        RomIdentity::Overdump(_)
+    ));
+
+    assert!(matches!(
+        database.identify(b"oops", 0),
+        RomIdentity::BadDump(_)
+    ));
+    assert_eq!(database.identify(&rom[1..], 0x8000), RomIdentity::Unknown);
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::{CLOCK_HZ, build_rom};
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::CpuContext;
+use dmg_core::headless::Headless;
+use dmg_core::mbc::RtcClock;
+
+/// MBC3+TIMER+RAM+BATTERY program that keeps latching the RTC and copies
+/// the seconds register to $C000.
+fn build_test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x0A,             // LD A, $0A
+        0xEA, 0x00, 0x00,       // LD ($0000), A    ; enable RAM and RTC
+        0x3E, 0x08,             // LD A, $08
+        0xEA, 0x00, 0x40,       // LD ($4000), A    ; select RTC seconds
+        0xAF,                   // loop: XOR A
+        0xEA, 0x00, 0x60,       // LD ($6000), A
+        0x3C,                   // INC A
+        0xEA, 0x00, 0x60,       // LD ($6000), A    ; latch
+        0xFA, 0x00, 0xA0,       // LD A, ($A000)
+        0xEA, 0x00, 0xC0,       // LD ($C000), A
+        0x18, 0xF0,             // JR loop
+    ];
+
+    build_rom(&[(0x147, &[0x10, 0x00, 0x02]), (0x150, main)])
+}
+
+#[test]
+fn emulated_rtc_follows_cycles() {
+    let mut rom = Cartridge::from_bytes("rtc.gb", &build_test_rom()).unwrap();
+    rom.set_rtc_clock(RtcClock::Emulated);
+    let mut emu = Headless::new(rom);
+
+    // A little past two emulated seconds
+    while emu.ticks() < 2 * CLOCK_HZ + CLOCK_HZ / 10 {
+        assert!(emu.step());
+    }
+
+    assert_eq!(emu.emulator_mut().peek(0xC000), 2);
+}
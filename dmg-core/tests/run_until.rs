@@ -0,0 +1,163 @@
+mod common;
+
+use common::{CLOCK_HZ, build_rom};
+use dmg_core::cart::Cartridge;
+use dmg_core::cpu::CpuContext;
+use dmg_core::headless::{Condition, Headless, RunOutcome};
+
+/// Sends `OK` over the serial port, then counts up at $C000.
+fn build_test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, b'O',         // LD A, 'O'
+        0xE0, 0x01,         // LDH (SB), A
+        0x3E, 0x81,         // LD A, $81
+        0xE0, 0x02,         // LDH (SC), A
+        0xF0, 0x02,         // wait: LDH A, (SC)
+        0xCB, 0x7F,         // BIT 7, A
+        0x20, 0xFA,         // JR NZ, wait
+        0x3E, b'K',         // LD A, 'K'
+        0xE0, 0x01,         // LDH (SB), A
+        0x3E, 0x81,         // LD A, $81
+        0xE0, 0x02,         // LDH (SC), A
+        0xF0, 0x02,         // wait: LDH A, (SC)
+        0xCB, 0x7F,         // BIT 7, A
+        0x20, 0xFA,         // JR NZ, wait
+        0x21, 0x00, 0xC0,   // LD HL, $C000
+        0x36, 0x00,         // LD (HL), 0
+        0x34,               // count: INC (HL)
+        0x18, 0xFD,         // JR count
+    ];
+
+    build_rom(&[(0x150, main)])
+}
+
+/// Plays all channels and counts the VBlank, timer and serial interrupts it
+/// sleeps in HALT between in HRAM, VBlank also starts an OAM DMA.
+fn build_halting_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x31, 0xFE, 0xFF,   // LD SP, $FFFE
+        0x3E, 0x80,         // LD A, $80
+        0xE0, 0x26,         // LDH (NR52), A
+        0x3E, 0xF0,         // LD A, $F0
+        0xE0, 0x12,         // LDH (NR12), A
+        0xE0, 0x21,         // LDH (NR42), A
+        0x3E, 0x80,         // LD A, $80
+        0xE0, 0x11,         // LDH (NR11), A
+        0xE0, 0x1A,         // LDH (NR30), A
+        0x3E, 0x20,         // LD A, $20
+        0xE0, 0x1C,         // LDH (NR32), A
+        0x3E, 0x11,         // LD A, $11
+        0xE0, 0x43,         // LDH (NR43), A
+        0x3E, 0x87,         // LD A, $87
+        0xE0, 0x14,         // LDH (NR14), A
+        0xE0, 0x1E,         // LDH (NR34), A
+        0xE0, 0x23,         // LDH (NR44), A
+        0x3E, 0x04,         // LD A, $04
+        0xE0, 0x07,         // LDH (TAC), A
+        0x3E, 0x0D,         // LD A, $0D       ; VBlank, timer and serial
+        0xE0, 0xFF,         // LDH (IE), A
+        0x3E, 0x81,         // LD A, $81
+        0xE0, 0x02,         // LDH (SC), A
+        0xFB,               // EI
+        0x76,               // loop: HALT
+        0x18, 0xFD,         // JR loop
+    ];
+    #[rustfmt::skip]
+    let vblank: &[u8] = &[
+        0xF5,               // PUSH AF
+        0x3E, 0x80,         // LD A, $80
+        0xE0, 0x46,         // LDH (DMA), A
+        0xF0, 0x80,         // LDH A, ($80)
+        0x3C,               // INC A
+        0xE0, 0x80,         // LDH ($80), A
+        0xF1,               // POP AF
+        0xD9,               // RETI
+    ];
+    #[rustfmt::skip]
+    let timer: &[u8] = &[
+        0xF5,               // PUSH AF
+        0xF0, 0x81,         // LDH A, ($81)
+        0x3C,               // INC A
+        0xE0, 0x81,         // LDH ($81), A
+        0xF1,               // POP AF
+        0xD9,               // RETI
+    ];
+    #[rustfmt::skip]
+    let serial: &[u8] = &[
+        0xF5,               // PUSH AF
+        0x3E, 0x81,         // LD A, $81
+        0xE0, 0x02,         // LDH (SC), A
+        0xF0, 0x82,         // LDH A, ($82)
+        0x3C,               // INC A
+        0xE0, 0x82,         // LDH ($82), A
+        0xF1,               // POP AF
+        0xD9,               // RETI
+    ];
+
+    build_rom(&[(0x40, vblank), (0x50, timer), (0x58, serial), (0x150, main)])
+}
+
+fn emulator() -> Headless {
+    Headless::new(Cartridge::from_bytes("run_until.gb", &build_test_rom()).unwrap())
+}
+
+#[test]
+fn runs_until_each_condition() {
+    let mut emu = emulator();
+
+    let outcome = emu.run_until(&Condition::SerialMatch("OK".into()), CLOCK_HZ);
+    assert_eq!(outcome, RunOutcome::Met);
+    assert_eq!(emu.serial_output(), "OK");
+
+    let outcome = emu.run_until(&Condition::MemoryEquals(0xC000, 5), CLOCK_HZ);
+    assert_eq!(outcome, RunOutcome::Met);
+    assert_eq!(emu.emulator_mut().peek(0xC000), 5);
+
+    let outcome = emu.run_until(&Condition::PcEquals(0x0171), CLOCK_HZ);
+    assert_eq!(outcome, RunOutcome::Met);
+    assert_eq!(emu.cpu().registers().pc, 0x0171);
+
+    let frame = emu.emulator().get_current_frame();
+    let outcome = emu.run_until(&Condition::FrameCount(3), 10 * CLOCK_HZ);
+    assert_eq!(outcome, RunOutcome::Met);
+    assert_eq!(emu.emulator().get_current_frame(), frame + 3);
+}
+
+#[test]
+fn halted_batches_match_stepping() {
+    let rom = build_halting_rom();
+    let mut batched = Headless::new(Cartridge::from_bytes("halt.gb", &rom).unwrap());
+    let mut stepped = Headless::new(Cartridge::from_bytes("halt.gb", &rom).unwrap());
+    let end = 10 * 70224;
+
+    assert!(batched.cpu_mut().run_until(end));
+    while stepped.ticks() < end {
+        assert!(stepped.cpu_mut().step());
+    }
+
+    assert_eq!(batched.ticks(), stepped.ticks());
+    assert!(
+        batched.save_state() == stepped.save_state(),
+        "states differ"
+    );
+
+    let emu = batched.emulator_mut();
+    assert_ne!(emu.peek(0xFF80), 0);
+    assert_ne!(emu.peek(0xFF81), 0);
+    assert_ne!(emu.peek(0xFF82), 0);
+}
+
+#[test]
+fn runs_stop_at_the_tick_budget() {
+    let mut emu = emulator();
+
+    let outcome = emu.run_until(&Condition::PcEquals(0x4000), 1000);
+    assert_eq!(outcome, RunOutcome::TimedOut);
+    assert!(emu.ticks() < 1100);
+
+    let outcome = emu.run_until(&Condition::FrameCount(100), CLOCK_HZ);
+    assert_eq!(outcome, RunOutcome::TimedOut);
+    assert!((CLOCK_HZ..CLOCK_HZ + 100).contains(&emu.ticks()));
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::headless::Headless;
+use dmg_core::state::{self, EMULATOR_CHUNK, PPU_CHUNK, StateError, StateReader, StateWriter};
+
+fn counting_emulator() -> Headless {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        0x34,             // loop: INC (HL)
+        0x18, 0xFD,       // JR loop
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("state.gb", &rom).unwrap());
+    emu.run_frames(2);
+    emu
+}
+
+/// Top level chunks of a machine state, the emulator chunk passed through `edit`.
+fn rewrite(machine: &[u8], edit: impl Fn(&[u8], &mut StateWriter)) -> Vec<u8> {
+    let mut reader = StateReader::new(machine);
+    let mut writer = StateWriter::new();
+
+    while !reader.is_empty() {
+        let (tag, mut chunk) = reader.read_chunk().unwrap();
+        let data = chunk.read_rest();
+
+        writer.write_chunk(tag, |state| match tag {
+            EMULATOR_CHUNK => edit(data, state),
+            _ => state.write_bytes(data),
+        });
+    }
+
+    writer.into_bytes()
+}
+
+#[test]
+fn unknown_chunks_are_skipped() {
+    let mut emu = counting_emulator();
+    let machine = state::capture_machine(emu.cpu());
+
+    // What a newer build could add, at both levels
+    let mut newer = rewrite(&machine, |data, state| {
+        state.write_bytes(data);
+        state.write_chunk(*b"NEW1", |state| state.write_u64(0x1234));
+    });
+    let mut extra = StateWriter::new();
+    extra.write_chunk(*b"NEW2", |state| state.write_bytes(&[1, 2, 3]));
+    newer.extend(extra.into_bytes());
+
+    emu.run_frames(1);
+    state::restore_machine(emu.cpu_mut(), &newer).unwrap();
+    assert_eq!(state::capture_machine(emu.cpu()), machine);
+}
+
+#[test]
+fn line_past_the_frame_fails_to_load() {
+    let mut emu = counting_emulator();
+    let machine = state::capture_machine(emu.cpu());
+
+    // LY follows OAM, VRAM and four LCD registers in the PPU chunk
+    let corrupt = rewrite(&machine, |data, state| {
+        let mut emulator = StateReader::new(data);
+        while !emulator.is_empty() {
+            let (tag, mut chunk) = emulator.read_chunk().unwrap();
+            let mut data = chunk.read_rest().to_vec();
+            if tag == PPU_CHUNK {
+                data[0xA0 + 0x2000 + 4] = 255;
+            }
+            state.write_chunk(tag, |state| state.write_bytes(&data));
+        }
+    });
+
+    assert!(matches!(
+        state::restore_machine(emu.cpu_mut(), &corrupt),
+        Err(StateError::InvalidValue("LY"))
+    ));
+}
+
+#[test]
+fn truncated_chunks_fail_to_load() {
+    let mut emu = counting_emulator();
+    let machine = state::capture_machine(emu.cpu());
+
+    let truncated = &machine[..machine.len() - 1];
+    assert!(state::restore_machine(emu.cpu_mut(), truncated).is_err());
+
+    let data = state::save_machine(emu.cpu());
+    state::load_machine(emu.cpu_mut(), &data).unwrap();
+    assert_eq!(state::capture_machine(emu.cpu()), machine);
+}
@@ -0,0 +1,8 @@
+use dmg_core::selftest::run_selftest;
+
+#[test]
+fn selftest_passes() {
+    for check in run_selftest() {
+        assert!(check.passed, "{}: {}", check.name, check.detail);
+    }
+}
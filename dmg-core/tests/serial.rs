@@ -0,0 +1,196 @@
+mod common;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::clock::EmuClock;
+use dmg_core::cpu::CpuContext;
+use dmg_core::headless::Headless;
+use dmg_core::peripherals::{PeripheralError, PeripheralRegistry};
+use dmg_core::serial::{
+    Loopback, Peripheral, SerialCapture, SerialDevice, SerialExchange, SerialReplay,
+};
+
+/// Send $42 starting the transfer with `sc` and copy the received byte to
+/// $C000.
+fn build_test_rom(sc: u8) -> Vec<u8> {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x42,         // LD A, $42
+        0xE0, 0x01,         // LDH (SB), A
+        0x3E, sc,           // LD A, sc
+        0xE0, 0x02,         // LDH (SC), A
+        0xF0, 0x02,         // wait: LDH A, (SC)
+        0xCB, 0x7F,         // BIT 7, A
+        0x20, 0xFA,         // JR NZ, wait
+        0xF0, 0x01,         // LDH A, (SB)
+        0xEA, 0x00, 0xC0,   // LD ($C000), A
+        0x18, 0xFE,         // JR -2
+    ];
+
+    build_rom(&[(0x150, main)])
+}
+
+fn received(device: Option<Box<dyn SerialDevice>>) -> u8 {
+    received_with(0x81, device)
+}
+
+/// The byte received with the transfer started by writing `sc`.
+fn received_with(sc: u8, device: Option<Box<dyn SerialDevice>>) -> u8 {
+    let rom = Cartridge::from_bytes("serial.gb", &build_test_rom(sc)).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.emulator_mut().set_serial_device(device);
+
+    emu.run_frames(2);
+    assert_eq!(emu.serial_output(), "B");
+    emu.emulator_mut().peek(0xC000)
+}
+
+#[test]
+fn unconnected_port_receives_ones() {
+    assert_eq!(received(None), 0xFF);
+}
+
+#[test]
+fn loopback_echoes_sent_byte() {
+    assert_eq!(received(Some(Box::new(Loopback))), 0x42);
+}
+
+#[test]
+fn replay_answers_with_captured_bytes() {
+    let exchanges = SerialExchange::parse_capture("1000 42 99\n").unwrap();
+    assert_eq!(
+        exchanges,
+        [SerialExchange {
+            ticks: 1000,
+            sent: 0x42,
+            received: 0x99
+        }]
+    );
+    assert!(SerialExchange::parse_capture("1000 42").is_none());
+    assert_eq!(
+        SerialExchange::parse_capture("1000 42 99 # f0 002:088\n").unwrap(),
+        exchanges
+    );
+
+    assert_eq!(received(Some(Box::new(SerialReplay::new(exchanges)))), 0x99);
+}
+
+/// Answers every byte with its complement.
+struct Inverter;
+
+impl Peripheral for Inverter {
+    fn on_byte(&mut self, sent: u8) -> u8 {
+        !sent
+    }
+}
+
+/// Capture file the test can read back.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn captured_exchanges_replay_with_either_clock() {
+    let buffer = SharedBuffer::default();
+    let capture = SerialCapture::new(buffer.clone(), Some(Box::new(Inverter)));
+    assert_eq!(received(Some(Box::new(capture))), 0xBD);
+
+    let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let exchanges = SerialExchange::parse_capture(&text).unwrap();
+    assert_eq!(exchanges.len(), 1);
+    assert_eq!((exchanges[0].sent, exchanges[0].received), (0x42, 0xBD));
+
+    let replay = |sc| received_with(sc, Some(Box::new(SerialReplay::new(exchanges.clone()))));
+    assert_eq!(replay(0x81), 0xBD);
+    // The replay clocks the transfer the Game Boy waits on
+    assert_eq!(replay(0x80), 0xBD);
+}
+
+#[test]
+fn replay_reports_drift_from_the_capture() {
+    let exchanges = SerialExchange::parse_capture("1000 42 99\n2000 43 98\n").unwrap();
+    let mut replay = SerialReplay::new(exchanges);
+
+    assert_eq!(replay.exchange(0x42, EmuClock::from_ticks(990)), 0x99);
+    assert_eq!(replay.drift(), -10);
+    assert!(!replay.is_finished());
+
+    // Not yet at the captured time
+    assert_eq!(
+        replay.external_exchange(0x43, EmuClock::from_ticks(1990)),
+        None
+    );
+    assert_eq!(
+        replay.external_exchange(0x43, EmuClock::from_ticks(2000)),
+        Some(0x98)
+    );
+    assert_eq!(replay.drift(), 0);
+    assert!(replay.is_finished());
+    // Cable pulled
+    assert_eq!(replay.exchange(0x44, EmuClock::from_ticks(3000)), 0xFF);
+}
+
+#[test]
+fn registered_peripherals_are_created_by_name() {
+    let mut registry = PeripheralRegistry::builtin();
+    registry.register("inverter", "inverter", "complements bytes", |_| {
+        Ok(Box::new(Inverter))
+    });
+
+    assert_eq!(received(Some(registry.create("inverter").unwrap())), 0xBD);
+    assert_eq!(received(Some(registry.create("loopback").unwrap())), 0x42);
+    assert!(matches!(
+        registry.create("barcode-boy"),
+        Err(PeripheralError::Unknown(_))
+    ));
+    assert!(matches!(
+        registry.create("replay:missing.txt"),
+        Err(PeripheralError::Invalid { .. })
+    ));
+}
+
+/// Clocks an external transfer on the `polls`th poll, answering with $5A.
+struct LinkPartner {
+    polls: u32,
+}
+
+impl SerialDevice for LinkPartner {
+    fn exchange(&mut self, _sent: u8, _clock: EmuClock) -> u8 {
+        0xFF
+    }
+
+    fn external_exchange(&mut self, sent: u8, _clock: EmuClock) -> Option<u8> {
+        assert_eq!(sent, 0x42);
+        self.polls -= 1;
+        (self.polls == 0).then_some(0x5A)
+    }
+}
+
+#[test]
+fn external_clock_waits_for_the_partner() {
+    let rom = Cartridge::from_bytes("serial.gb", &build_test_rom(0x80)).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.run_frames(2);
+    assert_eq!(emu.serial_output(), "");
+    assert_eq!(emu.emulator_mut().peek(0xFF02) & 0x80, 0x80);
+
+    let rom = Cartridge::from_bytes("serial.gb", &build_test_rom(0x80)).unwrap();
+    let mut emu = Headless::new(rom);
+    emu.emulator_mut()
+        .set_serial_device(Some(Box::new(LinkPartner { polls: 100 })));
+    emu.run_frames(2);
+    assert_eq!(emu.serial_output(), "B");
+    assert_eq!(emu.emulator_mut().peek(0xC000), 0x5A);
+}
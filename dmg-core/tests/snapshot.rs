@@ -0,0 +1,133 @@
+mod common;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::headless::Headless;
+use dmg_core::snapshot::{Difference, MachineSnapshot, Region};
+use dmg_core::state;
+
+#[test]
+fn states_are_compared_without_the_rom() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x01,       // LD A, 1
+        0xE0, 0xFF,       // LDH (IE), A
+        0xFB,             // EI
+        0x21, 0xA5, 0xC0, // LD HL, $C0A5
+        0x34,             // loop: INC (HL)
+        0x76,             // HALT, until VBlank
+        0x18, 0xFC,       // JR loop
+    ];
+    let rom = build_rom(&[(0x40, &[0xD9]), (0x150, main)]); // RETI
+    let mut emu = Headless::new(Cartridge::from_bytes("lives.gb", &rom).unwrap());
+    emu.run_frames(2);
+
+    let before = state::save_machine(emu.cpu());
+    emu.run_frames(1);
+    let after = state::save_machine(emu.cpu());
+
+    let before = MachineSnapshot::from_state(&before).unwrap();
+    let after = MachineSnapshot::from_state(&after).unwrap();
+    assert!(after == MachineSnapshot::capture(emu.cpu()));
+    assert!(before.diff(&before).is_empty());
+
+    let differences = before.diff(&after);
+    let counter = differences
+        .iter()
+        .find(|difference| {
+            matches!(
+                difference,
+                Difference::Memory {
+                    region: Region::Wram,
+                    offset: 0xA5,
+                    ..
+                }
+            )
+        })
+        .unwrap();
+    assert_eq!(
+        *counter,
+        Difference::Memory {
+            region: Region::Wram,
+            offset: 0xA5,
+            before: before.wram[0xA5],
+            after: before.wram[0xA5].wrapping_add(1),
+        }
+    );
+    assert!(counter.to_string().starts_with("$C0A5 WRAM"));
+
+    // Only the savestate files have the BESS section
+    assert!(MachineSnapshot::from_state(&state::capture_machine(emu.cpu())).is_err());
+}
+
+#[test]
+fn json_snapshots_load_into_the_machine() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        0x34,             // loop: INC (HL)
+        0x18, 0xFD,       // JR loop
+    ];
+    let rom = build_rom(&[(0x150, main)]);
+    let cartridge = || Cartridge::from_bytes("json.gb", &rom).unwrap();
+    let mut emu = Headless::new(cartridge());
+    emu.run_frames(3);
+
+    let json = state::save_json(emu.cpu());
+    assert!(json.contains("\"C000\": \""), "{json}");
+
+    let mut other = Headless::new(cartridge());
+    state::load_json(other.cpu_mut(), &json).unwrap();
+    // Only the PPU starts its frame over
+    let differences =
+        MachineSnapshot::capture(other.cpu()).diff(&MachineSnapshot::capture(emu.cpu()));
+    assert!(
+        differences.iter().all(|difference| matches!(
+            difference,
+            Difference::Memory {
+                region: Region::Io,
+                offset: 0x41 | 0x44,
+                ..
+            }
+        )),
+        "{differences:?}"
+    );
+
+    // Fixtures only list what they need
+    let fixture = r#"{
+        "registers": {"pc": "0153", "a": "42", "h": "C0", "l": "00"},
+        "memory": {"C000": "FE 02 03"}
+    }"#;
+    state::load_json(other.cpu_mut(), fixture).unwrap();
+    assert_eq!(other.cpu().registers().a, 0x42);
+    assert_eq!(
+        MachineSnapshot::capture(other.cpu()).wram[..3],
+        [0xFE, 0x02, 0x03]
+    );
+    other.run_frames(1);
+    assert_ne!(MachineSnapshot::capture(other.cpu()).wram[0], 0xFE);
+
+    let before = MachineSnapshot::capture(other.cpu());
+    assert!(state::load_json(other.cpu_mut(), r#"{"memory": {"0100": "00"}}"#).is_err());
+    assert!(state::load_json(other.cpu_mut(), "{").is_err());
+    assert!(
+        state::load_json(
+            other.cpu_mut(),
+            r#"{"memory": {"FFFFFFFFFFFFFFFF": "00 00"}}"#
+        )
+        .is_err()
+    );
+    assert!(state::load_json(other.cpu_mut(), r#"{"cartridge_ram": {"FFFFFFFF": "00"}}"#).is_err());
+    assert!(MachineSnapshot::capture(other.cpu()) == before);
+}
+
+#[test]
+fn states_tell_the_rom_they_belong_to() {
+    let mut rom = build_rom(&[]);
+    rom[0x14E..0x150].copy_from_slice(&[0x12, 0x34]);
+    let emu = Headless::new(Cartridge::from_bytes("state.gb", &rom).unwrap());
+
+    let data = state::save_machine(emu.cpu());
+    assert_eq!(state::rom_checksum(&data), Some(0x1234));
+    assert_eq!(state::rom_checksum(&data[..data.len() - 8]), None);
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use std::time::Duration;
+
+use common::build_rom;
+use dmg_core::cart::Cartridge;
+use dmg_core::emu::DOTS_PER_FRAME;
+use dmg_core::headless::Headless;
+
+use dmg_core::stats::{
+    AvSync, FRAME_DURATION, MAX_SPEED_ADJUST, Pacing, PacingMode, STATS_HISTORY, SpeedReport, Stats,
+};
+
+#[test]
+fn averages_recent_frames() {
+    let mut stats = Stats::new();
+    assert_eq!(stats.fps(), 0.0);
+
+    stats.record_frame(Duration::from_millis(10));
+    stats.record_frame(Duration::from_millis(30));
+    assert_eq!(stats.average_frame_time(), Duration::from_millis(20));
+    assert_eq!(stats.fps(), 50.0);
+
+    for _ in 0..STATS_HISTORY {
+        stats.record_frame(FRAME_DURATION);
+    }
+
+    assert_eq!(stats.frame_times().count(), STATS_HISTORY);
+    assert!((stats.speed_percent() - 100.0).abs() < 0.01);
+}
+
+#[test]
+fn speed_follows_audio_drift() {
+    let mut sync = AvSync::new(48000);
+
+    // A second of video and of audio
+    for _ in 0..60 {
+        sync.record_frame();
+    }
+    sync.record_samples((60.0 * FRAME_DURATION.as_secs_f64() * 48000.0).round() as u64);
+    assert!(sync.drift().abs() < 0.001);
+    assert!((sync.speed_factor() - 1.0).abs() < 0.0001);
+
+    // The audio device lags behind, emulation slows down but not by more than the limit
+    for _ in 0..60 {
+        sync.record_frame();
+    }
+    sync.record_samples(24000);
+    assert!(sync.drift() > 0.4);
+    assert_eq!(sync.speed_factor(), 1.0 - MAX_SPEED_ADJUST);
+
+    sync.record_samples(48000);
+    assert!(sync.drift() < 0.0);
+    assert_eq!(sync.speed_factor(), 1.0 + MAX_SPEED_ADJUST);
+}
+
+#[test]
+fn pacing_trades_speed_for_repeated_frames() {
+    let exact = Pacing {
+        mode: PacingMode::Exact,
+        display_rate: 60.0,
+    };
+    assert_eq!(exact.frame_time(), FRAME_DURATION);
+    assert!((exact.speed_percent() - 100.0).abs() < 0.01);
+    // 60 refreshes for 59.73 frames
+    assert!((exact.repeated_frames() - 0.27).abs() < 0.01);
+    assert!(exact.to_string().contains("repeats a frame every 3.7 s"));
+
+    let synced = Pacing {
+        mode: PacingMode::Refresh,
+        ..exact
+    };
+    assert!(synced.repeated_frames().abs() < 0.001);
+    assert!((synced.speed_percent() - 100.45).abs() < 0.01);
+
+    let slow_display = Pacing {
+        display_rate: 50.0,
+        ..exact
+    };
+    assert!(slow_display.repeated_frames() < -9.0);
+    assert!(slow_display.to_string().contains("skips a frame"));
+}
+
+#[test]
+fn emulated_clock_follows_ticks() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0xAF,       // XOR A
+        0xE0, 0x40, // LDH (LCDC), A
+        0x18, 0xFE, // JR -2
+    ];
+    let rom = Cartridge::from_bytes("lcd_off.gb", &build_rom(&[(0x150, main)])).unwrap();
+    let mut emu = Headless::new(rom);
+
+    while emu.ticks() < 10 * DOTS_PER_FRAME {
+        emu.step();
+    }
+
+    let emulator = emu.emulator();
+    assert_eq!(emulator.frame_count(), 10);
+
+    let expected = FRAME_DURATION * 10;
+    let duration = emulator.emulated_duration();
+    assert!(duration >= expected && duration - expected < Duration::from_micros(10));
+}
+
+#[test]
+fn speed_report_compares_to_real_time() {
+    let report = SpeedReport {
+        frames: 600,
+        elapsed: Duration::from_secs(2),
+        emulated: FRAME_DURATION * 600,
+    };
+
+    assert!((report.fps() - 300.0).abs() < 1e-9);
+    assert!((report.multiple() - 5.02).abs() < 0.01);
+    assert_eq!(
+        report.to_string(),
+        "600 frames in 2.000 s, 300 fps, 5.0x real time"
+    );
+
+    let instant = SpeedReport {
+        elapsed: Duration::ZERO,
+        ..report
+    };
+    assert_eq!(instant.fps(), 0.0);
+    assert_eq!(instant.multiple(), 0.0);
+}
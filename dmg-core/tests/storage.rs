@@ -0,0 +1,46 @@
+use std::fs;
+use std::path::Path;
+
+use dmg_core::storage::{FileStorage, StorageBackend};
+
+#[test]
+fn file_storage_keeps_files_in_its_directory() {
+    let dir = std::env::temp_dir().join(format!("dmg-storage-{}", std::process::id()));
+    let storage = FileStorage::in_dir(&dir);
+    let key = Path::new("games/tetris.sav");
+
+    assert_eq!(storage.load(key).unwrap(), None);
+    storage.store(key, &[1, 2, 3]).unwrap();
+    assert_eq!(storage.load(key).unwrap(), Some(vec![1, 2, 3]));
+    assert_eq!(fs::read(dir.join("games/tetris.sav")).unwrap(), [1, 2, 3]);
+
+    storage.remove(key).unwrap();
+    assert_eq!(storage.load(key).unwrap(), None);
+    // Nothing left to remove
+    storage.remove(key).unwrap();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn file_storage_keeps_games_of_the_same_name_apart() {
+    let dir = std::env::temp_dir().join(format!("dmg-storage-apart-{}", std::process::id()));
+    let storage = FileStorage::in_dir(&dir);
+
+    storage.store(Path::new("a/tetris.sav"), &[1]).unwrap();
+    storage.store(Path::new("/b/tetris.sav"), &[2]).unwrap();
+    assert_eq!(
+        storage.load(Path::new("a/tetris.sav")).unwrap(),
+        Some(vec![1])
+    );
+    assert_eq!(
+        storage.load(Path::new("/b/tetris.sav")).unwrap(),
+        Some(vec![2])
+    );
+    assert_eq!(
+        storage.path(Path::new("../c/tetris.sav")),
+        dir.join("c/tetris.sav")
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
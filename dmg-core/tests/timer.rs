@@ -0,0 +1,149 @@
+mod common;
+
+use common::{Requests, build_rom};
+use dmg_core::bus::HardwareRegister;
+use dmg_core::cart::Cartridge;
+use dmg_core::counter::SystemCounter;
+use dmg_core::headless::Headless;
+use dmg_core::interrupts::InterruptFlag;
+use dmg_core::power::Quirks;
+use dmg_core::scheduler::{Event, Scheduler};
+use dmg_core::timer::Timer;
+
+/// Advance the system counter by `dots`, counting TIMA when the emulator's
+/// scheduler would.
+fn run_timer(timer: &mut Timer, counter: &mut SystemCounter, requests: &mut Requests, dots: u64) {
+    for _ in 0..dots {
+        let due = timer.next_increment(counter.value()) == Some(1);
+        counter.tick();
+
+        if due {
+            timer.increment_tima(requests);
+        }
+    }
+}
+
+#[test]
+fn scheduler_pops_in_due_order() {
+    let mut scheduler = Scheduler::new();
+    scheduler.schedule(30, Event::DivApuBit);
+    scheduler.schedule(10, Event::TimaIncrement);
+    assert_eq!(scheduler.next_due(), 10);
+
+    // Scheduling again replaces the earlier time
+    scheduler.schedule(40, Event::TimaIncrement);
+    assert_eq!(scheduler.pop_due(29), None);
+    assert_eq!(scheduler.pop_due(30), Some(Event::DivApuBit));
+    assert_eq!(scheduler.pop_due(45), Some(Event::TimaIncrement));
+    assert_eq!(scheduler.pop_due(45), None);
+    assert_eq!(scheduler.next_due(), u64::MAX);
+
+    // At the same tick the PPU goes before DMA, whichever was scheduled first
+    scheduler.schedule(50, Event::DmaCycle);
+    scheduler.schedule(50, Event::PpuUpdate);
+    assert_eq!(scheduler.pop_due(50), Some(Event::PpuUpdate));
+    assert_eq!(scheduler.pop_due(50), Some(Event::DmaCycle));
+}
+
+#[test]
+fn timer_overflows_at_the_selected_rate() {
+    #[rustfmt::skip]
+    let main: &[u8] = &[
+        0x3E, 0x04,       // LD A, $04
+        0xE0, 0xFF,       // LDH (IE), A, timer only
+        0xAF,             // XOR A
+        0xE0, 0x06,       // LDH (TMA), A
+        0x3E, 0x05,       // LD A, $05
+        0xE0, 0x07,       // LDH (TAC), A, every 16 T-cycles
+        0xFB,             // EI
+        0x18, 0xFE,       // loop: JR loop
+    ];
+    let timer: &[u8] = &[0xD9]; // RETI
+    let rom = build_rom(&[(0x50, timer), (0x150, main)]);
+    let mut emu = Headless::new(Cartridge::from_bytes("timer.gb", &rom).unwrap());
+
+    assert!(emu.run_frames(10));
+
+    // 256 increments of 16 T-cycles each per overflow, 171 in 10 frames
+    let timer = emu
+        .emulator()
+        .interrupt_stats()
+        .source(InterruptFlag::TIMER);
+    assert!((170..=172).contains(&timer.requested), "{timer:?}");
+    assert!(timer.serviced + 1 >= timer.requested);
+}
+
+#[test]
+fn resetting_div_counts_a_falling_edge() {
+    let tima_after_reset = |quirks: Quirks| {
+        let mut timer = Timer::new();
+        timer.set_quirks(quirks);
+        let mut counter = SystemCounter::new();
+        let mut requests = Requests::default();
+        timer.write(HardwareRegister::TAC as u16, 0x05, 0, &mut requests);
+        // DIV[3] is set, dropping it to 0 is an edge
+        counter.set(0x0008);
+        timer.counter_cleared(counter.clear(), &mut requests);
+        assert_eq!(counter.value(), 0);
+        timer.tima
+    };
+
+    assert_eq!(tima_after_reset(Quirks::TIMER_WRITE_EDGE), 1);
+    assert_eq!(tima_after_reset(Quirks::empty()), 0);
+}
+
+#[test]
+fn tima_counts_falling_edges_of_the_selected_div_bit() {
+    let mut timer = Timer::new();
+    let mut counter = SystemCounter::new();
+    let mut requests = Requests::default();
+    // DIV[9], every 1024 dots
+    timer.write(HardwareRegister::TAC as u16, 0x04, 0, &mut requests);
+    counter.set(0x0005);
+
+    run_timer(&mut timer, &mut counter, &mut requests, 1018);
+    assert_eq!(timer.tima, 0);
+    run_timer(&mut timer, &mut counter, &mut requests, 1);
+    assert_eq!(timer.tima, 1);
+    run_timer(&mut timer, &mut counter, &mut requests, 1023);
+    assert_eq!(timer.tima, 1);
+    run_timer(&mut timer, &mut counter, &mut requests, 1);
+    assert_eq!(timer.tima, 2);
+
+    // Stopped, DIV keeps counting
+    let value = counter.value();
+    timer.write(HardwareRegister::TAC as u16, 0x00, value, &mut requests);
+    run_timer(&mut timer, &mut counter, &mut requests, 4096);
+    assert_eq!(timer.tima, 2);
+    assert_eq!(counter.div(), 0x18);
+    assert!(requests.0.is_empty());
+}
+
+#[test]
+fn tima_overflows_into_tma_on_the_exact_dot() {
+    let mut timer = Timer::new();
+    let mut counter = SystemCounter::new();
+    let mut requests = Requests::default();
+    // DIV[3], every 16 dots
+    timer.write(HardwareRegister::TAC as u16, 0x05, 0, &mut requests);
+    timer.write(HardwareRegister::TIMA as u16, 0xFE, 0, &mut requests);
+    timer.write(HardwareRegister::TMA as u16, 0xAB, 0, &mut requests);
+
+    run_timer(&mut timer, &mut counter, &mut requests, 16);
+    assert_eq!(timer.tima, 0xFF);
+    assert!(requests.0.is_empty());
+
+    run_timer(&mut timer, &mut counter, &mut requests, 15);
+    assert_eq!(timer.tima, 0xFF);
+    assert!(requests.0.is_empty());
+
+    run_timer(&mut timer, &mut counter, &mut requests, 1);
+    assert_eq!(timer.tima, 0xAB);
+    assert_eq!(requests.0, [InterruptFlag::TIMER]);
+
+    // 85 more increments to the next overflow
+    run_timer(&mut timer, &mut counter, &mut requests, 85 * 16 - 1);
+    assert_eq!(timer.tima, 0xFF);
+    run_timer(&mut timer, &mut counter, &mut requests, 1);
+    assert_eq!(requests.0, [InterruptFlag::TIMER; 2]);
+}
@@ -0,0 +1,1041 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use dmg_core::cart::{Cartridge, CartridgeHeader};
+use dmg_core::cpu::{OpcodeCoverage, TRACE_MAGIC, TraceRecord};
+use dmg_core::desync::ChecksumStream;
+use dmg_core::emu::DOTS_PER_FRAME;
+use dmg_core::frame::Frame;
+use dmg_core::headless::Headless;
+use dmg_core::lockstep::{self, DOCTOR_FORMAT, TraceFormat};
+use dmg_core::monkey::MonkeyInput;
+use dmg_core::png;
+use dmg_core::romdb::RomHashes;
+use dmg_core::selftest::run_selftest;
+use dmg_core::snapshot::{Difference, MachineSnapshot};
+use dmg_core::stats::{FRAME_DURATION, SpeedReport};
+
+use crate::config::{describe_identity, load_rom_database};
+use crate::remote::RemoteViewer;
+
+/// How a command prints its result, `--format text|json`. JSON is one
+/// document on stdout for other tools, progress still goes to stderr.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    fn parse(name: Option<&String>) -> Result<Self, Box<dyn Error>> {
+        match name.map(String::as_str) {
+            Some("text") => Ok(OutputFormat::Text),
+            Some("json") => Ok(OutputFormat::Json),
+            _ => Err("--format is text or json".into()),
+        }
+    }
+}
+
+/// A JSON object of already encoded values, on one line.
+fn json_object(members: &[(&str, String)]) -> String {
+    let members: Vec<String> = members
+        .iter()
+        .map(|(name, value)| format!("{}: {value}", json_string(name)))
+        .collect();
+    format!("{{{}}}", members.join(", "))
+}
+
+fn json_array(values: &[String]) -> String {
+    format!("[{}]", values.join(", "))
+}
+
+fn json_option(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "null".to_string())
+}
+
+/// `dmgemu selftest`: run the built-in test ROM, a check that the build
+/// works on this machine before reporting a bug.
+pub fn selftest(_args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let checks = run_selftest();
+
+    println!("dmgemu {} self-test", env!("CARGO_PKG_VERSION"));
+    for check in &checks {
+        let result = if check.passed { "ok" } else { "FAILED" };
+        println!("{:<16} {result:<6} {}", check.name, check.detail);
+    }
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        eprintln!("{failed} of {} checks failed", checks.len());
+        return Ok(1);
+    }
+
+    println!("All {} checks passed", checks.len());
+    Ok(0)
+}
+
+/// `dmgemu info <rom> [--dat FILE] [--format F]`: print the header, its
+/// problems and the database match without starting the emulator.
+pub fn info(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let mut rom_file = None;
+    let mut dat = None;
+    let mut format = OutputFormat::Text;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dat" => dat = Some(args.next().ok_or("--dat needs a file")?),
+            "--format" => format = OutputFormat::parse(args.next())?,
+            _ => rom_file = Some(arg),
+        }
+    }
+
+    let rom_file =
+        rom_file.ok_or("Usage: dmgemu info <rom file> [--dat FILE] [--format text|json]")?;
+    let data = fs::read(rom_file)?;
+    let rom = Cartridge::from_bytes(rom_file, &data)?;
+
+    let hashes = RomHashes::new(&data);
+    let sha1: String = hashes.sha1.iter().map(|b| format!("{b:02x}")).collect();
+
+    let database = load_rom_database(dat.map(Path::new))?;
+    let (verified, mut warnings) = if database.is_empty() {
+        (None, Vec::new())
+    } else {
+        let identity = database.identify(&data, rom.header.rom_size() as usize);
+        let (name, warning) = describe_identity(&identity);
+        (Some(name), warning.into_iter().collect())
+    };
+
+    let issues = rom.header.validate();
+    warnings.extend(issues.iter().map(ToString::to_string));
+
+    match format {
+        OutputFormat::Text => {
+            println!("{rom}");
+            println!("\t CRC32    : {:08x}", hashes.crc32);
+            println!("\t SHA-1    : {sha1}");
+
+            if let Some(name) = &verified {
+                println!(
+                    "\t Verified : {}",
+                    name.as_deref().unwrap_or("not in database")
+                );
+            }
+
+            for warning in &warnings {
+                println!("Warning: {warning}");
+            }
+        }
+        OutputFormat::Json => {
+            let header = &rom.header;
+            let (type_code, type_name) = header.rom_type();
+            let warnings: Vec<String> = warnings.iter().map(|w| json_string(w)).collect();
+            // Without a database "verified" is left out, null means not in it
+            let mut members = vec![
+                ("file", json_string(rom_file)),
+                ("title", json_string(header.title())),
+                ("type", type_code.to_string()),
+                ("type_name", json_string(type_name)),
+                ("rom_size", header.rom_size().to_string()),
+                ("ram_size", header.ram_size().to_string()),
+                ("licensee", json_string(header.licensee())),
+                ("version", header.rom_version().to_string()),
+                ("crc32", json_string(&format!("{:08x}", hashes.crc32))),
+                ("sha1", json_string(&sha1)),
+            ];
+            if let Some(name) = &verified {
+                members.push(("verified", json_option(name.as_deref().map(json_string))));
+            }
+            members.push(("warnings", json_array(&warnings)));
+            println!("{}", json_object(&members));
+        }
+    }
+
+    Ok(if issues.is_empty() { 0 } else { 2 })
+}
+
+/// `dmgemu bench <rom> [--frames N] [--history FILE] [--format F]`: run a
+/// ROM headless as fast as possible and report how much faster than the
+/// real hardware it ran.
+pub fn bench(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let usage = "Usage: dmgemu bench <rom file> [--frames N] [--history FILE] [--format text|json]";
+    let mut rom_file = None;
+    let mut frames = 3600;
+    let mut history = None;
+    let mut format = OutputFormat::Text;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = args.next().ok_or(usage)?.parse()?,
+            "--history" => history = Some(PathBuf::from(args.next().ok_or(usage)?)),
+            "--format" => format = OutputFormat::parse(args.next())?,
+            _ => rom_file = Some(arg),
+        }
+    }
+
+    let rom_file = rom_file.ok_or(usage)?;
+    let mut emu = Headless::from_file(rom_file)?;
+    let start = Instant::now();
+    let ran = emu.run_frames(frames);
+
+    let report = SpeedReport {
+        frames,
+        elapsed: start.elapsed(),
+        emulated: emu.emulator().emulated_duration(),
+    };
+    let earlier = match &history {
+        Some(path) => record_speed(path, rom_file, &report)?,
+        None => None,
+    };
+
+    match format {
+        OutputFormat::Text => {
+            println!("{report}");
+
+            if let Some(earlier) = &earlier {
+                println!(
+                    "Previous {:.1}x ({:+.1}%), best {:.1}x over {} runs",
+                    earlier.previous,
+                    (report.multiple() / earlier.previous - 1.0) * 100.0,
+                    earlier.best,
+                    earlier.runs
+                );
+            }
+        }
+        OutputFormat::Json => {
+            let mut members = vec![
+                ("rom", json_string(rom_file)),
+                ("frames", report.frames.to_string()),
+                ("seconds", format!("{:.3}", report.elapsed.as_secs_f64())),
+                ("fps", format!("{:.1}", report.fps())),
+                ("multiple", format!("{:.3}", report.multiple())),
+                ("completed", ran.to_string()),
+            ];
+            if let Some(earlier) = &earlier {
+                members.push(("previous", format!("{:.3}", earlier.previous)));
+                members.push(("best", format!("{:.3}", earlier.best)));
+                members.push(("runs", earlier.runs.to_string()));
+            }
+            println!("{}", json_object(&members));
+        }
+    }
+
+    Ok(if ran { 0 } else { 1 })
+}
+
+/// Earlier runs of a ROM in a bench history, as multiples of real time.
+struct SpeedHistory {
+    previous: f64,
+    best: f64,
+    runs: usize,
+}
+
+/// Look up the earlier runs of the same ROM and frame count in the history
+/// file, then append `report`: a tab separated line per run with the Unix
+/// time, ROM, frames, FPS and multiple of real time.
+fn record_speed(
+    path: &Path,
+    rom_file: &str,
+    report: &SpeedReport,
+) -> io::Result<Option<SpeedHistory>> {
+    let earlier: Vec<f64> = match fs::read_to_string(path) {
+        Ok(text) => text
+            .lines()
+            .filter_map(|line| {
+                let columns: Vec<&str> = line.split('\t').collect();
+                match columns[..] {
+                    [_, rom, frames, _, multiple]
+                        if rom == rom_file && frames == report.frames.to_string() =>
+                    {
+                        multiple.parse().ok()
+                    }
+                    _ => None,
+                }
+            })
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+
+    let history = earlier.last().map(|&previous| SpeedHistory {
+        previous,
+        best: earlier.iter().copied().fold(f64::MIN, f64::max),
+        runs: earlier.len(),
+    });
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(
+        file,
+        "{time}\t{rom_file}\t{}\t{:.0}\t{:.3}",
+        report.frames,
+        report.fps(),
+        report.multiple()
+    )?;
+
+    Ok(history)
+}
+
+/// `dmgemu compare <rom> --frames N --against <png> [--diff <png>] [--format F]`:
+/// run a ROM headless and count the pixels of the last frame that differ
+/// from a golden screenshot, for visual regression tests of ROMs.
+pub fn compare(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let usage = "Usage: dmgemu compare <rom file> --frames N --against FILE [--diff FILE] \
+                 [--format text|json]";
+    let mut rom_file = None;
+    let mut frames = None;
+    let mut against = None;
+    let mut diff = None;
+    let mut format = OutputFormat::Text;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = Some(args.next().ok_or(usage)?.parse()?),
+            "--against" => against = Some(PathBuf::from(args.next().ok_or(usage)?)),
+            "--diff" => diff = Some(PathBuf::from(args.next().ok_or(usage)?)),
+            "--format" => format = OutputFormat::parse(args.next())?,
+            _ => rom_file = Some(arg),
+        }
+    }
+
+    let (Some(rom_file), Some(frames), Some(against)) = (rom_file, frames, against) else {
+        return Err(usage.into());
+    };
+
+    let (width, height, golden) = png::decode_rgba(&fs::read(&against)?)?;
+    if (width as usize, height as usize) != (Frame::WIDTH, Frame::HEIGHT) {
+        return Err(format!(
+            "{} is {width}x{height}, screenshots are {}x{}",
+            against.display(),
+            Frame::WIDTH,
+            Frame::HEIGHT
+        )
+        .into());
+    }
+
+    let mut emu = Headless::from_file(rom_file)?;
+    let ran = emu.run_frames(frames);
+    let actual = emu.last_frame().as_rgba8888();
+    let (differences, diff_image) = diff_pixels(&actual, &golden);
+
+    if let Some(path) = &diff {
+        fs::write(path, png::encode_rgba(width, height, &diff_image))?;
+    }
+
+    match format {
+        OutputFormat::Text => {
+            if !ran {
+                eprintln!("The CPU stopped before frame {frames}");
+            }
+            println!(
+                "{differences} of {} pixels differ from {}",
+                Frame::WIDTH * Frame::HEIGHT,
+                against.display()
+            );
+        }
+        OutputFormat::Json => {
+            let members = [
+                ("rom", json_string(rom_file)),
+                ("frames", frames.to_string()),
+                ("against", json_string(&against.display().to_string())),
+                ("differences", differences.to_string()),
+                (
+                    "diff",
+                    json_option(diff.map(|path| json_string(&path.display().to_string()))),
+                ),
+                ("completed", ran.to_string()),
+            ];
+            println!("{}", json_object(&members));
+        }
+    }
+
+    Ok(if differences == 0 { 0 } else { 2 })
+}
+
+/// `dmgemu serve <rom> --listen ADDRESS [--frames N]`: run a ROM without a
+/// window at hardware speed, streaming the screen to browsers that also
+/// press the buttons.
+pub fn serve(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let usage = "Usage: dmgemu serve <rom file> --listen ADDRESS [--frames N]";
+    let mut rom_file = None;
+    let mut listen = None;
+    let mut frames = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen = Some(args.next().ok_or(usage)?),
+            "--frames" => frames = Some(args.next().ok_or(usage)?.parse::<u32>()?),
+            _ => rom_file = Some(arg),
+        }
+    }
+
+    let (Some(rom_file), Some(listen)) = (rom_file, listen) else {
+        return Err(usage.into());
+    };
+
+    let mut emu = Headless::from_file(rom_file)?;
+    let viewer = RemoteViewer::bind(listen)?;
+    eprintln!("Serving {rom_file} on http://{listen}/");
+
+    let start = Instant::now();
+    let mut frame = 0;
+
+    while frames.is_none_or(|frames| frame < frames) {
+        emu.emulator_mut().set_buttons(viewer.buttons());
+
+        if !emu.run_frames(1) {
+            eprintln!("The CPU stopped at frame {frame}");
+            return Ok(1);
+        }
+        viewer.publish(emu.last_frame());
+        frame += 1;
+
+        // Paced from the start so that late frames don't add up
+        if let Some(wait) = (FRAME_DURATION * frame).checked_sub(start.elapsed()) {
+            thread::sleep(wait);
+        }
+    }
+
+    Ok(0)
+}
+
+/// Count the pixels of two RGBA images that differ. The image returned marks
+/// them red over a faded copy of `actual`.
+fn diff_pixels(actual: &[u8], expected: &[u8]) -> (usize, Vec<u8>) {
+    let mut differences = 0;
+    let mut image = Vec::with_capacity(actual.len());
+
+    for (pixel, other) in actual.chunks(4).zip(expected.chunks(4)) {
+        if pixel == other {
+            let gray = (pixel[0] as u16 + pixel[1] as u16 + pixel[2] as u16) / 3;
+            let faded = (gray / 4 + 192) as u8;
+            image.extend_from_slice(&[faded, faded, faded, 0xFF]);
+        } else {
+            differences += 1;
+            image.extend_from_slice(&[0xFF, 0x00, 0x00, 0xFF]);
+        }
+    }
+
+    (differences, image)
+}
+
+/// `dmgemu fix-header <rom> [--pad] [--output FILE]`: rewrite the header and
+/// global checksums, with `--pad` pad the ROM to a valid size first. The ROM
+/// is changed in place unless `--output` names another file.
+pub fn fix_header(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let usage = "Usage: dmgemu fix-header <rom file> [--pad] [--output FILE]";
+    let mut rom_file = None;
+    let mut pad = false;
+    let mut output = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pad" => pad = true,
+            "--output" => output = Some(PathBuf::from(args.next().ok_or(usage)?)),
+            _ => rom_file = Some(arg),
+        }
+    }
+
+    let rom_file = rom_file.ok_or(usage)?;
+    let mut data = fs::read(rom_file)?;
+
+    if data.len() < 0x150 {
+        return Err(format!("{rom_file} is too small to have a header").into());
+    }
+
+    let fixes = CartridgeHeader::fix(&mut data, pad);
+    let output = output.unwrap_or_else(|| PathBuf::from(rom_file));
+
+    if fixes.is_empty() && output == Path::new(rom_file) {
+        println!("{rom_file}: header is valid");
+        return Ok(0);
+    }
+
+    for fix in &fixes {
+        println!("{rom_file}: {fix}");
+    }
+
+    fs::write(&output, &data)?;
+    println!("Wrote {}", output.display());
+    Ok(0)
+}
+
+/// `dmgemu desync <log> <log>`: compare the checksum logs of two runs and
+/// report the first frame they disagree on.
+pub fn desync(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let [first, second] = args else {
+        return Err("Usage: dmgemu desync <checksum log> <checksum log>".into());
+    };
+
+    let parse = |path: &String| -> Result<Vec<(u32, u64)>, Box<dyn Error>> {
+        fs::read_to_string(path)?
+            .lines()
+            .map(|line| {
+                ChecksumStream::parse_line(line)
+                    .ok_or_else(|| format!("Invalid checksum line in {path}: {line}").into())
+            })
+            .collect()
+    };
+
+    // Every frame is compared, the logs may have been taken at different intervals
+    let mut stream = ChecksumStream::new(1);
+    let remote = parse(second)?;
+
+    for (frame, checksum) in parse(first)? {
+        if let Err(desync) = stream.push_local(frame, checksum) {
+            println!("{desync}");
+            return Ok(1);
+        }
+    }
+
+    for (frame, checksum) in remote {
+        if let Err(desync) = stream.push_remote(frame, checksum) {
+            println!("{desync}");
+            return Ok(1);
+        }
+    }
+
+    println!("In sync, {} checksums match", stream.matched());
+    Ok(0)
+}
+
+/// `dmgemu statediff <state> <state> [--format F]`: list the registers, IO
+/// registers and memory bytes two savestates disagree on, to find where a
+/// game keeps its lives or level and what went apart in a desync.
+pub fn statediff(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let usage = "Usage: dmgemu statediff <state file> <state file> [--format text|json]";
+    let mut files = Vec::new();
+    let mut format = OutputFormat::Text;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = OutputFormat::parse(args.next())?,
+            _ => files.push(arg),
+        }
+    }
+
+    let [first, second] = files[..] else {
+        return Err(usage.into());
+    };
+
+    let read = |path: &String| -> Result<MachineSnapshot, Box<dyn Error>> {
+        MachineSnapshot::from_state(&fs::read(path)?)
+            .map_err(|e| format!("Can't read {path}: {e}").into())
+    };
+    let differences = read(first)?.diff(&read(second)?);
+
+    if format == OutputFormat::Json {
+        let rows: Vec<String> = differences.iter().map(difference_json).collect();
+        println!("[{}]", rows.join(",\n "));
+    } else if differences.is_empty() {
+        println!("The states are the same");
+    } else {
+        for difference in &differences {
+            println!("{difference}");
+        }
+        println!("{} differences", differences.len());
+    }
+
+    Ok(if differences.is_empty() { 0 } else { 1 })
+}
+
+/// A `statediff` row, memory by region and offset into it.
+fn difference_json(difference: &Difference) -> String {
+    match *difference {
+        Difference::Register {
+            name,
+            before,
+            after,
+        } => json_object(&[
+            ("register", json_string(name)),
+            ("before", before.to_string()),
+            ("after", after.to_string()),
+        ]),
+        Difference::Memory {
+            region,
+            offset,
+            before,
+            after,
+        } => json_object(&[
+            ("region", json_string(region.name())),
+            ("offset", offset.to_string()),
+            ("before", before.to_string()),
+            ("after", after.to_string()),
+        ]),
+    }
+}
+
+/// `dmgemu statejson <state>`: print the registers and memory of a
+/// savestate as JSON for bug reports and test fixtures, `--load-state`
+/// takes it back.
+pub fn statejson(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let [path] = args else {
+        return Err("Usage: dmgemu statejson <state file>".into());
+    };
+
+    let snapshot = MachineSnapshot::from_state(&fs::read(path)?)
+        .map_err(|e| format!("Can't read {path}: {e}"))?;
+    print!("{}", snapshot.to_json());
+    Ok(0)
+}
+
+/// `dmgemu lockstep <rom> <trace> [--format F]`: run a ROM one instruction
+/// per line of another emulator's trace and stop at the first line the
+/// registers or cycles differ on.
+pub fn lockstep(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let usage = "Usage: dmgemu lockstep <rom file> <trace file> [--format doctor|TEMPLATE]";
+    let mut files = Vec::new();
+    let mut template = DOCTOR_FORMAT;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().ok_or(usage)?.as_str() {
+                "doctor" => template = DOCTOR_FORMAT,
+                other => template = other,
+            },
+            _ => files.push(arg),
+        }
+    }
+
+    let [rom_file, trace_file] = files[..] else {
+        return Err(usage.into());
+    };
+    let format = TraceFormat::parse(template)?;
+    let mut emu = Headless::from_file(rom_file)?;
+    // The comparison stops at the first line that can't be read
+    let mut read_error = None;
+    let lines = BufReader::new(File::open(trace_file)?)
+        .lines()
+        .map_while(|line| line.map_err(|e| read_error = Some(e)).ok());
+    let result = lockstep::compare(emu.cpu_mut(), &format, lines);
+
+    if let Some(e) = read_error {
+        return Err(format!("Can't read {trace_file}: {e}").into());
+    }
+
+    match result {
+        Ok(matched) => {
+            println!("In lockstep, {matched} lines match");
+            Ok(0)
+        }
+        Err(mismatch) => {
+            println!("{mismatch}");
+            Ok(1)
+        }
+    }
+}
+
+/// `dmgemu trace <binary trace>`: print a trace written with
+/// `--trace-format binary` as text.
+pub fn trace(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let [path] = args else {
+        return Err("Usage: dmgemu trace <binary trace file>".into());
+    };
+
+    let data = fs::read(path)?;
+    let records = data
+        .strip_prefix(TRACE_MAGIC)
+        .ok_or_else(|| format!("{path} is not a binary trace"))?;
+    let mut out = io::BufWriter::new(io::stdout().lock());
+
+    for chunk in records.chunks(TraceRecord::SIZE) {
+        let record = TraceRecord::decode(chunk).ok_or_else(|| format!("{path} is truncated"))?;
+        writeln!(out, "{record}")?;
+    }
+
+    out.flush()?;
+    Ok(0)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum BatchStatus {
+    /// Ran all frames and drew something
+    Ok,
+    /// Ran all frames but the screen stayed a single color
+    Blank,
+    /// The frame counter stopped advancing, e.g. the LCD was never turned on
+    Hung,
+    /// The CPU stopped
+    Stopped,
+    /// The CPU hit an illegal opcode
+    Fault,
+    Panicked,
+    LoadFailed,
+}
+
+impl BatchStatus {
+    fn name(self) -> &'static str {
+        match self {
+            BatchStatus::Ok => "ok",
+            BatchStatus::Blank => "blank",
+            BatchStatus::Hung => "hung",
+            BatchStatus::Stopped => "stopped",
+            BatchStatus::Fault => "fault",
+            BatchStatus::Panicked => "panicked",
+            BatchStatus::LoadFailed => "load_failed",
+        }
+    }
+}
+
+struct BatchResult {
+    file: String,
+    status: BatchStatus,
+    frames: u32,
+    message: String,
+}
+
+/// Run one ROM headless for `frames` frames, adding the executed opcodes to `coverage`.
+/// Failing runs save their diagnostics to `diagnostics`, see `Headless::save_diagnostics`,
+/// and with a `monkey` seed random buttons are pressed, see `MonkeyInput`.
+fn batch_run(
+    path: &Path,
+    frames: u32,
+    coverage: Option<&mut OpcodeCoverage>,
+    diagnostics: Option<&Path>,
+    monkey: Option<u64>,
+) -> BatchResult {
+    let file = path.display().to_string();
+    let result = |status, frames, message: String| BatchResult {
+        file: file.clone(),
+        status,
+        frames,
+        message,
+    };
+
+    let rom = match fs::read(path)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|data| Cartridge::from_bytes(&file, &data))
+    {
+        Ok(rom) => rom,
+        Err(e) => return result(BatchStatus::LoadFailed, 0, e.to_string()),
+    };
+
+    let mut emu = Headless::new(rom);
+    emu.cpu_mut().set_report_faults(true);
+
+    if coverage.is_some() {
+        emu.cpu_mut().set_coverage(Some(OpcodeCoverage::new()));
+    }
+
+    let mut monkey = monkey.map(MonkeyInput::new);
+
+    // Generous budget before calling a run hung
+    let max_ticks = (frames as u64 + 1) * 2 * DOTS_PER_FRAME;
+    let mut drew_something = false;
+
+    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut frame = emu.emulator().get_current_frame();
+
+        while frame < frames {
+            match emu.try_step() {
+                Ok(true) => (),
+                Ok(false) => return (BatchStatus::Stopped, String::new()),
+                Err(fault) => return (BatchStatus::Fault, fault.to_string()),
+            }
+
+            if emu.ticks() > max_ticks {
+                return (BatchStatus::Hung, String::new());
+            }
+
+            if emu.emulator().get_current_frame() != frame {
+                frame = emu.emulator().get_current_frame();
+                let pixels = emu.emulator().ppu().frame().as_argb8888();
+                drew_something |= pixels.iter().any(|pixel| *pixel != pixels[0]);
+
+                if let Some(monkey) = &mut monkey {
+                    emu.emulator_mut().set_buttons(monkey.next_frame());
+                }
+            }
+        }
+
+        if drew_something {
+            (BatchStatus::Ok, String::new())
+        } else {
+            (BatchStatus::Blank, String::new())
+        }
+    }));
+
+    let frames_run = emu.emulator().get_current_frame();
+
+    if let Some(dir) = diagnostics
+        && !matches!(run, Ok((BatchStatus::Ok, _)))
+    {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+
+        if let Err(e) = emu.save_diagnostics(dir, &name) {
+            eprintln!("Failed to save diagnostics for {file}: {e}");
+        }
+    }
+
+    if let Some(coverage) = coverage
+        && let Some(run_coverage) = emu.cpu().coverage()
+    {
+        coverage.merge(run_coverage);
+    }
+
+    match run {
+        Ok((status, message)) => result(status, frames_run, message),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            result(BatchStatus::Panicked, frames_run, message)
+        }
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+fn batch_report(results: &[BatchResult], json: bool) -> String {
+    if json {
+        let rows: Vec<String> = results
+            .iter()
+            .map(|r| {
+                format!(
+                    "  {{\"file\": {}, \"status\": \"{}\", \"frames\": {}, \"message\": {}}}",
+                    json_string(&r.file),
+                    r.status.name(),
+                    r.frames,
+                    json_string(&r.message)
+                )
+            })
+            .collect();
+
+        format!("[\n{}\n]\n", rows.join(",\n"))
+    } else {
+        let mut csv = String::from("file,status,frames,message\n");
+
+        for r in results {
+            csv.push_str(&format!(
+                "{},{},{},{}\n",
+                csv_field(&r.file),
+                r.status.name(),
+                r.frames,
+                csv_field(&r.message)
+            ));
+        }
+
+        csv
+    }
+}
+
+/// `dmgemu batch-test <dir> [--frames N] [--report FILE] [--coverage FILE]`: run
+/// every ROM in `dir` headless and write a compatibility report. A report file
+/// is JSON when it ends in `.json` and CSV otherwise, without one the report
+/// is printed as `--format` picks. `--monkey SEED` mashes random buttons.
+pub fn batch_test(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let usage = "Usage: dmgemu batch-test <dir> [--frames N] [--report FILE] [--coverage FILE] \
+                 [--diagnostics DIR] [--monkey SEED] [--format text|json]";
+    let mut dir = None;
+    let mut frames = 600;
+    let mut report = None;
+    let mut coverage_file = None;
+    let mut diagnostics = None;
+    let mut monkey = None;
+    let mut format = OutputFormat::Text;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = args.next().ok_or(usage)?.parse()?,
+            "--monkey" => monkey = Some(args.next().ok_or(usage)?.parse()?),
+            "--report" => report = Some(PathBuf::from(args.next().ok_or(usage)?)),
+            "--coverage" => coverage_file = Some(PathBuf::from(args.next().ok_or(usage)?)),
+            "--diagnostics" => diagnostics = Some(PathBuf::from(args.next().ok_or(usage)?)),
+            "--format" => format = OutputFormat::parse(args.next())?,
+            _ => dir = Some(arg),
+        }
+    }
+
+    let mut roms: Vec<PathBuf> = fs::read_dir(dir.ok_or(usage)?)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("gb") || ext.eq_ignore_ascii_case("gbc")
+            })
+        })
+        .collect();
+    roms.sort();
+
+    let mut results = Vec::new();
+    let mut coverage = coverage_file.as_ref().map(|_| OpcodeCoverage::new());
+
+    for path in &roms {
+        let result = batch_run(
+            path,
+            frames,
+            coverage.as_mut(),
+            diagnostics.as_deref(),
+            monkey,
+        );
+        eprintln!("{}: {}", result.file, result.status.name());
+        results.push(result);
+    }
+
+    match &report {
+        Some(path) => {
+            let json = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+            fs::write(path, batch_report(&results, json))?;
+        }
+        None => print!("{}", batch_report(&results, format == OutputFormat::Json)),
+    }
+
+    if let (Some(path), Some(coverage)) = (&coverage_file, &coverage) {
+        save_coverage(path, coverage)?;
+    }
+
+    let passed = results
+        .iter()
+        .filter(|r| r.status == BatchStatus::Ok)
+        .count();
+    eprintln!("{passed}/{} ROMs ran", results.len());
+
+    Ok(if passed == results.len() { 0 } else { 2 })
+}
+
+/// Merge `coverage` into the opcode coverage stored at `path`, if any.
+pub fn save_coverage(path: &Path, coverage: &OpcodeCoverage) -> Result<(), Box<dyn Error>> {
+    let mut merged = match fs::read_to_string(path) {
+        Ok(text) => OpcodeCoverage::parse(&text).ok_or("Invalid coverage file")?,
+        Err(_) => OpcodeCoverage::new(),
+    };
+
+    merged.merge(coverage);
+    fs::write(path, merged.to_string())?;
+    Ok(())
+}
+
+/// `dmgemu coverage <file>...`: merge opcode coverage files and list the
+/// opcodes none of the runs executed.
+pub fn coverage(args: &[String]) -> Result<i32, Box<dyn Error>> {
+    if args.is_empty() {
+        return Err("Usage: dmgemu coverage <coverage file>...".into());
+    }
+
+    let mut coverage = OpcodeCoverage::new();
+
+    for file in args {
+        let text = fs::read_to_string(file)?;
+        coverage
+            .merge(&OpcodeCoverage::parse(&text).ok_or(format!("Invalid coverage file {file}"))?);
+    }
+
+    println!(
+        "{}/{} opcodes executed",
+        coverage.executed(),
+        OpcodeCoverage::legal_count()
+    );
+
+    let unexercised: Vec<u16> = coverage.unexercised().collect();
+
+    if !unexercised.is_empty() {
+        println!("Never executed:");
+    }
+
+    for index in &unexercised {
+        println!(
+            "\t{:<4} {}",
+            OpcodeCoverage::opcode_name(*index),
+            OpcodeCoverage::mnemonic(*index)
+        );
+    }
+
+    Ok(if unexercised.is_empty() { 0 } else { 2 })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use dmg_core::selftest::selftest_rom;
+
+    use super::*;
+
+    #[test]
+    fn batch_test_reports_every_rom() {
+        let dir = env::temp_dir().join(format!("dmgemu-batch-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("selftest.gb"), selftest_rom()).unwrap();
+        fs::write(dir.join("broken.gb"), [0; 16]).unwrap();
+        fs::write(dir.join("notes.txt"), "not a ROM").unwrap();
+
+        let report = dir.join("report.json");
+        let args = [
+            dir.display().to_string(),
+            "--frames".to_string(),
+            "30".to_string(),
+            "--report".to_string(),
+            report.display().to_string(),
+        ];
+        let code = batch_test(&args).unwrap();
+        let text = fs::read_to_string(&report).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Sorted by path, only the .gb files
+        let rows: Vec<&str> = text.lines().filter(|line| line.contains("file")).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].contains("broken.gb\", \"status\": \"load_failed\", \"frames\": 0"));
+        assert!(rows[1].contains("selftest.gb\", \"status\": \"ok\", \"frames\": 30"));
+        assert_eq!(code, 2);
+    }
+
+    #[test]
+    fn csv_report_quotes_messages() {
+        let results = [BatchResult {
+            file: "a,b.gb".to_string(),
+            status: BatchStatus::Panicked,
+            frames: 12,
+            message: "said \"no\"".to_string(),
+        }];
+
+        assert_eq!(
+            batch_report(&results, false),
+            "file,status,frames,message\n\"a,b.gb\",panicked,12,\"said \"\"no\"\"\"\n"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use dmg_core::cheats::CheatList;
+use dmg_core::romdb::{RomDatabase, RomIdentity};
+
+const APP_NAME: &str = "dmgemu";
+
+/// Per-user configuration directory, e.g. `~/.config/dmgemu`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    base.map(|dir| dir.join(APP_NAME))
+}
+
+/// Cheats of a game are stored by its global checksum, so they follow the ROM
+/// when it is renamed or moved.
+fn cheat_file(global_checksum: u16) -> Option<PathBuf> {
+    config_dir().map(|dir| {
+        dir.join("cheats")
+            .join(format!("{global_checksum:04X}.cht"))
+    })
+}
+
+pub fn load_cheats(global_checksum: u16) -> Result<CheatList, Box<dyn Error>> {
+    let Some(path) = cheat_file(global_checksum) else {
+        return Ok(CheatList::new());
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(CheatList::parse(&text)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(CheatList::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save_cheats(global_checksum: u16, cheats: &CheatList) -> Result<(), Box<dyn Error>> {
+    let path = cheat_file(global_checksum).ok_or("No configuration directory")?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, cheats.to_string())?;
+    Ok(())
+}
+
+/// ROM database from `path`, or `gb.dat` in the configuration directory if it exists.
+pub fn load_rom_database(path: Option<&Path>) -> Result<RomDatabase, Box<dyn Error>> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match config_dir().map(|dir| dir.join("gb.dat")) {
+            Some(path) if path.exists() => path,
+            _ => return Ok(RomDatabase::default()),
+        },
+    };
+
+    Ok(RomDatabase::parse_dat(&fs::read_to_string(path)?))
+}
+
+/// Game name for the window title and a warning about the dump, if any.
+pub fn describe_identity(identity: &RomIdentity) -> (Option<String>, Option<String>) {
+    match identity {
+        RomIdentity::Verified(entry) => (Some(entry.name.clone()), None),
+        RomIdentity::BadDump(entry) => (
+            Some(entry.name.clone()),
+            Some("known bad dump, expect problems".to_string()),
+        ),
+        RomIdentity::Overdump(entry) => (
+            Some(entry.name.clone()),
+            Some("overdump, the file is larger than the ROM".to_string()),
+        ),
+        RomIdentity::Unknown => (None, None),
+    }
+}
+
+/// Look of the window around the game, from `display.cfg` in the configuration directory.
+pub struct DisplayConfig {
+    /// ARGB8888 color of the letterbox
+    pub border_color: u32,
+    /// BMP image drawn centered behind the game at the same scale, e.g. a
+    /// 256x224 frame with a 160x144 hole like a Super Game Boy border
+    pub border_image: Option<PathBuf>,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            border_color: 0xFF000000,
+            border_image: None,
+        }
+    }
+}
+
+/// Display settings with lines like `border_color = #202020` or
+/// `border_image = frame.bmp`, relative paths are taken from the configuration directory.
+pub fn load_display_config() -> Result<DisplayConfig, Box<dyn Error>> {
+    let mut display = DisplayConfig::default();
+    let Some(dir) = config_dir() else {
+        return Ok(display);
+    };
+
+    let text = match fs::read_to_string(dir.join("display.cfg")) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(display),
+        Err(e) => return Err(e.into()),
+    };
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, value) = line.split_once('=').ok_or("Invalid display line")?;
+        let value = value.trim();
+
+        match name.trim() {
+            "border_color" => {
+                let hex = value.strip_prefix('#').unwrap_or(value);
+                let color = u32::from_str_radix(hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 6)
+                    .ok_or_else(|| format!("Invalid color {value}"))?;
+                display.border_color = 0xFF000000 | color;
+            }
+            "border_image" => display.border_image = Some(dir.join(value)),
+            name => return Err(format!("Unknown display setting {name}").into()),
+        }
+    }
+
+    Ok(display)
+}
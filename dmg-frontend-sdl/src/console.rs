@@ -0,0 +1,102 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use dmg_core::ppu::{PpuEvent, YRES};
+use dmg_core::watch::Watch;
+
+/// Debugger commands typed into the terminal.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Run to the next VBlank
+    Frame,
+    /// Go back to the end of the previous frame from the rewind buffer
+    Back,
+    /// Run to the start of mode 2 on a line
+    Scanline(u8),
+    /// Show a value next to the game
+    Watch(Watch),
+    /// Stop showing a watch, all of them without one
+    Unwatch(Option<String>),
+    /// Resume or pause the `--trace`
+    Trace(bool),
+    /// Print the banks and RTC of the cartridge mapper
+    Mapper,
+}
+
+impl Command {
+    const USAGE: &str = "Commands: frame, back, scanline <0-143>, watch <expression>, unwatch [expression], trace on|off, mapper";
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        // Expressions may have spaces in them
+        match line.trim().split_once(char::is_whitespace) {
+            Some(("watch", expression)) => return Watch::parse(expression).map(Command::Watch),
+            Some(("unwatch", expression)) => {
+                return Ok(Command::Unwatch(Some(expression.trim().to_string())));
+            }
+            _ if line.trim() == "unwatch" => return Ok(Command::Unwatch(None)),
+            _ => (),
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["frame"] => Ok(Command::Frame),
+            ["back"] => Ok(Command::Back),
+            ["mapper"] => Ok(Command::Mapper),
+            ["trace", "on"] => Ok(Command::Trace(true)),
+            ["trace", "off"] => Ok(Command::Trace(false)),
+            ["scanline", line] => line
+                .parse::<u8>()
+                .ok()
+                .filter(|line| (*line as usize) < YRES)
+                .map(Command::Scanline)
+                .ok_or_else(|| format!("Invalid scanline {line}, lines 0 to 143 have mode 2")),
+            _ => Err(Self::USAGE.to_string()),
+        }
+    }
+
+    /// The PPU transition the command runs to.
+    pub fn event(&self) -> Option<PpuEvent> {
+        match self {
+            Command::Frame => Some(PpuEvent::VBlank),
+            Command::Scanline(line) => Some(PpuEvent::LineStart(*line)),
+            Command::Back
+            | Command::Watch(_)
+            | Command::Unwatch(_)
+            | Command::Trace(_)
+            | Command::Mapper => None,
+        }
+    }
+}
+
+/// Lines typed into the terminal, read on a thread of their own so the
+/// window never waits for them.
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+impl Console {
+    pub fn spawn() -> Self {
+        let (tx, lines) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                if !line.trim().is_empty() && tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Console { lines }
+    }
+
+    /// Commands typed since the last call.
+    pub fn commands(&self) -> impl Iterator<Item = Result<Command, String>> + '_ {
+        self.lines.try_iter().map(|line| Command::parse(&line))
+    }
+}
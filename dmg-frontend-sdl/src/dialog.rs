@@ -0,0 +1,56 @@
+//! Native file dialogs, run through the tools the desktop provides since SDL has none.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Ask for a ROM file.
+///
+/// Returns `Ok(None)` when the dialog was cancelled and an error when no
+/// dialog tool is installed.
+pub fn pick_rom() -> Result<Option<PathBuf>, String> {
+    let tools: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[(
+            "osascript",
+            &["-e", "POSIX path of (choose file with prompt \"Open ROM\")"],
+        )]
+    } else if cfg!(windows) {
+        &[(
+            "powershell",
+            &[
+                "-NoProfile",
+                "-Command",
+                "Add-Type -AssemblyName System.Windows.Forms; \
+                 $d = New-Object System.Windows.Forms.OpenFileDialog; \
+                 $d.Filter = 'Game Boy ROMs|*.gb;*.gbc|All files|*.*'; \
+                 if ($d.ShowDialog() -eq 'OK') { $d.FileName }",
+            ],
+        )]
+    } else {
+        &[
+            (
+                "zenity",
+                &[
+                    "--file-selection",
+                    "--title=Open ROM",
+                    "--file-filter=Game Boy ROMs | *.gb *.gbc",
+                    "--file-filter=All files | *",
+                ],
+            ),
+            ("kdialog", &["--getopenfilename", ".", "*.gb *.gbc"]),
+        ]
+    };
+
+    for (program, args) in tools {
+        // Not installed, try the next one
+        let Ok(output) = Command::new(program).args(*args).output() else {
+            continue;
+        };
+
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        return Ok((output.status.success() && !path.is_empty()).then(|| PathBuf::from(path)));
+    }
+
+    let names: Vec<&str> = tools.iter().map(|(program, _)| *program).collect();
+    Err(format!("No file dialog found, tried {}", names.join(", ")))
+}
@@ -0,0 +1,1340 @@
+use std::path::{Path, PathBuf};
+
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::messagebox::{
+    ButtonData, ClickedButton, MessageBoxButtonFlag, MessageBoxFlag, show_message_box,
+    show_simple_message_box,
+};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::surface::Surface;
+use sdl2::video::{FullscreenType, Window, WindowContext};
+
+use dmg_core::frame::PixelSource;
+use dmg_core::interrupts::InterruptStats;
+use dmg_core::joypad::Buttons;
+use dmg_core::lcd::DEFAULT_COLORS;
+use dmg_core::ppu::{XRES, YRES};
+use dmg_core::stats::{PacingMode, STATS_HISTORY, Stats, TARGET_FRAME_TIME};
+use dmg_core::vram::{self, TileSet};
+
+use crate::hotkeys::{Hotkey, Hotkeys};
+use crate::input::{InputSource, InputState, KeyMap, Orientation, controller_button};
+use crate::render::{DisassemblyView, FrameBlender, FrameSnapshot, InputDisplay, Overlay};
+
+// Both motors at full speed for a short buzz
+const RUMBLE_STRENGTH: u16 = 0xFFFF;
+const RUMBLE_MS: u32 = 250;
+
+/// 3x5 pixel digits for the overlay, one row of 3 bits per nibble from the top.
+const DIGITS: [u32; 10] = [
+    0x75557, 0x26222, 0x71747, 0x71717, 0x55711, 0x74717, 0x74757, 0x71111, 0x75757, 0x75717,
+];
+
+/// Letters and the punctuation of the disassembly in the style of `DIGITS`.
+const GLYPHS: [(char, u32); 37] = [
+    ('A', 0x25755),
+    ('B', 0x65656),
+    ('C', 0x34443),
+    ('D', 0x65556),
+    ('E', 0x74647),
+    ('F', 0x74644),
+    ('G', 0x34553),
+    ('H', 0x55755),
+    ('I', 0x72227),
+    ('J', 0x11152),
+    ('K', 0x55655),
+    ('L', 0x44447),
+    ('M', 0x57755),
+    ('N', 0x65555),
+    ('O', 0x25552),
+    ('P', 0x65644),
+    ('Q', 0x25563),
+    ('R', 0x65655),
+    ('S', 0x34216),
+    ('T', 0x72222),
+    ('U', 0x55557),
+    ('V', 0x55552),
+    ('W', 0x55775),
+    ('X', 0x55255),
+    ('Y', 0x55222),
+    ('Z', 0x71247),
+    (',', 0x00024),
+    ('(', 0x12221),
+    (')', 0x42224),
+    ('+', 0x02720),
+    ('-', 0x00700),
+    ('$', 0x36236),
+    (':', 0x02020),
+    ('>', 0x42124),
+    ('*', 0x52500),
+    ('[', 0x64446),
+    (']', 0x31113),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GuiAction {
+    Exit,
+    HotkeyDown(Hotkey),
+    HotkeyUp(Hotkey),
+    /// A file was dropped on the window
+    OpenRom(PathBuf),
+    /// A line of the disassembly was clicked
+    ToggleBreakpoint(u16),
+}
+
+/// Entries of the menu.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MenuItem {
+    Hotkey(Hotkey),
+    Options,
+}
+
+const MENU: [(&str, MenuItem); 10] = [
+    ("Open ROM", MenuItem::Hotkey(Hotkey::OpenRom)),
+    ("Save State", MenuItem::Hotkey(Hotkey::SaveState)),
+    ("Load State", MenuItem::Hotkey(Hotkey::LoadState)),
+    ("Undo State", MenuItem::Hotkey(Hotkey::UndoState)),
+    ("Reset", MenuItem::Hotkey(Hotkey::SoftReset)),
+    ("Power Cycle", MenuItem::Hotkey(Hotkey::HardReset)),
+    ("Pause", MenuItem::Hotkey(Hotkey::Pause)),
+    ("Screenshot", MenuItem::Hotkey(Hotkey::Screenshot)),
+    ("Controls", MenuItem::Hotkey(Hotkey::RemapControls)),
+    ("Options", MenuItem::Options),
+];
+
+const OPTIONS: [(&str, Hotkey); 14] = [
+    ("Fullscreen", Hotkey::Fullscreen),
+    ("Pixel Perfect", Hotkey::TogglePixelPerfect),
+    ("Rotate", Hotkey::Rotate),
+    ("Mirror", Hotkey::ToggleMirror),
+    ("Palette", Hotkey::CyclePalette),
+    ("Blend Frames", Hotkey::ToggleBlend),
+    ("Background", Hotkey::ToggleBackground),
+    ("Window", Hotkey::ToggleWindow),
+    ("Sprites", Hotkey::ToggleSprites),
+    ("Overlay", Hotkey::ToggleOverlay),
+    ("Priority", Hotkey::TogglePriority),
+    ("Stats", Hotkey::ToggleStats),
+    ("Input", Hotkey::ToggleInput),
+    ("Export VRAM", Hotkey::ExportVram),
+];
+
+#[allow(dead_code, clippy::upper_case_acronyms)]
+pub struct GUI {
+    sdl_context: sdl2::Sdl,
+    // Canvas to keeps windows open
+    canvas: Canvas<Window>,
+    // The game drawn upright at `SCALE`, turned when copied to the window
+    screen: Texture<'static>,
+    debug_canvas: Option<Canvas<Window>>,
+    // Tile viewer image, streamed from `debug_pixels`
+    debug_texture: Option<Texture<'static>>,
+    controllers: GameControllerSubsystem,
+    controller: Option<GameController>,
+    // Joystick index of the controller to use, any controller if None
+    controller_index: Option<u32>,
+    hotkeys: Hotkeys,
+    keymap: KeyMap,
+    // Draw the tile grid, window origin and sprite boxes over the game
+    show_overlay: bool,
+    // Tint pixels by the layer that won priority
+    show_priority: bool,
+    // Draw the frametime graph and FPS
+    show_stats: bool,
+    // Draw the buttons held and P1
+    show_input: bool,
+    // Scale the game by whole pixels, otherwise fill the window
+    pixel_perfect: bool,
+    // Mix every frame with the one before, None when off
+    blender: Option<FrameBlender>,
+    orientation: Orientation,
+    // Letterbox color and an image behind the game
+    border_color: Color,
+    border_image: Option<Surface<'static>>,
+    // Tile viewer at 1x with a pixel between tiles, ARGB8888
+    debug_pixels: Vec<u32>,
+    // Addresses of the disassembly lines drawn, empty while running
+    disassembly_rows: Vec<u16>,
+    // Line picked with the keyboard, the one at PC when None
+    selected_line: Option<u16>,
+}
+
+impl Default for GUI {
+    fn default() -> Self {
+        GUI::new(false, false)
+    }
+}
+
+impl GUI {
+    const DEBUG_SCREEN_WIDTH: u32 = 16;
+    const DEBUG_SCREEN_HEIGHT: u32 = 24;
+    const SCALE: u32 = 5;
+    // Tile viewer image size, 8 pixels and a gap per tile
+    const DEBUG_IMAGE_WIDTH: u32 = Self::DEBUG_SCREEN_WIDTH * 9;
+    const DEBUG_IMAGE_HEIGHT: u32 = Self::DEBUG_SCREEN_HEIGHT * 9;
+    // Disassembly panel in the pixels of the game texture
+    const DISASSEMBLY_WIDTH: u32 = 320;
+    const DISASSEMBLY_TOP: i32 = 8;
+    const DISASSEMBLY_LINE_HEIGHT: i32 = 16;
+
+    /// With `vsync` presenting waits for the next refresh of the display.
+    pub fn new(debug: bool, vsync: bool) -> Self {
+        // Let Windows report physical pixels and scale the window size itself
+        sdl2::hint::set("SDL_WINDOWS_DPI_AWARENESS", "permonitorv2");
+        sdl2::hint::set("SDL_WINDOWS_DPI_SCALING", "1");
+
+        // SIGINT and SIGTERM come as quit events and end the run like closing
+        // the window, saving the game on the way
+        sdl2::hint::set("SDL_NO_SIGNAL_HANDLERS", "0");
+
+        let sdl_context = sdl2::init().unwrap();
+        let video_subsystem = sdl_context.video().unwrap();
+        let (width, height) = (XRES as u32 * Self::SCALE, YRES as u32 * Self::SCALE);
+        let mut window = video_subsystem
+            .window("GameBoy Emulator", width, height)
+            .position_centered()
+            .allow_highdpi()
+            .resizable()
+            .build()
+            .unwrap();
+
+        let dpi_factor = Self::dpi_factor(&video_subsystem, &window);
+
+        if dpi_factor > 1 {
+            println!("Scaling the window by {dpi_factor} for the display DPI");
+            window
+                .set_size(width * dpi_factor, height * dpi_factor)
+                .unwrap();
+        }
+
+        let (posx, posy) = window.position();
+        // Controllers already plugged in are reported as added devices
+        let controllers = sdl_context.game_controller().unwrap();
+
+        let mut canvas = window.into_canvas();
+        if vsync {
+            canvas = canvas.present_vsync();
+        }
+        let mut canvas = canvas.build().unwrap();
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.present();
+        let screen = Self::texture_creator(&canvas)
+            .create_texture_target(PixelFormatEnum::ARGB8888, width, height)
+            .unwrap();
+
+        if debug {
+            let debug_width = Self::DEBUG_IMAGE_WIDTH * Self::SCALE;
+            let debug_height = Self::DEBUG_IMAGE_HEIGHT * Self::SCALE;
+            let debug_window = video_subsystem
+                .window(
+                    "Debug Info",
+                    debug_width * dpi_factor,
+                    debug_height * dpi_factor,
+                )
+                .position(posx + ((width + 8 * Self::SCALE) * dpi_factor) as i32, posy)
+                .allow_highdpi()
+                .build()
+                .unwrap();
+
+            let mut debug_canvas = debug_window.into_canvas().build().unwrap();
+            debug_canvas
+                .set_logical_size(debug_width, debug_height)
+                .unwrap();
+            debug_canvas.set_integer_scale(true).unwrap();
+            debug_canvas.set_blend_mode(BlendMode::Blend);
+            debug_canvas.set_draw_color(Color::RGB(0, 0, 0));
+            debug_canvas.clear();
+            debug_canvas.present();
+            let debug_texture = Self::texture_creator(&debug_canvas)
+                .create_texture_streaming(
+                    PixelFormatEnum::ARGB8888,
+                    Self::DEBUG_IMAGE_WIDTH,
+                    Self::DEBUG_IMAGE_HEIGHT,
+                )
+                .unwrap();
+
+            return GUI {
+                sdl_context,
+                canvas,
+                screen,
+                debug_canvas: Some(debug_canvas),
+                debug_texture: Some(debug_texture),
+                controllers,
+                controller: None,
+                controller_index: None,
+                hotkeys: Hotkeys::default(),
+                keymap: KeyMap::default(),
+                show_overlay: false,
+                show_priority: false,
+                show_stats: false,
+                show_input: false,
+                pixel_perfect: true,
+                blender: None,
+                orientation: Orientation::default(),
+                border_color: Color::RGB(0, 0, 0),
+                border_image: None,
+                debug_pixels: vec![
+                    0xFF000000;
+                    (Self::DEBUG_IMAGE_WIDTH * Self::DEBUG_IMAGE_HEIGHT) as usize
+                ],
+                disassembly_rows: Vec::new(),
+                selected_line: None,
+            };
+        }
+
+        GUI {
+            sdl_context,
+            canvas,
+            screen,
+            debug_canvas: None,
+            debug_texture: None,
+            controllers,
+            controller: None,
+            controller_index: None,
+            hotkeys: Hotkeys::default(),
+            keymap: KeyMap::default(),
+            show_overlay: false,
+            show_priority: false,
+            show_stats: false,
+            show_input: false,
+            pixel_perfect: true,
+            blender: None,
+            orientation: Orientation::default(),
+            border_color: Color::RGB(0, 0, 0),
+            border_image: None,
+            debug_pixels: Vec::new(),
+            disassembly_rows: Vec::new(),
+            selected_line: None,
+        }
+    }
+
+    /// Texture creator of `canvas` for textures kept in the GUI. It is leaked,
+    /// the GUI lasts as long as the program.
+    fn texture_creator(canvas: &Canvas<Window>) -> &'static TextureCreator<WindowContext> {
+        Box::leak(Box::new(canvas.texture_creator()))
+    }
+
+    /// Factor to enlarge the windows by on high DPI displays.
+    ///
+    /// macOS, Wayland and Windows (with the hints above) size windows in
+    /// points and give them more physical pixels, so there is nothing to do.
+    /// Elsewhere the window would be tiny and is enlarged by the display DPI
+    /// over the usual 96.
+    fn dpi_factor(video: &sdl2::VideoSubsystem, window: &Window) -> u32 {
+        if window.drawable_size() != window.size() {
+            return 1;
+        }
+
+        let index = window.display_index().unwrap_or(0);
+
+        video
+            .display_dpi(index)
+            .map_or(1, |(_, hdpi, _)| (hdpi / 96.0).round().max(1.0) as u32)
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        if let Err(e) = self.canvas.window_mut().set_title(title) {
+            eprintln!("Failed to set the window title: {e}");
+        }
+    }
+
+    /// Refresh rate of the display showing the game window, None when SDL
+    /// doesn't know it.
+    pub fn display_rate(&self) -> Option<f32> {
+        let mode = self.canvas.window().display_mode().ok()?;
+        (mode.refresh_rate > 0).then_some(mode.refresh_rate as f32)
+    }
+
+    pub fn set_hotkeys(&mut self, hotkeys: Hotkeys) {
+        self.hotkeys = hotkeys;
+    }
+
+    pub fn set_keymap(&mut self, keymap: KeyMap) {
+        self.keymap = keymap;
+    }
+
+    pub fn toggle_overlay(&mut self) {
+        self.show_overlay = !self.show_overlay;
+    }
+
+    pub fn toggle_priority(&mut self) {
+        self.show_priority = !self.show_priority;
+    }
+
+    pub fn toggle_stats(&mut self) {
+        self.show_stats = !self.show_stats;
+    }
+
+    pub fn toggle_input(&mut self) {
+        self.show_input = !self.show_input;
+    }
+
+    /// Move the selection in the disassembly by `lines`, up when negative.
+    pub fn move_selection(&mut self, lines: isize) {
+        let Some(current) = self.selected_row() else {
+            return;
+        };
+        let row = current.saturating_add_signed(lines);
+        self.selected_line = self
+            .disassembly_rows
+            .get(row.min(self.disassembly_rows.len() - 1))
+            .copied();
+    }
+
+    /// Address of the selected line of the disassembly, None while it's hidden.
+    pub fn selected_line(&self) -> Option<u16> {
+        self.selected_row().map(|row| self.disassembly_rows[row])
+    }
+
+    fn selected_row(&self) -> Option<usize> {
+        self.disassembly_rows
+            .iter()
+            .position(|address| Some(*address) == self.selected_line)
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    /// Fill the window around the game with `color` and draw the BMP `image` behind it.
+    pub fn set_border(&mut self, color: u32, image: Option<&Path>) -> Result<(), String> {
+        self.border_color = color_from_u32(color);
+        self.border_image = match image {
+            Some(path) => Some(
+                Surface::load_bmp(path)
+                    .map_err(|e| format!("Failed to load {}: {e}", path.display()))?,
+            ),
+            None => None,
+        };
+
+        Ok(())
+    }
+
+    pub fn set_blend(&mut self, enabled: bool) {
+        self.blender = enabled.then(FrameBlender::default);
+    }
+
+    pub fn blend(&self) -> bool {
+        self.blender.is_some()
+    }
+
+    pub fn toggle_pixel_perfect(&mut self) {
+        self.pixel_perfect = !self.pixel_perfect;
+    }
+
+    /// Resize the window to `scale` times the screen size, or the border
+    /// image if there is one, as turned.
+    pub fn set_window_scale(&mut self, scale: u32) {
+        let (content_width, content_height) = self.content_size();
+        let (mut width, mut height) = (content_width * scale, content_height * scale);
+
+        if self.orientation.is_sideways() {
+            (width, height) = (height, width);
+        }
+
+        let window = self.canvas.window_mut();
+
+        if window.fullscreen_state() != FullscreenType::Off {
+            let _ = window.set_fullscreen(FullscreenType::Off);
+        }
+
+        if let Err(e) = window.set_size(width, height) {
+            eprintln!("Failed to resize the window: {e}");
+        }
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+
+        if let Err(e) = window.set_fullscreen(fullscreen) {
+            eprintln!("Failed to toggle fullscreen: {e}");
+        }
+    }
+
+    /// Show the menu and wait for a choice, None if it was closed.
+    pub fn show_menu(&self) -> Option<MenuItem> {
+        self.choose("Menu", "Drop a ROM file on the window to open it.", &MENU)
+    }
+
+    /// Show the display options with the hotkey bindings.
+    pub fn show_options(&self) -> Option<Hotkey> {
+        let bindings: Vec<String> = self
+            .hotkeys
+            .bindings()
+            .map(|(hotkey, chord)| format!("{hotkey:?}: {chord}"))
+            .collect();
+
+        self.choose("Options", &bindings.join("\n"), &OPTIONS)
+    }
+
+    pub fn show_message(&self, title: &str, message: &str) {
+        let window = self.canvas.window();
+
+        if let Err(e) = show_simple_message_box(MessageBoxFlag::INFORMATION, title, message, window)
+        {
+            eprintln!("Failed to show a message: {e}");
+        }
+    }
+
+    /// Whether to continue from the state saved when the game was closed.
+    pub fn ask_resume(&self, game_name: &str) -> bool {
+        self.choose(
+            "Resume",
+            &format!("Continue {game_name} where you left off?"),
+            &[("Resume", true), ("Start Over", false)],
+        )
+        .unwrap_or(false)
+    }
+
+    /// Message box with a button per item, blocks until one is clicked.
+    fn choose<T: Copy>(&self, title: &str, message: &str, items: &[(&str, T)]) -> Option<T> {
+        let buttons: Vec<ButtonData> = items
+            .iter()
+            .enumerate()
+            .map(|(i, (text, _))| ButtonData {
+                flags: MessageBoxButtonFlag::NOTHING,
+                button_id: i as i32,
+                text,
+            })
+            .collect();
+
+        let window = self.canvas.window();
+
+        match show_message_box(
+            MessageBoxFlag::INFORMATION,
+            &buttons,
+            title,
+            message,
+            window,
+            None,
+        ) {
+            Ok(ClickedButton::CustomButton(button)) => Some(items[button.button_id as usize].1),
+            Ok(ClickedButton::CloseButton) => None,
+            Err(e) => {
+                eprintln!("Failed to show the {title}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Ask for a key for every joypad button in turn on the game screen,
+    /// blocking until the last one. Keys of hotkeys are refused, Escape or
+    /// closing the window cancels and returns None.
+    pub fn capture_controls(&mut self, input: &InputState) -> Option<KeyMap> {
+        // A key held now would stay pressed for the game once remapped
+        input.clear(InputSource::Keyboard);
+        let mut event_pump = self.sdl_context.event_pump().unwrap();
+        let mut keymap = self.keymap.clone();
+
+        for (button, name) in KeyMap::BUTTONS {
+            let current = keymap
+                .key(button)
+                .map_or_else(|| "none".to_string(), |key| key.name());
+            let mut lines = vec![
+                format!("press a key for {name}"),
+                format!("now {current}"),
+                String::new(),
+                "esc cancels".to_string(),
+            ];
+
+            let keycode = loop {
+                self.draw_prompt(&lines);
+
+                match event_pump.wait_event_timeout(100) {
+                    Some(Event::Quit { .. })
+                    | Some(Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    }) => return None,
+                    Some(Event::KeyDown {
+                        keycode: Some(keycode),
+                        repeat: false,
+                        ..
+                    }) => match self.hotkeys.pressed(keycode, Mod::NOMOD) {
+                        Some(hotkey) => lines[2] = format!("{} is {hotkey:?}", keycode.name()),
+                        None => break keycode,
+                    },
+                    _ => (),
+                }
+            };
+
+            keymap.bind(button, keycode);
+        }
+
+        Some(keymap)
+    }
+
+    /// Lines of text over a dark screen in place of the game, turned like it.
+    fn draw_prompt(&mut self, lines: &[String]) {
+        let _ = self.canvas.with_texture_canvas(&mut self.screen, |canvas| {
+            canvas.set_draw_color(Color::RGB(16, 16, 16));
+            canvas.clear();
+            canvas.set_draw_color(Color::RGB(255, 255, 255));
+
+            for (row, line) in lines.iter().enumerate() {
+                let y = 280 + row as i32 * Self::DISASSEMBLY_LINE_HEIGHT * 2;
+                Self::draw_text(canvas, line, 40, y);
+            }
+        });
+
+        let (game_rect, _) = self.layout();
+        let (angle, mirror) = (self.orientation.angle(), self.orientation.mirror);
+
+        self.canvas.set_draw_color(self.border_color);
+        self.canvas.clear();
+        let _ = self
+            .canvas
+            .copy_ex(&self.screen, None, game_rect, angle, None, mirror, false);
+        self.canvas.present();
+    }
+
+    /// Use the controller at joystick `index` for the joypad, any controller if None.
+    pub fn select_controller(&mut self, index: Option<u32>) {
+        self.controller_index = index;
+        self.controller = None;
+        self.open_controller();
+    }
+
+    /// Shake the controller for a moment, if it can.
+    pub fn rumble(&mut self) {
+        if let Some(controller) = &mut self.controller {
+            // Controllers without motors report an error, nothing to do about it
+            let _ = controller.set_rumble(RUMBLE_STRENGTH, RUMBLE_STRENGTH, RUMBLE_MS);
+        }
+    }
+
+    fn open_controller(&mut self) {
+        let count = self.controllers.num_joysticks().unwrap_or(0);
+        let mut candidates = (0..count).filter(|i| self.controllers.is_game_controller(*i));
+
+        let index = match self.controller_index {
+            Some(index) => candidates.find(|i| *i == index),
+            None => candidates.next(),
+        };
+
+        self.controller = index.and_then(|index| self.controllers.open(index).ok());
+
+        if let Some(controller) = &self.controller {
+            println!("Using controller {}", controller.name());
+        }
+    }
+
+    fn controller_event(&mut self, event: &Event, input: &InputState) {
+        let current = self.controller.as_ref().map(|c| c.instance_id());
+
+        match *event {
+            Event::ControllerDeviceAdded { .. } if self.controller.is_none() => {
+                self.open_controller();
+            }
+            Event::ControllerDeviceRemoved { which, .. } if Some(which) == current => {
+                println!("Controller disconnected, using the keyboard");
+                self.controller = None;
+                input.clear(InputSource::Controller);
+                self.open_controller();
+            }
+            Event::ControllerButtonDown { which, button, .. } if Some(which) == current => {
+                if let Some(button) = controller_button(button) {
+                    input.press(InputSource::Controller, button);
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } if Some(which) == current => {
+                if let Some(button) = controller_button(button) {
+                    input.release(InputSource::Controller, button);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Wait up to `timeout_ms` for events and handle everything pending.
+    ///
+    /// Key presses reach `input` as soon as they arrive rather than once per
+    /// redraw, which keeps the input latency below a frame. Keys bound to a
+    /// hotkey are reported as actions instead.
+    pub fn handle_events(&mut self, input: &InputState, timeout_ms: u32) -> Vec<GuiAction> {
+        let mut event_pump = self.sdl_context.event_pump().unwrap();
+        let mut actions = Vec::new();
+        let first = event_pump.wait_event_timeout(timeout_ms);
+        let events: Vec<Event> = first.into_iter().chain(event_pump.poll_iter()).collect();
+
+        for event in events {
+            self.controller_event(&event, input);
+
+            match event {
+                Event::Quit { .. } => actions.push(GuiAction::Exit),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat,
+                    ..
+                } => match self.hotkeys.pressed(keycode, keymod) {
+                    Some(Hotkey::Quit) => actions.push(GuiAction::Exit),
+                    Some(hotkey) if !repeat => actions.push(GuiAction::HotkeyDown(hotkey)),
+                    Some(_) => (),
+                    None => {
+                        if let Some(button) = self.keymap.button(keycode) {
+                            input.press(InputSource::Keyboard, button);
+                        }
+                    }
+                },
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    actions.extend(self.hotkeys.released(keycode).map(GuiAction::HotkeyUp));
+
+                    if let Some(button) = self.keymap.button(keycode) {
+                        input.release(InputSource::Keyboard, button);
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Right,
+                    window_id,
+                    ..
+                } if window_id == self.canvas.window().id() => {
+                    actions.push(GuiAction::HotkeyDown(Hotkey::Menu))
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    window_id,
+                    x,
+                    y,
+                    ..
+                } if window_id == self.canvas.window().id() => {
+                    if let Some(address) = self.clicked_line(x, y) {
+                        actions.push(GuiAction::ToggleBreakpoint(address));
+                    }
+                }
+                Event::DropFile { filename, .. } => {
+                    actions.push(GuiAction::OpenRom(PathBuf::from(filename)))
+                }
+                _ => (),
+            }
+        }
+
+        actions
+    }
+
+    pub fn update_window(
+        &mut self,
+        snapshot: &FrameSnapshot,
+        disassembly: Option<&DisassemblyView>,
+        watches: &[(String, String)],
+    ) {
+        self.disassembly_rows = disassembly.map_or_else(Vec::new, |view| {
+            view.lines.iter().map(|line| line.address).collect()
+        });
+        // A new pause starts at PC, as does a selection scrolled out of view
+        if self.selected_row().is_none() {
+            self.selected_line = disassembly.map(|view| view.pc);
+        }
+        let selected = self.selected_line;
+
+        let (show_overlay, show_priority, show_stats, show_input) = (
+            self.show_overlay,
+            self.show_priority,
+            self.show_stats,
+            self.show_input,
+        );
+        let frame = &snapshot.frame;
+        // The priority view shows each frame as it is, unblended
+        let pixels: Vec<u32> = match &mut self.blender {
+            _ if show_priority => (0..YRES)
+                .flat_map(|y| (0..XRES).map(move |x| frame.priority_pixel(x, y)))
+                .collect(),
+            Some(blender) => blender.blend(snapshot.frame_number, frame.as_argb8888()),
+            None => frame.as_argb8888(),
+        };
+
+        self.canvas
+            .with_texture_canvas(&mut self.screen, |canvas| {
+                for line_num in 0..(YRES as i32) {
+                    for x in 0..(XRES as i32) {
+                        let x_rc = x * (Self::SCALE as i32);
+                        let y_rc = line_num * (Self::SCALE as i32);
+                        let rc = Rect::new(x_rc, y_rc, Self::SCALE, Self::SCALE);
+                        let color = color_from_u32(pixels[line_num as usize * XRES + x as usize]);
+
+                        canvas.set_draw_color(color);
+                        canvas.fill_rect(rc).unwrap();
+                    }
+                }
+
+                if show_overlay {
+                    Self::draw_overlay(canvas, &snapshot.overlay);
+                }
+
+                if show_priority {
+                    Self::draw_priority_legend(canvas);
+                }
+
+                if show_stats {
+                    Self::draw_stats(canvas, &snapshot.stats);
+                    Self::draw_interrupt_stats(canvas, &snapshot.interrupts);
+                }
+
+                if show_input {
+                    Self::draw_input(canvas, &snapshot.input);
+                }
+
+                if let Some(view) = disassembly {
+                    Self::draw_disassembly(canvas, view, selected);
+                }
+
+                if !watches.is_empty() {
+                    Self::draw_watches(canvas, watches);
+                }
+            })
+            .unwrap();
+
+        let (game_rect, border_rect) = self.layout();
+        let (angle, mirror) = (self.orientation.angle(), self.orientation.mirror);
+
+        self.canvas.set_draw_color(self.border_color);
+        self.canvas.clear();
+
+        let texture_creator = self.canvas.texture_creator();
+        if let (Some(image), Some(rect)) = (&self.border_image, border_rect)
+            && let Ok(border) = texture_creator.create_texture_from_surface(image)
+        {
+            let _ = self
+                .canvas
+                .copy_ex(&border, None, rect, angle, None, mirror, false);
+        }
+
+        self.canvas
+            .copy_ex(&self.screen, None, game_rect, angle, None, mirror, false)
+            .unwrap();
+        self.canvas.present();
+    }
+
+    /// Size of the screen with the border image around it, in Game Boy pixels.
+    fn content_size(&self) -> (u32, u32) {
+        match &self.border_image {
+            Some(image) => (
+                image.width().max(XRES as u32),
+                image.height().max(YRES as u32),
+            ),
+            None => (XRES as u32, YRES as u32),
+        }
+    }
+
+    /// Where the game and the border image go in the window in physical
+    /// pixels, before they are turned.
+    ///
+    /// Both are centered and scaled alike, so the game fits the hole of a
+    /// border made for it. In pixel perfect mode a Game Boy pixel covers a
+    /// whole number of physical pixels. Both sides of the game then differ by
+    /// an even number of pixels, so a quarter turn around its center keeps
+    /// the grid on physical pixels too.
+    fn layout(&self) -> (Rect, Option<Rect>) {
+        let (width, height) = self.canvas.output_size().unwrap_or((1, 1));
+        // Room for the game once turned upright
+        let (room_width, room_height) = if self.orientation.is_sideways() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let (content_width, content_height) = self.content_size();
+        let fit = (room_width as f32 / content_width as f32)
+            .min(room_height as f32 / content_height as f32);
+        // Physical pixels per Game Boy pixel
+        let size = if self.pixel_perfect {
+            fit.floor().max(1.0)
+        } else {
+            fit
+        };
+
+        let centered = |content_width: u32, content_height: u32| {
+            let (scaled_width, scaled_height) = (
+                (size * content_width as f32) as u32,
+                (size * content_height as f32) as u32,
+            );
+
+            Rect::new(
+                (width as i32 - scaled_width as i32) / 2,
+                (height as i32 - scaled_height as i32) / 2,
+                scaled_width,
+                scaled_height,
+            )
+        };
+
+        let border = self
+            .border_image
+            .as_ref()
+            .map(|image| centered(image.width(), image.height()));
+
+        (centered(XRES as u32, YRES as u32), border)
+    }
+
+    fn draw_overlay(canvas: &mut Canvas<Window>, overlay: &Overlay) {
+        let scale = Self::SCALE as i32;
+        let (width, height) = (XRES as i32 * scale, YRES as i32 * scale);
+
+        // Background tile boundaries follow the scroll position
+        canvas.set_draw_color(Color::RGBA(128, 128, 128, 96));
+
+        for x in (0..XRES as i32).filter(|x| (x + overlay.scroll_x as i32) % 8 == 0) {
+            let _ = canvas.fill_rect(Rect::new(x * scale, 0, 1, height as u32));
+        }
+
+        for y in (0..YRES as i32).filter(|y| (y + overlay.scroll_y as i32) % 8 == 0) {
+            let _ = canvas.fill_rect(Rect::new(0, y * scale, width as u32, 1));
+        }
+
+        if let Some((x, y)) = overlay.window {
+            canvas.set_draw_color(Color::RGBA(0, 96, 255, 192));
+            let _ = canvas.draw_rect(Rect::new(
+                x * scale,
+                y * scale,
+                (width - x * scale) as u32,
+                (height - y * scale) as u32,
+            ));
+        }
+
+        for sprite in &overlay.sprites {
+            let rc = Rect::new(
+                sprite.x * scale,
+                sprite.y * scale,
+                8 * Self::SCALE,
+                overlay.sprite_height * Self::SCALE,
+            );
+            canvas.set_draw_color(Color::RGBA(255, 0, 64, 192));
+            let _ = canvas.draw_rect(rc);
+            Self::draw_number(canvas, sprite.index as u32, rc.x() + 2, rc.y() + 2);
+        }
+    }
+
+    /// Tint of each pixel source and its name along the top of the game.
+    fn draw_priority_legend(canvas: &mut Canvas<Window>) {
+        let mut x = 4;
+
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        let _ = canvas.fill_rect(Rect::new(0, 0, XRES as u32 * Self::SCALE, 18));
+
+        for source in PixelSource::ALL {
+            canvas.set_draw_color(color_from_u32(source.tint()));
+            let _ = canvas.fill_rect(Rect::new(x, 4, 10, 10));
+            canvas.set_draw_color(Color::RGB(220, 220, 220));
+            Self::draw_text(canvas, source.name(), x + 14, 4);
+            x += 14 + source.name().len() as i32 * 8 + 12;
+        }
+    }
+
+    /// Frametime graph in the bottom left corner, one bar per frame with a
+    /// line at the target frame time, and the FPS above it.
+    fn draw_stats(canvas: &mut Canvas<Window>, stats: &Stats) {
+        // Pixels per millisecond, the graph is 40 ms high
+        const MS_HEIGHT: i32 = 4;
+        let bottom = (YRES as u32 * Self::SCALE) as i32 - 4;
+
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        let _ = canvas.fill_rect(Rect::new(
+            0,
+            bottom - 40 * MS_HEIGHT - 16,
+            (STATS_HISTORY * 2 + 8) as u32,
+            (40 * MS_HEIGHT + 20) as u32,
+        ));
+
+        for (i, frame_time) in stats.frame_times().enumerate() {
+            let height = (frame_time.as_secs_f32() * 1000.0 * MS_HEIGHT as f32) as i32;
+            let height = height.clamp(1, 40 * MS_HEIGHT);
+            let color = if frame_time > TARGET_FRAME_TIME * 2 {
+                Color::RGB(255, 64, 64)
+            } else {
+                Color::RGB(64, 255, 64)
+            };
+
+            canvas.set_draw_color(color);
+            let _ = canvas.fill_rect(Rect::new(
+                4 + i as i32 * 2,
+                bottom - height,
+                2,
+                height as u32,
+            ));
+        }
+
+        let target = (TARGET_FRAME_TIME.as_millis() as i32) * MS_HEIGHT;
+        canvas.set_draw_color(Color::RGBA(255, 255, 255, 160));
+        let _ = canvas.fill_rect(Rect::new(
+            0,
+            bottom - target,
+            (STATS_HISTORY * 2 + 8) as u32,
+            1,
+        ));
+
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        Self::draw_number(
+            canvas,
+            stats.fps().round() as u32,
+            4,
+            bottom - 40 * MS_HEIGHT - 12,
+        );
+
+        // Which way the frames are paced
+        if let Some(pacing) = stats.pacing() {
+            let text = match pacing.mode {
+                PacingMode::Exact => "EXACT".to_string(),
+                PacingMode::Refresh => format!("SYNC {}", pacing.display_rate.round()),
+            };
+            canvas.set_draw_color(Color::RGB(160, 160, 160));
+            Self::draw_text(canvas, &text, 80, bottom - 40 * MS_HEIGHT - 12);
+        }
+    }
+
+    /// Table of the interrupt sources in priority order (VBlank, STAT, timer,
+    /// serial, joypad) with their requests, dispatches and longest latency in
+    /// T-cycles. Rows of sources whose handler runs but missed requests, like
+    /// a game missing VBlanks, turn red.
+    fn draw_interrupt_stats(canvas: &mut Canvas<Window>, interrupts: &InterruptStats) {
+        const SOURCE_COLORS: [Color; 5] = [
+            Color::RGB(96, 160, 255),
+            Color::RGB(64, 255, 64),
+            Color::RGB(255, 255, 64),
+            Color::RGB(255, 96, 255),
+            Color::RGB(255, 160, 64),
+        ];
+        const COLUMN: i32 = 80;
+
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        let _ = canvas.fill_rect(Rect::new(0, 0, (COLUMN * 3 + 20) as u32, 5 * 14 + 6));
+
+        for (row, (_, source)) in interrupts.iter().enumerate() {
+            let y = 4 + row as i32 * 14;
+            canvas.set_draw_color(SOURCE_COLORS[row]);
+            let _ = canvas.fill_rect(Rect::new(4, y, 8, 10));
+
+            let color = if source.serviced > 0 && source.requested > source.serviced {
+                Color::RGB(255, 64, 64)
+            } else {
+                Color::RGB(255, 255, 255)
+            };
+            canvas.set_draw_color(color);
+
+            let values = [source.requested, source.serviced, source.max_latency];
+            for (column, value) in values.into_iter().enumerate() {
+                let value = value.min(u32::MAX as u64) as u32;
+                Self::draw_number(canvas, value, 20 + column as i32 * COLUMN, y);
+            }
+        }
+    }
+
+    /// Joypad in the bottom right corner, held buttons lit, with P1 above
+    /// it. The d-pad and the buttons turn blue while the game selects their
+    /// group.
+    fn draw_input(canvas: &mut Canvas<Window>, input: &InputDisplay) {
+        const CELL: i32 = 14;
+        let (width, height) = (
+            XRES as i32 * Self::SCALE as i32,
+            YRES as i32 * Self::SCALE as i32,
+        );
+        let (left, top) = (width - 8 * CELL - 8, height - 4 * CELL - 24);
+
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        let _ = canvas.fill_rect(Rect::new(
+            left - 4,
+            top - 4,
+            (8 * CELL + 8) as u32,
+            (4 * CELL + 24) as u32,
+        ));
+
+        canvas.set_draw_color(Color::RGB(160, 160, 160));
+        Self::draw_text(canvas, &format!("P1 ${:02X}", input.register), left, top);
+
+        // Active low, like the lines
+        let dpad_selected = input.register & 0x10 == 0;
+        let buttons_selected = input.register & 0x20 == 0;
+        let top = top + 16;
+        let keys = [
+            (Buttons::UP, CELL, 0, CELL, CELL, dpad_selected),
+            (Buttons::LEFT, 0, CELL, CELL, CELL, dpad_selected),
+            (Buttons::RIGHT, 2 * CELL, CELL, CELL, CELL, dpad_selected),
+            (Buttons::DOWN, CELL, 2 * CELL, CELL, CELL, dpad_selected),
+            (Buttons::B, 4 * CELL, CELL, CELL, CELL, buttons_selected),
+            (Buttons::A, 6 * CELL, CELL / 2, CELL, CELL, buttons_selected),
+            (
+                Buttons::SELECT,
+                2 * CELL,
+                3 * CELL + 4,
+                CELL + 4,
+                CELL / 2,
+                buttons_selected,
+            ),
+            (
+                Buttons::START,
+                4 * CELL,
+                3 * CELL + 4,
+                CELL + 4,
+                CELL / 2,
+                buttons_selected,
+            ),
+        ];
+
+        for (button, x, y, key_width, key_height, selected) in keys {
+            let rc = Rect::new(left + x, top + y, key_width as u32, key_height as u32);
+            let color = match (input.buttons.contains(button), selected) {
+                (true, _) => Color::RGB(255, 255, 255),
+                (false, true) => Color::RGB(64, 96, 255),
+                (false, false) => Color::RGB(96, 96, 96),
+            };
+
+            canvas.set_draw_color(color);
+            if input.buttons.contains(button) {
+                let _ = canvas.fill_rect(rc);
+            } else {
+                let _ = canvas.draw_rect(rc);
+            }
+        }
+    }
+
+    /// Disassembly down the left side, the line at PC highlighted, breakpoints
+    /// marked red and the selected line framed.
+    fn draw_disassembly(
+        canvas: &mut Canvas<Window>,
+        view: &DisassemblyView,
+        selected: Option<u16>,
+    ) {
+        let height = YRES as u32 * Self::SCALE;
+
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        let _ = canvas.fill_rect(Rect::new(0, 0, Self::DISASSEMBLY_WIDTH, height));
+
+        for (row, line) in view.lines.iter().enumerate() {
+            let y = Self::DISASSEMBLY_TOP + row as i32 * Self::DISASSEMBLY_LINE_HEIGHT;
+            let rc = Rect::new(
+                0,
+                y - 3,
+                Self::DISASSEMBLY_WIDTH,
+                Self::DISASSEMBLY_LINE_HEIGHT as u32,
+            );
+
+            if line.address == view.pc {
+                canvas.set_draw_color(Color::RGBA(64, 96, 255, 160));
+                let _ = canvas.fill_rect(rc);
+            }
+            if Some(line.address) == selected {
+                canvas.set_draw_color(Color::RGB(255, 255, 255));
+                let _ = canvas.draw_rect(rc);
+            }
+            if view.breakpoints.contains(&line.address) {
+                canvas.set_draw_color(Color::RGB(255, 64, 64));
+                let _ = canvas.fill_rect(Rect::new(4, y, 10, 10));
+            }
+
+            let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{b:02X}")).collect();
+            canvas.set_draw_color(Color::RGB(160, 160, 160));
+            Self::draw_text(canvas, &format!("{:04X}", line.address), 20, y);
+            Self::draw_text(canvas, &bytes.join(""), 60, y);
+            canvas.set_draw_color(Color::RGB(255, 255, 255));
+            Self::draw_text(canvas, &line.text, 116, y);
+        }
+    }
+
+    /// Watch expressions and their values in the top right corner, one per
+    /// line.
+    fn draw_watches(canvas: &mut Canvas<Window>, watches: &[(String, String)]) {
+        let columns = watches
+            .iter()
+            .map(|(text, value)| text.len() + value.len() + 1)
+            .max()
+            .unwrap_or(0) as i32;
+        let width = columns * 8 + 8;
+        let x = (XRES as u32 * Self::SCALE) as i32 - width;
+
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        let _ = canvas.fill_rect(Rect::new(
+            x,
+            0,
+            width as u32,
+            (watches.len() as i32 * Self::DISASSEMBLY_LINE_HEIGHT + 6) as u32,
+        ));
+
+        for (row, (text, value)) in watches.iter().enumerate() {
+            let y = 4 + row as i32 * Self::DISASSEMBLY_LINE_HEIGHT;
+            canvas.set_draw_color(Color::RGB(160, 160, 160));
+            Self::draw_text(canvas, text, x + 4, y);
+            canvas.set_draw_color(Color::RGB(255, 255, 255));
+            Self::draw_text(canvas, value, x + 4 + (text.len() as i32 + 1) * 8, y);
+        }
+    }
+
+    /// Address of the disassembly line under a click at `x`, `y` in window
+    /// coordinates.
+    fn clicked_line(&self, x: i32, y: i32) -> Option<u16> {
+        if self.disassembly_rows.is_empty() {
+            return None;
+        }
+
+        // Window coordinates are in points, the layout in physical pixels
+        let (output_width, _) = self.canvas.output_size().ok()?;
+        let (window_width, _) = self.canvas.window().size();
+        let dpi = output_width as f32 / window_width.max(1) as f32;
+        let (game_rect, _) = self.layout();
+
+        // Undo the turn and the mirroring around the center of the game
+        let center = game_rect.center();
+        let (mut dx, mut dy) = (
+            x as f32 * dpi - center.x() as f32,
+            y as f32 * dpi - center.y() as f32,
+        );
+        for _ in 0..(self.orientation.angle() / 90.0) as u32 {
+            (dx, dy) = (dy, -dx);
+        }
+        if self.orientation.mirror {
+            dx = -dx;
+        }
+
+        let x = (dx / game_rect.width() as f32 + 0.5) * (XRES as u32 * Self::SCALE) as f32;
+        let y = (dy / game_rect.height() as f32 + 0.5) * (YRES as u32 * Self::SCALE) as f32;
+
+        if x < 0.0 || x >= Self::DISASSEMBLY_WIDTH as f32 || y < 0.0 {
+            return None;
+        }
+
+        let row = (y as i32 - Self::DISASSEMBLY_TOP + 3) / Self::DISASSEMBLY_LINE_HEIGHT;
+        self.disassembly_rows.get(row as usize).copied()
+    }
+
+    /// Draw `text` with the overlay glyphs, lowercase as uppercase. Characters
+    /// without a glyph are left blank.
+    fn draw_text(canvas: &mut Canvas<Window>, text: &str, x: i32, y: i32) {
+        for (i, c) in text.chars().enumerate() {
+            let c = c.to_ascii_uppercase();
+            let glyph = match c.to_digit(10) {
+                Some(digit) => DIGITS[digit as usize],
+                None => GLYPHS
+                    .iter()
+                    .find(|(glyph_char, _)| *glyph_char == c)
+                    .map_or(0, |(_, glyph)| *glyph),
+            };
+
+            Self::draw_glyph(canvas, glyph, x + i as i32 * 8, y);
+        }
+    }
+
+    /// Draw `value` with the overlay digits, top left corner at `x`, `y` in window pixels.
+    fn draw_number(canvas: &mut Canvas<Window>, value: u32, x: i32, y: i32) {
+        let digits = value.to_string();
+
+        for (i, digit) in digits.bytes().enumerate() {
+            let glyph = DIGITS[(digit - b'0') as usize];
+            Self::draw_glyph(canvas, glyph, x + (i as i32) * 8, y);
+        }
+    }
+
+    /// One 3x5 glyph at twice the size, 6x10 window pixels.
+    fn draw_glyph(canvas: &mut Canvas<Window>, glyph: u32, x: i32, y: i32) {
+        for row in 0..5 {
+            for column in 0..3 {
+                if glyph >> ((4 - row) * 4 + (2 - column)) & 1 != 0 {
+                    let _ = canvas.fill_rect(Rect::new(x + column * 2, y + row * 2, 2, 2));
+                }
+            }
+        }
+    }
+
+    /// Draw the tiles in `tiles`, the tile data area of VRAM starting at 0x8000.
+    ///
+    /// Only tiles in `dirty_tiles` are decoded again, the rest of the viewer
+    /// is kept from earlier frames. Those tiles are also tinted.
+    pub fn update_debug_window(&mut self, tiles: &[u8], dirty_tiles: &TileSet) {
+        if self.debug_canvas.is_none() {
+            return;
+        }
+
+        for tile_num in dirty_tiles.iter() {
+            self.display_tile(tiles, tile_num);
+        }
+
+        let (Some(canvas), Some(texture)) = (&mut self.debug_canvas, &mut self.debug_texture)
+        else {
+            return;
+        };
+        let bytes: Vec<u8> = self
+            .debug_pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_ne_bytes())
+            .collect();
+
+        texture
+            .update(None, &bytes, (Self::DEBUG_IMAGE_WIDTH * 4) as usize)
+            .unwrap();
+        let rc = Rect::new(
+            0,
+            0,
+            Self::DEBUG_IMAGE_WIDTH * Self::SCALE,
+            Self::DEBUG_IMAGE_HEIGHT * Self::SCALE,
+        );
+        canvas.copy(texture, None, rc).unwrap();
+
+        for tile_num in dirty_tiles.iter() {
+            self.highlight_tile(tile_num);
+        }
+
+        self.debug_canvas.as_mut().unwrap().present();
+    }
+
+    /// Top left corner of a tile in the tile viewer image.
+    fn debug_tile_position(tile_num: usize) -> (usize, usize) {
+        let columns = Self::DEBUG_SCREEN_WIDTH as usize;
+        ((tile_num % columns) * 9, (tile_num / columns) * 9)
+    }
+
+    /// Tint a tile written during the last frame.
+    fn highlight_tile(&mut self, tile_num: usize) {
+        let canvas = self.debug_canvas.as_mut().unwrap();
+        let (x, y) = Self::debug_tile_position(tile_num);
+        let scale = Self::SCALE as i32;
+        let rc = Rect::new(
+            x as i32 * scale,
+            y as i32 * scale,
+            8 * Self::SCALE,
+            8 * Self::SCALE,
+        );
+
+        canvas.set_draw_color(Color::RGBA(255, 0, 0, 96));
+        let _ = canvas.fill_rect(rc);
+    }
+
+    fn display_tile(&mut self, tiles: &[u8], tile_num: usize) {
+        let (x, y) = Self::debug_tile_position(tile_num);
+
+        let data: &[u8; 16] = tiles[tile_num * 16..][..16].try_into().unwrap();
+
+        for (row, indices) in vram::decode_tile(data).iter().enumerate() {
+            let offset = (y + row) * Self::DEBUG_IMAGE_WIDTH as usize + x;
+            for (pixel, index) in self.debug_pixels[offset..offset + 8]
+                .iter_mut()
+                .zip(indices)
+            {
+                *pixel = DEFAULT_COLORS[*index as usize];
+            }
+        }
+    }
+}
+
+// Convert from ARGB to SDL2::Color
+fn color_from_u32(color: u32) -> Color {
+    let a = ((color >> 24) & 0xFF) as u8;
+    let r = ((color >> 16) & 0xFF) as u8;
+    let g = ((color >> 8) & 0xFF) as u8;
+    let b = (color & 0xFF) as u8;
+
+    Color::RGBA(r, g, b, a)
+}
@@ -0,0 +1,373 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use sdl2::keyboard::{Keycode, Mod};
+
+use crate::config::config_dir;
+
+/// Emulator functions bound to a key.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Hotkey {
+    Quit,
+    SaveState,
+    LoadState,
+    /// Put back what the last state save or load replaced
+    UndoState,
+    /// Ask for the keys of the joypad buttons and save them
+    RemapControls,
+    /// Run without frame limiting while held
+    Turbo,
+    Pause,
+    Screenshot,
+    /// Run backwards while held
+    Rewind,
+    /// Pause and go back a frame
+    FrameBack,
+    Fullscreen,
+    ToggleBackground,
+    ToggleWindow,
+    ToggleSprites,
+    /// Tile grid, window origin and sprite boxes over the game
+    ToggleOverlay,
+    /// Tint pixels by the layer that won priority
+    TogglePriority,
+    /// Tile sheet and background map as PNG files next to the ROM
+    ExportVram,
+    /// Frametime graph and FPS over the game
+    ToggleStats,
+    /// Buttons held and the joypad register over the game
+    ToggleInput,
+    /// Restart the game keeping RAM
+    SoftReset,
+    /// Power cycle
+    HardReset,
+    /// Menu with the common actions
+    Menu,
+    /// Pick a ROM in a file dialog and switch to it
+    OpenRom,
+    /// Resize the window to a multiple of the screen, 1 to 6
+    WindowScale(u32),
+    /// Scale by whole pixels or fill the window
+    TogglePixelPerfect,
+    /// Turn the screen a quarter clockwise
+    Rotate,
+    /// Mirror the screen left to right
+    ToggleMirror,
+    /// Switch to the next palette preset
+    CyclePalette,
+    /// Mix every frame with the one before against flicker
+    ToggleBlend,
+    /// Select the previous line of the disassembly shown while paused
+    DebuggerUp,
+    /// Select the next line of the disassembly
+    DebuggerDown,
+    /// Set or clear a breakpoint on the selected line
+    ToggleBreakpoint,
+}
+
+impl Hotkey {
+    const ALL: [Hotkey; 37] = [
+        Hotkey::Quit,
+        Hotkey::SaveState,
+        Hotkey::LoadState,
+        Hotkey::UndoState,
+        Hotkey::RemapControls,
+        Hotkey::Turbo,
+        Hotkey::Pause,
+        Hotkey::Screenshot,
+        Hotkey::Rewind,
+        Hotkey::FrameBack,
+        Hotkey::Fullscreen,
+        Hotkey::ToggleBackground,
+        Hotkey::ToggleWindow,
+        Hotkey::ToggleSprites,
+        Hotkey::ToggleOverlay,
+        Hotkey::TogglePriority,
+        Hotkey::ExportVram,
+        Hotkey::ToggleStats,
+        Hotkey::ToggleInput,
+        Hotkey::SoftReset,
+        Hotkey::HardReset,
+        Hotkey::Menu,
+        Hotkey::OpenRom,
+        Hotkey::WindowScale(1),
+        Hotkey::WindowScale(2),
+        Hotkey::WindowScale(3),
+        Hotkey::WindowScale(4),
+        Hotkey::WindowScale(5),
+        Hotkey::WindowScale(6),
+        Hotkey::TogglePixelPerfect,
+        Hotkey::Rotate,
+        Hotkey::ToggleMirror,
+        Hotkey::CyclePalette,
+        Hotkey::ToggleBlend,
+        Hotkey::DebuggerUp,
+        Hotkey::DebuggerDown,
+        Hotkey::ToggleBreakpoint,
+    ];
+
+    /// Name used in the hotkey configuration file.
+    fn name(self) -> &'static str {
+        match self {
+            Hotkey::Quit => "quit",
+            Hotkey::SaveState => "save_state",
+            Hotkey::LoadState => "load_state",
+            Hotkey::UndoState => "undo_state",
+            Hotkey::RemapControls => "remap_controls",
+            Hotkey::Turbo => "turbo",
+            Hotkey::Pause => "pause",
+            Hotkey::Screenshot => "screenshot",
+            Hotkey::Rewind => "rewind",
+            Hotkey::FrameBack => "frame_back",
+            Hotkey::Fullscreen => "fullscreen",
+            Hotkey::ToggleBackground => "toggle_background",
+            Hotkey::ToggleWindow => "toggle_window",
+            Hotkey::ToggleSprites => "toggle_sprites",
+            Hotkey::ToggleOverlay => "toggle_overlay",
+            Hotkey::TogglePriority => "toggle_priority",
+            Hotkey::ExportVram => "export_vram",
+            Hotkey::ToggleStats => "toggle_stats",
+            Hotkey::ToggleInput => "toggle_input",
+            Hotkey::SoftReset => "soft_reset",
+            Hotkey::HardReset => "hard_reset",
+            Hotkey::Menu => "menu",
+            Hotkey::OpenRom => "open_rom",
+            Hotkey::WindowScale(scale) => [
+                "scale_1", "scale_2", "scale_3", "scale_4", "scale_5", "scale_6",
+            ][scale as usize - 1],
+            Hotkey::TogglePixelPerfect => "toggle_pixel_perfect",
+            Hotkey::Rotate => "rotate",
+            Hotkey::ToggleMirror => "toggle_mirror",
+            Hotkey::CyclePalette => "cycle_palette",
+            Hotkey::ToggleBlend => "toggle_blend",
+            Hotkey::DebuggerUp => "debugger_up",
+            Hotkey::DebuggerDown => "debugger_down",
+            Hotkey::ToggleBreakpoint => "toggle_breakpoint",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|hotkey| hotkey.name() == name)
+    }
+}
+
+/// A key with the modifiers that must be held with it, e.g. Shift+F1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeyChord {
+    keycode: Keycode,
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+}
+
+impl KeyChord {
+    pub const fn new(keycode: Keycode) -> Self {
+        KeyChord {
+            keycode,
+            shift: false,
+            ctrl: false,
+            alt: false,
+        }
+    }
+
+    pub const fn with_shift(mut self) -> Self {
+        self.shift = true;
+        self
+    }
+
+    pub const fn with_ctrl(mut self) -> Self {
+        self.ctrl = true;
+        self
+    }
+
+    pub const fn with_alt(mut self) -> Self {
+        self.alt = true;
+        self
+    }
+
+    fn from_event(keycode: Keycode, keymod: Mod) -> Self {
+        KeyChord {
+            keycode,
+            shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        }
+    }
+
+    /// Parse `Shift+F1`, `Ctrl+Alt+S` or a plain SDL key name like `Tab`.
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let mut chord = KeyChord::new(Keycode::from_name(parts.pop()?)?);
+
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "shift" => chord.shift = true,
+                "ctrl" => chord.ctrl = true,
+                "alt" => chord.alt = true,
+                _ => return None,
+            }
+        }
+
+        Some(chord)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+
+        write!(f, "{}", self.keycode.name())
+    }
+}
+
+/// Maps key chords to hotkeys.
+///
+/// Defaults can be overridden in `hotkeys.cfg` in the configuration
+/// directory, one `name = chord` per line, e.g. `save_state = Shift+F1`.
+pub struct Hotkeys {
+    bindings: Vec<(KeyChord, Hotkey)>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Hotkeys {
+            bindings: vec![
+                (KeyChord::new(Keycode::Escape), Hotkey::Quit),
+                (KeyChord::new(Keycode::F1).with_shift(), Hotkey::SaveState),
+                (KeyChord::new(Keycode::F1), Hotkey::LoadState),
+                (KeyChord::new(Keycode::F1).with_ctrl(), Hotkey::UndoState),
+                (KeyChord::new(Keycode::Tab), Hotkey::Turbo),
+                (KeyChord::new(Keycode::P), Hotkey::Pause),
+                (KeyChord::new(Keycode::F12), Hotkey::Screenshot),
+                (KeyChord::new(Keycode::R), Hotkey::Rewind),
+                (KeyChord::new(Keycode::R).with_shift(), Hotkey::FrameBack),
+                (KeyChord::new(Keycode::F11), Hotkey::Fullscreen),
+                (KeyChord::new(Keycode::F2), Hotkey::ToggleBackground),
+                (KeyChord::new(Keycode::F3), Hotkey::ToggleWindow),
+                (KeyChord::new(Keycode::F4), Hotkey::ToggleSprites),
+                (KeyChord::new(Keycode::F5), Hotkey::ToggleOverlay),
+                (
+                    KeyChord::new(Keycode::F5).with_shift(),
+                    Hotkey::TogglePriority,
+                ),
+                (KeyChord::new(Keycode::F6), Hotkey::ExportVram),
+                (KeyChord::new(Keycode::F7), Hotkey::ToggleStats),
+                (KeyChord::new(Keycode::F7).with_shift(), Hotkey::ToggleInput),
+                (KeyChord::new(Keycode::F8), Hotkey::SoftReset),
+                (KeyChord::new(Keycode::F8).with_shift(), Hotkey::HardReset),
+                (KeyChord::new(Keycode::F10), Hotkey::Menu),
+                (KeyChord::new(Keycode::O).with_ctrl(), Hotkey::OpenRom),
+                (
+                    KeyChord::new(Keycode::Num1).with_alt(),
+                    Hotkey::WindowScale(1),
+                ),
+                (
+                    KeyChord::new(Keycode::Num2).with_alt(),
+                    Hotkey::WindowScale(2),
+                ),
+                (
+                    KeyChord::new(Keycode::Num3).with_alt(),
+                    Hotkey::WindowScale(3),
+                ),
+                (
+                    KeyChord::new(Keycode::Num4).with_alt(),
+                    Hotkey::WindowScale(4),
+                ),
+                (
+                    KeyChord::new(Keycode::Num5).with_alt(),
+                    Hotkey::WindowScale(5),
+                ),
+                (
+                    KeyChord::new(Keycode::Num6).with_alt(),
+                    Hotkey::WindowScale(6),
+                ),
+                (KeyChord::new(Keycode::F9), Hotkey::TogglePixelPerfect),
+                (KeyChord::new(Keycode::R).with_ctrl(), Hotkey::Rotate),
+                (KeyChord::new(Keycode::M).with_ctrl(), Hotkey::ToggleMirror),
+                (KeyChord::new(Keycode::P).with_ctrl(), Hotkey::CyclePalette),
+                (KeyChord::new(Keycode::F9).with_shift(), Hotkey::ToggleBlend),
+                (KeyChord::new(Keycode::Up).with_shift(), Hotkey::DebuggerUp),
+                (
+                    KeyChord::new(Keycode::Down).with_shift(),
+                    Hotkey::DebuggerDown,
+                ),
+                (
+                    KeyChord::new(Keycode::B).with_ctrl(),
+                    Hotkey::ToggleBreakpoint,
+                ),
+            ],
+        }
+    }
+}
+
+impl Hotkeys {
+    /// Default bindings with the ones from the configuration file applied.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let mut hotkeys = Hotkeys::default();
+        let Some(path) = config_dir().map(|dir| dir.join("hotkeys.cfg")) else {
+            return Ok(hotkeys);
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(hotkeys),
+            Err(e) => return Err(e.into()),
+        };
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, chord) = line.split_once('=').ok_or("Invalid hotkey line")?;
+            let hotkey = Hotkey::from_name(name.trim())
+                .ok_or_else(|| format!("Unknown hotkey {}", name.trim()))?;
+            let chord =
+                KeyChord::parse(chord).ok_or_else(|| format!("Invalid key {}", chord.trim()))?;
+            hotkeys.bind(chord, hotkey);
+        }
+
+        Ok(hotkeys)
+    }
+
+    /// Bind `hotkey` to `chord`, replacing its previous binding.
+    pub fn bind(&mut self, chord: KeyChord, hotkey: Hotkey) {
+        self.bindings
+            .retain(|(bound_chord, bound_hotkey)| *bound_hotkey != hotkey && *bound_chord != chord);
+        self.bindings.push((chord, hotkey));
+    }
+
+    /// Hotkey triggered by pressing `keycode` with `keymod` held.
+    pub fn pressed(&self, keycode: Keycode, keymod: Mod) -> Option<Hotkey> {
+        let chord = KeyChord::from_event(keycode, keymod);
+
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == chord)
+            .map(|(_, hotkey)| *hotkey)
+    }
+
+    /// Hotkeys released with `keycode`, modifiers may already be up.
+    pub fn released(&self, keycode: Keycode) -> impl Iterator<Item = Hotkey> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(bound, _)| bound.keycode == keycode)
+            .map(|(_, hotkey)| *hotkey)
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = (Hotkey, KeyChord)> + '_ {
+        self.bindings
+            .iter()
+            .map(|(chord, hotkey)| (*hotkey, *chord))
+    }
+}
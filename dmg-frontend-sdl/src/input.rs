@@ -0,0 +1,320 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use dmg_core::joypad::Buttons;
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+
+use crate::config::config_dir;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputSource {
+    Keyboard,
+    Controller,
+}
+
+/// Keys held on the host as `Buttons`.
+///
+/// The GUI thread updates it as soon as an event arrives and the emulation
+/// thread takes a snapshot at VBlank, neither waits for the emulator lock.
+/// Each source is kept apart so that unplugging a controller doesn't release
+/// keys held on the keyboard.
+#[derive(Clone, Default)]
+pub struct InputState {
+    // `Buttons` bits of each source
+    keyboard: Arc<AtomicU8>,
+    controller: Arc<AtomicU8>,
+    // Orientation packed as in `Orientation::bits`
+    orientation: Arc<AtomicU8>,
+}
+
+/// Rotation and mirroring of the shown screen.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Orientation {
+    /// Clockwise quarter turns, 0 to 3
+    pub quarter_turns: u8,
+    /// Mirrored left to right, before turning
+    pub mirror: bool,
+}
+
+impl Orientation {
+    /// Parse a clockwise rotation in degrees, `0`, `90`, `180` or `270`.
+    pub fn parse_rotation(text: &str) -> Option<u8> {
+        match text {
+            "0" => Some(0),
+            "90" => Some(1),
+            "180" => Some(2),
+            "270" => Some(3),
+            _ => None,
+        }
+    }
+
+    /// The next quarter turn clockwise.
+    pub fn rotated(self) -> Self {
+        Orientation {
+            quarter_turns: (self.quarter_turns + 1) % 4,
+            ..self
+        }
+    }
+
+    pub fn angle(self) -> f64 {
+        self.quarter_turns as f64 * 90.0
+    }
+
+    /// Turned by 90 or 270 degrees.
+    pub fn is_sideways(self) -> bool {
+        self.quarter_turns % 2 == 1
+    }
+
+    /// Buttons for the game from directions pressed as seen on the screen.
+    pub fn remap(self, buttons: Buttons) -> Buttons {
+        // Directions clockwise, a quarter turn moves a direction one place on
+        const CLOCKWISE: [Buttons; 4] = [Buttons::UP, Buttons::RIGHT, Buttons::DOWN, Buttons::LEFT];
+        let mut remapped = buttons - Buttons::DPAD;
+
+        for (i, direction) in CLOCKWISE.into_iter().enumerate() {
+            if !buttons.contains(direction) {
+                continue;
+            }
+
+            let direction = CLOCKWISE[(i + 4 - self.quarter_turns as usize) % 4];
+            remapped |= match direction {
+                Buttons::LEFT if self.mirror => Buttons::RIGHT,
+                Buttons::RIGHT if self.mirror => Buttons::LEFT,
+                _ => direction,
+            };
+        }
+
+        remapped
+    }
+
+    fn bits(self) -> u8 {
+        self.quarter_turns | (self.mirror as u8) << 2
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        Orientation {
+            quarter_turns: bits & 3,
+            mirror: bits & 4 != 0,
+        }
+    }
+}
+
+impl InputState {
+    fn source(&self, source: InputSource) -> &AtomicU8 {
+        match source {
+            InputSource::Keyboard => &self.keyboard,
+            InputSource::Controller => &self.controller,
+        }
+    }
+
+    pub fn press(&self, source: InputSource, buttons: Buttons) {
+        self.source(source)
+            .fetch_or(buttons.bits(), Ordering::Relaxed);
+    }
+
+    pub fn release(&self, source: InputSource, buttons: Buttons) {
+        self.source(source)
+            .fetch_and(!buttons.bits(), Ordering::Relaxed);
+    }
+
+    /// Release everything held on `source`.
+    pub fn clear(&self, source: InputSource) {
+        self.source(source).store(0, Ordering::Relaxed);
+    }
+
+    /// Turn directions so they match the screen as shown.
+    pub fn set_orientation(&self, orientation: Orientation) {
+        self.orientation
+            .store(orientation.bits(), Ordering::Relaxed);
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        Orientation::from_bits(self.orientation.load(Ordering::Relaxed))
+    }
+
+    /// Keys held on all sources as the game sees them.
+    pub fn snapshot(&self) -> Buttons {
+        let bits = self.keyboard.load(Ordering::Relaxed) | self.controller.load(Ordering::Relaxed);
+
+        self.orientation().remap(Buttons::from_bits_retain(bits))
+    }
+}
+
+/// Maps keyboard keys to joypad buttons, a key per button.
+///
+/// Defaults can be overridden in `controls.cfg` in the configuration
+/// directory, one `button = key` per line with SDL key names, e.g. `a = X`.
+/// The Controls entry of the menu writes the file.
+#[derive(Clone)]
+pub struct KeyMap {
+    keys: Vec<(Buttons, Keycode)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap {
+            keys: vec![
+                (Buttons::UP, Keycode::Up),
+                (Buttons::DOWN, Keycode::Down),
+                (Buttons::LEFT, Keycode::Left),
+                (Buttons::RIGHT, Keycode::Right),
+                (Buttons::A, Keycode::X),
+                (Buttons::B, Keycode::Z),
+                (Buttons::SELECT, Keycode::Backspace),
+                (Buttons::START, Keycode::Return),
+            ],
+        }
+    }
+}
+
+impl KeyMap {
+    /// Buttons with their names in `controls.cfg`, in the order they are asked for.
+    pub const BUTTONS: [(Buttons, &str); 8] = [
+        (Buttons::UP, "up"),
+        (Buttons::DOWN, "down"),
+        (Buttons::LEFT, "left"),
+        (Buttons::RIGHT, "right"),
+        (Buttons::A, "a"),
+        (Buttons::B, "b"),
+        (Buttons::SELECT, "select"),
+        (Buttons::START, "start"),
+    ];
+
+    fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("controls.cfg"))
+    }
+
+    /// Default keys with the ones from the configuration file applied.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let mut keymap = KeyMap::default();
+        let Some(path) = Self::path() else {
+            return Ok(keymap);
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(keymap),
+            Err(e) => return Err(e.into()),
+        };
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, key) = line.split_once('=').ok_or("Invalid control line")?;
+            let (button, _) = Self::BUTTONS
+                .into_iter()
+                .find(|(_, button_name)| *button_name == name.trim())
+                .ok_or_else(|| format!("Unknown button {}", name.trim()))?;
+            let keycode = Keycode::from_name(key.trim())
+                .ok_or_else(|| format!("Invalid key {}", key.trim()))?;
+            keymap.bind(button, keycode);
+        }
+
+        Ok(keymap)
+    }
+
+    /// Write every key to the configuration file, returns its path.
+    pub fn save(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let path = Self::path().ok_or("No configuration directory")?;
+        let mut text = String::from("# Keys of the joypad buttons, written by the Controls menu\n");
+
+        for (button, name) in Self::BUTTONS {
+            if let Some(keycode) = self.key(button) {
+                text += &format!("{name} = {}\n", keycode.name());
+            }
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, text)?;
+        Ok(path)
+    }
+
+    /// Bind `button` to `keycode`, replacing the previous key of the button
+    /// and whatever the key was bound to.
+    pub fn bind(&mut self, button: Buttons, keycode: Keycode) {
+        self.keys
+            .retain(|(bound_button, bound_key)| *bound_button != button && *bound_key != keycode);
+        self.keys.push((button, keycode));
+    }
+
+    /// Joypad button of a keyboard key.
+    pub fn button(&self, keycode: Keycode) -> Option<Buttons> {
+        self.keys
+            .iter()
+            .find(|(_, key)| *key == keycode)
+            .map(|(button, _)| *button)
+    }
+
+    pub fn key(&self, button: Buttons) -> Option<Keycode> {
+        self.keys
+            .iter()
+            .find(|(bound, _)| *bound == button)
+            .map(|(_, key)| *key)
+    }
+}
+
+/// Joypad button of a game controller button.
+pub fn controller_button(button: Button) -> Option<Buttons> {
+    let button = match button {
+        Button::DPadRight => Buttons::RIGHT,
+        Button::DPadLeft => Buttons::LEFT,
+        Button::DPadUp => Buttons::UP,
+        Button::DPadDown => Buttons::DOWN,
+        Button::A => Buttons::A,
+        Button::B => Buttons::B,
+        Button::Back => Buttons::SELECT,
+        Button::Start => Buttons::START,
+        _ => return None,
+    };
+
+    Some(button)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remap_turns_directions_with_the_screen() {
+        let upright = Orientation::default();
+        assert_eq!(
+            upright.remap(Buttons::UP | Buttons::A),
+            Buttons::UP | Buttons::A
+        );
+
+        // The top of the game is on the right of the screen
+        let clockwise = Orientation {
+            quarter_turns: 1,
+            mirror: false,
+        };
+        assert_eq!(clockwise.remap(Buttons::RIGHT), Buttons::UP);
+        assert_eq!(
+            clockwise.remap(Buttons::UP | Buttons::B),
+            Buttons::LEFT | Buttons::B
+        );
+
+        let mirrored = Orientation {
+            quarter_turns: 0,
+            mirror: true,
+        };
+        assert_eq!(mirrored.remap(Buttons::LEFT), Buttons::RIGHT);
+        assert_eq!(mirrored.remap(Buttons::DOWN), Buttons::DOWN);
+
+        // Half a turn of a mirrored screen only swaps up and down
+        let both = Orientation {
+            quarter_turns: 2,
+            mirror: true,
+        };
+        assert_eq!(both.remap(Buttons::DOWN), Buttons::UP);
+        assert_eq!(both.remap(Buttons::LEFT), Buttons::LEFT);
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Mutex;
+
+/// Where a run of the frontend is.
+///
+/// ```text
+/// Running <-> Paused
+///    |          |
+///    +--> RomLoadError --> back to Running or Paused
+///    |          |
+///    +----------+--> Stopped
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunState {
+    /// The emulation thread produces frames
+    Running,
+    /// Frozen on the last frame, by the player, a breakpoint or a hang
+    Paused,
+    /// A ROM failed to load and the message waits to be shown, the game
+    /// stays frozen until it is dismissed
+    RomLoadError { message: String, paused: bool },
+    /// The run is over, the emulation thread ends
+    Stopped,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transition {
+    Pause,
+    Resume,
+    TogglePause,
+    LoadFailed(String),
+    /// The load error was shown
+    Dismiss,
+    Stop,
+}
+
+impl RunState {
+    /// The state `transition` leads to, None when it doesn't apply here.
+    pub fn next(&self, transition: &Transition) -> Option<RunState> {
+        match (self, transition) {
+            (RunState::Stopped, _) => None,
+            (_, Transition::Stop) => Some(RunState::Stopped),
+            (RunState::Running, Transition::Pause | Transition::TogglePause) => {
+                Some(RunState::Paused)
+            }
+            (RunState::Paused, Transition::Resume | Transition::TogglePause) => {
+                Some(RunState::Running)
+            }
+            (RunState::Running | RunState::Paused, Transition::LoadFailed(message)) => {
+                Some(RunState::RomLoadError {
+                    message: message.clone(),
+                    paused: *self == RunState::Paused,
+                })
+            }
+            (RunState::RomLoadError { paused, .. }, Transition::Dismiss) => Some(if *paused {
+                RunState::Paused
+            } else {
+                RunState::Running
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// `RunState` shared by the GUI and the emulation thread.
+///
+/// Every change goes through `apply`, the one place to hook pause menus,
+/// ROM reloads or error dialogs onto.
+pub struct Lifecycle {
+    state: Mutex<RunState>,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Lifecycle {
+            state: Mutex::new(RunState::Running),
+        }
+    }
+
+    pub fn state(&self) -> RunState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Make `transition`, returns the new state or None if it didn't apply.
+    pub fn apply(&self, transition: Transition) -> Option<RunState> {
+        let mut state = self.state.lock().unwrap();
+        let next = state.next(&transition)?;
+        *state = next.clone();
+        Some(next)
+    }
+
+    pub fn is_running(&self) -> bool {
+        *self.state.lock().unwrap() == RunState::Running
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.state.lock().unwrap() == RunState::Paused
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.state.lock().unwrap() == RunState::Stopped
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Lifecycle::new()
+    }
+}
@@ -0,0 +1,2196 @@
+mod commands;
+mod config;
+mod console;
+mod dialog;
+mod gui;
+mod hotkeys;
+mod input;
+mod lifecycle;
+mod remote;
+mod render;
+mod rumble;
+mod script;
+mod session_log;
+mod spectate;
+mod trace;
+
+use std::collections::BTreeSet;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, mpsc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dmg_core::camera;
+use dmg_core::cart::Cartridge;
+use dmg_core::cheats::{Cheat, CheatList};
+use dmg_core::cpu::{CPU, CpuContext, OpcodeCoverage, TraceConfig};
+use dmg_core::desync::{CHECKSUM_INTERVAL, ChecksumStream};
+use dmg_core::emu::{AccuracyConfig, AccuracyLevel, CLOCK_HZ, Emulator, RestrictedWrites};
+use dmg_core::frame::{Frame, Palette};
+use dmg_core::headless::{self, Condition, RunOutcome};
+use dmg_core::interrupts;
+use dmg_core::joypad::Buttons;
+use dmg_core::mapper_log::MapperLog;
+use dmg_core::mbc::RtcClock;
+use dmg_core::peripherals::{PeripheralError, PeripheralRegistry};
+use dmg_core::placeholder::placeholder_rom;
+use dmg_core::png;
+use dmg_core::polling::PollCounter;
+use dmg_core::power::{BOOT_ROM_SIZE, Model, RamInit};
+use dmg_core::ppu::{Layers, SPRITES_PER_LINE};
+use dmg_core::profiler::Profiler;
+use dmg_core::rewind::RewindBuffer;
+use dmg_core::romdb::RomDatabase;
+use dmg_core::serial::{SerialCapture, SerialDevice};
+use dmg_core::state;
+use dmg_core::stats::{Pacing, PacingMode, SleepStrategy, SpeedReport, TARGET_FRAME_TIME};
+use dmg_core::storage::{FileStorage, StorageBackend};
+use dmg_core::symbols::SymbolTable;
+use dmg_core::vram;
+use dmg_core::warnings::WarningLog;
+use dmg_core::watch::Watch;
+use dmg_core::watchdog::HangWatchdog;
+
+use console::{Command, Console};
+use gui::{GUI, GuiAction, MenuItem};
+use hotkeys::{Hotkey, Hotkeys};
+use input::{InputState, KeyMap, Orientation};
+use lifecycle::{Lifecycle, RunState, Transition};
+use render::{DisassemblyView, FrameSnapshot, triple_buffer};
+use rumble::{Rumble, RumbleEvent, parse_rumble};
+use script::{ExitConditions, ExitReason, ExitWatch, InputScript};
+use session_log::SessionLog;
+use spectate::{HostInput, HostState, Spectator, SpectatorHost};
+use trace::{TraceFormat, TraceWriter};
+
+// Memory for the states rewinding goes back to, a state is taken every frame
+const REWIND_MEMORY: usize = 32 * 1024 * 1024;
+
+struct Options {
+    // None shows the placeholder screen until a ROM is opened
+    rom_file: Option<String>,
+    rtc_clock: RtcClock,
+    // Codes to add to the cheats of the game
+    cheats: Vec<String>,
+    // Joystick index of the controller for the joypad
+    controller: Option<u32>,
+    exit: ExitConditions,
+    // Files for the last frame and the serial output when the run ends
+    dump_frame: Option<PathBuf>,
+    dump_serial: Option<PathBuf>,
+    // Files for the tile sheet and background map when the run ends
+    dump_tiles: Option<PathBuf>,
+    dump_bg_map: Option<PathBuf>,
+    // Print the hash of every Nth frame
+    hash_frames: Option<u32>,
+    // File for a checksum of the machine every second, `-` prints them
+    checksums: Option<PathBuf>,
+    // Joypad events to replay, `-` reads them from stdin
+    input_script: Option<String>,
+    // loopback, stdout, log:FILE or replay:FILE
+    serial: Option<String>,
+    // File recording every serial exchange
+    serial_capture: Option<PathBuf>,
+    // ROM database, defaults to gb.dat in the configuration directory
+    dat: Option<PathBuf>,
+    // File the executed opcodes are merged into
+    coverage: Option<PathBuf>,
+    // File for the instructions reading LY and STAT most, `-` prints them
+    poll_report: Option<PathBuf>,
+    // File for the homebrew diagnostics, `-` prints them
+    warnings: Option<PathBuf>,
+    // File for the writes to the mapper registers, `-` prints them
+    mapper_log: Option<PathBuf>,
+    // File for a line per executed instruction, `-` prints them
+    trace: Option<PathBuf>,
+    trace_format: TraceFormat,
+    // Only trace instructions in START-END
+    trace_range: Option<String>,
+    // Address or interrupt handler that turns the trace on
+    trace_from: Option<String>,
+    // Also trace the memory accesses of the traced instructions
+    trace_memory: bool,
+    // JSON file for the cycles and calls per routine
+    profile: Option<PathBuf>,
+    // Labels of the ROM for the profile, defaults to the .sym file next to it
+    symbols: Option<PathBuf>,
+    // Stop when code runs from an unmapped ROM bank or disabled external RAM
+    bank_guard: bool,
+    // Stop when code runs outside HRAM during OAM DMA
+    dma_guard: bool,
+    // Stop when a push or pop reaches outside WRAM and HRAM
+    stack_guard: bool,
+    // Warn on writes to the vectors and header that no mapper takes
+    header_guard: bool,
+    // Switch ROM only cartridges that select a ROM bank to MBC1
+    infer_mapper: bool,
+    // Log or pause on VRAM and OAM writes the PPU would block
+    restricted_writes: RestrictedWrites,
+    accuracy: AccuracyLevel,
+    // Sprites per line, more than the hardware's 10 isn't accurate
+    sprite_limit: usize,
+    // Show the frame after the current one, rolled back each frame
+    runahead: bool,
+    // Whether frames take their real time or one display refresh
+    pacing: PacingMode,
+    // How the frame limiter waits: sleeping, spinning the end or for vsync
+    sleep: SleepStrategy,
+    // Never limit the frame rate and report the speed reached at the end
+    max_speed: bool,
+    model: Model,
+    // zero, random, random:SEED or pattern(0xNN)
+    ram_init: Option<String>,
+    // 256 byte DMG boot ROM to run before the game
+    boot_rom: Option<PathBuf>,
+    // Run the boot ROM unthrottled and without showing it
+    fast_boot: bool,
+    // Rotation and mirroring of the screen, directions follow it
+    orientation: Orientation,
+    // Preset name or .pal file
+    palette: Option<String>,
+    // Mix every frame with the one before
+    blend: bool,
+    // Addresses or interrupt handlers like vblank-handler to pause at
+    breakpoints: Vec<String>,
+    // Expressions shown next to the game, like [C0A5] or HL
+    watches: Vec<String>,
+    // Events that rumble the controller: state-error, break or EXPRESSION=VALUE
+    rumble: Vec<String>,
+    // Savestate or JSON snapshot to start from
+    load_state: Option<PathBuf>,
+    // Save the machine when the run ends and resume from it next time
+    auto_state: bool,
+    // Directory for battery saves and states instead of next to the ROM
+    storage_dir: Option<PathBuf>,
+    // Frames without a change in the picture and executed code until a hang is reported
+    watchdog: Option<u32>,
+    // Address to stream the input to spectators from
+    host_spectators: Option<String>,
+    // Address of a session to watch
+    spectate: Option<String>,
+    // PNG image the Pocket Camera sees
+    camera_image: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Option<Self> {
+        let mut rom_file = None;
+        let mut rtc_clock = RtcClock::WallClock;
+        let mut cheats = Vec::new();
+        let mut controller = None;
+        let mut exit = ExitConditions::default();
+        let mut dump_frame = None;
+        let mut dump_serial = None;
+        let mut dump_tiles = None;
+        let mut dump_bg_map = None;
+        let mut hash_frames = None;
+        let mut checksums = None;
+        let mut input_script = None;
+        let mut serial = None;
+        let mut serial_capture = None;
+        let mut dat = None;
+        let mut coverage = None;
+        let mut poll_report = None;
+        let mut warnings = None;
+        let mut mapper_log = None;
+        let mut trace = None;
+        let mut trace_format = TraceFormat::Text;
+        let mut trace_range = None;
+        let mut trace_from = None;
+        let mut trace_memory = false;
+        let mut profile = None;
+        let mut symbols = None;
+        let mut bank_guard = false;
+        let mut dma_guard = false;
+        let mut stack_guard = false;
+        let mut header_guard = false;
+        let mut infer_mapper = false;
+        let mut restricted_writes = RestrictedWrites::Allow;
+        let mut accuracy = AccuracyLevel::Balanced;
+        let mut sprite_limit = SPRITES_PER_LINE;
+        let mut runahead = false;
+        let mut pacing = PacingMode::Refresh;
+        let mut sleep = SleepStrategy::Sleep;
+        let mut max_speed = false;
+        let mut model = Model::Dmg;
+        let mut ram_init = None;
+        let mut boot_rom = None;
+        let mut fast_boot = false;
+        let mut orientation = Orientation::default();
+        let mut palette = None;
+        let mut blend = false;
+        let mut breakpoints = Vec::new();
+        let mut watches = Vec::new();
+        let mut rumble = Vec::new();
+        let mut load_state = None;
+        let mut auto_state = false;
+        let mut storage_dir = None;
+        let mut watchdog = None;
+        let mut host_spectators = None;
+        let mut spectate = None;
+        let mut camera_image = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                // Keep MBC3 RTC games deterministic, e.g. for TAS or netplay
+                "--rtc-emulated" => rtc_clock = RtcClock::Emulated,
+                "--cheat" => cheats.push(args.next()?.clone()),
+                "--controller" => controller = Some(args.next()?.parse().ok()?),
+                "--frames" => exit.frames = Some(args.next()?.parse().ok()?),
+                "--seconds" => exit.seconds = Some(args.next()?.parse().ok()?),
+                "--exit-on-serial" => exit.serial = Some(args.next()?.clone()),
+                "--exit-on-breakpoint" => exit.breakpoint = true,
+                "--dump-frame" => dump_frame = Some(PathBuf::from(args.next()?)),
+                "--dump-serial" => dump_serial = Some(PathBuf::from(args.next()?)),
+                "--dump-tiles" => dump_tiles = Some(PathBuf::from(args.next()?)),
+                "--dump-bg-map" => dump_bg_map = Some(PathBuf::from(args.next()?)),
+                "--hash-frames" => {
+                    hash_frames = Some(args.next()?.parse().ok().filter(|n| *n > 0)?)
+                }
+                "--input-script" => input_script = Some(args.next()?.clone()),
+                "--checksums" => checksums = Some(PathBuf::from(args.next()?)),
+                "--dat" => dat = Some(PathBuf::from(args.next()?)),
+                "--coverage" => coverage = Some(PathBuf::from(args.next()?)),
+                "--poll-report" => poll_report = Some(PathBuf::from(args.next()?)),
+                "--warnings" => warnings = Some(PathBuf::from(args.next()?)),
+                "--mapper-log" => mapper_log = Some(PathBuf::from(args.next()?)),
+                "--trace" => trace = Some(PathBuf::from(args.next()?)),
+                "--trace-range" => trace_range = Some(args.next()?.clone()),
+                "--trace-from" => trace_from = Some(args.next()?.clone()),
+                "--trace-memory" => trace_memory = true,
+                "--trace-format" => {
+                    trace_format = match args.next()?.as_str() {
+                        "text" => TraceFormat::Text,
+                        "binary" => TraceFormat::Binary,
+                        _ => return None,
+                    }
+                }
+                "--profile" => profile = Some(PathBuf::from(args.next()?)),
+                "--symbols" => symbols = Some(PathBuf::from(args.next()?)),
+                "--bank-guard" => bank_guard = true,
+                "--dma-guard" => dma_guard = true,
+                "--stack-guard" => stack_guard = true,
+                "--header-guard" => header_guard = true,
+                "--infer-mapper" => infer_mapper = true,
+                "--restricted-writes" => {
+                    restricted_writes = match args.next()?.as_str() {
+                        "log" => RestrictedWrites::Log,
+                        "break" => RestrictedWrites::Break,
+                        _ => return None,
+                    }
+                }
+                "--runahead" => runahead = true,
+                "--max-speed" => max_speed = true,
+                "--pacing" => {
+                    pacing = match args.next()?.as_str() {
+                        "exact" => PacingMode::Exact,
+                        "refresh" => PacingMode::Refresh,
+                        _ => return None,
+                    }
+                }
+                "--sleep" => {
+                    sleep = match args.next()?.as_str() {
+                        "sleep" => SleepStrategy::Sleep,
+                        "hybrid" => SleepStrategy::Hybrid,
+                        "vsync" => SleepStrategy::Vsync,
+                        _ => return None,
+                    }
+                }
+                "--ram-init" => ram_init = Some(args.next()?.clone()),
+                "--boot-rom" => boot_rom = Some(PathBuf::from(args.next()?)),
+                "--fast-boot" => fast_boot = true,
+                "--rotate" => {
+                    orientation.quarter_turns = Orientation::parse_rotation(args.next()?)?
+                }
+                "--mirror" => orientation.mirror = true,
+                "--palette" => palette = Some(args.next()?.clone()),
+                "--blend" => blend = true,
+                "--break" => breakpoints.push(args.next()?.clone()),
+                "--watch" => watches.push(args.next()?.clone()),
+                "--rumble" => rumble.push(args.next()?.clone()),
+                "--load-state" => load_state = Some(PathBuf::from(args.next()?)),
+                "--auto-state" => auto_state = true,
+                "--storage-dir" => storage_dir = Some(PathBuf::from(args.next()?)),
+                "--host-spectators" => host_spectators = Some(args.next()?.clone()),
+                "--spectate" => spectate = Some(args.next()?.clone()),
+                "--camera-image" => camera_image = Some(PathBuf::from(args.next()?)),
+                "--watchdog" => {
+                    let seconds: u32 = args.next()?.parse().ok().filter(|n| *n > 0)?;
+                    watchdog = Some(seconds.checked_mul(60)?);
+                }
+                "--model" => {
+                    model = match args.next()?.as_str() {
+                        "dmg0" => Model::Dmg0,
+                        "dmg" => Model::Dmg,
+                        "mgb" => Model::Mgb,
+                        "sgb" => Model::Sgb,
+                        "sgb2" => Model::Sgb2,
+                        _ => return None,
+                    }
+                }
+                "--accuracy" => {
+                    accuracy = match args.next()?.as_str() {
+                        "fast" => AccuracyLevel::Fast,
+                        "balanced" => AccuracyLevel::Balanced,
+                        "accurate" => AccuracyLevel::Accurate,
+                        _ => return None,
+                    }
+                }
+                "--sprite-limit" => {
+                    sprite_limit = match args.next()?.as_str() {
+                        "none" => 40,
+                        limit => limit.parse().ok().filter(|limit| *limit > 0)?,
+                    }
+                }
+                "--serial-capture" => serial_capture = Some(PathBuf::from(args.next()?)),
+                _ if arg.starts_with("--serial=") => {
+                    serial = arg.strip_prefix("--serial=").map(String::from)
+                }
+                _ => rom_file = Some(arg.clone()),
+            }
+        }
+
+        // The guard reports through the warnings, to stdout unless asked
+        if header_guard && warnings.is_none() {
+            warnings = Some(PathBuf::from("-"));
+        }
+
+        Some(Options {
+            rom_file,
+            rtc_clock,
+            cheats,
+            controller,
+            exit,
+            dump_frame,
+            dump_serial,
+            dump_tiles,
+            dump_bg_map,
+            hash_frames,
+            checksums,
+            input_script,
+            serial,
+            serial_capture,
+            dat,
+            coverage,
+            poll_report,
+            warnings,
+            mapper_log,
+            trace,
+            trace_format,
+            trace_range,
+            trace_from,
+            trace_memory,
+            profile,
+            symbols,
+            bank_guard,
+            dma_guard,
+            stack_guard,
+            header_guard,
+            infer_mapper,
+            restricted_writes,
+            accuracy,
+            sprite_limit,
+            runahead,
+            pacing,
+            sleep,
+            max_speed,
+            model,
+            ram_init,
+            boot_rom,
+            fast_boot,
+            orientation,
+            palette,
+            blend,
+            breakpoints,
+            watches,
+            rumble,
+            load_state,
+            auto_state,
+            storage_dir,
+            watchdog,
+            host_spectators,
+            spectate,
+            camera_image,
+        })
+    }
+}
+
+/// Flags the GUI sets for the emulation thread.
+#[derive(Default)]
+struct Control {
+    lifecycle: Lifecycle,
+    turbo: AtomicBool,
+    rewind: AtomicBool,
+    /// Go back a frame while paused
+    step_back: AtomicBool,
+    /// Addresses to pause at, toggled in the disassembly
+    breakpoints: Mutex<BTreeSet<u16>>,
+    /// Values shown next to the game
+    watches: Mutex<Vec<Watch>>,
+    /// What the last state save or load replaced
+    undo: Mutex<Option<StateUndo>>,
+    rumble: Rumble,
+    presents: Presents,
+    /// Another ROM was swapped in, the rewind states of the old one go
+    swapped: AtomicBool,
+}
+
+/// Counts the frames the GUI presented, which the frame limiter waits for
+/// with `--sleep vsync`.
+#[derive(Default)]
+struct Presents {
+    count: Mutex<u64>,
+    presented: Condvar,
+}
+
+impl Presents {
+    fn count(&self) -> u64 {
+        *self.count.lock().unwrap()
+    }
+
+    fn record(&self) {
+        *self.count.lock().unwrap() += 1;
+        self.presented.notify_all();
+    }
+
+    /// Wait for a present after the `count`th, at most `timeout` in case
+    /// the window isn't shown.
+    fn wait_after(&self, count: u64, timeout: Duration) {
+        let guard = self.count.lock().unwrap();
+        let _ = self
+            .presented
+            .wait_timeout_while(guard, timeout, |presents| *presents <= count);
+    }
+}
+
+/// Left to spin with `SleepStrategy::Hybrid`, more than the OS usually
+/// oversleeps by.
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+/// Wait `duration` with `SleepStrategy::Sleep` or `Hybrid`.
+fn wait(strategy: SleepStrategy, duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    if strategy != SleepStrategy::Hybrid {
+        thread::sleep(duration);
+        return;
+    }
+
+    if let Some(sleep) = duration.checked_sub(SPIN_MARGIN) {
+        thread::sleep(sleep);
+    }
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+/// Kept by a state save or load so that an overwritten slot or a wrong
+/// load can be taken back.
+enum StateUndo {
+    /// The machine before the load
+    Load(Vec<u8>),
+    /// The state file before the save, None when there was none
+    Save(Option<Vec<u8>>),
+}
+
+/// What the game keeps between runs, read and written through the storage
+/// backend, keyed by the paths next to the ROM.
+struct GameFiles {
+    storage: Arc<dyn StorageBackend>,
+    /// Battery backed RAM and RTC
+    save: PathBuf,
+    /// Cleared when the battery save couldn't be read, so that the blank RAM
+    /// of this run doesn't overwrite it
+    write_save: bool,
+    /// The slot of the state hotkeys
+    state: PathBuf,
+    /// With `--auto-state`
+    auto_state: Option<PathBuf>,
+}
+
+impl GameFiles {
+    /// The files named after `rom_file`, the auto-state only with `auto_state`.
+    fn new(storage: Arc<dyn StorageBackend>, rom_file: &str, auto_state: bool) -> Self {
+        let rom_file = Path::new(rom_file);
+
+        GameFiles {
+            storage,
+            save: rom_file.with_extension("sav"),
+            write_save: true,
+            state: rom_file.with_extension("state"),
+            auto_state: auto_state.then(|| rom_file.with_extension("auto.state")),
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let command = match args.get(1).map(String::as_str) {
+        Some("info") => Some(commands::info as fn(&[String]) -> _),
+        Some("batch-test") => Some(commands::batch_test as fn(&[String]) -> _),
+        Some("coverage") => Some(commands::coverage as fn(&[String]) -> _),
+        Some("bench") => Some(commands::bench as fn(&[String]) -> _),
+        Some("compare") => Some(commands::compare as fn(&[String]) -> _),
+        Some("fix-header") => Some(commands::fix_header as fn(&[String]) -> _),
+        Some("desync") => Some(commands::desync as fn(&[String]) -> _),
+        Some("statediff") => Some(commands::statediff as fn(&[String]) -> _),
+        Some("statejson") => Some(commands::statejson as fn(&[String]) -> _),
+        Some("lockstep") => Some(commands::lockstep as fn(&[String]) -> _),
+        Some("trace") => Some(commands::trace as fn(&[String]) -> _),
+        Some("serve") => Some(commands::serve as fn(&[String]) -> _),
+        Some("selftest") => Some(commands::selftest as fn(&[String]) -> _),
+        _ => None,
+    };
+
+    if let Some(command) = command {
+        match command(&args[2..]) {
+            Ok(code) => process::exit(code),
+            Err(e) => {
+                eprintln!("{e}");
+                process::exit(1);
+            }
+        }
+    }
+
+    let Some(options) = Options::parse(&args[1..]) else {
+        eprintln!("Invalid arguments, see the README for the options");
+        process::exit(1);
+    };
+
+    let mut gui: GUI = GUI::new(true, options.sleep == SleepStrategy::Vsync);
+    gui.set_orientation(options.orientation);
+    gui.set_blend(options.blend);
+    // The input script has stdin to itself
+    let console = (options.input_script.as_deref() != Some("-")).then(Console::spawn);
+
+    let log = match config::config_dir().map(|dir| SessionLog::create(&dir.join("logs"))) {
+        Some(Ok(log)) => log,
+        Some(Err(e)) => {
+            eprintln!("Can't write the session log: {e}");
+            SessionLog::disabled()
+        }
+        None => SessionLog::disabled(),
+    };
+    log.info(
+        "session_start",
+        &[
+            ("version", env!("CARGO_PKG_VERSION").to_string()),
+            ("args", args[1..].join(" ")),
+        ],
+    );
+
+    match run(&options, &mut gui, console.as_ref(), &log) {
+        Ok(code) => {
+            log.info("session_end", &[("exit_code", code.to_string())]);
+            process::exit(code);
+        }
+        Err(e) => {
+            log.error("run_failed", &[("error", e.to_string())]);
+            eprintln!("Error running emulator {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Name the placeholder screen runs under, for the files named after the ROM.
+const PLACEHOLDER_FILE: &str = "no-rom.gb";
+
+/// Text of the placeholder screen.
+const PLACEHOLDER_TEXT: [&str; 7] = [
+    "NO ROM LOADED",
+    "",
+    "DROP A ROM FILE",
+    "ON THE WINDOW",
+    "",
+    "OR PRESS CTRL+O",
+    "TO OPEN ONE",
+];
+
+/// A cartridge with the file it came from.
+struct LoadedRom {
+    // None for the placeholder screen
+    file: Option<String>,
+    data: Vec<u8>,
+    rom: Cartridge,
+    // Why the ROM of the options was replaced by the placeholder
+    error: Option<String>,
+}
+
+/// The ROM of `options`. Without one, or when it can't be loaded in a run
+/// that isn't scripted, the placeholder screen asking for a ROM runs instead.
+fn load_rom(options: &Options) -> Result<LoadedRom, Box<dyn Error>> {
+    let mut error = None;
+
+    if let Some(rom_file) = &options.rom_file {
+        println!("Reading {rom_file}");
+        let loaded = fs::read(rom_file)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|data| Ok((Cartridge::from_bytes(rom_file, &data)?, data)));
+
+        match loaded {
+            Ok((rom, data)) => {
+                return Ok(LoadedRom {
+                    file: Some(rom_file.clone()),
+                    data,
+                    rom,
+                    error: None,
+                });
+            }
+            Err(e) if options.exit.is_scripted() => return Err(e),
+            Err(e) => error = Some(format!("{rom_file}: {e}")),
+        }
+    } else if options.exit.is_scripted() {
+        return Err("a scripted run needs a ROM".into());
+    }
+
+    let data = placeholder_rom(&PLACEHOLDER_TEXT);
+    let rom = Cartridge::from_bytes(PLACEHOLDER_FILE, &data)?;
+    Ok(LoadedRom {
+        file: None,
+        data,
+        rom,
+        error,
+    })
+}
+
+/// Run until the window is closed or an exit condition is met, returns the
+/// exit code. ROMs opened on the way are swapped into the same emulator.
+fn run(
+    options: &Options,
+    gui: &mut GUI,
+    console: Option<&Console>,
+    log: &SessionLog,
+) -> Result<i32, Box<dyn Error>> {
+    let LoadedRom {
+        file: loaded_file,
+        data: rom_data,
+        mut rom,
+        error: load_error,
+    } = load_rom(options)?;
+    // The placeholder has no battery, its states go to the working directory
+    let rom_file = loaded_file.as_deref().unwrap_or(PLACEHOLDER_FILE);
+
+    if let Some(message) = &load_error {
+        log.error("rom_load_failed", &[("error", message.clone())]);
+    }
+
+    let database = config::load_rom_database(options.dat.as_deref())?;
+    let game_name = describe_rom(&rom, &rom_data, rom_file, &database, log);
+
+    let storage: Arc<dyn StorageBackend> = match &options.storage_dir {
+        Some(dir) => Arc::new(FileStorage::in_dir(dir)),
+        None => Arc::new(FileStorage::new()),
+    };
+    let mut files = GameFiles::new(
+        storage,
+        rom_file,
+        options.auto_state && loaded_file.is_some(),
+    );
+    prepare_rom(&mut rom, options, &mut files)?;
+    let cheats = game_cheats(options, rom.header.global_checksum())?;
+
+    let hotkeys = Hotkeys::load()?;
+
+    for (hotkey, chord) in hotkeys.bindings() {
+        println!("{hotkey:?}: {chord}");
+    }
+
+    let mut input_script = match &options.input_script {
+        Some(path) => {
+            let text = if path == "-" {
+                let mut text = String::new();
+                io::stdin().read_to_string(&mut text)?;
+                text
+            } else {
+                fs::read_to_string(path)?
+            };
+
+            Some(InputScript::parse(&text)?)
+        }
+        None => None,
+    };
+
+    let display = config::load_display_config()?;
+    gui.set_border(display.border_color, display.border_image.as_deref())?;
+    gui.set_title(&format!("GameBoy Emulator - {game_name}"));
+    gui.select_controller(options.controller);
+    gui.set_hotkeys(hotkeys);
+    gui.set_keymap(KeyMap::load()?);
+
+    let mut emu = Emulator::new();
+    emu.set_model(options.model);
+
+    if let Some(spec) = &options.ram_init {
+        emu.set_ram_init(ram_init(spec)?);
+    }
+    if let Some(path) = &options.boot_rom {
+        let data = fs::read(path)?;
+        let rom = <[u8; BOOT_ROM_SIZE]>::try_from(data.as_slice()).map_err(|_| {
+            format!(
+                "{} isn't a DMG boot ROM, it has {} bytes instead of {BOOT_ROM_SIZE}",
+                path.display(),
+                data.len()
+            )
+        })?;
+        emu.set_boot_rom(Some(rom));
+    } else if options.fast_boot {
+        return Err("--fast-boot needs a boot ROM, pass it with --boot-rom".into());
+    }
+    if let Some(spec) = &options.palette {
+        emu.set_palette(palette(spec)?);
+    }
+    emu.load_cartridge(rom);
+    emu.set_cheats(cheats);
+
+    let mut serial = match &options.serial {
+        Some(spec) => Some(serial_device(spec)?),
+        None => None,
+    };
+
+    if let Some(path) = &options.serial_capture {
+        serial = Some(Box::new(SerialCapture::new(
+            fs::File::create(path)?,
+            serial,
+        )));
+    }
+
+    emu.set_serial_device(serial);
+    emu.set_bank_guard(options.bank_guard);
+    emu.set_dma_guard(options.dma_guard);
+    emu.set_stack_guard(options.stack_guard);
+    emu.set_header_guard(options.header_guard);
+    emu.set_mapper_inference(options.infer_mapper);
+    emu.set_restricted_writes(options.restricted_writes);
+    emu.set_accuracy(AccuracyConfig::preset(options.accuracy));
+    emu.set_sprite_limit(options.sprite_limit);
+
+    if options.sprite_limit > SPRITES_PER_LINE {
+        println!(
+            "Up to {} sprites per line, not accurate",
+            options.sprite_limit.min(40)
+        );
+    }
+
+    let mut cpu = CPU::new(emu);
+
+    if options.fast_boot {
+        if fast_boot(&mut cpu) {
+            println!("Boot ROM done at frame {}", cpu.context().frame_count());
+        } else {
+            println!("The boot ROM didn't finish, running it normally");
+        }
+    }
+
+    if options.coverage.is_some() {
+        cpu.set_coverage(Some(OpcodeCoverage::new()));
+    }
+
+    if options.poll_report.is_some() {
+        cpu.context_mut().set_poll_counter(Some(PollCounter::new()));
+    }
+
+    if options.warnings.is_some() {
+        cpu.context_mut().set_warnings(Some(WarningLog::new()));
+    }
+
+    if options.mapper_log.is_some() {
+        cpu.context_mut().set_mapper_log(Some(MapperLog::default()));
+    }
+
+    let trace_out: Option<Box<dyn Write + Send>> = match &options.trace {
+        Some(path) if path.as_os_str() == "-" => Some(Box::new(io::stdout())),
+        Some(path) => Some(Box::new(io::BufWriter::new(fs::File::create(path)?))),
+        None => None,
+    };
+    if let Some(out) = trace_out {
+        cpu.set_trace(Some(Box::new(TraceWriter::spawn(
+            out,
+            options.trace_format,
+        ))));
+    }
+    cpu.set_trace_config(trace_config(options)?);
+
+    // Read before the run so that a broken file doesn't waste it
+    let symbols = match &options.profile {
+        Some(_) => {
+            cpu.context_mut().set_profiler(Some(Profiler::new()));
+            load_symbols(options)?
+        }
+        None => None,
+    };
+
+    if let Some(frames) = options.watchdog {
+        cpu.context_mut()
+            .set_watchdog(Some(HangWatchdog::new(frames)));
+    }
+
+    cpu.set_report_faults(options.bank_guard || options.dma_guard || options.stack_guard);
+
+    if let Some(path) = &options.load_state {
+        let data = fs::read(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => state::load_json(&mut cpu, &String::from_utf8(data)?)?,
+            _ => state::load_machine(&mut cpu, &data)?,
+        }
+        println!("Loaded state from {}", path.display());
+    } else if options.spectate.is_none() {
+        resume_auto_state(
+            &mut cpu,
+            &files,
+            gui,
+            options.exit.is_scripted(),
+            &game_name,
+        );
+    }
+
+    println!("CPU initialized\n{}", cpu);
+    let cpu_mutex = Arc::new(Mutex::new(cpu));
+
+    let (tx, rx): (Sender<ExitReason>, Receiver<ExitReason>) = mpsc::channel();
+    let mut exit_watch = ExitWatch::new(options.exit.clone());
+
+    let input = InputState::default();
+    input.set_orientation(gui.orientation());
+    let cpu_input = input.clone();
+    let control = Arc::new(Control::default());
+    let cpu_control = control.clone();
+
+    if let Some(message) = load_error {
+        control.lifecycle.apply(Transition::LoadFailed(message));
+    }
+
+    if options.runahead && (options.serial.is_some() || options.serial_capture.is_some()) {
+        // The device would see every exchange of the frames run ahead
+        return Err("--runahead can't be used with a serial device".into());
+    }
+
+    *control.breakpoints.lock().unwrap() = options
+        .breakpoints
+        .iter()
+        .map(|spec| breakpoint(spec))
+        .collect::<Result<_, _>>()?;
+    *control.watches.lock().unwrap() = options
+        .watches
+        .iter()
+        .map(|text| Watch::parse(text))
+        .collect::<Result<_, _>>()?;
+    let (rumble_events, mut rumble_triggers) = parse_rumble(&options.rumble)?;
+    control.rumble.set_events(rumble_events);
+    // Where the last breakpoint paused, so resuming runs past it
+    let mut paused_at = None;
+
+    let mut spectator_host = match &options.host_spectators {
+        Some(address) => Some(SpectatorHost::bind(address)?),
+        None => None,
+    };
+    // Follows the host's input instead of the local one
+    let mut spectator = match &options.spectate {
+        Some(address) => Some(Spectator::connect(address)?),
+        None => None,
+    };
+
+    let hash_frames = options.hash_frames;
+    let mut checksum_log: Option<Box<dyn Write + Send>> = match &options.checksums {
+        Some(path) if path.as_os_str() == "-" => Some(Box::new(io::stdout())),
+        Some(path) => Some(Box::new(io::BufWriter::new(fs::File::create(path)?))),
+        None => None,
+    };
+    let checksum_stream = ChecksumStream::new(CHECKSUM_INTERVAL);
+    let mut runahead = options.runahead;
+    let max_speed = options.max_speed;
+    let sleep = options.sleep;
+    let pacing = Pacing {
+        mode: options.pacing,
+        // What most displays run at when SDL can't tell
+        display_rate: gui.display_rate().unwrap_or(60.0),
+    };
+    println!("Frame pacing: {pacing}");
+    log.info(
+        "settings",
+        &[
+            ("model", format!("{:?}", options.model)),
+            ("accuracy", format!("{:?}", options.accuracy)),
+            ("pacing", pacing.to_string()),
+            ("sprite_limit", options.sprite_limit.to_string()),
+            ("runahead", options.runahead.to_string()),
+            ("sleep", format!("{:?}", options.sleep)),
+        ],
+    );
+    cpu_mutex
+        .lock()
+        .unwrap()
+        .context_mut()
+        .stats_mut()
+        .set_pacing(Some(pacing));
+    let scripted = options.exit.is_scripted();
+    let speed_start = {
+        let cpu = cpu_mutex.lock().unwrap();
+        let emulator = cpu.context();
+        (
+            Instant::now(),
+            emulator.get_current_frame(),
+            emulator.emulated_duration(),
+        )
+    };
+    let cpu_thread_mutex = cpu_mutex.clone();
+    // Completed frames are handed to the GUI, which draws without holding the emulator
+    let (mut frame_writer, mut frame_reader) = triple_buffer(FrameSnapshot::default());
+
+    thread::spawn(move || {
+        let timer = Instant::now();
+        let mut frame = 0;
+        // Presents the GUI made before the last frame was handed to it
+        let mut presents = 0;
+        let mut prev_frame_time = timer.elapsed();
+        let mut fps_start_time = prev_frame_time;
+        let mut rewind = RewindBuffer::new(REWIND_MEMORY);
+
+        loop {
+            if cpu_control.lifecycle.is_stopped() {
+                break;
+            }
+
+            if !cpu_control.lifecycle.is_running() {
+                if cpu_control.step_back.swap(false, Ordering::Relaxed)
+                    && cpu_control.lifecycle.is_paused()
+                    && spectator.is_none()
+                {
+                    let mut cpu = cpu_thread_mutex.lock().unwrap();
+                    let unread = frame_writer.back_unread();
+
+                    if step_back(&mut cpu, &mut rewind, frame_writer.back_mut(), unread) {
+                        frame_writer.publish();
+                        frame = cpu.context().get_current_frame();
+                        println!("Back to frame {frame}\n{cpu}");
+                    } else {
+                        println!("Nothing to go back to");
+                    }
+                }
+
+                thread::sleep(TARGET_FRAME_TIME);
+                prev_frame_time = timer.elapsed();
+                continue;
+            }
+
+            if let Some(spectator) = &mut spectator {
+                // The buttons of the frame about to complete have to be known
+                let input = spectator.poll(frame + 1);
+
+                if let Some(host_state) = spectator.take_state() {
+                    let mut cpu = cpu_thread_mutex.lock().unwrap();
+
+                    if let Err(e) = join_host(&mut cpu, &host_state) {
+                        eprintln!("Can't watch the session: {e}");
+                        let _ = tx.send(ExitReason::SessionEnded);
+                        break;
+                    }
+
+                    frame = cpu.context().get_current_frame();
+                    println!("Watching from frame {frame}");
+                    continue;
+                }
+
+                match input {
+                    HostInput::Ready => {}
+                    HostInput::Waiting => {
+                        thread::sleep(Duration::from_millis(1));
+                        prev_frame_time = timer.elapsed();
+                        continue;
+                    }
+                    HostInput::Ended => {
+                        println!("Emulation ended: {:?}", ExitReason::SessionEnded);
+                        let _ = tx.send(ExitReason::SessionEnded);
+                        break;
+                    }
+                }
+            }
+
+            if cpu_control.swapped.swap(false, Ordering::Relaxed) {
+                rewind.clear();
+            }
+
+            // A spectator stays on the host's timeline
+            if cpu_control.rewind.load(Ordering::Relaxed) && spectator.is_none() {
+                if let Some(state) = rewind.pop() {
+                    let mut cpu = cpu_thread_mutex.lock().unwrap();
+                    let unread = frame_writer.back_unread();
+                    rewind_frame(&mut cpu, &state, frame_writer.back_mut(), unread);
+                    frame_writer.publish();
+                    frame = cpu.context().get_current_frame();
+                }
+
+                thread::sleep(TARGET_FRAME_TIME);
+                prev_frame_time = timer.elapsed();
+                continue;
+            }
+
+            let (exit_reason, current_frame) = {
+                // Locked once for a whole frame, stepping alone when breakpoints are set
+                let mut cpu = cpu_thread_mutex.lock().unwrap();
+                let frame_end = cpu.context().next_frame_tick();
+                let breakpoints = cpu_control.breakpoints.lock().unwrap().clone();
+                let stepping = !breakpoints.is_empty()
+                    || cpu.context().ppu_break().is_some()
+                    || cpu.context().restricted_writes() == RestrictedWrites::Break;
+                let mut exit_reason = None;
+
+                if !stepping {
+                    exit_reason = exit_watch.run_until(&mut cpu, frame_end);
+                }
+
+                while stepping && exit_reason.is_none() && cpu.context().ticks() < frame_end {
+                    let pc = cpu.registers().pc;
+
+                    if breakpoints.contains(&pc) && paused_at != Some(pc) {
+                        let counts: Vec<String> = cpu
+                            .context()
+                            .interrupt_stats()
+                            .iter()
+                            .map(|(source, counts)| {
+                                format!("{} {}", source.source_name(), counts.serviced)
+                            })
+                            .collect();
+                        println!("Breakpoint at {pc:04X}, P resumes\n{cpu}");
+                        println!("Interrupts serviced: {}", counts.join(", "));
+
+                        paused_at = Some(pc);
+                        cpu_control.lifecycle.apply(Transition::Pause);
+                        cpu_control.rumble.event(RumbleEvent::Break);
+                        break;
+                    }
+
+                    paused_at = None;
+                    exit_reason = exit_watch.step(&mut cpu);
+
+                    if let Some(event) = cpu.context_mut().take_ppu_break() {
+                        println!("Reached {event}, P resumes\n{cpu}");
+                        cpu_control.lifecycle.apply(Transition::Pause);
+                        cpu_control.rumble.event(RumbleEvent::Break);
+                        break;
+                    }
+
+                    if let Some(write) = cpu.context_mut().take_restricted_write() {
+                        println!("{write}, P resumes\n{cpu}");
+                        cpu_control.lifecycle.apply(Transition::Pause);
+                        cpu_control.rumble.event(RumbleEvent::Break);
+                        break;
+                    }
+                }
+
+                if let Some(fault) = cpu.fault()
+                    && exit_reason == Some(ExitReason::CpuStopped)
+                {
+                    eprintln!("CPU stopped, {fault}");
+                }
+
+                if exit_reason.is_none() && cpu.context_mut().take_hang() {
+                    let frame = cpu.context().get_current_frame();
+
+                    if scripted {
+                        eprintln!("Hang detected at frame {frame}\n{cpu}");
+                        exit_reason = Some(ExitReason::Hang);
+                    } else {
+                        println!("Hang detected at frame {frame}, P resumes\n{cpu}");
+                        cpu_control.lifecycle.apply(Transition::Pause);
+                        cpu_control.rumble.event(RumbleEvent::Break);
+                    }
+                }
+
+                let current_frame = cpu.context().get_current_frame();
+
+                if current_frame != frame {
+                    if let Some(every) = hash_frames
+                        && current_frame.is_multiple_of(every)
+                    {
+                        println!(
+                            "frame {current_frame} {:016x}",
+                            cpu.context().ppu().frame().hash()
+                        );
+                    }
+
+                    if let Some(log) = &mut checksum_log
+                        && checksum_stream.is_due(current_frame)
+                    {
+                        let checksum = state::checksum(&cpu);
+                        let line = ChecksumStream::format_line(current_frame, checksum);
+                        let _ = writeln!(log, "{line}");
+                    }
+
+                    // The frame counter moves at VBlank, take the keys for the next frame
+                    let held = match &mut spectator {
+                        Some(spectator) => spectator.buttons(current_frame),
+                        None => {
+                            let scripted = input_script
+                                .as_mut()
+                                .map_or(Buttons::empty(), |script| script.advance(current_frame));
+                            cpu_input.snapshot() | scripted
+                        }
+                    };
+                    cpu.context_mut().set_buttons(held);
+
+                    if let Some(host) = &mut spectator_host {
+                        host.send_frame(&cpu, held);
+                    }
+                    rewind.push(state::capture_machine(&cpu));
+
+                    let unread = frame_writer.back_unread();
+
+                    if runahead {
+                        runahead = run_ahead(&mut cpu, frame_writer.back_mut(), unread);
+                    } else {
+                        frame_writer.back_mut().capture(cpu.context_mut(), unread);
+                    }
+                    frame_writer.back_mut().watches = watch_values(&cpu, &cpu_control);
+
+                    // Every trigger is checked to keep track of which ones match
+                    let triggered = rumble_triggers
+                        .iter_mut()
+                        .map(|trigger| trigger.check(&cpu))
+                        .filter(|started| *started)
+                        .count();
+                    if triggered > 0 {
+                        cpu_control.rumble.event(RumbleEvent::Memory);
+                    }
+                    presents = cpu_control.presents.count();
+                    frame_writer.publish();
+                }
+
+                (exit_reason, current_frame)
+            };
+
+            if let Some(reason) = exit_reason {
+                println!("Emulation ended: {reason:?}");
+                // The GUI may already be gone
+                let _ = tx.send(reason);
+                break;
+            }
+
+            // Limit the frame rate to the pacing
+            if current_frame != frame {
+                frame = current_frame;
+                let frame_time = timer.elapsed() - prev_frame_time;
+
+                let target = pacing.frame_time();
+
+                let catching_up = spectator
+                    .as_ref()
+                    .is_some_and(|spectator| spectator.frames_behind(frame) > 1);
+
+                let unlimited = max_speed || cpu_control.turbo.load(Ordering::Relaxed);
+
+                if !unlimited && !catching_up {
+                    match sleep {
+                        // Late presents fall back to half the rate
+                        SleepStrategy::Vsync => {
+                            cpu_control.presents.wait_after(presents, target * 2)
+                        }
+                        _ if frame_time < target => wait(sleep, target - frame_time),
+                        _ => {}
+                    }
+                }
+
+                let now = timer.elapsed();
+                let mut cpu = cpu_thread_mutex.lock().unwrap();
+                cpu.context_mut().record_frame_time(now - prev_frame_time);
+                prev_frame_time = now;
+
+                if (prev_frame_time - fps_start_time).as_millis() > 1000 {
+                    let stats = cpu.context().stats();
+                    println!(
+                        "FPS: {:.1}, speed {:.0}%",
+                        stats.fps(),
+                        stats.speed_percent()
+                    );
+                    fps_start_time = prev_frame_time;
+                }
+            }
+        }
+    });
+
+    let mut exit_code = 0;
+
+    loop {
+        // The game stays frozen until the error is dismissed
+        if let RunState::RomLoadError { message, .. } = control.lifecycle.state() {
+            gui.show_message("Open ROM", &message);
+            control.lifecycle.apply(Transition::Dismiss);
+        }
+
+        // Limit frame rate to 60Hz, returns early to handle input
+        let mut exit = false;
+
+        for action in gui.handle_events(&input, 16) {
+            let open = match action {
+                GuiAction::Exit => {
+                    exit = true;
+                    None
+                }
+                GuiAction::HotkeyDown(hotkey) => on_hotkey(
+                    hotkey,
+                    gui,
+                    &input,
+                    &cpu_mutex,
+                    &control,
+                    &files,
+                    &frame_reader.front().frame,
+                ),
+                GuiAction::HotkeyUp(Hotkey::Turbo) => {
+                    control.turbo.store(false, Ordering::Relaxed);
+                    None
+                }
+                GuiAction::HotkeyUp(Hotkey::Rewind) => {
+                    control.rewind.store(false, Ordering::Relaxed);
+                    None
+                }
+                GuiAction::HotkeyUp(_) => None,
+                GuiAction::OpenRom(path) => Some(path),
+                GuiAction::ToggleBreakpoint(address) => {
+                    toggle_breakpoint(&control, address);
+                    None
+                }
+            };
+
+            if let Some(path) = open {
+                let watched = options.spectate.is_some() || options.host_spectators.is_some();
+                let opened = if watched {
+                    // The other side would go on with the old game
+                    Err("can't switch games in a spectator session".into())
+                } else {
+                    let mut cpu = cpu_mutex.lock().unwrap();
+                    open_rom(&path, options, gui, &mut cpu, &mut files, &database, log)
+                };
+
+                match opened {
+                    Ok(()) => {
+                        control.swapped.store(true, Ordering::Relaxed);
+                        // The state replaced last belongs to the old game
+                        control.undo.lock().unwrap().take();
+                        control.lifecycle.apply(Transition::Resume);
+                    }
+                    // Keep running the current game if the file isn't a ROM
+                    Err(e) => {
+                        let message = format!("{}: {e}", path.display());
+                        control.lifecycle.apply(Transition::LoadFailed(message));
+                    }
+                }
+            }
+        }
+
+        if exit {
+            break;
+        }
+
+        if control.rumble.take() {
+            gui.rumble();
+        }
+
+        for command in console.into_iter().flat_map(Console::commands) {
+            match command {
+                Ok(Command::Watch(watch)) => control.watches.lock().unwrap().push(watch),
+                Ok(Command::Trace(_)) if options.trace.is_none() => {
+                    eprintln!("Not tracing, start with --trace <file>");
+                }
+                Ok(Command::Trace(enabled)) => {
+                    cpu_mutex.lock().unwrap().set_tracing(enabled);
+                    println!("Trace {}", if enabled { "on" } else { "off" });
+                }
+                Ok(Command::Back) if !control.lifecycle.is_paused() => {
+                    eprintln!("Pause first, back steps through the paused game");
+                }
+                Ok(Command::Back) => control.step_back.store(true, Ordering::Relaxed),
+                Ok(Command::Mapper) => match cpu_mutex.lock().unwrap().context().cartridge() {
+                    Some(rom) => print!("{}", rom.mapper_report()),
+                    None => eprintln!("No cartridge"),
+                },
+                Ok(Command::Unwatch(text)) => control
+                    .watches
+                    .lock()
+                    .unwrap()
+                    .retain(|watch| text.as_ref().is_some_and(|text| watch.text() != text)),
+                Ok(command) => {
+                    let Some(event) = command.event() else {
+                        continue;
+                    };
+                    cpu_mutex
+                        .lock()
+                        .unwrap()
+                        .context_mut()
+                        .set_ppu_break(Some(event));
+                    control.lifecycle.apply(Transition::Resume);
+                    println!("Running to {event}");
+                }
+                Err(message) => eprintln!("{message}"),
+            }
+        }
+
+        // No new frames come while paused, the disassembly is drawn over the
+        // last one and watches added since are evaluated here
+        let paused = control.lifecycle.is_paused().then(|| {
+            let cpu = cpu_mutex.lock().unwrap();
+            (
+                disassembly_view(&cpu, &control),
+                watch_values(&cpu, &control),
+            )
+        });
+        let fresh = frame_reader.latest().is_some();
+
+        if fresh || paused.is_some() {
+            let snapshot = frame_reader.front();
+            let (disassembly, watches) = match &paused {
+                Some((disassembly, watches)) => (Some(disassembly), watches),
+                None => (None, &snapshot.watches),
+            };
+            gui.update_window(snapshot, disassembly, watches);
+            control.presents.record();
+        }
+        if fresh {
+            let snapshot = frame_reader.front();
+            gui.update_debug_window(&snapshot.tiles, &snapshot.dirty_tiles);
+        }
+
+        match rx.try_recv() {
+            Ok(reason) => {
+                log.info("emulation_ended", &[("reason", format!("{reason:?}"))]);
+                exit_code = options.exit.exit_code(reason);
+                break;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                break;
+            }
+            Err(mpsc::TryRecvError::Empty) => (),
+        };
+    }
+
+    control.lifecycle.apply(Transition::Stop);
+    // A panic of the emulation thread leaves a machine still worth saving
+    let mut cpu = cpu_mutex.lock().unwrap_or_else(PoisonError::into_inner);
+    // Before anything that can fail
+    save_progress(&cpu, options, &files);
+    // Flushes the file
+    drop(cpu.take_trace());
+
+    // Rewinding, resets and swapped ROMs can take the machine back past
+    // where it was at the start
+    let (started, start_frame, start_emulated) = speed_start;
+    let speed = SpeedReport {
+        frames: cpu
+            .context()
+            .get_current_frame()
+            .saturating_sub(start_frame),
+        elapsed: started.elapsed(),
+        emulated: cpu
+            .context()
+            .emulated_duration()
+            .saturating_sub(start_emulated),
+    };
+    log.info(
+        "performance",
+        &[
+            ("frames", speed.frames.to_string()),
+            ("seconds", format!("{:.1}", speed.elapsed.as_secs_f64())),
+            ("fps", format!("{:.1}", speed.fps())),
+            ("speed", format!("{:.2}", speed.multiple())),
+        ],
+    );
+
+    if options.max_speed {
+        println!("Max speed: {speed}");
+    }
+
+    if let Some(path) = &options.dump_frame {
+        // Pick up the final frame if the GUI didn't draw it
+        frame_reader.latest();
+        fs::write(path, frame_reader.front().frame.to_png())?;
+    }
+
+    if let Some(path) = &options.dump_serial {
+        fs::write(path, cpu.context().serial_output())?;
+    }
+
+    if let Some(path) = &options.dump_tiles {
+        fs::write(path, vram::tile_sheet(cpu.context().ppu()).to_png())?;
+    }
+
+    if let Some(path) = &options.dump_bg_map {
+        fs::write(path, vram::background_map(cpu.context().ppu()).to_png())?;
+    }
+
+    if let Some(path) = &options.coverage
+        && let Some(coverage) = cpu.coverage()
+    {
+        commands::save_coverage(path, coverage)?;
+    }
+
+    if let Some(path) = &options.poll_report
+        && let Some(polls) = cpu.context().poll_counter()
+    {
+        if path.as_os_str() == "-" {
+            print!("{polls}");
+        } else {
+            fs::write(path, polls.to_string())?;
+        }
+    }
+
+    if let Some(path) = &options.warnings
+        && let Some(warnings) = cpu.context().warnings()
+    {
+        if path.as_os_str() == "-" {
+            print!("{warnings}");
+        } else {
+            fs::write(path, warnings.to_string())?;
+        }
+    }
+
+    if let Some(path) = &options.mapper_log
+        && let Some(log) = cpu.context().mapper_log()
+    {
+        if path.as_os_str() == "-" {
+            print!("{log}");
+        } else {
+            fs::write(path, log.to_string())?;
+        }
+    }
+
+    if let Some(path) = &options.profile
+        && let Some(profiler) = cpu.context().profiler()
+    {
+        fs::write(path, profiler.to_json(symbols.as_ref()))?;
+    }
+
+    Ok(exit_code)
+}
+
+/// Print what `rom` is and log it, returns the name of the game, the one
+/// in `database` when it's a known dump.
+fn describe_rom(
+    rom: &Cartridge,
+    data: &[u8],
+    rom_file: &str,
+    database: &RomDatabase,
+    log: &SessionLog,
+) -> String {
+    println!("{rom}");
+
+    let identity = database.identify(data, rom.header.rom_size() as usize);
+    let (verified_name, dump_warning) = config::describe_identity(&identity);
+
+    if let Some(warning) = dump_warning {
+        log.warn("bad_dump", &[("warning", warning.clone())]);
+        println!("Warning: {warning}");
+    }
+
+    let game_name = verified_name.unwrap_or_else(|| rom.header.title().to_string());
+
+    log.info(
+        "rom_loaded",
+        &[
+            ("file", rom_file.to_string()),
+            ("title", game_name.clone()),
+            ("type", rom.header.rom_type().1.to_string()),
+            ("checksum", format!("{:04X}", rom.header.global_checksum())),
+        ],
+    );
+
+    for issue in rom.header.validate() {
+        log.warn("header_issue", &[("issue", issue.to_string())]);
+        println!("Warning: {issue}");
+    }
+
+    game_name
+}
+
+/// Set up `rom` from the options and load its battery RAM and RTC.
+fn prepare_rom(
+    rom: &mut Cartridge,
+    options: &Options,
+    files: &mut GameFiles,
+) -> Result<(), Box<dyn Error>> {
+    rom.set_rtc_clock(options.rtc_clock);
+
+    if let Some(path) = &options.camera_image {
+        let (width, height, rgba) = png::decode_rgba(&fs::read(path)?)?;
+        rom.set_camera_image(&camera::sensor_image(width, height, &rgba));
+    }
+
+    if rom.has_battery() {
+        match files.storage.load(&files.save) {
+            Ok(Some(data)) => rom.load_battery_data(&data),
+            Ok(None) => (),
+            Err(e) => {
+                eprintln!(
+                    "Failed to load {}, it won't be saved over: {e}",
+                    files.storage.location(&files.save)
+                );
+                files.write_save = false;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Cheats of the game, new ones from the command line are stored with them.
+fn game_cheats(options: &Options, global_checksum: u16) -> Result<CheatList, Box<dyn Error>> {
+    let mut cheats = config::load_cheats(global_checksum)?;
+
+    if !options.cheats.is_empty() {
+        for code in &options.cheats {
+            cheats.push(Cheat::new(code, "")?);
+        }
+
+        config::save_cheats(global_checksum, &cheats)?;
+    }
+
+    for cheat in cheats.iter() {
+        let state = if cheat.enabled { "on" } else { "off" };
+        println!("Cheat {} ({state}) {}", cheat.text, cheat.description);
+    }
+
+    Ok(cheats)
+}
+
+/// Offer to resume the `--auto-state` of the game, when it has one. A state
+/// that can't be loaded, corrupt or from an incompatible build, is reported
+/// and the game starts fresh.
+fn resume_auto_state(
+    cpu: &mut CPU<Emulator>,
+    files: &GameFiles,
+    gui: &mut GUI,
+    scripted: bool,
+    game_name: &str,
+) {
+    let Some(auto_state_file) = &files.auto_state else {
+        return;
+    };
+    let Ok(Some(data)) = files.storage.load(auto_state_file) else {
+        return;
+    };
+    let location = files.storage.location(auto_state_file);
+
+    // The ROM file may have been replaced by another game or revision
+    let global_checksum = cpu
+        .context()
+        .cartridge()
+        .map(|rom| rom.header.global_checksum());
+    let same_rom =
+        state::rom_checksum(&data).is_none_or(|checksum| Some(checksum) == global_checksum);
+
+    if !same_rom {
+        println!("{location} belongs to another ROM, starting over");
+    } else if scripted || gui.ask_resume(game_name) {
+        // A state failing halfway would leave a mix of both machines
+        let fresh = state::capture_machine(cpu);
+
+        match state::load_machine(cpu, &data) {
+            Ok(()) => println!("Resumed from {location}"),
+            Err(e) => {
+                eprintln!("Failed to load {location}, starting over: {e}");
+
+                if let Err(e) = state::restore_machine(cpu, &fresh) {
+                    eprintln!("Failed to start over: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Power the running emulator on with the ROM at `path`. The battery RAM
+/// and auto-state of the game it replaces are saved first, the settings
+/// carry over. Fails only while the old game is still running, once the
+/// cartridge is swapped the rest is reported as warnings.
+fn open_rom(
+    path: &Path,
+    options: &Options,
+    gui: &mut GUI,
+    cpu: &mut CPU<Emulator>,
+    files: &mut GameFiles,
+    database: &RomDatabase,
+    log: &SessionLog,
+) -> Result<(), Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let rom_file = path.to_string_lossy().into_owned();
+    let mut rom = Cartridge::from_bytes(&rom_file, &data)?;
+    let game_name = describe_rom(&rom, &data, &rom_file, database, log);
+
+    let mut new_files = GameFiles::new(files.storage.clone(), &rom_file, options.auto_state);
+    prepare_rom(&mut rom, options, &mut new_files)?;
+    let cheats = game_cheats(options, rom.header.global_checksum())?;
+
+    // While the machine still runs the old game
+    save_auto_state(cpu, files);
+    if let Some(old) = state::swap_cartridge(cpu, rom) {
+        save_battery(&old, files);
+    }
+    cpu.context_mut().set_cheats(cheats);
+    *files = new_files;
+
+    gui.set_title(&format!("GameBoy Emulator - {game_name}"));
+    resume_auto_state(cpu, files, gui, options.exit.is_scripted(), &game_name);
+    Ok(())
+}
+
+/// Write what the player would lose when the run ends: battery RAM with the
+/// RTC and, with `--auto-state`, the whole machine. Closing the window,
+/// SIGINT and SIGTERM (SDL turns both into a quit event) and scripted exits
+/// all get here. Failures are reported without stopping the shutdown.
+fn save_progress(cpu: &CPU<Emulator>, options: &Options, files: &GameFiles) {
+    // A spectator's cartridge RAM is the host's game, not the local one
+    if options.spectate.is_some() {
+        return;
+    }
+
+    if let Some(rom) = cpu.context().cartridge() {
+        save_battery(rom, files);
+    }
+    save_auto_state(cpu, files);
+}
+
+fn save_battery(rom: &Cartridge, files: &GameFiles) {
+    if rom.has_battery()
+        && files.write_save
+        && let Err(e) = files.storage.store(&files.save, &rom.battery_data())
+    {
+        eprintln!(
+            "Failed to save {}: {e}",
+            files.storage.location(&files.save)
+        );
+    }
+}
+
+fn save_auto_state(cpu: &CPU<Emulator>, files: &GameFiles) {
+    if let Some(auto_state_file) = &files.auto_state {
+        let location = files.storage.location(auto_state_file);
+
+        match files
+            .storage
+            .store(auto_state_file, &state::save_machine(cpu))
+        {
+            Ok(()) => println!("Saved state to {location}"),
+            Err(e) => eprintln!("Failed to save {location}: {e}"),
+        }
+    }
+}
+
+/// Start a spectator from the state the host sent.
+fn join_host(cpu: &mut CPU<Emulator>, host_state: &HostState) -> Result<(), Box<dyn Error>> {
+    let global_checksum = cpu
+        .context()
+        .cartridge()
+        .map(|rom| rom.header.global_checksum());
+
+    if global_checksum != Some(host_state.global_checksum) {
+        return Err("the host runs another ROM".into());
+    }
+
+    state::load_machine(cpu, &host_state.data)?;
+    cpu.context_mut().set_buttons(host_state.buttons);
+    Ok(())
+}
+
+/// Go back to a state from the rewind buffer.
+///
+/// The picture isn't part of a state, the frame following it is run to draw
+/// it again.
+fn rewind_frame(cpu: &mut CPU<Emulator>, state: &[u8], snapshot: &mut FrameSnapshot, unread: bool) {
+    if let Err(e) = state::restore_machine(cpu, state) {
+        eprintln!("Failed to rewind: {e}");
+        return;
+    }
+
+    let frame = cpu.context().get_current_frame();
+    while cpu.context().get_current_frame() == frame && cpu.step() {}
+
+    snapshot.capture(cpu.context_mut(), unread);
+}
+
+/// Go back to where the frame before the current one ended, for looking
+/// at a glitch frame by frame while paused. States are taken when frames
+/// end, so the newest one older than that is restored and run forward.
+/// False when the rewind buffer is empty, at its oldest state it stops there.
+fn step_back(
+    cpu: &mut CPU<Emulator>,
+    rewind: &mut RewindBuffer,
+    snapshot: &mut FrameSnapshot,
+    unread: bool,
+) -> bool {
+    let target = cpu.context().get_current_frame().saturating_sub(1);
+    let mut restored = false;
+
+    while let Some(state) = rewind.pop() {
+        if let Err(e) = state::restore_machine(cpu, &state) {
+            eprintln!("Failed to go back: {e}");
+            break;
+        }
+        restored = true;
+
+        if cpu.context().get_current_frame() < target {
+            break;
+        }
+    }
+
+    if !restored {
+        return false;
+    }
+
+    // The picture isn't part of a state, it's drawn again on the way
+    let end = target.max(cpu.context().get_current_frame() + 1);
+    while cpu.context().get_current_frame() < end && cpu.step() {}
+
+    snapshot.capture(cpu.context_mut(), unread);
+    true
+}
+
+/// Capture the frame after the current one instead of the current one.
+///
+/// The next frame is run with the keys already set for it and the machine is
+/// rolled back, the same frame is then run again for real. This hides one
+/// frame of the latency games add between reading the joypad and drawing.
+/// False when the machine couldn't be rolled back and stays a frame ahead,
+/// running ahead again would only skip more frames.
+fn run_ahead(cpu: &mut CPU<Emulator>, snapshot: &mut FrameSnapshot, unread: bool) -> bool {
+    let state = state::capture_machine(cpu);
+    let coverage = cpu.coverage().cloned();
+    let polls = cpu.context().poll_counter().cloned();
+    let profiler = cpu.context().profiler().cloned();
+    let warnings = cpu.context().warnings().cloned();
+    let mapper_log = cpu.context().mapper_log().cloned();
+    // The frame isn't run for real yet
+    let trace = cpu.take_trace();
+    let serial_len = cpu.context().serial_output().len();
+    let frame = cpu.context().get_current_frame();
+
+    while cpu.context().get_current_frame() == frame && cpu.step() {}
+
+    snapshot.capture(cpu.context_mut(), unread);
+
+    let restored = state::restore_machine(cpu, &state);
+    if let Err(e) = &restored {
+        eprintln!("Failed to roll back after running ahead, turning run-ahead off: {e}");
+    }
+
+    cpu.set_coverage(coverage);
+    cpu.context_mut().set_poll_counter(polls);
+    cpu.context_mut().set_profiler(profiler);
+    cpu.context_mut().set_warnings(warnings);
+    cpu.context_mut().set_mapper_log(mapper_log);
+    cpu.set_trace(trace);
+    cpu.context_mut().truncate_serial_output(serial_len);
+    // Tiles written again by the real frame are marked again, the rest didn't change
+    cpu.context_mut().take_dirty_tiles();
+    restored.is_ok()
+}
+
+/// RAM contents for a `--ram-init` value, `random` without a seed picks one and prints it.
+fn ram_init(spec: &str) -> Result<RamInit, Box<dyn Error>> {
+    let invalid = || format!("Invalid RAM init {spec}");
+
+    let ram_init = match spec {
+        "zero" => RamInit::Zero,
+        "random" => {
+            let seed = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+            println!("RAM init seed: {seed}");
+            RamInit::Random(seed)
+        }
+        _ if spec.starts_with("random:") => RamInit::Random(spec[7..].parse()?),
+        _ => {
+            let value = spec
+                .strip_prefix("pattern(")
+                .and_then(|s| s.strip_suffix(')'))
+                .ok_or_else(invalid)?;
+            let value = match value.strip_prefix("0x") {
+                Some(hex) => u8::from_str_radix(hex, 16)?,
+                None => value.parse()?,
+            };
+            RamInit::Pattern(value)
+        }
+    };
+
+    Ok(ram_init)
+}
+
+/// Emulated time `--fast-boot` gives the boot ROM, a header it rejects
+/// locks it up for good.
+const FAST_BOOT_LIMIT: u64 = 10 * CLOCK_HZ;
+
+/// Run the boot ROM as fast as the host allows until it hands over to the
+/// cartridge at 0x0100, false when it didn't within `FAST_BOOT_LIMIT`.
+fn fast_boot(cpu: &mut CPU<Emulator>) -> bool {
+    let outcome = headless::run_until(cpu, &Condition::PcEquals(0x0100), FAST_BOOT_LIMIT);
+    outcome == RunOutcome::Met && !cpu.context().boot_rom_mapped()
+}
+
+/// Address for a `--break` value, hex like `0x0150` or `$0150` or an
+/// interrupt handler like `vblank-handler`.
+fn breakpoint(spec: &str) -> Result<u16, Box<dyn Error>> {
+    if let Some(address) = interrupts::handler_address(spec) {
+        return Ok(address);
+    }
+
+    let hex = spec
+        .strip_prefix("0x")
+        .or_else(|| spec.strip_prefix('$'))
+        .unwrap_or(spec);
+
+    u16::from_str_radix(hex, 16).map_err(|_| {
+        format!(
+            "Invalid breakpoint {spec}, use an address or vblank-handler, stat-handler, \
+             timer-handler, serial-handler or joypad-handler"
+        )
+        .into()
+    })
+}
+
+/// The `--trace-range` and `--trace-from` limits of the trace and `--trace-memory`.
+fn trace_config(options: &Options) -> Result<TraceConfig, Box<dyn Error>> {
+    let range = match &options.trace_range {
+        Some(spec) => {
+            let (start, end) = spec
+                .split_once('-')
+                .ok_or_else(|| format!("Invalid trace range {spec}, use START-END"))?;
+            let (start, end) = (breakpoint(start)?, breakpoint(end)?);
+            if start > end {
+                return Err(format!("Invalid trace range {spec}, START is after END").into());
+            }
+            Some(start..=end)
+        }
+        None => None,
+    };
+    let start_at = options.trace_from.as_deref().map(breakpoint).transpose()?;
+
+    Ok(TraceConfig {
+        enabled: start_at.is_none(),
+        range,
+        start_at,
+        memory: options.trace_memory,
+    })
+}
+
+/// The `--symbols` file, or the `.sym` file next to the ROM when there is one.
+fn load_symbols(options: &Options) -> Result<Option<SymbolTable>, Box<dyn Error>> {
+    let path = match (&options.symbols, &options.rom_file) {
+        (Some(path), _) => path.clone(),
+        (None, Some(rom_file)) => Path::new(rom_file).with_extension("sym"),
+        (None, None) => return Ok(None),
+    };
+
+    if options.symbols.is_none() && !path.exists() {
+        return Ok(None);
+    }
+
+    let text = fs::read_to_string(&path)?;
+    let symbols = SymbolTable::parse(&text)
+        .ok_or_else(|| format!("Invalid symbol file {}", path.display()))?;
+    println!("Loaded {} symbols from {}", symbols.len(), path.display());
+    Ok(Some(symbols))
+}
+
+/// Palette for a `--palette` value, a preset name or a `.pal` file.
+fn palette(spec: &str) -> Result<Palette, Box<dyn Error>> {
+    if let Some(palette) = Palette::preset(spec) {
+        return Ok(palette);
+    }
+
+    if !spec.ends_with(".pal") {
+        let names: Vec<&str> = Palette::PRESETS.iter().map(|(name, _)| *name).collect();
+        return Err(format!(
+            "Unknown palette {spec}, use a .pal file or {}",
+            names.join(", ")
+        )
+        .into());
+    }
+
+    let text = fs::read_to_string(spec)?;
+    Palette::parse(&text).ok_or_else(|| format!("Invalid palette file {spec}").into())
+}
+
+/// Serial device for a `--serial=` value, unknown names list the devices.
+fn serial_device(spec: &str) -> Result<Box<dyn SerialDevice>, Box<dyn Error>> {
+    let registry = PeripheralRegistry::builtin();
+
+    registry.create(spec).map_err(|error| match error {
+        PeripheralError::Unknown(_) => {
+            let mut message = format!("{error}, available devices:");
+            for entry in registry.entries() {
+                message += &format!("\n  {:<16} {}", entry.usage, entry.description);
+            }
+            message.into()
+        }
+        _ => error.into(),
+    })
+}
+
+fn on_hotkey(
+    hotkey: Hotkey,
+    gui: &mut GUI,
+    input: &InputState,
+    cpu: &Mutex<CPU<Emulator>>,
+    control: &Control,
+    files: &GameFiles,
+    frame: &Frame,
+) -> Option<PathBuf> {
+    let state_file = &files.state;
+
+    match hotkey {
+        Hotkey::Quit => (),
+        Hotkey::SaveState => {
+            let data = state::save_machine(&cpu.lock().unwrap());
+            let previous = files.storage.load(state_file).ok().flatten();
+
+            match files.storage.store(state_file, &data) {
+                Ok(()) => {
+                    println!("Saved state to {}", files.storage.location(state_file));
+                    *control.undo.lock().unwrap() = Some(StateUndo::Save(previous));
+                }
+                Err(e) => {
+                    eprintln!("Failed to save state: {e}");
+                    control.rumble.event(RumbleEvent::StateError);
+                }
+            }
+        }
+        Hotkey::LoadState => {
+            let mut cpu = cpu.lock().unwrap();
+            let previous = state::save_machine(&cpu);
+            let result = match files.storage.load(state_file) {
+                Ok(Some(data)) => state::load_machine(&mut cpu, &data).map_err(Box::from),
+                Ok(None) => Err("no state saved".into()),
+                Err(e) => Err(Box::<dyn Error>::from(e)),
+            };
+
+            match result {
+                Ok(()) => {
+                    println!("Loaded state from {}", files.storage.location(state_file));
+                    *control.undo.lock().unwrap() = Some(StateUndo::Load(previous));
+                }
+                Err(e) => {
+                    // A load failing halfway leaves a mix of both states
+                    let _ = state::load_machine(&mut cpu, &previous);
+                    eprintln!("Failed to load state: {e}");
+                    control.rumble.event(RumbleEvent::StateError);
+                }
+            }
+        }
+        Hotkey::UndoState => match control.undo.lock().unwrap().take() {
+            Some(StateUndo::Load(previous)) => {
+                match state::load_machine(&mut cpu.lock().unwrap(), &previous) {
+                    Ok(()) => println!("Undid the state load"),
+                    Err(e) => eprintln!("Failed to undo the state load: {e}"),
+                }
+            }
+            Some(StateUndo::Save(previous)) => {
+                let result = match previous {
+                    Some(data) => files.storage.store(state_file, &data),
+                    None => files.storage.remove(state_file),
+                };
+
+                match result {
+                    Ok(()) => println!("Restored {}", files.storage.location(state_file)),
+                    Err(e) => eprintln!("Failed to undo the state save: {e}"),
+                }
+            }
+            None => println!("No state save or load to undo"),
+        },
+        Hotkey::Turbo => control.turbo.store(true, Ordering::Relaxed),
+        Hotkey::Pause => match control.lifecycle.apply(Transition::TogglePause) {
+            Some(RunState::Paused) => println!("Paused"),
+            Some(_) => println!("Resumed"),
+            None => (),
+        },
+        Hotkey::ExportVram => {
+            let cpu = cpu.lock().unwrap();
+            let ppu = cpu.context().ppu();
+            let tiles = state_file.with_extension("tiles.png");
+            let bg_map = state_file.with_extension("bgmap.png");
+
+            let result = fs::write(&tiles, vram::tile_sheet(ppu).to_png())
+                .and_then(|_| fs::write(&bg_map, vram::background_map(ppu).to_png()));
+
+            match result {
+                Ok(()) => println!(
+                    "Exported VRAM to {} and {}",
+                    tiles.display(),
+                    bg_map.display()
+                ),
+                Err(e) => eprintln!("Failed to export VRAM: {e}"),
+            }
+        }
+        Hotkey::SoftReset => {
+            state::soft_reset(&mut cpu.lock().unwrap());
+            println!("Soft reset");
+        }
+        Hotkey::HardReset => {
+            state::hard_reset(&mut cpu.lock().unwrap());
+            println!("Hard reset");
+        }
+        Hotkey::Fullscreen => gui.toggle_fullscreen(),
+        Hotkey::WindowScale(scale) => gui.set_window_scale(scale),
+        Hotkey::TogglePixelPerfect => gui.toggle_pixel_perfect(),
+        Hotkey::ToggleBlend => {
+            gui.set_blend(!gui.blend());
+            println!("Frame blending {}", if gui.blend() { "on" } else { "off" });
+        }
+        Hotkey::CyclePalette => {
+            let mut cpu = cpu.lock().unwrap();
+            let current = cpu.context().palette();
+            // A palette from a file goes back to the first preset
+            let next = Palette::PRESETS
+                .iter()
+                .position(|(_, palette)| *palette == current)
+                .map_or(0, |index| (index + 1) % Palette::PRESETS.len());
+            let (name, palette) = Palette::PRESETS[next];
+
+            cpu.context_mut().set_palette(palette);
+            println!("Palette: {name}");
+        }
+        Hotkey::Rotate | Hotkey::ToggleMirror => {
+            let mut orientation = gui.orientation();
+
+            if hotkey == Hotkey::Rotate {
+                orientation = orientation.rotated();
+            } else {
+                orientation.mirror = !orientation.mirror;
+            }
+
+            gui.set_orientation(orientation);
+            input.set_orientation(orientation);
+            println!(
+                "Screen turned {} degrees{}",
+                orientation.angle(),
+                if orientation.mirror { ", mirrored" } else { "" }
+            );
+        }
+        Hotkey::ToggleOverlay => gui.toggle_overlay(),
+        Hotkey::TogglePriority => gui.toggle_priority(),
+        Hotkey::ToggleStats => gui.toggle_stats(),
+        Hotkey::ToggleInput => gui.toggle_input(),
+        Hotkey::ToggleBackground | Hotkey::ToggleWindow | Hotkey::ToggleSprites => {
+            let layer = match hotkey {
+                Hotkey::ToggleBackground => Layers::BACKGROUND,
+                Hotkey::ToggleWindow => Layers::WINDOW,
+                _ => Layers::SPRITES,
+            };
+            let mut cpu = cpu.lock().unwrap();
+            let layers = cpu.context().ppu().visible_layers() ^ layer;
+            cpu.context_mut().set_visible_layers(layers);
+            println!("Visible layers: {layers:?}");
+        }
+        Hotkey::Screenshot => {
+            // The first free <rom>.<n>.png
+            let path = (1..)
+                .map(|n| state_file.with_extension(format!("{n}.png")))
+                .find(|path| !path.exists())
+                .unwrap();
+
+            match fs::write(&path, frame.to_png()) {
+                Ok(()) => println!("Saved screenshot to {}", path.display()),
+                Err(e) => eprintln!("Failed to save screenshot: {e}"),
+            }
+        }
+        Hotkey::Menu => {
+            // Keep the game still while the menu is open
+            let paused = control.lifecycle.apply(Transition::Pause).is_some();
+            let item = gui.show_menu();
+
+            if paused {
+                control.lifecycle.apply(Transition::Resume);
+            }
+
+            let hotkey = match item {
+                Some(MenuItem::Hotkey(hotkey)) => Some(hotkey),
+                Some(MenuItem::Options) => gui.show_options(),
+                None => None,
+            };
+
+            if let Some(hotkey) = hotkey {
+                return on_hotkey(hotkey, gui, input, cpu, control, files, frame);
+            }
+        }
+        Hotkey::RemapControls => {
+            let paused = control.lifecycle.apply(Transition::Pause).is_some();
+            let keymap = gui.capture_controls(input);
+
+            if paused {
+                control.lifecycle.apply(Transition::Resume);
+            }
+
+            if let Some(keymap) = keymap {
+                match keymap.save() {
+                    Ok(path) => println!("Saved the controls to {}", path.display()),
+                    Err(e) => eprintln!("Failed to save the controls: {e}"),
+                }
+                gui.set_keymap(keymap);
+            }
+        }
+        Hotkey::OpenRom => {
+            let paused = control.lifecycle.apply(Transition::Pause).is_some();
+            let picked = dialog::pick_rom();
+
+            if paused {
+                control.lifecycle.apply(Transition::Resume);
+            }
+
+            match picked {
+                Ok(path) => return path,
+                Err(e) => gui.show_message(
+                    "Open ROM",
+                    &format!("{e}, drop a ROM file on the window to open it."),
+                ),
+            }
+        }
+        Hotkey::Rewind => control.rewind.store(true, Ordering::Relaxed),
+        Hotkey::FrameBack => {
+            control.lifecycle.apply(Transition::Pause);
+            control.step_back.store(true, Ordering::Relaxed);
+        }
+        Hotkey::DebuggerUp => gui.move_selection(-1),
+        Hotkey::DebuggerDown => gui.move_selection(1),
+        Hotkey::ToggleBreakpoint => {
+            if let Some(address) = gui.selected_line() {
+                toggle_breakpoint(control, address);
+            }
+        }
+    }
+
+    None
+}
+
+/// Instructions around PC with the breakpoints among them, shown while paused.
+fn disassembly_view(cpu: &CPU<Emulator>, control: &Control) -> DisassemblyView {
+    let pc = cpu.registers().pc;
+
+    DisassemblyView {
+        lines: cpu.context().disassemble_around(pc, 20, 20),
+        pc,
+        breakpoints: control.breakpoints.lock().unwrap().clone(),
+    }
+}
+
+/// Each watch with its current value.
+fn watch_values(cpu: &CPU<Emulator>, control: &Control) -> Vec<(String, String)> {
+    control
+        .watches
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|watch| (watch.text().to_string(), watch.show(cpu)))
+        .collect()
+}
+
+fn toggle_breakpoint(control: &Control, address: u16) {
+    let mut breakpoints = control.breakpoints.lock().unwrap();
+
+    if breakpoints.remove(&address) {
+        println!("Breakpoint at {address:04X} removed");
+    } else {
+        breakpoints.insert(address);
+        println!("Breakpoint at {address:04X} set");
+    }
+}
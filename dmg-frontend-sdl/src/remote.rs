@@ -0,0 +1,448 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use dmg_core::frame::Frame;
+use dmg_core::frame_export::FrameExport;
+use dmg_core::hash::sha1;
+use dmg_core::joypad::Buttons;
+
+/// Page served at `/`, showing the screen and sending the keys.
+const VIEWER_PAGE: &str = include_str!("viewer.html");
+
+/// Appended to the key of a WebSocket handshake before hashing it, from RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// WebSocket opcodes
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+
+/// Longest message taken from a viewer, button events are a few bytes.
+const MAX_MESSAGE_LEN: u64 = 1024;
+/// Longest HTTP request line and headers taken from a client.
+const MAX_REQUEST_LEN: u64 = 8 * 1024;
+
+/// How often a viewer's connection checks for a new frame.
+const FRAME_POLL: Duration = Duration::from_millis(4);
+/// How long a client has to send its request before it is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How frames are sent, picked by the path the WebSocket connects to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum FrameFormat {
+    /// `/png`, a PNG file per frame
+    Png,
+    /// `/raw`, 160x144 pixels as R, G, B, A bytes
+    Raw,
+}
+
+/// Streams the screen to viewers connecting over WebSocket and takes the
+/// buttons they press.
+///
+/// Browsers get a page at `/` that connects by itself. Other clients
+/// connect to `/png` or `/raw` and get a binary message per frame; slow
+/// ones skip frames instead of holding up the emulation. Viewers send
+/// text messages like `press a` or `release start`, the buttons held by
+/// any of them are held.
+pub struct RemoteViewer {
+    frames: FrameExport,
+    buttons: Arc<HeldButtons>,
+}
+
+/// How many viewers hold each button, so one letting go doesn't release it
+/// for the others.
+#[derive(Default)]
+struct HeldButtons {
+    counts: [AtomicU32; 8],
+}
+
+impl HeldButtons {
+    fn press(&self, buttons: Buttons) {
+        for button in buttons.iter() {
+            self.count(button).fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn release(&self, buttons: Buttons) {
+        for button in buttons.iter() {
+            self.count(button).fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn held(&self) -> Buttons {
+        (0..8)
+            .filter(|&bit| self.counts[bit].load(Ordering::Relaxed) > 0)
+            .fold(Buttons::empty(), |held, bit| {
+                held | Buttons::from_bits_retain(1 << bit)
+            })
+    }
+
+    fn count(&self, button: Buttons) -> &AtomicU32 {
+        &self.counts[button.bits().trailing_zeros() as usize]
+    }
+}
+
+impl RemoteViewer {
+    /// Listen on `address`, e.g. `0.0.0.0:8080`.
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let frames = FrameExport::new();
+        let buttons = Arc::new(HeldButtons::default());
+        let (viewer_frames, viewer_buttons) = (frames.clone(), buttons.clone());
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let frames = viewer_frames.clone();
+                let buttons = viewer_buttons.clone();
+                thread::spawn(move || serve_client(stream, &frames, &buttons));
+            }
+        });
+
+        Ok(RemoteViewer { frames, buttons })
+    }
+
+    /// Show `frame` to the viewers.
+    pub fn publish(&self, frame: &Frame) {
+        // A frame dropped while every slot is read is replaced by the next one
+        self.frames.publish(frame);
+    }
+
+    /// Buttons held by the viewers.
+    pub fn buttons(&self) -> Buttons {
+        self.buttons.held()
+    }
+}
+
+/// What a client asked for in its HTTP request.
+struct Request {
+    path: String,
+    // Sec-WebSocket-Key, None when it isn't a WebSocket handshake
+    key: Option<String>,
+}
+
+fn serve_client(stream: TcpStream, frames: &FrameExport, buttons: &HeldButtons) {
+    let peer = peer_name(&stream);
+    let _ = stream.set_nodelay(true);
+    if stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err() {
+        return;
+    }
+    let Ok(mut reader) = stream.try_clone().map(BufReader::new) else {
+        return;
+    };
+    let Ok(request) = read_request(&mut reader) else {
+        return;
+    };
+    let mut stream = stream;
+
+    let format = match request.path.as_str() {
+        "/png" => FrameFormat::Png,
+        "/raw" => FrameFormat::Raw,
+        "/" if request.key.is_none() => {
+            let _ = write_response(
+                &mut stream,
+                "200 OK",
+                "text/html; charset=utf-8",
+                VIEWER_PAGE,
+            );
+            return;
+        }
+        _ => {
+            let _ = write_response(&mut stream, "404 Not Found", "text/plain", "Not found");
+            return;
+        }
+    };
+
+    let Some(key) = request.key else {
+        let _ = write_response(
+            &mut stream,
+            "400 Bad Request",
+            "text/plain",
+            "Connect with a WebSocket",
+        );
+        return;
+    };
+
+    // A viewer may watch for long without pressing anything
+    if accept_websocket(&mut stream, &key).is_err() || stream.set_read_timeout(None).is_err() {
+        return;
+    }
+    println!("Remote viewer joined from {peer}");
+
+    // Ends at the first frame it can't send, shutting the connection down
+    // so that a viewer gone without closing it is let go here as well
+    let frames = frames.clone();
+    thread::spawn(move || stream_frames(stream, &frames, format));
+
+    let held = read_buttons(&mut reader, buttons);
+    buttons.release(held);
+    let _ = reader.get_ref().shutdown(Shutdown::Both);
+    println!("Remote viewer {peer} left");
+}
+
+/// Read the request line and headers, the connection is dropped when they
+/// don't end within `MAX_REQUEST_LEN`.
+fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let mut reader = reader.take(MAX_REQUEST_LEN);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let path = line.split_whitespace().nth(1).unwrap_or("/").to_string();
+    let mut key = None;
+
+    // Headers up to the empty line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("sec-websocket-key")
+        {
+            key = Some(value.trim().to_string());
+        }
+    }
+
+    Ok(Request { path, key })
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn accept_websocket(stream: &mut TcpStream, key: &str) -> io::Result<()> {
+    let accept = base64(&sha1(format!("{key}{WEBSOCKET_GUID}").as_bytes()));
+
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {accept}\r\n\r\n"
+    )
+}
+
+/// Send every new frame until the viewer is gone.
+fn stream_frames(mut stream: TcpStream, frames: &FrameExport, format: FrameFormat) {
+    let mut sent = 0;
+
+    loop {
+        if frames.frame_number() == sent {
+            thread::sleep(FRAME_POLL);
+            continue;
+        }
+
+        let encoded = frames.read(|number, frame| {
+            let data = match format {
+                FrameFormat::Png => frame.to_png(),
+                FrameFormat::Raw => frame.as_rgba8888(),
+            };
+            (number, data)
+        });
+
+        let Some((number, data)) = encoded else {
+            continue;
+        };
+
+        if write_message(&mut stream, BINARY, &data).is_err() {
+            let _ = stream.shutdown(Shutdown::Both);
+            break;
+        }
+        sent = number;
+    }
+}
+
+/// Hold the buttons the viewer presses until it disconnects, returns the
+/// ones it still held then.
+fn read_buttons(reader: &mut impl Read, buttons: &HeldButtons) -> Buttons {
+    let mut held = Buttons::empty();
+
+    while let Ok((opcode, payload)) = read_message(reader) {
+        match opcode {
+            TEXT => {
+                let text = String::from_utf8_lossy(&payload);
+                let Some((action, name)) = text.trim().split_once(' ') else {
+                    continue;
+                };
+                let Some(button) = Buttons::from_button_name(name) else {
+                    continue;
+                };
+
+                // Counted once however often the viewer repeats it
+                match action {
+                    "press" if !held.contains(button) => {
+                        held |= button;
+                        buttons.press(button);
+                    }
+                    "release" if held.contains(button) => {
+                        held -= button;
+                        buttons.release(button);
+                    }
+                    _ => {}
+                }
+            }
+            CLOSE => break,
+            // Pings, continuations and binary messages aren't used
+            _ => {}
+        }
+    }
+
+    held
+}
+
+/// Read a message frame from a client, its opcode and unmasked payload.
+fn read_message(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0F;
+
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "remote viewer message too long",
+        ));
+    }
+
+    // Clients always mask their frames
+    let mut mask = [0; 4];
+    if header[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok((opcode, payload))
+}
+
+/// Write an unmasked, unfragmented message frame, in one write so it goes
+/// out in as few packets as possible.
+fn write_message(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut message = Vec::with_capacity(payload.len() + 10);
+    message.push(0x80 | opcode);
+
+    match payload.len() {
+        len @ 0..126 => message.push(len as u8),
+        len @ 126..=0xFFFF => {
+            message.push(126);
+            message.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            message.push(127);
+            message.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+
+    message.extend_from_slice(payload);
+    writer.write_all(&message)
+}
+
+/// Standard base64 with padding, for the handshake.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let mut bytes = [0; 4];
+        bytes[1..=chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes(bytes);
+
+        // A character per 6 bits, padded to 4
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+fn peer_name(stream: &TcpStream) -> String {
+    stream
+        .peer_addr()
+        .map_or_else(|_| "unknown".to_string(), |address| address.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_accepts_the_key_of_rfc_6455() {
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let accept = base64(&sha1(format!("{key}{WEBSOCKET_GUID}").as_bytes()));
+        assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+    }
+
+    #[test]
+    fn masked_messages_are_unmasked() {
+        let mask = [0x37, 0xFA, 0x21, 0x3D];
+        let mut frame = vec![0x80 | TEXT, 0x80 | 7];
+        frame.extend_from_slice(&mask);
+        frame.extend(
+            b"press a"
+                .iter()
+                .zip(mask.iter().cycle())
+                .map(|(b, m)| b ^ m),
+        );
+
+        let (opcode, payload) = read_message(&mut frame.as_slice()).unwrap();
+        assert_eq!((opcode, payload.as_slice()), (TEXT, b"press a".as_slice()));
+    }
+
+    #[test]
+    fn written_messages_read_back() {
+        for len in [0, 125, 126, 1000] {
+            let payload = vec![0x5A; len];
+            let mut frame = Vec::new();
+            write_message(&mut frame, BINARY, &payload).unwrap();
+
+            let (opcode, read) = read_message(&mut frame.as_slice()).unwrap();
+            assert_eq!((opcode, read), (BINARY, payload));
+        }
+
+        let mut frame = Vec::new();
+        write_message(&mut frame, BINARY, &[0; MAX_MESSAGE_LEN as usize + 1]).unwrap();
+        assert!(read_message(&mut frame.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::BTreeSet;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use dmg_core::bus::HardwareRegister;
+use dmg_core::cpu::Disassembly;
+use dmg_core::emu::Emulator;
+use dmg_core::frame::Frame;
+use dmg_core::interrupts::InterruptStats;
+use dmg_core::joypad::Buttons;
+use dmg_core::lcd::LcdControl;
+use dmg_core::ppu::{XRES, YRES};
+use dmg_core::stats::Stats;
+use dmg_core::vram::{self, TileSet};
+
+/// Sprite on screen, position in screen pixels and may be partly off screen.
+#[derive(Copy, Clone, Default)]
+pub struct SpriteBox {
+    /// OAM index
+    pub index: u8,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Layout of the frame for the debug overlay, registers as they were at the end of the frame.
+#[derive(Clone, Default)]
+pub struct Overlay {
+    pub scroll_x: u8,
+    pub scroll_y: u8,
+    /// Top left corner of the window in screen pixels, if it is shown
+    pub window: Option<(i32, i32)>,
+    pub sprite_height: u32,
+    pub sprites: Vec<SpriteBox>,
+}
+
+impl Overlay {
+    fn capture(&mut self, emu: &Emulator) {
+        let ppu = emu.ppu();
+        let lcdc = LcdControl::from_bits_truncate(ppu.lcd_read(HardwareRegister::LCDC));
+        let wx = ppu.lcd_read(HardwareRegister::WX) as i32;
+        let wy = ppu.lcd_read(HardwareRegister::WY) as i32;
+
+        self.scroll_x = ppu.lcd_read(HardwareRegister::SCX);
+        self.scroll_y = ppu.lcd_read(HardwareRegister::SCY);
+        self.window = (lcdc.contains(LcdControl::WINDOW_ENABLE) && wx <= 166 && wy < YRES as i32)
+            .then_some((wx - 7, wy));
+        self.sprite_height = vram::sprite_height(ppu) as u32;
+
+        self.sprites.clear();
+
+        for sprite in vram::sprites(ppu) {
+            let (x, y) = sprite.screen_position();
+
+            if x > -8 && x < XRES as i32 && y > -(self.sprite_height as i32) && y < YRES as i32 {
+                self.sprites.push(SpriteBox {
+                    index: sprite.index,
+                    x,
+                    y,
+                });
+            }
+        }
+    }
+}
+
+/// Mixes every frame with the one before, so sprites a game shows every
+/// other frame to look see-through do instead of flickering, much like the
+/// slow LCD of the hardware. Only the presentation changes.
+#[derive(Default)]
+pub struct FrameBlender {
+    // Emulator frame number of `current`
+    frame_number: Option<u32>,
+    current: Vec<u32>,
+    previous: Vec<u32>,
+}
+
+impl FrameBlender {
+    /// ARGB8888 `frame` mixed half and half with the frame before it. The
+    /// same frame again, like when redrawn while paused, isn't mixed with
+    /// itself, even when it shows the same pixels as the one before.
+    pub fn blend(&mut self, frame_number: u32, frame: Vec<u32>) -> Vec<u32> {
+        if self.frame_number != Some(frame_number) {
+            self.frame_number = Some(frame_number);
+            self.previous = mem::replace(&mut self.current, frame);
+        }
+
+        if self.previous.len() != self.current.len() {
+            return self.current.clone();
+        }
+
+        self.current
+            .iter()
+            .zip(&self.previous)
+            .map(|(a, b)| 0xFF000000 | (((a & 0xFEFEFE) >> 1) + ((b & 0xFEFEFE) >> 1)))
+            .collect()
+    }
+}
+
+/// Joypad as the game saw it at the end of the frame.
+#[derive(Copy, Clone, Default)]
+pub struct InputDisplay {
+    pub buttons: Buttons,
+    /// P1, the groups the game selected and the lines it reads
+    pub register: u8,
+}
+
+/// Code around PC drawn over the game while paused.
+pub struct DisassemblyView {
+    pub lines: Vec<Disassembly>,
+    pub pc: u16,
+    pub breakpoints: BTreeSet<u16>,
+}
+
+/// Copy of everything the windows draw, taken when the PPU completes a frame.
+#[derive(Clone, Default)]
+pub struct FrameSnapshot {
+    pub frame: Frame,
+    /// Emulator frame number of `frame`
+    pub frame_number: u32,
+    // Tile data for the debug window, 0x8000 - 0x97FF
+    pub tiles: Vec<u8>,
+    // Tiles written since the previous frame the reader took
+    pub dirty_tiles: TileSet,
+    pub overlay: Overlay,
+    pub stats: Stats,
+    pub interrupts: InterruptStats,
+    pub input: InputDisplay,
+    /// Watch expressions and their values at the end of the frame
+    pub watches: Vec<(String, String)>,
+}
+
+impl FrameSnapshot {
+    const TILE_DATA_START: u16 = 0x8000;
+    const TILE_DATA_END: u16 = 0x97FF;
+
+    /// Copy the current frame, `unread` tells that this buffer was published
+    /// but replaced before the reader took it, so its dirty tiles are kept.
+    pub fn capture(&mut self, emu: &mut Emulator, unread: bool) {
+        if !unread {
+            self.dirty_tiles = TileSet::new();
+        }
+        self.dirty_tiles.extend(&emu.take_dirty_tiles());
+
+        let ppu = emu.ppu();
+        self.frame.clone_from(ppu.frame());
+        self.frame_number = emu.get_current_frame();
+        self.tiles.clear();
+        self.tiles.extend(
+            (Self::TILE_DATA_START..=Self::TILE_DATA_END).map(|address| ppu.vram_read(address)),
+        );
+        self.overlay.capture(emu);
+        self.stats.clone_from(emu.stats());
+        self.interrupts.clone_from(emu.interrupt_stats());
+        self.input = InputDisplay {
+            buttons: emu.buttons(),
+            register: emu.joypad_register(),
+        };
+    }
+}
+
+struct Shared<T> {
+    middle: T,
+    fresh: bool,
+}
+
+/// Producer side of a triple buffer, owned by the emulation thread.
+///
+/// The producer always has a buffer of its own to fill and publishing only
+/// swaps it with the shared middle buffer, so a slow consumer never blocks it.
+/// Unread frames are overwritten by newer ones.
+pub struct FrameWriter<T> {
+    back: T,
+    // The back buffer was published and dropped without being read
+    back_unread: bool,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/// Consumer side of a triple buffer, owned by the render thread.
+pub struct FrameReader<T> {
+    front: T,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+pub fn triple_buffer<T: Clone>(initial: T) -> (FrameWriter<T>, FrameReader<T>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        middle: initial.clone(),
+        fresh: false,
+    }));
+
+    let writer = FrameWriter {
+        back: initial.clone(),
+        back_unread: false,
+        shared: shared.clone(),
+    };
+    let reader = FrameReader {
+        front: initial,
+        shared,
+    };
+
+    (writer, reader)
+}
+
+impl<T> FrameWriter<T> {
+    /// Buffer to fill before calling `publish`.
+    pub fn back_mut(&mut self) -> &mut T {
+        &mut self.back
+    }
+
+    /// Whether the buffer returned by `back_mut` was published before and
+    /// overwritten by a newer one without the reader seeing it.
+    pub fn back_unread(&self) -> bool {
+        self.back_unread
+    }
+
+    pub fn publish(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        self.back_unread = shared.fresh;
+        mem::swap(&mut self.back, &mut shared.middle);
+        shared.fresh = true;
+    }
+}
+
+impl<T> FrameReader<T> {
+    /// The most recently published buffer, None if nothing new was published
+    /// since the last call.
+    pub fn latest(&mut self) -> Option<&T> {
+        let mut shared = self.shared.lock().unwrap();
+
+        if !shared.fresh {
+            return None;
+        }
+
+        mem::swap(&mut self.front, &mut shared.middle);
+        shared.fresh = false;
+        Some(&self.front)
+    }
+
+    /// The buffer taken by the last call to `latest`.
+    pub fn front(&self) -> &T {
+        &self.front
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blender_mixes_each_frame_with_the_one_before() {
+        let mut blender = FrameBlender::default();
+        let white = vec![0xFFFFFFFF; 4];
+        let black = vec![0xFF000000; 4];
+
+        assert_eq!(blender.blend(1, white.clone()), white);
+        assert_eq!(blender.blend(2, black.clone()), vec![0xFF7F7F7F; 4]);
+        // Redrawn while paused, still mixed with frame 1
+        assert_eq!(blender.blend(2, black.clone()), vec![0xFF7F7F7F; 4]);
+        // Two frames alike are still two frames
+        assert_eq!(blender.blend(3, black.clone()), black);
+    }
+}
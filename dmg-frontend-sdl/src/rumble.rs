@@ -0,0 +1,102 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use dmg_core::cpu::CPU;
+use dmg_core::emu::Emulator;
+use dmg_core::watch::Watch;
+
+/// What makes the controller rumble.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RumbleEvent {
+    /// A state save or load failed
+    StateError,
+    /// A breakpoint, PPU break, restricted write or hang paused the game
+    Break,
+    /// A `--rumble EXPRESSION=VALUE` condition became true
+    Memory,
+}
+
+/// A watch expression compared to a value every frame, rumbling when it
+/// starts to match, like an achievement unlocking.
+pub struct MemoryTrigger {
+    watch: Watch,
+    value: u16,
+    met: bool,
+}
+
+impl MemoryTrigger {
+    /// `EXPRESSION=VALUE` with a hex value, like `[C0A5]=03`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (expression, value) = text
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Invalid rumble trigger {text}, use EXPRESSION=VALUE"))?;
+        let value = value.trim();
+        let hex = value.strip_prefix('$').unwrap_or(value);
+        let value = u16::from_str_radix(hex, 16)
+            .map_err(|_| format!("Invalid value {value} in rumble trigger {text}"))?;
+
+        Ok(MemoryTrigger {
+            watch: Watch::parse(expression)?,
+            value,
+            met: false,
+        })
+    }
+
+    /// Whether the condition became true since the last check.
+    pub fn check(&mut self, cpu: &CPU<Emulator>) -> bool {
+        let met = self.watch.evaluate(cpu) == self.value;
+        let started = met && !self.met;
+        self.met = met;
+        started
+    }
+}
+
+/// `--rumble` values: `state-error`, `break` or a memory trigger.
+pub fn parse_rumble(specs: &[String]) -> Result<(Vec<RumbleEvent>, Vec<MemoryTrigger>), String> {
+    let mut events = Vec::new();
+    let mut triggers = Vec::new();
+
+    for spec in specs {
+        match spec.as_str() {
+            "state-error" => events.push(RumbleEvent::StateError),
+            "break" => events.push(RumbleEvent::Break),
+            _ if spec.contains('=') => {
+                triggers.push(MemoryTrigger::parse(spec)?);
+                events.push(RumbleEvent::Memory);
+            }
+            _ => {
+                return Err(format!(
+                    "Invalid rumble event {spec}, use state-error, break or EXPRESSION=VALUE"
+                ));
+            }
+        }
+    }
+
+    Ok((events, triggers))
+}
+
+/// Rumbles asked for by either thread, played by the GUI thread which owns
+/// the controller.
+#[derive(Default)]
+pub struct Rumble {
+    events: Mutex<Vec<RumbleEvent>>,
+    due: AtomicBool,
+}
+
+impl Rumble {
+    pub fn set_events(&self, events: Vec<RumbleEvent>) {
+        *self.events.lock().unwrap() = events;
+    }
+
+    /// Ask for a rumble if `event` is one of the chosen ones.
+    pub fn event(&self, event: RumbleEvent) {
+        if self.events.lock().unwrap().contains(&event) {
+            self.due.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether a rumble was asked for since the last call.
+    pub fn take(&self) -> bool {
+        self.due.swap(false, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,260 @@
+use dmg_core::cpu::{CPU, CpuContext};
+use dmg_core::emu::{CLOCK_HZ, Emulator};
+
+use dmg_core::joypad::Buttons;
+
+/// `LD B, B`, used by test ROMs as a software breakpoint.
+const LD_B_B: u8 = 0x40;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExitReason {
+    CpuStopped,
+    Serial,
+    Breakpoint,
+    /// The frame or time limit was reached
+    Limit,
+    /// The watchdog saw the same frame and code for too long
+    Hang,
+    /// The host of the watched session quit
+    SessionEnded,
+}
+
+/// Conditions that end a scripted run, all checked against emulated time
+/// so a run stops at the same point whatever the host speed.
+#[derive(Clone, Default)]
+pub struct ExitConditions {
+    pub frames: Option<u32>,
+    pub seconds: Option<u64>,
+    pub serial: Option<String>,
+    pub breakpoint: bool,
+}
+
+impl ExitConditions {
+    /// Process exit code: 0 when the run ended as requested, 1 when the CPU
+    /// stopped, 2 when the limit ran out while waiting for the serial output
+    /// or breakpoint and 3 when the game locked up.
+    pub fn exit_code(&self, reason: ExitReason) -> i32 {
+        match reason {
+            ExitReason::Serial | ExitReason::Breakpoint | ExitReason::SessionEnded => 0,
+            ExitReason::Limit if self.serial.is_none() && !self.breakpoint => 0,
+            ExitReason::Limit => 2,
+            ExitReason::CpuStopped => 1,
+            ExitReason::Hang => 3,
+        }
+    }
+
+    /// Whether the run ends by itself, instead of when the window is closed.
+    pub fn is_scripted(&self) -> bool {
+        self.frames.is_some() || self.seconds.is_some() || self.serial.is_some() || self.breakpoint
+    }
+}
+
+/// Checks the exit conditions after every instruction.
+pub struct ExitWatch {
+    conditions: ExitConditions,
+    serial_checked: usize,
+}
+
+impl ExitWatch {
+    pub fn new(conditions: ExitConditions) -> Self {
+        ExitWatch {
+            conditions,
+            serial_checked: 0,
+        }
+    }
+
+    /// Execute one instruction and report why the run should end, if it should.
+    pub fn step(&mut self, cpu: &mut CPU<Emulator>) -> Option<ExitReason> {
+        let pc = cpu.registers().pc;
+        let at_breakpoint = self.conditions.breakpoint && cpu.context_mut().peek(pc) == LD_B_B;
+
+        if !cpu.step() {
+            return Some(ExitReason::CpuStopped);
+        }
+
+        if at_breakpoint {
+            return Some(ExitReason::Breakpoint);
+        }
+
+        let emu = cpu.context();
+
+        if let Some(pattern) = &self.conditions.serial {
+            let output = emu.serial_output();
+
+            // Only search again when new bytes arrived
+            if output.len() != self.serial_checked {
+                self.serial_checked = output.len();
+
+                if output.contains(pattern.as_str()) {
+                    return Some(ExitReason::Serial);
+                }
+            }
+        }
+
+        self.limit_reached(emu).then_some(ExitReason::Limit)
+    }
+
+    /// Execute instructions until `ticks` and report why the run should end.
+    ///
+    /// Runs in one go with `CPU::run_until` unless the serial output or the
+    /// breakpoint have to be checked after every instruction.
+    pub fn run_until(&mut self, cpu: &mut CPU<Emulator>, ticks: u64) -> Option<ExitReason> {
+        if self.conditions.serial.is_some() || self.conditions.breakpoint {
+            while cpu.context().ticks() < ticks {
+                if let Some(reason) = self.step(cpu) {
+                    return Some(reason);
+                }
+            }
+
+            return None;
+        }
+
+        // Stop at the time limit instead of the end of the batch
+        let ticks = match self.conditions.seconds {
+            Some(seconds) => ticks.min(seconds * CLOCK_HZ),
+            None => ticks,
+        };
+
+        if !cpu.run_until(ticks) {
+            return Some(ExitReason::CpuStopped);
+        }
+
+        self.limit_reached(cpu.context())
+            .then_some(ExitReason::Limit)
+    }
+
+    fn limit_reached(&self, emu: &Emulator) -> bool {
+        let frames_done = self
+            .conditions
+            .frames
+            .is_some_and(|frames| emu.get_current_frame() >= frames);
+        let seconds_done = self
+            .conditions
+            .seconds
+            .is_some_and(|seconds| emu.ticks() >= seconds * CLOCK_HZ);
+
+        frames_done || seconds_done
+    }
+}
+
+struct InputEvent {
+    frame: u32,
+    buttons: Buttons,
+    press: bool,
+}
+
+/// Joypad events injected at given frames, one per line:
+///
+/// ```text
+/// # Skip the title screen
+/// frame 120: press A
+/// frame 180: release A
+/// frame 200: press Up Left
+/// ```
+pub struct InputScript {
+    events: Vec<InputEvent>,
+    next: usize,
+    held: Buttons,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || format!("Invalid input script line {}: {line}", line_index + 1);
+            let (frame, action) = line.split_once(':').ok_or_else(invalid)?;
+            let frame = frame
+                .trim()
+                .strip_prefix("frame")
+                .and_then(|n| n.trim().parse().ok())
+                .ok_or_else(invalid)?;
+
+            let mut words = action.split_whitespace();
+            let press = match words.next() {
+                Some("press") => true,
+                Some("release") => false,
+                _ => return Err(invalid()),
+            };
+
+            let mut buttons = Buttons::empty();
+            for name in words {
+                buttons |= Buttons::from_button_name(name).ok_or_else(invalid)?;
+            }
+
+            events.push(InputEvent {
+                frame,
+                buttons,
+                press,
+            });
+        }
+
+        // Lines may come in any order, events of the same frame keep theirs
+        events.sort_by_key(|event| event.frame);
+
+        Ok(InputScript {
+            events,
+            next: 0,
+            held: Buttons::empty(),
+        })
+    }
+
+    /// Apply the events up to and including `frame`, returns the held buttons.
+    pub fn advance(&mut self, frame: u32) -> Buttons {
+        while let Some(event) = self.events.get(self.next) {
+            if event.frame > frame {
+                break;
+            }
+
+            if event.press {
+                self.held |= event.buttons;
+            } else {
+                self.held -= event.buttons;
+            }
+
+            self.next += 1;
+        }
+
+        self.held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_holds_buttons_between_events() {
+        let mut script = InputScript::parse(
+            "# Out of order on purpose\n\
+             frame 10: release A\n\
+             frame 5: press A B\n\
+             \n\
+             frame 10: press Up\n",
+        )
+        .unwrap();
+
+        assert_eq!(script.advance(4), Buttons::empty());
+        assert_eq!(script.advance(5), Buttons::A | Buttons::B);
+        assert_eq!(script.advance(9), Buttons::A | Buttons::B);
+        // Frames skipped by a slow host still get their events
+        assert_eq!(script.advance(20), Buttons::B | Buttons::UP);
+    }
+
+    #[test]
+    fn invalid_lines_name_their_number() {
+        let error = InputScript::parse("frame 1: press A\nframe 2: hold A\n")
+            .err()
+            .unwrap();
+        assert_eq!(error, "Invalid input script line 2: frame 2: hold A");
+
+        assert!(InputScript::parse("frame 1: press Turbo").is_err());
+        assert!(InputScript::parse("press A").is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Logs kept besides the current one, `session.1.log` is the previous session.
+const KEPT_LOGS: usize = 4;
+
+/// Size at which the current log is rotated in the middle of a session.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        })
+    }
+}
+
+/// Log of what happened in a session, for users to attach to bug reports.
+///
+/// Each event is a line with the Unix time, the level, the event name and
+/// `key=value` fields, values with spaces or quotes quoted:
+///
+/// ```text
+/// 1760000000.123 INFO rom_loaded file="games/tetris.gb" title=TETRIS
+/// ```
+///
+/// Every session starts a new `session.log`, the earlier ones move up to
+/// `session.4.log` and older ones are deleted. A log that grows too large
+/// is rotated the same way.
+pub struct SessionLog {
+    path: PathBuf,
+    // File and bytes written to it, None when there is nowhere to write
+    file: Mutex<Option<(File, u64)>>,
+}
+
+impl SessionLog {
+    /// Start a new log in `dir`, rotating the earlier ones.
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("session.log");
+        rotate(&path)?;
+
+        Ok(SessionLog {
+            file: Mutex::new(Some((File::create(&path)?, 0))),
+            path,
+        })
+    }
+
+    /// A log that drops every event.
+    pub fn disabled() -> Self {
+        SessionLog {
+            path: PathBuf::new(),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Append an event, failures to write are ignored so that logging
+    /// never stops the emulator.
+    pub fn event(&self, level: Level, name: &str, fields: &[(&str, String)]) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut line = format!(
+            "{}.{:03} {level} {name}",
+            time.as_secs(),
+            time.subsec_millis()
+        );
+
+        for (key, value) in fields {
+            line.push_str(&format!(" {key}={}", quote(value)));
+        }
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        let Some((file, written)) = file.as_mut() else {
+            return;
+        };
+
+        if *written + line.len() as u64 > MAX_LOG_SIZE
+            && rotate(&self.path).is_ok()
+            && let Ok(new_file) = File::create(&self.path)
+        {
+            *file = new_file;
+            *written = 0;
+        }
+
+        if file.write_all(line.as_bytes()).is_ok() {
+            *written += line.len() as u64;
+        }
+    }
+
+    pub fn info(&self, name: &str, fields: &[(&str, String)]) {
+        self.event(Level::Info, name, fields);
+    }
+
+    pub fn warn(&self, name: &str, fields: &[(&str, String)]) {
+        self.event(Level::Warn, name, fields);
+    }
+
+    pub fn error(&self, name: &str, fields: &[(&str, String)]) {
+        self.event(Level::Error, name, fields);
+    }
+}
+
+/// `session.log` as `session.1.log`, `session.1.log` as `session.2.log` and
+/// so on, dropping the oldest.
+fn rotate(path: &Path) -> io::Result<()> {
+    let numbered = |n: usize| path.with_extension(format!("{n}.log"));
+
+    for n in (1..KEPT_LOGS).rev() {
+        match fs::rename(numbered(n), numbered(n + 1)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+    }
+
+    match fs::rename(path, numbered(1)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn quote(value: &str) -> String {
+    if !value.is_empty() && !value.contains([' ', '"', '=', '\n']) {
+        return value.to_string();
+    }
+
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
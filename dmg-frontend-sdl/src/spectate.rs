@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::thread;
+
+use dmg_core::cpu::CPU;
+use dmg_core::emu::Emulator;
+use dmg_core::joypad::Buttons;
+use dmg_core::state;
+
+// Messages start with a tag, numbers are little endian
+/// Buttons held from a frame on: the frame as a u32 and the buttons.
+const INPUT: u8 = b'I';
+/// Where a spectator starts: the global checksum of the ROM as a u16, the
+/// buttons, the length of the savestate as a u32 and the savestate.
+const STATE: u8 = b'S';
+
+/// Longest savestate taken from a host, real ones are well under it.
+const MAX_STATE_LEN: usize = 1024 * 1024;
+/// Messages queued for a spectator before it's dropped, two seconds of frames.
+const MAX_BACKLOG: usize = 120;
+
+/// Sends the machine state and then the buttons of every frame to
+/// spectators connecting over TCP.
+///
+/// Spectators run the same ROM with the same options and start from the
+/// state of the frame they joined at, the host doesn't have to restart.
+/// Loading states, rewinding and resetting on the host aren't sent.
+pub struct SpectatorHost {
+    joined: Receiver<TcpStream>,
+    spectators: Vec<SpectatorLink>,
+}
+
+/// The queue of a spectator's writer and its connection, to end it.
+struct SpectatorLink {
+    messages: SyncSender<Vec<u8>>,
+    stream: TcpStream,
+}
+
+impl SpectatorLink {
+    /// Queue `message`, false when the spectator left or fell too far behind.
+    fn send(&self, message: &[u8]) -> bool {
+        match self.messages.try_send(message.to_vec()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                println!("Spectator {} fell behind", peer_name(&self.stream));
+                let _ = self.stream.shutdown(Shutdown::Both);
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+impl SpectatorHost {
+    /// Listen on `address`, e.g. `0.0.0.0:5000`.
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (tx, joined) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if tx.send(stream).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(SpectatorHost {
+            joined,
+            spectators: Vec::new(),
+        })
+    }
+
+    /// Send the buttons held from the current frame on, spectators who
+    /// joined since the last frame get the state of the machine with them.
+    pub fn send_frame(&mut self, cpu: &CPU<Emulator>, buttons: Buttons) {
+        let mut joined = None;
+
+        while let Ok(stream) = self.joined.try_recv() {
+            println!("Spectator joined from {}", peer_name(&stream));
+            // Saved once for everyone joining at this frame
+            let message = joined.get_or_insert_with(|| state_message(cpu, buttons));
+            let Some(spectator) = spawn_writer(stream) else {
+                continue;
+            };
+
+            if spectator.send(message) {
+                self.spectators.push(spectator);
+            }
+        }
+
+        let message = input_message(cpu.context().get_current_frame(), buttons);
+
+        // Writers end when their spectator disconnects
+        self.spectators.retain(|spectator| spectator.send(&message));
+    }
+}
+
+/// Answer from `Spectator::poll`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HostInput {
+    Ready,
+    /// The host hasn't reached the frame yet
+    Waiting,
+    /// The host ended the session
+    Ended,
+}
+
+enum Message {
+    Input(u32, Buttons),
+    State(HostState),
+}
+
+/// The machine as the host had it when the spectator joined.
+pub struct HostState {
+    pub global_checksum: u16,
+    pub buttons: Buttons,
+    /// Savestate from `state::save_machine`
+    pub data: Vec<u8>,
+}
+
+/// Follows the input of a `SpectatorHost`, local input is ignored.
+pub struct Spectator {
+    messages: Receiver<Message>,
+    // Changes not applied yet, oldest first
+    changes: VecDeque<(u32, Buttons)>,
+    held: Buttons,
+    // Latest frame the host sent the buttons of
+    host_frame: Option<u32>,
+    state: Option<HostState>,
+    ended: bool,
+}
+
+impl Spectator {
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        let (tx, messages) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stream);
+
+            while let Ok(message) = read_message(&mut reader) {
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Spectator {
+            messages,
+            changes: VecDeque::new(),
+            held: Buttons::empty(),
+            host_frame: None,
+            state: None,
+            ended: false,
+        })
+    }
+
+    /// Whether the buttons of `frame` are known. Nothing is ready before the
+    /// state sent on joining was taken with `take_state`.
+    pub fn poll(&mut self, frame: u32) -> HostInput {
+        while self.state.is_none() {
+            match self.messages.try_recv() {
+                Ok(Message::Input(host_frame, buttons)) => {
+                    self.host_frame = Some(host_frame);
+                    self.changes.push_back((host_frame, buttons));
+                }
+                Ok(Message::State(state)) => {
+                    self.changes.clear();
+                    self.held = state.buttons;
+                    self.state = Some(state);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.ended = true;
+                    break;
+                }
+            }
+        }
+
+        match self.host_frame {
+            _ if self.state.is_some() => HostInput::Waiting,
+            Some(host_frame) if host_frame >= frame => HostInput::Ready,
+            _ if self.ended => HostInput::Ended,
+            _ => HostInput::Waiting,
+        }
+    }
+
+    /// The state to start from, once it arrived.
+    pub fn take_state(&mut self) -> Option<HostState> {
+        self.state.take()
+    }
+
+    /// Buttons held at `frame`, known once `poll` returned `Ready` for it.
+    pub fn buttons(&mut self, frame: u32) -> Buttons {
+        while let Some(&(change_frame, buttons)) = self.changes.front() {
+            if change_frame > frame {
+                break;
+            }
+
+            self.held = buttons;
+            self.changes.pop_front();
+        }
+
+        self.held
+    }
+
+    /// Frames the host is ahead of `frame`, run without frame limiting to catch up.
+    pub fn frames_behind(&self, frame: u32) -> u32 {
+        self.host_frame
+            .map_or(0, |host_frame| host_frame.saturating_sub(frame))
+    }
+}
+
+fn input_message(frame: u32, buttons: Buttons) -> Vec<u8> {
+    let mut message = vec![INPUT];
+    message.extend_from_slice(&frame.to_le_bytes());
+    message.push(buttons.bits());
+    message
+}
+
+fn state_message(cpu: &CPU<Emulator>, buttons: Buttons) -> Vec<u8> {
+    let global_checksum = cpu
+        .context()
+        .cartridge()
+        .map_or(0, |rom| rom.header.global_checksum());
+    let data = state::save_machine(cpu);
+
+    let mut message = vec![STATE];
+    message.extend_from_slice(&global_checksum.to_le_bytes());
+    message.push(buttons.bits());
+    message.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    message.extend_from_slice(&data);
+    message
+}
+
+fn read_message(reader: &mut impl Read) -> io::Result<Message> {
+    let mut tag = [0; 1];
+    reader.read_exact(&mut tag)?;
+
+    match tag[0] {
+        INPUT => {
+            let mut body = [0; 5];
+            reader.read_exact(&mut body)?;
+            let [frame @ .., buttons] = body;
+
+            Ok(Message::Input(
+                u32::from_le_bytes(frame),
+                Buttons::from_bits_retain(buttons),
+            ))
+        }
+        STATE => {
+            let mut header = [0; 7];
+            reader.read_exact(&mut header)?;
+            let [checksum_low, checksum_high, buttons, len @ ..] = header;
+
+            let len = u32::from_le_bytes(len) as usize;
+            if len > MAX_STATE_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "spectator state too long",
+                ));
+            }
+
+            let mut data = vec![0; len];
+            reader.read_exact(&mut data)?;
+
+            Ok(Message::State(HostState {
+                global_checksum: u16::from_le_bytes([checksum_low, checksum_high]),
+                buttons: Buttons::from_bits_retain(buttons),
+                data,
+            }))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unknown spectator message",
+        )),
+    }
+}
+
+/// Writes the messages for one spectator on its own thread, so a slow
+/// connection doesn't hold up the emulation.
+fn spawn_writer(mut stream: TcpStream) -> Option<SpectatorLink> {
+    let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(MAX_BACKLOG);
+    let _ = stream.set_nodelay(true);
+    let link = SpectatorLink {
+        messages: tx,
+        stream: stream.try_clone().ok()?,
+    };
+
+    thread::spawn(move || {
+        for message in rx {
+            if stream.write_all(&message).is_err() {
+                println!("Spectator {} left", peer_name(&stream));
+                break;
+            }
+        }
+    });
+
+    Some(link)
+}
+
+fn peer_name(stream: &TcpStream) -> String {
+    stream
+        .peer_addr()
+        .map_or_else(|_| "unknown".to_string(), |address| address.to_string())
+}
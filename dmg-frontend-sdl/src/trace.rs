@@ -0,0 +1,135 @@
+use std::io::{self, Write};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use dmg_core::clock::EmuClock;
+use dmg_core::cpu::{MemoryAccess, TRACE_MAGIC, TraceRecord, TraceSink, interrupt_line};
+use dmg_core::interrupts::InterruptFlag;
+
+/// Records handed to the writer thread at once.
+const BATCH_SIZE: usize = 4096;
+/// Batches waiting for the writer thread before the emulation waits for it.
+const QUEUED_BATCHES: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TraceFormat {
+    /// A line per instruction, interrupt dispatch and traced memory access
+    Text,
+    /// `TRACE_MAGIC` and fixed size records of the instructions, `dmgemu
+    /// trace` prints them
+    Binary,
+}
+
+enum TraceEvent {
+    Instruction(TraceRecord),
+    Interrupt(InterruptFlag, EmuClock),
+    Access(MemoryAccess),
+}
+
+/// Hands the CPU trace to a thread that formats and writes it, so the
+/// emulation only copies records. The queue is bounded, a writer that
+/// can't keep up slows the emulation down instead of filling memory.
+pub struct TraceWriter {
+    batch: Vec<TraceEvent>,
+    sender: Option<SyncSender<Vec<TraceEvent>>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl TraceWriter {
+    pub fn spawn(out: Box<dyn Write + Send>, format: TraceFormat) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(QUEUED_BATCHES);
+        let thread = thread::spawn(move || write_trace(out, format, receiver));
+
+        TraceWriter {
+            batch: Vec::with_capacity(BATCH_SIZE),
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    fn push(&mut self, event: TraceEvent) {
+        self.batch.push(event);
+
+        if self.batch.len() == BATCH_SIZE {
+            self.send_batch();
+        }
+    }
+
+    fn send_batch(&mut self) {
+        let batch = mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+
+        // The thread only stops early on a write error, reported on drop
+        if let Some(sender) = &self.sender
+            && sender.send(batch).is_err()
+        {
+            self.sender = None;
+        }
+    }
+}
+
+impl TraceSink for TraceWriter {
+    fn record(&mut self, record: &TraceRecord) {
+        self.push(TraceEvent::Instruction(*record));
+    }
+
+    fn interrupt(&mut self, source: InterruptFlag, clock: EmuClock) {
+        self.push(TraceEvent::Interrupt(source, clock));
+    }
+
+    fn access(&mut self, access: &MemoryAccess) {
+        self.push(TraceEvent::Access(*access));
+    }
+}
+
+/// Writes what is left and waits for the file to be complete.
+impl Drop for TraceWriter {
+    fn drop(&mut self) {
+        self.send_batch();
+        self.sender = None;
+
+        if let Some(thread) = self.thread.take() {
+            match thread.join() {
+                Ok(Err(e)) => eprintln!("Failed to write the trace: {e}"),
+                Err(_) => eprintln!("The trace writer panicked"),
+                Ok(Ok(())) => (),
+            }
+        }
+    }
+}
+
+fn write_trace(
+    mut out: Box<dyn Write + Send>,
+    format: TraceFormat,
+    receiver: Receiver<Vec<TraceEvent>>,
+) -> io::Result<()> {
+    let mut buffer = Vec::new();
+
+    if format == TraceFormat::Binary {
+        out.write_all(TRACE_MAGIC)?;
+    }
+
+    for batch in receiver {
+        buffer.clear();
+
+        for event in &batch {
+            match (event, format) {
+                (TraceEvent::Instruction(record), TraceFormat::Text) => {
+                    writeln!(buffer, "{record}")?
+                }
+                (TraceEvent::Instruction(record), TraceFormat::Binary) => {
+                    record.encode(&mut buffer)
+                }
+                (TraceEvent::Interrupt(source, clock), TraceFormat::Text) => {
+                    writeln!(buffer, "{}", interrupt_line(*source, *clock))?
+                }
+                (TraceEvent::Access(access), TraceFormat::Text) => writeln!(buffer, "{access}")?,
+                (TraceEvent::Interrupt(..) | TraceEvent::Access(_), TraceFormat::Binary) => (),
+            }
+        }
+
+        out.write_all(&buffer)?;
+    }
+
+    out.flush()
+}
@@ -1,12 +0,0 @@
-pub mod bus;
-pub mod cart;
-pub mod cpu;
-pub mod dma;
-pub mod emu;
-pub mod gui;
-pub mod interrupts;
-pub mod lcd;
-pub mod ppu;
-pub mod timer;
-
-pub use emu::*;